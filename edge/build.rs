@@ -0,0 +1,6 @@
+//! 为与主 crate 共用的模块设置 `cfg(edge)`，排除只在源站使用的部分（指标、会话存储、用量报表、HTTP 响应）
+
+fn main() {
+    println!("cargo::rustc-check-cfg=cfg(edge)");
+    println!("cargo::rustc-cfg=edge");
+}
@@ -0,0 +1,46 @@
+//! 与主 crate 相同的模块路径（`crate::anthropic::*`）：协议转换器、SSE 流式状态机及其依赖的纯逻辑模块
+//!
+//! 只在源站使用的部分（指标、会话 ID 核对、用量报表、axum 响应）在这些模块中以 `cfg(not(edge))` 排除
+
+#[path = "../../../src/anthropic/client_tools.rs"]
+mod client_tools;
+#[path = "../../../src/anthropic/converter.rs"]
+pub mod converter;
+#[path = "../../../src/anthropic/diff_preview.rs"]
+mod diff_preview;
+#[path = "../../../src/anthropic/echo_filter.rs"]
+mod echo_filter;
+#[path = "../../../src/anthropic/error.rs"]
+pub mod error;
+#[path = "../../../src/anthropic/event_order.rs"]
+mod event_order;
+#[path = "../../../src/anthropic/language.rs"]
+mod language;
+#[path = "../../../src/anthropic/models.rs"]
+pub mod models;
+#[path = "../../../src/anthropic/normalize.rs"]
+mod normalize;
+#[path = "../../../src/anthropic/references.rs"]
+mod references;
+#[path = "../../../src/anthropic/refusal.rs"]
+pub mod refusal;
+#[path = "../../../src/anthropic/schema.rs"]
+mod schema;
+#[path = "../../../src/anthropic/server_tools.rs"]
+mod server_tools;
+#[path = "../../../src/anthropic/stop_sequence.rs"]
+mod stop_sequence;
+#[path = "../../../src/anthropic/stream.rs"]
+pub mod stream;
+#[path = "../../../src/anthropic/template.rs"]
+mod template;
+#[path = "../../../src/anthropic/tool_choice.rs"]
+mod tool_choice;
+#[path = "../../../src/anthropic/tool_ids.rs"]
+mod tool_ids;
+#[path = "../../../src/anthropic/tool_validation.rs"]
+mod tool_validation;
+#[path = "../../../src/anthropic/types.rs"]
+pub mod types;
+#[path = "../../../src/anthropic/workspace.rs"]
+mod workspace;
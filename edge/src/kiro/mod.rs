@@ -0,0 +1,6 @@
+//! 与主 crate 相同的模块路径（`crate::kiro::parser`、`crate::kiro::model::events`）
+
+#[path = "../../../src/kiro/parser/mod.rs"]
+pub mod parser;
+
+pub mod model;
@@ -0,0 +1,4 @@
+#[path = "../../../../src/kiro/model/events/mod.rs"]
+pub mod events;
+#[path = "../../../../src/kiro/model/requests/mod.rs"]
+pub mod requests;
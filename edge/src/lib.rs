@@ -0,0 +1,65 @@
+//! Kiro 请求/响应转换核心
+//!
+//! 主 crate 只有 bin 目标，这里与 `fuzz/` 一样直接引入自包含的模块：AWS Event Stream 解析器、
+//! Kiro 请求与事件模型、协议转换器（[`convert_request`]）和 SSE 流式状态机（[`StreamContext`]）。
+//! 它们不依赖 tokio、reqwest，可以编译到 wasm32，在边缘 Worker 中把 Anthropic 请求转换为 Kiro 请求、
+//! 把上游响应增量转换为 Anthropic SSE 事件；凭据管理与上游请求仍留在源站。
+//!
+//! 共用模块中只在源站使用的部分（指标、会话 ID 核对、用量报表、axum 响应）以 `cfg(not(edge))` 排除，
+//! `cfg(edge)` 由本 crate 的构建脚本设置。本 crate 是主 workspace 的成员，
+//! 主 crate 的 `cargo build/test --workspace` 会一并构建和测试它
+
+#[allow(dead_code)]
+pub mod anthropic;
+#[allow(dead_code)]
+pub mod kiro;
+#[allow(dead_code)]
+pub mod model;
+#[allow(dead_code)]
+#[path = "../../src/token/mod.rs"]
+pub mod token;
+
+pub use anthropic::converter::{ConversionResult, convert_request};
+pub use anthropic::stream::{SseEncoder, SseEvent, StreamContext};
+pub use anthropic::types::MessagesRequest;
+pub use kiro::model::events::{Event, ExceptionKind};
+pub use kiro::parser::decoder::{DecoderStats, EventStreamDecoder};
+pub use kiro::parser::error::ParseError;
+pub use model::config::ConverterConfig;
+
+/// 上游响应体的增量解码器
+#[derive(Default)]
+pub struct ResponseDecoder {
+    decoder: EventStreamDecoder,
+}
+
+impl ResponseDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 输入一段响应体字节，返回已完整到达的事件
+    ///
+    /// 损坏的帧由解码器跳过，无法识别的事件被忽略；缓冲区溢出等不可恢复的错误返回 Err
+    pub fn feed(&mut self, chunk: &[u8]) -> Result<Vec<Event>, ParseError> {
+        self.decoder.feed(chunk)?;
+        let mut events = Vec::new();
+        for result in self.decoder.decode_iter() {
+            match result {
+                Ok(frame) => {
+                    if let Ok(event) = Event::from_frame(frame) {
+                        events.push(event);
+                    }
+                }
+                Err(e) => tracing::warn!("解码帧失败: {}", e),
+            }
+        }
+        Ok(events)
+    }
+
+    /// 解码统计
+    pub fn stats(&self) -> DecoderStats {
+        self.decoder.stats()
+    }
+}
+
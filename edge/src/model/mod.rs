@@ -0,0 +1,6 @@
+//! 与主 crate 相同的模块路径（`crate::model::config`），转换器选项直接使用源站的配置类型
+
+#[path = "../../../src/model/config.rs"]
+pub mod config;
+#[path = "../../../src/model/migrate.rs"]
+mod migrate;
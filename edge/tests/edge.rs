@@ -0,0 +1,54 @@
+use kiro_rs_edge::kiro::parser::encoder::{encode_event, encode_exception};
+use kiro_rs_edge::{ConverterConfig, Event, MessagesRequest, ResponseDecoder, StreamContext};
+
+#[test]
+fn test_decode_split_chunks() {
+    let mut body = encode_event("assistantResponseEvent", br#"{"content":"Hello"}"#);
+    body.extend(encode_exception("ThrottlingException", "slow down"));
+
+    let mut decoder = ResponseDecoder::new();
+    let (head, tail) = body.split_at(7);
+    assert!(decoder.feed(head).unwrap().is_empty());
+    let events = decoder.feed(tail).unwrap();
+    assert_eq!(events.len(), 2);
+    assert!(matches!(&events[0], Event::AssistantResponse(e) if e.content == "Hello"));
+    assert!(matches!(&events[1], Event::Exception { .. }));
+    assert_eq!(decoder.stats().frames_decoded, 2);
+}
+
+#[test]
+fn test_convert_request_and_stream_response() {
+    let request: MessagesRequest = serde_json::from_value(serde_json::json!({
+        "model": "claude-sonnet-4-6",
+        "max_tokens": 64,
+        "messages": [{"role": "user", "content": "hi"}]
+    }))
+    .unwrap();
+    let result = kiro_rs_edge::convert_request(&request, &ConverterConfig::default()).unwrap();
+    assert_eq!(
+        result
+            .conversation_state
+            .current_message
+            .user_input_message
+            .content,
+        "hi"
+    );
+
+    let mut decoder = ResponseDecoder::new();
+    let body = encode_event("assistantResponseEvent", br#"{"content":"Hello"}"#);
+    let mut ctx = StreamContext::new_with_thinking(&request.model, 1, false);
+    let mut events = ctx.generate_initial_events();
+    for event in decoder.feed(&body).unwrap() {
+        events.extend(ctx.process_kiro_event(&event));
+    }
+    events.extend(ctx.generate_final_events());
+
+    let names: Vec<&str> = events.iter().map(|e| e.event.as_str()).collect();
+    assert_eq!(names.first(), Some(&"message_start"));
+    assert_eq!(names.last(), Some(&"message_stop"));
+    assert!(
+        events
+            .iter()
+            .any(|e| e.data["delta"]["text"].as_str() == Some("Hello"))
+    );
+}
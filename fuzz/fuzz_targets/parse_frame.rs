@@ -0,0 +1,10 @@
+#![no_main]
+
+// 主 crate 只有 bin 目标，这里直接引入自包含的解析器模块
+#[allow(dead_code)]
+#[path = "../../src/kiro/parser/mod.rs"]
+mod parser;
+
+libfuzzer_sys::fuzz_target!(|data: &[u8]| {
+    parser::fuzz::parse_frame(data);
+});
@@ -20,6 +20,24 @@ pub enum AdminServiceError {
 
     /// 凭据无效（验证失败）
     InvalidCredential(String),
+
+    /// 用量报表不存在
+    ReportNotFound { date: String },
+
+    /// 会话不存在（未缓存历史或已过期）
+    ConversationNotFound { id: String },
+
+    /// 死信记录不存在（未启用死信队列或已被淘汰）
+    DeadLetterNotFound { id: String },
+
+    /// SSE 会话记录不存在（未启用会话记录或已被删除）
+    SseTranscriptNotFound { id: String },
+
+    /// 记录无法重放（请求体被截断或无法解析）
+    ReplayUnavailable(String),
+
+    /// 模型未命中任何映射规则
+    ModelNotFound { model: String },
 }
 
 impl fmt::Display for AdminServiceError {
@@ -31,6 +49,14 @@ impl fmt::Display for AdminServiceError {
             AdminServiceError::UpstreamError(msg) => write!(f, "上游服务错误: {}", msg),
             AdminServiceError::InternalError(msg) => write!(f, "内部错误: {}", msg),
             AdminServiceError::InvalidCredential(msg) => write!(f, "凭据无效: {}", msg),
+            AdminServiceError::ReportNotFound { date } => write!(f, "用量报表不存在: {}", date),
+            AdminServiceError::ConversationNotFound { id } => write!(f, "会话不存在: {}", id),
+            AdminServiceError::DeadLetterNotFound { id } => write!(f, "死信记录不存在: {}", id),
+            AdminServiceError::SseTranscriptNotFound { id } => {
+                write!(f, "SSE 会话记录不存在: {}", id)
+            }
+            AdminServiceError::ReplayUnavailable(msg) => write!(f, "无法重放: {}", msg),
+            AdminServiceError::ModelNotFound { model } => write!(f, "未知模型: {}", model),
         }
     }
 }
@@ -41,22 +67,36 @@ impl AdminServiceError {
     /// 获取对应的 HTTP 状态码
     pub fn status_code(&self) -> StatusCode {
         match self {
-            AdminServiceError::NotFound { .. } => StatusCode::NOT_FOUND,
+            AdminServiceError::NotFound { .. }
+            | AdminServiceError::ReportNotFound { .. }
+            | AdminServiceError::ConversationNotFound { .. }
+            | AdminServiceError::DeadLetterNotFound { .. }
+            | AdminServiceError::SseTranscriptNotFound { .. }
+            | AdminServiceError::ModelNotFound { .. } => StatusCode::NOT_FOUND,
             AdminServiceError::UpstreamError(_) => StatusCode::BAD_GATEWAY,
             AdminServiceError::InternalError(_) => StatusCode::INTERNAL_SERVER_ERROR,
-            AdminServiceError::InvalidCredential(_) => StatusCode::BAD_REQUEST,
+            AdminServiceError::InvalidCredential(_) | AdminServiceError::ReplayUnavailable(_) => {
+                StatusCode::BAD_REQUEST
+            }
         }
     }
 
     /// 转换为 API 错误响应
     pub fn into_response(self) -> AdminErrorResponse {
         match &self {
-            AdminServiceError::NotFound { .. } => AdminErrorResponse::not_found(self.to_string()),
+            AdminServiceError::NotFound { .. }
+            | AdminServiceError::ReportNotFound { .. }
+            | AdminServiceError::ConversationNotFound { .. }
+            | AdminServiceError::DeadLetterNotFound { .. }
+            | AdminServiceError::SseTranscriptNotFound { .. }
+            | AdminServiceError::ModelNotFound { .. } => {
+                AdminErrorResponse::not_found(self.to_string())
+            }
             AdminServiceError::UpstreamError(_) => AdminErrorResponse::api_error(self.to_string()),
             AdminServiceError::InternalError(_) => {
                 AdminErrorResponse::internal_error(self.to_string())
             }
-            AdminServiceError::InvalidCredential(_) => {
+            AdminServiceError::InvalidCredential(_) | AdminServiceError::ReplayUnavailable(_) => {
                 AdminErrorResponse::invalid_request(self.to_string())
             }
         }
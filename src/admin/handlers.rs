@@ -2,15 +2,20 @@
 
 use axum::{
     Json,
-    extract::{Path, State},
+    body::Body,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
     response::IntoResponse,
 };
 
+use crate::anthropic::transcript::TranscriptFormat;
+
 use super::{
     middleware::AdminState,
     types::{
-        AddCredentialRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetPriorityRequest,
-        SuccessResponse,
+        AddCredentialRequest, AdminErrorResponse, DeadLetterQuery, RenderConversationQuery,
+        ReplayRequest, SetDisabledRequest, SetLoadBalancingModeRequest, SetPriorityRequest,
+        SetThinkingExtractionRequest, SseTranscriptQuery, SuccessResponse, UsageReportQuery,
     },
 };
 
@@ -70,6 +75,18 @@ pub async fn reset_failure_count(
     }
 }
 
+/// POST /api/admin/credentials/:id/refresh
+/// 立即刷新指定凭据的 Token
+pub async fn refresh_credential_token(
+    State(state): State<AdminState>,
+    Path(id): Path<u64>,
+) -> impl IntoResponse {
+    match state.service.refresh_token(id).await {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
 /// GET /api/admin/credentials/:id/balance
 /// 获取指定凭据的余额
 pub async fn get_credential_balance(
@@ -124,3 +141,202 @@ pub async fn set_load_balancing_mode(
         Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
     }
 }
+
+/// GET /api/admin/config/thinking-extraction
+/// 获取运行时设置的 thinking 提取开关
+pub async fn get_thinking_extraction(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_thinking_extraction())
+}
+
+/// PUT /api/admin/config/thinking-extraction
+/// 设置模型的 thinking 提取开关
+pub async fn set_thinking_extraction(
+    State(state): State<AdminState>,
+    Json(payload): Json<SetThinkingExtractionRequest>,
+) -> impl IntoResponse {
+    match state.service.set_thinking_extraction(payload) {
+        Ok(response) => Json(response).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/status
+/// 获取运行状态（正在处理的请求数、凭据概况等）
+pub async fn get_status(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_status())
+}
+
+/// GET /api/admin/stats/stream
+/// 获取流式响应统计（慢客户端等）
+pub async fn get_stream_stats(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_stream_stats())
+}
+
+/// GET /api/admin/stats/errors
+/// 获取最近失败的上游尝试
+pub async fn get_recent_errors(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.get_recent_errors())
+}
+
+/// GET /api/admin/reports/usage
+/// 获取有用量报表的日期
+pub async fn list_usage_reports(State(state): State<AdminState>) -> impl IntoResponse {
+    Json(state.service.list_usage_reports())
+}
+
+/// GET /api/admin/reports/usage/:date
+/// 获取单日用量报表（`?format=csv` 导出 CSV）
+pub async fn get_usage_report(
+    State(state): State<AdminState>,
+    Path(date): Path<String>,
+    Query(query): Query<UsageReportQuery>,
+) -> impl IntoResponse {
+    match state.service.get_usage_report(&date) {
+        Ok(report) if query.format.as_deref() == Some("csv") => (
+            [(header::CONTENT_TYPE, "text/csv; charset=utf-8")],
+            report.to_csv(),
+        )
+            .into_response(),
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// GET /api/admin/conversations/:id/render
+/// 渲染会话记录（`?format=markdown` 导出 Markdown，默认 HTML）
+pub async fn render_conversation(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    Query(query): Query<RenderConversationQuery>,
+) -> impl IntoResponse {
+    let format = match TranscriptFormat::parse(query.format.as_deref()) {
+        Ok(format) => format,
+        Err(message) => {
+            return (
+                StatusCode::BAD_REQUEST,
+                Json(AdminErrorResponse::invalid_request(message)),
+            )
+                .into_response();
+        }
+    };
+
+    match state.service.render_conversation(&id, format) {
+        Ok(body) => {
+            let disposition = format!(
+                "inline; filename=\"conversation-{}.{}\"",
+                id.replace(|c: char| !c.is_ascii_alphanumeric() && c != '-', "_"),
+                format.extension()
+            );
+            (
+                [
+                    (header::CONTENT_TYPE, format.content_type().to_string()),
+                    (header::CONTENT_DISPOSITION, disposition),
+                ],
+                body,
+            )
+                .into_response()
+        }
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// 死信队列默认返回的条数
+const DEFAULT_DEAD_LETTER_LIMIT: usize = 50;
+
+/// GET /api/admin/dead-letters
+/// 查询转换失败的请求（`?kind=` 按错误类别过滤，`?limit=` 限制条数）
+pub async fn list_dead_letters(
+    State(state): State<AdminState>,
+    Query(query): Query<DeadLetterQuery>,
+) -> impl IntoResponse {
+    Json(state.service.list_dead_letters(
+        query.kind.as_deref(),
+        query.limit.unwrap_or(DEFAULT_DEAD_LETTER_LIMIT),
+    ))
+}
+
+/// GET /api/admin/dead-letters/:id
+/// 获取单条死信记录
+pub async fn get_dead_letter(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.get_dead_letter(&id) {
+        Ok(entry) => Json(entry).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// DELETE /api/admin/dead-letters
+/// 清空死信队列
+pub async fn clear_dead_letters(State(state): State<AdminState>) -> impl IntoResponse {
+    let count = state.service.clear_dead_letters();
+    Json(SuccessResponse::new(format!("已清除 {} 条死信记录", count)))
+}
+
+/// SSE 会话记录默认返回的条数
+const DEFAULT_SSE_TRANSCRIPT_LIMIT: usize = 50;
+
+/// GET /api/admin/sse-transcripts
+/// 查询 SSE 会话记录（`?limit=` 限制条数）
+pub async fn list_sse_transcripts(
+    State(state): State<AdminState>,
+    Query(query): Query<SseTranscriptQuery>,
+) -> impl IntoResponse {
+    Json(
+        state
+            .service
+            .list_sse_transcripts(query.limit.unwrap_or(DEFAULT_SSE_TRANSCRIPT_LIMIT)),
+    )
+}
+
+/// GET /api/admin/sse-transcripts/:id
+/// 下载 SSE 会话记录（流式解压为原始 SSE 文本）
+pub async fn get_sse_transcript(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.open_sse_transcript(&id) {
+        Ok((meta, stream)) => (
+            [
+                (
+                    header::CONTENT_TYPE,
+                    "text/plain; charset=utf-8".to_string(),
+                ),
+                (
+                    header::CONTENT_DISPOSITION,
+                    format!("inline; filename=\"{}.sse\"", meta.id),
+                ),
+            ],
+            Body::from_stream(stream),
+        )
+            .into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/models/:id/probe
+/// 经完整处理流程向模型发送探测请求，报告成功与否、首个增量耗时、thinking 呈现方式与工具调用支持
+pub async fn probe_model(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+) -> impl IntoResponse {
+    match state.service.probe_model(&id).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
+
+/// POST /api/admin/replay/:id
+/// 重放死信记录中的请求（可覆盖 model、thinking、converter），返回与原始结果的差异
+pub async fn replay_request(
+    State(state): State<AdminState>,
+    Path(id): Path<String>,
+    payload: Option<Json<ReplayRequest>>,
+) -> impl IntoResponse {
+    let overrides = payload.map(|Json(p)| p).unwrap_or_default();
+    match state.service.replay(&id, &overrides).await {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => (e.status_code(), Json(e.into_response())).into_response(),
+    }
+}
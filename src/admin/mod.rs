@@ -19,6 +19,8 @@
 mod error;
 mod handlers;
 mod middleware;
+mod probe;
+mod replay;
 mod router;
 mod service;
 pub mod types;
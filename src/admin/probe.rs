@@ -0,0 +1,231 @@
+//! 模型能力探测
+//!
+//! 通过完整的 `/v1/messages` 处理流程（模型映射、转换、上游调用、流式转换）发送一个极小的
+//! 固定请求，记录首个内容增量的到达时间、thinking 的呈现方式以及是否产生了工具调用，
+//! 用于在 Kiro 侧模型更新后发现静默的行为变化
+
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+};
+use futures::StreamExt;
+use serde_json::{Value, json};
+
+use crate::anthropic::types::MessagesRequest;
+use crate::anthropic::{self, AppState};
+use crate::common::telemetry::TracedJson;
+
+use super::types::{ModelProbeResponse, ThinkingBehavior};
+
+/// 探测请求的截止时间
+const PROBE_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// 探测用的工具名
+const PROBE_TOOL: &str = "get_current_time";
+
+/// 构造探测请求：开启 thinking 并要求调用一个无参数工具
+fn probe_request(model: &str) -> Result<MessagesRequest, String> {
+    serde_json::from_value(json!({
+        "model": model,
+        "max_tokens": 2048,
+        "stream": true,
+        "thinking": { "type": "enabled", "budget_tokens": 1024 },
+        "messages": [{
+            "role": "user",
+            "content": format!("What time is it? Call the {} tool to find out.", PROBE_TOOL)
+        }],
+        "tools": [{
+            "name": PROBE_TOOL,
+            "description": "Returns the current time.",
+            "input_schema": { "type": "object", "properties": {} }
+        }]
+    }))
+    .map_err(|e| e.to_string())
+}
+
+/// 对单个模型执行探测
+pub async fn run(state: AppState, model: &str) -> ModelProbeResponse {
+    let mut observer = ProbeObserver::new(model);
+    let request = match probe_request(model) {
+        Ok(request) => request,
+        Err(message) => return observer.fail(None, message),
+    };
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        "x-kiro-deadline-ms",
+        HeaderValue::from(PROBE_TIMEOUT.as_millis() as u64),
+    );
+
+    let started = Instant::now();
+    let response = anthropic::post_messages(State(state), None, headers, TracedJson(request)).await;
+    let status = response.status();
+    let mut body = response.into_body().into_data_stream();
+    let mut buffer = Vec::new();
+
+    while let Some(chunk) = body.next().await {
+        let chunk = match chunk {
+            Ok(chunk) => chunk,
+            Err(e) => return observer.fail(Some(status.as_u16()), e.to_string()),
+        };
+        buffer.extend_from_slice(&chunk);
+        if !status.is_success() {
+            continue;
+        }
+        while let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+            let block: Vec<u8> = buffer.drain(..end + 2).collect();
+            let block = String::from_utf8_lossy(&block);
+            let data: Vec<&str> = block
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(str::trim_start)
+                .collect();
+            if let Ok(event) = serde_json::from_str::<Value>(&data.join("\n")) {
+                observer.observe(&event, started.elapsed());
+            }
+        }
+    }
+
+    if !status.is_success() {
+        let message = serde_json::from_slice::<Value>(&buffer)
+            .ok()
+            .and_then(|v| v["error"]["message"].as_str().map(str::to_string))
+            .unwrap_or_else(|| String::from_utf8_lossy(&buffer).into_owned());
+        return observer.fail(Some(status.as_u16()), message);
+    }
+    observer.finish(status.as_u16(), started.elapsed())
+}
+
+/// 从 Anthropic 流式事件中收集探测结果
+#[derive(Debug)]
+struct ProbeObserver {
+    report: ModelProbeResponse,
+    saw_thinking_block: bool,
+    saw_thinking_tag: bool,
+    saw_message_stop: bool,
+}
+
+impl ProbeObserver {
+    fn new(model: &str) -> Self {
+        Self {
+            report: ModelProbeResponse {
+                model: model.to_string(),
+                success: false,
+                status: None,
+                ttfb_ms: None,
+                duration_ms: 0,
+                thinking: ThinkingBehavior::Absent,
+                tool_call: false,
+                stop_reason: None,
+                error: None,
+            },
+            saw_thinking_block: false,
+            saw_thinking_tag: false,
+            saw_message_stop: false,
+        }
+    }
+
+    fn observe(&mut self, event: &Value, elapsed: Duration) {
+        match event["type"].as_str() {
+            Some("content_block_start") => match event["content_block"]["type"].as_str() {
+                Some("thinking") => self.saw_thinking_block = true,
+                Some("tool_use") if event["content_block"]["name"] == PROBE_TOOL => {
+                    self.report.tool_call = true;
+                }
+                _ => {}
+            },
+            Some("content_block_delta") => {
+                if self.report.ttfb_ms.is_none() {
+                    self.report.ttfb_ms = Some(elapsed.as_millis() as u64);
+                }
+                if let Some(text) = event["delta"]["text"].as_str()
+                    && (text.contains("<thinking>") || text.contains("</thinking>"))
+                {
+                    self.saw_thinking_tag = true;
+                }
+            }
+            Some("message_delta") => {
+                if let Some(reason) = event["delta"]["stop_reason"].as_str() {
+                    self.report.stop_reason = Some(reason.to_string());
+                }
+            }
+            Some("message_stop") => self.saw_message_stop = true,
+            Some("error") => {
+                self.report.error = event["error"]["message"].as_str().map(str::to_string);
+            }
+            _ => {}
+        }
+    }
+
+    fn finish(mut self, status: u16, elapsed: Duration) -> ModelProbeResponse {
+        self.report.status = Some(status);
+        self.report.duration_ms = elapsed.as_millis() as u64;
+        self.report.thinking = match (self.saw_thinking_block, self.saw_thinking_tag) {
+            (_, true) => ThinkingBehavior::Leaked,
+            (true, false) => ThinkingBehavior::Extracted,
+            (false, false) => ThinkingBehavior::Absent,
+        };
+        self.report.success = self.saw_message_stop && self.report.error.is_none();
+        if !self.saw_message_stop && self.report.error.is_none() {
+            self.report.error = Some("响应流在 message_stop 之前结束".to_string());
+        }
+        self.report
+    }
+
+    fn fail(mut self, status: Option<u16>, message: String) -> ModelProbeResponse {
+        self.report.status = status;
+        self.report.error = Some(message);
+        self.report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn observe_all(events: &[Value]) -> ModelProbeResponse {
+        let mut observer = ProbeObserver::new("claude-sonnet-4");
+        for (i, event) in events.iter().enumerate() {
+            observer.observe(event, Duration::from_millis(100 * (i as u64 + 1)));
+        }
+        observer.finish(200, Duration::from_secs(1))
+    }
+
+    #[test]
+    fn test_probe_request_is_valid() {
+        let request = probe_request("claude-sonnet-4").unwrap();
+        assert!(request.stream);
+        assert!(request.thinking.unwrap().is_enabled());
+        assert_eq!(request.tools.unwrap()[0].name, PROBE_TOOL);
+    }
+
+    #[test]
+    fn test_observer_reports_thinking_and_tool_call() {
+        let report = observe_all(&[
+            json!({"type": "message_start", "message": {}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "thinking"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "thinking_delta", "thinking": "hmm"}}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "name": PROBE_TOOL}}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}}),
+            json!({"type": "message_stop"}),
+        ]);
+        assert!(report.success);
+        assert_eq!(report.ttfb_ms, Some(300));
+        assert_eq!(report.thinking, ThinkingBehavior::Extracted);
+        assert!(report.tool_call);
+        assert_eq!(report.stop_reason.as_deref(), Some("tool_use"));
+    }
+
+    #[test]
+    fn test_observer_detects_leaked_thinking_and_truncation() {
+        let report = observe_all(&[
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text"}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "<thinking>x"}}),
+        ]);
+        assert!(!report.success);
+        assert_eq!(report.thinking, ThinkingBehavior::Leaked);
+        assert!(!report.tool_call);
+        assert!(report.error.is_some());
+    }
+}
@@ -0,0 +1,246 @@
+//! 请求重放
+//!
+//! 按死信记录保存的请求体重新执行一次完整的 `/v1/messages` 处理流程（以非流式请求执行），
+//! 可覆盖模型、thinking 设置与转换器版本，并与原始结果对比，用于调整模型映射或配置后
+//! 确认问题是否消失，或定位模型行为的回归。
+//!
+//! 记录中的请求体经过脱敏（凭据字段、base64 图片被替换），被截断的记录无法重放；
+//! 重放失败不会再写入死信队列
+
+use std::time::Instant;
+
+use axum::{
+    extract::State,
+    http::{HeaderMap, HeaderValue},
+};
+use serde_json::{Value, json};
+
+use crate::anthropic::dead_letter::DeadLetter;
+use crate::anthropic::types::MessagesRequest;
+use crate::anthropic::{self, AppState};
+use crate::common::telemetry::TracedJson;
+
+use super::types::{ReplayOutcome, ReplayRequest, ReplayResponse};
+
+/// 转换器版本请求头
+const CONVERTER_HEADER: &str = "x-kiro-converter";
+
+/// 按记录与覆盖参数构造重放请求
+fn replay_request(
+    entry: &DeadLetter,
+    overrides: &ReplayRequest,
+) -> Result<MessagesRequest, String> {
+    if entry.truncated {
+        return Err(format!(
+            "记录的请求体已被截断（原始 {} 字节），无法重放",
+            entry.payload_bytes
+        ));
+    }
+    let mut payload: Value = serde_json::from_str(&entry.payload)
+        .map_err(|e| format!("记录的请求体不是有效的 JSON: {}", e))?;
+
+    // 死信只保存了 system 的文本
+    if let Some(system) = payload["system"].as_array_mut() {
+        for item in system.iter_mut() {
+            if let Some(text) = item.as_str() {
+                *item = json!({ "type": "text", "text": text });
+            }
+        }
+    }
+    if let Some(model) = &overrides.model {
+        payload["model"] = json!(model);
+    }
+    if let Some(thinking) = &overrides.thinking {
+        payload["thinking"] = thinking.clone();
+    }
+    payload["stream"] = json!(false);
+
+    serde_json::from_value(payload).map_err(|e| format!("无法解析记录的请求体: {}", e))
+}
+
+/// 死信记录对应的原始结果（转换失败时返回 400 invalid_request_error）
+fn original_outcome(entry: &DeadLetter) -> ReplayOutcome {
+    ReplayOutcome {
+        model: entry.model.clone(),
+        status: 400,
+        success: false,
+        error_type: Some("invalid_request_error".to_string()),
+        error: Some(entry.error.clone()),
+        ..Default::default()
+    }
+}
+
+/// 从非流式响应中提取结果摘要
+fn outcome_from_response(model: &str, status: u16, body: &Value) -> ReplayOutcome {
+    let success = (200..300).contains(&status);
+    ReplayOutcome {
+        model: model.to_string(),
+        status,
+        success,
+        error_type: body["error"]["type"].as_str().map(str::to_string),
+        error: body["error"]["message"].as_str().map(str::to_string),
+        stop_reason: body["stop_reason"].as_str().map(str::to_string),
+        content: body["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["type"].as_str().map(str::to_string))
+            .collect(),
+        output_tokens: body["usage"]["output_tokens"].as_u64(),
+    }
+}
+
+/// 对比两次结果，列出发生变化的字段
+fn diff(original: &ReplayOutcome, replayed: &ReplayOutcome) -> Vec<String> {
+    fn show<T: std::fmt::Debug>(value: &Option<T>) -> String {
+        match value {
+            Some(v) => format!("{:?}", v),
+            None => "-".to_string(),
+        }
+    }
+
+    let mut changes = Vec::new();
+    if original.model != replayed.model {
+        changes.push(format!("model: {} -> {}", original.model, replayed.model));
+    }
+    if original.status != replayed.status {
+        changes.push(format!(
+            "status: {} -> {}",
+            original.status, replayed.status
+        ));
+    }
+    if original.error_type != replayed.error_type {
+        changes.push(format!(
+            "errorType: {} -> {}",
+            show(&original.error_type),
+            show(&replayed.error_type)
+        ));
+    }
+    if original.error != replayed.error {
+        changes.push(format!(
+            "error: {} -> {}",
+            show(&original.error),
+            show(&replayed.error)
+        ));
+    }
+    if original.stop_reason != replayed.stop_reason {
+        changes.push(format!(
+            "stopReason: {} -> {}",
+            show(&original.stop_reason),
+            show(&replayed.stop_reason)
+        ));
+    }
+    if original.content != replayed.content {
+        changes.push(format!(
+            "content: {:?} -> {:?}",
+            original.content, replayed.content
+        ));
+    }
+    changes
+}
+
+/// 重放一条死信记录
+pub async fn run(
+    mut state: AppState,
+    entry: &DeadLetter,
+    overrides: &ReplayRequest,
+) -> Result<ReplayResponse, String> {
+    let request = replay_request(entry, overrides)?;
+    let model = request.model.clone();
+    let mut headers = HeaderMap::new();
+    if let Some(converter) = &overrides.converter {
+        let value = HeaderValue::from_str(converter).map_err(|_| "无效的转换器版本".to_string())?;
+        headers.insert(CONVERTER_HEADER, value);
+    }
+    state.dead_letters = None;
+
+    let started = Instant::now();
+    let response = anthropic::post_messages(State(state), None, headers, TracedJson(request)).await;
+    let status = response.status().as_u16();
+    let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+        .await
+        .map_err(|e| format!("读取重放响应失败: {}", e))?;
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let original = original_outcome(entry);
+    let replayed = outcome_from_response(&model, status, &body);
+    Ok(ReplayResponse {
+        id: entry.id.clone(),
+        changes: diff(&original, &replayed),
+        original,
+        replayed,
+        duration_ms: started.elapsed().as_millis() as u64,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dead_letter(payload: Value) -> DeadLetter {
+        DeadLetter {
+            id: "dl-1".to_string(),
+            at: "2026-01-01T00:00:00Z".to_string(),
+            endpoint: "/v1/messages".to_string(),
+            key: "sk-***".to_string(),
+            model: "gpt-4o".to_string(),
+            kind: "unsupported_model".to_string(),
+            error: "模型不支持: gpt-4o".to_string(),
+            payload_bytes: payload.to_string().len(),
+            payload: payload.to_string(),
+            truncated: false,
+        }
+    }
+
+    #[test]
+    fn test_replay_request_applies_overrides() {
+        let entry = dead_letter(json!({
+            "model": "gpt-4o",
+            "max_tokens": 1024,
+            "stream": true,
+            "system": ["You are terse."],
+            "messages": [{ "role": "user", "content": "hi" }],
+            "thinking": { "type": "enabled", "budget_tokens": 2048 }
+        }));
+        let overrides = ReplayRequest {
+            model: Some("claude-sonnet-4-6".to_string()),
+            thinking: Some(json!({ "type": "disabled" })),
+            converter: None,
+        };
+        let request = replay_request(&entry, &overrides).unwrap();
+        assert_eq!(request.model, "claude-sonnet-4-6");
+        assert!(!request.stream);
+        assert!(!request.thinking.unwrap().is_enabled());
+        assert_eq!(request.system.unwrap()[0].text, "You are terse.");
+
+        let truncated = DeadLetter {
+            truncated: true,
+            ..entry
+        };
+        assert!(replay_request(&truncated, &ReplayRequest::default()).is_err());
+    }
+
+    #[test]
+    fn test_diff_against_original() {
+        let entry = dead_letter(json!({}));
+        let original = original_outcome(&entry);
+        let replayed = outcome_from_response(
+            "claude-sonnet-4-6",
+            200,
+            &json!({
+                "content": [{ "type": "thinking" }, { "type": "text", "text": "hello" }],
+                "stop_reason": "end_turn",
+                "usage": { "input_tokens": 10, "output_tokens": 5 }
+            }),
+        );
+        assert!(replayed.success);
+        assert_eq!(replayed.output_tokens, Some(5));
+
+        let changes = diff(&original, &replayed);
+        assert_eq!(changes[0], "model: gpt-4o -> claude-sonnet-4-6");
+        assert_eq!(changes[1], "status: 400 -> 200");
+        assert!(changes.iter().any(|c| c.starts_with("stopReason: - -> ")));
+        assert!(changes.iter().any(|c| c.starts_with("content: [] -> ")));
+        assert!(diff(&original, &original).is_empty());
+    }
+}
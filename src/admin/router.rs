@@ -7,9 +7,13 @@ use axum::{
 
 use super::{
     handlers::{
-        add_credential, delete_credential, get_all_credentials, get_credential_balance,
-        get_load_balancing_mode, reset_failure_count, set_credential_disabled,
-        set_credential_priority, set_load_balancing_mode,
+        add_credential, clear_dead_letters, delete_credential, get_dead_letter, list_dead_letters,
+        get_all_credentials, get_credential_balance, get_load_balancing_mode, get_recent_errors,
+        get_sse_transcript, get_status, get_stream_stats, get_thinking_extraction,
+        get_usage_report, list_sse_transcripts, list_usage_reports, probe_model,
+        refresh_credential_token, render_conversation, replay_request, reset_failure_count,
+        set_credential_disabled, set_credential_priority, set_load_balancing_mode,
+        set_thinking_extraction,
     },
     middleware::{AdminState, admin_auth_middleware},
 };
@@ -23,9 +27,23 @@ use super::{
 /// - `POST /credentials/:id/disabled` - 设置凭据禁用状态
 /// - `POST /credentials/:id/priority` - 设置凭据优先级
 /// - `POST /credentials/:id/reset` - 重置失败计数
+/// - `POST /credentials/:id/refresh` - 立即刷新凭据 Token
 /// - `GET /credentials/:id/balance` - 获取凭据余额
 /// - `GET /config/load-balancing` - 获取负载均衡模式
 /// - `PUT /config/load-balancing` - 设置负载均衡模式
+/// - `GET /config/thinking-extraction` - 获取运行时设置的 thinking 提取开关
+/// - `PUT /config/thinking-extraction` - 设置模型的 thinking 提取开关
+/// - `GET /status` - 获取运行状态（正在处理的请求数、凭据概况等）
+/// - `GET /stats/stream` - 获取流式响应统计
+/// - `GET /stats/errors` - 获取最近失败的上游尝试
+/// - `GET /reports/usage` - 获取有用量报表的日期
+/// - `GET /reports/usage/:date` - 获取单日用量报表（`?format=csv` 导出 CSV）
+/// - `GET /dead-letters` - 查询转换失败的请求（`?kind=` 按错误类别过滤，`?limit=` 限制条数）
+/// - `GET /dead-letters/:id` - 获取单条死信记录
+/// - `DELETE /dead-letters` - 清空死信队列
+/// - `GET /conversations/:id/render` - 渲染会话记录（`?format=markdown` 导出 Markdown，默认 HTML）
+/// - `POST /models/:id/probe` - 向模型发送探测请求，报告能力与首个增量耗时
+/// - `POST /replay/:id` - 重放死信记录中的请求（可覆盖模型、thinking 与转换器版本），返回与原始结果的差异
 ///
 /// # 认证
 /// 需要 Admin API Key 认证，支持：
@@ -41,11 +59,31 @@ pub fn create_admin_router(state: AdminState) -> Router {
         .route("/credentials/{id}/disabled", post(set_credential_disabled))
         .route("/credentials/{id}/priority", post(set_credential_priority))
         .route("/credentials/{id}/reset", post(reset_failure_count))
+        .route("/credentials/{id}/refresh", post(refresh_credential_token))
         .route("/credentials/{id}/balance", get(get_credential_balance))
         .route(
             "/config/load-balancing",
             get(get_load_balancing_mode).put(set_load_balancing_mode),
         )
+        .route(
+            "/config/thinking-extraction",
+            get(get_thinking_extraction).put(set_thinking_extraction),
+        )
+        .route("/status", get(get_status))
+        .route("/stats/stream", get(get_stream_stats))
+        .route("/stats/errors", get(get_recent_errors))
+        .route("/reports/usage", get(list_usage_reports))
+        .route("/reports/usage/{date}", get(get_usage_report))
+        .route(
+            "/dead-letters",
+            get(list_dead_letters).delete(clear_dead_letters),
+        )
+        .route("/dead-letters/{id}", get(get_dead_letter))
+        .route("/sse-transcripts", get(list_sse_transcripts))
+        .route("/sse-transcripts/{id}", get(get_sse_transcript))
+        .route("/conversations/{id}/render", get(render_conversation))
+        .route("/models/{id}/probe", post(probe_model))
+        .route("/replay/{id}", post(replay_request))
         .layer(middleware::from_fn_with_state(
             state.clone(),
             admin_auth_middleware,
@@ -8,13 +8,25 @@ use chrono::Utc;
 use parking_lot::Mutex;
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::dead_letter::DeadLetter;
+use crate::anthropic::models;
+use crate::anthropic::sse_transcript::SseTranscript;
+use crate::anthropic::{AppState, DeadLetterStore, SessionStore};
+use crate::anthropic::transcript::{self, TranscriptFormat};
+use crate::common::metrics;
+use crate::common::usage::{self, DailyReport};
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::token_manager::MultiTokenManager;
 
 use super::error::AdminServiceError;
+use super::{probe, replay};
 use super::types::{
     AddCredentialRequest, AddCredentialResponse, BalanceResponse, CredentialStatusItem,
-    CredentialsStatusResponse, LoadBalancingModeResponse, SetLoadBalancingModeRequest,
+    CredentialsStatusResponse, DeadLetterListResponse, LoadBalancingModeResponse,
+    ModelProbeResponse, RecentErrorsResponse, RefreshTokenResponse, ReplayRequest, ReplayResponse,
+    RuntimeStatusResponse, SetLoadBalancingModeRequest, SetThinkingExtractionRequest,
+    SseTranscriptListResponse, StreamStatsResponse, ThinkingExtractionResponse,
+    UsageReportListResponse,
 };
 
 /// 余额缓存过期时间（秒），5 分钟
@@ -36,6 +48,11 @@ pub struct AdminService {
     token_manager: Arc<MultiTokenManager>,
     balance_cache: Mutex<HashMap<u64, CachedBalance>>,
     cache_path: Option<PathBuf>,
+    /// 会话存储（用于渲染会话记录）
+    session_store: Option<Arc<SessionStore>>,
+    dead_letters: Option<Arc<DeadLetterStore>>,
+    /// Anthropic API 状态（用于模型探测）
+    app_state: Option<AppState>,
 }
 
 impl AdminService {
@@ -50,9 +67,30 @@ impl AdminService {
             token_manager,
             balance_cache: Mutex::new(balance_cache),
             cache_path,
+            session_store: None,
+            dead_letters: None,
+            app_state: None,
         }
     }
 
+    /// 设置会话存储
+    pub fn with_session_store(mut self, store: Arc<SessionStore>) -> Self {
+        self.session_store = Some(store);
+        self
+    }
+
+    /// 设置转换失败死信队列
+    pub fn with_dead_letters(mut self, store: Arc<DeadLetterStore>) -> Self {
+        self.dead_letters = Some(store);
+        self
+    }
+
+    /// 设置 Anthropic API 状态（模型探测经由完整的消息处理流程）
+    pub fn with_app_state(mut self, state: AppState) -> Self {
+        self.app_state = Some(state);
+        self
+    }
+
     /// 获取所有凭据状态
     pub fn get_all_credentials(&self) -> CredentialsStatusResponse {
         let snapshot = self.token_manager.snapshot();
@@ -75,6 +113,7 @@ impl AdminService {
                 last_used_at: entry.last_used_at.clone(),
                 has_proxy: entry.has_proxy,
                 proxy_url: entry.proxy_url,
+                health: entry.health,
             })
             .collect();
 
@@ -209,6 +248,11 @@ impl AdminService {
             proxy_password: req.proxy_password,
             disabled: false, // 新添加的凭据默认启用
         };
+        if let Some(proxy) = new_cred.effective_proxy(None) {
+            proxy
+                .validate()
+                .map_err(AdminServiceError::InvalidCredential)?;
+        }
 
         // 调用 token_manager 添加凭据
         let credential_id = self
@@ -246,6 +290,30 @@ impl AdminService {
         Ok(())
     }
 
+    /// 立即刷新凭据的 Token
+    pub async fn refresh_token(&self, id: u64) -> Result<RefreshTokenResponse, AdminServiceError> {
+        let expires_at = self
+            .token_manager
+            .force_refresh(id)
+            .await
+            .map_err(|e| self.classify_balance_error(e, id))?;
+        Ok(RefreshTokenResponse { id, expires_at })
+    }
+
+    /// 获取运行状态
+    pub fn get_status(&self) -> RuntimeStatusResponse {
+        let snapshot = self.token_manager.snapshot();
+        RuntimeStatusResponse {
+            version: env!("CARGO_PKG_VERSION"),
+            in_flight_requests: metrics::in_flight().active(),
+            active_streams: metrics::active_streams().snapshot().active,
+            total_credentials: snapshot.total,
+            available_credentials: snapshot.available,
+            current_id: snapshot.current_id,
+            load_balancing_mode: self.token_manager.get_load_balancing_mode(),
+        }
+    }
+
     /// 获取负载均衡模式
     pub fn get_load_balancing_mode(&self) -> LoadBalancingModeResponse {
         LoadBalancingModeResponse {
@@ -272,6 +340,162 @@ impl AdminService {
         Ok(LoadBalancingModeResponse { mode: req.mode })
     }
 
+    /// 获取运行时设置的 thinking 提取开关
+    pub fn get_thinking_extraction(&self) -> ThinkingExtractionResponse {
+        ThinkingExtractionResponse {
+            overrides: models::thinking_extraction_overrides(),
+        }
+    }
+
+    /// 运行时设置模型的 thinking 提取开关（重启后恢复为配置值）
+    pub fn set_thinking_extraction(
+        &self,
+        req: SetThinkingExtractionRequest,
+    ) -> Result<ThinkingExtractionResponse, AdminServiceError> {
+        let target = models::set_thinking_extraction(&req.model, req.enabled)
+            .ok_or(AdminServiceError::ModelNotFound { model: req.model })?;
+        tracing::info!(
+            "模型 {} 的 thinking 提取开关设置为 {:?}",
+            target,
+            req.enabled
+        );
+        Ok(self.get_thinking_extraction())
+    }
+
+    /// 获取流式响应统计
+    pub fn get_stream_stats(&self) -> StreamStatsResponse {
+        metrics::snapshot()
+    }
+
+    /// 获取最近失败的上游尝试
+    pub fn get_recent_errors(&self) -> RecentErrorsResponse {
+        RecentErrorsResponse {
+            errors: metrics::recent_errors().list(),
+        }
+    }
+
+    /// 获取有用量报表的日期
+    pub fn list_usage_reports(&self) -> UsageReportListResponse {
+        UsageReportListResponse {
+            dates: usage::store().map(|s| s.dates()).unwrap_or_default(),
+        }
+    }
+
+    /// 获取指定日期（UTC，`YYYY-MM-DD`）的用量报表
+    pub fn get_usage_report(&self, date: &str) -> Result<DailyReport, AdminServiceError> {
+        usage::store()
+            .filter(|_| usage::is_valid_date(date))
+            .and_then(|s| s.report(date))
+            .ok_or_else(|| AdminServiceError::ReportNotFound {
+                date: date.to_string(),
+            })
+    }
+
+    /// 渲染会话记录（会话存储中保存的历史）
+    pub fn render_conversation(
+        &self,
+        id: &str,
+        format: TranscriptFormat,
+    ) -> Result<String, AdminServiceError> {
+        let history = self
+            .session_store
+            .as_ref()
+            .and_then(|store| store.history(id))
+            .filter(|history| !history.is_empty())
+            .ok_or_else(|| AdminServiceError::ConversationNotFound { id: id.to_string() })?;
+        Ok(transcript::render(id, &history, format))
+    }
+
+    /// 查询转换失败的请求（新的在前）
+    pub fn list_dead_letters(&self, kind: Option<&str>, limit: usize) -> DeadLetterListResponse {
+        match &self.dead_letters {
+            Some(store) => DeadLetterListResponse {
+                enabled: true,
+                counts: store.counts(),
+                entries: store.list(kind, limit),
+            },
+            None => DeadLetterListResponse {
+                enabled: false,
+                counts: Default::default(),
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    /// 获取单条死信记录
+    pub fn get_dead_letter(&self, id: &str) -> Result<DeadLetter, AdminServiceError> {
+        self.dead_letters
+            .as_ref()
+            .and_then(|store| store.get(id))
+            .ok_or_else(|| AdminServiceError::DeadLetterNotFound { id: id.to_string() })
+    }
+
+    /// 清空死信队列，返回清除的条数
+    pub fn clear_dead_letters(&self) -> usize {
+        self.dead_letters.as_ref().map_or(0, |store| store.clear())
+    }
+
+    /// 查询 SSE 会话记录（新的在前）
+    pub fn list_sse_transcripts(&self, limit: usize) -> SseTranscriptListResponse {
+        match self
+            .app_state
+            .as_ref()
+            .and_then(|s| s.sse_transcripts.as_ref())
+        {
+            Some(store) => SseTranscriptListResponse {
+                enabled: true,
+                entries: store.list(limit),
+            },
+            None => SseTranscriptListResponse {
+                enabled: false,
+                entries: Vec::new(),
+            },
+        }
+    }
+
+    /// 打开 SSE 会话记录，返回元数据与解压后的字节流
+    pub fn open_sse_transcript(
+        &self,
+        id: &str,
+    ) -> Result<
+        (
+            SseTranscript,
+            impl futures::Stream<Item = std::io::Result<bytes::Bytes>> + use<>,
+        ),
+        AdminServiceError,
+    > {
+        self.app_state
+            .as_ref()
+            .and_then(|s| s.sse_transcripts.as_ref())
+            .and_then(|store| store.open(id))
+            .ok_or_else(|| AdminServiceError::SseTranscriptNotFound { id: id.to_string() })
+    }
+
+    /// 向指定模型发送探测请求
+    pub async fn probe_model(&self, model: &str) -> Result<ModelProbeResponse, AdminServiceError> {
+        let state = self
+            .app_state
+            .clone()
+            .ok_or_else(|| AdminServiceError::InternalError("未配置 Anthropic API 状态".into()))?;
+        Ok(probe::run(state, model).await)
+    }
+
+    /// 重放死信记录中的请求并与原始结果对比
+    pub async fn replay(
+        &self,
+        id: &str,
+        overrides: &ReplayRequest,
+    ) -> Result<ReplayResponse, AdminServiceError> {
+        let entry = self.get_dead_letter(id)?;
+        let state = self
+            .app_state
+            .clone()
+            .ok_or_else(|| AdminServiceError::InternalError("未配置 Anthropic API 状态".into()))?;
+        replay::run(state, &entry, overrides)
+            .await
+            .map_err(AdminServiceError::ReplayUnavailable)
+    }
+
     // ============ 余额缓存持久化 ============
 
     fn load_balance_cache_from(cache_path: &Option<PathBuf>) -> HashMap<u64, CachedBalance> {
@@ -341,7 +565,7 @@ impl AdminService {
         }
     }
 
-    /// 分类余额查询错误（可能涉及上游 API 调用）
+    /// 分类余额查询与 Token 刷新错误（可能涉及上游 API 调用）
     fn classify_balance_error(&self, e: anyhow::Error, id: u64) -> AdminServiceError {
         let msg = e.to_string();
 
@@ -1,7 +1,14 @@
 //! Admin API 类型定义
 
+use std::collections::BTreeMap;
+
 use serde::{Deserialize, Serialize};
 
+use crate::anthropic::dead_letter::DeadLetter;
+use crate::anthropic::sse_transcript::SseTranscript;
+use crate::common::metrics::{MetricsSnapshot, RecentError};
+use crate::kiro::health::HealthSnapshot;
+
 // ============ 凭据状态 ============
 
 /// 所有凭据状态响应
@@ -51,6 +58,8 @@ pub struct CredentialStatusItem {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 健康度（评分、平均延迟、错误率、限流率）
+    pub health: HealthSnapshot,
 }
 
 // ============ 操作请求 ============
@@ -158,6 +167,38 @@ pub struct BalanceResponse {
     pub next_reset_at: Option<f64>,
 }
 
+/// 手动刷新 Token 响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RefreshTokenResponse {
+    /// 凭据 ID
+    pub id: u64,
+    /// 刷新后的过期时间（RFC3339）
+    pub expires_at: Option<String>,
+}
+
+// ============ 运行状态 ============
+
+/// 运行状态响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RuntimeStatusResponse {
+    /// 服务版本
+    pub version: &'static str,
+    /// 正在处理的 API 请求数
+    pub in_flight_requests: u64,
+    /// 正在转发的流式响应数
+    pub active_streams: u64,
+    /// 凭据总数
+    pub total_credentials: usize,
+    /// 可用凭据数量（未禁用）
+    pub available_credentials: usize,
+    /// 当前活跃凭据 ID
+    pub current_id: u64,
+    /// 负载均衡模式
+    pub load_balancing_mode: String,
+}
+
 // ============ 负载均衡配置 ============
 
 /// 负载均衡模式响应
@@ -176,6 +217,187 @@ pub struct SetLoadBalancingModeRequest {
     pub mode: String,
 }
 
+// ============ thinking 提取开关 ============
+
+/// 运行时设置的 thinking 提取开关（按 Kiro 模型 ID）
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingExtractionResponse {
+    /// Kiro 模型 ID → 是否提取 thinking 块（未列出的模型使用配置值）
+    pub overrides: BTreeMap<String, bool>,
+}
+
+/// 设置 thinking 提取开关请求
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SetThinkingExtractionRequest {
+    /// 客户端模型名或 Kiro 模型 ID
+    pub model: String,
+    /// 是否提取 thinking 块，`null` 表示恢复配置值
+    pub enabled: Option<bool>,
+}
+
+// ============ 运行统计 ============
+
+/// 流式响应统计
+pub type StreamStatsResponse = MetricsSnapshot;
+
+/// 最近错误响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentErrorsResponse {
+    /// 最近失败的上游尝试（新的在前）
+    pub errors: Vec<RecentError>,
+}
+
+/// 用量报表列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageReportListResponse {
+    /// 有报表的日期（UTC，升序）
+    pub dates: Vec<String>,
+}
+
+/// 用量报表查询参数
+#[derive(Debug, Deserialize)]
+pub struct UsageReportQuery {
+    /// 导出格式：`json`（默认）或 `csv`
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// 会话记录渲染查询参数
+#[derive(Debug, Deserialize)]
+pub struct RenderConversationQuery {
+    /// 渲染格式：`html`（默认）或 `markdown`
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+/// 死信队列查询参数
+#[derive(Debug, Deserialize)]
+pub struct DeadLetterQuery {
+    /// 按错误类别过滤，如 `unsupported_model`
+    #[serde(default)]
+    pub kind: Option<String>,
+    /// 最多返回的条数（默认 50）
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// 死信队列响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetterListResponse {
+    /// 是否启用了死信队列
+    pub enabled: bool,
+    /// 按错误类别统计的条数（全部记录）
+    pub counts: BTreeMap<String, usize>,
+    /// 记录（新的在前）
+    pub entries: Vec<DeadLetter>,
+}
+
+/// SSE 会话记录查询参数
+#[derive(Debug, Deserialize)]
+pub struct SseTranscriptQuery {
+    /// 最多返回的条数（默认 50）
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
+
+/// SSE 会话记录列表响应
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SseTranscriptListResponse {
+    /// 是否启用了 SSE 会话记录
+    pub enabled: bool,
+    /// 记录（新的在前）
+    pub entries: Vec<SseTranscript>,
+}
+
+// ============ 模型探测 ============
+
+/// 探测中观察到的 thinking 呈现方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ThinkingBehavior {
+    /// 以独立的 thinking 内容块返回
+    Extracted,
+    /// `<thinking>` 标签出现在正文中（提取失效）
+    Leaked,
+    /// 未观察到 thinking
+    Absent,
+}
+
+/// 模型探测结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ModelProbeResponse {
+    pub model: String,
+    /// 请求成功且流正常结束
+    pub success: bool,
+    /// 处理流程返回的 HTTP 状态码
+    pub status: Option<u16>,
+    /// 首个内容增量到达的时间（毫秒）
+    pub ttfb_ms: Option<u64>,
+    /// 完整响应耗时（毫秒）
+    pub duration_ms: u64,
+    pub thinking: ThinkingBehavior,
+    /// 是否按要求调用了探测工具
+    pub tool_call: bool,
+    pub stop_reason: Option<String>,
+    pub error: Option<String>,
+}
+
+// ============ 请求重放 ============
+
+/// 请求重放参数（均为可选，未指定时沿用原请求）
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReplayRequest {
+    /// 覆盖模型
+    pub model: Option<String>,
+    /// 覆盖 thinking 设置，如 `{"type":"disabled"}`
+    pub thinking: Option<serde_json::Value>,
+    /// 指定转换器版本（同 `x-kiro-converter`）
+    pub converter: Option<String>,
+}
+
+/// 一次请求的处理结果摘要
+#[derive(Debug, Clone, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayOutcome {
+    pub model: String,
+    /// HTTP 状态码
+    pub status: u16,
+    pub success: bool,
+    /// Anthropic 错误类型，如 `invalid_request_error`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_type: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stop_reason: Option<String>,
+    /// 响应内容块类型（按顺序）
+    pub content: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output_tokens: Option<u64>,
+}
+
+/// 请求重放结果
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ReplayResponse {
+    /// 重放的死信记录 ID
+    pub id: String,
+    pub original: ReplayOutcome,
+    pub replayed: ReplayOutcome,
+    /// 与原始结果相比发生变化的字段，如 `status: 400 -> 200`
+    pub changes: Vec<String>,
+    /// 重放耗时（毫秒）
+    pub duration_ms: u64,
+}
+
 // ============ 通用响应 ============
 
 /// 操作成功响应
@@ -14,13 +14,35 @@ use rust_embed::Embed;
 #[folder = "admin-ui/dist"]
 struct Asset;
 
+/// 内置状态面板（单页，不依赖前端构建产物）
+const DASHBOARD_HTML: &str = include_str!("dashboard.html");
+
 /// 创建 Admin UI 路由
-pub fn create_admin_ui_router() -> Router {
-    Router::new()
+///
+/// `dashboard` 为 true 时在 `/ui` 提供状态面板
+pub fn create_admin_ui_router(dashboard: bool) -> Router {
+    let router = Router::new();
+    let router = if dashboard {
+        router.route("/ui", get(dashboard_handler))
+    } else {
+        router
+    };
+    router
         .route("/", get(index_handler))
         .route("/{*file}", get(static_handler))
 }
 
+/// 处理状态面板请求
+async fn dashboard_handler() -> impl IntoResponse {
+    (
+        [
+            (header::CONTENT_TYPE, "text/html; charset=utf-8"),
+            (header::CACHE_CONTROL, "no-cache"),
+        ],
+        DASHBOARD_HTML,
+    )
+}
+
 /// 处理首页请求
 async fn index_handler() -> impl IntoResponse {
     serve_index()
@@ -0,0 +1,198 @@
+//! 多 API Key 认证
+//!
+//! 除主 `apiKey` 外，`apiKeys` 中的每个 Key 有自己的名称、模型白名单和每分钟请求数限制。
+//! 认证中间件按 Key 找到身份后写入请求扩展，日志 span、`/metrics` 和用量报表按名称区分调用方
+
+use std::collections::HashSet;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::common::auth::{self, KeyIdentity};
+use crate::model::config::ApiKeyConfig;
+
+use super::models;
+
+/// 校验 API Key 配置（启动时调用）
+pub fn validate_config(keys: &[ApiKeyConfig]) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let mut values = HashSet::new();
+    for key in keys {
+        if key.name.is_empty() || key.key.is_empty() {
+            return Err("apiKeys 中的每一项都必须指定 name 和 key".to_string());
+        }
+        if !names.insert(key.name.as_str()) {
+            return Err(format!("apiKeys 中的名称重复: {}", key.name));
+        }
+        if !values.insert(key.key.as_str()) {
+            return Err(format!("apiKeys 中的 Key 重复: {}", key.name));
+        }
+        if key.requests_per_minute == Some(0) {
+            return Err(format!("requestsPerMinute 必须大于 0: {}", key.name));
+        }
+    }
+    Ok(())
+}
+
+/// 身份是否允许使用该模型
+pub fn allows_model(identity: &KeyIdentity, model: &str) -> bool {
+    let model = model.to_lowercase();
+    identity.models.is_empty()
+        || identity
+            .models
+            .iter()
+            .any(|pattern| models::matches(pattern, &model))
+}
+
+/// 令牌桶限流器：容量为每分钟请求数，按秒匀速补充
+#[derive(Debug)]
+struct RateLimiter {
+    capacity: f64,
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    fn new(requests_per_minute: u32, now: Instant) -> Self {
+        let capacity = f64::from(requests_per_minute);
+        Self {
+            capacity,
+            tokens: capacity,
+            updated: now,
+        }
+    }
+
+    /// 取一个令牌，令牌不足时返回需要等待的时长
+    fn acquire(&mut self, now: Instant) -> Result<(), Duration> {
+        let per_second = self.capacity / 60.0;
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * per_second).min(self.capacity);
+        self.updated = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / per_second))
+        }
+    }
+}
+
+/// 已配置的 API Key
+#[derive(Debug)]
+pub struct ApiKeyEntry {
+    identity: KeyIdentity,
+    limiter: Option<Mutex<RateLimiter>>,
+}
+
+impl ApiKeyEntry {
+    pub fn identity(&self) -> &KeyIdentity {
+        &self.identity
+    }
+
+    /// 计入一次请求，超出每分钟请求数时返回建议的重试等待时长
+    pub fn acquire(&self) -> Result<(), Duration> {
+        match &self.limiter {
+            Some(limiter) => limiter.lock().acquire(Instant::now()),
+            None => Ok(()),
+        }
+    }
+}
+
+/// API Key 注册表
+#[derive(Debug, Default)]
+pub struct ApiKeyRegistry {
+    entries: Vec<ApiKeyEntry>,
+}
+
+impl ApiKeyRegistry {
+    pub fn new(keys: &[ApiKeyConfig]) -> Self {
+        let now = Instant::now();
+        let entries = keys
+            .iter()
+            .map(|key| ApiKeyEntry {
+                identity: KeyIdentity {
+                    name: key.name.clone(),
+                    api_key: key.key.clone(),
+                    models: key.models.clone(),
+                },
+                limiter: key
+                    .requests_per_minute
+                    .map(|rpm| Mutex::new(RateLimiter::new(rpm, now))),
+            })
+            .collect();
+        Self { entries }
+    }
+
+    /// 按 Key 查找（逐个常量时间比较）
+    pub fn find(&self, key: &str) -> Option<&ApiKeyEntry> {
+        self.entries
+            .iter()
+            .find(|entry| auth::constant_time_eq(key, &entry.identity.api_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(name: &str, key: &str) -> ApiKeyConfig {
+        ApiKeyConfig {
+            name: name.to_string(),
+            key: key.to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config(&[]).is_ok());
+        assert!(validate_config(&[key("team-a", "sk-a"), key("team-b", "sk-b")]).is_ok());
+        assert!(validate_config(&[key("", "sk-a")]).is_err());
+        assert!(validate_config(&[key("team-a", "sk-a"), key("team-a", "sk-b")]).is_err());
+        assert!(validate_config(&[key("team-a", "sk-a"), key("team-b", "sk-a")]).is_err());
+        let zero = ApiKeyConfig {
+            requests_per_minute: Some(0),
+            ..key("team-a", "sk-a")
+        };
+        assert!(validate_config(&[zero]).is_err());
+    }
+
+    #[test]
+    fn test_registry() {
+        let limited = ApiKeyConfig {
+            models: vec!["claude-haiku-*".to_string()],
+            requests_per_minute: Some(2),
+            ..key("team-b", "sk-b")
+        };
+        let registry = ApiKeyRegistry::new(&[key("team-a", "sk-a"), limited]);
+        assert!(registry.find("sk-c").is_none());
+
+        let entry = registry.find("sk-a").unwrap();
+        assert_eq!(entry.identity().name, "team-a");
+        assert!(allows_model(entry.identity(), "claude-opus-4-6"));
+        for _ in 0..10 {
+            assert!(entry.acquire().is_ok());
+        }
+
+        let entry = registry.find("sk-b").unwrap();
+        assert!(allows_model(entry.identity(), "Claude-Haiku-4-5"));
+        assert!(!allows_model(entry.identity(), "claude-opus-4-6"));
+        assert!(entry.acquire().is_ok());
+        assert!(entry.acquire().is_ok());
+        assert!(entry.acquire().is_err());
+    }
+
+    #[test]
+    fn test_rate_limiter_refill() {
+        let start = Instant::now();
+        let mut limiter = RateLimiter::new(60, start);
+        for _ in 0..60 {
+            assert!(limiter.acquire(start).is_ok());
+        }
+        let wait = limiter.acquire(start).unwrap_err();
+        assert!(wait <= Duration::from_secs(1) && wait > Duration::ZERO);
+        // 每秒补充一个
+        assert!(limiter.acquire(start + Duration::from_secs(1)).is_ok());
+        assert!(limiter.acquire(start + Duration::from_secs(1)).is_err());
+    }
+}
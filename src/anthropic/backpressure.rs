@@ -0,0 +1,417 @@
+//! 有界 SSE 发送队列
+//!
+//! 上游读取与客户端写出解耦：上游事件由后台任务写入有界队列，响应体从队列读取。
+//! 客户端读取过慢导致队列写满时，依次采用以下策略：
+//! 1. 丢弃 ping 事件
+//! 2. 合并相邻的同类增量事件（text / thinking / input_json；请求可通过
+//!    `x-kiro-options` 的 `coalesceDeltas: false` 关闭）
+//! 3. 仍无法写入时发送 `overloaded_error` 并中止流
+
+use std::collections::VecDeque;
+use std::convert::Infallible;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use parking_lot::Mutex;
+use serde_json::json;
+use tokio::sync::Notify;
+
+use crate::common::metrics;
+
+use super::compat::{self, CompatShim};
+use super::stream::{SseEncoder, SseEvent};
+
+/// 写入结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum PushOutcome {
+    /// 已入队（或被合并/丢弃，流继续）
+    Accepted,
+    /// 流已中止或客户端已断开，生产者应停止
+    Closed,
+}
+
+#[derive(Default)]
+struct QueueState {
+    events: VecDeque<SseEvent>,
+    /// 生产者已结束
+    finished: bool,
+    /// 已因客户端过慢中止，或客户端已断开
+    closed: bool,
+    /// 是否已记录过慢客户端
+    slow: bool,
+}
+
+/// 有界 SSE 事件队列
+struct OutgoingQueue {
+    state: Mutex<QueueState>,
+    notify: Notify,
+    /// 通知生产者消费端已断开
+    closed: Notify,
+    capacity: usize,
+    /// 队列写满时是否合并增量事件
+    coalesce: bool,
+}
+
+impl OutgoingQueue {
+    fn new(capacity: usize, coalesce: bool) -> Self {
+        Self {
+            state: Mutex::new(QueueState::default()),
+            notify: Notify::new(),
+            closed: Notify::new(),
+            capacity: capacity.max(1),
+            coalesce,
+        }
+    }
+
+    /// 写入事件，队列已满时按策略降级
+    fn push(&self, event: SseEvent) -> PushOutcome {
+        let outcome = self.push_inner(event);
+        self.notify.notify_one();
+        outcome
+    }
+
+    fn push_inner(&self, event: SseEvent) -> PushOutcome {
+        let mut state = self.state.lock();
+        if state.closed {
+            return PushOutcome::Closed;
+        }
+
+        if state.events.len() < self.capacity {
+            state.events.push_back(event);
+            return PushOutcome::Accepted;
+        }
+
+        let slow_client = metrics::slow_client();
+        if !state.slow {
+            state.slow = true;
+            slow_client.record_slow_stream();
+            tracing::warn!(
+                "客户端读取过慢，SSE 发送队列已满 ({} 个事件)",
+                self.capacity
+            );
+        }
+
+        // 1. 丢弃 ping
+        if event.event == "ping" {
+            slow_client.record_pings_dropped(1);
+            return PushOutcome::Accepted;
+        }
+        let before = state.events.len();
+        state.events.retain(|e| e.event != "ping");
+        let dropped = before - state.events.len();
+        if dropped > 0 {
+            slow_client.record_pings_dropped(dropped as u64);
+        }
+
+        // 2. 合并增量事件
+        let mut coalesced = if self.coalesce {
+            coalesce_deltas(&mut state.events)
+        } else {
+            0
+        };
+        let event = if self.coalesce
+            && let Some(last) = state.events.back_mut()
+            && try_merge_delta(last, &event)
+        {
+            coalesced += 1;
+            None
+        } else {
+            Some(event)
+        };
+        if coalesced > 0 {
+            slow_client.record_deltas_coalesced(coalesced as u64);
+        }
+
+        let Some(event) = event else {
+            return PushOutcome::Accepted;
+        };
+        if state.events.len() < self.capacity {
+            state.events.push_back(event);
+            return PushOutcome::Accepted;
+        }
+
+        // 3. 中止：已排队的事件照常发送，末尾追加错误事件
+        slow_client.record_stream_aborted();
+        tracing::warn!("客户端读取过慢，中止 SSE 流");
+        state.events.push_back(create_overloaded_event());
+        state.closed = true;
+        PushOutcome::Closed
+    }
+
+    /// 生产者结束
+    fn finish(&self) {
+        self.state.lock().finished = true;
+        self.notify.notify_one();
+    }
+
+    /// 消费者断开
+    fn close(&self) {
+        let mut state = self.state.lock();
+        state.closed = true;
+        state.events.clear();
+        self.closed.notify_one();
+    }
+
+    /// 等待消费者断开
+    async fn wait_closed(&self) {
+        loop {
+            if self.state.lock().closed {
+                return;
+            }
+            self.closed.notified().await;
+        }
+    }
+
+    /// 取出下一个事件，队列为空且生产者已结束时返回 None
+    async fn pop(&self) -> Option<SseEvent> {
+        loop {
+            {
+                let mut state = self.state.lock();
+                if let Some(event) = state.events.pop_front() {
+                    return Some(event);
+                }
+                if state.finished || state.closed {
+                    return None;
+                }
+            }
+            self.notify.notified().await;
+        }
+    }
+}
+
+/// 消费端句柄，被丢弃（客户端断开）时通知生产者停止
+struct Receiver(Arc<OutgoingQueue>);
+
+impl Drop for Receiver {
+    fn drop(&mut self) {
+        self.0.close();
+    }
+}
+
+/// 增量事件中可合并的文本字段
+fn delta_text_field(delta_type: &str) -> Option<&'static str> {
+    match delta_type {
+        "text_delta" => Some("text"),
+        "thinking_delta" => Some("thinking"),
+        "input_json_delta" => Some("partial_json"),
+        _ => None,
+    }
+}
+
+/// 尝试将 `next` 合并到 `last`（同一内容块的同类增量）
+fn try_merge_delta(last: &mut SseEvent, next: &SseEvent) -> bool {
+    if last.event != "content_block_delta" || next.event != "content_block_delta" {
+        return false;
+    }
+    if last.data["index"] != next.data["index"]
+        || last.data["delta"]["type"] != next.data["delta"]["type"]
+    {
+        return false;
+    }
+    let Some(field) = next.data["delta"]["type"]
+        .as_str()
+        .and_then(delta_text_field)
+    else {
+        return false;
+    };
+    let (Some(prev), Some(more)) = (
+        last.data["delta"][field].as_str(),
+        next.data["delta"][field].as_str(),
+    ) else {
+        return false;
+    };
+
+    let merged = format!("{}{}", prev, more);
+    last.data["delta"][field] = serde_json::Value::String(merged);
+    true
+}
+
+/// 合并队列中相邻的同类增量事件，返回被合并掉的事件数
+fn coalesce_deltas(events: &mut VecDeque<SseEvent>) -> usize {
+    let mut merged: VecDeque<SseEvent> = VecDeque::with_capacity(events.len());
+    let mut count = 0;
+    for event in events.drain(..) {
+        if let Some(last) = merged.back_mut()
+            && try_merge_delta(last, &event)
+        {
+            count += 1;
+        } else {
+            merged.push_back(event);
+        }
+    }
+    *events = merged;
+    count
+}
+
+/// 客户端过慢时发送的错误事件
+fn create_overloaded_event() -> SseEvent {
+    SseEvent::new(
+        "error",
+        json!({
+            "type": "error",
+            "error": {
+                "type": "overloaded_error",
+                "message": "Client is reading the stream too slowly"
+            }
+        }),
+    )
+}
+
+/// 通过有界队列转发 SSE 事件流
+///
+/// 上游事件流在后台任务中驱动，客户端断开或流被中止后立即丢弃上游事件流
+/// （即使上游暂时没有输出），从而关闭上游连接
+/// 事件出队后再经兼容层改写，合并与丢弃策略始终按原始事件名判断
+pub fn bounded<S>(
+    events: S,
+    capacity: usize,
+    coalesce: bool,
+    compat: Option<CompatShim>,
+) -> impl Stream<Item = Result<Bytes, Infallible>>
+where
+    S: Stream<Item = SseEvent> + Send + 'static,
+{
+    let queue = Arc::new(OutgoingQueue::new(capacity, coalesce));
+
+    let producer = queue.clone();
+    tokio::spawn(async move {
+        let mut events = std::pin::pin!(events);
+        loop {
+            let event = tokio::select! {
+                event = events.next() => event,
+                _ = producer.wait_closed() => None,
+            };
+            let Some(event) = event else {
+                break;
+            };
+            if producer.push(event) == PushOutcome::Closed {
+                break;
+            }
+        }
+        producer.finish();
+    });
+
+    stream::unfold(
+        (Receiver(queue), SseEncoder::default(), compat),
+        |(receiver, mut encoder, compat)| async move {
+            let event = loop {
+                if let Some(event) = compat::apply(compat.as_ref(), receiver.0.pop().await?) {
+                    break event;
+                }
+            };
+            let bytes = encoder.encode(&event);
+            Some((Ok(bytes), (receiver, encoder, compat)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn text_delta(index: i32, text: &str) -> SseEvent {
+        SseEvent::new(
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {"type": "text_delta", "text": text}
+            }),
+        )
+    }
+
+    fn ping() -> SseEvent {
+        SseEvent::new("ping", json!({"type": "ping"}))
+    }
+
+    fn drain(queue: &OutgoingQueue) -> Vec<SseEvent> {
+        queue.state.lock().events.drain(..).collect()
+    }
+
+    #[test]
+    fn test_drops_pings_when_full() {
+        let queue = OutgoingQueue::new(2, true);
+        assert_eq!(queue.push(ping()), PushOutcome::Accepted);
+        assert_eq!(queue.push(text_delta(0, "a")), PushOutcome::Accepted);
+
+        // 队列已满：新 ping 直接丢弃，已排队的 ping 让出位置
+        assert_eq!(queue.push(ping()), PushOutcome::Accepted);
+        assert_eq!(queue.push(text_delta(1, "b")), PushOutcome::Accepted);
+
+        let events = drain(&queue);
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.event != "ping"));
+    }
+
+    #[test]
+    fn test_coalesces_text_deltas_when_full() {
+        let queue = OutgoingQueue::new(2, true);
+        queue.push(text_delta(0, "Hello"));
+        queue.push(text_delta(0, ", "));
+        assert_eq!(queue.push(text_delta(0, "world")), PushOutcome::Accepted);
+
+        let events = drain(&queue);
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].data["delta"]["text"], "Hello, world");
+    }
+
+    #[test]
+    fn test_coalescing_disabled_aborts_instead() {
+        let queue = OutgoingQueue::new(2, false);
+        queue.push(ping());
+        queue.push(text_delta(0, "Hello"));
+        queue.push(text_delta(0, ", "));
+        assert_eq!(queue.push(text_delta(0, "world")), PushOutcome::Closed);
+
+        let events = drain(&queue);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[1].data["delta"]["text"], ", ");
+        assert_eq!(events[2].event, "error");
+    }
+
+    #[test]
+    fn test_aborts_with_overloaded_error() {
+        let queue = OutgoingQueue::new(2, true);
+        queue.push(text_delta(0, "a"));
+        queue.push(SseEvent::new("content_block_stop", json!({"index": 0})));
+
+        // 无法丢弃或合并，中止流
+        assert_eq!(queue.push(text_delta(1, "b")), PushOutcome::Closed);
+        assert_eq!(queue.push(text_delta(1, "c")), PushOutcome::Closed);
+
+        let events = drain(&queue);
+        assert_eq!(events.len(), 3);
+        assert_eq!(events[2].event, "error");
+        assert_eq!(events[2].data["error"]["type"], "overloaded_error");
+    }
+
+    #[tokio::test]
+    async fn test_bounded_stream_forwards_all_events() {
+        let events = stream::iter(vec![text_delta(0, "a"), ping(), text_delta(0, "b")]);
+        let chunks: Vec<Bytes> = bounded(events, 8, true, None)
+            .map(|chunk| chunk.unwrap())
+            .collect()
+            .await;
+
+        assert_eq!(chunks.len(), 3);
+        assert!(String::from_utf8_lossy(&chunks[1]).starts_with("event: ping"));
+    }
+
+    #[tokio::test]
+    async fn test_disconnect_drops_idle_upstream() {
+        // 上游事件流被丢弃时 sender 随闭包一起释放
+        let (tx, rx) = tokio::sync::oneshot::channel::<()>();
+        let events = stream::iter(vec![text_delta(0, "a")])
+            .chain(stream::pending())
+            .inspect(move |_| {
+                let _ = &tx;
+            });
+        let mut body = Box::pin(bounded(events, 8, true, None));
+        assert!(body.next().await.is_some());
+
+        drop(body);
+        let dropped = tokio::time::timeout(std::time::Duration::from_secs(1), rx).await;
+        assert!(matches!(dropped, Ok(Err(_))), "上游事件流未被丢弃");
+    }
+}
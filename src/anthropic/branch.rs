@@ -0,0 +1,354 @@
+//! 会话分支
+//!
+//! 客户端通过扩展字段 `parent_message_id` 指定从哪条助手消息继续，
+//! 服务端按会话维护一棵消息树：每个节点是一条助手回复（以响应的 `msg_` ID 标识），
+//! 保存该次请求新增的消息和助手回复本身。沿父节点链拼接即可重建任意分支的完整历史，
+//! 用于客户端的“重新生成”和“编辑后重发”。
+//!
+//! 消息树按调用方的 API Key 隔离，不同 Key 即使使用相同的会话 ID 也无法沿对方的分支重建历史。
+//! 未指定 `parent_message_id` 的请求如果接着最近一轮继续，只登记新增的消息
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
+
+use futures::{Stream, StreamExt};
+use serde_json::json;
+
+use super::session::SessionStore;
+use super::stream::SseEvent;
+use super::types::Message;
+
+/// 单个会话最多保留的分支节点数
+const MAX_BRANCH_NODES: usize = 512;
+
+/// 分支节点
+#[derive(Debug, Clone)]
+struct BranchNode {
+    parent: Option<String>,
+    /// 本轮新增的消息（含助手回复）
+    messages: Vec<Message>,
+}
+
+/// 会话消息树
+#[derive(Debug, Clone, Default)]
+pub struct ConversationTree {
+    nodes: HashMap<String, BranchNode>,
+    /// 插入顺序，超出上限时淘汰最早的节点
+    order: VecDeque<String>,
+    /// 最近登记的节点
+    latest: Option<String>,
+}
+
+impl ConversationTree {
+    /// 添加节点
+    pub fn insert(&mut self, id: String, parent: Option<String>, messages: Vec<Message>) {
+        if !self.nodes.contains_key(&id) {
+            self.order.push_back(id.clone());
+        }
+        self.latest = Some(id.clone());
+        self.nodes.insert(id, BranchNode { parent, messages });
+
+        while self.order.len() > MAX_BRANCH_NODES {
+            if let Some(oldest) = self.order.pop_front() {
+                self.nodes.remove(&oldest);
+            }
+        }
+    }
+
+    /// 从根节点到指定节点的完整消息序列
+    ///
+    /// 节点不存在或父节点链断裂（已被淘汰）时返回 None
+    pub fn path(&self, id: &str) -> Option<Vec<Message>> {
+        let segments = self.segments(id)?;
+        Some(segments.into_iter().rev().flatten().cloned().collect())
+    }
+
+    /// 消息序列接着最近登记的节点继续时，返回该节点 ID 与其完整历史的消息数
+    pub fn continuation(&self, messages: &[Message]) -> Option<(String, usize)> {
+        let latest = self.latest.as_deref()?;
+        let segments = self.segments(latest)?;
+        let len: usize = segments.iter().map(|s| s.len()).sum();
+        if len >= messages.len() {
+            return None;
+        }
+        let continues = segments
+            .iter()
+            .rev()
+            .flat_map(|s| s.iter())
+            .zip(messages)
+            .all(|(stored, sent)| stored.role == sent.role && stored.content == sent.content);
+        continues.then(|| (latest.to_string(), len))
+    }
+
+    /// 从指定节点到根节点的各节点消息（节点不存在或链路断裂时返回 None）
+    fn segments(&self, id: &str) -> Option<Vec<&Vec<Message>>> {
+        let mut segments = Vec::new();
+        let mut current = Some(id);
+        while let Some(node_id) = current {
+            let node = self.nodes.get(node_id)?;
+            segments.push(&node.messages);
+            // 防御环路
+            if segments.len() > self.nodes.len() {
+                return None;
+            }
+            current = node.parent.as_deref();
+        }
+        Some(segments)
+    }
+}
+
+/// 分支记录器：在响应完成后把本轮消息登记到会话消息树
+pub struct BranchRecorder {
+    store: Arc<SessionStore>,
+    session_id: String,
+    /// 调用方的 API Key
+    owner: String,
+    parent_id: Option<String>,
+    /// 本轮请求新增的消息
+    messages: Vec<Message>,
+}
+
+impl BranchRecorder {
+    pub fn new(
+        store: Arc<SessionStore>,
+        session_id: String,
+        owner: String,
+        parent_id: Option<String>,
+        messages: Vec<Message>,
+    ) -> Self {
+        Self {
+            store,
+            session_id,
+            owner,
+            parent_id,
+            messages,
+        }
+    }
+
+    /// 登记助手回复
+    pub fn record(mut self, message_id: &str, content: Vec<serde_json::Value>) {
+        self.messages.push(Message {
+            role: "assistant".to_string(),
+            content: serde_json::Value::Array(content),
+        });
+        self.store.put_branch(
+            &self.session_id,
+            &self.owner,
+            message_id.to_string(),
+            self.parent_id,
+            self.messages,
+        );
+    }
+}
+
+/// 从 SSE 事件中还原助手回复内容
+#[derive(Debug, Default)]
+struct ResponseAccumulator {
+    message_id: Option<String>,
+    blocks: Vec<(i64, serde_json::Value)>,
+    /// tool_use 块的增量 JSON
+    partial_json: HashMap<i64, String>,
+}
+
+impl ResponseAccumulator {
+    fn block_mut(&mut self, index: i64) -> Option<&mut serde_json::Value> {
+        self.blocks
+            .iter_mut()
+            .find(|(i, _)| *i == index)
+            .map(|(_, block)| block)
+    }
+
+    fn observe(&mut self, event: &SseEvent) {
+        let data = &event.data;
+        let index = data["index"].as_i64().unwrap_or_default();
+        match event.event.as_str() {
+            "message_start" => {
+                self.message_id = data["message"]["id"].as_str().map(String::from);
+            }
+            "content_block_start" => {
+                self.blocks.push((index, data["content_block"].clone()));
+            }
+            "content_block_delta" => {
+                let delta = &data["delta"];
+                match delta["type"].as_str() {
+                    Some("input_json_delta") => {
+                        self.partial_json
+                            .entry(index)
+                            .or_default()
+                            .push_str(delta["partial_json"].as_str().unwrap_or_default());
+                    }
+                    Some(delta_type) => {
+                        let field = match delta_type {
+                            "text_delta" => "text",
+                            "thinking_delta" => "thinking",
+                            "signature_delta" => "signature",
+                            _ => return,
+                        };
+                        let Some(block) = self.block_mut(index) else {
+                            return;
+                        };
+                        let mut value = block[field].as_str().unwrap_or_default().to_string();
+                        value.push_str(delta[field].as_str().unwrap_or_default());
+                        block[field] = json!(value);
+                    }
+                    None => {}
+                }
+            }
+            "content_block_stop" => {
+                if let Some(partial) = self.partial_json.remove(&index)
+                    && let Some(block) = self.block_mut(index)
+                {
+                    block["input"] = serde_json::from_str(&partial).unwrap_or_else(|_| json!({}));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn into_content(self) -> Vec<serde_json::Value> {
+        self.blocks.into_iter().map(|(_, block)| block).collect()
+    }
+}
+
+/// 观察 SSE 事件流，在 `message_stop` 时登记助手回复
+pub fn record_stream<S>(events: S, recorder: BranchRecorder) -> impl Stream<Item = SseEvent>
+where
+    S: Stream<Item = SseEvent>,
+{
+    let mut state = Some((recorder, ResponseAccumulator::default()));
+    events.inspect(move |event| {
+        let Some((_, accumulator)) = state.as_mut() else {
+            return;
+        };
+        accumulator.observe(event);
+
+        if event.event == "message_stop"
+            && let Some((recorder, accumulator)) = state.take()
+            && let Some(message_id) = accumulator.message_id.clone()
+        {
+            recorder.record(&message_id, accumulator.into_content());
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message(role: &str, text: &str) -> Message {
+        Message {
+            role: role.to_string(),
+            content: json!(text),
+        }
+    }
+
+    #[test]
+    fn test_path_follows_selected_branch() {
+        let mut tree = ConversationTree::default();
+        tree.insert(
+            "msg_1".to_string(),
+            None,
+            vec![message("user", "hi"), message("assistant", "hello")],
+        );
+        tree.insert(
+            "msg_2a".to_string(),
+            Some("msg_1".to_string()),
+            vec![message("user", "a"), message("assistant", "A")],
+        );
+        tree.insert(
+            "msg_2b".to_string(),
+            Some("msg_1".to_string()),
+            vec![message("user", "b"), message("assistant", "B")],
+        );
+
+        let path = tree.path("msg_2b").unwrap();
+        let texts: Vec<_> = path.iter().map(|m| m.content.as_str().unwrap()).collect();
+        assert_eq!(texts, vec!["hi", "hello", "b", "B"]);
+        assert!(tree.path("msg_missing").is_none());
+    }
+
+    #[test]
+    fn test_path_broken_after_eviction() {
+        let mut tree = ConversationTree::default();
+        tree.insert("root".to_string(), None, vec![message("user", "0")]);
+        let mut parent = "root".to_string();
+        for i in 0..MAX_BRANCH_NODES {
+            let id = format!("msg_{}", i);
+            tree.insert(id.clone(), Some(parent), vec![message("user", "x")]);
+            parent = id;
+        }
+
+        // 根节点已被淘汰，链路断裂
+        assert!(tree.path(&parent).is_none());
+    }
+
+    #[test]
+    fn test_continuation_of_latest_turn() {
+        let mut tree = ConversationTree::default();
+        assert!(tree.continuation(&[message("user", "hi")]).is_none());
+        tree.insert(
+            "msg_1".to_string(),
+            None,
+            vec![message("user", "hi"), message("assistant", "hello")],
+        );
+
+        let next = [
+            message("user", "hi"),
+            message("assistant", "hello"),
+            message("user", "more"),
+        ];
+        assert_eq!(tree.continuation(&next), Some(("msg_1".to_string(), 2)));
+        // 编辑过的历史或没有新增消息时不是延续
+        let edited = [
+            message("user", "hey"),
+            message("assistant", "hello"),
+            message("user", "more"),
+        ];
+        assert!(tree.continuation(&edited).is_none());
+        assert!(tree.continuation(&next[..2]).is_none());
+    }
+
+    #[test]
+    fn test_accumulator_rebuilds_content() {
+        let mut acc = ResponseAccumulator::default();
+        let events = [
+            SseEvent::new(
+                "message_start",
+                json!({"type": "message_start", "message": {"id": "msg_x"}}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"index": 0, "content_block": {"type": "text", "text": ""}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 0, "delta": {"type": "text_delta", "text": "Hel"}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 0, "delta": {"type": "text_delta", "text": "lo"}}),
+            ),
+            SseEvent::new(
+                "content_block_start",
+                json!({"index": 1, "content_block": {"type": "tool_use", "id": "t1", "name": "Read", "input": {}}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"path\":"}}),
+            ),
+            SseEvent::new(
+                "content_block_delta",
+                json!({"index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"a.rs\"}"}}),
+            ),
+            SseEvent::new("content_block_stop", json!({"index": 1})),
+        ];
+        for event in &events {
+            acc.observe(event);
+        }
+
+        assert_eq!(acc.message_id.as_deref(), Some("msg_x"));
+        let content = acc.into_content();
+        assert_eq!(content[0]["text"], "Hello");
+        assert_eq!(content[1]["input"], json!({"path": "a.rs"}));
+    }
+}
@@ -0,0 +1,146 @@
+//! 按 API Key 的每日 token 预算
+//!
+//! 某个 Key 当天在规则模型上的用量（来自用量报表）达到 `dailyTokens` 后，后续请求改用
+//! `downgradeTo` 指定的模型，并通过 `x-kiro-model-substitution` 响应头提示，
+//! 而不是等到上游配额耗尽后在工作时间中途报错
+
+use crate::common::usage;
+use crate::model::config::{BudgetConfig, BudgetRule};
+
+use super::models;
+
+/// 校验预算配置（启动时调用）
+pub fn validate_config(config: &BudgetConfig) -> Result<(), String> {
+    for rule in &config.rules {
+        if rule.model.is_empty() || rule.downgrade_to.is_empty() {
+            return Err("预算规则必须指定 model 和 downgradeTo".to_string());
+        }
+        if models::resolve(&rule.downgrade_to).is_none() {
+            return Err(format!("降级模型没有对应的模型映射: {}", rule.downgrade_to));
+        }
+    }
+    Ok(())
+}
+
+/// 适用于该 Key 和模型的第一条规则
+fn rule_for<'a>(config: &'a BudgetConfig, api_key: &str, model: &str) -> Option<&'a BudgetRule> {
+    config.rules.iter().find(|rule| {
+        models::matches(&rule.model, model)
+            && (rule.api_keys.is_empty() || rule.api_keys.iter().any(|k| k == api_key))
+    })
+}
+
+/// 按预算选择模型，超出预算时返回降级后的模型名
+///
+/// `used` 返回该 Key 当天在规则模型上已用的 tokens。降级后的模型超出自己的预算时继续沿规则降级，
+/// 降级链的最后一个模型即使超出预算也照常使用
+fn select(
+    config: &BudgetConfig,
+    api_key: &str,
+    model: &str,
+    used: impl Fn(&BudgetRule) -> u64,
+) -> Option<String> {
+    let mut current = model.to_string();
+    let mut visited = vec![current.to_lowercase()];
+    while let Some(rule) = rule_for(config, api_key, &current) {
+        if used(rule) < rule.daily_tokens || visited.contains(&rule.downgrade_to.to_lowercase()) {
+            break;
+        }
+        current = rule.downgrade_to.clone();
+        visited.push(current.to_lowercase());
+    }
+    (current != model).then_some(current)
+}
+
+/// 按用量报表中当天的用量选择模型，超出预算时返回降级后的模型名
+pub fn downgrade(config: &BudgetConfig, api_key: &str, model: &str) -> Option<String> {
+    if config.rules.is_empty() {
+        return None;
+    }
+    let store = usage::store()?;
+    select(config, api_key, model, |rule| {
+        store.tokens_today(api_key, |m| models::matches(&rule.model, m))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(model: &str, daily_tokens: u64, downgrade_to: &str) -> BudgetRule {
+        BudgetRule {
+            model: model.to_string(),
+            daily_tokens,
+            downgrade_to: downgrade_to.to_string(),
+            api_keys: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_select() {
+        let config = BudgetConfig {
+            rules: vec![
+                rule("*opus*", 1000, "claude-sonnet-4-6"),
+                rule("*sonnet*", 5000, "claude-haiku-4-5"),
+            ],
+        };
+        let used = |opus: u64, sonnet: u64| {
+            move |rule: &BudgetRule| if rule.model == "*opus*" { opus } else { sonnet }
+        };
+
+        assert_eq!(
+            select(&config, "sk-a", "claude-opus-4-6", used(999, 0)),
+            None
+        );
+        assert_eq!(
+            select(&config, "sk-a", "claude-opus-4-6", used(1000, 0)).as_deref(),
+            Some("claude-sonnet-4-6")
+        );
+        // 沿规则继续降级
+        assert_eq!(
+            select(&config, "sk-a", "claude-opus-4-6", used(1000, 5000)).as_deref(),
+            Some("claude-haiku-4-5")
+        );
+        assert_eq!(
+            select(&config, "sk-a", "claude-haiku-4-5", used(1000, 5000)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_select_scoped_and_cyclic() {
+        let mut scoped = rule("*opus*", 10, "claude-sonnet-4-6");
+        scoped.api_keys = vec!["sk-team-a".to_string()];
+        let config = BudgetConfig {
+            rules: vec![scoped, rule("*sonnet*", 10, "claude-opus-4-6")],
+        };
+        let exhausted = |_: &BudgetRule| 100;
+
+        assert_eq!(
+            select(&config, "sk-team-b", "claude-opus-4-6", exhausted),
+            None
+        );
+        // opus -> sonnet 后规则指回 opus，停在 sonnet
+        assert_eq!(
+            select(&config, "sk-team-a", "claude-opus-4-6", exhausted).as_deref(),
+            Some("claude-sonnet-4-6")
+        );
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config(&BudgetConfig::default()).is_ok());
+        let valid = BudgetConfig {
+            rules: vec![rule("*opus*", 10, "claude-sonnet-4-6")],
+        };
+        assert!(validate_config(&valid).is_ok());
+        let missing = BudgetConfig {
+            rules: vec![rule("*opus*", 10, "")],
+        };
+        assert!(validate_config(&missing).is_err());
+        let unmapped = BudgetConfig {
+            rules: vec![rule("*opus*", 10, "gpt-4")],
+        };
+        assert!(validate_config(&unmapped).is_err());
+    }
+}
@@ -0,0 +1,314 @@
+//! 转换器灰度
+//!
+//! 配置灰度：两个版本使用同一份转换器代码（[`super::converter`]），稳定版本使用 `converter` 配置，
+//! 实验版本使用叠加了 `canary.converter` 覆盖项的配置，转换器配置的改动可以先灰度上线。
+//! 转换代码本身的改动不经过灰度，按常规发布。按请求选择版本：
+//! - 请求头 `x-kiro-converter: stable|experimental` 指定版本
+//! - 未指定时按 `canary.percentage` 随机选中实验版本
+//!
+//! 启用 `canary.logDiffs` 时，使用实验版本的请求会在后台线程上再用稳定版本转换一次，
+//! 记录两者产出的 Kiro 请求的结构差异，不占用请求处理路径；同时进行的对照转换数有上限，
+//! 超出时跳过对照，避免大量实验请求复制请求体并占满阻塞线程池
+
+use std::collections::BTreeSet;
+use std::sync::Arc;
+
+use serde_json::Value;
+use tokio::sync::Semaphore;
+
+use crate::kiro::model::requests::conversation::ConversationState;
+use crate::model::config::{Config, ConverterConfig};
+
+use super::converter::{self, ConversionError, ConversionResult};
+use super::types::MessagesRequest;
+
+/// 指定转换器版本的请求头
+pub const CONVERTER_HEADER: &str = "x-kiro-converter";
+
+/// 每次最多记录的差异数
+const MAX_DIFFS: usize = 20;
+
+/// 同时进行的对照转换数上限
+const MAX_PENDING_DIFFS: usize = 2;
+
+/// 比较时忽略的字段（每次转换随机生成）
+const IGNORED_FIELDS: &[&str] = &["conversationId", "agentContinuationId"];
+
+/// 转换器版本
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConverterVersion {
+    Stable,
+    Experimental,
+}
+
+/// 转换器灰度
+pub struct Canary {
+    percentage: u8,
+    log_diffs: bool,
+    /// 稳定版本的转换器配置
+    stable: Arc<ConverterConfig>,
+    /// 实验版本的转换器配置
+    experimental: Arc<ConverterConfig>,
+    /// 对照转换名额
+    pending_diffs: Arc<Semaphore>,
+}
+
+impl Canary {
+    pub fn from_config(config: &Config) -> Self {
+        let experimental = config
+            .canary
+            .experimental_converter(&config.converter)
+            .unwrap_or_else(|e| {
+                tracing::warn!("canary.converter 配置无效，实验转换器使用稳定配置: {}", e);
+                config.converter.clone()
+            });
+        Self {
+            percentage: config.canary.percentage.min(100),
+            log_diffs: config.canary.log_diffs,
+            stable: Arc::new(config.converter.clone()),
+            experimental: Arc::new(experimental),
+            pending_diffs: Arc::new(Semaphore::new(MAX_PENDING_DIFFS)),
+        }
+    }
+
+    /// 按请求头或灰度比例选择转换器版本
+    ///
+    /// 未指定版本但带有种子时，按种子确定性地选择，保证相同种子命中相同版本
+    pub fn select(
+        &self,
+        header: Option<&str>,
+        seed: Option<u64>,
+    ) -> Result<ConverterVersion, String> {
+        match header.map(str::trim) {
+            Some(v) if v.eq_ignore_ascii_case("stable") => Ok(ConverterVersion::Stable),
+            Some(v) if v.eq_ignore_ascii_case("experimental") => Ok(ConverterVersion::Experimental),
+            Some(v) => Err(format!(
+                "{} 只能是 stable 或 experimental，收到: {}",
+                CONVERTER_HEADER, v
+            )),
+            None => {
+                let roll = match seed {
+                    Some(seed) => fastrand::Rng::with_seed(seed).u8(..100),
+                    None => fastrand::u8(..100),
+                };
+                if roll < self.percentage {
+                    Ok(ConverterVersion::Experimental)
+                } else {
+                    Ok(ConverterVersion::Stable)
+                }
+            }
+        }
+    }
+
+    /// 用指定版本转换请求；使用实验版本时在后台记录与稳定版本的差异
+    pub fn convert(
+        &self,
+        req: &MessagesRequest,
+        version: ConverterVersion,
+    ) -> Result<ConversionResult, ConversionError> {
+        let config = match version {
+            ConverterVersion::Stable => &self.stable,
+            ConverterVersion::Experimental => &self.experimental,
+        };
+        let result = converter::convert_request(req, config);
+        if version == ConverterVersion::Experimental && self.log_diffs {
+            self.spawn_diff(req, &result);
+        }
+        result
+    }
+
+    /// 在后台用稳定版本转换同一请求并记录差异；对照名额用尽时跳过
+    fn spawn_diff(
+        &self,
+        req: &MessagesRequest,
+        result: &Result<ConversionResult, ConversionError>,
+    ) {
+        let Ok(permit) = self.pending_diffs.clone().try_acquire_owned() else {
+            tracing::debug!("转换器灰度: 对照转换名额已满，跳过本次差异记录");
+            return;
+        };
+        let experimental = match result {
+            Ok(result) => Ok(result.conversation_state.clone()),
+            Err(e) => Err(e.to_string()),
+        };
+        let req = req.clone();
+        let config = self.stable.clone();
+        let compare = move || {
+            let _permit = permit;
+            let baseline = converter::convert_request(&req, &config)
+                .map(|r| r.conversation_state)
+                .map_err(|e| e.to_string());
+            log_diffs(&baseline, &experimental);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => drop(handle.spawn_blocking(compare)),
+            Err(_) => compare(),
+        }
+    }
+}
+
+/// 记录稳定版本与实验版本产出的差异
+fn log_diffs(
+    stable: &Result<ConversationState, String>,
+    experimental: &Result<ConversationState, String>,
+) {
+    let diffs = match (stable, experimental) {
+        (Ok(a), Ok(b)) => {
+            let a = serde_json::to_value(a).unwrap_or_default();
+            let b = serde_json::to_value(b).unwrap_or_default();
+            structural_diff(&a, &b)
+        }
+        (Ok(_), Err(e)) => vec![format!("experimental 转换失败: {}", e)],
+        (Err(e), Ok(_)) => vec![format!("stable 转换失败: {}", e)],
+        (Err(a), Err(b)) if a == b => Vec::new(),
+        (Err(a), Err(b)) => vec![format!("转换错误不同: stable={}, experimental={}", a, b)],
+    };
+
+    if diffs.is_empty() {
+        tracing::debug!("转换器灰度: 实验版本与稳定版本产出一致");
+    } else {
+        tracing::info!(
+            "转换器灰度: 实验版本与稳定版本有 {} 处差异: {}",
+            diffs.len(),
+            diffs.join("; ")
+        );
+    }
+}
+
+/// 比较两个 JSON 值，返回存在差异的路径（JSON Pointer 格式）
+pub fn structural_diff(a: &Value, b: &Value) -> Vec<String> {
+    let mut diffs = Vec::new();
+    diff_at("", a, b, &mut diffs);
+    diffs
+}
+
+fn diff_at(path: &str, a: &Value, b: &Value, diffs: &mut Vec<String>) {
+    if diffs.len() >= MAX_DIFFS {
+        return;
+    }
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let keys: BTreeSet<&String> = a.keys().chain(b.keys()).collect();
+            for key in keys {
+                if IGNORED_FIELDS.contains(&key.as_str()) {
+                    continue;
+                }
+                let child = format!("{}/{}", path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(x), Some(y)) => diff_at(&child, x, y, diffs),
+                    (Some(_), None) => diffs.push(format!("{}: 仅 stable 存在", child)),
+                    (None, _) => diffs.push(format!("{}: 仅 experimental 存在", child)),
+                }
+                if diffs.len() >= MAX_DIFFS {
+                    return;
+                }
+            }
+        }
+        (Value::Array(a), Value::Array(b)) => {
+            if a.len() != b.len() {
+                diffs.push(format!("{}: 长度 {} -> {}", path, a.len(), b.len()));
+            }
+            for (i, (x, y)) in a.iter().zip(b).enumerate() {
+                diff_at(&format!("{}/{}", path, i), x, y, diffs);
+            }
+        }
+        _ if a != b => diffs.push(format!("{}: 值不同", path)),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_structural_diff() {
+        let a = json!({
+            "conversationId": "a",
+            "history": [{"content": "x"}, {"content": "y"}],
+            "currentMessage": {"content": "hi", "extra": 1}
+        });
+        let b = json!({
+            "conversationId": "b",
+            "history": [{"content": "x"}],
+            "currentMessage": {"content": "hello"}
+        });
+        assert_eq!(
+            structural_diff(&a, &b),
+            vec![
+                "/currentMessage/content: 值不同",
+                "/currentMessage/extra: 仅 stable 存在",
+                "/history: 长度 2 -> 1",
+            ]
+        );
+        assert!(structural_diff(&a, &a).is_empty());
+    }
+
+    #[test]
+    fn test_select_and_experimental_config() {
+        let mut config = Config::default();
+        config
+            .canary
+            .converter
+            .insert("userAckText".into(), json!("OK"));
+        let canary = Canary::from_config(&config);
+
+        assert_eq!(canary.select(None, None), Ok(ConverterVersion::Stable));
+        assert_eq!(
+            canary.select(Some("Experimental"), None),
+            Ok(ConverterVersion::Experimental)
+        );
+        assert!(canary.select(Some("beta"), None).is_err());
+        assert_eq!(canary.experimental.user_ack_text, "OK");
+        assert_eq!(
+            canary.experimental.system_ack_text,
+            canary.stable.system_ack_text
+        );
+
+        config.canary.percentage = 100;
+        let canary = Canary::from_config(&config);
+        assert_eq!(
+            canary.select(None, None),
+            Ok(ConverterVersion::Experimental)
+        );
+
+        // 相同种子总是选中相同版本
+        config.canary.percentage = 50;
+        let canary = Canary::from_config(&config);
+        for seed in 0..32 {
+            let first = canary.select(None, Some(seed));
+            assert!((0..8).all(|_| canary.select(None, Some(seed)) == first));
+        }
+        assert_eq!(
+            canary.select(Some("stable"), Some(1)),
+            Ok(ConverterVersion::Stable)
+        );
+    }
+
+    #[test]
+    fn test_versions_use_their_own_config() {
+        let mut config = Config::default();
+        config
+            .canary
+            .converter
+            .insert("systemAckText".into(), json!("Noted."));
+        let canary = Canary::from_config(&config);
+        let req: MessagesRequest = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 16,
+            "system": "be brief",
+            "messages": [{"role": "user", "content": "hi"}]
+        }))
+        .unwrap();
+        let converted = |version| {
+            let result = canary.convert(&req, version).unwrap();
+            serde_json::to_string(&result.conversation_state).unwrap()
+        };
+
+        assert!(!converted(ConverterVersion::Stable).contains("Noted."));
+        assert!(converted(ConverterVersion::Experimental).contains("Noted."));
+        // 没有运行时时对照转换同步完成，名额随之释放
+        assert_eq!(canary.pending_diffs.available_permits(), MAX_PENDING_DIFFS);
+    }
+}
@@ -0,0 +1,181 @@
+//! Anthropic 定义的客户端工具（computer use 等）
+//!
+//! `computer_20250124`、`text_editor_20250124`、`bash_20250124` 等工具只有 `type` 和 `name`，
+//! 没有 `input_schema`，其输入格式由 Anthropic 约定。Kiro 只认识普通工具，
+//! 这里为它们补全描述和 JSON Schema，使模型能正确生成调用；
+//! 调用本身仍以普通 `tool_use` 块返回，由客户端执行
+
+use serde_json::json;
+
+use super::types::Tool;
+
+/// Anthropic 定义的客户端工具类型
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClientToolKind {
+    /// 屏幕操作（computer_*）
+    Computer,
+    /// 文件查看与编辑（text_editor_*）
+    TextEditor,
+    /// Shell 命令（bash_*）
+    Bash,
+}
+
+impl ClientToolKind {
+    /// 工具类型名称，用于日志和错误信息
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientToolKind::Computer => "computer",
+            ClientToolKind::TextEditor => "text_editor",
+            ClientToolKind::Bash => "bash",
+        }
+    }
+}
+
+/// 根据 `type` 字段识别 Anthropic 定义的客户端工具
+pub fn classify(tool: &Tool) -> Option<ClientToolKind> {
+    let tool_type = tool.tool_type.as_deref()?;
+    if tool_type.starts_with("computer_") {
+        Some(ClientToolKind::Computer)
+    } else if tool_type.starts_with("text_editor_") {
+        Some(ClientToolKind::TextEditor)
+    } else if tool_type.starts_with("bash_") {
+        Some(ClientToolKind::Bash)
+    } else {
+        None
+    }
+}
+
+/// 生成工具描述和输入 Schema
+pub fn describe(kind: ClientToolKind, tool: &Tool) -> (String, serde_json::Value) {
+    match kind {
+        ClientToolKind::Computer => (computer_description(tool), computer_schema()),
+        ClientToolKind::TextEditor => (
+            "View, create and edit files. `view` shows a file (optionally a line range) or lists a directory; \
+             `create` writes `file_text` to a new file; `str_replace` replaces the unique occurrence of \
+             `old_str` with `new_str`; `insert` inserts `new_str` after line `insert_line`; \
+             `undo_edit` reverts the last edit."
+                .to_string(),
+            text_editor_schema(),
+        ),
+        ClientToolKind::Bash => (
+            "Run a command in a persistent bash shell. Set `restart` to true to restart the shell."
+                .to_string(),
+            json!({
+                "type": "object",
+                "properties": {
+                    "command": {"type": "string", "description": "The bash command to run"},
+                    "restart": {"type": "boolean", "description": "Restart the shell session"}
+                },
+                "required": []
+            }),
+        ),
+    }
+}
+
+fn computer_description(tool: &Tool) -> String {
+    let mut description =
+        "Use a mouse and keyboard to interact with a computer and take screenshots. \
+        Coordinates are [x, y] pixels from the top-left corner of the screen."
+            .to_string();
+    if let (Some(width), Some(height)) = (tool.display_width_px, tool.display_height_px) {
+        description.push_str(&format!(" The screen resolution is {}x{}.", width, height));
+    }
+    if let Some(display) = tool.display_number {
+        description.push_str(&format!(" Display number: {}.", display));
+    }
+    description
+}
+
+fn computer_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "action": {
+                "type": "string",
+                "enum": [
+                    "key", "hold_key", "type", "cursor_position", "mouse_move",
+                    "left_mouse_down", "left_mouse_up", "left_click", "left_click_drag",
+                    "right_click", "middle_click", "double_click", "triple_click",
+                    "scroll", "wait", "screenshot"
+                ]
+            },
+            "coordinate": {
+                "type": "array",
+                "items": {"type": "integer"},
+                "description": "[x, y] target position"
+            },
+            "start_coordinate": {
+                "type": "array",
+                "items": {"type": "integer"},
+                "description": "[x, y] drag start position (left_click_drag)"
+            },
+            "text": {"type": "string", "description": "Text to type or key combination to press"},
+            "scroll_direction": {"type": "string", "enum": ["up", "down", "left", "right"]},
+            "scroll_amount": {"type": "integer"},
+            "duration": {"type": "number", "description": "Seconds to hold a key or wait"}
+        },
+        "required": ["action"]
+    })
+}
+
+fn text_editor_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "command": {
+                "type": "string",
+                "enum": ["view", "create", "str_replace", "insert", "undo_edit"]
+            },
+            "path": {"type": "string", "description": "Absolute path to the file or directory"},
+            "file_text": {"type": "string"},
+            "old_str": {"type": "string"},
+            "new_str": {"type": "string"},
+            "insert_line": {"type": "integer"},
+            "view_range": {"type": "array", "items": {"type": "integer"}}
+        },
+        "required": ["command", "path"]
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(tool_type: &str, name: &str) -> Tool {
+        Tool {
+            tool_type: Some(tool_type.to_string()),
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: Default::default(),
+            max_uses: None,
+            display_width_px: Some(1024),
+            display_height_px: Some(768),
+            display_number: None,
+        }
+    }
+
+    #[test]
+    fn test_classify() {
+        assert_eq!(
+            classify(&tool("computer_20250124", "computer")),
+            Some(ClientToolKind::Computer)
+        );
+        assert_eq!(
+            classify(&tool("text_editor_20250124", "str_replace_editor")),
+            Some(ClientToolKind::TextEditor)
+        );
+        assert_eq!(
+            classify(&tool("bash_20250124", "bash")),
+            Some(ClientToolKind::Bash)
+        );
+        assert_eq!(classify(&tool("web_search_20250305", "web_search")), None);
+    }
+
+    #[test]
+    fn test_computer_description_includes_resolution() {
+        let t = tool("computer_20250124", "computer");
+        let (description, schema) = describe(ClientToolKind::Computer, &t);
+        assert!(description.contains("1024x768"));
+        assert_eq!(schema["required"], json!(["action"]));
+    }
+}
@@ -0,0 +1,210 @@
+//! SSE 兼容层
+//!
+//! 部分第三方"Anthropic 兼容"客户端期望的事件名或字段与官方略有不同。兼容层在 SSE 编码前
+//! 按端点配置的 profile（`stream.v1Compat` / `stream.ccCompat`）改写事件：
+//! - `claude-code`：丢弃 `kiro_usage`、`kiro_stats`、`kiro_diff` 扩展事件
+//! - `strict-anthropic`：在此基础上删除 `message_delta` 中的 `deadline_exceeded`、`kiro_estimated_cost` 扩展字段
+//! - `openai-bridge`：在 strict-anthropic 基础上为用量补齐 `cache_creation_input_tokens`、
+//!   `cache_read_input_tokens`（转换网关据此生成 OpenAI 的 `prompt_tokens_details`）
+//! - `custom`：使用 `stream.compatRules` 中的规则
+//!
+//! 兼容层只作用于发给客户端的字节，会话分支记录等内部消费者仍看到原始事件
+
+use std::collections::BTreeMap;
+
+use serde_json::{Value, json};
+
+use crate::model::config::{CompatProfile, CompatRulesConfig};
+
+use super::stream::SseEvent;
+
+/// 扩展事件
+const EXTENSION_EVENTS: &[&str] = &["kiro_usage", "kiro_stats", "kiro_diff"];
+
+/// SSE 兼容层
+#[derive(Debug, Clone)]
+pub struct CompatShim {
+    rules: CompatRulesConfig,
+}
+
+impl CompatShim {
+    /// 按 profile 创建兼容层（`none` 时返回 None）
+    pub fn new(profile: CompatProfile, custom: &CompatRulesConfig) -> Option<Self> {
+        let rules = match profile {
+            CompatProfile::None => return None,
+            CompatProfile::ClaudeCode => claude_code_rules(),
+            CompatProfile::StrictAnthropic => strict_anthropic_rules(),
+            CompatProfile::OpenaiBridge => openai_bridge_rules(),
+            CompatProfile::Custom => custom.clone(),
+        };
+        Some(Self { rules })
+    }
+
+    /// 改写事件，返回 None 表示丢弃
+    pub fn apply(&self, mut event: SseEvent) -> Option<SseEvent> {
+        if self.rules.drop_events.contains(&event.event) {
+            return None;
+        }
+        if let Some(paths) = self.rules.remove_fields.get(&event.event) {
+            for path in paths {
+                remove_field(&mut event.data, path);
+            }
+        }
+        if let Some(fields) = self.rules.add_fields.get(&event.event) {
+            for (path, value) in fields {
+                add_field(&mut event.data, path, value);
+            }
+        }
+        if let Some(name) = self.rules.rename_events.get(&event.event) {
+            if event.data.get("type").and_then(Value::as_str) == Some(event.event.as_str()) {
+                event.data["type"] = Value::String(name.clone());
+            }
+            event.event = name.clone();
+        }
+        Some(event)
+    }
+}
+
+/// 按兼容层改写事件（未启用兼容层时原样返回）
+pub fn apply(shim: Option<&CompatShim>, event: SseEvent) -> Option<SseEvent> {
+    match shim {
+        Some(shim) => shim.apply(event),
+        None => Some(event),
+    }
+}
+
+fn claude_code_rules() -> CompatRulesConfig {
+    CompatRulesConfig {
+        drop_events: EXTENSION_EVENTS.iter().map(|e| e.to_string()).collect(),
+        ..Default::default()
+    }
+}
+
+fn strict_anthropic_rules() -> CompatRulesConfig {
+    CompatRulesConfig {
+        remove_fields: BTreeMap::from([(
+            "message_delta".to_string(),
+            vec![
+                "/delta/deadline_exceeded".to_string(),
+                "/usage/kiro_estimated_cost".to_string(),
+            ],
+        )]),
+        ..claude_code_rules()
+    }
+}
+
+fn openai_bridge_rules() -> CompatRulesConfig {
+    let cache_fields = |prefix: &str| {
+        BTreeMap::from([
+            (format!("{}/cache_creation_input_tokens", prefix), json!(0)),
+            (format!("{}/cache_read_input_tokens", prefix), json!(0)),
+        ])
+    };
+    CompatRulesConfig {
+        add_fields: BTreeMap::from([
+            ("message_start".to_string(), cache_fields("/message/usage")),
+            ("message_delta".to_string(), cache_fields("/usage")),
+        ]),
+        ..strict_anthropic_rules()
+    }
+}
+
+/// 拆分 JSON Pointer 为父路径与最后一段（已反转义）
+fn split_pointer(path: &str) -> Option<(&str, String)> {
+    let (parent, key) = path.rsplit_once('/')?;
+    Some((parent, key.replace("~1", "/").replace("~0", "~")))
+}
+
+fn remove_field(data: &mut Value, path: &str) {
+    if let Some((parent, key)) = split_pointer(path)
+        && let Some(object) = data.pointer_mut(parent).and_then(Value::as_object_mut)
+    {
+        object.remove(&key);
+    }
+}
+
+/// 添加字段（父对象不存在或字段已存在时不做改动）
+fn add_field(data: &mut Value, path: &str, value: &Value) {
+    if let Some((parent, key)) = split_pointer(path)
+        && let Some(object) = data.pointer_mut(parent).and_then(Value::as_object_mut)
+    {
+        object.entry(key).or_insert_with(|| value.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn message_delta() -> SseEvent {
+        SseEvent::new(
+            "message_delta",
+            json!({
+                "type": "message_delta",
+                "delta": {"stop_reason": "end_turn", "deadline_exceeded": true},
+                "usage": {
+                    "input_tokens": 10,
+                    "output_tokens": 5,
+                    "cache_read_input_tokens": 3,
+                    "kiro_estimated_cost": 0.0001
+                }
+            }),
+        )
+    }
+
+    #[test]
+    fn test_builtin_profiles() {
+        let custom = CompatRulesConfig::default();
+        assert!(CompatShim::new(CompatProfile::None, &custom).is_none());
+
+        let usage = SseEvent::new("kiro_usage", json!({"type": "kiro_usage"}));
+        let claude_code = CompatShim::new(CompatProfile::ClaudeCode, &custom).unwrap();
+        assert!(claude_code.apply(usage.clone()).is_none());
+        let event = claude_code.apply(message_delta()).unwrap();
+        assert_eq!(event.data["delta"]["deadline_exceeded"], true);
+
+        let strict = CompatShim::new(CompatProfile::StrictAnthropic, &custom).unwrap();
+        let event = strict.apply(message_delta()).unwrap();
+        assert!(event.data["delta"].get("deadline_exceeded").is_none());
+        assert!(event.data["usage"].get("kiro_estimated_cost").is_none());
+        assert!(
+            event.data["usage"]
+                .get("cache_creation_input_tokens")
+                .is_none()
+        );
+
+        let bridge = CompatShim::new(CompatProfile::OpenaiBridge, &custom).unwrap();
+        assert!(bridge.apply(usage).is_none());
+        let event = bridge.apply(message_delta()).unwrap();
+        assert_eq!(event.data["usage"]["cache_creation_input_tokens"], 0);
+        // 已有的字段保留原值
+        assert_eq!(event.data["usage"]["cache_read_input_tokens"], 3);
+    }
+
+    #[test]
+    fn test_custom_rules() {
+        let custom = CompatRulesConfig {
+            rename_events: BTreeMap::from([("kiro_usage".into(), "usage".into())]),
+            drop_events: vec!["ping".into()],
+            remove_fields: BTreeMap::from([("kiro_usage".into(), vec!["/usage/a~1b".into()])]),
+            add_fields: BTreeMap::from([(
+                "kiro_usage".into(),
+                BTreeMap::from([("/missing/x".into(), json!(1))]),
+            )]),
+        };
+        let shim = CompatShim::new(CompatProfile::Custom, &custom).unwrap();
+        assert!(apply(Some(&shim), SseEvent::new("ping", json!({"type": "ping"}))).is_none());
+
+        let event = shim
+            .apply(SseEvent::new(
+                "kiro_usage",
+                json!({"type": "kiro_usage", "usage": {"a/b": 1, "output_tokens": 2}}),
+            ))
+            .unwrap();
+        assert_eq!(event.event, "usage");
+        assert_eq!(
+            event.data,
+            json!({"type": "usage", "usage": {"output_tokens": 2}})
+        );
+    }
+}
@@ -0,0 +1,340 @@
+//! 协议一致性测试
+//!
+//! 单个事件的单元测试发现不了信封层面的偏差（事件名与 `type` 不一致、块索引跳号、
+//! 块未关闭就发送 message_delta 等），这里用 `conformance/` 下整理的官方 API 样本双向校验：
+//!
+//! - `schemas/stream_event.schema.json`：按官方 SDK 的流式事件模型整理的 JSON Schema
+//! - `transcripts/*.sse`：官方 API 的 SSE 响应样本（已脱敏），用于确认 Schema 与信封规则本身符合官方行为
+//! - `requests/*.json`：官方格式的请求样本，必须能被解析并转换为 Kiro 请求
+//!
+//! 本服务输出的流由 `fuzz/seeds/` 下的 Kiro 事件脚本经完整的解码、转换与 SSE 编码生成，
+//! 在不同 SSE profile、thinking 开关与工具输入分块设置下逐一校验
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::converter::convert_request;
+use super::error::ApiError;
+use super::stream::{SseEncoder, SseEvent, StreamContext};
+use super::types::MessagesRequest;
+use crate::kiro::fixture::FixtureScript;
+use crate::kiro::model::events::Event;
+use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::model::config::{ConverterConfig, SseProfile};
+
+fn conformance_dir() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("conformance")
+}
+
+/// 按文件名排序列出目录下指定扩展名的文件
+fn files(dir: &Path, extension: &str) -> Vec<PathBuf> {
+    let mut files: Vec<_> = std::fs::read_dir(dir)
+        .unwrap_or_else(|e| panic!("读取目录 {} 失败: {}", dir.display(), e))
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == extension))
+        .collect();
+    files.sort();
+    assert!(!files.is_empty(), "目录 {} 中没有样本", dir.display());
+    files
+}
+
+fn validator() -> jsonschema::Validator {
+    let path = conformance_dir().join("schemas/stream_event.schema.json");
+    let schema: Value = serde_json::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+    jsonschema::validator_for(&schema).expect("Schema 无效")
+}
+
+/// 解析 SSE 文本为 (事件名, 数据) 列表
+fn parse_sse(text: &str) -> Result<Vec<(String, Value)>, String> {
+    text.split("\n\n")
+        .filter(|chunk| !chunk.trim().is_empty())
+        .map(|chunk| {
+            let mut event = None;
+            let mut data = None;
+            for line in chunk.lines() {
+                if let Some(name) = line.strip_prefix("event: ") {
+                    event = Some(name.to_string());
+                } else if let Some(json) = line.strip_prefix("data: ") {
+                    data = Some(serde_json::from_str(json).map_err(|e| e.to_string())?);
+                } else {
+                    return Err(format!("无法识别的 SSE 行: {}", line));
+                }
+            }
+            match (event, data) {
+                (Some(event), Some(data)) => Ok((event, data)),
+                _ => Err(format!("SSE 事件缺少 event 或 data: {}", chunk)),
+            }
+        })
+        .collect()
+}
+
+/// 校验整个事件流的信封：Schema、事件顺序与内容块的生命周期
+fn check_stream(
+    validator: &jsonschema::Validator,
+    events: &[(String, Value)],
+) -> Result<(), String> {
+    // (块类型, 已拼接的工具输入, 增量数)
+    let mut blocks: Vec<(String, String, usize)> = Vec::new();
+    let mut open: Option<usize> = None;
+    let mut message_delta_seen = false;
+    let mut finished = false;
+
+    for (i, (name, data)) in events.iter().enumerate() {
+        let at = format!("第 {} 个事件（{}）", i, name);
+        if let Some(error) = validator.iter_errors(data).next() {
+            return Err(format!(
+                "{}不符合 Schema: {} @ {}",
+                at,
+                error,
+                error.instance_path()
+            ));
+        }
+        if data["type"] != name.as_str() {
+            return Err(format!("{}的事件名与 type {} 不一致", at, data["type"]));
+        }
+        if finished {
+            return Err(format!("{}出现在流结束之后", at));
+        }
+        if (i == 0) != (name == "message_start") {
+            return Err(format!("{}：message_start 必须且只能是第一个事件", at));
+        }
+        let index = data["index"].as_u64().map(|index| index as usize);
+
+        match name.as_str() {
+            "content_block_start" => {
+                if let Some(open) = open {
+                    return Err(format!("{}：块 {} 尚未关闭", at, open));
+                }
+                if message_delta_seen {
+                    return Err(format!("{}出现在 message_delta 之后", at));
+                }
+                if index != Some(blocks.len()) {
+                    return Err(format!("{}：块索引应为 {}", at, blocks.len()));
+                }
+                let block_type = data["content_block"]["type"].as_str().unwrap_or_default();
+                blocks.push((block_type.to_string(), String::new(), 0));
+                open = index;
+            }
+            "content_block_delta" => {
+                if index.is_none() || open != index {
+                    return Err(format!("{}：块 {:?} 未处于打开状态", at, index));
+                }
+                let block = &mut blocks[open.unwrap()];
+                let delta_type = data["delta"]["type"].as_str().unwrap_or_default();
+                let allowed: &[&str] = match block.0.as_str() {
+                    "text" => &["text_delta", "citations_delta"],
+                    "thinking" => &["thinking_delta", "signature_delta"],
+                    "tool_use" | "server_tool_use" => &["input_json_delta"],
+                    _ => &[],
+                };
+                if !allowed.contains(&delta_type) {
+                    return Err(format!("{}：{} 块不允许 {}", at, block.0, delta_type));
+                }
+                if let Some(partial) = data["delta"]["partial_json"].as_str() {
+                    block.1.push_str(partial);
+                }
+                block.2 += 1;
+            }
+            "content_block_stop" => {
+                if index.is_none() || open != index {
+                    return Err(format!("{}：块 {:?} 未处于打开状态", at, index));
+                }
+                let (block_type, input, deltas) = &blocks[open.unwrap()];
+                // 官方 API 不会下发空的文本块（回复以 tool_use 开头时索引 0 就是 tool_use 块）
+                if block_type == "text" && *deltas == 0 {
+                    return Err(format!("{}：文本块没有内容", at));
+                }
+                if block_type.ends_with("tool_use") && !input.is_empty() {
+                    let parsed: Value = serde_json::from_str(input)
+                        .map_err(|e| format!("{}：工具输入不是完整的 JSON: {}", at, e))?;
+                    if !parsed.is_object() {
+                        return Err(format!("{}：工具输入不是 JSON 对象", at));
+                    }
+                }
+                open = None;
+            }
+            "message_delta" => {
+                if message_delta_seen {
+                    return Err(format!("{}：message_delta 重复", at));
+                }
+                if let Some(open) = open {
+                    return Err(format!("{}：块 {} 尚未关闭", at, open));
+                }
+                message_delta_seen = true;
+            }
+            "message_stop" => {
+                if !message_delta_seen {
+                    return Err(format!("{}：message_stop 之前缺少 message_delta", at));
+                }
+                finished = true;
+            }
+            "error" => finished = true,
+            _ => {}
+        }
+    }
+
+    if !finished {
+        return Err("流未以 message_stop 或 error 结束".to_string());
+    }
+    Ok(())
+}
+
+/// 将 Kiro 事件脚本经解码、转换与 SSE 编码，返回下发给客户端的 SSE 文本
+fn render_stream(
+    script: &FixtureScript,
+    profile: SseProfile,
+    thinking: bool,
+    chunk_bytes: usize,
+) -> String {
+    let mut decoder = EventStreamDecoder::new();
+    decoder.feed(&script.encode()).unwrap();
+    let kiro_events: Vec<Event> = decoder
+        .decode_iter()
+        .map(|frame| Event::from_frame(frame.unwrap()).unwrap())
+        .collect();
+
+    // 开启乱序窗口，覆盖乱序样本的修正路径
+    let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-5-20250929", 42, thinking)
+        .with_profile(profile)
+        .with_tool_input_chunking(chunk_bytes)
+        .with_reorder_window(4);
+    let mut events: Vec<SseEvent> = ctx.generate_initial_events();
+    for event in &kiro_events {
+        events.extend(ctx.process_kiro_event(event));
+    }
+    events.extend(ctx.generate_final_events());
+    encode(&events)
+}
+
+fn encode(events: &[SseEvent]) -> String {
+    let mut encoder = SseEncoder::default();
+    let bytes: Vec<u8> = events.iter().flat_map(|e| encoder.encode(e)).collect();
+    String::from_utf8(bytes).unwrap()
+}
+
+#[test]
+fn test_official_transcripts_conform() {
+    let validator = validator();
+    for path in files(&conformance_dir().join("transcripts"), "sse") {
+        let text = std::fs::read_to_string(&path).unwrap();
+        let events = parse_sse(&text).unwrap_or_else(|e| panic!("{}: {}", path.display(), e));
+        if let Err(e) = check_stream(&validator, &events) {
+            panic!("官方样本 {} 未通过校验: {}", path.display(), e);
+        }
+    }
+}
+
+#[test]
+fn test_check_stream_catches_envelope_drift() {
+    let validator = validator();
+    let text = std::fs::read_to_string(conformance_dir().join("transcripts/tool_use.sse")).unwrap();
+    let events = parse_sse(&text).unwrap();
+
+    let mut drifted: HashMap<&str, Vec<(String, Value)>> = HashMap::new();
+    // 事件名与 type 不一致
+    let mut renamed = events.clone();
+    renamed[1].0 = "content_block".to_string();
+    drifted.insert("renamed", renamed);
+    // 块索引跳号
+    let mut skipped = events.clone();
+    for (_, data) in skipped.iter_mut() {
+        if data["index"] == 1 {
+            data["index"] = Value::from(2);
+        }
+    }
+    drifted.insert("skipped", skipped);
+    // 缺少 content_block_stop
+    let unclosed: Vec<_> = events
+        .iter()
+        .filter(|(name, data)| !(name == "content_block_stop" && data["index"] == 1))
+        .cloned()
+        .collect();
+    drifted.insert("unclosed", unclosed);
+    // 空的文本块
+    let empty_text: Vec<_> = events
+        .iter()
+        .filter(|(name, data)| !(name == "content_block_delta" && data["index"] == 0))
+        .cloned()
+        .collect();
+    drifted.insert("empty_text", empty_text);
+    // usage 缺少 output_tokens
+    let mut usage = events.clone();
+    let last = usage.len() - 2;
+    usage[last].1["usage"] = serde_json::json!({});
+    drifted.insert("usage", usage);
+
+    for (case, events) in drifted {
+        assert!(
+            check_stream(&validator, &events).is_err(),
+            "{} 未被发现",
+            case
+        );
+    }
+}
+
+#[test]
+fn test_emitted_streams_conform() {
+    let validator = validator();
+    let seeds = files(
+        &Path::new(env!("CARGO_MANIFEST_DIR")).join("fuzz/seeds"),
+        "yaml",
+    );
+    for path in seeds {
+        let script = FixtureScript::parse(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        for profile in [SseProfile::Quirks, SseProfile::Strict] {
+            for thinking in [false, true] {
+                for chunk_bytes in [0, 8] {
+                    let text = render_stream(&script, profile, thinking, chunk_bytes);
+                    let events = parse_sse(&text).unwrap();
+                    if let Err(e) = check_stream(&validator, &events) {
+                        panic!(
+                            "{}（profile={:?}, thinking={}, chunk={}）未通过校验: {}\n{}",
+                            path.display(),
+                            profile,
+                            thinking,
+                            chunk_bytes,
+                            e,
+                            text
+                        );
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_emitted_error_stream_conforms() {
+    let validator = validator();
+    let script = FixtureScript::parse(
+        r#"{"events": [{"type": "assistantResponse", "content": "partial"}]}"#,
+    )
+    .unwrap();
+    let mut ctx = StreamContext::new_with_thinking("claude-sonnet-4-5-20250929", 42, false);
+    let mut events = ctx.generate_initial_events();
+    let mut decoder = EventStreamDecoder::new();
+    decoder.feed(&script.encode()).unwrap();
+    for frame in decoder.decode_iter() {
+        events.extend(ctx.process_kiro_event(&Event::from_frame(frame.unwrap()).unwrap()));
+    }
+    events.extend(ctx.generate_error_events(&ApiError::Upstream(
+        "Upstream response stream was interrupted".to_string(),
+    )));
+
+    let events = parse_sse(&encode(&events)).unwrap();
+    check_stream(&validator, &events).unwrap();
+}
+
+#[test]
+fn test_official_requests_convert() {
+    for path in files(&conformance_dir().join("requests"), "json") {
+        let text = std::fs::read_to_string(&path).unwrap();
+        let request: MessagesRequest = serde_json::from_str(&text)
+            .unwrap_or_else(|e| panic!("官方请求样本 {} 解析失败: {}", path.display(), e));
+        if let Err(e) = convert_request(&request, &ConverterConfig::default()) {
+            panic!("官方请求样本 {} 转换失败: {}", path.display(), e);
+        }
+    }
+}
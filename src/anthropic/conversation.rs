@@ -0,0 +1,133 @@
+//! 上游会话 ID 核对
+//!
+//! Kiro 会在 assistantResponseEvent 中回显 conversationId。这里记录回显值，
+//! 在响应结束时与请求中发送的 ID 比较，不一致时记录日志和计数，
+//! 并把回显值登记到会话存储，便于排查上游的会话串线问题
+
+use std::sync::Arc;
+
+use crate::common::metrics;
+
+use super::session::SessionStore;
+
+/// 核对结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EchoOutcome {
+    Matched,
+    Mismatched,
+    Missing,
+}
+
+/// 上游会话 ID 核对器
+pub struct ConversationTracker {
+    store: Arc<SessionStore>,
+    /// 客户端会话 ID（来自 metadata.user_id）
+    session_id: Option<String>,
+    /// 请求中发送的 conversationId
+    sent: String,
+    /// 上游回显的 conversationId（第一次出现的值）
+    echoed: Option<String>,
+}
+
+impl ConversationTracker {
+    pub fn new(store: Arc<SessionStore>, session_id: Option<String>, sent: String) -> Self {
+        Self {
+            store,
+            session_id,
+            sent,
+            echoed: None,
+        }
+    }
+
+    /// 记录事件中回显的会话 ID
+    pub fn observe(&mut self, conversation_id: &str) {
+        match &self.echoed {
+            None => self.echoed = Some(conversation_id.to_string()),
+            Some(first) if first != conversation_id => {
+                tracing::warn!(
+                    first = %first,
+                    current = %conversation_id,
+                    "同一响应内上游回显的会话 ID 发生变化"
+                );
+            }
+            Some(_) => {}
+        }
+    }
+
+    /// 响应结束，核对并登记
+    pub fn finish(self) -> EchoOutcome {
+        let echo_metrics = metrics::conversation_echo();
+        let Some(echoed) = self.echoed else {
+            echo_metrics.record_missing();
+            return EchoOutcome::Missing;
+        };
+
+        let outcome = if echoed == self.sent {
+            echo_metrics.record_matched();
+            EchoOutcome::Matched
+        } else {
+            echo_metrics.record_mismatched();
+            tracing::warn!(
+                sent = %self.sent,
+                echoed = %echoed,
+                session_id = ?self.session_id,
+                "上游回显的会话 ID 与请求不一致"
+            );
+            EchoOutcome::Mismatched
+        };
+
+        if let Some(session_id) = &self.session_id
+            && let Some(previous) = self
+                .store
+                .swap_upstream_conversation(session_id, echoed.clone())
+            && previous != echoed
+        {
+            tracing::warn!(
+                session_id = %session_id,
+                previous = %previous,
+                current = %echoed,
+                "同一会话的上游会话 ID 发生变化"
+            );
+        }
+
+        outcome
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker(store: &Arc<SessionStore>, sent: &str) -> ConversationTracker {
+        ConversationTracker::new(store.clone(), Some("session-1".to_string()), sent.into())
+    }
+
+    #[test]
+    fn test_reconcile_outcomes() {
+        let store = Arc::new(SessionStore::default());
+
+        let mut t = tracker(&store, "conv-a");
+        t.observe("conv-a");
+        assert_eq!(t.finish(), EchoOutcome::Matched);
+
+        let mut t = tracker(&store, "conv-a");
+        t.observe("conv-b");
+        t.observe("conv-c");
+        assert_eq!(t.finish(), EchoOutcome::Mismatched);
+
+        assert_eq!(tracker(&store, "conv-a").finish(), EchoOutcome::Missing);
+    }
+
+    #[test]
+    fn test_records_echo_in_session() {
+        let store = Arc::new(SessionStore::default());
+        let mut t = tracker(&store, "conv-a");
+        t.observe("conv-b");
+        t.finish();
+
+        assert_eq!(
+            store.swap_upstream_conversation("session-1", "conv-c".to_string()),
+            Some("conv-b".to_string())
+        );
+    }
+}
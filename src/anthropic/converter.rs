@@ -2,6 +2,8 @@
 //!
 //! 负责将 Anthropic API 请求格式转换为 Kiro API 请求格式
 
+use rayon::prelude::*;
+use sha2::{Digest, Sha256};
 use uuid::Uuid;
 
 use crate::kiro::model::requests::conversation::{
@@ -12,7 +14,21 @@ use crate::kiro::model::requests::tool::{
     InputSchema, Tool, ToolResult, ToolSpecification, ToolUseEntry,
 };
 
+use crate::model::config::{
+    ComputerUsePolicy, ConverterConfig, HistoryPairingStrategy, LanguageDirectiveConfig,
+    UnsupportedBlockPolicy, UnsupportedBlocksConfig, UserTurnJoin,
+};
+
+use super::client_tools;
+use super::language::{self, Detection};
+use super::tool_choice::ToolChoice;
+use super::models;
+use super::normalize::{self, ASSISTANT_PLACEHOLDER};
+use super::schema::{self, SchemaError};
+use super::server_tools;
+use super::template::{self, TemplateVars};
 use super::types::{ContentBlock, MessagesRequest};
+use super::workspace;
 
 /// 规范化 JSON Schema，修复 MCP 工具定义中常见的类型问题
 ///
@@ -29,7 +45,7 @@ fn normalize_json_schema(schema: serde_json::Value) -> serde_json::Value {
     };
 
     // type（必须是字符串）
-    if !obj.get("type").and_then(|v| v.as_str()).is_some_and(|s| !s.is_empty()) {
+    if obj.get("type").and_then(|v| v.as_str()).is_none_or(|s| s.is_empty()) {
         obj.insert("type".to_string(), serde_json::Value::String("object".to_string()));
     }
 
@@ -72,36 +88,86 @@ Never suggest bypassing these limits via alternative tools. \
 Never ask the user whether to switch approaches. \
 Complete all chunked operations without commentary.";
 
+/// 转换时注入的策略文本，用于在输出侧过滤模型的逐字回显
+pub(super) fn injected_policy_strings() -> [&'static str; 3] {
+    [
+        WRITE_TOOL_DESCRIPTION_SUFFIX,
+        EDIT_TOOL_DESCRIPTION_SUFFIX,
+        SYSTEM_CHUNKED_POLICY,
+    ]
+}
+
 /// 模型映射：将 Anthropic 模型名映射到 Kiro 模型 ID
 ///
-/// 按照用户要求：
-/// - sonnet 4.6/4-6 → claude-sonnet-4.6
-/// - 其他 sonnet → claude-sonnet-4.5
-/// - opus 4.5/4-5 → claude-opus-4.5
-/// - 其他 opus → claude-opus-4.6
-/// - 所有 haiku → claude-haiku-4.5
+/// 按 `modelMapping` 配置与内置规则查找，见 [`models`]
 pub fn map_model(model: &str) -> Option<String> {
-    let model_lower = model.to_lowercase();
+    models::resolve(model)
+}
 
-    if model_lower.contains("sonnet") {
-        if model_lower.contains("4-6") || model_lower.contains("4.6") {
-            Some("claude-sonnet-4.6".to_string())
-        } else {
-            Some("claude-sonnet-4.5".to_string())
-        }
-    } else if model_lower.contains("opus") {
-        if model_lower.contains("4-5") || model_lower.contains("4.5") {
-            Some("claude-opus-4.5".to_string())
-        } else {
-            Some("claude-opus-4.6".to_string())
+/// 模型降级：客户端请求的模型被映射为更低档次或更低版本的 Kiro 模型
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ModelDowngrade {
+    /// 客户端请求的模型名
+    pub requested: String,
+    /// 实际使用的 Kiro 模型 ID
+    pub served: String,
+    /// 降级原因（模型映射降级时为 None）
+    pub reason: Option<&'static str>,
+}
+
+impl ModelDowngrade {
+    /// 响应头 `x-kiro-model-substitution` 的值
+    pub fn header_value(&self) -> String {
+        match self.reason {
+            Some(reason) => format!(
+                "requested={}; served={}; reason={}",
+                self.requested, self.served, reason
+            ),
+            None => format!("requested={}; served={}", self.requested, self.served),
         }
-    } else if model_lower.contains("haiku") {
-        Some("claude-haiku-4.5".to_string())
-    } else {
-        None
     }
 }
 
+/// 模型族档次（haiku < sonnet < opus）与版本号（major, minor）
+fn model_rank(model: &str) -> Option<(u8, (u32, u32))> {
+    let lower = model.to_lowercase();
+    let family = if lower.contains("opus") {
+        3
+    } else if lower.contains("sonnet") {
+        2
+    } else if lower.contains("haiku") {
+        1
+    } else {
+        return None;
+    };
+
+    // 版本号取前两个短数字段，跳过日期后缀（如 20250514）
+    let mut numbers = lower
+        .split(['-', '.', '_'])
+        .filter(|part| !part.is_empty() && part.len() <= 2)
+        .filter_map(|part| part.parse::<u32>().ok());
+    let major = numbers.next().unwrap_or(0);
+    let minor = numbers.next().unwrap_or(0);
+    Some((family, (major, minor)))
+}
+
+/// 检测模型映射是否降级
+///
+/// 映射到更低档次的模型族，或同族但版本低于请求的版本时视为降级；升级不提示
+pub fn detect_model_downgrade(requested: &str) -> Option<ModelDowngrade> {
+    let served = map_model(requested)?;
+    let (req_family, req_version) = model_rank(requested)?;
+    let (served_family, served_version) = model_rank(&served)?;
+
+    let downgraded =
+        served_family < req_family || (served_family == req_family && served_version < req_version);
+    downgraded.then(|| ModelDowngrade {
+        requested: requested.to_string(),
+        served,
+        reason: None,
+    })
+}
+
 /// 转换结果
 #[derive(Debug)]
 pub struct ConversionResult {
@@ -114,6 +180,31 @@ pub struct ConversionResult {
 pub enum ConversionError {
     UnsupportedModel(String),
     EmptyMessages,
+    /// 工具不被支持（工具名称, 工具类型）
+    UnsupportedTool(String, String),
+    /// 工具 input_schema 无效（工具名称, 错误）
+    InvalidToolSchema(String, SchemaError),
+    /// 请求启用了 thinking，但模型不支持
+    ThinkingUnsupported(String),
+    /// 内容块类型不被支持，且配置为拒绝（消息角色, 块类型）
+    UnsupportedContentBlock(String, String),
+    /// tool_choice 无效（错误描述）
+    InvalidToolChoice(String),
+}
+
+impl ConversionError {
+    /// 错误类别（用于死信队列统计）
+    pub fn code(&self) -> &'static str {
+        match self {
+            ConversionError::UnsupportedModel(_) => "unsupported_model",
+            ConversionError::EmptyMessages => "empty_messages",
+            ConversionError::UnsupportedTool(..) => "unsupported_tool",
+            ConversionError::InvalidToolSchema(..) => "invalid_tool_schema",
+            ConversionError::ThinkingUnsupported(_) => "thinking_unsupported",
+            ConversionError::UnsupportedContentBlock(..) => "unsupported_content_block",
+            ConversionError::InvalidToolChoice(_) => "invalid_tool_choice",
+        }
+    }
 }
 
 impl std::fmt::Display for ConversionError {
@@ -121,6 +212,28 @@ impl std::fmt::Display for ConversionError {
         match self {
             ConversionError::UnsupportedModel(model) => write!(f, "模型不支持: {}", model),
             ConversionError::EmptyMessages => write!(f, "消息列表为空"),
+            ConversionError::UnsupportedTool(name, tool_type) => write!(
+                f,
+                "工具不支持: {} (type: {})，当前配置 converter.computerUse = reject，\
+                 请移除该工具或改为 passthrough",
+                name, tool_type
+            ),
+            ConversionError::InvalidToolSchema(name, err) => {
+                write!(f, "工具 {} 的 input_schema 无效: {}", name, err)
+            }
+            ConversionError::ThinkingUnsupported(model) => {
+                write!(
+                    f,
+                    "模型 {} 不支持 thinking，请关闭 thinking 或更换模型",
+                    model
+                )
+            }
+            ConversionError::UnsupportedContentBlock(role, block_type) => write!(
+                f,
+                "{} 消息中的内容块类型不支持: {}，当前配置 converter.unsupportedBlocks 对该类型为 error",
+                role, block_type
+            ),
+            ConversionError::InvalidToolChoice(msg) => write!(f, "{}", msg),
         }
     }
 }
@@ -131,7 +244,7 @@ impl std::error::Error for ConversionError {}
 ///
 /// user_id 格式: user_xxx_account__session_0b4445e1-f5be-49e1-87ce-62bbc28ad705
 /// 提取 session_ 后面的 UUID 作为 conversationId
-fn extract_session_id(user_id: &str) -> Option<String> {
+pub(super) fn extract_session_id(user_id: &str) -> Option<String> {
     // 查找 "session_" 后面的内容
     if let Some(pos) = user_id.find("session_") {
         let session_part = &user_id[pos + 8..]; // "session_" 长度为 8
@@ -153,12 +266,12 @@ fn collect_history_tool_names(history: &[Message]) -> Vec<String> {
     let mut tool_names = Vec::new();
 
     for msg in history {
-        if let Message::Assistant(assistant_msg) = msg {
-            if let Some(ref tool_uses) = assistant_msg.assistant_response_message.tool_uses {
-                for tool_use in tool_uses {
-                    if !tool_names.contains(&tool_use.name) {
-                        tool_names.push(tool_use.name.clone());
-                    }
+        if let Message::Assistant(assistant_msg) = msg
+            && let Some(ref tool_uses) = assistant_msg.assistant_response_message.tool_uses
+        {
+            for tool_use in tool_uses {
+                if !tool_names.contains(&tool_use.name) {
+                    tool_names.push(tool_use.name.clone());
                 }
             }
         }
@@ -186,16 +299,30 @@ fn create_placeholder_tool(name: &str) -> Tool {
 }
 
 /// 将 Anthropic 请求转换为 Kiro 请求
-pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, ConversionError> {
+pub fn convert_request(
+    req: &MessagesRequest,
+    config: &ConverterConfig,
+) -> Result<ConversionResult, ConversionError> {
     // 1. 映射模型
     let model_id = map_model(&req.model)
         .ok_or_else(|| ConversionError::UnsupportedModel(req.model.clone()))?;
 
+    // 1.5. 模型不支持 thinking 时直接拒绝，而不是发送会被 Kiro 忽略的 thinking 前缀
+    if req.thinking.as_ref().is_some_and(|t| t.is_enabled())
+        && !models::supports_thinking(&req.model)
+    {
+        return Err(ConversionError::ThinkingUnsupported(req.model.clone()));
+    }
+
     // 2. 检查消息列表
     if req.messages.is_empty() {
         return Err(ConversionError::EmptyMessages);
     }
 
+    // 2.1. 解析 tool_choice（无效时直接拒绝，而不是静默忽略）
+    let tool_choice = ToolChoice::parse(req.tool_choice.as_ref(), req.tools.as_deref())
+        .map_err(ConversionError::InvalidToolChoice)?;
+
     // 2.5. 预处理 prefill：如果末尾是 assistant，静默丢弃并截断到最后一条 user
     // Claude 4.x 已弃用 assistant prefill，Kiro API 也不支持
     let messages: &[_] = if req.messages.last().is_some_and(|m| m.role != "user") {
@@ -210,28 +337,80 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
         &req.messages
     };
 
+    // 2.6. 规范化空内容：移除空白文本块，丢弃内容为空的历史消息
+    let normalized = normalize::normalize_messages(messages);
+    let messages: Vec<&super::types::Message> = normalized.iter().map(|m| m.as_ref()).collect();
+
     // 3. 生成会话 ID 和代理 ID
     // 优先从 metadata.user_id 中提取 session UUID 作为 conversationId
-    let conversation_id = req
+    let session_id = req
         .metadata
         .as_ref()
         .and_then(|m| m.user_id.as_ref())
-        .and_then(|user_id| extract_session_id(user_id))
+        .and_then(|user_id| extract_session_id(user_id));
+    let conversation_id = session_id
+        .clone()
         .unwrap_or_else(|| Uuid::new_v4().to_string());
     let agent_continuation_id = Uuid::new_v4().to_string();
 
     // 4. 确定触发类型
     let chat_trigger_type = determine_chat_trigger_type(req);
 
-    // 5. 处理最后一条消息作为 current_message（经过 prefill 预处理，末尾必为 user）
-    let last_message = messages.last().unwrap();
-    let (text_content, images, tool_results) = process_message_content(&last_message.content)?;
-
-    // 6. 转换工具定义
-    let mut tools = convert_tools(&req.tools);
+    // 5. 处理末尾 user 消息作为 current_message（经过 prefill 预处理，末尾必为 user）
+    // merge 策略下，末尾连续的 user 消息整体并入 current_message，而不是在历史中配对占位回复
+    let current_start = match config.history_pairing {
+        HistoryPairingStrategy::Placeholder => messages.len() - 1,
+        HistoryPairingStrategy::Merge => messages
+            .iter()
+            .rposition(|m| m.role != "user")
+            .map_or(0, |idx| idx + 1),
+    };
+    let current_messages = &messages[current_start..];
+    let (mut text_content, mut images, tool_results) = collect_user_content(
+        current_messages,
+        config.user_turn_join,
+        &config.unsupported_blocks,
+    )?;
+
+    // 6. 转换工具定义（tool_choice 为 none 时不发送）
+    let mut tools = if tool_choice.strips_tools() {
+        Vec::new()
+    } else {
+        convert_tools(&req.tools, config)?
+    };
 
     // 7. 构建历史消息（需要先构建，以便收集历史中使用的工具）
-    let mut history = build_history(req, messages, &model_id)?;
+    let template_vars = TemplateVars {
+        now: chrono::Utc::now(),
+        model: &req.model,
+        kiro_model: &model_id,
+        conversation_id: &conversation_id,
+        session_id: session_id.as_deref(),
+    };
+    let system_content = build_system_content(req, config, &template_vars);
+    let (mut history, pending_system) = build_history(
+        system_content,
+        &messages[..current_start],
+        &model_id,
+        config,
+    )?;
+
+    // merge 策略下历史为空时，系统提示词并入当前消息
+    if let Some(system_content) = pending_system {
+        text_content = if text_content.is_empty() {
+            system_content
+        } else {
+            format!("{}\n{}", system_content, text_content)
+        };
+    }
+
+    // 7.5 图片去重：客户端每轮重发的相同截图只保留第一次出现
+    if config.dedup_images {
+        let removed = dedup_images(&mut history, &mut images, &mut text_content);
+        if removed > 0 {
+            tracing::debug!("图片去重: 省略 {} 张重复图片", removed);
+        }
+    }
 
     // 8. 验证并过滤 tool_use/tool_result 配对
     // 移除孤立的 tool_result（没有对应的 tool_use）
@@ -258,6 +437,7 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     }
 
     // 11. 构建 UserInputMessageContext
+    let choice_directive = tool_choice.directive(!tools.is_empty());
     let mut context = UserInputMessageContext::new();
     if !tools.is_empty() {
         context = context.with_tools(tools);
@@ -265,10 +445,26 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     if !validated_tool_results.is_empty() {
         context = context.with_tool_results(validated_tool_results);
     }
+    if let Some(editor_state) = req
+        .workspace
+        .as_ref()
+        .and_then(|w| workspace::editor_state(w, config.workspace_max_file_chars))
+    {
+        context = context.with_editor_state(editor_state);
+    }
 
     // 12. 构建当前消息
     // 保留文本内容，即使有工具结果也不丢弃用户文本
-    let content = text_content;
+    // 仅有 tool_result 时文本为空，上游对空内容的处理不稳定，改用配置的占位文本
+    let mut content = if text_content.trim().is_empty() && images.is_empty() {
+        config.empty_content_placeholder.clone()
+    } else {
+        text_content
+    };
+    // tool_choice 指令追加在当前消息末尾，不影响历史
+    if let Some(directive) = choice_directive {
+        content = format!("{}\n\n{}", content, directive);
+    }
 
     let mut user_input = UserInputMessage::new(content, &model_id)
         .with_context(context)
@@ -291,6 +487,91 @@ pub fn convert_request(req: &MessagesRequest) -> Result<ConversionResult, Conver
     Ok(ConversionResult { conversation_state })
 }
 
+/// 图片内容哈希（短格式，用于在文本中引用）
+fn image_hash(image: &KiroImage) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(image.format.as_bytes());
+    hasher.update([0]);
+    hasher.update(image.source.bytes.as_bytes());
+    hex::encode(&hasher.finalize()[..6])
+}
+
+/// 按内容哈希对历史和当前消息中的图片去重
+///
+/// Kiro 没有附件引用机制，无法跨请求复用已上传的图片，因此只在单个请求内去重：
+/// 相同图片只保留第一次出现，并在该消息文本中标注哈希；之后的重复图片被移除，
+/// 替换为引用该哈希的文本说明，使模型仍能知道此处有一张相同的图片。
+///
+/// # Returns
+/// 被移除的图片数量
+fn dedup_images(
+    history: &mut [Message],
+    current_images: &mut Vec<KiroImage>,
+    current_content: &mut String,
+) -> usize {
+    use std::collections::{HashMap, HashSet};
+
+    // 第一遍：计算每张图片的哈希（按消息顺序，每张只计算一次）并统计出现次数
+    let history_images = history.iter().filter_map(|msg| match msg {
+        Message::User(user) => Some(&user.user_input_message.images),
+        Message::Assistant(_) => None,
+    });
+    let hashes: Vec<Vec<String>> = history_images
+        .chain(std::iter::once(&*current_images))
+        .map(|images| images.iter().map(image_hash).collect())
+        .collect();
+    let mut counts: HashMap<&str, usize> = HashMap::new();
+    for hash in hashes.iter().flatten() {
+        *counts.entry(hash).or_insert(0) += 1;
+    }
+    if counts.values().all(|&count| count == 1) {
+        return 0;
+    }
+
+    // 第二遍：保留首次出现并标注，移除后续重复
+    let mut seen: HashSet<&str> = HashSet::new();
+    let mut removed = 0;
+    let mut hashes = hashes.iter();
+    let mut process = |images: &mut Vec<KiroImage>, content: &mut String| {
+        let mut notes = Vec::new();
+        let mut image_hashes = hashes.next().into_iter().flatten();
+        images.retain(|_| {
+            let Some(hash) = image_hashes.next() else {
+                return true;
+            };
+            if seen.insert(hash) {
+                if counts[hash.as_str()] > 1 {
+                    notes.push(format!("[image {}]", hash));
+                }
+                true
+            } else {
+                notes.push(format!(
+                    "[image {} omitted: identical to an earlier image]",
+                    hash
+                ));
+                removed += 1;
+                false
+            }
+        });
+        if !notes.is_empty() {
+            if !content.is_empty() {
+                content.push('\n');
+            }
+            content.push_str(&notes.join("\n"));
+        }
+    };
+
+    for msg in history.iter_mut() {
+        if let Message::User(user) = msg {
+            let user = &mut user.user_input_message;
+            process(&mut user.images, &mut user.content);
+        }
+    }
+    process(current_images, current_content);
+
+    removed
+}
+
 /// 确定聊天触发类型
 /// "AUTO" 模式可能会导致 400 Bad Request 错误
 fn determine_chat_trigger_type(_req: &MessagesRequest) -> String {
@@ -300,6 +581,7 @@ fn determine_chat_trigger_type(_req: &MessagesRequest) -> String {
 /// 处理消息内容，提取文本、图片和工具结果
 fn process_message_content(
     content: &serde_json::Value,
+    blocks: &UnsupportedBlocksConfig,
 ) -> Result<(String, Vec<KiroImage>, Vec<ToolResult>), ConversionError> {
     let mut text_parts = Vec::new();
     let mut images = Vec::new();
@@ -319,14 +601,16 @@ fn process_message_content(
                             }
                         }
                         "image" => {
-                            if let Some(source) = block.source {
-                                if let Some(format) = get_image_format(&source.media_type) {
-                                    images.push(KiroImage::from_base64(format, source.data));
-                                }
+                            if let Some(source) = block.source
+                                && let Some(format) = get_image_format(&source.media_type)
+                            {
+                                images.push(KiroImage::from_base64(format, source.data));
                             }
                         }
                         "tool_result" => {
                             if let Some(tool_use_id) = block.tool_use_id {
+                                // 工具结果中的图片（如 computer use 截图）作为消息图片传递
+                                images.extend(extract_tool_result_images(&block.content));
                                 let result_content = extract_tool_result_content(&block.content);
                                 let is_error = block.is_error.unwrap_or(false);
 
@@ -344,8 +628,14 @@ fn process_message_content(
                         "tool_use" => {
                             // tool_use 在 assistant 消息中处理，这里忽略
                         }
-                        _ => {}
+                        _ => {
+                            if let Some(text) = unsupported_block(item, "user", blocks)? {
+                                text_parts.push(text);
+                            }
+                        }
                     }
+                } else if let Some(text) = unsupported_block(item, "user", blocks)? {
+                    text_parts.push(text);
                 }
             }
         }
@@ -355,6 +645,31 @@ fn process_message_content(
     Ok((text_parts.join("\n"), images, tool_results))
 }
 
+/// 按配置处理转换器不支持的内容块
+///
+/// drop 时记录警告并丢弃，error 时拒绝请求，stringify 时返回内容块的 JSON 文本
+fn unsupported_block(
+    item: &serde_json::Value,
+    role: &str,
+    blocks: &UnsupportedBlocksConfig,
+) -> Result<Option<String>, ConversionError> {
+    let block_type = item
+        .get("type")
+        .and_then(|v| v.as_str())
+        .unwrap_or("unknown");
+    match blocks.policy(block_type) {
+        UnsupportedBlockPolicy::Drop => {
+            tracing::warn!("丢弃 {} 消息中不支持的内容块: {}", role, block_type);
+            Ok(None)
+        }
+        UnsupportedBlockPolicy::Error => Err(ConversionError::UnsupportedContentBlock(
+            role.to_string(),
+            block_type.to_string(),
+        )),
+        UnsupportedBlockPolicy::Stringify => Ok(Some(item.to_string())),
+    }
+}
+
 /// 从 media_type 获取图片格式
 fn get_image_format(media_type: &str) -> Option<String> {
     match media_type {
@@ -366,6 +681,23 @@ fn get_image_format(media_type: &str) -> Option<String> {
     }
 }
 
+/// 提取工具结果中的图片块
+fn extract_tool_result_images(content: &Option<serde_json::Value>) -> Vec<KiroImage> {
+    let Some(serde_json::Value::Array(arr)) = content else {
+        return Vec::new();
+    };
+
+    arr.iter()
+        .filter(|item| item.get("type").and_then(|v| v.as_str()) == Some("image"))
+        .filter_map(|item| serde_json::from_value::<ContentBlock>(item.clone()).ok())
+        .filter_map(|block| block.source)
+        .filter_map(|source| {
+            get_image_format(&source.media_type)
+                .map(|format| KiroImage::from_base64(format, source.data))
+        })
+        .collect()
+}
+
 /// 提取工具结果内容
 fn extract_tool_result_content(content: &Option<serde_json::Value>) -> String {
     match content {
@@ -485,36 +817,63 @@ fn remove_orphaned_tool_uses(
     }
 
     for msg in history.iter_mut() {
-        if let Message::Assistant(assistant_msg) = msg {
-            if let Some(ref mut tool_uses) = assistant_msg.assistant_response_message.tool_uses {
-                let original_len = tool_uses.len();
-                tool_uses.retain(|tu| !orphaned_ids.contains(&tu.tool_use_id));
-
-                // 如果移除后为空，设置为 None
-                if tool_uses.is_empty() {
-                    assistant_msg.assistant_response_message.tool_uses = None;
-                } else if tool_uses.len() != original_len {
-                    tracing::debug!(
-                        "从 assistant 消息中移除了 {} 个孤立的 tool_use",
-                        original_len - tool_uses.len()
-                    );
-                }
+        if let Message::Assistant(assistant_msg) = msg
+            && let Some(ref mut tool_uses) = assistant_msg.assistant_response_message.tool_uses
+        {
+            let original_len = tool_uses.len();
+            tool_uses.retain(|tu| !orphaned_ids.contains(&tu.tool_use_id));
+
+            // 如果移除后为空，设置为 None
+            if tool_uses.is_empty() {
+                assistant_msg.assistant_response_message.tool_uses = None;
+            } else if tool_uses.len() != original_len {
+                tracing::debug!(
+                    "从 assistant 消息中移除了 {} 个孤立的 tool_use",
+                    original_len - tool_uses.len()
+                );
             }
         }
     }
 }
 
 /// 转换工具定义
-fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
+///
+/// Computer use 等客户端工具按 `converter.computerUse` 策略补全 Schema 或拒绝，
+/// input_schema 中的 `$ref` 在此内联，无法规范化时返回指明工具名的错误
+///
+/// 客户端每轮重发的相同工具也照常转换：Kiro API 无法引用此前请求中的工具定义，
+/// 请求大小不会因缓存而减少，而判断工具是否变化的校验和与跳过的规范化开销相当
+fn convert_tools(
+    tools: &Option<Vec<super::types::Tool>>,
+    config: &ConverterConfig,
+) -> Result<Vec<Tool>, ConversionError> {
     let Some(tools) = tools else {
-        return Vec::new();
+        return Ok(Vec::new());
     };
 
+    if config.computer_use == ComputerUsePolicy::Reject
+        && let Some(t) = tools.iter().find(|t| client_tools::classify(t).is_some())
+    {
+        return Err(ConversionError::UnsupportedTool(
+            t.name.clone(),
+            t.tool_type.clone().unwrap_or_default(),
+        ));
+    }
+
     tools
         .iter()
-        .filter(|t| !t.is_web_search() && t.name != "web_search")
+        // 服务端工具（web_search、code_execution 等）由服务端执行，不传给 Kiro
+        .filter(|t| server_tools::classify(t).is_none())
         .map(|t| {
-            let mut description = t.description.clone();
+            let (mut description, input_schema) = match client_tools::classify(t) {
+                Some(kind) => {
+                    tracing::debug!("补全客户端工具定义: {} ({})", t.name, kind.as_str());
+                    client_tools::describe(kind, t)
+                }
+                None => (t.description.clone(), serde_json::json!(t.input_schema)),
+            };
+            let input_schema = schema::resolve_schema(&input_schema, config.tool_schema_max_depth)
+                .map_err(|e| ConversionError::InvalidToolSchema(t.name.clone(), e))?;
 
             // 对 Write/Edit 工具追加自定义描述后缀
             let suffix = match t.name.as_str() {
@@ -533,19 +892,21 @@ fn convert_tools(tools: &Option<Vec<super::types::Tool>>) -> Vec<Tool> {
                 None => description,
             };
 
-            Tool {
+            Ok(Tool {
                 tool_specification: ToolSpecification {
                     name: t.name.clone(),
                     description,
-                    input_schema: InputSchema::from_json(normalize_json_schema(serde_json::json!(t.input_schema))),
+                    input_schema: InputSchema::from_json(normalize_json_schema(input_schema)),
                 },
-            }
+            })
         })
         .collect()
 }
 
 /// 生成thinking标签前缀
-fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
+///
+/// 输出侧按同一字符串剥离模型对它的回显
+pub(super) fn generate_thinking_prefix(req: &MessagesRequest) -> Option<String> {
     if let Some(t) = &req.thinking {
         if t.thinking_type == "enabled" {
             return Some(format!(
@@ -568,129 +929,377 @@ fn has_thinking_tags(content: &str) -> bool {
     content.contains("<thinking_mode>") || content.contains("<max_thinking_length>")
 }
 
-/// 构建历史消息
-///
-/// # Arguments
-/// * `req` - 原始请求，用于读取 `system`、`thinking` 等配置字段
-/// * `messages` - 经过 prefill 预处理的消息切片，末尾必定是 user 消息。
-///   注意：该切片与 `req.messages` 可能不同（prefill 时会截断末尾的 assistant 消息），
-///   调用方应始终使用此参数而非 `req.messages`。
-/// * `model_id` - 已映射的 Kiro 模型 ID
-fn build_history(req: &MessagesRequest, messages: &[super::types::Message], model_id: &str) -> Result<Vec<Message>, ConversionError> {
-    let mut history = Vec::new();
+/// 历史消息数达到该值时并行转换
+const PARALLEL_CONVERSION_THRESHOLD: usize = 64;
 
+/// 构建系统提示词内容（含配置的前后缀、thinking 前缀和分块写入策略）
+///
+/// 配置的 `systemPrepend` / `systemAppend` 在此展开模板变量
+fn build_system_content(
+    req: &MessagesRequest,
+    config: &ConverterConfig,
+    vars: &TemplateVars,
+) -> Option<String> {
     // 生成thinking前缀（如果需要）
     let thinking_prefix = generate_thinking_prefix(req);
 
-    // 1. 处理系统消息
-    if let Some(ref system) = req.system {
-        let system_content: String = system
+    let prepend =
+        (!config.system_prepend.is_empty()).then(|| template::render(&config.system_prepend, vars));
+    let append =
+        (!config.system_append.is_empty()).then(|| template::render(&config.system_append, vars));
+    let directive = language_directive(req, &config.language_directive);
+    let client_system = req.system.as_ref().map(|system| {
+        system
             .iter()
             .map(|s| s.text.clone())
             .collect::<Vec<_>>()
+            .join("\n")
+    });
+
+    if client_system.is_some() || prepend.is_some() || append.is_some() || directive.is_some() {
+        let system_content = [prepend, client_system, append, directive]
+            .into_iter()
+            .flatten()
+            .filter(|part| !part.is_empty())
+            .collect::<Vec<_>>()
             .join("\n");
 
-        if !system_content.is_empty() {
-            // 追加分块写入策略到系统消息
-            let system_content = format!("{}\n{}", system_content, SYSTEM_CHUNKED_POLICY);
-
-            // 注入thinking标签到系统消息最前面（如果需要且不存在）
-            let final_content = if let Some(ref prefix) = thinking_prefix {
-                if !has_thinking_tags(&system_content) {
-                    format!("{}\n{}", prefix, system_content)
-                } else {
-                    system_content
-                }
-            } else {
-                system_content
-            };
+        if system_content.is_empty() {
+            return None;
+        }
 
-            // 系统消息作为 user + assistant 配对
-            let user_msg = HistoryUserMessage::new(final_content, model_id);
-            history.push(Message::User(user_msg));
+        // 追加分块写入策略到系统消息
+        let system_content = format!("{}\n{}", system_content, SYSTEM_CHUNKED_POLICY);
 
-            let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
-            history.push(Message::Assistant(assistant_msg));
+        // 注入thinking标签到系统消息最前面（如果需要且不存在）
+        match thinking_prefix {
+            Some(prefix) if !has_thinking_tags(&system_content) => {
+                Some(format!("{}\n{}", prefix, system_content))
+            }
+            _ => Some(system_content),
         }
-    } else if let Some(ref prefix) = thinking_prefix {
+    } else {
         // 没有系统消息但有thinking配置，插入新的系统消息
-        let user_msg = HistoryUserMessage::new(prefix.clone(), model_id);
-        history.push(Message::User(user_msg));
+        thinking_prefix
+    }
+}
 
-        let assistant_msg = HistoryAssistantMessage::new("I will follow these instructions.");
-        history.push(Message::Assistant(assistant_msg));
+/// 按最新一条 user 消息的语言生成回复语言指令（启用 `converter.languageDirective` 时）
+fn language_directive(req: &MessagesRequest, config: &LanguageDirectiveConfig) -> Option<String> {
+    if !config.enabled {
+        return None;
     }
+    let text = match req.messages.iter().rev().find(|m| m.role == "user") {
+        Some(msg) => match &msg.content {
+            serde_json::Value::String(text) => text.clone(),
+            serde_json::Value::Array(blocks) => blocks
+                .iter()
+                .filter(|b| b.get("type").and_then(|t| t.as_str()) == Some("text"))
+                .filter_map(|b| b.get("text").and_then(|t| t.as_str()))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            _ => String::new(),
+        },
+        None => String::new(),
+    };
 
-    // 2. 处理常规消息历史
-    // 最后一条消息作为 currentMessage，不加入历史
-    // 经过 prefill 预处理后，messages 末尾必定是 user，故直接截掉最后一条即可
-    let history_end_index = messages.len().saturating_sub(1);
-
-    // 收集并配对消息
-    let mut user_buffer: Vec<&super::types::Message> = Vec::new();
-    let mut assistant_buffer: Vec<&super::types::Message> = Vec::new();
-
-    for i in 0..history_end_index {
-        let msg = &messages[i];
-
-        if msg.role == "user" {
-            // 先处理累积的 assistant 消息
-            if !assistant_buffer.is_empty() {
-                let merged = merge_assistant_messages(&assistant_buffer)?;
-                history.push(Message::Assistant(merged));
-                assistant_buffer.clear();
-            }
-            user_buffer.push(msg);
-        } else if msg.role == "assistant" {
-            // 先处理累积的 user 消息
-            if !user_buffer.is_empty() {
-                let merged_user = merge_user_messages(&user_buffer, model_id)?;
-                history.push(Message::User(merged_user));
-                user_buffer.clear();
+    let language = match language::detect(&text, config.min_letters) {
+        Detection::Language(language) => language,
+        Detection::Latin => return None,
+        Detection::Inconclusive => req
+            .accept_language
+            .as_deref()
+            .filter(|_| config.use_accept_language)
+            .and_then(language::from_accept_language)?,
+    };
+    Some(config.template.replace("{{language}}", language.name()))
+}
+
+/// 构建历史消息
+///
+/// # Arguments
+/// * `system_content` - 已构建的系统提示词（见 [`build_system_content`]）
+/// * `messages` - 历史部分的消息切片，不包含作为 currentMessage 的末尾 user 消息。
+///   注意：该切片与 `req.messages` 可能不同（prefill 时会截断末尾的 assistant 消息），
+///   调用方应始终使用此参数而非 `req.messages`。
+/// * `model_id` - 已映射的 Kiro 模型 ID
+/// * `config` - 转换配置（配对策略与占位回复）
+///
+/// # Returns
+/// 元组：(历史消息, 尚未放置的系统提示词)。
+/// 仅 merge 策略且历史为空时返回系统提示词，由调用方并入当前消息。
+///
+/// 每个请求都完整转换历史，不按会话缓存已转换的前缀：确认前缀未变本身就要遍历整个历史，
+/// 而配对、窗口和孤立 tool_use 的处理会就地修改转换结果，复用缓存时仍须复制一份。
+/// 实测缓存反而更慢（300 轮工具调用密集的对话，release 构建下 4.9s 对完整转换 1.4s）
+fn build_history(
+    system_content: Option<String>,
+    messages: &[&super::types::Message],
+    model_id: &str,
+    config: &ConverterConfig,
+) -> Result<(Vec<Message>, Option<String>), ConversionError> {
+    let mut history = Vec::new();
+
+    // 1. 处理系统消息
+    let mut pending_system = None;
+    if let Some(system_content) = system_content {
+        match config.history_pairing {
+            HistoryPairingStrategy::Placeholder => {
+                // 系统消息作为 user + assistant 配对
+                let user_msg = HistoryUserMessage::new(system_content, model_id);
+                history.push(Message::User(user_msg));
+
+                let assistant_msg = HistoryAssistantMessage::new(&config.system_ack_text);
+                history.push(Message::Assistant(assistant_msg));
             }
-            // 累积 assistant 消息（支持连续多条）
-            assistant_buffer.push(msg);
+            HistoryPairingStrategy::Merge => pending_system = Some(system_content),
+        }
+    }
+
+    // 2. 处理常规消息历史
+    // 先按角色切分为连续的 user / assistant 分组（支持连续多条），再逐组转换
+    let mut groups: Vec<Vec<&super::types::Message>> = Vec::new();
+    for &msg in messages {
+        if msg.role != "user" && msg.role != "assistant" {
+            continue;
+        }
+        match groups.last_mut() {
+            Some(group) if group[0].role == msg.role => group.push(msg),
+            _ => groups.push(vec![msg]),
         }
     }
 
-    // 处理末尾累积的 assistant 消息
-    if !assistant_buffer.is_empty() {
-        let merged = merge_assistant_messages(&assistant_buffer)?;
-        history.push(Message::Assistant(merged));
+    let parallel = messages.len() >= PARALLEL_CONVERSION_THRESHOLD;
+    let mut converted = convert_history_groups(
+        &groups,
+        model_id,
+        config.user_turn_join,
+        &config.empty_content_placeholder,
+        &config.unsupported_blocks,
+        parallel,
+    )?;
+    let dropped = apply_history_window(
+        &mut converted,
+        config.max_history_turns,
+        &config.empty_content_placeholder,
+    );
+    if dropped > 0 {
+        tracing::debug!(
+            "历史窗口: 保留最近 {} 轮，丢弃 {} 条历史消息",
+            config.max_history_turns,
+            dropped
+        );
     }
 
-    // 处理结尾的孤立 user 消息
-    if !user_buffer.is_empty() {
-        let merged_user = merge_user_messages(&user_buffer, model_id)?;
-        history.push(Message::User(merged_user));
+    // 历史以 assistant 开头（客户端裁剪了开头的 user 消息，或其规范化后为空）时补齐配对
+    if matches!(converted.first(), Some(Message::Assistant(_))) {
+        match history.last() {
+            // placeholder 策略：由这条 assistant 消息代替系统提示词的占位回复
+            Some(Message::Assistant(_)) => {
+                history.pop();
+            }
+            // merge 策略：系统提示词在第 3 步作为独立 user 消息插入
+            _ if pending_system.is_some() => {}
+            // 没有系统提示词：补一条占位 user 消息
+            _ => history.push(Message::User(HistoryUserMessage::new(
+                config.empty_content_placeholder.clone(),
+                model_id,
+            ))),
+        }
+    }
+    history.extend(converted);
 
-        // 自动配对一个 "OK" 的 assistant 响应
-        let auto_assistant = HistoryAssistantMessage::new("OK");
+    // 处理结尾的孤立 user 消息（merge 策略下调用方已将其并入当前消息）
+    if groups.last().is_some_and(|group| group[0].role == "user") {
+        // 自动配对一个占位 assistant 响应
+        let auto_assistant = HistoryAssistantMessage::new(&config.user_ack_text);
         history.push(Message::Assistant(auto_assistant));
     }
 
-    Ok(history)
+    // 3. merge 策略：系统提示词并入历史中的第一条 user 消息
+    let pending_system = match (pending_system, history.first_mut()) {
+        (Some(system_content), Some(Message::User(first_user))) => {
+            let content = &mut first_user.user_input_message.content;
+            *content = if content.is_empty() {
+                system_content
+            } else {
+                format!("{}\n{}", system_content, content)
+            };
+            None
+        }
+        (Some(system_content), Some(Message::Assistant(_))) => {
+            // 历史以 assistant 开头，系统提示词作为独立 user 消息即可完成配对
+            history.insert(
+                0,
+                Message::User(HistoryUserMessage::new(system_content, model_id)),
+            );
+            None
+        }
+        (pending, _) => pending,
+    };
+
+    Ok((history, pending_system))
+}
+
+/// 按 `converter.maxHistoryTurns` 只保留最近的 N 轮对话（一条 user 消息及其回复为一轮）
+///
+/// 窗口总是从 user 消息开始；窗口内第一条 user 消息中引用已丢弃 tool_use 的 tool_result
+/// 一并移除，保证工具配对完整。`max_turns` 为 0 时不限制
+///
+/// # Returns
+/// 被丢弃的消息数量
+fn apply_history_window(messages: &mut Vec<Message>, max_turns: usize, placeholder: &str) -> usize {
+    if max_turns == 0 {
+        return 0;
+    }
+    let user_indices: Vec<usize> = messages
+        .iter()
+        .enumerate()
+        .filter(|(_, m)| matches!(m, Message::User(_)))
+        .map(|(i, _)| i)
+        .collect();
+    if user_indices.len() <= max_turns {
+        return 0;
+    }
+
+    let start = user_indices[user_indices.len() - max_turns];
+    messages.drain(..start);
+
+    let kept_tool_uses: std::collections::HashSet<&str> = messages
+        .iter()
+        .filter_map(|m| match m {
+            Message::Assistant(a) => a.assistant_response_message.tool_uses.as_ref(),
+            Message::User(_) => None,
+        })
+        .flatten()
+        .map(|t| t.tool_use_id.as_str())
+        .collect();
+    let dangling: std::collections::HashSet<String> = match messages.first() {
+        Some(Message::User(user)) => user
+            .user_input_message
+            .user_input_message_context
+            .tool_results
+            .iter()
+            .filter(|r| !kept_tool_uses.contains(r.tool_use_id.as_str()))
+            .map(|r| r.tool_use_id.clone())
+            .collect(),
+        _ => Default::default(),
+    };
+
+    if !dangling.is_empty()
+        && let Some(Message::User(first)) = messages.first_mut()
+    {
+        let msg = &mut first.user_input_message;
+        msg.user_input_message_context
+            .tool_results
+            .retain(|r| !dangling.contains(&r.tool_use_id));
+        if msg.content.trim().is_empty()
+            && msg.images.is_empty()
+            && msg.user_input_message_context.tool_results.is_empty()
+        {
+            msg.content = placeholder.to_string();
+        }
+    }
+    start
 }
 
-/// 合并多个 user 消息
-fn merge_user_messages(
-    messages: &[&super::types::Message],
+/// 将按角色分组的消息转换为历史消息
+///
+/// 并行模式下各分组在 rayon 线程池中独立转换，collect 保持原有顺序；
+/// 短历史串行转换以避免线程池调度开销
+fn convert_history_groups(
+    groups: &[Vec<&super::types::Message>],
     model_id: &str,
-) -> Result<HistoryUserMessage, ConversionError> {
+    join: UserTurnJoin,
+    placeholder: &str,
+    blocks: &UnsupportedBlocksConfig,
+    parallel: bool,
+) -> Result<Vec<Message>, ConversionError> {
+    let convert_group = |group: &Vec<&super::types::Message>| {
+        if group[0].role == "user" {
+            merge_user_messages(group, model_id, join, placeholder, blocks).map(Message::User)
+        } else {
+            merge_assistant_messages(group, blocks).map(Message::Assistant)
+        }
+    };
+
+    if parallel {
+        groups.par_iter().map(convert_group).collect()
+    } else {
+        groups.iter().map(convert_group).collect()
+    }
+}
+
+/// 汇总多条 user 消息的文本、图片和工具结果
+fn collect_user_content(
+    messages: &[&super::types::Message],
+    join: UserTurnJoin,
+    blocks: &UnsupportedBlocksConfig,
+) -> Result<(String, Vec<KiroImage>, Vec<ToolResult>), ConversionError> {
     let mut content_parts = Vec::new();
     let mut all_images = Vec::new();
     let mut all_tool_results = Vec::new();
 
-    for msg in messages {
-        let (text, images, tool_results) = process_message_content(&msg.content)?;
+    for (index, msg) in messages.iter().enumerate() {
+        let (text, images, tool_results) = process_message_content(&msg.content, blocks)?;
         if !text.is_empty() {
-            content_parts.push(text);
+            content_parts.push((index, text));
         }
         all_images.extend(images);
         all_tool_results.extend(tool_results);
     }
 
-    let content = content_parts.join("\n");
+    Ok((
+        join_user_turns(&content_parts, join),
+        all_images,
+        all_tool_results,
+    ))
+}
+
+/// 按配置格式拼接多段 user 文本，保留轮次边界
+///
+/// `parts` 为 (消息在合并组中的下标, 文本)，标记按原始消息位置编号，
+/// 不含文本的消息（如仅有 tool_result）不会使后续编号错位。
+/// 仅有一段文本时原样返回，不添加任何标记
+fn join_user_turns(parts: &[(usize, String)], join: UserTurnJoin) -> String {
+    if let [(_, text)] = parts {
+        return text.clone();
+    }
+
+    match join {
+        UserTurnJoin::Newline => parts
+            .iter()
+            .map(|(_, text)| text.as_str())
+            .collect::<Vec<_>>()
+            .join("\n"),
+        UserTurnJoin::Marker => parts
+            .iter()
+            .map(|(index, text)| format!("[user message {}]\n{}", index + 1, text))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+        UserTurnJoin::Transcript => parts
+            .iter()
+            .map(|(_, text)| format!("User: {}", text))
+            .collect::<Vec<_>>()
+            .join("\n\n"),
+    }
+}
+
+/// 合并多个 user 消息
+///
+/// 与当前消息的规则一致：没有文本也没有图片（如仅包含 tool_result）时使用占位文本
+fn merge_user_messages(
+    messages: &[&super::types::Message],
+    model_id: &str,
+    join: UserTurnJoin,
+    placeholder: &str,
+    blocks: &UnsupportedBlocksConfig,
+) -> Result<HistoryUserMessage, ConversionError> {
+    let (mut content, all_images, all_tool_results) = collect_user_content(messages, join, blocks)?;
+    if content.trim().is_empty() && all_images.is_empty() {
+        content = placeholder.to_string();
+    }
+
     // 保留文本内容，即使有工具结果也不丢弃用户文本
     let mut user_msg = UserMessage::new(&content, model_id);
 
@@ -712,6 +1321,7 @@ fn merge_user_messages(
 /// 转换 assistant 消息
 fn convert_assistant_message(
     msg: &super::types::Message,
+    blocks: &UnsupportedBlocksConfig,
 ) -> Result<HistoryAssistantMessage, ConversionError> {
     let mut thinking_content = String::new();
     let mut text_content = String::new();
@@ -745,33 +1355,39 @@ fn convert_assistant_message(
                         "server_tool_use" => {}
                         "web_search_tool_result" => {
                             // 将搜索结果提取为文本，保留在对话历史中
-                            if let Some(content) = item.get("content") {
-                                if let Some(arr) = content.as_array() {
-                                    for result in arr {
-                                        if result.get("type").and_then(|t| t.as_str())
-                                            == Some("web_search_result")
-                                        {
-                                            let title = result
-                                                .get("title")
-                                                .and_then(|t| t.as_str())
-                                                .unwrap_or("");
-                                            let url = result
-                                                .get("url")
-                                                .and_then(|u| u.as_str())
-                                                .unwrap_or("");
-                                            if !title.is_empty() || !url.is_empty() {
-                                                text_content.push_str(&format!(
-                                                    "[{}]({})\n",
-                                                    title, url
-                                                ));
-                                            }
+                            if let Some(content) = item.get("content")
+                                && let Some(arr) = content.as_array()
+                            {
+                                for result in arr {
+                                    if result.get("type").and_then(|t| t.as_str())
+                                        == Some("web_search_result")
+                                    {
+                                        let title = result
+                                            .get("title")
+                                            .and_then(|t| t.as_str())
+                                            .unwrap_or("");
+                                        let url = result
+                                            .get("url")
+                                            .and_then(|u| u.as_str())
+                                            .unwrap_or("");
+                                        if !title.is_empty() || !url.is_empty() {
+                                            text_content.push_str(&format!(
+                                                "[{}]({})\n",
+                                                title, url
+                                            ));
                                         }
                                     }
                                 }
                             }
                         }
-                        _ => {}
+                        _ => {
+                            if let Some(text) = unsupported_block(item, "assistant", blocks)? {
+                                text_content.push_str(&text);
+                            }
+                        }
                     }
+                } else if let Some(text) = unsupported_block(item, "assistant", blocks)? {
+                    text_content.push_str(&text);
                 }
             }
         }
@@ -780,7 +1396,7 @@ fn convert_assistant_message(
 
     // 组合 thinking 和 text 内容
     // 格式: <thinking>思考内容</thinking>\n\ntext内容
-    // 注意: Kiro API 要求 content 字段不能为空，当只有 tool_use 时需要占位符
+    // 注意: Kiro API 要求 content 字段不能为空，没有文本（如只有 tool_use）时需要占位符
     let final_content = if !thinking_content.is_empty() {
         if !text_content.is_empty() {
             format!(
@@ -790,8 +1406,8 @@ fn convert_assistant_message(
         } else {
             format!("<thinking>{}</thinking>", thinking_content)
         }
-    } else if text_content.is_empty() && !tool_uses.is_empty() {
-        " ".to_string()
+    } else if text_content.trim().is_empty() {
+        ASSISTANT_PLACEHOLDER.to_string()
     } else {
         text_content
     };
@@ -810,17 +1426,18 @@ fn convert_assistant_message(
 /// 用于处理网络不稳定时产生的连续 assistant 消息（Issue #79）
 fn merge_assistant_messages(
     messages: &[&super::types::Message],
+    blocks: &UnsupportedBlocksConfig,
 ) -> Result<HistoryAssistantMessage, ConversionError> {
     assert!(!messages.is_empty());
     if messages.len() == 1 {
-        return convert_assistant_message(messages[0]);
+        return convert_assistant_message(messages[0], blocks);
     }
 
     let mut all_tool_uses: Vec<ToolUseEntry> = Vec::new();
     let mut content_parts: Vec<String> = Vec::new();
 
     for msg in messages {
-        let converted = convert_assistant_message(msg)?;
+        let converted = convert_assistant_message(msg, blocks)?;
         let am = converted.assistant_response_message;
         if !am.content.trim().is_empty() {
             content_parts.push(am.content);
@@ -830,8 +1447,8 @@ fn merge_assistant_messages(
         }
     }
 
-    let content = if content_parts.is_empty() && !all_tool_uses.is_empty() {
-        " ".to_string()
+    let content = if content_parts.is_empty() {
+        ASSISTANT_PLACEHOLDER.to_string()
     } else {
         content_parts.join("\n\n")
     };
@@ -845,7 +1462,7 @@ fn merge_assistant_messages(
     })
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(edge)))]
 mod tests {
     use super::*;
 
@@ -881,6 +1498,27 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_detect_model_downgrade() {
+        // 请求的版本高于映射结果
+        assert_eq!(
+            detect_model_downgrade("claude-opus-4-7"),
+            Some(ModelDowngrade {
+                requested: "claude-opus-4-7".to_string(),
+                served: "claude-opus-4.6".to_string(),
+                reason: None,
+            })
+        );
+        assert!(detect_model_downgrade("claude-sonnet-4-7-20260101").is_some());
+
+        // 版本一致或升级
+        assert!(detect_model_downgrade("claude-opus-4-6").is_none());
+        assert!(detect_model_downgrade("claude-sonnet-4-20250514").is_none());
+        assert!(detect_model_downgrade("claude-3-5-sonnet-20241022").is_none());
+        assert!(detect_model_downgrade("claude-haiku-4-5-20251001").is_none());
+        assert!(detect_model_downgrade("gpt-4").is_none());
+    }
+
     #[test]
     fn test_map_model_unsupported() {
         assert!(map_model("gpt-4").is_none());
@@ -928,6 +1566,12 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
         assert_eq!(determine_chat_trigger_type(&req), "MANUAL");
     }
@@ -1007,9 +1651,15 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
 
         // 验证 tools 列表中包含了历史中使用的工具的占位符定义
         let tools = &result
@@ -1076,27 +1726,43 @@ mod tests {
                     "user_0dede55c6dcc4a11a30bbb5e7f22e6fdf86cdeba3820019cc27612af4e1243cd_account__session_a0662283-7fd3-4399-a7eb-52b9a717ae88".to_string(),
                 ),
             }),
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
-        let result = convert_request(&req).unwrap();
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
         assert_eq!(
             result.conversation_state.conversation_id,
             "a0662283-7fd3-4399-a7eb-52b9a717ae88"
         );
     }
 
-    #[test]
-    fn test_convert_request_without_metadata() {
+    fn tool_result_turn_request(last_user_content: serde_json::Value) -> MessagesRequest {
         use super::super::types::Message as AnthropicMessage;
 
-        // 测试没有 metadata 的请求，应该生成新的 UUID
-        let req = MessagesRequest {
+        MessagesRequest {
             model: "claude-sonnet-4".to_string(),
             max_tokens: 1024,
-            messages: vec![AnthropicMessage {
-                role: "user".to_string(),
-                content: serde_json::json!("Hello"),
-            }],
+            messages: vec![
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("Read a.rs"),
+                },
+                AnthropicMessage {
+                    role: "assistant".to_string(),
+                    content: serde_json::json!([
+                        {"type": "tool_use", "id": "toolu_1", "name": "Read", "input": {"path": "a.rs"}}
+                    ]),
+                },
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: last_user_content,
+                },
+            ],
             stream: false,
             system: None,
             tools: None,
@@ -1104,13 +1770,235 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
+        }
+    }
+
+    #[test]
+    fn test_system_prepend_append_expand_template_vars() {
+        let req = tool_result_turn_request(serde_json::json!("Continue"));
+        let config = ConverterConfig {
+            system_prepend: "Today is {{date}}.".to_string(),
+            system_append: "Conversation {{conversation_id}} on {{kiro_model}}".to_string(),
+            ..ConverterConfig::default()
         };
 
-        let result = convert_request(&req).unwrap();
-        // 验证生成的是有效的 UUID 格式
-        assert_eq!(result.conversation_state.conversation_id.len(), 36);
-        assert_eq!(
-            result
+        let result = convert_request(&req, &config).unwrap();
+        let state = &result.conversation_state;
+        let Message::User(system) = &state.history[0] else {
+            panic!("expected system message first");
+        };
+        let content = &system.user_input_message.content;
+        let today = chrono::Utc::now().format("%Y-%m-%d").to_string();
+        assert!(content.starts_with(&format!("Today is {}.\n", today)));
+        assert!(content.contains(&format!(
+            "Conversation {} on {}",
+            state.conversation_id,
+            map_model(&req.model).unwrap()
+        )));
+        assert!(content.contains(SYSTEM_CHUNKED_POLICY));
+    }
+
+    #[test]
+    fn test_language_directive_from_latest_user_message() {
+        let request = |text: &str, accept_language: Option<&str>| -> MessagesRequest {
+            let mut req: MessagesRequest = serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [
+                    {"role": "user", "content": "Explain this code please"},
+                    {"role": "assistant", "content": "Sure."},
+                    {"role": "user", "content": [{"type": "text", "text": text}]}
+                ]
+            }))
+            .unwrap();
+            req.accept_language = accept_language.map(str::to_string);
+            req
+        };
+        let config = LanguageDirectiveConfig {
+            enabled: true,
+            ..Default::default()
+        };
+
+        let directive = language_directive(&request("这个函数为什么会报错？", None), &config);
+        assert!(directive.unwrap().starts_with("Respond in Chinese"));
+        assert_eq!(
+            language_directive(&request("Why does this fail?", Some("zh-CN")), &config),
+            None
+        );
+        // 文字过少时按 Accept-Language 推断
+        let directive = language_directive(&request("ok", Some("ja,en;q=0.5")), &config);
+        assert!(directive.unwrap().starts_with("Respond in Japanese"));
+        let config = LanguageDirectiveConfig {
+            use_accept_language: false,
+            ..config
+        };
+        assert_eq!(
+            language_directive(&request("ok", Some("ja")), &config),
+            None
+        );
+
+        // 默认关闭
+        assert_eq!(
+            language_directive(
+                &request("这个函数为什么会报错？", None),
+                &LanguageDirectiveConfig::default()
+            ),
+            None
+        );
+    }
+
+    #[test]
+    fn test_tool_result_only_turn_uses_placeholder() {
+        let req = tool_result_turn_request(serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"}
+        ]));
+
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
+        let current = &result.conversation_state.current_message.user_input_message;
+        assert_eq!(current.content, "Continue.");
+        assert_eq!(current.user_input_message_context.tool_results.len(), 1);
+
+        // 占位文本为空时保持原有的空内容
+        let config = ConverterConfig {
+            empty_content_placeholder: String::new(),
+            ..Default::default()
+        };
+        let result = convert_request(&req, &config).unwrap();
+        assert_eq!(
+            result
+                .conversation_state
+                .current_message
+                .user_input_message
+                .content,
+            ""
+        );
+    }
+
+    #[test]
+    fn test_empty_content_normalized_uniformly() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let mut req = tool_result_turn_request(serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"},
+            {"type": "text", "text": "  "}
+        ]));
+        for (role, content) in [
+            ("assistant", serde_json::json!("Done.")),
+            ("user", serde_json::json!([])),
+            (
+                "assistant",
+                serde_json::json!([{"type": "text", "text": ""}]),
+            ),
+            ("user", serde_json::json!("Thanks")),
+        ] {
+            req.messages.push(AnthropicMessage {
+                role: role.to_string(),
+                content,
+            });
+        }
+
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
+        let contents: Vec<_> = result
+            .conversation_state
+            .history
+            .iter()
+            .map(|msg| match msg {
+                Message::User(user) => user.user_input_message.content.as_str(),
+                Message::Assistant(assistant) => {
+                    assistant.assistant_response_message.content.as_str()
+                }
+            })
+            .collect();
+
+        // 空的 user/assistant 消息被丢弃，仅有 tool_use / tool_result 的消息使用占位内容
+        assert_eq!(contents, vec!["Read a.rs", " ", "Continue.", "Done."]);
+        assert_eq!(
+            result
+                .conversation_state
+                .current_message
+                .user_input_message
+                .content,
+            "Thanks"
+        );
+    }
+
+    #[test]
+    fn test_tool_result_with_text_keeps_text() {
+        let req = tool_result_turn_request(serde_json::json!([
+            {"type": "tool_result", "tool_use_id": "toolu_1", "content": "fn main() {}"},
+            {"type": "text", "text": "Now explain it"}
+        ]));
+
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
+        assert_eq!(
+            result
+                .conversation_state
+                .current_message
+                .user_input_message
+                .content,
+            "Now explain it"
+        );
+    }
+
+    #[test]
+    fn test_thinking_rejected_for_unsupported_model() {
+        use super::super::types::Thinking;
+
+        let mut req = tool_result_turn_request(serde_json::json!("Hi"));
+        req.thinking = Some(Thinking {
+            thinking_type: "enabled".to_string(),
+            budget_tokens: 1024,
+        });
+        assert!(convert_request(&req, &ConverterConfig::default()).is_ok());
+
+        req.model = "claude-haiku-4-5-20251001".to_string();
+        assert!(matches!(
+            convert_request(&req, &ConverterConfig::default()),
+            Err(ConversionError::ThinkingUnsupported(model)) if model == "claude-haiku-4-5-20251001"
+        ));
+
+        req.thinking = None;
+        assert!(convert_request(&req, &ConverterConfig::default()).is_ok());
+    }
+
+    #[test]
+    fn test_convert_request_without_metadata() {
+        use super::super::types::Message as AnthropicMessage;
+
+        // 测试没有 metadata 的请求，应该生成新的 UUID
+        let req = MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("Hello"),
+            }],
+            stream: false,
+            system: None,
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
+        };
+
+        let result = convert_request(&req, &ConverterConfig::default()).unwrap();
+        // 验证生成的是有效的 UUID 格式
+        assert_eq!(result.conversation_state.conversation_id.len(), 36);
+        assert_eq!(
+            result
                 .conversation_state
                 .conversation_id
                 .chars()
@@ -1333,7 +2221,8 @@ mod tests {
             ]),
         };
 
-        let result = convert_assistant_message(&msg).expect("应该成功转换");
+        let result = convert_assistant_message(&msg, &UnsupportedBlocksConfig::default())
+            .expect("应该成功转换");
 
         // 验证 content 不为空（使用占位符）
         assert!(
@@ -1368,7 +2257,8 @@ mod tests {
             ]),
         };
 
-        let result = convert_assistant_message(&msg).expect("应该成功转换");
+        let result = convert_assistant_message(&msg, &UnsupportedBlocksConfig::default())
+            .expect("应该成功转换");
 
         // 验证 content 使用原始文本（不是占位符）
         assert_eq!(
@@ -1481,7 +2371,8 @@ mod tests {
         };
 
         let messages: Vec<&AnthropicMessage> = vec![&msg1, &msg2];
-        let result = merge_assistant_messages(&messages).expect("合并应成功");
+        let result = merge_assistant_messages(&messages, &UnsupportedBlocksConfig::default())
+            .expect("合并应成功");
 
         let content = &result.assistant_response_message.content;
         assert!(content.contains("<thinking>"), "应包含 thinking 标签");
@@ -1534,9 +2425,15 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
-        let result = convert_request(&req);
+        let result = convert_request(&req, &ConverterConfig::default());
         assert!(result.is_ok(), "连续 assistant 消息场景不应报错: {:?}", result.err());
 
         let state = result.unwrap().conversation_state;
@@ -1553,4 +2450,873 @@ mod tests {
         }
         assert!(found_tool_use, "合并后的 assistant 消息应包含 tool_use");
     }
+
+    fn pairing_test_request() -> MessagesRequest {
+        use super::super::types::{Message as AnthropicMessage, SystemMessage};
+
+        MessagesRequest {
+            model: "claude-sonnet-4".to_string(),
+            max_tokens: 1024,
+            messages: vec![
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("first"),
+                },
+                AnthropicMessage {
+                    role: "user".to_string(),
+                    content: serde_json::json!("second"),
+                },
+            ],
+            stream: false,
+            system: Some(vec![SystemMessage {
+                text: "You are helpful".to_string(),
+            }]),
+            tools: None,
+            tool_choice: None,
+            thinking: None,
+            output_config: None,
+            metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
+        }
+    }
+
+    #[test]
+    fn test_placeholder_pairing_uses_configured_ack_text() {
+        let config = ConverterConfig {
+            system_ack_text: "Understood.".to_string(),
+            user_ack_text: "Continue.".to_string(),
+            ..Default::default()
+        };
+
+        let result = convert_request(&pairing_test_request(), &config).unwrap();
+        let history = &result.conversation_state.history;
+
+        // system(user) + ack, "first"(user) + ack
+        assert_eq!(history.len(), 4);
+        match &history[1] {
+            Message::Assistant(msg) => {
+                assert_eq!(msg.assistant_response_message.content, "Understood.")
+            }
+            _ => panic!("应为 assistant 占位回复"),
+        }
+        match &history[3] {
+            Message::Assistant(msg) => {
+                assert_eq!(msg.assistant_response_message.content, "Continue.")
+            }
+            _ => panic!("应为 assistant 占位回复"),
+        }
+    }
+
+    #[test]
+    fn test_merge_pairing_without_history() {
+        let config = ConverterConfig {
+            history_pairing: HistoryPairingStrategy::Merge,
+            ..Default::default()
+        };
+
+        let result = convert_request(&pairing_test_request(), &config).unwrap();
+        let state = &result.conversation_state;
+
+        // 不产生任何伪造的 assistant 回复，全部内容并入当前消息
+        assert!(state.history.is_empty());
+        let content = &state.current_message.user_input_message.content;
+        assert!(content.starts_with("You are helpful"));
+        assert!(content.ends_with("first\nsecond"));
+    }
+
+    #[test]
+    fn test_merge_pairing_with_history() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let config = ConverterConfig {
+            history_pairing: HistoryPairingStrategy::Merge,
+            ..Default::default()
+        };
+        let mut req = pairing_test_request();
+        req.messages.insert(
+            1,
+            AnthropicMessage {
+                role: "assistant".to_string(),
+                content: serde_json::json!("reply"),
+            },
+        );
+
+        let result = convert_request(&req, &config).unwrap();
+        let state = &result.conversation_state;
+
+        // 系统提示词并入第一条 user 消息，之后是真实的 assistant 回复
+        assert_eq!(state.history.len(), 2);
+        match &state.history[0] {
+            Message::User(msg) => {
+                assert!(
+                    msg.user_input_message
+                        .content
+                        .starts_with("You are helpful")
+                );
+                assert!(msg.user_input_message.content.ends_with("first"));
+            }
+            _ => panic!("第一条历史消息应为 user"),
+        }
+        match &state.history[1] {
+            Message::Assistant(msg) => assert_eq!(msg.assistant_response_message.content, "reply"),
+            _ => panic!("第二条历史消息应为 assistant"),
+        }
+        assert_eq!(state.current_message.user_input_message.content, "second");
+    }
+
+    #[test]
+    fn test_assistant_first_history_pairing() {
+        let request = |system: bool| -> MessagesRequest {
+            let mut req = serde_json::json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [
+                    {"role": "assistant", "content": "earlier answer"},
+                    {"role": "assistant", "content": "more"},
+                    {"role": "user", "content": "q2"},
+                    {"role": "assistant", "content": "a2"},
+                    {"role": "user", "content": "q3"}
+                ]
+            });
+            if system {
+                req["system"] = serde_json::json!("You are helpful");
+            }
+            serde_json::from_value(req).unwrap()
+        };
+        let roles = |history: &[Message]| -> Vec<(&'static str, String)> {
+            history
+                .iter()
+                .map(|m| match m {
+                    Message::User(u) => ("user", u.user_input_message.content.clone()),
+                    Message::Assistant(a) => {
+                        ("assistant", a.assistant_response_message.content.clone())
+                    }
+                })
+                .collect()
+        };
+        let owned = |pairs: &[(&'static str, &str)]| -> Vec<(&'static str, String)> {
+            pairs.iter().map(|(r, c)| (*r, c.to_string())).collect()
+        };
+
+        for pairing in [
+            HistoryPairingStrategy::Placeholder,
+            HistoryPairingStrategy::Merge,
+        ] {
+            let config = ConverterConfig {
+                history_pairing: pairing,
+                ..Default::default()
+            };
+
+            // 没有系统提示词：补一条占位 user 消息，连续的 assistant 消息合并
+            let result = convert_request(&request(false), &config).unwrap();
+            assert_eq!(
+                roles(&result.conversation_state.history),
+                owned(&[
+                    ("user", "Continue."),
+                    ("assistant", "earlier answer\n\nmore"),
+                    ("user", "q2"),
+                    ("assistant", "a2"),
+                ]),
+                "{:?}",
+                pairing
+            );
+
+            // 有系统提示词：由第一条 assistant 消息与系统提示词配对，不再插入占位回复
+            let result = convert_request(&request(true), &config).unwrap();
+            let history = roles(&result.conversation_state.history);
+            assert_eq!(history.len(), 4, "{:?}", pairing);
+            assert_eq!(history[0].0, "user");
+            assert!(history[0].1.contains("You are helpful"));
+            assert_eq!(
+                history[1],
+                ("assistant", "earlier answer\n\nmore".to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn test_tool_choice_conversion() {
+        let request = |tool_choice: serde_json::Value| -> MessagesRequest {
+            serde_json::from_value(serde_json::json!({
+                "model": "claude-sonnet-4",
+                "max_tokens": 1024,
+                "messages": [
+                    {"role": "user", "content": "q1"},
+                    {"role": "assistant", "content": [
+                        {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                    ]},
+                    {"role": "user", "content": [
+                        {"type": "tool_result", "tool_use_id": "t1", "content": "r1"},
+                        {"type": "text", "text": "What's the weather?"}
+                    ]}
+                ],
+                "tools": [{
+                    "name": "get_weather",
+                    "description": "Get the weather",
+                    "input_schema": {"type": "object", "properties": {}}
+                }],
+                "tool_choice": tool_choice
+            }))
+            .unwrap()
+        };
+        let convert = |tool_choice: serde_json::Value| {
+            convert_request(&request(tool_choice), &ConverterConfig::default())
+                .map(|result| result.conversation_state.current_message.user_input_message)
+        };
+        let tool_names = |message: &UserInputMessage| -> Vec<String> {
+            message
+                .user_input_message_context
+                .tools
+                .iter()
+                .map(|t| t.tool_specification.name.clone())
+                .collect()
+        };
+
+        let auto = convert(serde_json::json!({"type": "auto"})).unwrap();
+        assert_eq!(auto.content, "What's the weather?");
+        assert_eq!(tool_names(&auto), ["get_weather", "read"]);
+
+        let forced = convert(serde_json::json!({"type": "tool", "name": "get_weather"})).unwrap();
+        assert!(forced.content.starts_with("What's the weather?\n\n"));
+        assert!(forced.content.ends_with("calling the `get_weather` tool."));
+
+        // none 不发送请求中的工具，历史引用的工具仍保留占位定义
+        let none = convert(serde_json::json!({"type": "none"})).unwrap();
+        assert_eq!(tool_names(&none), ["read"]);
+        assert!(none.content.contains("Do not call any tools"));
+
+        let err = convert(serde_json::json!({"type": "tool", "name": "get_time"})).unwrap_err();
+        assert_eq!(err.code(), "invalid_tool_choice");
+        assert!(err.to_string().contains("get_time"));
+    }
+
+    #[test]
+    fn test_max_history_turns_window() {
+        let req: MessagesRequest = serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4",
+            "max_tokens": 1024,
+            "messages": [
+                {"role": "user", "content": "q1"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t1", "content": "r1"},
+                    {"type": "text", "text": "q2"}
+                ]},
+                {"role": "assistant", "content": "a2"},
+                {"role": "user", "content": "q3"},
+                {"role": "assistant", "content": [
+                    {"type": "tool_use", "id": "t3", "name": "read", "input": {}}
+                ]},
+                {"role": "user", "content": [
+                    {"type": "tool_result", "tool_use_id": "t3", "content": "r3"}
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let unlimited = convert_request(&req, &ConverterConfig::default()).unwrap();
+        assert_eq!(unlimited.conversation_state.history.len(), 6);
+
+        let config = ConverterConfig {
+            max_history_turns: 2,
+            ..Default::default()
+        };
+        let state = convert_request(&req, &config)
+            .unwrap()
+            .conversation_state;
+        assert_eq!(state.history.len(), 4);
+        match &state.history[0] {
+            Message::User(msg) => {
+                // 引用已丢弃 tool_use 的 tool_result 被移除，文本保留
+                assert_eq!(msg.user_input_message.content, "q2");
+                assert!(
+                    msg.user_input_message
+                        .user_input_message_context
+                        .tool_results
+                        .is_empty()
+                );
+            }
+            _ => panic!("窗口应从 user 消息开始"),
+        }
+        // 当前消息的 tool_result 仍与窗口内的 tool_use 配对
+        let current = &state.current_message.user_input_message;
+        assert_eq!(current.user_input_message_context.tool_results.len(), 1);
+    }
+
+    #[test]
+    fn test_join_user_turns_formats() {
+        let parts = vec![(0, "first".to_string()), (1, "second".to_string())];
+
+        assert_eq!(
+            join_user_turns(&parts, UserTurnJoin::Newline),
+            "first\nsecond"
+        );
+        assert_eq!(
+            join_user_turns(&parts, UserTurnJoin::Marker),
+            "[user message 1]\nfirst\n\n[user message 2]\nsecond"
+        );
+        assert_eq!(
+            join_user_turns(&parts, UserTurnJoin::Transcript),
+            "User: first\n\nUser: second"
+        );
+        // 单段文本不加标记
+        assert_eq!(join_user_turns(&parts[..1], UserTurnJoin::Marker), "first");
+        assert_eq!(join_user_turns(&[], UserTurnJoin::Marker), "");
+    }
+
+    #[test]
+    fn test_turn_markers_follow_message_position() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let messages = [
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("first"),
+            },
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!([
+                    {"type": "tool_result", "tool_use_id": "tool-1", "content": "ok"}
+                ]),
+            },
+            AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!("third"),
+            },
+        ];
+        let group: Vec<&AnthropicMessage> = messages.iter().collect();
+
+        // 仅含 tool_result 的第 2 条消息不占用文本，但第 3 条仍标记为 3
+        let (text, _, tool_results) = collect_user_content(
+            &group,
+            UserTurnJoin::Marker,
+            &UnsupportedBlocksConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(text, "[user message 1]\nfirst\n\n[user message 3]\nthird");
+        assert_eq!(tool_results.len(), 1);
+    }
+
+    #[test]
+    fn test_placeholder_pairing_with_turn_markers() {
+        let config = ConverterConfig {
+            user_turn_join: UserTurnJoin::Marker,
+            ..Default::default()
+        };
+        let mut req = pairing_test_request();
+        req.messages.push(super::super::types::Message {
+            role: "user".to_string(),
+            content: serde_json::json!("third"),
+        });
+
+        let result = convert_request(&req, &config).unwrap();
+        let history = &result.conversation_state.history;
+
+        // 末尾的 "first" 与 "second" 合并为一条历史 user 消息，"third" 为当前消息
+        match &history[2] {
+            Message::User(msg) => assert_eq!(
+                msg.user_input_message.content,
+                "[user message 1]\nfirst\n\n[user message 2]\nsecond"
+            ),
+            _ => panic!("应为合并后的 user 消息"),
+        }
+    }
+
+    /// 长历史转换基准（串行 vs 并行）
+    ///
+    /// 运行：`cargo test --release bench_convert_history_groups -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_convert_history_groups() {
+        use super::super::types::Message as AnthropicMessage;
+        use std::time::{Duration, Instant};
+
+        // 1000 轮对话，每轮携带较大的文本与工具调用/结果块
+        let big_text = "lorem ipsum dolor sit amet ".repeat(400);
+        let mut messages = Vec::new();
+        for i in 0..1000 {
+            messages.push(AnthropicMessage {
+                role: "user".to_string(),
+                content: serde_json::json!([
+                    {"type": "text", "text": big_text},
+                    {"type": "tool_result", "tool_use_id": format!("tool-{}", i), "content": big_text}
+                ]),
+            });
+            messages.push(AnthropicMessage {
+                role: "assistant".to_string(),
+                content: serde_json::json!([
+                    {"type": "text", "text": big_text},
+                    {"type": "tool_use", "id": format!("tool-{}", i + 1), "name": "Read", "input": {"path": big_text}}
+                ]),
+            });
+        }
+        let groups: Vec<Vec<&AnthropicMessage>> = messages.iter().map(|m| vec![m]).collect();
+
+        let percentiles = |parallel: bool| {
+            let mut samples: Vec<Duration> = (0..50)
+                .map(|_| {
+                    let start = Instant::now();
+                    convert_history_groups(
+                        &groups,
+                        "claude-sonnet-4",
+                        UserTurnJoin::Newline,
+                        "Continue.",
+                        &UnsupportedBlocksConfig::default(),
+                        parallel,
+                    )
+                    .unwrap();
+                    start.elapsed()
+                })
+                .collect();
+            samples.sort();
+            (
+                samples[samples.len() / 2],
+                samples[samples.len() * 99 / 100],
+            )
+        };
+
+        let (serial_p50, serial_p99) = percentiles(false);
+        let (parallel_p50, parallel_p99) = percentiles(true);
+        println!("serial:   p50={:?} p99={:?}", serial_p50, serial_p99);
+        println!("parallel: p50={:?} p99={:?}", parallel_p50, parallel_p99);
+
+        // 单核环境下并行无收益，仅在多核时断言
+        if rayon::current_num_threads() > 1 {
+            assert!(parallel_p99 < serial_p99);
+        }
+    }
+
+    #[test]
+    fn test_convert_history_groups_parallel_preserves_order() {
+        use super::super::types::Message as AnthropicMessage;
+
+        let messages: Vec<AnthropicMessage> = (0..200)
+            .map(|i| AnthropicMessage {
+                role: if i % 2 == 0 { "user" } else { "assistant" }.to_string(),
+                content: serde_json::json!(format!("message {}", i)),
+            })
+            .collect();
+        let groups: Vec<Vec<&AnthropicMessage>> = messages.iter().map(|m| vec![m]).collect();
+
+        let serial = convert_history_groups(
+            &groups,
+            "claude-sonnet-4",
+            UserTurnJoin::Newline,
+            "Continue.",
+            &UnsupportedBlocksConfig::default(),
+            false,
+        )
+        .unwrap();
+        let parallel = convert_history_groups(
+            &groups,
+            "claude-sonnet-4",
+            UserTurnJoin::Newline,
+            "Continue.",
+            &UnsupportedBlocksConfig::default(),
+            true,
+        )
+        .unwrap();
+
+        assert_eq!(
+            serde_json::to_string(&serial).unwrap(),
+            serde_json::to_string(&parallel).unwrap()
+        );
+    }
+
+    fn computer_tool() -> super::super::types::Tool {
+        serde_json::from_value(serde_json::json!({
+            "type": "computer_20250124",
+            "name": "computer",
+            "display_width_px": 1280,
+            "display_height_px": 800
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_convert_tools_computer_use_passthrough() {
+        let tools =
+            convert_tools(&Some(vec![computer_tool()]), &ConverterConfig::default()).unwrap();
+
+        assert_eq!(tools.len(), 1);
+        let spec = &tools[0].tool_specification;
+        assert_eq!(spec.name, "computer");
+        assert!(spec.description.contains("1280x800"));
+        assert_eq!(
+            spec.input_schema.json["required"],
+            serde_json::json!(["action"])
+        );
+    }
+
+    #[test]
+    fn test_convert_tools_computer_use_reject() {
+        let config = ConverterConfig {
+            computer_use: ComputerUsePolicy::Reject,
+            ..Default::default()
+        };
+
+        let result = convert_tools(&Some(vec![computer_tool()]), &config);
+        assert!(matches!(
+            result,
+            Err(ConversionError::UnsupportedTool(name, tool_type))
+                if name == "computer" && tool_type == "computer_20250124"
+        ));
+    }
+
+    #[test]
+    fn test_convert_tools_keeps_client_tool_named_like_server_tool() {
+        let tools: Vec<super::super::types::Tool> = serde_json::from_value(serde_json::json!([
+            {"type": "code_execution_20250522", "name": "code_execution"},
+            {
+                "name": "web_fetch",
+                "description": "Fetch a URL",
+                "input_schema": {"type": "object", "properties": {"url": {"type": "string"}}}
+            }
+        ]))
+        .unwrap();
+
+        // 以 type 声明的服务端工具不传给 Kiro，同名的客户端工具照常保留
+        let tools = convert_tools(&Some(tools), &ConverterConfig::default()).unwrap();
+        assert_eq!(tools.len(), 1);
+        assert_eq!(tools[0].tool_specification.name, "web_fetch");
+    }
+
+    #[test]
+    fn test_unsupported_block_policies() {
+        let content = serde_json::json!([
+            {"type": "text", "text": "see attached"},
+            {"type": "document", "source": {"type": "text", "data": "notes"}}
+        ]);
+
+        // 默认丢弃
+        let mut blocks = UnsupportedBlocksConfig::default();
+        let (text, _, _) = process_message_content(&content, &blocks).unwrap();
+        assert_eq!(text, "see attached");
+
+        // stringify 保留为 JSON 文本
+        blocks
+            .types
+            .insert("document".to_string(), UnsupportedBlockPolicy::Stringify);
+        let (text, _, _) = process_message_content(&content, &blocks).unwrap();
+        assert!(text.starts_with("see attached\n{\"source\""));
+
+        // 严格部署：未列出的类型报错，显式列出的 redacted_thinking 仍丢弃
+        let blocks = UnsupportedBlocksConfig {
+            default: UnsupportedBlockPolicy::Error,
+            ..Default::default()
+        };
+        assert!(matches!(
+            process_message_content(&content, &blocks),
+            Err(ConversionError::UnsupportedContentBlock(role, block_type))
+                if role == "user" && block_type == "document"
+        ));
+        let msg = super::super::types::Message {
+            role: "assistant".to_string(),
+            content: serde_json::json!([
+                {"type": "redacted_thinking", "data": "abc"},
+                {"type": "text", "text": "done"}
+            ]),
+        };
+        let result = convert_assistant_message(&msg, &blocks).unwrap();
+        assert_eq!(result.assistant_response_message.content, "done");
+    }
+
+    #[test]
+    fn test_convert_tools_resolves_schema_refs() {
+        let tool: super::super::types::Tool = serde_json::from_value(serde_json::json!({
+            "name": "mcp__db__query",
+            "description": "Run a query",
+            "input_schema": {
+                "type": "object",
+                "properties": {"filter": {"$ref": "#/$defs/Filter"}},
+                "$defs": {"Filter": {"type": "object", "properties": {"field": {"type": "string"}}}}
+            }
+        }))
+        .unwrap();
+
+        let tools = convert_tools(&Some(vec![tool]), &ConverterConfig::default()).unwrap();
+        let schema = &tools[0].tool_specification.input_schema.json;
+        assert!(schema.get("$defs").is_none());
+        assert_eq!(
+            schema["properties"]["filter"]["properties"]["field"]["type"],
+            "string"
+        );
+    }
+
+    #[test]
+    fn test_convert_tools_invalid_schema_names_tool() {
+        let tool: super::super::types::Tool = serde_json::from_value(serde_json::json!({
+            "name": "mcp__db__query",
+            "description": "Run a query",
+            "input_schema": {
+                "type": "object",
+                "properties": {"filter": {"$ref": "#/$defs/Missing"}}
+            }
+        }))
+        .unwrap();
+
+        let err = convert_tools(&Some(vec![tool]), &ConverterConfig::default()).unwrap_err();
+        assert!(matches!(
+            &err,
+            ConversionError::InvalidToolSchema(name, _) if name == "mcp__db__query"
+        ));
+        assert!(err.to_string().contains("#/$defs/Missing"));
+    }
+
+    #[test]
+    fn test_tool_result_screenshot_becomes_image() {
+        let content = serde_json::json!([{
+            "type": "tool_result",
+            "tool_use_id": "toolu_1",
+            "content": [
+                {"type": "text", "text": "screenshot taken"},
+                {"type": "image", "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}}
+            ]
+        }]);
+
+        let (_, images, tool_results) =
+            process_message_content(&content, &UnsupportedBlocksConfig::default()).unwrap();
+        assert_eq!(images.len(), 1);
+        assert_eq!(tool_results.len(), 1);
+    }
+
+    #[test]
+    fn test_dedup_images_across_turns() {
+        let screenshot = || KiroImage::from_base64("png", "c2NyZWVuc2hvdA==");
+        let other = KiroImage::from_base64("png", "b3RoZXI=");
+
+        let mut history = vec![
+            Message::User(HistoryUserMessage {
+                user_input_message: UserMessage::new("turn 1", "claude-sonnet-4.5")
+                    .with_images(vec![screenshot(), other]),
+            }),
+            Message::Assistant(HistoryAssistantMessage::new("ok")),
+        ];
+        let mut current_images = vec![screenshot()];
+        let mut current_content = "turn 2".to_string();
+
+        let removed = dedup_images(&mut history, &mut current_images, &mut current_content);
+
+        assert_eq!(removed, 1);
+        assert!(current_images.is_empty());
+        let hash = image_hash(&screenshot());
+        assert!(current_content.contains(&format!("[image {} omitted", hash)));
+        match &history[0] {
+            Message::User(user) => {
+                assert_eq!(user.user_input_message.images.len(), 2);
+                assert!(
+                    user.user_input_message
+                        .content
+                        .ends_with(&format!("[image {}]", hash))
+                );
+            }
+            _ => panic!("应为 user 消息"),
+        }
+    }
+
+    #[test]
+    fn test_dedup_images_no_duplicates_untouched() {
+        let mut history = Vec::new();
+        let mut current_images = vec![
+            KiroImage::from_base64("png", "YQ=="),
+            KiroImage::from_base64("png", "Yg=="),
+        ];
+        let mut current_content = "hi".to_string();
+
+        assert_eq!(
+            dedup_images(&mut history, &mut current_images, &mut current_content),
+            0
+        );
+        assert_eq!(current_images.len(), 2);
+        assert_eq!(current_content, "hi");
+    }
+}
+
+/// 性质测试：随机生成 Anthropic 请求，检查转换结果的不变量
+#[cfg(all(test, not(edge)))]
+mod proptests {
+    use std::collections::HashSet;
+
+    use proptest::prelude::*;
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    const TOOL_NAMES: &[&str] = &["read", "Read", "Write", "bash", "web_fetch"];
+
+    fn text() -> impl Strategy<Value = String> {
+        prop_oneof![
+            Just(String::new()),
+            Just(" \n\t".to_string()),
+            "[a-z ]{1,16}",
+            "\\PC{1,24}",
+        ]
+    }
+
+    fn tool_use_id() -> impl Strategy<Value = String> {
+        (0..4u8).prop_map(|i| format!("toolu_{}", i))
+    }
+
+    fn tool_name() -> impl Strategy<Value = String> {
+        prop::sample::select(TOOL_NAMES).prop_map(str::to_string)
+    }
+
+    fn block() -> impl Strategy<Value = Value> {
+        let tool_result_content = prop_oneof![
+            Just(Value::Null),
+            text().prop_map(Value::String),
+            prop::collection::vec(text(), 0..3).prop_map(|texts| {
+                texts
+                    .into_iter()
+                    .map(|t| json!({"type": "text", "text": t}))
+                    .collect()
+            }),
+        ];
+        prop_oneof![
+            4 => text().prop_map(|t| json!({"type": "text", "text": t})),
+            2 => (tool_use_id(), tool_name(), text()).prop_map(|(id, name, arg)| {
+                json!({"type": "tool_use", "id": id, "name": name, "input": {"arg": arg}})
+            }),
+            2 => (tool_use_id(), tool_result_content, any::<bool>()).prop_map(
+                |(id, content, is_error)| {
+                    let mut block = json!({"type": "tool_result", "tool_use_id": id});
+                    if !content.is_null() {
+                        block["content"] = content;
+                    }
+                    if is_error {
+                        block["is_error"] = json!(true);
+                    }
+                    block
+                }
+            ),
+            1 => text().prop_map(|t| json!({"type": "thinking", "thinking": t, "signature": "sig"})),
+            1 => Just(json!({
+                "type": "image",
+                "source": {"type": "base64", "media_type": "image/png", "data": "iVBORw0KGgo="}
+            })),
+        ]
+    }
+
+    fn message() -> impl Strategy<Value = Value> {
+        let content = prop_oneof![
+            text().prop_map(Value::String),
+            prop::collection::vec(block(), 0..4).prop_map(Value::Array),
+        ];
+        (prop::sample::select(&["user", "assistant"][..]), content)
+            .prop_map(|(role, content)| json!({"role": role, "content": content}))
+    }
+
+    fn request() -> impl Strategy<Value = MessagesRequest> {
+        (
+            prop::collection::vec(message(), 1..8),
+            prop::sample::subsequence(TOOL_NAMES, 0..=TOOL_NAMES.len()),
+            prop::option::of(text()),
+        )
+            .prop_map(|(messages, tools, system)| {
+                let tools: Vec<Value> = tools
+                    .into_iter()
+                    .map(|name| {
+                        json!({
+                            "name": name,
+                            "description": "test tool",
+                            "input_schema": {"type": "object", "properties": {}}
+                        })
+                    })
+                    .collect();
+                let mut req = json!({
+                    "model": "claude-sonnet-4-20250514",
+                    "max_tokens": 1024,
+                    "messages": messages,
+                    "tools": tools
+                });
+                if let Some(system) = system {
+                    req["system"] = json!([{"type": "text", "text": system}]);
+                }
+                serde_json::from_value(req).expect("generated request should deserialize")
+            })
+    }
+
+    fn history_pairing() -> impl Strategy<Value = HistoryPairingStrategy> {
+        prop_oneof![
+            Just(HistoryPairingStrategy::Placeholder),
+            Just(HistoryPairingStrategy::Merge),
+        ]
+    }
+
+    proptest! {
+        #![proptest_config(ProptestConfig::with_cases(256))]
+
+        #[test]
+        fn prop_conversion_invariants(req in request(), pairing in history_pairing()) {
+            let config = ConverterConfig {
+                history_pairing: pairing,
+                ..Default::default()
+            };
+            // 转换可以失败（如不支持的内容块），但不能 panic
+            let Ok(result) = convert_request(&req, &config) else {
+                return Ok(());
+            };
+            let state = &result.conversation_state;
+
+            // 历史严格按 user / assistant 交替，且以 assistant 结尾
+            for (i, msg) in state.history.iter().enumerate() {
+                let is_user = matches!(msg, Message::User(_));
+                prop_assert_eq!(is_user, i % 2 == 0, "history[{}] breaks alternation", i);
+            }
+
+            // 历史中的消息与当前消息内容非空
+            for msg in &state.history {
+                match msg {
+                    Message::User(user) => {
+                        let user = &user.user_input_message;
+                        prop_assert!(
+                            !user.content.is_empty()
+                                || !user.images.is_empty()
+                                || !user.user_input_message_context.tool_results.is_empty()
+                        );
+                    }
+                    // 没有文本时使用 ASSISTANT_PLACEHOLDER
+                    Message::Assistant(assistant) => {
+                        prop_assert!(!assistant.assistant_response_message.content.is_empty());
+                    }
+                }
+            }
+            let current = &state.current_message.user_input_message;
+            prop_assert!(!current.content.is_empty() || !current.images.is_empty());
+
+            // 历史中引用的工具都有定义（Kiro 匹配工具名时忽略大小写）
+            let defined: HashSet<String> = current
+                .user_input_message_context
+                .tools
+                .iter()
+                .map(|t| t.tool_specification.name.to_lowercase())
+                .collect();
+            let mut tool_use_ids = HashSet::new();
+            for msg in &state.history {
+                if let Message::Assistant(assistant) = msg {
+                    for tool_use in assistant
+                        .assistant_response_message
+                        .tool_uses
+                        .iter()
+                        .flatten()
+                    {
+                        prop_assert!(defined.contains(&tool_use.name.to_lowercase()));
+                        tool_use_ids.insert(tool_use.tool_use_id.clone());
+                    }
+                }
+            }
+
+            // 当前消息的 tool_result 都对应历史中的 tool_use
+            for result in &current.user_input_message_context.tool_results {
+                prop_assert!(tool_use_ids.contains(&result.tool_use_id));
+            }
+        }
+    }
 }
@@ -0,0 +1,329 @@
+//! 转换失败死信队列
+//!
+//! 启用 `deadLetter.enabled` 后，协议转换失败（模型不支持、消息为空、工具或内容块不被支持等）
+//! 的请求连同错误信息一起保存，写入缓存目录下的 `kiro_dead_letters.json`，
+//! 通过 Admin API 查询，便于发现客户端系统性的请求体问题，而不是淹没在 400 日志里。
+//!
+//! 请求体按日志脱敏配置处理（严格模式下不保存），并按 `deadLetter.maxPayloadBytes` 截断。
+//! 记录时只标记待保存，由后台任务每隔 [`SAVE_INTERVAL`] 在阻塞线程上写盘，
+//! 持续的转换失败不会让每个 400 响应都同步重写整个队列
+
+use std::collections::{BTreeMap, VecDeque};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+use chrono::Utc;
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+
+use crate::common::{redact, usage};
+use crate::model::config::DeadLetterConfig;
+
+use super::converter::ConversionError;
+use super::types::MessagesRequest;
+
+/// 后台写盘间隔
+const SAVE_INTERVAL: Duration = Duration::from_secs(5);
+
+/// 一条死信记录
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    pub id: String,
+    /// 记录时间（RFC3339）
+    pub at: String,
+    /// 请求端点，如 `/v1/messages`
+    pub endpoint: String,
+    /// 脱敏后的 API Key
+    pub key: String,
+    pub model: String,
+    /// 错误类别，如 `unsupported_model`
+    pub kind: String,
+    pub error: String,
+    /// 脱敏后的请求体（JSON 文本，可能被截断）
+    pub payload: String,
+    /// 请求体原始大小（字节）
+    pub payload_bytes: usize,
+    pub truncated: bool,
+}
+
+/// 死信存储
+pub struct DeadLetterStore {
+    /// 持久化文件路径，为 None 时只保存在内存中
+    path: Option<PathBuf>,
+    capacity: usize,
+    max_payload_bytes: usize,
+    /// 记录（旧的在前）
+    entries: Mutex<VecDeque<DeadLetter>>,
+    /// 是否有尚未写盘的变更
+    dirty: AtomicBool,
+}
+
+impl DeadLetterStore {
+    /// 创建存储并加载已持久化的记录
+    pub fn new(path: Option<PathBuf>, config: &DeadLetterConfig) -> Self {
+        let capacity = config.capacity.max(1);
+        let mut entries: VecDeque<DeadLetter> = path
+            .as_deref()
+            .and_then(load_entries)
+            .unwrap_or_default()
+            .into();
+        while entries.len() > capacity {
+            entries.pop_front();
+        }
+        Self {
+            path,
+            capacity,
+            max_payload_bytes: config.max_payload_bytes,
+            entries: Mutex::new(entries),
+            dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// 启动后台任务，定期把变更写入磁盘
+    pub fn spawn_flusher(self: &Arc<Self>) {
+        if self.path.is_none() {
+            return;
+        }
+        let store = Arc::downgrade(self);
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(SAVE_INTERVAL);
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                if store.dirty.load(Ordering::Relaxed)
+                    && let Err(e) = tokio::task::spawn_blocking(move || store.flush()).await
+                {
+                    tracing::warn!("保存死信队列任务失败: {}", e);
+                }
+            }
+        });
+    }
+
+    /// 记录一次转换失败的请求
+    pub fn record(
+        &self,
+        endpoint: &str,
+        api_key: &str,
+        request: &MessagesRequest,
+        error: &ConversionError,
+    ) {
+        let payload = serde_json::to_string(&payload_json(request)).unwrap_or_default();
+        let payload_bytes = payload.len();
+        let mut payload = redact::body(&payload).into_owned();
+        let truncated = payload.len() > self.max_payload_bytes;
+        if truncated {
+            let mut end = self.max_payload_bytes;
+            while !payload.is_char_boundary(end) {
+                end -= 1;
+            }
+            payload.truncate(end);
+        }
+
+        let entry = DeadLetter {
+            id: uuid::Uuid::new_v4().to_string(),
+            at: Utc::now().to_rfc3339(),
+            endpoint: endpoint.to_string(),
+            key: usage::key_label(api_key),
+            model: request.model.clone(),
+            kind: error.code().to_string(),
+            error: error.to_string(),
+            payload,
+            payload_bytes,
+            truncated,
+        };
+
+        let mut entries = self.entries.lock();
+        if entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back(entry);
+        self.dirty.store(true, Ordering::Relaxed);
+    }
+
+    /// 查询记录（新的在前），可按错误类别过滤
+    pub fn list(&self, kind: Option<&str>, limit: usize) -> Vec<DeadLetter> {
+        self.entries
+            .lock()
+            .iter()
+            .rev()
+            .filter(|e| kind.is_none_or(|k| e.kind == k))
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// 按错误类别统计条数
+    pub fn counts(&self) -> BTreeMap<String, usize> {
+        let mut counts = BTreeMap::new();
+        for entry in self.entries.lock().iter() {
+            *counts.entry(entry.kind.clone()).or_default() += 1;
+        }
+        counts
+    }
+
+    pub fn get(&self, id: &str) -> Option<DeadLetter> {
+        self.entries.lock().iter().find(|e| e.id == id).cloned()
+    }
+
+    /// 清空记录，返回清除的条数
+    pub fn clear(&self) -> usize {
+        let mut entries = self.entries.lock();
+        let count = entries.len();
+        entries.clear();
+        self.dirty.store(true, Ordering::Relaxed);
+        count
+    }
+
+    /// 把尚未写盘的变更写入磁盘（阻塞），只在序列化期间持有锁
+    pub fn flush(&self) {
+        let Some(path) = &self.path else {
+            return;
+        };
+        if !self.dirty.swap(false, Ordering::Relaxed) {
+            return;
+        }
+        let json = match serde_json::to_string(&*self.entries.lock()) {
+            Ok(json) => json,
+            Err(e) => {
+                tracing::warn!("序列化死信队列失败: {}", e);
+                return;
+            }
+        };
+        if let Err(e) = std::fs::write(path, json) {
+            tracing::warn!("保存死信队列失败: {}", e);
+            self.dirty.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+impl Drop for DeadLetterStore {
+    fn drop(&mut self) {
+        self.flush();
+    }
+}
+
+/// 保存的请求字段（不含 metadata、工作区内容等与转换失败无关的扩展字段）
+fn payload_json(request: &MessagesRequest) -> Value {
+    json!({
+        "model": request.model,
+        "max_tokens": request.max_tokens,
+        "stream": request.stream,
+        "system": request
+            .system
+            .as_ref()
+            .map(|system| system.iter().map(|m| m.text.as_str()).collect::<Vec<_>>()),
+        "messages": request.messages,
+        "tools": request.tools,
+        "tool_choice": request.tool_choice,
+        "thinking": request.thinking.as_ref().map(|t| json!({
+            "type": t.thinking_type,
+            "budget_tokens": t.budget_tokens
+        })),
+        "stop_sequences": request.stop_sequences,
+        "parent_message_id": request.parent_message_id,
+    })
+}
+
+fn load_entries(path: &Path) -> Option<Vec<DeadLetter>> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(entries) => Some(entries),
+        Err(e) => {
+            tracing::warn!("解析死信队列缓存失败，将忽略: {}", e);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(model: &str, text: &str) -> MessagesRequest {
+        serde_json::from_value(json!({
+            "model": model,
+            "max_tokens": 1024,
+            "messages": [{"role": "user", "content": text}]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_record_evicts_and_filters() {
+        let config = DeadLetterConfig {
+            capacity: 2,
+            ..Default::default()
+        };
+        let store = DeadLetterStore::new(None, &config);
+        let key = "sk-test-0123456789abcdef";
+        store.record(
+            "/v1/messages",
+            key,
+            &request("gpt-4", "hi"),
+            &ConversionError::UnsupportedModel("gpt-4".into()),
+        );
+        store.record(
+            "/v1/messages",
+            key,
+            &request("gpt-5", "hi"),
+            &ConversionError::UnsupportedModel("gpt-5".into()),
+        );
+        store.record(
+            "/cc/v1/messages",
+            key,
+            &request("claude-sonnet-4", "hi"),
+            &ConversionError::EmptyMessages,
+        );
+
+        let all = store.list(None, 10);
+        assert_eq!(all.len(), 2);
+        assert_eq!(all[0].kind, "empty_messages");
+        assert_eq!(all[1].model, "gpt-5");
+        assert_eq!(all[0].key, "sk-t***cdef");
+        assert_eq!(store.list(Some("unsupported_model"), 10).len(), 1);
+        assert_eq!(store.counts().get("empty_messages"), Some(&1));
+        assert!(store.get(&all[1].id).is_some());
+
+        assert_eq!(store.clear(), 2);
+        assert!(store.list(None, 10).is_empty());
+    }
+
+    #[test]
+    fn test_payload_redacted_truncated_and_persisted() {
+        let path =
+            std::env::temp_dir().join(format!("kiro_dead_letters_{}.json", uuid::Uuid::new_v4()));
+        let config = DeadLetterConfig {
+            enabled: true,
+            capacity: 10,
+            max_payload_bytes: 120,
+        };
+        let store = DeadLetterStore::new(Some(path.clone()), &config);
+        let text = format!("my key is sk-abcdefghijklmnop {}", "很长的内容".repeat(50));
+        store.record(
+            "/v1/messages",
+            "sk-test-0123456789abcdef",
+            &request("gpt-4", &text),
+            &ConversionError::UnsupportedModel("gpt-4".into()),
+        );
+
+        let entry = &store.list(None, 1)[0];
+        assert!(entry.truncated);
+        assert!(entry.payload.len() <= 120);
+        assert!(entry.payload_bytes > 120);
+        assert!(!entry.payload.contains("sk-abcdefghijklmnop"));
+
+        // 记录时不写盘，写盘后重启可从磁盘恢复
+        assert!(!path.exists());
+        store.flush();
+        assert!(path.exists());
+        let reloaded = DeadLetterStore::new(Some(path.clone()), &config);
+        assert_eq!(reloaded.list(None, 10).len(), 1);
+        let _ = std::fs::remove_file(path);
+    }
+}
@@ -0,0 +1,66 @@
+//! 流式响应的下发方式
+//!
+//! `/v1/messages` 把内部的 [`SseEvent`] 序列经端点的 SSE 兼容层编码为响应体。OpenAI 兼容端点与
+//! WebSocket 传输复用同一处理流程，但需要的是事件本身：以 [`Delivery::Events`] 调用时不做编码，
+//! 事件流随响应扩展 [`EventStream`] 交给调用方，响应体为空。这样调用方不依赖 SSE 编码格式，
+//! 也不受 `stream.v1Compat` 等兼容层改写的影响
+
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    http::{StatusCode, header},
+    response::Response,
+};
+use futures::stream::BoxStream;
+use parking_lot::Mutex;
+
+use super::stream::SseEvent;
+
+/// 流式响应的下发方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Delivery {
+    /// 编码为 SSE 响应体（应用端点的 SSE 兼容层）
+    Sse,
+    /// 不编码，事件流随响应扩展交给调用方
+    Events,
+}
+
+/// 以 [`Delivery::Events`] 下发时随响应扩展交给调用方的事件流
+///
+/// 响应扩展要求 `Clone + Sync`，事件流只能取出一次
+#[derive(Clone)]
+pub struct EventStream(Arc<Mutex<Option<BoxStream<'static, SseEvent>>>>);
+
+impl EventStream {
+    /// 以事件流为内容的流式响应，响应头与 SSE 响应相同
+    pub fn response(events: BoxStream<'static, SseEvent>) -> Response {
+        let mut response = Response::builder()
+            .status(StatusCode::OK)
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .header(header::CACHE_CONTROL, "no-cache")
+            .body(Body::empty())
+            .unwrap();
+        response
+            .extensions_mut()
+            .insert(Self(Arc::new(Mutex::new(Some(events)))));
+        response
+    }
+
+    /// 取出响应携带的事件流；响应不是以事件流下发时返回 None
+    pub fn take(response: &mut Response) -> Option<BoxStream<'static, SseEvent>> {
+        response.extensions_mut().remove::<Self>()?.0.lock().take()
+    }
+
+    /// 在事件下发时逐个观察（如记录请求日志），不改变事件流
+    pub fn inspect(response: &mut Response, mut f: impl FnMut(&SseEvent) + Send + 'static) {
+        use futures::StreamExt;
+
+        if let Some(events) = Self::take(response) {
+            let events = events.inspect(move |event| f(event)).boxed();
+            response
+                .extensions_mut()
+                .insert(Self(Arc::new(Mutex::new(Some(events)))));
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! 编辑类工具的流式 diff 预览
+//!
+//! Edit/Write 工具的输入以 `input_json_delta` 分片下发，客户端要等完整 JSON 到达后才能解析出
+//! 修改内容。启用 `stream.editDiffPreview.enabled` 后，在工具输入流式下发的同时增量解析其中的
+//! `file_path`、`old_string`、`new_string`、`content` 字段，把已完整的行转换为统一 diff 格式，
+//! 通过扩展事件 `kiro_diff` 与 `input_json_delta` 并行下发：
+//!
+//! ```text
+//! event: kiro_diff
+//! data: {"type":"kiro_diff","index":1,"tool_use_id":"...","diff":"-old line\n+new line\n","done":false}
+//! ```
+//!
+//! 同一工具调用的各 `diff` 片段依次拼接即为完整预览；`done` 为 true 的最后一个事件附带增删行数。
+//! 预览只用于展示，hunk 头不含行号，原始 `input_json_delta` 保持不变
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use super::stream::SseEvent;
+
+/// 流式提取 JSON 对象顶层字符串字段
+///
+/// 不要求输入完整：尚未结束的字符串字段返回已收到的部分
+#[derive(Debug, Default)]
+struct PartialFields {
+    /// 嵌套深度（顶层对象内为 1）
+    depth: usize,
+    in_string: bool,
+    escape: bool,
+    /// `\uXXXX` 转义中已收到的十六进制字符
+    unicode: Option<String>,
+    /// 等待与低位代理组合的高位代理
+    high_surrogate: Option<u16>,
+    /// 当前顶层字符串是否为字段值（否则为字段名）
+    in_value: bool,
+    /// 冒号之后、逗号之前
+    after_colon: bool,
+    key: String,
+    /// 已收到的字符串字段值
+    values: HashMap<String, String>,
+    /// 已结束的字符串字段
+    complete: Vec<String>,
+}
+
+impl PartialFields {
+    fn feed(&mut self, chunk: &str) {
+        for c in chunk.chars() {
+            if self.in_string {
+                self.feed_string_char(c);
+                continue;
+            }
+            match c {
+                '"' => {
+                    self.in_string = true;
+                    if self.depth == 1 {
+                        self.in_value = self.after_colon;
+                        if self.in_value {
+                            self.values.insert(self.key.clone(), String::new());
+                        } else {
+                            self.key.clear();
+                        }
+                    }
+                }
+                ':' if self.depth == 1 => self.after_colon = true,
+                ',' if self.depth == 1 => self.after_colon = false,
+                '{' | '[' => self.depth += 1,
+                '}' | ']' => self.depth = self.depth.saturating_sub(1),
+                _ => {}
+            }
+        }
+    }
+
+    fn feed_string_char(&mut self, c: char) {
+        if let Some(hex) = self.unicode.as_mut() {
+            hex.push(c);
+            if hex.len() == 4 {
+                let code = u16::from_str_radix(hex, 16).unwrap_or(0xfffd);
+                self.unicode = None;
+                self.push_utf16(code);
+            }
+            return;
+        }
+        if self.escape {
+            self.escape = false;
+            match c {
+                'u' => self.unicode = Some(String::with_capacity(4)),
+                'n' => self.push('\n'),
+                't' => self.push('\t'),
+                'r' => self.push('\r'),
+                'b' => self.push('\u{8}'),
+                'f' => self.push('\u{c}'),
+                other => self.push(other),
+            }
+            return;
+        }
+        match c {
+            '\\' => self.escape = true,
+            '"' => {
+                self.in_string = false;
+                if self.depth == 1 && self.in_value {
+                    self.complete.push(self.key.clone());
+                }
+            }
+            other => self.push(other),
+        }
+    }
+
+    fn push_utf16(&mut self, code: u16) {
+        match (self.high_surrogate.take(), code) {
+            (None, 0xd800..=0xdbff) => self.high_surrogate = Some(code),
+            (Some(high), 0xdc00..=0xdfff) => {
+                let c = char::decode_utf16([high, code]).next().and_then(Result::ok);
+                self.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+            }
+            (_, code) => {
+                self.push(char::from_u32(code as u32).unwrap_or(char::REPLACEMENT_CHARACTER))
+            }
+        }
+    }
+
+    fn push(&mut self, c: char) {
+        if self.depth != 1 {
+            return;
+        }
+        if self.in_value {
+            if let Some(value) = self.values.get_mut(&self.key) {
+                value.push(c);
+            }
+        } else {
+            self.key.push(c);
+        }
+    }
+
+    fn get(&self, field: &str) -> Option<&str> {
+        self.values.get(field).map(String::as_str)
+    }
+
+    fn is_complete(&self, field: &str) -> bool {
+        self.complete.iter().any(|f| f == field)
+    }
+}
+
+/// 按顺序转换为 diff 行的字段及行前缀（删除行先于新增行）
+const DIFF_SOURCES: &[(&str, char)] = &[("old_string", '-'), ("new_string", '+'), ("content", '+')];
+
+/// 单个工具调用的 diff 预览状态
+#[derive(Debug, Default)]
+struct ToolDiff {
+    fields: PartialFields,
+    header_sent: bool,
+    /// 各字段已转换为 diff 行的字节数
+    emitted: HashMap<&'static str, usize>,
+    removed: usize,
+    added: usize,
+}
+
+impl ToolDiff {
+    /// 追加输入片段，返回新完整的 diff 文本
+    fn feed(&mut self, chunk: &str, done: bool) -> String {
+        self.fields.feed(chunk);
+        let mut diff = String::new();
+
+        if !self.header_sent {
+            let path = match self.fields.get("file_path") {
+                Some(path) if self.fields.is_complete("file_path") => path,
+                _ if done => self.fields.get("file_path").unwrap_or("unknown"),
+                _ => return diff,
+            };
+            diff.push_str(&format!("--- a/{path}\n+++ b/{path}\n@@ @@\n"));
+            self.header_sent = true;
+        }
+
+        for &(field, prefix) in DIFF_SOURCES {
+            // 删除行全部输出前不输出新增行（字段顺序不固定），保持统一 diff 的顺序
+            if field == "new_string" && !done && !self.fields.is_complete("old_string") {
+                continue;
+            }
+            let Some(value) = self.fields.get(field) else {
+                continue;
+            };
+            let offset = self.emitted.get(field).copied().unwrap_or(0);
+            let rest = &value[offset..];
+            let end = if done || self.fields.is_complete(field) {
+                rest.len()
+            } else {
+                rest.rfind('\n').map_or(0, |pos| pos + 1)
+            };
+            for line in rest[..end].lines() {
+                diff.push(prefix);
+                diff.push_str(line);
+                diff.push('\n');
+                match prefix {
+                    '-' => self.removed += 1,
+                    _ => self.added += 1,
+                }
+            }
+            self.emitted.insert(field, offset + end);
+        }
+        diff
+    }
+}
+
+/// 编辑类工具 diff 预览生成器
+#[derive(Debug)]
+pub struct DiffPreviewer {
+    tools: Vec<String>,
+    /// tool_use_id -> 预览状态
+    active: HashMap<String, ToolDiff>,
+}
+
+impl DiffPreviewer {
+    /// `tools` 为启用预览的工具名（区分大小写）
+    pub fn new(tools: &[String]) -> Self {
+        Self {
+            tools: tools.to_vec(),
+            active: HashMap::new(),
+        }
+    }
+
+    /// 处理一个工具输入片段，有新的 diff 内容或调用结束时返回 `kiro_diff` 事件
+    pub fn feed(
+        &mut self,
+        index: i32,
+        tool_use_id: &str,
+        name: &str,
+        input: &str,
+        done: bool,
+    ) -> Option<SseEvent> {
+        if !self.tools.iter().any(|t| t == name) {
+            return None;
+        }
+        let tool = self.active.entry(tool_use_id.to_string()).or_default();
+        let diff = tool.feed(input, done);
+        if !done {
+            return (!diff.is_empty()).then(|| diff_event(index, tool_use_id, diff, false));
+        }
+
+        let tool = self.active.remove(tool_use_id)?;
+        let mut event = diff_event(index, tool_use_id, diff, true);
+        event.data["removed"] = json!(tool.removed);
+        event.data["added"] = json!(tool.added);
+        Some(event)
+    }
+}
+
+fn diff_event(index: i32, tool_use_id: &str, diff: String, done: bool) -> SseEvent {
+    SseEvent::new(
+        "kiro_diff",
+        json!({
+            "type": "kiro_diff",
+            "index": index,
+            "tool_use_id": tool_use_id,
+            "diff": diff,
+            "done": done
+        }),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 按固定长度切分输入并拼接所有 diff 片段
+    fn stream_diff(input: &str, chunk_size: usize) -> (String, SseEvent) {
+        let mut previewer = DiffPreviewer::new(&["Edit".to_string(), "Write".to_string()]);
+        let chars: Vec<char> = input.chars().collect();
+        let mut diff = String::new();
+        for chunk in chars.chunks(chunk_size) {
+            let chunk: String = chunk.iter().collect();
+            if let Some(event) = previewer.feed(1, "toolu_1", "Edit", &chunk, false) {
+                diff.push_str(event.data["diff"].as_str().unwrap());
+            }
+        }
+        let last = previewer.feed(1, "toolu_1", "Edit", "", true).unwrap();
+        diff.push_str(last.data["diff"].as_str().unwrap());
+        (diff, last)
+    }
+
+    #[test]
+    fn test_edit_input_streams_as_unified_diff() {
+        let input = serde_json::to_string(&json!({
+            "file_path": "src/main.rs",
+            "old_string": "fn main() {\n    println!(\"hi\");\n}",
+            "new_string": "fn main() {\n    println!(\"你好 😀\");\n}",
+            "replace_all": false
+        }))
+        .unwrap();
+        let expected = "--- a/src/main.rs\n+++ b/src/main.rs\n@@ @@\n\
+            -fn main() {\n-    println!(\"hi\");\n-}\n\
+            +fn main() {\n+    println!(\"你好 😀\");\n+}\n";
+
+        for chunk_size in [1, 3, 7, input.len()] {
+            let (diff, last) = stream_diff(&input, chunk_size);
+            assert_eq!(diff, expected, "chunk size {}", chunk_size);
+            assert_eq!(last.data["done"], true);
+            assert_eq!(last.data["removed"], 3);
+            assert_eq!(last.data["added"], 3);
+        }
+
+        // 转义的 unicode（含代理对）按字符解码
+        let (diff, _) = stream_diff(
+            r#"{"file_path":"a.txt","old_string":"\u4f60\ud83d\ude00","new_string":"x"}"#,
+            2,
+        );
+        assert!(diff.contains("-你😀\n+x\n"));
+    }
+
+    #[test]
+    fn test_only_configured_tools_previewed() {
+        let mut previewer = DiffPreviewer::new(&["Write".to_string()]);
+        assert!(
+            previewer
+                .feed(0, "toolu_1", "Bash", r#"{"command":"ls\n"}"#, true)
+                .is_none()
+        );
+
+        // 未完成的行暂不输出
+        let input = r#"{"file_path":"notes.md","content":"line 1\nline"#;
+        let event = previewer.feed(0, "toolu_2", "Write", input, false).unwrap();
+        assert_eq!(
+            event.data["diff"],
+            "--- a/notes.md\n+++ b/notes.md\n@@ @@\n+line 1\n"
+        );
+        let event = previewer
+            .feed(0, "toolu_2", "Write", r#" 2"}"#, true)
+            .unwrap();
+        assert_eq!(event.data["diff"], "+line 2\n");
+        assert_eq!(event.data["added"], 2);
+    }
+}
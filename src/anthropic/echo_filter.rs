@@ -0,0 +1,195 @@
+//! 注入策略回显过滤
+//!
+//! 转换请求时会向 Write/Edit 工具描述和系统提示词追加策略文本、在系统提示词前注入
+//! `<thinking_mode>` 等 thinking 指令，模型偶尔会在回复中原样引用这些文本。这里在输出侧剥离它们的逐字回显：
+//! 文本按流式分片到达，末尾可能是某条策略文本的前缀时先暂存，待后续分片确认
+
+#[cfg(not(edge))]
+use crate::common::metrics;
+
+/// 注入策略回显过滤器
+#[derive(Debug, Clone)]
+pub struct EchoFilter {
+    patterns: Vec<String>,
+    /// 本次请求注入的 thinking 指令（单独计数）
+    directives: Vec<String>,
+    /// 尚未确认的文本（可能是某条策略文本的开头）
+    pending: String,
+}
+
+impl EchoFilter {
+    /// 创建过滤器，空字符串会被忽略
+    pub fn new<I, S>(patterns: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        Self {
+            patterns: patterns
+                .into_iter()
+                .map(Into::into)
+                .filter(|p: &String| !p.is_empty())
+                .collect(),
+            directives: Vec::new(),
+            pending: String::new(),
+        }
+    }
+
+    /// 同时剥离本次请求注入的 thinking 指令前缀
+    ///
+    /// 除完整前缀外，其中的 `<thinking_mode>`、`<max_thinking_length>` 标签单独出现时也会被剥离
+    pub fn with_thinking_directive(mut self, prefix: &str) -> Self {
+        if prefix.is_empty() {
+            return self;
+        }
+        // 完整前缀优先匹配，只计一次
+        self.directives.push(prefix.to_string());
+        for tag in ["thinking_mode", "max_thinking_length"] {
+            let open = format!("<{}>", tag);
+            let close = format!("</{}>", tag);
+            if let Some(start) = prefix.find(&open)
+                && let Some(end) = prefix[start..].find(&close)
+            {
+                let element = &prefix[start..start + end + close.len()];
+                if element != prefix {
+                    self.directives.push(element.to_string());
+                }
+            }
+        }
+        self
+    }
+
+    /// 输入一段文本，返回可以安全输出的部分
+    pub fn push(&mut self, text: &str) -> String {
+        self.pending.push_str(text);
+        self.strip_complete_matches();
+
+        let hold = self.partial_match_len();
+        let emit_len = self.pending.len() - hold;
+        let remaining = self.pending.split_off(emit_len);
+        std::mem::replace(&mut self.pending, remaining)
+    }
+
+    /// 流结束或需要立即输出时，返回暂存的全部文本
+    pub fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 移除暂存文本中完整出现的策略文本和 thinking 指令
+    fn strip_complete_matches(&mut self) {
+        let directives = strip_all(&mut self.pending, &self.directives);
+        if directives > 0 {
+            tracing::warn!("剥离模型回显的 thinking 指令 {} 处", directives);
+            #[cfg(not(edge))]
+            metrics::policy_echo().record_directives_stripped(directives);
+        }
+        let stripped = strip_all(&mut self.pending, &self.patterns);
+        if stripped > 0 {
+            tracing::warn!("剥离模型回显的注入策略文本 {} 处", stripped);
+            #[cfg(not(edge))]
+            metrics::policy_echo().record_stripped(stripped);
+        }
+    }
+
+    /// 暂存文本末尾与任一策略文本前缀重合的最大长度（字节）
+    fn partial_match_len(&self) -> usize {
+        let max_len = self
+            .patterns()
+            .map(|p| p.len() - 1)
+            .max()
+            .unwrap_or(0)
+            .min(self.pending.len());
+        let window_start = self.pending.len() - max_len;
+
+        self.pending
+            .char_indices()
+            .map(|(i, _)| i)
+            .filter(|&i| i >= window_start)
+            .find(|&i| {
+                let suffix = &self.pending[i..];
+                self.patterns().any(|p| p.starts_with(suffix))
+            })
+            .map(|i| self.pending.len() - i)
+            .unwrap_or(0)
+    }
+
+    /// 全部需要剥离的文本
+    fn patterns(&self) -> impl Iterator<Item = &String> {
+        self.directives.iter().chain(&self.patterns)
+    }
+}
+
+/// 移除文本中所有完整出现的模式，返回移除的次数
+fn strip_all(text: &mut String, patterns: &[String]) -> u64 {
+    let mut stripped = 0;
+    for pattern in patterns {
+        while let Some(pos) = text.find(pattern.as_str()) {
+            text.replace_range(pos..pos + pattern.len(), "");
+            stripped += 1;
+        }
+    }
+    stripped
+}
+
+#[cfg(all(test, not(edge)))]
+mod tests {
+    use super::*;
+
+    const POLICY: &str = "Never ask the user whether to switch approaches.";
+
+    #[test]
+    fn test_strips_verbatim_echo_in_one_chunk() {
+        let mut filter = EchoFilter::new([POLICY]);
+        let out = filter.push(&format!("Done. {} Next step.", POLICY));
+        assert_eq!(format!("{}{}", out, filter.flush()), "Done.  Next step.");
+    }
+
+    #[test]
+    fn test_strips_echo_across_chunks() {
+        let mut filter = EchoFilter::new([POLICY]);
+        let mut out = String::new();
+        out.push_str(&filter.push("Sure. Never ask the user"));
+        // 可能是策略文本开头的部分被暂存
+        assert_eq!(out, "Sure. ");
+        out.push_str(&filter.push(" whether to switch approaches. OK"));
+        out.push_str(&filter.flush());
+        assert_eq!(out, "Sure.  OK");
+    }
+
+    #[test]
+    fn test_releases_text_that_diverges() {
+        let mut filter = EchoFilter::new([POLICY]);
+        let mut out = filter.push("Never ask");
+        assert_eq!(out, "");
+        out.push_str(&filter.push(" me again，好吗"));
+        assert_eq!(out, "Never ask me again，好吗");
+        assert_eq!(filter.flush(), "");
+    }
+
+    #[test]
+    fn test_strips_thinking_directive_across_chunks() {
+        let prefix =
+            "<thinking_mode>enabled</thinking_mode><max_thinking_length>1024</max_thinking_length>";
+        let mut filter = EchoFilter::new([POLICY]).with_thinking_directive(prefix);
+        assert_eq!(filter.directives.len(), 3);
+
+        let before = metrics::policy_echo().directives_stripped();
+        let mut out = String::new();
+        for chunk in [
+            "<thinking_mode>enab",
+            "led</thinking_mode><max_thinking_length>10",
+            "24</max_thinking_length>Hello",
+            " <thinking_mode>enabled</thinking_mode> and <thinking>x</thinking>",
+        ] {
+            out.push_str(&filter.push(chunk));
+        }
+        out.push_str(&filter.flush());
+        assert_eq!(out, "Hello  and <thinking>x</thinking>");
+        assert!(metrics::policy_echo().directives_stripped() >= before + 2);
+
+        // 其他长度的指令不是本次注入的内容，原样保留
+        let mut filter = EchoFilter::new([POLICY]).with_thinking_directive(prefix);
+        let text = "<max_thinking_length>2048</max_thinking_length>";
+        assert_eq!(filter.push(text) + &filter.flush(), text);
+    }
+}
@@ -0,0 +1,363 @@
+//! Anthropic API 错误类型定义
+//!
+//! 所有对外错误统一转换为 `ApiError`，按 Anthropic 错误格式返回：
+//! `{"type": "error", "error": {"type": "...", "message": "..."}}`
+//!
+//! axum 自身产生的拒绝（请求体解析失败、请求体过大、方法不允许等）是纯文本响应，
+//! 由 [`into_envelope`] 改写为同样的格式，SDK 客户端可以按统一格式解析所有错误
+
+use std::fmt;
+
+#[cfg(not(edge))]
+use axum::{
+    body::to_bytes,
+    http::header,
+    response::{IntoResponse, Json, Response},
+};
+use http::StatusCode;
+
+use crate::kiro::model::events::ExceptionKind;
+use crate::kiro::parser::error::ParseError;
+
+use super::converter::ConversionError;
+#[cfg(not(edge))]
+use super::types::ErrorResponse;
+
+/// 上游过载（Anthropic 的 529 状态码）
+const OVERLOADED: u16 = 529;
+
+/// 改写纯文本错误响应时读取的最大长度
+#[cfg(not(edge))]
+const MAX_PLAIN_ERROR_BODY: usize = 64 * 1024;
+
+/// Anthropic API 错误
+#[derive(Debug)]
+pub enum ApiError {
+    /// 请求无效（400）
+    InvalidRequest(String),
+
+    /// API Key 无效（401）
+    Authentication,
+
+    /// 上游拒绝凭据，所有凭据均不可用（401）
+    UpstreamUnauthorized(String),
+
+    /// API Key 无权执行该请求，如模型不在白名单内（403）
+    PermissionDenied(String),
+
+    /// 服务不可用，如未配置 KiroProvider（503）
+    ServiceUnavailable(String),
+
+    /// 上游限流，重试耗尽后仍被拒绝（429）
+    RateLimited(String),
+
+    /// 上游暂时过载或不可用，重试耗尽后仍失败（529）
+    Overloaded(String),
+
+    /// 上游调用或响应解析失败（502）
+    Upstream(String),
+
+    /// 内部错误（500）
+    Internal(String),
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::InvalidRequest(msg) => write!(f, "{}", msg),
+            ApiError::Authentication => write!(f, "Invalid API key"),
+            ApiError::UpstreamUnauthorized(msg) => write!(f, "{}", msg),
+            ApiError::PermissionDenied(msg) => write!(f, "{}", msg),
+            ApiError::ServiceUnavailable(msg) => write!(f, "{}", msg),
+            ApiError::RateLimited(msg) => write!(f, "{}", msg),
+            ApiError::Overloaded(msg) => write!(f, "{}", msg),
+            ApiError::Upstream(msg) => write!(f, "{}", msg),
+            ApiError::Internal(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+impl ApiError {
+    /// 获取对应的 HTTP 状态码
+    pub fn status_code(&self) -> StatusCode {
+        match self {
+            ApiError::InvalidRequest(_) => StatusCode::BAD_REQUEST,
+            ApiError::Authentication | ApiError::UpstreamUnauthorized(_) => {
+                StatusCode::UNAUTHORIZED
+            }
+            ApiError::PermissionDenied(_) => StatusCode::FORBIDDEN,
+            ApiError::ServiceUnavailable(_) => StatusCode::SERVICE_UNAVAILABLE,
+            ApiError::RateLimited(_) => StatusCode::TOO_MANY_REQUESTS,
+            ApiError::Overloaded(_) => StatusCode::from_u16(OVERLOADED).expect("529 是有效状态码"),
+            ApiError::Upstream(_) => StatusCode::BAD_GATEWAY,
+            ApiError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+
+    /// Anthropic 错误类型
+    pub fn error_type(&self) -> &'static str {
+        error_type_for_status(self.status_code())
+    }
+}
+
+/// 按状态码对应的 Anthropic 错误类型
+pub fn error_type_for_status(status: StatusCode) -> &'static str {
+    match status.as_u16() {
+        401 => "authentication_error",
+        403 => "permission_error",
+        404 => "not_found_error",
+        413 => "request_too_large",
+        429 => "rate_limit_error",
+        OVERLOADED => "overloaded_error",
+        400..=499 => "invalid_request_error",
+        _ => "api_error",
+    }
+}
+
+/// 将非 JSON 的错误响应改写为 Anthropic 错误格式，保留状态码和其他响应头
+#[cfg(not(edge))]
+pub async fn into_envelope(response: Response) -> Response {
+    let status = response.status();
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    if !(status.is_client_error() || status.is_server_error()) || is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let message = match to_bytes(body, MAX_PLAIN_ERROR_BODY).await {
+        Ok(bytes) if !bytes.trim_ascii().is_empty() => {
+            String::from_utf8_lossy(bytes.trim_ascii()).into_owned()
+        }
+        _ => status.canonical_reason().unwrap_or("Error").to_string(),
+    };
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    let body = ErrorResponse::new(error_type_for_status(status), message);
+    (parts, Json(body)).into_response()
+}
+
+impl ApiError {
+    /// 按上游异常种类映射错误
+    ///
+    /// | 异常种类 | 状态码 | 错误类型 |
+    /// |---|---|---|
+    /// | Throttling | 429 | `rate_limit_error` |
+    /// | AccessDenied | 401 | `authentication_error` |
+    /// | Validation / ResourceNotFound / ContentLengthExceeded | 400 | `invalid_request_error` |
+    /// | ServiceUnavailable | 529 | `overloaded_error` |
+    /// | Unknown | 502 | `api_error` |
+    ///
+    /// 请求前的错误按状态码返回，流式响应中途的错误以同样类型的 `error` 事件结束流
+    pub fn from_exception(kind: ExceptionKind, message: String) -> Self {
+        match kind {
+            ExceptionKind::Throttling => ApiError::RateLimited(message),
+            ExceptionKind::AccessDenied => ApiError::UpstreamUnauthorized(message),
+            ExceptionKind::Validation
+            | ExceptionKind::ResourceNotFound
+            | ExceptionKind::ContentLengthExceeded => ApiError::InvalidRequest(message),
+            ExceptionKind::ServiceUnavailable => ApiError::Overloaded(message),
+            ExceptionKind::Unknown => ApiError::Upstream(message),
+        }
+    }
+
+    /// 按上游 `Error`/`Exception` 事件的类型和消息映射错误
+    pub fn from_upstream_event(error_type: &str, message: &str) -> Self {
+        Self::from_exception(
+            ExceptionKind::from_type(error_type),
+            format!("上游 API 异常: {}: {}", error_type, message),
+        )
+    }
+}
+
+#[cfg(not(edge))]
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = ErrorResponse::new(self.error_type(), self.to_string());
+        (self.status_code(), Json(body)).into_response()
+    }
+}
+
+impl From<ConversionError> for ApiError {
+    fn from(err: ConversionError) -> Self {
+        match err {
+            ConversionError::UnsupportedModel(model) => {
+                ApiError::InvalidRequest(format!("模型不支持: {}", model))
+            }
+            ConversionError::EmptyMessages => ApiError::InvalidRequest("消息列表为空".to_string()),
+            ConversionError::UnsupportedTool(..)
+            | ConversionError::InvalidToolSchema(..)
+            | ConversionError::ThinkingUnsupported(..)
+            | ConversionError::UnsupportedContentBlock(..)
+            | ConversionError::InvalidToolChoice(_) => ApiError::InvalidRequest(err.to_string()),
+        }
+    }
+}
+
+impl From<ParseError> for ApiError {
+    fn from(err: ParseError) -> Self {
+        ApiError::Upstream(format!("解析上游响应失败: {}", err))
+    }
+}
+
+impl From<serde_json::Error> for ApiError {
+    fn from(err: serde_json::Error) -> Self {
+        ApiError::Internal(format!("序列化请求失败: {}", err))
+    }
+}
+
+/// KiroProvider 错误（已包含多凭据故障转移后的最终错误）
+impl From<anyhow::Error> for ApiError {
+    fn from(err: anyhow::Error) -> Self {
+        let err_str = err.to_string();
+
+        // 上下文窗口满了（对话历史累积超出模型上下文窗口限制）
+        if err_str.contains("CONTENT_LENGTH_EXCEEDS_THRESHOLD") {
+            tracing::warn!(error = %err, "上游拒绝请求：上下文窗口已满（不应重试）");
+            return ApiError::InvalidRequest(
+                "Context window is full. Reduce conversation history, system prompt, or tools."
+                    .to_string(),
+            );
+        }
+
+        // 单次输入太长（请求体本身超出上游限制）
+        if err_str.contains("Input is too long") {
+            tracing::warn!(error = %err, "上游拒绝请求：输入过长（不应重试）");
+            return ApiError::InvalidRequest(
+                "Input is too long. Reduce the size of your messages.".to_string(),
+            );
+        }
+
+        tracing::error!("Kiro API 调用失败: {}", err);
+        let message = format!("上游 API 调用失败: {}", err);
+        match ExceptionKind::from_error_body(&err_str) {
+            Some(kind) => ApiError::from_exception(kind, message),
+            None => ApiError::Upstream(message),
+        }
+    }
+}
+
+#[cfg(all(test, not(edge)))]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_mapping() {
+        assert_eq!(
+            ApiError::InvalidRequest("x".to_string()).status_code(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            ApiError::Authentication.status_code(),
+            StatusCode::UNAUTHORIZED
+        );
+        assert_eq!(
+            ApiError::Upstream("x".to_string()).error_type(),
+            "api_error"
+        );
+    }
+
+    #[test]
+    fn test_from_conversion_error() {
+        let err = ApiError::from(ConversionError::EmptyMessages);
+        assert!(matches!(err, ApiError::InvalidRequest(_)));
+        assert_eq!(err.error_type(), "invalid_request_error");
+    }
+
+    #[test]
+    fn test_from_provider_error() {
+        let err = ApiError::from(anyhow::anyhow!(
+            "流式 API 请求失败: 400 CONTENT_LENGTH_EXCEEDS_THRESHOLD"
+        ));
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+
+        let err = ApiError::from(anyhow::anyhow!("connection reset"));
+        assert_eq!(err.status_code(), StatusCode::BAD_GATEWAY);
+
+        let err = ApiError::from(anyhow::anyhow!(
+            r#"流式 API 请求失败: 429 {{"__type":"ThrottlingException"}}"#
+        ));
+        assert_eq!(err.status_code(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(err.error_type(), "rate_limit_error");
+
+        let err = ApiError::from(anyhow::anyhow!(
+            "流式 API 请求失败: 400 ValidationException: bad field"
+        ));
+        assert_eq!(err.status_code(), StatusCode::BAD_REQUEST);
+
+        let err = ApiError::from(anyhow::anyhow!(
+            r#"流式 API 请求失败: 503 {{"__type":"ServiceUnavailableException"}}"#
+        ));
+        assert_eq!(err.status_code().as_u16(), 529);
+        assert_eq!(err.error_type(), "overloaded_error");
+
+        let err = ApiError::from(anyhow::anyhow!(
+            r#"流式 API 请求失败: 403 {{"__type":"AccessDeniedException"}}"#
+        ));
+        assert_eq!(err.status_code(), StatusCode::UNAUTHORIZED);
+        assert_eq!(err.error_type(), "authentication_error");
+    }
+
+    #[test]
+    fn test_from_upstream_event() {
+        let cases = [
+            ("ThrottlingException", "rate_limit_error"),
+            ("ExpiredTokenException", "authentication_error"),
+            ("ValidationException", "invalid_request_error"),
+            ("InternalServerException", "overloaded_error"),
+            ("SomethingNewException", "api_error"),
+        ];
+        for (error_type, expected) in cases {
+            let err = ApiError::from_upstream_event(error_type, "boom");
+            assert_eq!(err.error_type(), expected, "{}", error_type);
+            assert!(err.to_string().contains("boom"));
+        }
+    }
+
+    async fn body_json(response: Response) -> serde_json::Value {
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_error_envelope() {
+        let response = ApiError::Authentication.into_response();
+        assert_eq!(
+            body_json(response).await,
+            serde_json::json!({
+                "type": "error",
+                "error": {"type": "authentication_error", "message": "Invalid API key"}
+            })
+        );
+
+        // axum 的纯文本拒绝改写为同样的格式
+        let plain = (StatusCode::PAYLOAD_TOO_LARGE, "length limit exceeded").into_response();
+        let response = into_envelope(plain).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        let body = body_json(response).await;
+        assert_eq!(body["type"], "error");
+        assert_eq!(body["error"]["type"], "request_too_large");
+        assert_eq!(body["error"]["message"], "length limit exceeded");
+
+        let empty = StatusCode::METHOD_NOT_ALLOWED.into_response();
+        let body = body_json(into_envelope(empty).await).await;
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+        assert_eq!(body["error"]["message"], "Method Not Allowed");
+
+        // 已是 JSON 的错误和成功响应保持不变
+        let response =
+            into_envelope(ApiError::RateLimited("slow down".to_string()).into_response()).await;
+        assert_eq!(
+            body_json(response).await["error"]["type"],
+            "rate_limit_error"
+        );
+        let ok = into_envelope("hello".into_response()).await;
+        assert_eq!(ok.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,220 @@
+//! 上游事件乱序修正
+//!
+//! Kiro 偶尔在工具调用的 `stop` 之后才送来该调用剩余的输入片段。按原样处理时内容块已经结束，
+//! 迟到的片段被当作复用 ID 的新调用，客户端拿到的工具输入不是完整的 JSON。
+//!
+//! 配置 `stream.reorderWindow` 后，`stop` 到达时输入还不是完整 JSON 的调用暂不结束内容块，
+//! 在之后的若干个上游事件内继续接收同一 ID 的片段；输入补全、窗口用完或其他内容开始时再结束。
+//!
+//! 同样地，命中 stop sequence 或达到 max_tokens 后流在逻辑上已经结束，通常紧随其后的
+//! `contextUsage` 会随上游一起被丢弃，`input_tokens` 只能报告估算值。配置窗口后输出停止时
+//! 先不断开上游，在之后的若干个上游事件内等待 `contextUsage` 并计入最终用量
+
+use std::collections::HashMap;
+
+use serde::de::IgnoredAny;
+
+#[cfg(not(edge))]
+use crate::common::metrics;
+
+/// 等待迟到片段的工具调用
+#[derive(Debug)]
+struct Pending {
+    /// 下发的调用 ID
+    call_id: String,
+    block_index: i32,
+    input: String,
+    /// 还可以等待的上游事件数
+    remaining: u32,
+}
+
+/// 合并的迟到片段
+#[derive(Debug, PartialEq)]
+pub struct LateFragment {
+    pub block_index: i32,
+    /// 输入已补全（或片段带有 stop），可以结束内容块
+    pub complete: bool,
+}
+
+/// 工具调用迟到片段的等待窗口
+#[derive(Debug, Default)]
+pub struct ReorderWindow {
+    /// 等待的上游事件数，0 表示不等待
+    window: u32,
+    /// 进行中调用已收到的输入（下发的调用 ID -> 输入）
+    inputs: HashMap<String, String>,
+    /// 暂缓结束的调用（上游 ID -> 状态）
+    pending: HashMap<String, Pending>,
+    /// 输出停止后已处理的上游事件数
+    after_stop: u32,
+}
+
+impl ReorderWindow {
+    pub fn new(window: u32) -> Self {
+        Self {
+            window,
+            ..Default::default()
+        }
+    }
+
+    /// 记录进行中调用收到的输入片段
+    pub fn observe(&mut self, call_id: &str, fragment: &str) {
+        if self.window > 0 {
+            self.inputs
+                .entry(call_id.to_string())
+                .or_default()
+                .push_str(fragment);
+        }
+    }
+
+    /// 调用的 `stop` 到达：输入不完整时暂缓结束内容块并返回 true
+    pub fn defer_stop(&mut self, upstream_id: &str, call_id: &str, block_index: i32) -> bool {
+        let input = self.inputs.remove(call_id).unwrap_or_default();
+        if self.window == 0 || is_complete(&input) {
+            return false;
+        }
+        tracing::debug!(
+            "工具调用 {} 的 stop 先于完整输入到达，等待迟到片段",
+            call_id
+        );
+        self.pending.insert(
+            upstream_id.to_string(),
+            Pending {
+                call_id: call_id.to_string(),
+                block_index,
+                input,
+                remaining: self.window,
+            },
+        );
+        true
+    }
+
+    /// 合并属于暂缓调用的迟到片段，不属于任何暂缓调用时返回 None
+    pub fn accept(
+        &mut self,
+        upstream_id: &str,
+        fragment: &str,
+        stop: bool,
+    ) -> Option<LateFragment> {
+        let pending = self.pending.get_mut(upstream_id)?;
+        pending.input.push_str(fragment);
+        let complete = stop || is_complete(&pending.input);
+        tracing::info!("合并工具调用 {} 迟到的输入片段", pending.call_id);
+        #[cfg(not(edge))]
+        metrics::late_tool_fragments().record_merged();
+        let block_index = pending.block_index;
+        if complete {
+            self.pending.remove(upstream_id);
+        }
+        Some(LateFragment {
+            block_index,
+            complete,
+        })
+    }
+
+    /// 每处理一个其他上游事件调用一次，返回窗口已用完、需要结束的块索引
+    pub fn tick(&mut self) -> Vec<i32> {
+        let mut expired = Vec::new();
+        self.pending.retain(|_, pending| {
+            pending.remaining -= 1;
+            if pending.remaining > 0 {
+                return true;
+            }
+            tracing::warn!("工具调用 {} 的输入在 stop 后仍不完整", pending.call_id);
+            #[cfg(not(edge))]
+            metrics::late_tool_fragments().record_expired();
+            expired.push(pending.block_index);
+            false
+        });
+        expired.sort_unstable();
+        expired
+    }
+
+    /// 输出停止后每处理一个上游事件调用一次
+    pub fn tick_after_stop(&mut self) {
+        self.after_stop += 1;
+    }
+
+    /// 输出停止后是否仍在窗口内（继续等待迟到的 contextUsage）
+    pub fn within_window_after_stop(&self) -> bool {
+        self.after_stop < self.window
+    }
+
+    /// 其他内容开始前结束所有暂缓的调用，返回块索引
+    pub fn drain(&mut self) -> Vec<i32> {
+        let mut indices: Vec<i32> = self.pending.drain().map(|(_, p)| p.block_index).collect();
+        indices.sort_unstable();
+        indices
+    }
+}
+
+/// 输入是否已是完整的 JSON（空输入视为无参数调用）
+fn is_complete(input: &str) -> bool {
+    input.trim().is_empty() || serde_json::from_str::<IgnoredAny>(input).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_complete_input_is_not_deferred() {
+        let mut window = ReorderWindow::new(4);
+        window.observe("a", r#"{"path":"#);
+        window.observe("a", r#""/tmp"}"#);
+        assert!(!window.defer_stop("a", "a", 0));
+        assert!(!window.defer_stop("b", "b", 1));
+        assert_eq!(window.accept("a", "}", false), None);
+
+        let mut disabled = ReorderWindow::new(0);
+        disabled.observe("a", r#"{"path":"#);
+        assert!(!disabled.defer_stop("a", "a", 0));
+    }
+
+    #[test]
+    fn test_late_fragment_completes_input() {
+        let mut window = ReorderWindow::new(4);
+        window.observe("a", r#"{"path":"/tmp","#);
+        assert!(window.defer_stop("a", "a", 2));
+        assert_eq!(
+            window.accept("a", r#""mode":"#, false),
+            Some(LateFragment {
+                block_index: 2,
+                complete: false
+            })
+        );
+        assert_eq!(
+            window.accept("a", r#""w"}"#, false),
+            Some(LateFragment {
+                block_index: 2,
+                complete: true
+            })
+        );
+        assert_eq!(window.accept("a", "{}", true), None);
+    }
+
+    #[test]
+    fn test_window_expires() {
+        let mut window = ReorderWindow::new(2);
+        window.observe("a", r#"{"path":"#);
+        window.observe("b_2", r#"{"path":"#);
+        assert!(window.defer_stop("a", "a", 0));
+        assert!(window.tick().is_empty());
+        assert!(window.defer_stop("b", "b_2", 1));
+        assert_eq!(window.tick(), vec![0]);
+        assert_eq!(window.drain(), vec![1]);
+        assert!(window.tick().is_empty());
+    }
+
+    #[test]
+    fn test_window_after_stop() {
+        let mut window = ReorderWindow::new(2);
+        assert!(window.within_window_after_stop());
+        window.tick_after_stop();
+        assert!(window.within_window_after_stop());
+        window.tick_after_stop();
+        assert!(!window.within_window_after_stop());
+
+        assert!(!ReorderWindow::new(0).within_window_after_stop());
+    }
+}
@@ -2,173 +2,79 @@
 
 use std::convert::Infallible;
 
-use anyhow::Error;
-use crate::kiro::model::events::Event;
+use crate::common::auth::KeyIdentity;
+use crate::common::telemetry::TracedJson;
+use crate::common::{metrics, redact};
+use crate::common::usage::RequestUsage;
+use crate::kiro::attempt::UpstreamAttempts;
+use crate::kiro::model::events::{Event, ExceptionKind};
 use crate::kiro::model::requests::kiro::KiroRequest;
-use crate::kiro::parser::decoder::EventStreamDecoder;
+use crate::kiro::parser::decoder::{DecoderStats, EventStreamDecoder};
+use crate::kiro::frame_ring::FrameRing;
+use crate::kiro::scheduler::{self, Permit, Priority, Scheduler};
+use crate::model::config::{
+    CodeReferenceMode, MaxTokensConfig, MaxTokensPolicy, SseProfile, StreamConfig,
+    ToolInputValidation,
+};
 use crate::token;
 use axum::{
     Json as JsonExtractor,
     body::Body,
-    extract::State,
-    http::{StatusCode, header},
+    extract::{Extension, State},
+    http::{HeaderMap, StatusCode, header},
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
 use futures::{Stream, StreamExt, stream};
 use serde_json::json;
-use std::time::Duration;
+use std::future::Future;
+use std::time::{Duration, Instant};
 use tokio::time::interval;
+use tracing::Instrument;
 use uuid::Uuid;
 
-use super::converter::{ConversionError, convert_request};
+use super::api_keys;
+use super::backpressure;
+use super::branch::{self, BranchRecorder};
+use super::budget;
+use super::compat::{self, CompatShim};
+use super::converter::{
+    ModelDowngrade, detect_model_downgrade, extract_session_id, generate_thinking_prefix,
+    injected_policy_strings,
+};
+use super::conversation::ConversationTracker;
+use super::delivery::{Delivery, EventStream};
+use super::diff_preview::DiffPreviewer;
+use super::echo_filter::EchoFilter;
+use super::error::ApiError;
 use super::middleware::AppState;
-use super::stream::{SseEvent, StreamContext};
-use super::types::{CountTokensRequest, CountTokensResponse, ErrorResponse, MessagesRequest, Model, ModelsResponse, OutputConfig, Thinking};
+use super::models::{self, available_models};
+use super::pricing;
+use super::profile;
+use super::references::ReferenceCollector;
+use super::refusal;
+use super::request_options::RequestOptions;
+use super::server_tools::{self, DeclaredServerTools, ServerToolUsage};
+use super::sse_transcript::{TRANSCRIPT_HEADER, TranscriptWriter};
+use super::stop_sequence::StopSequenceMatcher;
+use super::stream::{self as sse, SseEncoder, SseEvent, StreamContext, UsageReporter};
+use super::tool_ids::ToolUseIds;
+use super::tool_validation::{self, ToolInputValidator};
+use super::types::{
+    CountTokensRequest, CountTokensResponse, MessagesRequest, ModelsResponse, OutputConfig,
+    StreamOptions, Thinking,
+};
 use super::websearch;
 
-/// 将 KiroProvider 错误映射为 HTTP 响应
-fn map_provider_error(err: Error) -> Response {
-    let err_str = err.to_string();
-
-    // 上下文窗口满了（对话历史累积超出模型上下文窗口限制）
-    if err_str.contains("CONTENT_LENGTH_EXCEEDS_THRESHOLD") {
-        tracing::warn!(error = %err, "上游拒绝请求：上下文窗口已满（不应重试）");
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse::new(
-                "invalid_request_error",
-                "Context window is full. Reduce conversation history, system prompt, or tools.",
-            )),
-        )
-            .into_response();
-    }
-
-    // 单次输入太长（请求体本身超出上游限制）
-    if err_str.contains("Input is too long") {
-        tracing::warn!(error = %err, "上游拒绝请求：输入过长（不应重试）");
-        return (
-            StatusCode::BAD_REQUEST,
-            Json(ErrorResponse::new(
-                "invalid_request_error",
-                "Input is too long. Reduce the size of your messages.",
-            )),
-        )
-            .into_response();
-    }
-    tracing::error!("Kiro API 调用失败: {}", err);
-    (
-        StatusCode::BAD_GATEWAY,
-        Json(ErrorResponse::new(
-            "api_error",
-            format!("上游 API 调用失败: {}", err),
-        )),
-    )
-        .into_response()
-}
-
 /// GET /v1/models
 ///
 /// 返回可用的模型列表
 pub async fn get_models() -> impl IntoResponse {
     tracing::info!("Received GET /v1/models request");
 
-    let models = vec![
-        Model {
-            id: "claude-sonnet-4-5-20250929".to_string(),
-            object: "model".to_string(),
-            created: 1727568000,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-5-20250929-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1727568000,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101".to_string(),
-            object: "model".to_string(),
-            created: 1730419200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-5-20251101-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1730419200,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-6".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-sonnet-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Sonnet 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-6".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-opus-4-6-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1770314400,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Opus 4.6 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001".to_string(),
-            object: "model".to_string(),
-            created: 1727740800,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-        Model {
-            id: "claude-haiku-4-5-20251001-thinking".to_string(),
-            object: "model".to_string(),
-            created: 1727740800,
-            owned_by: "anthropic".to_string(),
-            display_name: "Claude Haiku 4.5 (Thinking)".to_string(),
-            model_type: "chat".to_string(),
-            max_tokens: 32000,
-        },
-    ];
-
     Json(ModelsResponse {
         object: "list".to_string(),
-        data: models,
+        data: available_models(),
     })
 }
 
@@ -177,7 +83,20 @@ pub async fn get_models() -> impl IntoResponse {
 /// 创建消息（对话）
 pub async fn post_messages(
     State(state): State<AppState>,
-    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    TracedJson(payload): TracedJson<MessagesRequest>,
+) -> Response {
+    create_message(state, identity, headers, payload, Delivery::Sse).await
+}
+
+/// 按 `/v1/messages` 的流程处理请求，流式响应按 `delivery` 下发
+pub async fn create_message(
+    state: AppState,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    mut payload: MessagesRequest,
+    delivery: Delivery,
 ) -> Response {
     tracing::info!(
         model = %payload.model,
@@ -186,24 +105,61 @@ pub async fn post_messages(
         message_count = %payload.messages.len(),
         "Received POST /v1/messages request"
     );
+    let state = match apply_identity(state, identity, &payload.model) {
+        Ok(state) => state,
+        Err(e) => return e.into_response(),
+    };
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
         Some(p) => p.clone(),
         None => {
             tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
+            return ApiError::ServiceUnavailable("Kiro API provider not configured".to_string())
                 .into_response();
         }
     };
 
+    let request_options = match RequestOptions::from_headers(&headers) {
+        Ok(options) => options,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    payload.seed = match request_seed(&headers, payload.seed) {
+        Ok(seed) => seed,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    let converter_version = match state
+        .canary
+        .select(request_options.converter.as_deref(), payload.seed)
+    {
+        Ok(version) => version,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    let profile_arn = match profile::resolve(&state, &headers) {
+        Ok(arn) => arn,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+
+    let pinned = apply_model_pin(&state, request_options.unpin_model, &mut payload);
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
+    let budget_downgrade = apply_budget(&state, &mut payload).or(pinned);
+    let max_tokens_clamped = match apply_max_tokens(&state.config.max_tokens, &mut payload) {
+        Ok(clamped) => clamped,
+        Err(e) => return e.into_response(),
+    };
+    payload.accept_language = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    // 沿 parent_message_id 指定的分支补全历史
+    let branch = match resolve_branch(&state, &mut payload) {
+        Ok(branch) => branch,
+        Err(message) => {
+            tracing::warn!("会话分支解析失败: {}", message);
+            return ApiError::InvalidRequest(message).into_response();
+        }
+    };
 
     // 检查是否为 WebSearch 请求
     if websearch::has_web_search_tool(&payload) {
@@ -219,59 +175,84 @@ pub async fn post_messages(
                 payload.tools.clone(),
             ) as i32;
 
-            return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+            let compat = CompatShim::new(
+                state.config.stream.v1_compat,
+                &state.config.stream.compat_rules,
+            );
+            let usage = request_usage(&state, &payload.model, None);
+            return websearch::handle_websearch_request(
+                provider,
+                &payload,
+                input_tokens,
+                compat,
+                usage,
+                delivery,
+            )
+            .await;
         } else {
             tracing::debug!("包含 WebSearch 工具但非搜索请求，过滤后走普通对话路径");
             // 过滤掉 web_search 工具，避免传给 Kiro 后端
-            payload.tools = payload.tools.map(|tools| {
-                tools.into_iter().filter(|t| !t.is_web_search() && t.name != "web_search").collect()
-            });
+            payload.tools = payload
+                .tools
+                .map(|tools| tools.into_iter().filter(|t| !t.is_web_search()).collect());
         }
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    // 转换请求（按 x-kiro-converter 或灰度比例选择转换器版本）
+    let conversion_result = match tracing::info_span!("convert_request").in_scope(|| {
+        state
+            .canary
+            .convert(&payload, converter_version)
+    }) {
         Ok(result) => result,
         Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
             tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+            if let Some(store) = &state.dead_letters {
+                store.record("/v1/messages", &state.api_key, &payload, &e);
+            }
+            return ApiError::from(e).into_response();
         }
     };
 
+    let conversation = conversation_tracker(
+        &state,
+        &payload,
+        conversion_result.conversation_state.conversation_id.clone(),
+    );
+    let downgrade = budget_downgrade.or_else(|| model_downgrade_notice(&state, &payload));
+
     // 构建 Kiro 请求
     let kiro_request = KiroRequest {
         conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+        profile_arn: profile_arn.clone(),
     };
 
     let request_body = match serde_json::to_string(&kiro_request) {
         Ok(body) => body,
         Err(e) => {
             tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
+            return ApiError::from(e).into_response();
         }
     };
+    keep_session_history(&state, &payload, kiro_request, request_body.len());
+
+    tracing::debug!("Kiro request body: {}", redact::body(&request_body));
+    let request_log = state.request_logs.as_ref().map(|store| {
+        store.start(
+            "/v1/messages",
+            &state.api_key,
+            &payload,
+            converter_version,
+            &request_body,
+        )
+    });
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    let tool_validator = ToolInputValidator::new(
+        state.config.stream.tool_input_validation,
+        payload.tools.as_deref(),
+    );
+    let server_tools = DeclaredServerTools::from_tools(payload.tools.as_deref());
+    let thinking_directive = generate_thinking_prefix(&payload);
 
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
@@ -287,21 +268,441 @@ pub async fn post_messages(
         .as_ref()
         .map(|t| t.is_enabled())
         .unwrap_or(false);
+    // thinking 呈现为文本或模型关闭了 thinking 提取时不提取 thinking 块，`<thinking>` 标签原样保留在文本中
+    let extract_thinking = thinking_enabled
+        && request_options.extract_thinking()
+        && models::extracts_thinking(&payload.model);
+
+    let options = ResponseOptions {
+        stream: &state.config.stream,
+        profile: request_options.profile(state.config.stream.v1_profile),
+        compat: CompatShim::new(
+            state.config.stream.v1_compat,
+            &state.config.stream.compat_rules,
+        ),
+        branch,
+        usage_reporter: usage_reporter(payload.stream_options.as_ref(), &state.config.stream),
+        conversation,
+        downgrade,
+        deadline: request_options.deadline,
+        thinking_budget: thinking_budget(&state.config.stream, payload.thinking.as_ref()),
+        stop_sequences: payload.stop_sequences.clone().unwrap_or_default(),
+        max_tokens: payload.max_tokens,
+        max_tokens_clamped,
+        attempts_header: state.config.upstream.attempts_header,
+        tool_validator,
+        server_tools,
+        thinking_directive,
+        usage: request_usage(&state, &payload.model, profile_arn.as_deref()),
+        scheduler: state.scheduler.clone(),
+        priority: request_options.priority,
+        coalesce_deltas: request_options.coalesce_deltas(),
+        transcript: state
+            .sse_transcripts
+            .as_ref()
+            .filter(|_| payload.stream)
+            .map(|store| store.start("/v1/messages", &payload.model)),
+        delivery,
+    };
 
-    if payload.stream {
+    let response = if payload.stream {
         // 流式响应
         handle_stream_request(
             provider,
             &request_body,
             &payload.model,
             input_tokens,
-            thinking_enabled,
+            extract_thinking,
+            options,
         )
         .await
     } else {
         // 非流式响应
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+        handle_non_stream_request(
+            provider,
+            &request_body,
+            &payload.model,
+            input_tokens,
+            options,
+        )
+        .await
+    };
+    match request_log {
+        Some(log) => log.finish(response).await,
+        None => response,
+    }
+}
+
+/// 响应处理选项
+struct ResponseOptions<'a> {
+    /// 流式响应配置
+    stream: &'a StreamConfig,
+    /// 当前端点的 SSE 严格程度
+    profile: SseProfile,
+    /// 当前端点的 SSE 兼容层
+    compat: Option<CompatShim>,
+    /// 会话分支记录器（启用分支时）
+    branch: Option<BranchRecorder>,
+    /// 周期性用量事件触发器（请求设置了 `stream_options.include_usage` 时）
+    usage_reporter: Option<UsageReporter>,
+    /// 上游会话 ID 核对器
+    conversation: ConversationTracker,
+    /// 模型映射降级提示
+    downgrade: Option<ModelDowngrade>,
+    /// 请求截止时间
+    deadline: Option<tokio::time::Instant>,
+    /// 流式 thinking 输出预算（tokens）
+    thinking_budget: Option<i32>,
+    /// 请求的 stop_sequences
+    stop_sequences: Vec<String>,
+    /// 请求的 max_tokens
+    max_tokens: i32,
+    /// max_tokens 被下调到模型输出上限时请求的原值
+    max_tokens_clamped: Option<i32>,
+    /// 是否添加 `x-kiro-upstream-attempts` 响应头
+    attempts_header: bool,
+    /// 工具输入校验器（启用 `toolInputValidation` 时）
+    tool_validator: Option<ToolInputValidator>,
+    /// 请求中声明的服务端工具
+    server_tools: DeclaredServerTools,
+    /// 注入到请求中的 thinking 指令前缀（模型可能在输出中回显）
+    thinking_directive: Option<String>,
+    /// 用量报表记录句柄
+    usage: RequestUsage,
+    /// 上游请求调度器（限制并发时）
+    scheduler: Option<std::sync::Arc<Scheduler>>,
+    /// 请求优先级
+    priority: Priority,
+    /// 发送队列写满时是否合并增量事件
+    coalesce_deltas: bool,
+    /// SSE 会话记录器（启用会话记录的流式请求）
+    transcript: Option<TranscriptWriter>,
+    /// 流式响应的下发方式
+    delivery: Delivery,
+}
+
+impl ResponseOptions<'_> {
+    /// 获取调度名额（未限制并发时为 None）
+    async fn acquire(&self) -> Option<Permit> {
+        match &self.scheduler {
+            Some(scheduler) => Some(scheduler.acquire(self.priority).await),
+            None => None,
+        }
+    }
+}
+
+/// 回显过滤器（未启用 `stripPolicyEcho` 时为 None）
+fn echo_filter(config: &StreamConfig, thinking_directive: Option<&str>) -> Option<EchoFilter> {
+    if !config.strip_policy_echo {
+        return None;
+    }
+    let filter = EchoFilter::new(injected_policy_strings());
+    Some(match thinking_directive {
+        Some(prefix) => filter.with_thinking_directive(prefix),
+        None => filter,
+    })
+}
+
+/// 需要强制执行的 thinking 预算
+///
+/// 仅 `enabled` 类型由客户端显式指定预算；adaptive 由模型自行决定，不做截断
+fn thinking_budget(config: &StreamConfig, thinking: Option<&Thinking>) -> Option<i32> {
+    if !config.enforce_thinking_budget {
+        return None;
+    }
+    thinking
+        .filter(|t| t.thinking_type == "enabled")
+        .map(|t| t.budget_tokens)
+}
+
+/// 解析 `x-kiro-seed` 请求头，优先于请求体中的 `seed` 字段
+fn request_seed(headers: &HeaderMap, body: Option<u64>) -> Result<Option<u64>, String> {
+    let Some(value) = headers.get(SEED_HEADER) else {
+        return Ok(body);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|v| v.trim().parse().ok())
+        .map(Some)
+        .ok_or_else(|| format!("{} 必须是非负整数", SEED_HEADER))
+}
+
+/// 在截止时间前等待 future 完成，超时返回 None
+async fn until_deadline<F: Future>(
+    deadline: Option<tokio::time::Instant>,
+    future: F,
+) -> Option<F::Output> {
+    match deadline {
+        Some(deadline) => tokio::time::timeout_at(deadline, future).await.ok(),
+        None => Some(future.await),
+    }
+}
+
+/// 等待截止时间到达；未设置截止时间时永不完成
+async fn sleep_until_deadline(deadline: Option<tokio::time::Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// 请求的用量记录句柄（按 `pricing` 配置估算费用）
+fn request_usage(state: &AppState, model: &str, profile_arn: Option<&str>) -> RequestUsage {
+    RequestUsage::new(&state.api_key, model)
+        .with_profile(profile_arn)
+        .with_price(pricing::price_for(&state.config.pricing, model))
+}
+
+/// 根据请求的 `stream_options` 创建周期性用量事件触发器
+///
+/// 请求未指定的间隔使用配置中的默认值
+fn usage_reporter(options: Option<&StreamOptions>, config: &StreamConfig) -> Option<UsageReporter> {
+    let options = options.filter(|o| o.include_usage)?;
+    UsageReporter::new(
+        options
+            .usage_interval_secs
+            .unwrap_or(config.usage_interval_secs),
+        options
+            .usage_interval_blocks
+            .unwrap_or(config.usage_interval_blocks),
+    )
+}
+
+/// 处理 `parent_message_id` 分支扩展
+///
+/// 启用分支时沿指定分支补全历史消息，并返回登记本轮回复的记录器；
+/// 消息树按调用方的 API Key 隔离。缺少 session 或分支不存在时返回错误信息
+fn resolve_branch(
+    state: &AppState,
+    payload: &mut MessagesRequest,
+) -> Result<Option<BranchRecorder>, String> {
+    if !state.config.converter.conversation_branches {
+        if payload.parent_message_id.is_some() {
+            tracing::warn!("未启用 converter.conversationBranches，忽略 parent_message_id");
+        }
+        return Ok(None);
+    }
+
+    let session_id = session_id_of(payload);
+    let Some(session_id) = session_id else {
+        if payload.parent_message_id.is_some() {
+            return Err("parent_message_id requires a session in metadata.user_id".to_string());
+        }
+        return Ok(None);
+    };
+
+    // apply_identity 之后 state.api_key 即调用方的 Key
+    let owner = &state.api_key;
+    let store = &state.session_store;
+    let (parent_id, new_messages) = match &payload.parent_message_id {
+        Some(parent_id) => {
+            let Some(mut history) = store.branch_path(&session_id, owner, parent_id) else {
+                return Err(format!("Unknown parent_message_id: {}", parent_id));
+            };
+            tracing::debug!(
+                parent_message_id = %parent_id,
+                history_len = history.len(),
+                "沿会话分支重建历史"
+            );
+            let new_messages = payload.messages.clone();
+            history.append(&mut payload.messages);
+            payload.messages = history;
+            (Some(parent_id.clone()), new_messages)
+        }
+        // 未指定分支：接着最近一轮继续时只登记新增的消息
+        None => match store.branch_continuation(&session_id, owner, &payload.messages) {
+            Some((latest, len)) => (Some(latest), payload.messages[len..].to_vec()),
+            None => (None, payload.messages.clone()),
+        },
+    };
+
+    Ok(Some(BranchRecorder::new(
+        store.clone(),
+        session_id,
+        owner.clone(),
+        parent_id,
+        new_messages,
+    )))
+}
+
+/// 从 metadata.user_id 中提取客户端会话 ID
+fn session_id_of(payload: &MessagesRequest) -> Option<String> {
+    payload
+        .metadata
+        .as_ref()
+        .and_then(|m| m.user_id.as_deref())
+        .and_then(extract_session_id)
+}
+
+/// 按认证中间件确定的调用方身份处理请求
+///
+/// 模型不在该身份的白名单内时拒绝；用量统计、profile 选择和预算按该身份的 Key 进行
+fn apply_identity(
+    state: AppState,
+    identity: Option<Extension<KeyIdentity>>,
+    model: &str,
+) -> Result<AppState, ApiError> {
+    let Some(Extension(identity)) = identity else {
+        return Ok(state);
+    };
+    if !api_keys::allows_model(&identity, model) {
+        tracing::warn!(api_key = %identity.name, model = %model, "模型不在 API Key 的白名单内");
+        return Err(ApiError::PermissionDenied(format!(
+            "API key '{}' is not allowed to use model {}",
+            identity.name, model
+        )));
+    }
+    Ok(state.with_api_key(identity.api_key))
+}
+
+/// 超出每日 token 预算时改用降级模型，返回降级提示（每个请求都提示）
+fn apply_budget(state: &AppState, payload: &mut MessagesRequest) -> Option<ModelDowngrade> {
+    let model = budget::downgrade(&state.config.budgets, &state.api_key, &payload.model)?;
+    let requested = std::mem::replace(&mut payload.model, model);
+    tracing::warn!(
+        requested = %requested,
+        model = %payload.model,
+        "超出每日 token 预算，降级模型"
+    );
+    Some(ModelDowngrade {
+        requested,
+        served: models::resolve(&payload.model).unwrap_or_else(|| payload.model.clone()),
+        reason: Some("budget"),
+    })
+}
+
+/// 保存会话最近一次发送给上游的历史（`sessions.keepHistory`），供会话渲染接口使用
+///
+/// 请求体已序列化，历史直接移入会话存储，不再复制
+fn keep_session_history(
+    state: &AppState,
+    payload: &MessagesRequest,
+    kiro_request: KiroRequest,
+    body_len: usize,
+) {
+    if !state.config.sessions.keep_history {
+        return;
+    }
+    if let Some(session_id) = session_id_of(payload) {
+        state.session_store.put_history(
+            &session_id,
+            kiro_request.conversation_state.history,
+            body_len,
+        );
+    }
+}
+
+/// 会话固定模型（`sessions.pinModel`）
+///
+/// 同一会话的后续请求改用首轮的模型名，客户端中途切换到映射为其他 Kiro 模型的模型名时
+/// 返回替换提示；`unpin` 为 true 时改为固定到本次请求的模型
+fn apply_model_pin(
+    state: &AppState,
+    unpin: bool,
+    payload: &mut MessagesRequest,
+) -> Option<ModelDowngrade> {
+    if !state.config.sessions.pin_model {
+        return None;
+    }
+    let session_id = session_id_of(payload)?;
+    let pinned = state
+        .session_store
+        .pin_model(&session_id, &payload.model, unpin);
+    let served = models::resolve(&pinned).unwrap_or_else(|| pinned.clone());
+    if models::resolve(&payload.model).as_ref() == Some(&served) || payload.model == pinned {
+        return None;
+    }
+
+    let requested = std::mem::replace(&mut payload.model, pinned);
+    tracing::info!(
+        requested = %requested,
+        model = %payload.model,
+        session_id = %session_id,
+        "会话已固定模型，沿用首轮的模型"
+    );
+    Some(ModelDowngrade {
+        requested,
+        served,
+        reason: Some("pinned"),
+    })
+}
+
+/// 按配置补全缺省的 max_tokens，并限制在模型的输出上限内
+///
+/// 超出上限且按配置下调时返回请求的原值；参数无效或按配置拒绝时返回与官方 API 相同的错误信息
+fn apply_max_tokens(
+    config: &MaxTokensConfig,
+    payload: &mut MessagesRequest,
+) -> Result<Option<i32>, ApiError> {
+    if payload.max_tokens == 0 {
+        payload.max_tokens = config
+            .default
+            .ok_or_else(|| ApiError::InvalidRequest("max_tokens: Field required".to_string()))?;
+    }
+    if payload.max_tokens < 1 {
+        return Err(ApiError::InvalidRequest(
+            "max_tokens: Input should be greater than or equal to 1".to_string(),
+        ));
+    }
+    let Some(limit) = models::max_output_tokens(&payload.model) else {
+        return Ok(None);
+    };
+    if payload.max_tokens <= limit {
+        return Ok(None);
+    }
+    match config.over_limit {
+        MaxTokensPolicy::Reject => Err(ApiError::InvalidRequest(format!(
+            "max_tokens: {} > {}, which is the maximum allowed number of output tokens for {}",
+            payload.max_tokens, limit, payload.model
+        ))),
+        MaxTokensPolicy::Clamp => {
+            tracing::debug!(
+                requested = payload.max_tokens,
+                limit,
+                model = %payload.model,
+                "max_tokens 超出模型输出上限，已下调"
+            );
+            Ok(Some(std::mem::replace(&mut payload.max_tokens, limit)))
+        }
+    }
+}
+
+/// 检测模型映射降级，同一会话对同一模型只提示一次
+fn model_downgrade_notice(state: &AppState, payload: &MessagesRequest) -> Option<ModelDowngrade> {
+    if !state.config.converter.model_downgrade_notice {
+        return None;
     }
+    let downgrade = detect_model_downgrade(&payload.model)?;
+
+    let session_id = session_id_of(payload);
+    if let Some(id) = &session_id
+        && !state
+            .session_store
+            .mark_downgrade_notified(id, &downgrade.requested)
+    {
+        return None;
+    }
+
+    tracing::warn!(
+        requested = %downgrade.requested,
+        served = %downgrade.served,
+        session_id = ?session_id,
+        "模型映射降级"
+    );
+    Some(downgrade)
+}
+
+/// 创建上游会话 ID 核对器
+///
+/// `sent` 为请求中发送给 Kiro 的 conversationId
+fn conversation_tracker(
+    state: &AppState,
+    payload: &MessagesRequest,
+    sent: String,
+) -> ConversationTracker {
+    let session_id = session_id_of(payload);
+    ConversationTracker::new(state.session_store.clone(), session_id, sent)
 }
 
 /// 处理流式请求
@@ -311,125 +712,372 @@ async fn handle_stream_request(
     model: &str,
     input_tokens: i32,
     thinking_enabled: bool,
+    mut options: ResponseOptions<'_>,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api_stream(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
+    let started_at = Instant::now();
+
+    // 按优先级获取调度名额后调用 Kiro API（支持多凭据故障转移），截止时间到达时放弃等待
+    let upstream = async {
+        let permit = options.acquire().await;
+        provider
+            .call_api_stream(request_body)
+            .instrument(tracing::info_span!("kiro_api_call", stream = true))
+            .await
+            .map(|resp| (resp, permit))
+    };
+    let response = match until_deadline(options.deadline, upstream).await {
+        Some(Ok(resp)) => Some(resp),
+        Some(Err(e)) => return ApiError::from(e).into_response(),
+        None => None,
     };
+    let attempts = response
+        .as_ref()
+        .and_then(|(r, _)| r.extensions().get::<UpstreamAttempts>().cloned());
 
     // 创建流处理上下文
-    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled);
+    let mut ctx = StreamContext::new_with_thinking(model, input_tokens, thinking_enabled)
+        .with_profile(options.profile)
+        .with_server_tools(options.server_tools)
+        .with_conversation_tracker(options.conversation);
+    if let Some(filter) = echo_filter(options.stream, options.thinking_directive.as_deref()) {
+        ctx = ctx.with_echo_filter(filter);
+    }
+    if let Some(reporter) = options.usage_reporter {
+        ctx = ctx.with_usage_reporter(reporter);
+    }
+    if let Some(budget) = options.thinking_budget {
+        ctx = ctx.with_thinking_budget(budget);
+    }
+    if options.stream.code_references == CodeReferenceMode::Append {
+        ctx = ctx.with_code_references();
+    }
+    if !options.stop_sequences.is_empty() {
+        ctx = ctx.with_stop_sequences(&options.stop_sequences);
+    }
+    if let Some(validator) = options.tool_validator {
+        ctx = ctx.with_tool_validator(validator);
+    }
+    if options.stream.edit_diff_preview.enabled {
+        ctx = ctx.with_diff_preview(DiffPreviewer::new(&options.stream.edit_diff_preview.tools));
+    }
+    if let Some(classifier) = refusal::classifier() {
+        ctx = ctx.with_refusal_detection(classifier);
+    }
+    ctx = ctx.with_max_tokens(options.max_tokens);
+    ctx = ctx.with_tool_input_chunking(options.stream.tool_input_chunk_bytes);
+    ctx = ctx.with_reorder_window(options.stream.reorder_window);
+    ctx = ctx.with_request_usage(options.usage);
 
     // 生成初始事件
     let initial_events = ctx.generate_initial_events();
 
     // 创建 SSE 流
-    let stream = create_sse_stream(response, ctx, initial_events);
+    let stream_config = options.stream;
+    let mut events = match response {
+        Some((response, permit)) => create_sse_stream(
+            response,
+            permit,
+            ctx,
+            initial_events,
+            stream_config.decoder_stats.then_some(started_at),
+            options.deadline,
+        )
+        .boxed(),
+        None => {
+            tracing::warn!("上游响应前已到达请求截止时间");
+            ctx.state_manager.mark_deadline_exceeded();
+            let final_events = ctx.generate_final_events();
+            stream::iter(initial_events.into_iter().chain(final_events)).boxed()
+        }
+    };
+    if let Some(recorder) = options.branch {
+        events = branch::record_stream(events, recorder).boxed();
+    }
+    let transcript = options.transcript.take();
+    let transcript_id = transcript.as_ref().map(|t| t.id().to_string());
+    let mut response = match options.delivery {
+        Delivery::Sse => sse_body_response(
+            events,
+            stream_config,
+            options.downgrade.as_ref(),
+            options.coalesce_deltas,
+            options.compat.clone(),
+            transcript,
+        ),
+        // 不编码：事件流交给调用方，会话记录仍按 SSE 编码保存
+        Delivery::Events => EventStream::response(match transcript {
+            Some(mut transcript) => {
+                let mut encoder = SseEncoder::default();
+                events
+                    .inspect(move |event| transcript.write(&encoder.encode(event)))
+                    .boxed()
+            }
+            None => events,
+        }),
+    };
+    insert_downgrade_header(&mut response, options.downgrade.as_ref());
+    insert_max_tokens_header(
+        &mut response,
+        options.max_tokens_clamped,
+        options.max_tokens,
+    );
+    if options.attempts_header {
+        insert_attempts_header(&mut response, attempts.as_ref());
+    }
+    if let Some(id) = transcript_id
+        && let Ok(value) = header::HeaderValue::from_str(&id)
+    {
+        response.headers_mut().insert(TRANSCRIPT_HEADER, value);
+    }
+    response
+}
+
+/// 把事件流经端点的 SSE 兼容层编码为响应体
+fn sse_body_response(
+    events: futures::stream::BoxStream<'static, SseEvent>,
+    stream_config: &StreamConfig,
+    downgrade: Option<&ModelDowngrade>,
+    coalesce_deltas: bool,
+    compat: Option<CompatShim>,
+    transcript: Option<TranscriptWriter>,
+) -> Response {
+    // 模型降级提示以 SSE 注释形式放在流的开头，客户端解析器会忽略注释行
+    let notice = downgrade.map(|d| {
+        Ok::<_, Infallible>(Bytes::from(format!(
+            ": model substituted: {}\n\n",
+            d.header_value()
+        )))
+    });
+    let bytes = if stream_config.outgoing_queue_size > 0 {
+        stream::iter(notice)
+            .chain(backpressure::bounded(
+                events,
+                stream_config.outgoing_queue_size,
+                coalesce_deltas,
+                compat,
+            ))
+            .boxed()
+    } else {
+        let mut encoder = SseEncoder::default();
+        stream::iter(notice)
+            .chain(
+                events
+                    .filter_map(move |e| std::future::ready(compat::apply(compat.as_ref(), e)))
+                    .map(move |e| Ok::<_, Infallible>(encoder.encode(&e))),
+            )
+            .boxed()
+    };
+    // 记录下发给客户端的字节流，记录器随响应体一起释放
+    let body = match transcript {
+        Some(mut transcript) => Body::from_stream(bytes.inspect(move |chunk| {
+            if let Ok(chunk) = chunk {
+                transcript.write(chunk);
+            }
+        })),
+        None => Body::from_stream(bytes),
+    };
 
-    // 返回 SSE 响应
     Response::builder()
         .status(StatusCode::OK)
         .header(header::CONTENT_TYPE, "text/event-stream")
         .header(header::CACHE_CONTROL, "no-cache")
         .header(header::CONNECTION, "keep-alive")
-        .body(Body::from_stream(stream))
+        .body(body)
         .unwrap()
 }
 
+/// 添加模型降级提示响应头
+fn insert_downgrade_header(response: &mut Response, downgrade: Option<&ModelDowngrade>) {
+    if let Some(downgrade) = downgrade
+        && let Ok(value) = header::HeaderValue::from_str(&downgrade.header_value())
+    {
+        response
+            .headers_mut()
+            .insert("x-kiro-model-substitution", value);
+    }
+}
+
+/// max_tokens 被下调时添加 `x-kiro-max-tokens-clamped` 响应头
+fn insert_max_tokens_header(response: &mut Response, requested: Option<i32>, max_tokens: i32) {
+    if let Some(requested) = requested
+        && let Ok(value) = header::HeaderValue::from_str(&format!(
+            "requested={}; applied={}",
+            requested, max_tokens
+        ))
+    {
+        response
+            .headers_mut()
+            .insert("x-kiro-max-tokens-clamped", value);
+    }
+}
+
+/// 添加上游尝试次数与失败原因响应头
+fn insert_attempts_header(response: &mut Response, attempts: Option<&UpstreamAttempts>) {
+    if let Some(attempts) = attempts
+        && let Ok(value) = header::HeaderValue::from_str(&attempts.header_value())
+    {
+        response
+            .headers_mut()
+            .insert("x-kiro-upstream-attempts", value);
+    }
+}
+
+/// 采样种子扩展头
+const SEED_HEADER: &str = "x-kiro-seed";
+
 /// Ping 事件间隔（25秒）
 const PING_INTERVAL_SECS: u64 = 25;
 
-/// 创建 ping 事件的 SSE 字符串
-fn create_ping_sse() -> Bytes {
-    Bytes::from("event: ping\ndata: {\"type\": \"ping\"}\n\n")
+/// 创建 ping 事件
+fn create_ping_event() -> SseEvent {
+    SseEvent::new("ping", json!({ "type": "ping" }))
+}
+
+/// 创建上游解码统计事件（kiro_stats）
+fn create_decoder_stats_event(stats: DecoderStats, upstream_duration: Duration) -> SseEvent {
+    SseEvent::new(
+        "kiro_stats",
+        json!({
+            "type": "kiro_stats",
+            "frames_decoded": stats.frames_decoded,
+            "bytes_received": stats.bytes_received,
+            "bytes_skipped": stats.bytes_skipped,
+            "parse_errors": stats.parse_errors,
+            "upstream_duration_ms": upstream_duration.as_millis() as u64
+        }),
+    )
+}
+
+/// 生成流结束时的最终事件
+///
+/// `stats_since` 不为空时，在 `message_stop` 之前插入解码统计事件
+fn final_sse_events(
+    ctx: &mut StreamContext,
+    decoder: &EventStreamDecoder,
+    stats_since: Option<Instant>,
+) -> Vec<SseEvent> {
+    let mut final_events = ctx.generate_final_events();
+    if let Some(started_at) = stats_since {
+        let stats_event = create_decoder_stats_event(decoder.stats(), started_at.elapsed());
+        let pos = final_events
+            .iter()
+            .position(|e| e.event == "message_stop")
+            .unwrap_or(final_events.len());
+        final_events.insert(pos, stats_event);
+    }
+    final_events
 }
 
 /// 创建 SSE 事件流
+///
+/// `stats_since` 为上游请求开始时间，启用解码统计时传入；
+/// 到达 `deadline` 或命中 stop sequence 时关闭已打开的块并结束流，丢弃上游响应以取消生成
 fn create_sse_stream(
     response: reqwest::Response,
+    permit: Option<Permit>,
     ctx: StreamContext,
     initial_events: Vec<SseEvent>,
-) -> impl Stream<Item = Result<Bytes, Infallible>> {
+    stats_since: Option<Instant>,
+    deadline: Option<tokio::time::Instant>,
+) -> impl Stream<Item = SseEvent> + Send + 'static {
     // 先发送初始事件
-    let initial_stream = stream::iter(
-        initial_events
-            .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
-    );
+    let initial_stream = stream::iter(initial_events);
 
     // 然后处理 Kiro 响应流，同时每25秒发送 ping 保活
-    let body_stream = response.bytes_stream();
+    // 流存续期间持有调度名额，batch 流在竞争时降速
+    let body_stream = match permit {
+        Some(permit) => scheduler::paced(response.bytes_stream(), permit).boxed(),
+        None => response.bytes_stream().boxed(),
+    };
+    // 守卫与上游响应流一起存续：客户端断开时响应体被丢弃，上游连接随之关闭
+    let guard = UpstreamStreamGuard::new();
 
     let processing_stream = stream::unfold(
-        (body_stream, ctx, EventStreamDecoder::new(), false, interval(Duration::from_secs(PING_INTERVAL_SECS))),
-        |(mut body_stream, mut ctx, mut decoder, finished, mut ping_interval)| async move {
-            if finished {
-                return None;
-            }
+        (body_stream, ctx, EventStreamDecoder::new(), FrameRing::for_request(), guard, interval(Duration::from_secs(PING_INTERVAL_SECS))),
+        move |(mut body_stream, mut ctx, mut decoder, mut frames, guard, mut ping_interval)| {
+            let span = guard.span.clone();
+            async move {
+                if guard.finished {
+                    return None;
+                }
 
-            // 使用 select! 同时等待数据和 ping 定时器
-            tokio::select! {
-                // 处理数据流
-                chunk_result = body_stream.next() => {
-                    match chunk_result {
-                        Some(Ok(chunk)) => {
-                            // 解码事件
-                            if let Err(e) = decoder.feed(&chunk) {
-                                tracing::warn!("缓冲区溢出: {}", e);
-                            }
+                // 使用 select! 同时等待数据和 ping 定时器
+                tokio::select! {
+                    // 处理数据流
+                    chunk_result = body_stream.next() => {
+                        match chunk_result {
+                            Some(Ok(chunk)) => {
+                                // 解码事件
+                                if let Err(e) = decoder.feed(&chunk) {
+                                    tracing::warn!("缓冲区溢出: {}", e);
+                                }
 
-                            let mut events = Vec::new();
-                            for result in decoder.decode_iter() {
-                                match result {
-                                    Ok(frame) => {
-                                        if let Ok(event) = Event::from_frame(frame) {
-                                            let sse_events = ctx.process_kiro_event(&event);
-                                            events.extend(sse_events);
+                                let mut events = Vec::new();
+                                for result in decoder.decode_iter() {
+                                    match result {
+                                        Ok(frame) => {
+                                            frames.record(&frame);
+                                            if let Ok(event) = Event::from_frame(frame) {
+                                                if is_upstream_failure(&event) {
+                                                    frames.dump("上游返回错误");
+                                                }
+                                                let sse_events = ctx.process_kiro_event(&event);
+                                                events.extend(sse_events);
+                                            }
+                                        }
+                                        Err(e) => {
+                                            tracing::warn!("解码事件失败: {}", e);
                                         }
-                                    }
-                                    Err(e) => {
-                                        tracing::warn!("解码事件失败: {}", e);
                                     }
                                 }
-                            }
+                                events.extend(ctx.poll_usage_event());
 
-                            // 转换为 SSE 字节流
-                            let bytes: Vec<Result<Bytes, Infallible>> = events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
+                                // 上游返回错误事件：已发送 error 事件，结束流
+                                if ctx.failed() {
+                                    return Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard.finish(), ping_interval)));
+                                }
 
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
-                        }
-                        Some(Err(e)) => {
-                            tracing::error!("读取响应流失败: {}", e);
-                            // 发送最终事件并结束
-                            let final_events = ctx.generate_final_events();
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
-                        }
-                        None => {
-                            // 流结束，发送最终事件
-                            let final_events = ctx.generate_final_events();
-                            let bytes: Vec<Result<Bytes, Infallible>> = final_events
-                                .into_iter()
-                                .map(|e| Ok(Bytes::from(e.to_sse_string())))
-                                .collect();
-                            Some((stream::iter(bytes), (body_stream, ctx, decoder, true, ping_interval)))
+                                // 命中 stop sequence 或达到 max_tokens：结束流，丢弃上游响应以取消生成
+                                // （配置了乱序窗口时先在窗口内等待迟到的 contextUsage）
+                                if ctx.output_stopped() && !ctx.awaits_context_usage() {
+                                    tracing::debug!("命中 stop sequence 或达到 max_tokens，提前结束响应");
+                                    events.extend(final_sse_events(&mut ctx, &decoder, stats_since));
+                                    return Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard.finish(), ping_interval)));
+                                }
+
+                                Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard, ping_interval)))
+                            }
+                            Some(Err(e)) => {
+                                tracing::error!("读取响应流失败: {}", e);
+                                frames.dump("上游响应流中断");
+                                // 发送 error 事件并结束，而不是以 end_turn 伪装成正常结束
+                                let events = ctx.generate_error_events(&ApiError::Upstream(
+                                    "Upstream response stream was interrupted".to_string(),
+                                ));
+                                Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard.finish(), ping_interval)))
+                            }
+                            None => {
+                                // 流结束，发送最终事件
+                                let events = final_sse_events(&mut ctx, &decoder, stats_since);
+                                Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard.finish(), ping_interval)))
+                            }
                         }
                     }
-                }
-                // 发送 ping 保活
-                _ = ping_interval.tick() => {
-                    tracing::trace!("发送 ping 保活事件");
-                    let bytes: Vec<Result<Bytes, Infallible>> = vec![Ok(create_ping_sse())];
-                    Some((stream::iter(bytes), (body_stream, ctx, decoder, false, ping_interval)))
+                    // 请求截止时间到达
+                    _ = sleep_until_deadline(deadline) => {
+                        tracing::warn!("已到达请求截止时间，提前结束响应");
+                        ctx.state_manager.mark_deadline_exceeded();
+                        let events = final_sse_events(&mut ctx, &decoder, stats_since);
+                        Some((stream::iter(events), (body_stream, ctx, decoder, frames, guard.finish(), ping_interval)))
+                    }
+                    // 发送 ping 保活
+                    _ = ping_interval.tick() => {
+                        tracing::trace!("发送 ping 保活事件");
+                        Some((stream::iter(vec![create_ping_event()]), (body_stream, ctx, decoder, frames, guard, ping_interval)))
+                    }
                 }
             }
+            .instrument(span)
         },
     )
     .flatten();
@@ -437,6 +1085,59 @@ fn create_sse_stream(
     initial_stream.chain(processing_stream)
 }
 
+/// 上游事件是否表示请求失败（输出截断除外）
+fn is_upstream_failure(event: &Event) -> bool {
+    match event {
+        Event::Error { .. } => true,
+        Event::Exception { exception_type, .. } => {
+            ExceptionKind::from_type(exception_type) != ExceptionKind::ContentLengthExceeded
+        }
+        _ => false,
+    }
+}
+
+/// 上游响应流守卫
+///
+/// 流存续期间计入活跃流并保持 `sse_stream` span；未读完上游响应即被释放说明客户端已断开，
+/// 此时上游响应随之丢弃（关闭连接，Kiro 停止生成），记录一次取消
+struct UpstreamStreamGuard {
+    _active: metrics::ActiveStreamGuard,
+    span: tracing::Span,
+    started_at: Instant,
+    /// 上游响应已读完或已主动结束
+    finished: bool,
+}
+
+impl UpstreamStreamGuard {
+    fn new() -> Self {
+        Self {
+            _active: metrics::active_streams().start(),
+            span: tracing::info_span!("sse_stream", cancelled = tracing::field::Empty),
+            started_at: Instant::now(),
+            finished: false,
+        }
+    }
+
+    /// 标记流已正常结束
+    fn finish(mut self) -> Self {
+        self.finished = true;
+        self
+    }
+}
+
+impl Drop for UpstreamStreamGuard {
+    fn drop(&mut self) {
+        if !self.finished {
+            metrics::active_streams().record_cancelled();
+            self.span.record("cancelled", true);
+            tracing::info!(
+                "客户端已断开，取消上游 Kiro 请求（已转发 {:?}）",
+                self.started_at.elapsed()
+            );
+        }
+    }
+}
+
 /// 上下文窗口大小（200k tokens）
 const CONTEXT_WINDOW_SIZE: i32 = 200_000;
 
@@ -446,28 +1147,48 @@ async fn handle_non_stream_request(
     request_body: &str,
     model: &str,
     input_tokens: i32,
+    options: ResponseOptions<'_>,
 ) -> Response {
-    // 调用 Kiro API（支持多凭据故障转移）
-    let response = match provider.call_api(request_body).await {
-        Ok(resp) => resp,
-        Err(e) => return map_provider_error(e),
+    let started_at = Instant::now();
+
+    // 调用 Kiro API（支持多凭据故障转移），截止时间到达时放弃等待
+    let mut deadline_exceeded = false;
+    let mut body_bytes = Vec::new();
+    let mut attempts = None;
+    let upstream = async {
+        let permit = options.acquire().await;
+        provider
+            .call_api(request_body)
+            .instrument(tracing::info_span!("kiro_api_call", stream = false))
+            .await
+            .map(|resp| (resp, permit))
     };
-
-    // 读取响应体
-    let body_bytes = match response.bytes().await {
-        Ok(bytes) => bytes,
-        Err(e) => {
-            tracing::error!("读取响应体失败: {}", e);
-            return (
-                StatusCode::BAD_GATEWAY,
-                Json(ErrorResponse::new(
-                    "api_error",
-                    format!("读取响应失败: {}", e),
-                )),
-            )
-                .into_response();
+    match until_deadline(options.deadline, upstream).await {
+        // 读取完响应体前持有调度名额
+        Some(Ok((mut response, _permit))) => {
+            attempts = response.extensions().get::<UpstreamAttempts>().cloned();
+            // 读取响应体；截止时间到达时保留已收到的部分
+            loop {
+                match until_deadline(options.deadline, response.chunk()).await {
+                    Some(Ok(Some(chunk))) => body_bytes.extend_from_slice(&chunk),
+                    Some(Ok(None)) => break,
+                    Some(Err(e)) => {
+                        tracing::error!("读取响应体失败: {}", e);
+                        return ApiError::Upstream(format!("读取响应失败: {}", e)).into_response();
+                    }
+                    None => {
+                        deadline_exceeded = true;
+                        break;
+                    }
+                }
+            }
         }
-    };
+        Some(Err(e)) => return ApiError::from(e).into_response(),
+        None => deadline_exceeded = true,
+    }
+    if deadline_exceeded {
+        tracing::warn!("已到达请求截止时间，返回已生成的部分结果");
+    }
 
     // 解析事件流
     let mut decoder = EventStreamDecoder::new();
@@ -475,55 +1196,142 @@ async fn handle_non_stream_request(
         tracing::warn!("缓冲区溢出: {}", e);
     }
 
+    let mut conversation = options.conversation;
     let mut text_content = String::new();
     let mut tool_uses: Vec<serde_json::Value> = Vec::new();
     let mut has_tool_use = false;
+    // 被拦截的工具调用的说明文本
+    let mut corrections = String::new();
+    let mut server_tool_usage = ServerToolUsage::default();
+    let mut references = ReferenceCollector::default();
     let mut stop_reason = "end_turn".to_string();
     // 从 contextUsageEvent 计算的实际输入 tokens
     let mut context_input_tokens: Option<i32> = None;
-
-    // 收集工具调用的增量 JSON
+    // 上游错误或异常（截断类异常除外），按种类映射为对应的错误
+    let mut upstream_error: Option<ApiError> = None;
+    let refusal_classifier = refusal::classifier();
+    // 是否收到了表示拒绝的异常
+    let mut refusal_exception = false;
+    // 命中 stop sequence 后丢弃之后的文本与工具调用
+    let mut stop_sequences = StopSequenceMatcher::new(&options.stop_sequences);
+
+    // 收集工具调用的增量 JSON（按去重后的调用 ID）
     let mut tool_json_buffers: std::collections::HashMap<String, String> =
         std::collections::HashMap::new();
+    let mut tool_ids = ToolUseIds::default();
+    // 与流式响应相同，按模型输出（文本含 thinking，以及工具输入）累计 tokens 并在收到时截断
+    let mut output = sse::OutputBudget::new(Some(options.max_tokens));
+    let mut max_tokens_reached = false;
 
     for result in decoder.decode_iter() {
         match result {
             Ok(frame) => {
                 if let Ok(event) = Event::from_frame(frame) {
+                    let stopped = max_tokens_reached
+                        || stop_sequences
+                            .as_ref()
+                            .is_some_and(|m| m.matched().is_some());
                     match event {
+                        // 命中 stop sequence 或达到 max_tokens 后丢弃之后的文本与工具调用
+                        Event::AssistantResponse(resp) if stopped => {
+                            if let Some(id) = &resp.conversation_id {
+                                conversation.observe(id);
+                            }
+                        }
                         Event::AssistantResponse(resp) => {
-                            text_content.push_str(&resp.content);
+                            if let Some(id) = &resp.conversation_id {
+                                conversation.observe(id);
+                            }
+                            let (content, reached) = output.admit(&resp.content);
+                            if reached {
+                                tracing::debug!(
+                                    "输出达到 max_tokens ({})，截断后续内容",
+                                    options.max_tokens
+                                );
+                                max_tokens_reached = true;
+                            }
+                            match stop_sequences.as_mut() {
+                                Some(matcher) => text_content.push_str(&matcher.push(content)),
+                                None => text_content.push_str(content),
+                            }
                         }
+                        Event::ToolUse(_) if stopped => {}
                         Event::ToolUse(tool_use) => {
-                            has_tool_use = true;
+                            // 输入超出 max_tokens 的调用不完整，与之后的内容一起丢弃
+                            if output.admit(&tool_use.input).1 {
+                                tracing::debug!(
+                                    "工具输入达到 max_tokens ({})，丢弃该调用",
+                                    options.max_tokens
+                                );
+                                max_tokens_reached = true;
+                                continue;
+                            }
+                            let server_tool = options.server_tools.lookup(&tool_use.name);
+                            if server_tool.is_none() {
+                                has_tool_use = true;
+                            }
 
-                            // 累积工具的 JSON 输入
-                            let buffer = tool_json_buffers
-                                .entry(tool_use.tool_use_id.clone())
-                                .or_insert_with(String::new);
-                            buffer.push_str(&tool_use.input);
+                            // 累积工具的 JSON 输入（上游复用已结束调用的 ID 时分配新 ID）
+                            let call_id = tool_ids.observe(&tool_use.tool_use_id, tool_use.stop);
+                            tool_json_buffers
+                                .entry(call_id.clone())
+                                .or_default()
+                                .push_str(&tool_use.input);
 
                             // 如果是完整的工具调用，添加到列表
                             if tool_use.stop {
+                                let buffer = tool_json_buffers.remove(&call_id).unwrap_or_default();
                                 let input: serde_json::Value = if buffer.is_empty() {
                                     serde_json::json!({})
                                 } else {
-                                    serde_json::from_str(buffer)
-                                        .unwrap_or_else(|e| {
-                                            tracing::warn!(
-                                                "工具输入 JSON 解析失败: {}, tool_use_id: {}",
-                                                e, tool_use.tool_use_id
-                                            );
-                                            serde_json::json!({})
-                                        })
+                                    serde_json::from_str(&buffer).unwrap_or_else(|e| {
+                                        tracing::warn!(
+                                            "工具输入 JSON 解析失败: {}, tool_use_id: {}",
+                                            e,
+                                            call_id
+                                        );
+                                        serde_json::json!({})
+                                    })
+                                };
+
+                                let id = match server_tool {
+                                    Some(spec) => {
+                                        server_tool_usage.add(spec, 1);
+                                        server_tools::to_server_tool_use_id(&call_id)
+                                    }
+                                    None => call_id.clone(),
                                 };
 
-                                tool_uses.push(json!({
-                                    "type": "tool_use",
-                                    "id": tool_use.tool_use_id,
+                                let mut block = json!({
+                                    "type": options.server_tools.content_block_type(&tool_use.name),
+                                    "id": id,
                                     "name": tool_use.name,
                                     "input": input
-                                }));
+                                });
+
+                                // 按 input_schema 校验客户端工具的输入
+                                let validation = match &options.tool_validator {
+                                    Some(validator) if server_tool.is_none() => {
+                                        validator.validate(&tool_use.name, &buffer)
+                                    }
+                                    _ => Ok(()),
+                                };
+                                match validation {
+                                    Ok(()) => tool_uses.push(block),
+                                    Err(errors)
+                                        if options.stream.tool_input_validation
+                                            == ToolInputValidation::Correct =>
+                                    {
+                                        corrections.push_str(&tool_validation::correction_text(
+                                            &tool_use.name,
+                                            &errors,
+                                        ));
+                                    }
+                                    Err(errors) => {
+                                        block["kiro_warning"] = tool_validation::warning(&errors);
+                                        tool_uses.push(block);
+                                    }
+                                }
                             }
                         }
                         Event::ContextUsage(context_usage) => {
@@ -544,11 +1352,44 @@ async fn handle_non_stream_request(
                                 actual_input_tokens
                             );
                         }
-                        Event::Exception { exception_type, .. } => {
-                            if exception_type == "ContentLengthExceededException" {
+                        Event::Exception {
+                            exception_type,
+                            message,
+                        } => {
+                            let kind = ExceptionKind::from_type(&exception_type);
+                            tracing::warn!(
+                                kind = %kind,
+                                retryable = kind.is_retryable(),
+                                "收到异常事件: {} - {}",
+                                exception_type,
+                                message
+                            );
+                            if kind == ExceptionKind::ContentLengthExceeded {
                                 stop_reason = "max_tokens".to_string();
+                            } else if refusal_classifier
+                                .is_some_and(|c| c.matches_exception(&exception_type))
+                            {
+                                refusal_exception = true;
+                            } else if upstream_error.is_none() {
+                                upstream_error =
+                                    Some(ApiError::from_upstream_event(&exception_type, &message));
+                            }
+                        }
+                        Event::Error {
+                            error_code,
+                            error_message,
+                        } => {
+                            tracing::error!("收到错误事件: {} - {}", error_code, error_message);
+                            if upstream_error.is_none() {
+                                upstream_error = Some(ApiError::from_upstream_event(
+                                    &error_code,
+                                    &error_message,
+                                ));
                             }
                         }
+                        Event::CodeReference(code_reference) => {
+                            references.push(&code_reference);
+                        }
                         _ => {}
                     }
                 }
@@ -558,10 +1399,61 @@ async fn handle_non_stream_request(
             }
         }
     }
+    conversation.finish();
+
+    // 上游在产出任何内容前返回错误：按种类返回对应状态码，而不是空响应
+    if let Some(error) = upstream_error
+        && text_content.is_empty()
+        && tool_uses.is_empty()
+        && corrections.is_empty()
+    {
+        return error.into_response();
+    }
+
+    // 输出在 stop sequence 处截断；未命中时补上暂存的文本
+    let stop_sequence = stop_sequences.and_then(|mut m| {
+        text_content.push_str(&m.flush());
+        m.matched().map(str::to_string)
+    });
+
+    // 剥离注入策略文本和 thinking 指令的回显
+    if let Some(mut filter) = echo_filter(options.stream, options.thinking_directive.as_deref()) {
+        text_content = filter.push(&text_content) + &filter.flush();
+    }
+
+    // 追加代码引用说明
+    if options.stream.code_references == CodeReferenceMode::Append
+        && let Some(text) = references.render()
+    {
+        text_content.push_str(&text);
+    }
+
+    // 被拦截的工具调用改为说明文本，全部被拦截时不再以 tool_use 结束
+    if !corrections.is_empty() {
+        text_content.push_str(&corrections);
+        has_tool_use = tool_uses.iter().any(|t| t["type"] == "tool_use");
+    }
+
+    // 拒绝回复：没有文本输出时补上固定的说明文本
+    let refused =
+        refusal_classifier.is_some_and(|c| refusal_exception || c.matches_text(&text_content));
+    if refused
+        && !deadline_exceeded
+        && text_content.is_empty()
+        && let Some(message) = refusal_classifier.and_then(|c| c.message())
+    {
+        text_content.push_str(message);
+    }
 
     // 确定 stop_reason
-    if has_tool_use && stop_reason == "end_turn" {
+    if deadline_exceeded || max_tokens_reached {
+        stop_reason = "max_tokens".to_string();
+    } else if refused {
+        stop_reason = "refusal".to_string();
+    } else if has_tool_use && stop_reason == "end_turn" {
         stop_reason = "tool_use".to_string();
+    } else if stop_sequence.is_some() && stop_reason == "end_turn" {
+        stop_reason = "stop_sequence".to_string();
     }
 
     // 构建响应内容
@@ -581,23 +1473,68 @@ async fn handle_non_stream_request(
 
     // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
     let final_input_tokens = context_input_tokens.unwrap_or(input_tokens);
+    let estimated_cost = options.usage.record(
+        final_input_tokens,
+        output_tokens,
+        server_tool_usage.count(&server_tools::WEB_SEARCH),
+    );
 
     // 构建 Anthropic 响应
-    let response_body = json!({
-        "id": format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
+    let mut usage = json!({
+        "input_tokens": final_input_tokens,
+        "output_tokens": output_tokens
+    });
+    if !server_tool_usage.is_empty() {
+        usage["server_tool_use"] = server_tool_usage.to_json();
+    }
+    if let Some(cost) = estimated_cost {
+        usage["kiro_estimated_cost"] = json!(cost);
+    }
+    let message_id = format!("msg_{}", Uuid::new_v4().to_string().replace('-', ""));
+    if let Some(recorder) = options.branch {
+        recorder.record(&message_id, content.clone());
+    }
+    let mut response_body = json!({
+        "id": message_id,
         "type": "message",
         "role": "assistant",
         "content": content,
         "model": model,
         "stop_reason": stop_reason,
-        "stop_sequence": null,
-        "usage": {
-            "input_tokens": final_input_tokens,
-            "output_tokens": output_tokens
-        }
+        "stop_sequence": stop_sequence.filter(|_| stop_reason == "stop_sequence"),
+        "usage": usage
     });
+    if deadline_exceeded {
+        response_body["deadline_exceeded"] = json!(true);
+    }
 
-    (StatusCode::OK, Json(response_body)).into_response()
+    let mut response = (StatusCode::OK, Json(response_body)).into_response();
+    if options.stream.decoder_stats {
+        let stats = decoder.stats();
+        let headers = response.headers_mut();
+        for (name, value) in [
+            ("x-kiro-frames-decoded", stats.frames_decoded as u64),
+            ("x-kiro-bytes-received", stats.bytes_received as u64),
+            ("x-kiro-bytes-skipped", stats.bytes_skipped as u64),
+            ("x-kiro-parse-errors", stats.parse_errors as u64),
+            (
+                "x-kiro-upstream-duration-ms",
+                started_at.elapsed().as_millis() as u64,
+            ),
+        ] {
+            headers.insert(name, header::HeaderValue::from(value));
+        }
+    }
+    insert_downgrade_header(&mut response, options.downgrade.as_ref());
+    insert_max_tokens_header(
+        &mut response,
+        options.max_tokens_clamped,
+        options.max_tokens,
+    );
+    if options.attempts_header {
+        insert_attempts_header(&mut response, attempts.as_ref());
+    }
+    response
 }
 
 /// 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
@@ -668,7 +1605,20 @@ pub async fn count_tokens(
 /// message_start 中使用估算的 input_tokens，message_delta 中携带从 contextUsageEvent 计算的准确值。
 pub async fn post_messages_cc(
     State(state): State<AppState>,
-    JsonExtractor(mut payload): JsonExtractor<MessagesRequest>,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    TracedJson(payload): TracedJson<MessagesRequest>,
+) -> Response {
+    create_message_cc(state, identity, headers, payload, Delivery::Sse).await
+}
+
+/// 按 `/cc/v1/messages` 的流程处理请求，流式响应按 `delivery` 下发
+pub async fn create_message_cc(
+    state: AppState,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    mut payload: MessagesRequest,
+    delivery: Delivery,
 ) -> Response {
     tracing::info!(
         model = %payload.model,
@@ -677,25 +1627,62 @@ pub async fn post_messages_cc(
         message_count = %payload.messages.len(),
         "Received POST /cc/v1/messages request"
     );
+    let state = match apply_identity(state, identity, &payload.model) {
+        Ok(state) => state,
+        Err(e) => return e.into_response(),
+    };
 
     // 检查 KiroProvider 是否可用
     let provider = match &state.kiro_provider {
         Some(p) => p.clone(),
         None => {
             tracing::error!("KiroProvider 未配置");
-            return (
-                StatusCode::SERVICE_UNAVAILABLE,
-                Json(ErrorResponse::new(
-                    "service_unavailable",
-                    "Kiro API provider not configured",
-                )),
-            )
+            return ApiError::ServiceUnavailable("Kiro API provider not configured".to_string())
                 .into_response();
         }
     };
 
+    let request_options = match RequestOptions::from_headers(&headers) {
+        Ok(options) => options,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    payload.seed = match request_seed(&headers, payload.seed) {
+        Ok(seed) => seed,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    let converter_version = match state
+        .canary
+        .select(request_options.converter.as_deref(), payload.seed)
+    {
+        Ok(version) => version,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+    let profile_arn = match profile::resolve(&state, &headers) {
+        Ok(arn) => arn,
+        Err(message) => return ApiError::InvalidRequest(message).into_response(),
+    };
+
+    let pinned = apply_model_pin(&state, request_options.unpin_model, &mut payload);
     // 检测模型名是否包含 "thinking" 后缀，若包含则覆写 thinking 配置
     override_thinking_from_model_name(&mut payload);
+    let budget_downgrade = apply_budget(&state, &mut payload).or(pinned);
+    let max_tokens_clamped = match apply_max_tokens(&state.config.max_tokens, &mut payload) {
+        Ok(clamped) => clamped,
+        Err(e) => return e.into_response(),
+    };
+    payload.accept_language = headers
+        .get(header::ACCEPT_LANGUAGE)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+
+    // 沿 parent_message_id 指定的分支补全历史
+    let branch = match resolve_branch(&state, &mut payload) {
+        Ok(branch) => branch,
+        Err(message) => {
+            tracing::warn!("会话分支解析失败: {}", message);
+            return ApiError::InvalidRequest(message).into_response();
+        }
+    };
 
     // 检查是否为 WebSearch 请求
     if websearch::has_web_search_tool(&payload) {
@@ -711,59 +1698,84 @@ pub async fn post_messages_cc(
                 payload.tools.clone(),
             ) as i32;
 
-            return websearch::handle_websearch_request(provider, &payload, input_tokens).await;
+            let compat = CompatShim::new(
+                state.config.stream.cc_compat,
+                &state.config.stream.compat_rules,
+            );
+            let usage = request_usage(&state, &payload.model, None);
+            return websearch::handle_websearch_request(
+                provider,
+                &payload,
+                input_tokens,
+                compat,
+                usage,
+                delivery,
+            )
+            .await;
         } else {
             tracing::debug!("包含 WebSearch 工具但非搜索请求，过滤后走普通对话路径");
             // 过滤掉 web_search 工具，避免传给 Kiro 后端
-            payload.tools = payload.tools.map(|tools| {
-                tools.into_iter().filter(|t| !t.is_web_search() && t.name != "web_search").collect()
-            });
+            payload.tools = payload
+                .tools
+                .map(|tools| tools.into_iter().filter(|t| !t.is_web_search()).collect());
         }
     }
 
-    // 转换请求
-    let conversion_result = match convert_request(&payload) {
+    // 转换请求（按 x-kiro-converter 或灰度比例选择转换器版本）
+    let conversion_result = match tracing::info_span!("convert_request").in_scope(|| {
+        state
+            .canary
+            .convert(&payload, converter_version)
+    }) {
         Ok(result) => result,
         Err(e) => {
-            let (error_type, message) = match &e {
-                ConversionError::UnsupportedModel(model) => {
-                    ("invalid_request_error", format!("模型不支持: {}", model))
-                }
-                ConversionError::EmptyMessages => {
-                    ("invalid_request_error", "消息列表为空".to_string())
-                }
-            };
             tracing::warn!("请求转换失败: {}", e);
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(error_type, message)),
-            )
-                .into_response();
+            if let Some(store) = &state.dead_letters {
+                store.record("/cc/v1/messages", &state.api_key, &payload, &e);
+            }
+            return ApiError::from(e).into_response();
         }
     };
 
+    let conversation = conversation_tracker(
+        &state,
+        &payload,
+        conversion_result.conversation_state.conversation_id.clone(),
+    );
+    let downgrade = budget_downgrade.or_else(|| model_downgrade_notice(&state, &payload));
+
     // 构建 Kiro 请求
     let kiro_request = KiroRequest {
         conversation_state: conversion_result.conversation_state,
-        profile_arn: state.profile_arn.clone(),
+        profile_arn: profile_arn.clone(),
     };
 
     let request_body = match serde_json::to_string(&kiro_request) {
         Ok(body) => body,
         Err(e) => {
             tracing::error!("序列化请求失败: {}", e);
-            return (
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(ErrorResponse::new(
-                    "internal_error",
-                    format!("序列化请求失败: {}", e),
-                )),
-            )
-                .into_response();
+            return ApiError::from(e).into_response();
         }
     };
+    keep_session_history(&state, &payload, kiro_request, request_body.len());
+
+    tracing::debug!("Kiro request body: {}", redact::body(&request_body));
+    let request_log = state.request_logs.as_ref().map(|store| {
+        store.start(
+            "/cc/v1/messages",
+            &state.api_key,
+            &payload,
+            converter_version,
+            &request_body,
+        )
+    });
 
-    tracing::debug!("Kiro request body: {}", request_body);
+    let tool_validator = ToolInputValidator::new(
+        state.config.stream.tool_input_validation,
+        payload.tools.as_deref(),
+    );
+    let server_tools = DeclaredServerTools::from_tools(payload.tools.as_deref());
+    let thinking_directive = generate_thinking_prefix(&payload);
 
     // 估算输入 tokens
     let input_tokens = token::count_all_tokens(
@@ -779,20 +1791,68 @@ pub async fn post_messages_cc(
         .as_ref()
         .map(|t| t.is_enabled())
         .unwrap_or(false);
+    // thinking 呈现为文本或模型关闭了 thinking 提取时不提取 thinking 块，`<thinking>` 标签原样保留在文本中
+    let extract_thinking = thinking_enabled
+        && request_options.extract_thinking()
+        && models::extracts_thinking(&payload.model);
+
+    let options = ResponseOptions {
+        stream: &state.config.stream,
+        profile: request_options.profile(state.config.stream.cc_profile),
+        compat: CompatShim::new(
+            state.config.stream.cc_compat,
+            &state.config.stream.compat_rules,
+        ),
+        branch,
+        usage_reporter: usage_reporter(payload.stream_options.as_ref(), &state.config.stream),
+        conversation,
+        downgrade,
+        deadline: request_options.deadline,
+        thinking_budget: thinking_budget(&state.config.stream, payload.thinking.as_ref()),
+        stop_sequences: payload.stop_sequences.clone().unwrap_or_default(),
+        max_tokens: payload.max_tokens,
+        max_tokens_clamped,
+        attempts_header: state.config.upstream.attempts_header,
+        tool_validator,
+        server_tools,
+        thinking_directive,
+        usage: request_usage(&state, &payload.model, profile_arn.as_deref()),
+        scheduler: state.scheduler.clone(),
+        priority: request_options.priority,
+        coalesce_deltas: request_options.coalesce_deltas(),
+        transcript: state
+            .sse_transcripts
+            .as_ref()
+            .filter(|_| payload.stream)
+            .map(|store| store.start("/cc/v1/messages", &payload.model)),
+        delivery,
+    };
 
-    if payload.stream {
+    let response = if payload.stream {
         // 流式响应（实时转发，message_delta 中携带准确的 input_tokens）
         handle_stream_request(
             provider,
             &request_body,
             &payload.model,
             input_tokens,
-            thinking_enabled,
+            extract_thinking,
+            options,
         )
         .await
     } else {
         // 非流式响应（复用现有逻辑，已经使用正确的 input_tokens）
-        handle_non_stream_request(provider, &request_body, &payload.model, input_tokens).await
+        handle_non_stream_request(
+            provider,
+            &request_body,
+            &payload.model,
+            input_tokens,
+            options,
+        )
+        .await
+    };
+    match request_log {
+        Some(log) => log.finish(response).await,
+        None => response,
     }
 }
 
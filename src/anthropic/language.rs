@@ -0,0 +1,171 @@
+//! 回复语言提示
+//!
+//! 系统提示词以英文为主时，Kiro 模型有时会用英文回答中文提问。启用
+//! `converter.languageDirective.enabled` 后，按最新一条 user 消息的主要文字（忽略代码块）判断语言，
+//! 在系统提示词末尾追加一句回复语言指令；消息文字过少无法判断时按请求头 `Accept-Language` 推断。
+//!
+//! 只识别文字系统有明显区别的语言（中文、日文、韩文、俄文），以拉丁字母为主的消息不追加指令
+
+/// 可识别的语言
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Language {
+    Chinese,
+    Japanese,
+    Korean,
+    Russian,
+}
+
+impl Language {
+    /// 指令中使用的语言名称
+    pub fn name(self) -> &'static str {
+        match self {
+            Language::Chinese => "Chinese",
+            Language::Japanese => "Japanese",
+            Language::Korean => "Korean",
+            Language::Russian => "Russian",
+        }
+    }
+
+    /// 按语言标签（如 `zh-CN`）的主标签识别
+    fn from_tag(tag: &str) -> Option<Self> {
+        let primary = tag.split(['-', '_']).next()?.trim().to_ascii_lowercase();
+        match primary.as_str() {
+            "zh" => Some(Language::Chinese),
+            "ja" => Some(Language::Japanese),
+            "ko" => Some(Language::Korean),
+            "ru" => Some(Language::Russian),
+            _ => None,
+        }
+    }
+}
+
+/// 语言识别结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detection {
+    Language(Language),
+    /// 以拉丁字母为主
+    Latin,
+    /// 文字过少，无法判断
+    Inconclusive,
+}
+
+/// 表意文字、假名与谚文按单字计为多个字母，与拉丁字母按词长大致对齐
+const IDEOGRAPH_WEIGHT: usize = 3;
+
+/// 识别文本的主要语言（忽略 Markdown 代码块与行内代码）
+pub fn detect(text: &str, min_letters: usize) -> Detection {
+    let (mut han, mut kana, mut hangul, mut cyrillic, mut latin) = (0, 0, 0, 0, 0);
+    for c in prose(text).chars() {
+        match c {
+            '\u{3040}'..='\u{30ff}' => kana += 1,
+            '\u{ac00}'..='\u{d7af}' | '\u{1100}'..='\u{11ff}' => hangul += 1,
+            '\u{4e00}'..='\u{9fff}' | '\u{3400}'..='\u{4dbf}' => han += 1,
+            '\u{0400}'..='\u{04ff}' => cyrillic += 1,
+            c if c.is_ascii_alphabetic() || ('\u{00c0}'..='\u{024f}').contains(&c) => latin += 1,
+            _ => {}
+        }
+    }
+
+    let cjk = (han + kana + hangul) * IDEOGRAPH_WEIGHT;
+    let total = cjk + cyrillic + latin;
+    if total < min_letters.max(1) {
+        return Detection::Inconclusive;
+    }
+
+    // 日文混用汉字与假名，出现足够的假名即视为日文
+    let language = if cjk * 2 >= total && kana > 0 && kana * 5 >= han + kana {
+        Language::Japanese
+    } else if hangul * IDEOGRAPH_WEIGHT * 2 >= total {
+        Language::Korean
+    } else if cjk * 2 >= total {
+        Language::Chinese
+    } else if cyrillic * 2 >= total {
+        Language::Russian
+    } else {
+        return Detection::Latin;
+    };
+    Detection::Language(language)
+}
+
+/// 按 `Accept-Language` 请求头推断语言（取权重最高且可识别的语言）
+pub fn from_accept_language(header: &str) -> Option<Language> {
+    header
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.split(';');
+            let language = Language::from_tag(parts.next()?)?;
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse::<f32>().ok())
+                .unwrap_or(1.0);
+            (quality > 0.0).then_some((language, quality))
+        })
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(language, _)| language)
+}
+
+/// 去掉代码块与行内代码，只保留正文
+fn prose(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for (i, segment) in text.split("```").enumerate() {
+        // 奇数段位于代码块内
+        if i % 2 == 1 {
+            continue;
+        }
+        for (j, part) in segment.split('`').enumerate() {
+            if j % 2 == 0 {
+                out.push_str(part);
+                out.push(' ');
+            }
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_dominant_script() {
+        assert_eq!(
+            detect("帮我看看这个函数为什么会 panic：`parse_config`", 4),
+            Detection::Language(Language::Chinese)
+        );
+        assert_eq!(
+            detect(
+                "修复这个 bug\n```rust\nfn main() { println!(\"hello world\"); }\n```",
+                4
+            ),
+            Detection::Language(Language::Chinese)
+        );
+        assert_eq!(
+            detect("この関数を説明してください", 4),
+            Detection::Language(Language::Japanese)
+        );
+        assert_eq!(
+            detect("이 코드를 설명해 주세요", 4),
+            Detection::Language(Language::Korean)
+        );
+        assert_eq!(
+            detect("Объясни этот код", 4),
+            Detection::Language(Language::Russian)
+        );
+        assert_eq!(detect("Explain this code please", 4), Detection::Latin);
+        assert_eq!(detect("```\nls -la\n```", 4), Detection::Inconclusive);
+    }
+
+    #[test]
+    fn test_accept_language() {
+        assert_eq!(
+            from_accept_language("en-US;q=0.8, zh-CN;q=0.9"),
+            Some(Language::Chinese)
+        );
+        assert_eq!(
+            from_accept_language("ja,en;q=0.5"),
+            Some(Language::Japanese)
+        );
+        assert_eq!(from_accept_language("en-US,fr;q=0.5"), None);
+        assert_eq!(from_accept_language("zh;q=0"), None);
+    }
+}
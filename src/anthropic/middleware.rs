@@ -5,26 +5,52 @@ use std::sync::Arc;
 use axum::{
     body::Body,
     extract::State,
-    http::{Request, StatusCode},
+    http::{HeaderValue, Request, header},
     middleware::Next,
-    response::{IntoResponse, Json, Response},
+    response::{IntoResponse, Response},
 };
+use tracing::Instrument;
 
-use crate::common::auth;
+use crate::common::auth::{self, KeyIdentity};
+use crate::common::metrics;
 use crate::kiro::provider::KiroProvider;
+use crate::kiro::scheduler::Scheduler;
+use crate::model::config::Config;
 
-use super::types::ErrorResponse;
+use super::api_keys::ApiKeyRegistry;
+use super::canary::Canary;
+use super::dead_letter::DeadLetterStore;
+use super::error::{self, ApiError};
+use super::request_log::RequestLogStore;
+use super::session::SessionStore;
+use super::sse_transcript::SseTranscriptStore;
 
 /// 应用共享状态
 #[derive(Clone)]
 pub struct AppState {
     /// API 密钥
     pub api_key: String,
+    /// `apiKeys` 中配置的其他 API Key
+    pub api_keys: Arc<ApiKeyRegistry>,
     /// Kiro Provider（可选，用于实际 API 调用）
     /// 内部使用 MultiTokenManager，已支持线程安全的多凭据管理
     pub kiro_provider: Option<Arc<KiroProvider>>,
     /// Profile ARN（可选，用于请求）
     pub profile_arn: Option<String>,
+    /// 应用配置（只读快照，用于协议转换等行为开关）
+    pub config: Arc<Config>,
+    /// 会话存储（跨请求复用已转换的历史等）
+    pub session_store: Arc<SessionStore>,
+    /// 上游请求调度器（限制并发时）
+    pub scheduler: Option<Arc<Scheduler>>,
+    /// 转换器灰度
+    pub canary: Arc<Canary>,
+    /// 转换失败死信队列（启用 `deadLetter.enabled` 时）
+    pub dead_letters: Option<Arc<DeadLetterStore>>,
+    /// SSE 会话记录（启用 `logging.sseTranscripts.enabled` 时）
+    pub sse_transcripts: Option<Arc<SseTranscriptStore>>,
+    /// 请求/响应日志（启用 `logging.requests.enabled` 时）
+    pub request_logs: Option<Arc<RequestLogStore>>,
 }
 
 impl AppState {
@@ -32,11 +58,25 @@ impl AppState {
     pub fn new(api_key: impl Into<String>) -> Self {
         Self {
             api_key: api_key.into(),
+            api_keys: Arc::new(ApiKeyRegistry::default()),
             kiro_provider: None,
             profile_arn: None,
+            config: Arc::new(Config::default()),
+            session_store: Arc::new(SessionStore::default()),
+            scheduler: None,
+            canary: Arc::new(Canary::from_config(&Config::default())),
+            dead_letters: None,
+            sse_transcripts: None,
+            request_logs: None,
         }
     }
 
+    /// 设置 API 密钥
+    pub fn with_api_key(mut self, api_key: impl Into<String>) -> Self {
+        self.api_key = api_key.into();
+        self
+    }
+
     /// 设置 KiroProvider
     pub fn with_kiro_provider(mut self, provider: KiroProvider) -> Self {
         self.kiro_provider = Some(Arc::new(provider));
@@ -48,21 +88,93 @@ impl AppState {
         self.profile_arn = Some(arn.into());
         self
     }
+
+    /// 设置转换失败死信队列
+    pub fn with_dead_letters(mut self, store: Arc<DeadLetterStore>) -> Self {
+        self.dead_letters = Some(store);
+        self
+    }
+
+    /// 设置 SSE 会话记录存储
+    pub fn with_sse_transcripts(mut self, store: Arc<SseTranscriptStore>) -> Self {
+        self.sse_transcripts = Some(store);
+        self
+    }
+
+    /// 设置请求/响应日志存储
+    pub fn with_request_logs(mut self, store: Arc<RequestLogStore>) -> Self {
+        self.request_logs = Some(store);
+        self
+    }
+
+    /// 设置应用配置（按配置重建会话存储、调度器与转换器灰度）
+    pub fn with_config(mut self, config: Config) -> Self {
+        self.api_keys = Arc::new(ApiKeyRegistry::new(&config.api_keys));
+        self.session_store = Arc::new(SessionStore::from_config(&config.sessions));
+        self.scheduler = Scheduler::from_config(&config.scheduler);
+        self.canary = Arc::new(Canary::from_config(&config));
+        self.config = Arc::new(config);
+        self
+    }
 }
 
 /// API Key 认证中间件
+///
+/// 按 `apiKeys` 或主 API Key 确定调用方身份并写入请求扩展，后续处理都在带有身份名称的 span 中进行。
+/// 客户端证书已映射到 API Key 身份的请求不再读取请求头中的 Key
 pub async fn auth_middleware(
     State(state): State<AppState>,
-    request: Request<Body>,
+    mut request: Request<Body>,
     next: Next,
 ) -> Response {
-    match auth::extract_api_key(&request) {
-        Some(key) if auth::constant_time_eq(&key, &state.api_key) => next.run(request).await,
-        _ => {
-            let error = ErrorResponse::authentication_error();
-            (StatusCode::UNAUTHORIZED, Json(error)).into_response()
+    let client_cert = request.extensions().get::<KeyIdentity>().cloned();
+    let key = match &client_cert {
+        Some(identity) => Some(identity.api_key.clone()),
+        None => auth::extract_api_key(&request),
+    };
+    let Some(key) = key else {
+        return ApiError::Authentication.into_response();
+    };
+
+    let identity = match state.api_keys.find(&key) {
+        Some(entry) => {
+            let identity = entry.identity();
+            if let Err(retry_after) = entry.acquire() {
+                metrics::api_keys().record_rate_limited(&identity.name);
+                tracing::warn!(api_key = %identity.name, "API Key 超出每分钟请求数限制");
+                let mut response = ApiError::RateLimited(format!(
+                    "API key '{}' rate limit exceeded",
+                    identity.name
+                ))
+                .into_response();
+                response.headers_mut().insert(
+                    header::RETRY_AFTER,
+                    HeaderValue::from(retry_after.as_secs_f64().ceil().max(1.0) as u64),
+                );
+                return response;
+            }
+            identity.clone()
         }
-    }
+        None => match client_cert {
+            Some(identity) => identity,
+            None if auth::constant_time_eq(&key, &state.api_key) => KeyIdentity {
+                name: "default".to_string(),
+                api_key: key,
+                models: Vec::new(),
+            },
+            None => return ApiError::Authentication.into_response(),
+        },
+    };
+
+    metrics::api_keys().record_request(&identity.name);
+    let span = tracing::info_span!("api_key", name = %identity.name);
+    request.extensions_mut().insert(identity);
+    next.run(request).instrument(span).await
+}
+
+/// 错误格式中间件：将 axum 产生的纯文本拒绝（请求体解析失败、请求体过大等）改写为 Anthropic 错误格式
+pub async fn error_envelope(request: Request<Body>, next: Next) -> Response {
+    error::into_envelope(next.run(request).await).await
 }
 
 /// CORS 中间件层
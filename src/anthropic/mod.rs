@@ -8,6 +8,7 @@
 //! - `GET /v1/models` - 获取可用模型列表
 //! - `POST /v1/messages` - 创建消息（对话）
 //! - `POST /v1/messages/count_tokens` - 计算 token 数量
+//! - `GET /v1/messages/ws` - 创建消息（WebSocket 流式传输，事件序列与 SSE 相同）
 //!
 //! ## Claude Code 兼容端点 (/cc/v1)
 //! - `POST /cc/v1/messages` - 创建消息（实时流式转发，message_delta 中携带准确的 input_tokens）
@@ -17,17 +18,62 @@
 //! ```rust,ignore
 //! use kiro_rs::anthropic;
 //!
-//! let app = anthropic::create_router("your-api-key");
+//! let state = anthropic::AppState::new("your-api-key");
+//! let app = anthropic::create_router(state, ListenerAuth::ApiKey);
 //! let listener = tokio::net::TcpListener::bind("0.0.0.0:3000").await?;
 //! axum::serve(listener, app).await?;
 //! ```
 
+pub mod api_keys;
+mod backpressure;
+mod branch;
+pub mod budget;
+mod canary;
+mod client_tools;
+mod compat;
+#[cfg(test)]
+mod conformance;
+mod conversation;
 mod converter;
+mod delivery;
+pub mod dead_letter;
+mod diff_preview;
+mod echo_filter;
+mod error;
+mod event_order;
 mod handlers;
+mod language;
 mod middleware;
+pub mod models;
+mod normalize;
+pub mod pricing;
+pub mod profile;
+mod references;
+pub mod refusal;
+mod request_log;
+mod request_options;
 mod router;
+mod schema;
+mod server_tools;
+mod session;
+pub mod sse_transcript;
+mod stop_sequence;
 mod stream;
+mod template;
+mod tool_choice;
+mod tool_ids;
+mod tool_validation;
+pub mod transcript;
 pub mod types;
 mod websearch;
+mod websocket;
+mod workspace;
 
-pub use router::create_router_with_provider;
+pub use dead_letter::DeadLetterStore;
+pub use delivery::{Delivery, EventStream};
+pub use handlers::{create_message, post_messages};
+pub use middleware::{AppState, auth_middleware, cors_layer};
+pub use request_log::RequestLogStore;
+pub use router::create_router;
+pub use session::SessionStore;
+pub use sse_transcript::SseTranscriptStore;
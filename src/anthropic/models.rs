@@ -0,0 +1,464 @@
+//! 模型映射表
+//!
+//! 记录客户端模型名到 Kiro 模型的映射规则及模型能力，模型映射、`/v1/models` 的模型列表
+//! 与 thinking 能力校验都以此为准。配置 `modelMapping.aliases` 中的规则优先，
+//! 之后是内置规则（除非 `modelMapping.builtin` 为 false）
+
+use std::collections::BTreeMap;
+use std::sync::OnceLock;
+
+use parking_lot::Mutex;
+
+use crate::model::config::{ModelAlias, ModelMappingConfig};
+
+use super::types::Model;
+
+/// 内置公布的模型
+struct ModelProfile {
+    /// 对外公布的模型 ID
+    id: &'static str,
+    display_name: &'static str,
+    created: i64,
+    /// 映射到的 Kiro 模型 ID
+    kiro_id: &'static str,
+    /// Kiro 是否支持该模型的 thinking 模式
+    thinking: bool,
+    /// 输出 tokens 上限
+    max_output_tokens: i32,
+}
+
+/// 未设置输出上限的模型在 `/v1/models` 中公布的 max_tokens
+const MAX_TOKENS: i32 = 32000;
+
+const MODEL_PROFILES: &[ModelProfile] = &[
+    ModelProfile {
+        id: "claude-sonnet-4-5-20250929",
+        display_name: "Claude Sonnet 4.5",
+        created: 1727568000,
+        kiro_id: "claude-sonnet-4.5",
+        thinking: true,
+        max_output_tokens: 64000,
+    },
+    ModelProfile {
+        id: "claude-opus-4-5-20251101",
+        display_name: "Claude Opus 4.5",
+        created: 1730419200,
+        kiro_id: "claude-opus-4.5",
+        thinking: true,
+        max_output_tokens: 64000,
+    },
+    ModelProfile {
+        id: "claude-sonnet-4-6",
+        display_name: "Claude Sonnet 4.6",
+        created: 1770314400,
+        kiro_id: "claude-sonnet-4.6",
+        thinking: true,
+        max_output_tokens: 64000,
+    },
+    ModelProfile {
+        id: "claude-opus-4-6",
+        display_name: "Claude Opus 4.6",
+        created: 1770314400,
+        kiro_id: "claude-opus-4.6",
+        thinking: true,
+        max_output_tokens: 128000,
+    },
+    ModelProfile {
+        id: "claude-haiku-4-5-20251001",
+        display_name: "Claude Haiku 4.5",
+        created: 1727740800,
+        kiro_id: "claude-haiku-4.5",
+        thinking: false,
+        max_output_tokens: 64000,
+    },
+];
+
+/// 内置的通配规则（不公布）：
+/// - sonnet 4.6/4-6 → claude-sonnet-4.6
+/// - 其他 sonnet → claude-sonnet-4.5
+/// - opus 4.5/4-5 → claude-opus-4.5
+/// - 其他 opus → claude-opus-4.6
+/// - 所有 haiku → claude-haiku-4.5
+const FALLBACK_RULES: &[(&str, &str)] = &[
+    ("*sonnet*4-6*", "claude-sonnet-4.6"),
+    ("*sonnet*4.6*", "claude-sonnet-4.6"),
+    ("*sonnet*", "claude-sonnet-4.5"),
+    ("*opus*4-5*", "claude-opus-4.5"),
+    ("*opus*4.5*", "claude-opus-4.5"),
+    ("*opus*", "claude-opus-4.6"),
+    ("*haiku*", "claude-haiku-4.5"),
+];
+
+/// thinking 变体的模型名后缀
+const THINKING_SUFFIX: &str = "-thinking";
+
+/// 模型映射表
+#[derive(Debug)]
+pub struct ModelTable {
+    rules: Vec<ModelAlias>,
+    /// 运行时设置的 thinking 提取开关（按 Kiro 模型 ID），优先于规则中的 `extractThinking`
+    extraction_overrides: Mutex<BTreeMap<String, bool>>,
+}
+
+impl ModelTable {
+    /// 按配置构建映射表，规则缺少模型名或 Kiro 模型 ID 时返回错误
+    pub fn from_config(config: &ModelMappingConfig) -> Result<Self, String> {
+        if let Some(alias) = config
+            .aliases
+            .iter()
+            .find(|a| a.name.trim().is_empty() || a.target.trim().is_empty())
+        {
+            return Err(format!(
+                "modelMapping.aliases 中的规则必须同时设置 name 与 target: {:?}",
+                alias.name
+            ));
+        }
+        let mut rules = config.aliases.clone();
+        if config.builtin {
+            rules.extend(builtin_rules());
+        }
+        Ok(Self {
+            rules,
+            extraction_overrides: Mutex::new(BTreeMap::new()),
+        })
+    }
+
+    /// 查找模型名命中的规则；`-thinking` 变体未单独配置时按去掉后缀的模型名查找
+    fn lookup(&self, model: &str) -> Option<&ModelAlias> {
+        let model = model.to_lowercase();
+        let find = |name: &str| self.rules.iter().find(|r| matches(&r.name, name));
+        find(&model).or_else(|| find(model.strip_suffix(THINKING_SUFFIX)?))
+    }
+
+    /// 映射到的 Kiro 模型 ID
+    pub fn resolve(&self, model: &str) -> Option<&str> {
+        self.lookup(model).map(|r| r.target.as_str())
+    }
+
+    /// 模型的输出 tokens 上限（未映射或未设置上限时为 None）
+    pub fn max_output_tokens(&self, model: &str) -> Option<i32> {
+        self.lookup(model).and_then(|r| r.max_output_tokens)
+    }
+
+    /// Kiro 是否支持该模型的 thinking 模式（未映射的模型视为支持，保持原有行为）
+    pub fn supports_thinking(&self, model: &str) -> bool {
+        self.lookup(model).is_none_or(|r| r.thinking)
+    }
+
+    /// 是否从该模型的流式响应中提取 thinking 块
+    ///
+    /// 关闭后文本不再为探测 `<thinking>` 标签而暂存，直接下发；未映射的模型视为开启
+    pub fn extracts_thinking(&self, model: &str) -> bool {
+        let Some(rule) = self.lookup(model) else {
+            return true;
+        };
+        self.extraction_overrides
+            .lock()
+            .get(&rule.target)
+            .copied()
+            .unwrap_or(rule.extract_thinking)
+    }
+
+    /// 运行时设置模型的 thinking 提取开关，`None` 表示恢复配置值
+    ///
+    /// 开关作用于映射到的 Kiro 模型（同一 Kiro 模型的所有别名），返回该 Kiro 模型 ID；
+    /// 模型未命中任何映射规则时返回 `None`
+    pub fn set_thinking_extraction(&self, model: &str, enabled: Option<bool>) -> Option<String> {
+        let target = self.resolve(model)?.to_string();
+        let mut overrides = self.extraction_overrides.lock();
+        match enabled {
+            Some(enabled) => overrides.insert(target.clone(), enabled),
+            None => overrides.remove(&target),
+        };
+        Some(target)
+    }
+
+    /// 运行时设置的 thinking 提取开关
+    pub fn thinking_extraction_overrides(&self) -> BTreeMap<String, bool> {
+        self.extraction_overrides.lock().clone()
+    }
+
+    /// 对外公布的模型列表
+    ///
+    /// 支持 thinking 的模型额外公布一个 `-thinking` 变体
+    pub fn models(&self) -> Vec<Model> {
+        let mut models: Vec<Model> = Vec::new();
+        for rule in self
+            .rules
+            .iter()
+            .filter(|r| r.list && !r.name.contains('*'))
+        {
+            // 同名规则只有第一条生效
+            if models.iter().any(|m| m.id.eq_ignore_ascii_case(&rule.name)) {
+                continue;
+            }
+            let display_name = rule.display_name.as_deref().unwrap_or(&rule.name);
+            models.push(model(rule, rule.name.clone(), display_name.to_string()));
+            if rule.thinking {
+                models.push(model(
+                    rule,
+                    format!("{}{}", rule.name, THINKING_SUFFIX),
+                    format!("{} (Thinking)", display_name),
+                ));
+            }
+        }
+        models
+    }
+}
+
+/// 内置规则：公布的模型在前，通配规则在后
+fn builtin_rules() -> Vec<ModelAlias> {
+    let listed = MODEL_PROFILES.iter().map(|p| ModelAlias {
+        name: p.id.to_string(),
+        target: p.kiro_id.to_string(),
+        thinking: p.thinking,
+        extract_thinking: true,
+        list: true,
+        display_name: Some(p.display_name.to_string()),
+        created: p.created,
+        max_output_tokens: Some(p.max_output_tokens),
+    });
+    let fallback = FALLBACK_RULES.iter().map(|&(name, target)| {
+        let profile = MODEL_PROFILES.iter().find(|p| p.kiro_id == target);
+        ModelAlias {
+            name: name.to_string(),
+            target: target.to_string(),
+            thinking: profile.is_none_or(|p| p.thinking),
+            extract_thinking: true,
+            list: false,
+            display_name: None,
+            created: 0,
+            max_output_tokens: profile.map(|p| p.max_output_tokens),
+        }
+    });
+    listed.chain(fallback).collect()
+}
+
+/// 模型名是否匹配规则（不区分大小写，`*` 匹配任意字符）
+pub fn matches(pattern: &str, name: &str) -> bool {
+    let pattern = pattern.to_lowercase();
+    let mut parts = pattern.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = name.strip_prefix(first) else {
+        return false;
+    };
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // 没有通配符：精确匹配
+        return rest.is_empty();
+    };
+    for part in middle {
+        match rest.find(part) {
+            Some(pos) => rest = &rest[pos + part.len()..],
+            None => return false,
+        }
+    }
+    rest.ends_with(last)
+}
+
+static MODEL_TABLE: OnceLock<ModelTable> = OnceLock::new();
+
+/// 初始化全局模型映射表
+///
+/// 应在应用启动时调用一次；未初始化时使用默认配置
+pub fn init(config: &ModelMappingConfig) -> Result<(), String> {
+    let table = ModelTable::from_config(config)?;
+    let _ = MODEL_TABLE.set(table);
+    Ok(())
+}
+
+fn table() -> &'static ModelTable {
+    MODEL_TABLE.get_or_init(|| {
+        ModelTable::from_config(&ModelMappingConfig::default()).expect("内置模型映射有效")
+    })
+}
+
+/// 模型映射：将客户端模型名映射到 Kiro 模型 ID
+pub fn resolve(model: &str) -> Option<String> {
+    table().resolve(model).map(str::to_string)
+}
+
+/// 模型的输出 tokens 上限
+pub fn max_output_tokens(model: &str) -> Option<i32> {
+    table().max_output_tokens(model)
+}
+
+/// Kiro 是否支持该模型的 thinking 模式
+pub fn supports_thinking(model: &str) -> bool {
+    table().supports_thinking(model)
+}
+
+/// 是否从该模型的流式响应中提取 thinking 块
+pub fn extracts_thinking(model: &str) -> bool {
+    table().extracts_thinking(model)
+}
+
+/// 运行时设置模型的 thinking 提取开关，返回映射到的 Kiro 模型 ID
+pub fn set_thinking_extraction(model: &str, enabled: Option<bool>) -> Option<String> {
+    table().set_thinking_extraction(model, enabled)
+}
+
+/// 运行时设置的 thinking 提取开关
+pub fn thinking_extraction_overrides() -> BTreeMap<String, bool> {
+    table().thinking_extraction_overrides()
+}
+
+/// 对外公布的模型列表
+pub fn available_models() -> Vec<Model> {
+    table().models()
+}
+
+fn model(rule: &ModelAlias, id: String, display_name: String) -> Model {
+    Model {
+        id,
+        object: "model".to_string(),
+        created: rule.created,
+        owned_by: "anthropic".to_string(),
+        display_name,
+        model_type: "chat".to_string(),
+        max_tokens: rule.max_output_tokens.unwrap_or(MAX_TOKENS),
+        thinking: rule.thinking,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::anthropic::converter::map_model;
+
+    #[test]
+    fn test_profiles_match_model_mapping() {
+        for profile in MODEL_PROFILES {
+            assert_eq!(map_model(profile.id).as_deref(), Some(profile.kiro_id));
+        }
+    }
+
+    #[test]
+    fn test_thinking_variants_follow_capability() {
+        let ids: Vec<_> = available_models().into_iter().map(|m| m.id).collect();
+        assert!(ids.contains(&"claude-sonnet-4-6-thinking".to_string()));
+        assert!(ids.contains(&"claude-haiku-4-5-20251001".to_string()));
+        assert!(!ids.contains(&"claude-haiku-4-5-20251001-thinking".to_string()));
+
+        assert!(supports_thinking("claude-opus-4.6"));
+        assert!(!supports_thinking("claude-haiku-4.5"));
+    }
+
+    #[test]
+    fn test_glob_matching() {
+        assert!(matches("*sonnet*4-6*", "claude-sonnet-4-6-20260101"));
+        assert!(matches("Claude-*", "claude-x"));
+        assert!(matches("gpt-4o", "gpt-4o"));
+        assert!(!matches("gpt-4o", "gpt-4o-mini"));
+        assert!(!matches("*opus*4-5*", "claude-opus-4-6"));
+        assert!(matches("a*a", "aa"));
+        assert!(!matches("a*a", "a"));
+    }
+
+    #[test]
+    fn test_custom_aliases_override_builtin() {
+        let config = ModelMappingConfig {
+            aliases: vec![
+                ModelAlias {
+                    name: "fast".to_string(),
+                    target: "claude-haiku-4.5".to_string(),
+                    thinking: false,
+                    display_name: Some("Fast".to_string()),
+                    ..Default::default()
+                },
+                ModelAlias {
+                    name: "*sonnet*".to_string(),
+                    target: "claude-sonnet-4.6".to_string(),
+                    ..Default::default()
+                },
+            ],
+            builtin: true,
+        };
+        let table = ModelTable::from_config(&config).unwrap();
+        assert_eq!(table.resolve("FAST"), Some("claude-haiku-4.5"));
+        assert_eq!(
+            table.resolve("claude-3-5-sonnet-20241022"),
+            Some("claude-sonnet-4.6")
+        );
+        assert_eq!(table.resolve("claude-opus-4-6"), Some("claude-opus-4.6"));
+        assert!(!table.supports_thinking("fast"));
+        assert_eq!(table.max_output_tokens("fast"), None);
+        assert_eq!(table.max_output_tokens("claude-opus-4-6"), Some(128000));
+        // 通配规则沿用映射到的模型的上限
+        assert_eq!(table.max_output_tokens("claude-3-opus"), Some(128000));
+
+        let models = table.models();
+        assert_eq!(models[0].id, "fast");
+        assert_eq!(models[0].display_name, "Fast");
+        assert!(!models.iter().any(|m| m.id == "fast-thinking"));
+
+        let table = ModelTable::from_config(&ModelMappingConfig {
+            builtin: false,
+            ..config
+        })
+        .unwrap();
+        assert_eq!(table.resolve("claude-opus-4-6"), None);
+        assert_eq!(table.models().len(), 1);
+    }
+
+    #[test]
+    fn test_thinking_suffix_falls_back_to_base_alias() {
+        let config = ModelMappingConfig {
+            aliases: vec![ModelAlias {
+                name: "kiro-sonnet".to_string(),
+                target: "claude-sonnet-4.5".to_string(),
+                ..Default::default()
+            }],
+            builtin: false,
+        };
+        let table = ModelTable::from_config(&config).unwrap();
+        assert_eq!(
+            table.resolve("kiro-sonnet-thinking"),
+            Some("claude-sonnet-4.5")
+        );
+        let ids: Vec<_> = table.models().into_iter().map(|m| m.id).collect();
+        assert_eq!(ids, ["kiro-sonnet", "kiro-sonnet-thinking"]);
+
+        let invalid = ModelMappingConfig {
+            aliases: vec![ModelAlias::default()],
+            builtin: true,
+        };
+        assert!(ModelTable::from_config(&invalid).is_err());
+    }
+
+    #[test]
+    fn test_thinking_extraction_toggle() {
+        let config = ModelMappingConfig {
+            aliases: vec![ModelAlias {
+                name: "plain".to_string(),
+                target: "claude-haiku-4.5".to_string(),
+                extract_thinking: false,
+                ..Default::default()
+            }],
+            builtin: true,
+        };
+        let table = ModelTable::from_config(&config).unwrap();
+        assert!(!table.extracts_thinking("plain"));
+        assert!(table.extracts_thinking("claude-haiku-4-5-20251001"));
+        assert!(table.extracts_thinking("unmapped-model"));
+
+        // 运行时开关作用于同一 Kiro 模型的所有别名，优先于配置
+        assert_eq!(
+            table.set_thinking_extraction("claude-haiku-4-5-20251001", Some(false)),
+            Some("claude-haiku-4.5".to_string())
+        );
+        assert!(!table.extracts_thinking("claude-3-haiku"));
+        table.set_thinking_extraction("plain", Some(true));
+        assert!(table.extracts_thinking("plain"));
+        assert_eq!(
+            table.thinking_extraction_overrides(),
+            BTreeMap::from([("claude-haiku-4.5".to_string(), true)])
+        );
+
+        // 恢复配置值
+        table.set_thinking_extraction("plain", None);
+        assert!(!table.extracts_thinking("plain"));
+        assert!(table.thinking_extraction_overrides().is_empty());
+        assert_eq!(table.set_thinking_extraction("unmapped-model", None), None);
+    }
+}
@@ -0,0 +1,131 @@
+//! 消息内容规范化
+//!
+//! 客户端偶尔会发送 `content: []`、`""` 或仅包含空白的文本块（如重试时残留的
+//! `{"type": "text", "text": " "}`）。Kiro 对空内容的处理不稳定，因此在转换前统一规范化：
+//!
+//! 1. 空文本块和仅含空白的文本块被移除；仅含空白的字符串内容视为空
+//! 2. 规范化后为空的 user 消息被丢弃并记录警告（末尾的 user 消息除外，它是当前消息，
+//!    由转换器替换为 `emptyContentPlaceholder`）
+//! 3. 规范化后为空的 assistant 消息被丢弃并记录警告
+//!
+//! 丢弃消息后相邻的同角色消息会在分组阶段合并。转换阶段的占位规则见
+//! [`ASSISTANT_PLACEHOLDER`] 与 `converter.emptyContentPlaceholder`
+
+use std::borrow::Cow;
+
+use super::types::Message;
+
+/// assistant 消息没有文本（如仅包含 tool_use）时使用的占位内容
+///
+/// Kiro 要求 assistant 的 content 字段不能为空
+pub const ASSISTANT_PLACEHOLDER: &str = " ";
+
+/// 规范化消息列表，未修改的消息不会被复制
+pub fn normalize_messages(messages: &[Message]) -> Vec<Cow<'_, Message>> {
+    let last = messages.len().saturating_sub(1);
+    let mut normalized = Vec::with_capacity(messages.len());
+
+    for (index, msg) in messages.iter().enumerate() {
+        let content = match normalize_content(&msg.content) {
+            Some(content) => Cow::Owned(Message {
+                role: msg.role.clone(),
+                content,
+            }),
+            None => Cow::Borrowed(msg),
+        };
+
+        if index != last && is_empty_content(&content.content) {
+            tracing::warn!("丢弃内容为空的 {} 消息（第 {} 条）", msg.role, index + 1);
+            continue;
+        }
+        normalized.push(content);
+    }
+
+    normalized
+}
+
+/// 移除空白文本块；内容无需修改时返回 None
+fn normalize_content(content: &serde_json::Value) -> Option<serde_json::Value> {
+    match content {
+        serde_json::Value::String(s) if s.trim().is_empty() && !s.is_empty() => {
+            Some(serde_json::Value::String(String::new()))
+        }
+        serde_json::Value::Array(blocks) if blocks.iter().any(is_blank_text_block) => {
+            Some(serde_json::Value::Array(
+                blocks
+                    .iter()
+                    .filter(|block| !is_blank_text_block(block))
+                    .cloned()
+                    .collect(),
+            ))
+        }
+        _ => None,
+    }
+}
+
+fn is_blank_text_block(block: &serde_json::Value) -> bool {
+    block.get("type").and_then(|t| t.as_str()) == Some("text")
+        && block
+            .get("text")
+            .and_then(|t| t.as_str())
+            .is_none_or(|text| text.trim().is_empty())
+}
+
+fn is_empty_content(content: &serde_json::Value) -> bool {
+    match content {
+        serde_json::Value::String(s) => s.is_empty(),
+        serde_json::Value::Array(blocks) => blocks.is_empty(),
+        _ => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn msg(role: &str, content: serde_json::Value) -> Message {
+        Message {
+            role: role.to_string(),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_blank_text_blocks_removed() {
+        let messages = vec![
+            msg(
+                "assistant",
+                json!([
+                    {"type": "text", "text": " \n"},
+                    {"type": "tool_use", "id": "t1", "name": "read", "input": {}}
+                ]),
+            ),
+            msg("user", json!("hello")),
+        ];
+
+        let normalized = normalize_messages(&messages);
+        assert_eq!(normalized.len(), 2);
+        assert_eq!(
+            normalized[0].content,
+            json!([{"type": "tool_use", "id": "t1", "name": "read", "input": {}}])
+        );
+        assert!(matches!(normalized[1], Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_empty_turns_dropped_except_current() {
+        let messages = vec![
+            msg("user", json!("first")),
+            msg("assistant", json!([{"type": "text", "text": ""}])),
+            msg("user", json!([])),
+            msg("assistant", json!("reply")),
+            msg("user", json!("   ")),
+        ];
+
+        let normalized = normalize_messages(&messages);
+        let roles: Vec<_> = normalized.iter().map(|m| m.role.as_str()).collect();
+        assert_eq!(roles, vec!["user", "assistant", "user"]);
+        assert_eq!(normalized[2].content, json!(""));
+    }
+}
@@ -0,0 +1,93 @@
+//! 请求费用估算
+//!
+//! 客户端模型名经模型映射得到 Kiro 模型 ID，按 `pricing.models` 中该模型的单价和
+//! `pricing.webSearchPerRequest` 估算费用。费用随用量写入用量报表，并通过响应用量中的
+//! `kiro_estimated_cost` 扩展字段返回，供按团队核算成本
+
+use crate::common::usage::Price;
+use crate::model::config::PricingConfig;
+
+use super::models;
+
+/// 校验费用配置（启动时调用）
+pub fn validate_config(config: &PricingConfig) -> Result<(), String> {
+    if !is_valid_price(config.web_search_per_request) {
+        return Err("webSearchPerRequest 必须是非负数".to_string());
+    }
+    for (model, price) in &config.models {
+        if !is_valid_price(price.input_per_million) || !is_valid_price(price.output_per_million) {
+            return Err(format!("模型 {} 的单价必须是非负数", model));
+        }
+    }
+    Ok(())
+}
+
+fn is_valid_price(price: f64) -> bool {
+    price.is_finite() && price >= 0.0
+}
+
+/// Kiro 模型的单价（未配置时为 None）
+fn kiro_price(config: &PricingConfig, kiro_model: &str) -> Option<Price> {
+    config
+        .models
+        .iter()
+        .find(|(model, _)| model.eq_ignore_ascii_case(kiro_model))
+        .map(|(_, price)| Price {
+            model: *price,
+            web_search: config.web_search_per_request,
+        })
+}
+
+/// 客户端模型名对应的单价（模型未映射或未配置单价时为 None）
+pub fn price_for(config: &PricingConfig, model: &str) -> Option<Price> {
+    if config.models.is_empty() {
+        return None;
+    }
+    kiro_price(config, &models::resolve(model)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::model::config::ModelPrice;
+
+    fn config() -> PricingConfig {
+        PricingConfig {
+            models: BTreeMap::from([(
+                "claude-sonnet-4.5".to_string(),
+                ModelPrice {
+                    input_per_million: 3.0,
+                    output_per_million: 15.0,
+                },
+            )]),
+            web_search_per_request: 0.01,
+        }
+    }
+
+    #[test]
+    fn test_kiro_price() {
+        let config = config();
+        let price = kiro_price(&config, "CLAUDE-SONNET-4.5").unwrap();
+        assert_eq!(price.model.output_per_million, 15.0);
+        assert_eq!(price.web_search, 0.01);
+        assert!(kiro_price(&config, "claude-opus-4.6").is_none());
+        assert!(price_for(&PricingConfig::default(), "claude-sonnet-4-5").is_none());
+    }
+
+    #[test]
+    fn test_validate_config() {
+        assert!(validate_config(&config()).is_ok());
+        let mut negative = config();
+        negative.web_search_per_request = -1.0;
+        assert!(validate_config(&negative).is_err());
+        let mut negative = config();
+        negative
+            .models
+            .get_mut("claude-sonnet-4.5")
+            .unwrap()
+            .input_per_million = f64::NAN;
+        assert!(validate_config(&negative).is_err());
+    }
+}
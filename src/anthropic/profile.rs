@@ -0,0 +1,145 @@
+//! 按请求选择 Kiro profile ARN
+//!
+//! 默认所有请求使用第一个凭据的 profileArn。为了让一个部署服务多个 Kiro profile，
+//! 按以下顺序选择请求使用的 profile ARN：
+//! 1. 请求头 `x-kiro-profile-arn`（必须在 `profiles.allowed` 白名单中）
+//! 2. `profiles.byApiKey` 中为请求所用 API Key 配置的 ARN
+//! 3. 第一个凭据的 profileArn
+//!
+//! 选中的 ARN 会记入用量报表
+
+use axum::http::HeaderMap;
+
+use crate::model::config::ProfilesConfig;
+
+use super::middleware::AppState;
+
+/// 指定 profile ARN 的请求头
+pub const PROFILE_ARN_HEADER: &str = "x-kiro-profile-arn";
+
+/// 校验 profile ARN 格式：`arn:<partition>:<service>:<region>:<account>:profile/<id>`
+pub fn is_valid_arn(arn: &str) -> bool {
+    let parts: Vec<&str> = arn.splitn(6, ':').collect();
+    let [prefix, partition, service, region, account, resource] = parts[..] else {
+        return false;
+    };
+    let is_name = |s: &str| {
+        s.chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')
+    };
+    let id = resource.strip_prefix("profile/").unwrap_or_default();
+    prefix == "arn"
+        && partition.starts_with("aws")
+        && is_name(partition)
+        && !service.is_empty()
+        && is_name(service)
+        && is_name(region)
+        && !account.is_empty()
+        && account.chars().all(|c| c.is_ascii_digit())
+        && !id.is_empty()
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+/// 校验配置中的 profile ARN（启动时调用）
+pub fn validate_config(config: &ProfilesConfig) -> Result<(), String> {
+    config
+        .by_api_key
+        .values()
+        .chain(&config.allowed)
+        .find(|arn| !is_valid_arn(arn))
+        .map_or(Ok(()), |arn| Err(format!("无效的 profile ARN: {}", arn)))
+}
+
+/// 选择请求使用的 profile ARN
+///
+/// 请求头中的 ARN 格式无效或不在白名单中时返回错误信息
+pub fn resolve(state: &AppState, headers: &HeaderMap) -> Result<Option<String>, String> {
+    let profiles = &state.config.profiles;
+    if let Some(value) = headers.get(PROFILE_ARN_HEADER) {
+        let arn = value
+            .to_str()
+            .map_err(|_| format!("{} is not a valid profile ARN", PROFILE_ARN_HEADER))?
+            .trim();
+        if !is_valid_arn(arn) {
+            return Err(format!("{} is not a valid profile ARN", PROFILE_ARN_HEADER));
+        }
+        if !profiles.allowed.iter().any(|a| a == arn) {
+            return Err(format!("profile ARN {} is not allowed", arn));
+        }
+        return Ok(Some(arn.to_string()));
+    }
+
+    Ok(profiles
+        .by_api_key
+        .get(&state.api_key)
+        .or(state.profile_arn.as_ref())
+        .cloned())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::model::config::Config;
+
+    const ARN_A: &str = "arn:aws:codewhisperer:us-east-1:699475941385:profile/EHGA3GRVQMUK";
+    const ARN_B: &str = "arn:aws:codewhisperer:eu-central-1:123456789012:profile/ABC123";
+
+    #[test]
+    fn test_arn_format() {
+        assert!(is_valid_arn(ARN_A));
+        assert!(is_valid_arn("arn:aws:sso::123456789:profile/test"));
+        assert!(!is_valid_arn(
+            "arn:aws:codewhisperer:us-east-1:69947594138x:profile/A"
+        ));
+        assert!(!is_valid_arn(
+            "arn:aws:codewhisperer:us-east-1:699475941385:role/A"
+        ));
+        assert!(!is_valid_arn(
+            "arn:aws:codewhisperer:us-east-1:699475941385:profile/"
+        ));
+        assert!(!is_valid_arn("EHGA3GRVQMUK"));
+    }
+
+    #[test]
+    fn test_resolve_order() {
+        let mut config = Config::default();
+        config.profiles = ProfilesConfig {
+            by_api_key: BTreeMap::from([("sk-team-a".to_string(), ARN_A.to_string())]),
+            allowed: vec![ARN_B.to_string()],
+        };
+        assert!(validate_config(&config.profiles).is_ok());
+
+        let state = AppState::new("sk-team-a")
+            .with_config(config)
+            .with_profile_arn("arn:aws:codewhisperer:us-east-1:111111111111:profile/DEFAULT");
+        let mut headers = HeaderMap::new();
+        assert_eq!(resolve(&state, &headers).unwrap().as_deref(), Some(ARN_A));
+
+        headers.insert(PROFILE_ARN_HEADER, ARN_B.parse().unwrap());
+        assert_eq!(resolve(&state, &headers).unwrap().as_deref(), Some(ARN_B));
+
+        // 格式正确但不在白名单中
+        headers.insert(
+            PROFILE_ARN_HEADER,
+            "arn:aws:codewhisperer:us-east-1:222222222222:profile/OTHER"
+                .parse()
+                .unwrap(),
+        );
+        assert!(
+            resolve(&state, &headers)
+                .unwrap_err()
+                .contains("not allowed")
+        );
+
+        // 未为该 Key 配置时使用默认 ARN
+        let state = state.with_api_key("sk-team-b");
+        assert_eq!(
+            resolve(&state, &HeaderMap::new()).unwrap().as_deref(),
+            Some("arn:aws:codewhisperer:us-east-1:111111111111:profile/DEFAULT")
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! 代码引用归属
+//!
+//! 收集上游 codeReferenceEvent 给出的许可证归属信息，按配置在回复末尾追加引用说明
+
+use crate::kiro::model::events::{CodeReference, CodeReferenceEvent};
+
+/// 引用收集器
+#[derive(Debug, Default)]
+pub struct ReferenceCollector {
+    references: Vec<CodeReference>,
+}
+
+impl ReferenceCollector {
+    /// 记录事件中的引用（按仓库、链接和许可证去重）
+    pub fn push(&mut self, event: &CodeReferenceEvent) {
+        for reference in &event.references {
+            let duplicate = self.references.iter().any(|r| {
+                r.repository == reference.repository
+                    && r.url == reference.url
+                    && r.license_name == reference.license_name
+            });
+            if !duplicate {
+                self.references.push(reference.clone());
+            }
+        }
+    }
+
+    /// 渲染为追加到回复末尾的引用说明，没有引用时返回 None
+    pub fn render(&self) -> Option<String> {
+        if self.references.is_empty() {
+            return None;
+        }
+
+        let mut out = String::from("\n\n---\nCode references:\n");
+        for reference in &self.references {
+            let source = reference
+                .repository
+                .as_deref()
+                .or(reference.url.as_deref())
+                .unwrap_or("unknown source");
+            out.push_str("- ");
+            out.push_str(source);
+            if let Some(license) = &reference.license_name {
+                out.push_str(&format!(" ({})", license));
+            }
+            if let Some(url) = &reference.url
+                && reference.repository.is_some()
+            {
+                out.push_str(&format!(" <{}>", url));
+            }
+            out.push('\n');
+        }
+        Some(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn reference(repository: &str, license: &str) -> CodeReference {
+        CodeReference {
+            license_name: Some(license.to_string()),
+            repository: Some(repository.to_string()),
+            url: Some(format!("https://github.com/{}", repository)),
+            ..CodeReference::default()
+        }
+    }
+
+    #[test]
+    fn test_render_dedups_references() {
+        let mut collector = ReferenceCollector::default();
+        assert_eq!(collector.render(), None);
+
+        let event = CodeReferenceEvent {
+            references: vec![reference("foo/bar", "MIT"), reference("foo/bar", "MIT")],
+        };
+        collector.push(&event);
+        collector.push(&CodeReferenceEvent {
+            references: vec![reference("baz/qux", "Apache-2.0")],
+        });
+
+        assert_eq!(
+            collector.render().unwrap(),
+            "\n\n---\nCode references:\n\
+             - foo/bar (MIT) <https://github.com/foo/bar>\n\
+             - baz/qux (Apache-2.0) <https://github.com/baz/qux>\n"
+        );
+    }
+}
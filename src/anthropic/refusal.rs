@@ -0,0 +1,193 @@
+//! 拒绝回复识别
+//!
+//! Kiro 没有 `refusal` 停止原因：安全拒绝要么以异常事件结束，要么是一段普通的回复文本。
+//! 按 `stream.refusal` 配置识别这两种情况，将 `stop_reason` 报告为 `refusal`，
+//! 没有文本输出时可补发一个内容固定的文本块，客户端的拒绝处理逻辑与对接官方 API 时一致
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::kiro::model::events::exception_name;
+use crate::model::config::RefusalConfig;
+
+/// 参与正则匹配的回复开头长度（字符数）
+const TEXT_WINDOW_CHARS: usize = 512;
+
+/// 拒绝回复分类器
+#[derive(Debug)]
+pub struct RefusalClassifier {
+    exceptions: Vec<String>,
+    patterns: Vec<Regex>,
+    message: Option<String>,
+}
+
+impl RefusalClassifier {
+    /// 根据配置创建分类器，未配置任何规则时返回 None
+    ///
+    /// 无效的正则会被忽略并输出警告
+    pub fn new(config: &RefusalConfig) -> Option<Self> {
+        let patterns: Vec<Regex> = config
+            .patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    tracing::warn!("忽略无效的拒绝识别正则 {}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+        if config.exceptions.is_empty() && patterns.is_empty() {
+            return None;
+        }
+        Some(Self {
+            exceptions: config.exceptions.clone(),
+            patterns,
+            message: Some(config.message.clone()).filter(|m| !m.is_empty()),
+        })
+    }
+
+    /// 上游异常是否表示拒绝
+    pub fn matches_exception(&self, exception_type: &str) -> bool {
+        let name = exception_name(exception_type);
+        self.exceptions.iter().any(|known| known == name)
+    }
+
+    /// 回复文本是否为拒绝（只匹配开头部分）
+    pub fn matches_text(&self, text: &str) -> bool {
+        let end = text
+            .char_indices()
+            .nth(TEXT_WINDOW_CHARS)
+            .map_or(text.len(), |(i, _)| i);
+        let window = &text[..end];
+        self.patterns.iter().any(|re| re.is_match(window))
+    }
+
+    /// 没有文本输出时补发的文本
+    pub fn message(&self) -> Option<&str> {
+        self.message.as_deref()
+    }
+
+    /// 创建单个响应的识别状态
+    pub fn detector(&'static self) -> RefusalDetector {
+        RefusalDetector {
+            classifier: self,
+            text: String::new(),
+            chars: 0,
+            exception: false,
+        }
+    }
+}
+
+/// 单个流式响应的拒绝识别状态
+#[derive(Debug)]
+pub struct RefusalDetector {
+    classifier: &'static RefusalClassifier,
+    /// 已下发文本的开头部分
+    text: String,
+    /// `text` 的字符数
+    chars: usize,
+    /// 是否收到了表示拒绝的异常
+    exception: bool,
+}
+
+impl RefusalDetector {
+    /// 记录下发的文本（只保留匹配窗口内的部分）
+    pub fn observe_text(&mut self, text: &str) {
+        for c in text.chars().take(TEXT_WINDOW_CHARS - self.chars) {
+            self.text.push(c);
+            self.chars += 1;
+        }
+    }
+
+    /// 记录上游异常，返回是否表示拒绝
+    pub fn observe_exception(&mut self, exception_type: &str) -> bool {
+        let refused = self.classifier.matches_exception(exception_type);
+        self.exception |= refused;
+        refused
+    }
+
+    /// 响应结束时判断是否为拒绝
+    pub fn refused(&self) -> bool {
+        self.exception || self.classifier.matches_text(&self.text)
+    }
+
+    /// 识别为拒绝后需要补发的文本，下发过文本时返回 None
+    pub fn fallback_text(&self) -> Option<&'static str> {
+        self.classifier.message().filter(|_| self.text.is_empty())
+    }
+}
+
+static CLASSIFIER: OnceLock<Option<RefusalClassifier>> = OnceLock::new();
+
+/// 初始化全局拒绝识别配置
+///
+/// 应在应用启动时调用一次；未初始化时不识别
+pub fn init(config: &RefusalConfig) {
+    let _ = CLASSIFIER.set(RefusalClassifier::new(config));
+}
+
+/// 全局拒绝回复分类器，未配置规则时返回 None
+pub fn classifier() -> Option<&'static RefusalClassifier> {
+    CLASSIFIER.get_or_init(|| None).as_ref()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config() -> RefusalConfig {
+        RefusalConfig {
+            exceptions: vec!["GuardrailInterventionException".to_string()],
+            patterns: vec![r"^I can(no|')t help with".to_string(), "(".to_string()],
+            message: "I can't help with that.".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_classifier() {
+        assert!(RefusalClassifier::new(&RefusalConfig::default()).is_none());
+
+        let classifier = RefusalClassifier::new(&config()).unwrap();
+        assert_eq!(classifier.patterns.len(), 1);
+        assert!(
+            classifier
+                .matches_exception("com.amazon.aws.codewhisperer#GuardrailInterventionException")
+        );
+        assert!(!classifier.matches_exception("ThrottlingException"));
+        assert!(classifier.matches_text("I can't help with that request."));
+        assert!(!classifier.matches_text("Sure, I can't wait to help with that."));
+
+        // 只匹配回复的开头部分
+        let long = format!("{}I cannot help with", "x".repeat(TEXT_WINDOW_CHARS));
+        let late = RefusalClassifier::new(&RefusalConfig {
+            patterns: vec!["I cannot help".to_string()],
+            ..config()
+        })
+        .unwrap();
+        assert!(!late.matches_text(&long));
+    }
+
+    #[test]
+    fn test_detector() {
+        let classifier: &'static RefusalClassifier =
+            Box::leak(Box::new(RefusalClassifier::new(&config()).unwrap()));
+
+        let mut detector = classifier.detector();
+        assert!(detector.observe_exception("GuardrailInterventionException"));
+        assert!(detector.refused());
+        assert_eq!(detector.fallback_text(), Some("I can't help with that."));
+
+        // 文本分多段到达
+        let mut detector = classifier.detector();
+        detector.observe_text("I can");
+        detector.observe_text("not help with this.");
+        assert!(detector.refused());
+        assert_eq!(detector.fallback_text(), None);
+
+        let mut detector = classifier.detector();
+        detector.observe_text("Here is the answer.");
+        assert!(!detector.refused());
+    }
+}
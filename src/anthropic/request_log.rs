@@ -0,0 +1,465 @@
+//! 请求/响应日志
+//!
+//! 启用 `logging.requests.enabled` 后，每个消息请求（模型、消息数、转换后的 Kiro 请求体）连同
+//! 响应摘要（状态码、stop_reason、用量、内容块类型、错误）以一行 JSON 追加写入缓存目录下的
+//! `kiro_request_logs/requests.jsonl`，用于复现用户报告的转换问题。
+//!
+//! 文件超过 `maxFileBytes` 时轮转为 `requests.1.jsonl`、`requests.2.jsonl`……，最多保留 `maxFiles` 个文件。
+//! Kiro 请求体按日志脱敏配置处理（API Key、Token、base64 图片，严格模式下只记录长度）
+
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    body::{Body, to_bytes},
+    http::header,
+    response::Response,
+};
+use chrono::Utc;
+use futures::StreamExt;
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::Value;
+
+use crate::common::{redact, usage};
+use crate::model::config::RequestLogConfig;
+
+use super::canary::ConverterVersion;
+use super::delivery::EventStream;
+use super::types::MessagesRequest;
+
+/// 当前写入的日志文件名
+const CURRENT_FILE: &str = "requests.jsonl";
+
+/// 请求中各角色的消息数
+#[derive(Debug, Default, Serialize)]
+struct MessageCounts {
+    total: usize,
+    user: usize,
+    assistant: usize,
+}
+
+/// 响应摘要
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct ResponseSummary {
+    status: u16,
+    /// 是否完整结束（非流式响应成功，或流式响应收到 `message_stop`）
+    completed: bool,
+    stop_reason: Option<String>,
+    input_tokens: Option<i64>,
+    output_tokens: Option<i64>,
+    /// 按顺序排列的内容块类型
+    content: Vec<String>,
+    /// Anthropic 格式的错误（`{type, message}`）
+    error: Option<Value>,
+    duration_ms: u64,
+}
+
+/// 一行日志
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RequestRecord {
+    id: String,
+    /// 请求时间（RFC3339）
+    at: String,
+    /// 请求端点，如 `/v1/messages`
+    endpoint: String,
+    /// 脱敏后的 API Key
+    key: String,
+    model: String,
+    stream: bool,
+    messages: MessageCounts,
+    tools: usize,
+    converter: String,
+    /// Kiro 请求体原始大小（字节）
+    kiro_request_bytes: usize,
+    /// 脱敏后的 Kiro 请求体（严格模式下为省略说明）
+    kiro_request: Value,
+    response: ResponseSummary,
+}
+
+/// 已打开的日志文件
+struct LogFile {
+    file: File,
+    size: u64,
+}
+
+/// 请求日志存储
+pub struct RequestLogStore {
+    dir: PathBuf,
+    max_file_bytes: u64,
+    max_files: usize,
+    file: Mutex<Option<LogFile>>,
+}
+
+impl RequestLogStore {
+    /// 创建日志目录
+    pub fn new(dir: PathBuf, config: &RequestLogConfig) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        Ok(Self {
+            dir,
+            max_file_bytes: config.max_file_bytes.max(1),
+            max_files: config.max_files.max(1),
+            file: Mutex::new(None),
+        })
+    }
+
+    /// 开始记录一个已完成转换的请求
+    pub fn start(
+        self: &Arc<Self>,
+        endpoint: &str,
+        api_key: &str,
+        request: &MessagesRequest,
+        converter: ConverterVersion,
+        kiro_body: &str,
+    ) -> RequestLog {
+        let mut messages = MessageCounts {
+            total: request.messages.len(),
+            ..Default::default()
+        };
+        for message in &request.messages {
+            match message.role.as_str() {
+                "user" => messages.user += 1,
+                "assistant" => messages.assistant += 1,
+                _ => {}
+            }
+        }
+        let redacted = redact::body(kiro_body);
+        let kiro_request = serde_json::from_str(&redacted)
+            .unwrap_or_else(|_| Value::String(redacted.into_owned()));
+
+        RequestLog {
+            store: self.clone(),
+            started_at: Instant::now(),
+            pending: Vec::new(),
+            record: RequestRecord {
+                id: uuid::Uuid::new_v4().to_string(),
+                at: Utc::now().to_rfc3339(),
+                endpoint: endpoint.to_string(),
+                key: usage::key_label(api_key),
+                model: request.model.clone(),
+                stream: request.stream,
+                messages,
+                tools: request.tools.as_ref().map_or(0, Vec::len),
+                converter: format!("{:?}", converter).to_lowercase(),
+                kiro_request_bytes: kiro_body.len(),
+                kiro_request,
+                response: ResponseSummary::default(),
+            },
+        }
+    }
+
+    fn path(&self, index: usize) -> PathBuf {
+        match index {
+            0 => self.dir.join(CURRENT_FILE),
+            n => self.dir.join(format!("requests.{}.jsonl", n)),
+        }
+    }
+
+    /// 追加一行，写入前文件将超出上限时先轮转
+    fn append(&self, line: &[u8]) -> io::Result<()> {
+        let mut file = self.file.lock();
+        if let Some(current) = file.as_ref()
+            && current.size > 0
+            && current.size + line.len() as u64 > self.max_file_bytes
+        {
+            *file = None;
+            self.rotate()?;
+        }
+        if file.is_none() {
+            *file = Some(open_append(&self.path(0))?);
+        }
+        let current = file.as_mut().expect("日志文件已打开");
+        current.file.write_all(line)?;
+        current.size += line.len() as u64;
+        Ok(())
+    }
+
+    /// `requests.jsonl` -> `requests.1.jsonl` -> ...，删除超出 `max_files` 的最早文件
+    fn rotate(&self) -> io::Result<()> {
+        let oldest = self.path(self.max_files - 1);
+        if oldest.exists() {
+            fs::remove_file(oldest)?;
+        }
+        for index in (0..self.max_files - 1).rev() {
+            let from = self.path(index);
+            if from.exists() {
+                fs::rename(from, self.path(index + 1))?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn open_append(path: &Path) -> io::Result<LogFile> {
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+    let size = file.metadata()?.len();
+    Ok(LogFile { file, size })
+}
+
+/// 单个请求的日志
+///
+/// 被释放时写出一行：流式响应在流结束或客户端断开时写出，未收到 `message_stop` 的记为未完成
+pub struct RequestLog {
+    store: Arc<RequestLogStore>,
+    started_at: Instant,
+    /// 尚未读到换行的 SSE 输出
+    pending: Vec<u8>,
+    record: RequestRecord,
+}
+
+impl RequestLog {
+    /// 记录响应摘要并原样返回响应
+    ///
+    /// JSON 响应读出后重建；SSE 响应在下发过程中逐行解析 `data:`，以事件流下发的响应逐个观察事件
+    pub async fn finish(mut self, mut response: Response) -> Response {
+        self.record.response.status = response.status().as_u16();
+        if response.extensions().get::<EventStream>().is_some() {
+            EventStream::inspect(&mut response, move |event| self.observe_event(&event.data));
+            return response;
+        }
+        let is_sse = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+        let (parts, body) = response.into_parts();
+
+        if is_sse {
+            let stream = body.into_data_stream().inspect(move |chunk| {
+                if let Ok(chunk) = chunk {
+                    self.observe_sse(chunk);
+                }
+            });
+            return Response::from_parts(parts, Body::from_stream(stream));
+        }
+
+        // 非流式响应体已在内存中
+        let bytes = to_bytes(body, usize::MAX).await.unwrap_or_default();
+        if let Ok(body) = serde_json::from_slice::<Value>(&bytes) {
+            self.observe_json(&body);
+        }
+        Response::from_parts(parts, Body::from(bytes))
+    }
+
+    /// 非流式响应体：消息或错误
+    fn observe_json(&mut self, body: &Value) {
+        let summary = &mut self.record.response;
+        if body["type"] == "error" {
+            summary.error = Some(body["error"].clone());
+            return;
+        }
+        summary.completed = true;
+        summary.stop_reason = body["stop_reason"].as_str().map(str::to_string);
+        summary.input_tokens = body["usage"]["input_tokens"].as_i64();
+        summary.output_tokens = body["usage"]["output_tokens"].as_i64();
+        summary.content = body["content"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .filter_map(|block| block["type"].as_str().map(str::to_string))
+            .collect();
+    }
+
+    /// 流式响应的一段输出
+    fn observe_sse(&mut self, chunk: &[u8]) {
+        self.pending.extend_from_slice(chunk);
+        while let Some(end) = self.pending.iter().position(|&b| b == b'\n') {
+            let line: Vec<u8> = self.pending.drain(..=end).collect();
+            if let Some(data) = line.strip_prefix(b"data: ")
+                && let Ok(event) = serde_json::from_slice::<Value>(data)
+            {
+                self.observe_event(&event);
+            }
+        }
+    }
+
+    fn observe_event(&mut self, event: &Value) {
+        let summary = &mut self.record.response;
+        match event["type"].as_str() {
+            Some("message_start") => {
+                summary.input_tokens = event["message"]["usage"]["input_tokens"].as_i64();
+            }
+            Some("content_block_start") => {
+                if let Some(block_type) = event["content_block"]["type"].as_str() {
+                    summary.content.push(block_type.to_string());
+                }
+            }
+            Some("message_delta") => {
+                summary.stop_reason = event["delta"]["stop_reason"].as_str().map(str::to_string);
+                if let Some(tokens) = event["usage"]["input_tokens"].as_i64() {
+                    summary.input_tokens = Some(tokens);
+                }
+                summary.output_tokens = event["usage"]["output_tokens"].as_i64();
+            }
+            Some("message_stop") => summary.completed = true,
+            Some("error") => summary.error = Some(event["error"].clone()),
+            _ => {}
+        }
+    }
+}
+
+impl Drop for RequestLog {
+    fn drop(&mut self) {
+        self.record.response.duration_ms = self.started_at.elapsed().as_millis() as u64;
+        let mut line = match serde_json::to_vec(&self.record) {
+            Ok(line) => line,
+            Err(e) => {
+                tracing::warn!("序列化请求日志失败: {}", e);
+                return;
+            }
+        };
+        line.push(b'\n');
+        if let Err(e) = self.store.append(&line) {
+            tracing::warn!("写入请求日志失败: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use axum::http::StatusCode;
+    use axum::response::IntoResponse;
+
+    fn temp_store(config: &RequestLogConfig) -> (Arc<RequestLogStore>, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("kiro-request-log-{}", uuid::Uuid::new_v4()));
+        (
+            Arc::new(RequestLogStore::new(dir.clone(), config).unwrap()),
+            dir,
+        )
+    }
+
+    fn request(stream: bool) -> MessagesRequest {
+        serde_json::from_value(serde_json::json!({
+            "model": "claude-sonnet-4-5",
+            "max_tokens": 1024,
+            "stream": stream,
+            "messages": [
+                {"role": "user", "content": "hi"},
+                {"role": "assistant", "content": "hello"},
+                {"role": "user", "content": "bye"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn test_logs_request_and_response_summary() {
+        let (store, dir) = temp_store(&RequestLogConfig::default());
+        let kiro_body = format!(
+            r#"{{"conversationState":{{"images":[{{"bytes":"{}"}}]}},"auth":"sk-kiro-rs-123456789"}}"#,
+            "A".repeat(100)
+        );
+
+        // 非流式
+        let log = store.start(
+            "/v1/messages",
+            "sk-kiro-rs-abcdefgh",
+            &request(false),
+            ConverterVersion::Stable,
+            &kiro_body,
+        );
+        let body = serde_json::json!({
+            "type": "message",
+            "content": [{"type": "thinking"}, {"type": "text"}],
+            "stop_reason": "end_turn",
+            "usage": {"input_tokens": 12, "output_tokens": 34}
+        });
+        let response = log.finish(axum::Json(body.clone()).into_response()).await;
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(serde_json::from_slice::<Value>(&bytes).unwrap(), body);
+
+        // 流式：以 error 事件结束
+        let log = store.start(
+            "/cc/v1/messages",
+            "sk-kiro-rs-abcdefgh",
+            &request(true),
+            ConverterVersion::Experimental,
+            "{}",
+        );
+        let sse = concat!(
+            "event: message_start\ndata: {\"type\":\"message_start\",\"message\":{\"usage\":{\"input_tokens\":5}}}\n\n",
+            "event: content_block_start\ndata: {\"type\":\"content_block_start\",\"content_block\":{\"type\":\"text\"}}\n\n",
+            "event: error\ndata: {\"type\":\"error\",\"error\":{\"type\":\"rate_limit_error\",\"message\":\"slow down\"}}\n\n",
+        );
+        let response = Response::builder()
+            .header(header::CONTENT_TYPE, "text/event-stream")
+            .body(Body::from(sse))
+            .unwrap();
+        let response = log.finish(response).await;
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(bytes, sse.as_bytes());
+
+        let lines = read_lines(&dir.join(CURRENT_FILE));
+        assert_eq!(lines.len(), 2);
+        let line = &lines[0];
+        assert_eq!(line["endpoint"], "/v1/messages");
+        assert_eq!(line["key"], "sk-k***efgh");
+        assert_eq!(
+            line["messages"],
+            serde_json::json!({"total": 3, "user": 2, "assistant": 1})
+        );
+        assert_eq!(line["converter"], "stable");
+        let kiro = line["kiroRequest"].to_string();
+        assert!(kiro.contains("<base64 100 bytes>"));
+        assert!(!kiro.contains("123456789"));
+        let response = &line["response"];
+        assert_eq!(response["status"], 200);
+        assert_eq!(response["completed"], true);
+        assert_eq!(response["stopReason"], "end_turn");
+        assert_eq!(response["outputTokens"], 34);
+        assert_eq!(response["content"], serde_json::json!(["thinking", "text"]));
+
+        let response = &lines[1]["response"];
+        assert_eq!(lines[1]["converter"], "experimental");
+        assert_eq!(response["completed"], false);
+        assert_eq!(response["inputTokens"], 5);
+        assert_eq!(response["content"], serde_json::json!(["text"]));
+        assert_eq!(response["error"]["type"], "rate_limit_error");
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rotation() {
+        let (store, dir) = temp_store(&RequestLogConfig {
+            enabled: true,
+            max_file_bytes: 1,
+            max_files: 3,
+        });
+        for _ in 0..5 {
+            let log = store.start(
+                "/v1/messages",
+                "",
+                &request(false),
+                ConverterVersion::Stable,
+                "{}",
+            );
+            log.finish(StatusCode::BAD_REQUEST.into_response()).await;
+        }
+        // 每个文件一行，只保留最近 3 个文件
+        assert_eq!(read_lines(&dir.join(CURRENT_FILE)).len(), 1);
+        assert_eq!(read_lines(&dir.join("requests.1.jsonl")).len(), 1);
+        assert_eq!(read_lines(&dir.join("requests.2.jsonl")).len(), 1);
+        assert!(!dir.join("requests.3.jsonl").exists());
+        assert_eq!(
+            read_lines(&dir.join(CURRENT_FILE))[0]["response"]["status"],
+            400
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
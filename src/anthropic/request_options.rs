@@ -0,0 +1,225 @@
+//! 请求级扩展选项
+//!
+//! 请求头 `x-kiro-options` 携带一个 JSON 对象，按请求覆盖服务端的流式与调度配置，
+//! 新的扩展功能在这里增加字段，而不是各自新增请求头：
+//!
+//! ```text
+//! x-kiro-options: {"thinking":"text","coalesceDeltas":false,"strictSse":true,"deadlineMs":30000,"priority":"batch"}
+//! ```
+//!
+//! 原有的单项请求头（`x-kiro-deadline-ms`、`x-kiro-priority`、`x-kiro-converter`）仍然有效，
+//! 与 JSON 中的同名选项同时出现时以单项请求头为准
+
+use std::time::Duration;
+
+use axum::http::HeaderMap;
+use serde::Deserialize;
+
+use crate::kiro::scheduler::{PRIORITY_HEADER, Priority};
+use crate::model::config::SseProfile;
+
+use super::canary::CONVERTER_HEADER;
+
+/// 请求级扩展选项请求头
+pub const OPTIONS_HEADER: &str = "x-kiro-options";
+
+/// 请求截止时间扩展头
+pub const DEADLINE_HEADER: &str = "x-kiro-deadline-ms";
+
+/// 流式 thinking 的呈现方式
+#[derive(Debug, Clone, Copy, Default, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ThinkingRender {
+    /// 提取为独立的 thinking 内容块（默认）
+    #[default]
+    Blocks,
+    /// 保留在文本块中，原样输出 `<thinking>` 标签
+    Text,
+}
+
+/// `x-kiro-options` 的 JSON 结构
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, rename_all = "camelCase", deny_unknown_fields)]
+struct RawOptions {
+    thinking: ThinkingRender,
+    coalesce_deltas: Option<bool>,
+    strict_sse: Option<bool>,
+    deadline_ms: Option<u64>,
+    priority: Option<String>,
+    converter: Option<String>,
+    unpin_model: bool,
+}
+
+/// 请求级扩展选项
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// 流式 thinking 的呈现方式
+    pub thinking: ThinkingRender,
+    /// 发送队列写满时是否合并增量事件（未指定时合并）
+    pub coalesce_deltas: Option<bool>,
+    /// 是否使用严格 SSE 序列（未指定时使用端点配置）
+    pub strict_sse: Option<bool>,
+    /// 请求截止时间（从收到请求开始计算）
+    pub deadline: Option<tokio::time::Instant>,
+    /// 请求优先级
+    pub priority: Priority,
+    /// 指定的转换器版本（未指定时按灰度比例选择）
+    pub converter: Option<String>,
+    /// 解除会话的模型固定，改为固定到本次请求的模型
+    pub unpin_model: bool,
+}
+
+impl RequestOptions {
+    /// 解析 `x-kiro-options` 及单项扩展请求头
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, String> {
+        let mut raw = match header_str(headers, OPTIONS_HEADER)? {
+            Some(value) => serde_json::from_str::<RawOptions>(value)
+                .map_err(|e| format!("{} 不是有效的选项 JSON: {}", OPTIONS_HEADER, e))?,
+            None => RawOptions::default(),
+        };
+
+        if let Some(value) = header_str(headers, DEADLINE_HEADER)? {
+            let millis = value.trim().parse::<u64>().map_err(|_| {
+                format!(
+                    "Invalid {} header: expected a positive integer",
+                    DEADLINE_HEADER
+                )
+            })?;
+            raw.deadline_ms = Some(millis);
+        }
+        if let Some(value) = header_str(headers, PRIORITY_HEADER)? {
+            raw.priority = Some(value.to_string());
+        }
+        if let Some(value) = header_str(headers, CONVERTER_HEADER)? {
+            raw.converter = Some(value.to_string());
+        }
+
+        let deadline = match raw.deadline_ms {
+            Some(0) => {
+                return Err(format!(
+                    "Invalid {} header: expected a positive integer",
+                    DEADLINE_HEADER
+                ));
+            }
+            Some(millis) => Some(tokio::time::Instant::now() + Duration::from_millis(millis)),
+            None => None,
+        };
+
+        Ok(Self {
+            thinking: raw.thinking,
+            coalesce_deltas: raw.coalesce_deltas,
+            strict_sse: raw.strict_sse,
+            deadline,
+            priority: Priority::from_header(raw.priority.as_deref())?,
+            converter: raw.converter,
+            unpin_model: raw.unpin_model,
+        })
+    }
+
+    /// 当前请求的 SSE 严格程度
+    pub fn profile(&self, endpoint: SseProfile) -> SseProfile {
+        match self.strict_sse {
+            Some(true) => SseProfile::Strict,
+            Some(false) => SseProfile::Quirks,
+            None => endpoint,
+        }
+    }
+
+    /// 发送队列写满时是否合并增量事件
+    pub fn coalesce_deltas(&self) -> bool {
+        self.coalesce_deltas.unwrap_or(true)
+    }
+
+    /// 是否将 thinking 提取为独立内容块
+    pub fn extract_thinking(&self) -> bool {
+        self.thinking == ThinkingRender::Blocks
+    }
+}
+
+/// 读取字符串请求头
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, String> {
+    headers
+        .get(name)
+        .map(|v| v.to_str().map_err(|_| format!("{} 不是有效的字符串", name)))
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::HeaderValue;
+
+    fn headers(pairs: &[(&'static str, &str)]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for &(name, value) in pairs {
+            headers.insert(name, HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    #[test]
+    fn test_defaults_without_headers() {
+        let options = RequestOptions::from_headers(&HeaderMap::new()).unwrap();
+        assert!(options.extract_thinking());
+        assert!(options.coalesce_deltas());
+        assert_eq!(options.profile(SseProfile::Quirks), SseProfile::Quirks);
+        assert!(options.deadline.is_none());
+        assert_eq!(options.priority, Priority::Interactive);
+        assert!(options.converter.is_none());
+        assert!(!options.unpin_model);
+    }
+
+    #[test]
+    fn test_parse_json_options() {
+        let options = RequestOptions::from_headers(&headers(&[(
+            OPTIONS_HEADER,
+            r#"{"thinking":"text","coalesceDeltas":false,"strictSse":true,"deadlineMs":30000,"priority":"batch","converter":"experimental","unpinModel":true}"#,
+        )]))
+        .unwrap();
+        assert!(!options.extract_thinking());
+        assert!(!options.coalesce_deltas());
+        assert_eq!(options.profile(SseProfile::Quirks), SseProfile::Strict);
+        assert!(options.deadline.is_some());
+        assert_eq!(options.priority, Priority::Batch);
+        assert_eq!(options.converter.as_deref(), Some("experimental"));
+        assert!(options.unpin_model);
+    }
+
+    #[test]
+    fn test_dedicated_headers_take_precedence() {
+        let options = RequestOptions::from_headers(&headers(&[
+            (
+                OPTIONS_HEADER,
+                r#"{"priority":"batch","converter":"stable"}"#,
+            ),
+            (PRIORITY_HEADER, "interactive"),
+            (CONVERTER_HEADER, "experimental"),
+        ]))
+        .unwrap();
+        assert_eq!(options.priority, Priority::Interactive);
+        assert_eq!(options.converter.as_deref(), Some("experimental"));
+
+        let options =
+            RequestOptions::from_headers(&headers(&[(OPTIONS_HEADER, r#"{"strictSse":false}"#)]))
+                .unwrap();
+        assert_eq!(options.profile(SseProfile::Strict), SseProfile::Quirks);
+    }
+
+    #[test]
+    fn test_invalid_options_rejected() {
+        for value in [
+            "not json",
+            r#"{"unknown":1}"#,
+            r#"{"thinking":"inline"}"#,
+            r#"{"deadlineMs":0}"#,
+            r#"{"priority":"urgent"}"#,
+        ] {
+            assert!(
+                RequestOptions::from_headers(&headers(&[(OPTIONS_HEADER, value)])).is_err(),
+                "{}",
+                value
+            );
+        }
+        assert!(RequestOptions::from_headers(&headers(&[(DEADLINE_HEADER, "-1")])).is_err());
+    }
+}
@@ -7,15 +7,16 @@ use axum::{
     routing::{get, post},
 };
 
-use crate::kiro::provider::KiroProvider;
+use crate::model::config::ListenerAuth;
 
 use super::{
     handlers::{count_tokens, get_models, post_messages, post_messages_cc},
-    middleware::{AppState, auth_middleware, cors_layer},
+    middleware::{AppState, auth_middleware, cors_layer, error_envelope},
+    websocket::{messages_ws, messages_ws_cc},
 };
 
 /// 请求体最大大小限制 (50MB)
-const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+pub(super) const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 
 /// 创建 Anthropic API 路由
 ///
@@ -23,52 +24,48 @@ const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
 /// - `GET /v1/models` - 获取可用模型列表
 /// - `POST /v1/messages` - 创建消息（对话）
 /// - `POST /v1/messages/count_tokens` - 计算 token 数量
+/// - `GET /v1/messages/ws` - 创建消息（WebSocket 流式传输）
 ///
 /// # 认证
-/// 所有 `/v1` 路径需要 API Key 认证，支持：
+/// `auth` 为 `ApiKey` 时所有 `/v1` 路径需要 API Key 认证，支持：
 /// - `x-api-key` header
 /// - `Authorization: Bearer <token>` header
 ///
+/// # 错误格式
+/// 所有错误响应（包括请求体解析失败等 axum 拒绝）都使用 Anthropic 错误格式
+///
 /// # 参数
-/// - `api_key`: API 密钥，用于验证客户端请求
-/// - `kiro_provider`: 可选的 KiroProvider，用于调用上游 API
-
-/// 创建带有 KiroProvider 的 Anthropic API 路由
-pub fn create_router_with_provider(
-    api_key: impl Into<String>,
-    kiro_provider: Option<KiroProvider>,
-    profile_arn: Option<String>,
-) -> Router {
-    let mut state = AppState::new(api_key);
-    if let Some(provider) = kiro_provider {
-        state = state.with_kiro_provider(provider);
-    }
-    if let Some(arn) = profile_arn {
-        state = state.with_profile_arn(arn);
-    }
-
-    // 需要认证的 /v1 路由
-    let v1_routes = Router::new()
+/// - `state`: 应用共享状态（多个监听器共享同一份 KiroProvider 和会话存储）
+/// - `auth`: 认证策略
+pub fn create_router(state: AppState, auth: ListenerAuth) -> Router {
+    // /v1 路由
+    let mut v1_routes = Router::new()
         .route("/models", get(get_models))
         .route("/messages", post(post_messages))
-        .route("/messages/count_tokens", post(count_tokens))
-        .layer(middleware::from_fn_with_state(
+        .route("/messages/ws", get(messages_ws))
+        .route("/messages/count_tokens", post(count_tokens));
+
+    // /cc/v1 路由（Claude Code 兼容端点）
+    let mut cc_v1_routes = Router::new()
+        .route("/messages", post(post_messages_cc))
+        .route("/messages/ws", get(messages_ws_cc))
+        .route("/messages/count_tokens", post(count_tokens));
+
+    if auth == ListenerAuth::ApiKey {
+        v1_routes = v1_routes.layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
-
-    // 需要认证的 /cc/v1 路由（Claude Code 兼容端点）
-    let cc_v1_routes = Router::new()
-        .route("/messages", post(post_messages_cc))
-        .route("/messages/count_tokens", post(count_tokens))
-        .layer(middleware::from_fn_with_state(
+        cc_v1_routes = cc_v1_routes.layer(middleware::from_fn_with_state(
             state.clone(),
             auth_middleware,
         ));
+    }
 
     Router::new()
         .nest("/v1", v1_routes)
         .nest("/cc/v1", cc_v1_routes)
+        .layer(middleware::from_fn(error_envelope))
         .layer(cors_layer())
         .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
         .with_state(state)
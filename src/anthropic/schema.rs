@@ -0,0 +1,250 @@
+//! 工具 input_schema 规范化
+//!
+//! 复杂的 MCP 工具会在 input_schema 中使用 `$ref` / `$defs`，Kiro 不支持引用，
+//! 直接透传会导致上游返回含义不明的 400。这里在转换前：
+//! 1. 内联文档内引用（`#/$defs/...`、`#/definitions/...` 及任意 JSON Pointer）
+//! 2. 递归引用在第二次展开时替换为不受约束的 object
+//! 3. 移除 Kiro 不识别的元数据关键字
+//! 4. 限制展开后的嵌套深度
+
+use serde_json::{Map, Value};
+
+/// 需要移除的关键字
+const UNSUPPORTED_KEYWORDS: &[&str] = &[
+    "$schema",
+    "$id",
+    "$defs",
+    "definitions",
+    "$comment",
+    "$anchor",
+    "$dynamicAnchor",
+    "$dynamicRef",
+    "$vocabulary",
+];
+
+/// Schema 规范化错误
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SchemaError {
+    /// 引用了文档外部的 Schema
+    ExternalRef(String),
+    /// 引用目标不存在
+    UnresolvedRef(String),
+    /// 展开后嵌套过深（出错位置）
+    TooDeep(String),
+}
+
+impl std::fmt::Display for SchemaError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SchemaError::ExternalRef(r) => write!(f, "不支持外部引用 $ref: {}", r),
+            SchemaError::UnresolvedRef(r) => write!(f, "无法解析 $ref: {}", r),
+            SchemaError::TooDeep(path) => write!(f, "嵌套层级过深: {}", path),
+        }
+    }
+}
+
+struct Resolver<'a> {
+    root: &'a Value,
+    max_depth: usize,
+    /// 正在展开的引用，用于识别递归
+    stack: Vec<String>,
+}
+
+impl Resolver<'_> {
+    fn resolve(&mut self, value: &Value, path: &str, depth: usize) -> Result<Value, SchemaError> {
+        self.resolve_inner(value, path, depth, false)
+    }
+
+    /// `property_map` 为 true 时 `value` 是 properties 这类“名称 → Schema”映射，键是属性名而非关键字
+    fn resolve_inner(
+        &mut self,
+        value: &Value,
+        path: &str,
+        depth: usize,
+        property_map: bool,
+    ) -> Result<Value, SchemaError> {
+        if depth > self.max_depth {
+            return Err(SchemaError::TooDeep(path.to_string()));
+        }
+
+        match value {
+            Value::Object(obj) => {
+                if !property_map && let Some(Value::String(reference)) = obj.get("$ref") {
+                    return self.resolve_ref(reference, obj, path, depth);
+                }
+
+                let mut out = Map::with_capacity(obj.len());
+                for (key, child) in obj {
+                    if !property_map && UNSUPPORTED_KEYWORDS.contains(&key.as_str()) {
+                        continue;
+                    }
+                    let child_path = format!("{}/{}", path, key);
+                    let child_is_map =
+                        !property_map && matches!(key.as_str(), "properties" | "patternProperties");
+                    out.insert(
+                        key.clone(),
+                        self.resolve_inner(child, &child_path, depth + 1, child_is_map)?,
+                    );
+                }
+                Ok(Value::Object(out))
+            }
+            Value::Array(items) => items
+                .iter()
+                .enumerate()
+                .map(|(i, item)| self.resolve(item, &format!("{}/{}", path, i), depth + 1))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Array),
+            _ => Ok(value.clone()),
+        }
+    }
+
+    fn resolve_ref(
+        &mut self,
+        reference: &str,
+        siblings: &Map<String, Value>,
+        path: &str,
+        depth: usize,
+    ) -> Result<Value, SchemaError> {
+        let Some(pointer) = reference.strip_prefix('#') else {
+            return Err(SchemaError::ExternalRef(reference.to_string()));
+        };
+
+        // 递归引用：不再展开
+        if self.stack.iter().any(|r| r == reference) {
+            tracing::debug!("递归 $ref 截断: {} ({})", reference, path);
+            let mut out = Map::new();
+            out.insert("type".to_string(), Value::String("object".to_string()));
+            copy_annotations(siblings, &mut out);
+            return Ok(Value::Object(out));
+        }
+
+        let target = self
+            .root
+            .pointer(pointer)
+            .ok_or_else(|| SchemaError::UnresolvedRef(reference.to_string()))?;
+
+        self.stack.push(reference.to_string());
+        let resolved = self.resolve(target, path, depth);
+        self.stack.pop();
+
+        let mut resolved = resolved?;
+        // 保留引用处的描述信息
+        if let Value::Object(out) = &mut resolved {
+            copy_annotations(siblings, out);
+        }
+        Ok(resolved)
+    }
+}
+
+/// 将 `$ref` 旁的 description / title 带到展开结果中
+fn copy_annotations(from: &Map<String, Value>, to: &mut Map<String, Value>) {
+    for key in ["description", "title"] {
+        if let Some(value) = from.get(key) {
+            to.insert(key.to_string(), value.clone());
+        }
+    }
+}
+
+/// 内联引用、移除不支持的关键字并检查嵌套深度
+pub fn resolve_schema(schema: &Value, max_depth: usize) -> Result<Value, SchemaError> {
+    let mut resolver = Resolver {
+        root: schema,
+        max_depth,
+        stack: Vec::new(),
+    };
+    resolver.resolve(schema, "#", 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_inlines_defs() {
+        let schema = json!({
+            "$schema": "https://json-schema.org/draft/2020-12/schema",
+            "type": "object",
+            "properties": {
+                "target": {"$ref": "#/$defs/Point", "description": "目标位置"},
+                "points": {"type": "array", "items": {"$ref": "#/definitions/Point"}}
+            },
+            "$defs": {"Point": {"type": "object", "properties": {"x": {"type": "number"}}}},
+            "definitions": {"Point": {"type": "object", "properties": {"x": {"type": "number"}}}}
+        });
+
+        let resolved = resolve_schema(&schema, 32).unwrap();
+        assert!(resolved.get("$schema").is_none());
+        assert!(resolved.get("$defs").is_none());
+        assert!(resolved.get("definitions").is_none());
+        assert_eq!(
+            resolved["properties"]["target"]["properties"]["x"]["type"],
+            "number"
+        );
+        assert_eq!(resolved["properties"]["target"]["description"], "目标位置");
+        assert_eq!(resolved["properties"]["points"]["items"]["type"], "object");
+    }
+
+    #[test]
+    fn test_truncates_recursive_ref() {
+        let schema = json!({
+            "type": "object",
+            "properties": {"root": {"$ref": "#/$defs/Node"}},
+            "$defs": {
+                "Node": {
+                    "type": "object",
+                    "properties": {
+                        "children": {"type": "array", "items": {"$ref": "#/$defs/Node"}}
+                    }
+                }
+            }
+        });
+
+        let resolved = resolve_schema(&schema, 32).unwrap();
+        let items = &resolved["properties"]["root"]["properties"]["children"]["items"];
+        assert_eq!(items, &json!({"type": "object"}));
+    }
+
+    #[test]
+    fn test_reports_bad_refs() {
+        let schema = json!({"properties": {"a": {"$ref": "#/$defs/Missing"}}});
+        assert_eq!(
+            resolve_schema(&schema, 32),
+            Err(SchemaError::UnresolvedRef("#/$defs/Missing".to_string()))
+        );
+
+        let schema = json!({"properties": {"a": {"$ref": "https://example.com/a.json"}}});
+        assert!(matches!(
+            resolve_schema(&schema, 32),
+            Err(SchemaError::ExternalRef(_))
+        ));
+    }
+
+    #[test]
+    fn test_keeps_properties_named_like_keywords() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "definitions": {"type": "array", "items": {"type": "string"}},
+                "$id": {"type": "string"}
+            }
+        });
+
+        let resolved = resolve_schema(&schema, 32).unwrap();
+        assert_eq!(resolved["properties"]["definitions"]["type"], "array");
+        assert_eq!(resolved["properties"]["$id"]["type"], "string");
+    }
+
+    #[test]
+    fn test_depth_limit() {
+        let mut schema = json!({"type": "string"});
+        for _ in 0..10 {
+            schema = json!({"type": "object", "properties": {"a": schema}});
+        }
+        assert!(resolve_schema(&schema, 32).is_ok());
+        assert!(matches!(
+            resolve_schema(&schema, 8),
+            Err(SchemaError::TooDeep(path)) if path.starts_with("#/properties/a")
+        ));
+    }
+}
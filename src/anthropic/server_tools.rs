@@ -0,0 +1,241 @@
+//! 服务端工具（server tool）注册表
+//!
+//! Anthropic 区分两类工具调用：
+//! - `tool_use`：客户端工具，由调用方执行并回传 tool_result（包括 computer use、bash 等）
+//! - `server_tool_use`：内置服务端工具，由服务端执行，不会让 stop_reason 变为 `tool_use`
+//!
+//! 这里集中登记服务端工具，统一决定内容块类型、ID 前缀和 usage 计数键。
+//! 服务端工具只按请求中声明的 `type` 识别：客户端自定义的同名工具（如名为 `web_fetch`
+//! 的普通工具）仍是客户端工具，上游调用它时照常作为 `tool_use` 下发
+
+use std::collections::BTreeMap;
+
+use uuid::Uuid;
+
+use super::types::Tool;
+
+/// 服务端工具 ID 前缀
+pub const SERVER_TOOL_ID_PREFIX: &str = "srvtoolu_";
+
+/// 服务端工具定义
+#[derive(Debug, PartialEq, Eq)]
+pub struct ServerToolSpec {
+    /// 工具名称
+    pub name: &'static str,
+    /// 工具 `type` 字段前缀（如 `web_search_20250305` 的 `web_search`）
+    pub type_prefix: &'static str,
+    /// usage.server_tool_use 中的计数键
+    pub usage_key: &'static str,
+}
+
+/// WebSearch
+pub const WEB_SEARCH: ServerToolSpec = ServerToolSpec {
+    name: "web_search",
+    type_prefix: "web_search",
+    usage_key: "web_search_requests",
+};
+
+/// WebFetch
+pub const WEB_FETCH: ServerToolSpec = ServerToolSpec {
+    name: "web_fetch",
+    type_prefix: "web_fetch",
+    usage_key: "web_fetch_requests",
+};
+
+/// Code Execution
+pub const CODE_EXECUTION: ServerToolSpec = ServerToolSpec {
+    name: "code_execution",
+    type_prefix: "code_execution",
+    usage_key: "code_execution_requests",
+};
+
+/// 已登记的服务端工具
+pub const SERVER_TOOLS: &[&ServerToolSpec] = &[&WEB_SEARCH, &WEB_FETCH, &CODE_EXECUTION];
+
+/// 对请求中的工具定义分类，返回服务端工具定义（客户端工具返回 None）
+///
+/// 只按 `type` 前缀判断，不看工具名称
+pub fn classify(tool: &Tool) -> Option<&'static ServerToolSpec> {
+    let tool_type = tool.tool_type.as_deref()?;
+    SERVER_TOOLS
+        .iter()
+        .copied()
+        .find(|spec| tool_type.starts_with(spec.type_prefix))
+}
+
+/// 请求中声明的服务端工具（工具名称 -> 服务端工具定义）
+#[derive(Debug, Clone, Default)]
+pub struct DeclaredServerTools {
+    tools: Vec<(String, &'static ServerToolSpec)>,
+}
+
+impl DeclaredServerTools {
+    /// 从请求的工具定义中收集服务端工具
+    pub fn from_tools(tools: Option<&[Tool]>) -> Self {
+        Self {
+            tools: tools
+                .unwrap_or_default()
+                .iter()
+                .filter_map(|tool| classify(tool).map(|spec| (tool.name.clone(), spec)))
+                .collect(),
+        }
+    }
+
+    /// 按工具调用的名称查找本次请求声明的服务端工具
+    pub fn lookup(&self, name: &str) -> Option<&'static ServerToolSpec> {
+        self.tools
+            .iter()
+            .find(|(declared, _)| declared == name)
+            .map(|(_, spec)| *spec)
+    }
+
+    /// 工具调用对应的内容块类型
+    pub fn content_block_type(&self, name: &str) -> &'static str {
+        if self.lookup(name).is_some() {
+            "server_tool_use"
+        } else {
+            "tool_use"
+        }
+    }
+}
+
+/// 生成新的服务端工具调用 ID
+pub fn new_tool_use_id() -> String {
+    format!(
+        "{}{}",
+        SERVER_TOOL_ID_PREFIX,
+        &Uuid::new_v4().simple().to_string()[..32]
+    )
+}
+
+/// 将上游工具调用 ID 转换为服务端工具调用 ID（已带前缀时保持不变）
+pub fn to_server_tool_use_id(id: &str) -> String {
+    if id.starts_with(SERVER_TOOL_ID_PREFIX) {
+        id.to_string()
+    } else {
+        format!("{}{}", SERVER_TOOL_ID_PREFIX, id)
+    }
+}
+
+/// 服务端工具调用计数（usage.server_tool_use）
+#[derive(Debug, Clone, Default)]
+pub struct ServerToolUsage {
+    counts: BTreeMap<&'static str, i32>,
+}
+
+impl ServerToolUsage {
+    /// 累加指定服务端工具的调用次数
+    pub fn add(&mut self, spec: &ServerToolSpec, count: i32) {
+        *self.counts.entry(spec.usage_key).or_insert(0) += count;
+    }
+
+    /// 某类服务端工具的调用次数
+    pub fn count(&self, spec: &ServerToolSpec) -> i32 {
+        self.counts.get(spec.usage_key).copied().unwrap_or(0)
+    }
+
+    /// 是否没有任何计数
+    pub fn is_empty(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// 转换为 `usage.server_tool_use` JSON 对象
+    pub fn to_json(&self) -> serde_json::Value {
+        serde_json::Value::Object(
+            self.counts
+                .iter()
+                .map(|(key, count)| (key.to_string(), serde_json::json!(count)))
+                .collect(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(tool_type: Option<&str>, name: &str) -> Tool {
+        Tool {
+            tool_type: tool_type.map(String::from),
+            name: name.to_string(),
+            description: String::new(),
+            input_schema: Default::default(),
+            max_uses: None,
+            display_width_px: None,
+            display_height_px: None,
+            display_number: None,
+        }
+    }
+
+    #[test]
+    fn test_classify_server_tools() {
+        assert_eq!(
+            classify(&tool(Some("web_search_20250305"), "web_search")),
+            Some(&WEB_SEARCH)
+        );
+        assert_eq!(
+            classify(&tool(Some("code_execution_20250522"), "code_execution")),
+            Some(&CODE_EXECUTION)
+        );
+        assert_eq!(
+            classify(&tool(Some("web_fetch_20250910"), "fetch")),
+            Some(&WEB_FETCH)
+        );
+    }
+
+    #[test]
+    fn test_classify_client_tools() {
+        // computer use / bash 等由客户端执行，仍为普通 tool_use
+        assert_eq!(classify(&tool(Some("computer_20250124"), "computer")), None);
+        assert_eq!(classify(&tool(None, "Read")), None);
+        // 与服务端工具同名的客户端工具不是服务端工具
+        assert_eq!(classify(&tool(None, "web_fetch")), None);
+        assert_eq!(classify(&tool(None, "code_execution")), None);
+    }
+
+    #[test]
+    fn test_declared_server_tools() {
+        let tools = vec![
+            tool(Some("web_search_20250305"), "web_search"),
+            tool(None, "code_execution"),
+        ];
+        let declared = DeclaredServerTools::from_tools(Some(&tools));
+        assert_eq!(declared.lookup("web_search"), Some(&WEB_SEARCH));
+        assert_eq!(declared.content_block_type("web_search"), "server_tool_use");
+        // 客户端自定义的同名工具和未声明的服务端工具都按 tool_use 下发
+        assert_eq!(declared.lookup("code_execution"), None);
+        assert_eq!(declared.content_block_type("code_execution"), "tool_use");
+        assert_eq!(declared.content_block_type("web_fetch"), "tool_use");
+        assert!(
+            DeclaredServerTools::from_tools(None)
+                .lookup("web_search")
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_server_tool_use_ids() {
+        let id = new_tool_use_id();
+        assert!(id.starts_with(SERVER_TOOL_ID_PREFIX));
+        assert_eq!(id.len(), SERVER_TOOL_ID_PREFIX.len() + 32);
+
+        assert_eq!(to_server_tool_use_id("tooluse_abc"), "srvtoolu_tooluse_abc");
+        assert_eq!(to_server_tool_use_id("srvtoolu_abc"), "srvtoolu_abc");
+    }
+
+    #[test]
+    fn test_usage_to_json() {
+        let mut usage = ServerToolUsage::default();
+        assert!(usage.is_empty());
+
+        usage.add(&WEB_SEARCH, 1);
+        usage.add(&WEB_SEARCH, 1);
+        usage.add(&CODE_EXECUTION, 1);
+        assert_eq!(
+            usage.to_json(),
+            serde_json::json!({"code_execution_requests": 1, "web_search_requests": 2})
+        );
+        assert_eq!(usage.count(&WEB_SEARCH), 2);
+        assert_eq!(usage.count(&WEB_FETCH), 0);
+    }
+}
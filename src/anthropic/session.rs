@@ -0,0 +1,424 @@
+//! 会话存储
+//!
+//! 按 session ID（来自 `metadata.user_id`）保存跨请求复用的会话状态：
+//! - 最近一次发送给上游的 Kiro 历史消息（`sessions.keepHistory`），供会话渲染接口使用
+//! - 会话消息树，用于按 `parent_message_id` 分支
+//! - 会话固定使用的模型（`sessions.pinModel`）
+//!
+//! 会话按空闲时间、数量上限和估算的内存占用淘汰
+
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+
+use crate::common::metrics;
+use crate::kiro::model::requests::conversation::Message;
+use crate::model::config::SessionConfig;
+
+use super::branch::ConversationTree;
+use super::types;
+
+/// 默认最多保留的会话数
+const DEFAULT_MAX_SESSIONS: usize = 1024;
+
+/// 单个会话除历史和分支内容外的固定开销估算（字节）
+const ENTRY_OVERHEAD_BYTES: usize = 256;
+
+/// 单个会话的状态
+struct SessionEntry {
+    /// 最近一次发送给上游的历史
+    history: Vec<Message>,
+    /// 各调用方的消息树（API Key -> 消息树）
+    branches: HashMap<String, ConversationTree>,
+    /// 上游最近一次回显的会话 ID
+    upstream_conversation_id: Option<String>,
+    /// 已提示过降级的模型名
+    downgrade_notified: HashSet<String>,
+    /// 会话固定使用的客户端模型名
+    pinned_model: Option<String>,
+    last_access: Instant,
+    /// 历史的估算大小（字节）
+    history_bytes: usize,
+    /// 分支内容的估算大小（字节）
+    branch_bytes: usize,
+}
+
+impl SessionEntry {
+    /// 估算的内存占用
+    fn size(&self) -> usize {
+        ENTRY_OVERHEAD_BYTES
+            + self.history_bytes
+            + self.branch_bytes
+    }
+}
+
+/// 会话存储（线程安全）
+pub struct SessionStore {
+    sessions: Mutex<HashMap<String, SessionEntry>>,
+    max_sessions: usize,
+    /// 空闲淘汰时间
+    idle_ttl: Option<Duration>,
+    /// 内存预算（字节）
+    max_bytes: Option<usize>,
+}
+
+impl Default for SessionStore {
+    fn default() -> Self {
+        Self::new(DEFAULT_MAX_SESSIONS)
+    }
+}
+
+impl SessionStore {
+    /// 创建会话存储
+    ///
+    /// 超过 `max_sessions` 时淘汰最久未访问的会话
+    pub fn new(max_sessions: usize) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            max_sessions: max_sessions.max(1),
+            idle_ttl: None,
+            max_bytes: None,
+        }
+    }
+
+    /// 按配置创建会话存储
+    pub fn from_config(config: &SessionConfig) -> Self {
+        let mut store = Self::new(config.max_entries);
+        store.idle_ttl =
+            (config.idle_ttl_secs > 0).then(|| Duration::from_secs(config.idle_ttl_secs));
+        store.max_bytes = (config.max_memory_mb > 0).then(|| config.max_memory_mb * 1024 * 1024);
+        store
+    }
+
+    /// 启动后台任务，定期清理空闲超时的会话
+    pub fn spawn_sweeper(self: &Arc<Self>) {
+        let Some(ttl) = self.idle_ttl else {
+            return;
+        };
+        let store = Arc::downgrade(self);
+        let period = (ttl / 4).clamp(Duration::from_secs(1), Duration::from_secs(60));
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(period);
+            loop {
+                ticker.tick().await;
+                let Some(store) = store.upgrade() else {
+                    break;
+                };
+                let removed = store.evict_expired();
+                if removed > 0 {
+                    tracing::debug!("淘汰 {} 个空闲会话", removed);
+                }
+            }
+        });
+    }
+
+    /// 清理空闲超时的会话，返回清理数量
+    pub fn evict_expired(&self) -> usize {
+        let Some(ttl) = self.idle_ttl else {
+            return 0;
+        };
+        let mut sessions = self.sessions.lock();
+        let before = sessions.len();
+        sessions.retain(|_, entry| entry.last_access.elapsed() < ttl);
+        let removed = before - sessions.len();
+        for _ in 0..removed {
+            metrics::session_eviction().record_expired();
+        }
+        removed
+    }
+
+    /// 获取会话最近一次发送给上游的历史（克隆）
+    pub fn history(&self, session_id: &str) -> Option<Vec<Message>> {
+        let mut sessions = self.sessions.lock();
+        let entry = self.live_entry(&mut sessions, session_id)?;
+        entry.last_access = Instant::now();
+        Some(entry.history.clone())
+    }
+
+    /// 保存会话最近一次发送给上游的历史（替换上一份）
+    ///
+    /// `bytes` 为估算的内存占用，调用方传入已序列化的请求体大小，不必重新序列化历史
+    pub fn put_history(&self, session_id: &str, history: Vec<Message>, bytes: usize) {
+        self.with_entry(session_id, |entry| {
+            entry.history = history;
+            entry.history_bytes = bytes;
+        });
+    }
+
+    /// 沿分支重建从根到指定助手消息的完整消息序列
+    pub fn branch_path(
+        &self,
+        session_id: &str,
+        owner: &str,
+        message_id: &str,
+    ) -> Option<Vec<types::Message>> {
+        let mut sessions = self.sessions.lock();
+        let entry = self.live_entry(&mut sessions, session_id)?;
+        entry.last_access = Instant::now();
+        entry.branches.get(owner)?.path(message_id)
+    }
+
+    /// 消息序列接着该调用方最近登记的分支节点继续时，返回节点 ID 与其历史的消息数
+    pub fn branch_continuation(
+        &self,
+        session_id: &str,
+        owner: &str,
+        messages: &[types::Message],
+    ) -> Option<(String, usize)> {
+        let mut sessions = self.sessions.lock();
+        let entry = self.live_entry(&mut sessions, session_id)?;
+        entry.branches.get(owner)?.continuation(messages)
+    }
+
+    /// 登记分支节点
+    pub fn put_branch(
+        &self,
+        session_id: &str,
+        owner: &str,
+        message_id: String,
+        parent_id: Option<String>,
+        messages: Vec<types::Message>,
+    ) {
+        let bytes: usize = messages
+            .iter()
+            .map(|m| m.role.len() + m.content.to_string().len())
+            .sum();
+        self.with_entry(session_id, |entry| {
+            entry
+                .branches
+                .entry(owner.to_string())
+                .or_default()
+                .insert(message_id, parent_id, messages);
+            entry.branch_bytes += bytes;
+        });
+    }
+
+    /// 记录上游回显的会话 ID，返回此前记录的值
+    pub fn swap_upstream_conversation(
+        &self,
+        session_id: &str,
+        conversation_id: String,
+    ) -> Option<String> {
+        self.with_entry(session_id, |entry| {
+            entry.upstream_conversation_id.replace(conversation_id)
+        })
+    }
+
+    /// 标记会话已提示过该模型的降级，首次标记时返回 true
+    pub fn mark_downgrade_notified(&self, session_id: &str, model: &str) -> bool {
+        self.with_entry(session_id, |entry| {
+            entry.downgrade_notified.insert(model.to_string())
+        })
+    }
+
+    /// 返回会话固定使用的模型名
+    ///
+    /// 会话尚未固定模型或 `repin` 为 true 时固定为 `model`
+    pub fn pin_model(&self, session_id: &str, model: &str, repin: bool) -> String {
+        self.with_entry(session_id, |entry| match &entry.pinned_model {
+            Some(pinned) if !repin => pinned.clone(),
+            _ => entry.pinned_model.insert(model.to_string()).clone(),
+        })
+    }
+
+    /// 获取未过期的会话，已过期的会话就地移除
+    fn live_entry<'a>(
+        &self,
+        sessions: &'a mut HashMap<String, SessionEntry>,
+        session_id: &str,
+    ) -> Option<&'a mut SessionEntry> {
+        if let Some(ttl) = self.idle_ttl
+            && sessions
+                .get(session_id)
+                .is_some_and(|entry| entry.last_access.elapsed() >= ttl)
+        {
+            sessions.remove(session_id);
+            metrics::session_eviction().record_expired();
+            return None;
+        }
+        sessions.get_mut(session_id)
+    }
+
+    /// 获取或创建会话并更新访问时间，之后按数量上限和内存预算淘汰最久未访问的会话
+    fn with_entry<R>(&self, session_id: &str, f: impl FnOnce(&mut SessionEntry) -> R) -> R {
+        let mut sessions = self.sessions.lock();
+
+        // 过期会话视为新会话
+        self.live_entry(&mut sessions, session_id);
+        let entry = sessions
+            .entry(session_id.to_string())
+            .or_insert_with(|| SessionEntry {
+                history: Vec::new(),
+                branches: HashMap::new(),
+                upstream_conversation_id: None,
+                downgrade_notified: HashSet::new(),
+                pinned_model: None,
+                last_access: Instant::now(),
+                history_bytes: 0,
+                branch_bytes: 0,
+            });
+        entry.last_access = Instant::now();
+        let result = f(entry);
+
+        self.enforce_limits(&mut sessions, session_id);
+        result
+    }
+
+    /// 按数量上限和内存预算淘汰最久未访问的会话（不淘汰 `keep`）
+    fn enforce_limits(&self, sessions: &mut HashMap<String, SessionEntry>, keep: &str) {
+        let mut total_bytes: usize = sessions.values().map(SessionEntry::size).sum();
+        loop {
+            let over_capacity = sessions.len() > self.max_sessions;
+            let over_memory = self.max_bytes.is_some_and(|max| total_bytes > max);
+            if !over_capacity && !over_memory {
+                break;
+            }
+
+            let oldest = sessions
+                .iter()
+                .filter(|(id, _)| id.as_str() != keep)
+                .min_by_key(|(_, entry)| entry.last_access)
+                .map(|(id, _)| id.clone());
+            let Some(oldest) = oldest else {
+                break;
+            };
+            if let Some(entry) = sessions.remove(&oldest) {
+                total_bytes -= entry.size();
+            }
+            if over_capacity {
+                metrics::session_eviction().record_capacity();
+            } else {
+                metrics::session_eviction().record_memory();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::requests::conversation::HistoryAssistantMessage;
+
+    fn history_with(len: usize) -> Vec<Message> {
+        (0..len)
+            .map(|_| Message::Assistant(HistoryAssistantMessage::new("ok")))
+            .collect()
+    }
+
+    #[test]
+    fn test_put_and_get_history() {
+        let store = SessionStore::default();
+        assert!(store.history("s1").is_none());
+
+        store.put_history("s1", history_with(2), 64);
+        assert_eq!(store.history("s1").unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_evicts_least_recently_used_session() {
+        let store = SessionStore::new(2);
+        store.put_history("s1", history_with(1), 32);
+        store.put_history("s2", history_with(1), 32);
+
+        // 访问 s1，使 s2 成为最久未访问
+        store.history("s1");
+        store.put_history("s3", history_with(1), 32);
+
+        assert!(store.history("s1").is_some());
+        assert!(store.history("s2").is_none());
+        assert!(store.history("s3").is_some());
+    }
+
+    #[test]
+    fn test_branches_share_session_with_history() {
+        let store = SessionStore::default();
+        store.put_history("s1", history_with(1), 32);
+        store.put_branch(
+            "s1",
+            "sk-a",
+            "msg_1".to_string(),
+            None,
+            vec![types::Message {
+                role: "user".to_string(),
+                content: serde_json::json!("hi"),
+            }],
+        );
+
+        assert_eq!(store.branch_path("s1", "sk-a", "msg_1").unwrap().len(), 1);
+        assert!(store.branch_path("s1", "sk-a", "msg_2").is_none());
+        // 其他 API Key 使用相同的会话 ID 也无法读取该分支
+        assert!(store.branch_path("s1", "sk-b", "msg_1").is_none());
+        // 登记分支不应覆盖历史
+        assert_eq!(store.history("s1").unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_downgrade_notified_once_per_model() {
+        let store = SessionStore::default();
+        assert!(store.mark_downgrade_notified("s1", "claude-opus-4-7"));
+        assert!(!store.mark_downgrade_notified("s1", "claude-opus-4-7"));
+        assert!(store.mark_downgrade_notified("s1", "claude-sonnet-4-7"));
+        assert!(store.mark_downgrade_notified("s2", "claude-opus-4-7"));
+    }
+
+    #[test]
+    fn test_pin_model_until_repinned() {
+        let store = SessionStore::default();
+        assert_eq!(
+            store.pin_model("s1", "claude-sonnet-4-5", false),
+            "claude-sonnet-4-5"
+        );
+        assert_eq!(
+            store.pin_model("s1", "claude-haiku-4-5", false),
+            "claude-sonnet-4-5"
+        );
+        assert_eq!(
+            store.pin_model("s2", "claude-haiku-4-5", false),
+            "claude-haiku-4-5"
+        );
+        assert_eq!(
+            store.pin_model("s1", "claude-haiku-4-5", true),
+            "claude-haiku-4-5"
+        );
+        assert_eq!(
+            store.pin_model("s1", "claude-opus-4-6", false),
+            "claude-haiku-4-5"
+        );
+    }
+
+    #[test]
+    fn test_evicts_idle_sessions() {
+        let store = SessionStore::from_config(&SessionConfig {
+            idle_ttl_secs: 60,
+            ..SessionConfig::default()
+        });
+        store.put_history("s1", history_with(1), 32);
+        store.put_history("s2", history_with(1), 32);
+        store.sessions.lock().get_mut("s1").unwrap().last_access -= Duration::from_secs(120);
+
+        assert!(store.history("s1").is_none());
+        assert!(store.history("s2").is_some());
+
+        store.sessions.lock().get_mut("s2").unwrap().last_access -= Duration::from_secs(120);
+        assert_eq!(store.evict_expired(), 1);
+        assert!(store.sessions.lock().is_empty());
+    }
+
+    #[test]
+    fn test_evicts_over_memory_budget() {
+        let store = SessionStore::from_config(&SessionConfig {
+            max_memory_mb: 1,
+            ..SessionConfig::default()
+        });
+        store.put_history("s1", history_with(1), 300 * 1024);
+        store.put_history("s2", history_with(1), 300 * 1024);
+        store.put_history("s3", history_with(1), 300 * 1024);
+        // 超出预算时淘汰最久未访问的 s1，当前写入的会话始终保留
+        store.put_history("s4", history_with(1), 300 * 1024);
+
+        assert!(store.history("s1").is_none());
+        assert!(store.history("s4").is_some());
+    }
+}
@@ -0,0 +1,353 @@
+//! SSE 会话记录
+//!
+//! 启用 `logging.sseTranscripts.enabled` 后，流式响应下发给客户端的完整 SSE 字节流按请求保存到
+//! 缓存目录下的 `kiro_sse_transcripts/`：输出按 `chunkBytes` 分块，每块压缩为一个独立的 zstd 帧
+//! 追加写入 `<id>.sse.zst`，长时间生成也只在内存中保留一个分块；元数据（端点、模型、
+//! 原始与压缩后大小）在响应结束（或客户端断开）时写入同名的 `.json`。
+//!
+//! Admin API 读取时逐帧流式解压；记录数超出 `maxTranscripts` 时删除最早的记录
+
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use bytes::Bytes;
+use chrono::Utc;
+use futures::{SinkExt, Stream};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::SseTranscriptConfig;
+
+/// 返回记录 ID 的响应头
+pub const TRANSCRIPT_HEADER: &str = "x-kiro-sse-transcript";
+
+/// 解压时每次读取的字节数
+const READ_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 一条 SSE 会话记录的元数据
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SseTranscript {
+    pub id: String,
+    /// 开始时间（RFC3339）
+    pub at: String,
+    /// 请求端点，如 `/v1/messages`
+    pub endpoint: String,
+    pub model: String,
+    /// 解压后的大小（字节）
+    pub raw_bytes: u64,
+    /// 磁盘上的压缩大小（字节）
+    pub compressed_bytes: u64,
+    /// zstd 帧数
+    pub chunks: u32,
+}
+
+/// SSE 会话记录存储
+pub struct SseTranscriptStore {
+    dir: PathBuf,
+    chunk_bytes: usize,
+    level: i32,
+    max_transcripts: usize,
+    /// 已完成的记录（旧的在前）
+    entries: Mutex<VecDeque<SseTranscript>>,
+}
+
+impl SseTranscriptStore {
+    /// 创建存储目录并加载已有的记录
+    pub fn new(dir: PathBuf, config: &SseTranscriptConfig) -> io::Result<Self> {
+        fs::create_dir_all(&dir)?;
+        let mut entries = load_entries(&dir);
+        entries.sort_by(|a, b| a.at.cmp(&b.at));
+        let store = Self {
+            dir,
+            chunk_bytes: config.chunk_bytes.max(1),
+            level: config.level,
+            max_transcripts: config.max_transcripts.max(1),
+            entries: Mutex::new(entries.into()),
+        };
+        store.evict(&mut store.entries.lock());
+        Ok(store)
+    }
+
+    /// 开始记录一个流式响应
+    pub fn start(self: &Arc<Self>, endpoint: &str, model: &str) -> TranscriptWriter {
+        TranscriptWriter {
+            store: self.clone(),
+            meta: SseTranscript {
+                id: uuid::Uuid::new_v4().to_string(),
+                at: Utc::now().to_rfc3339(),
+                endpoint: endpoint.to_string(),
+                model: model.to_string(),
+                raw_bytes: 0,
+                compressed_bytes: 0,
+                chunks: 0,
+            },
+            buffer: Vec::new(),
+            file: None,
+            failed: false,
+        }
+    }
+
+    /// 查询记录（新的在前）
+    pub fn list(&self, limit: usize) -> Vec<SseTranscript> {
+        self.entries
+            .lock()
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+
+    /// 打开记录，返回元数据与解压后的 SSE 字节流
+    ///
+    /// 只接受已登记的记录 ID，不会按请求路径拼接任意文件
+    pub fn open(
+        &self,
+        id: &str,
+    ) -> Option<(SseTranscript, impl Stream<Item = io::Result<Bytes>> + use<>)> {
+        let meta = self.entries.lock().iter().find(|e| e.id == id).cloned()?;
+        let file = File::open(self.data_path(&meta.id)).ok()?;
+        Some((meta, decompress(file)))
+    }
+
+    fn data_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.sse.zst", id))
+    }
+
+    fn meta_path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+
+    /// 登记一条已完成的记录
+    fn finish(&self, meta: SseTranscript) {
+        match serde_json::to_vec(&meta) {
+            Ok(json) => {
+                if let Err(e) = fs::write(self.meta_path(&meta.id), json) {
+                    tracing::warn!("保存 SSE 会话记录元数据失败: {}", e);
+                    return;
+                }
+            }
+            Err(e) => {
+                tracing::warn!("序列化 SSE 会话记录元数据失败: {}", e);
+                return;
+            }
+        }
+        let mut entries = self.entries.lock();
+        entries.push_back(meta);
+        self.evict(&mut entries);
+    }
+
+    /// 删除超出上限的最早记录
+    fn evict(&self, entries: &mut VecDeque<SseTranscript>) {
+        while entries.len() > self.max_transcripts {
+            let Some(old) = entries.pop_front() else {
+                break;
+            };
+            let _ = fs::remove_file(self.data_path(&old.id));
+            let _ = fs::remove_file(self.meta_path(&old.id));
+        }
+    }
+}
+
+/// 单个流式响应的记录器
+///
+/// 被释放时写出剩余分块并登记记录，客户端中途断开的响应同样会被保存
+pub struct TranscriptWriter {
+    store: Arc<SseTranscriptStore>,
+    meta: SseTranscript,
+    /// 尚未压缩的输出
+    buffer: Vec<u8>,
+    file: Option<File>,
+    /// 写入失败后不再记录
+    failed: bool,
+}
+
+impl TranscriptWriter {
+    pub fn id(&self) -> &str {
+        &self.meta.id
+    }
+
+    /// 追加下发的 SSE 字节，攒满一个分块时压缩写盘
+    pub fn write(&mut self, bytes: &[u8]) {
+        if self.failed {
+            return;
+        }
+        self.buffer.extend_from_slice(bytes);
+        self.meta.raw_bytes += bytes.len() as u64;
+        if self.buffer.len() >= self.store.chunk_bytes {
+            self.flush_chunk();
+        }
+    }
+
+    /// 将缓冲区压缩为一个 zstd 帧追加到文件
+    fn flush_chunk(&mut self) {
+        if self.buffer.is_empty() || self.failed {
+            return;
+        }
+        if let Err(e) = self.try_flush_chunk() {
+            tracing::warn!("写入 SSE 会话记录失败，停止记录 {}: {}", self.meta.id, e);
+            self.failed = true;
+        }
+        self.buffer.clear();
+    }
+
+    fn try_flush_chunk(&mut self) -> io::Result<()> {
+        let frame = zstd::bulk::compress(&self.buffer, self.store.level)?;
+        let file = match &mut self.file {
+            Some(file) => file,
+            None => self.file.insert(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(self.store.data_path(&self.meta.id))?,
+            ),
+        };
+        file.write_all(&frame)?;
+        self.meta.compressed_bytes += frame.len() as u64;
+        self.meta.chunks += 1;
+        Ok(())
+    }
+}
+
+impl Drop for TranscriptWriter {
+    fn drop(&mut self) {
+        self.flush_chunk();
+        if self.failed || self.meta.chunks == 0 {
+            let _ = fs::remove_file(self.store.data_path(&self.meta.id));
+            return;
+        }
+        self.store.finish(self.meta.clone());
+    }
+}
+
+/// 在阻塞线程中逐帧解压，以流的形式返回
+fn decompress(file: File) -> impl Stream<Item = io::Result<Bytes>> {
+    let (mut tx, rx) = futures::channel::mpsc::channel(2);
+    tokio::task::spawn_blocking(move || {
+        let mut decoder = match zstd::stream::read::Decoder::new(file) {
+            Ok(decoder) => decoder,
+            Err(e) => {
+                let _ = futures::executor::block_on(tx.send(Err(e)));
+                return;
+            }
+        };
+        let mut buffer = vec![0u8; READ_BUFFER_SIZE];
+        loop {
+            let item = match decoder.read(&mut buffer) {
+                Ok(0) => return,
+                Ok(n) => Ok(Bytes::copy_from_slice(&buffer[..n])),
+                Err(e) => Err(e),
+            };
+            let failed = item.is_err();
+            // 接收端已丢弃（客户端断开）时停止解压
+            if futures::executor::block_on(tx.send(item)).is_err() || failed {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+/// 读取目录中已有记录的元数据
+fn load_entries(dir: &Path) -> Vec<SseTranscript> {
+    let Ok(read_dir) = fs::read_dir(dir) else {
+        return Vec::new();
+    };
+    read_dir
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .filter_map(|path| {
+            let content = fs::read(&path).ok()?;
+            match serde_json::from_slice(&content) {
+                Ok(meta) => Some(meta),
+                Err(e) => {
+                    tracing::warn!(
+                        "解析 SSE 会话记录元数据失败，将忽略 {}: {}",
+                        path.display(),
+                        e
+                    );
+                    None
+                }
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::StreamExt;
+
+    fn temp_store(chunk_bytes: usize, max_transcripts: usize) -> Arc<SseTranscriptStore> {
+        let dir = std::env::temp_dir().join(format!("kiro-sse-{}", uuid::Uuid::new_v4()));
+        let config = SseTranscriptConfig {
+            enabled: true,
+            chunk_bytes,
+            level: 3,
+            max_transcripts,
+        };
+        Arc::new(SseTranscriptStore::new(dir, &config).unwrap())
+    }
+
+    async fn read_all(store: &SseTranscriptStore, id: &str) -> Vec<u8> {
+        let (_, stream) = store.open(id).unwrap();
+        let chunks: Vec<Bytes> = stream.map(|chunk| chunk.unwrap()).collect().await;
+        chunks.concat()
+    }
+
+    #[tokio::test]
+    async fn test_chunked_roundtrip() {
+        let store = temp_store(100, 10);
+        let mut writer = store.start("/v1/messages", "claude-sonnet-4");
+        let id = writer.id().to_string();
+        let mut expected = Vec::new();
+        for i in 0..50 {
+            let event = format!(
+                "event: content_block_delta\ndata: {{\"delta\":{{\"text\":\"第 {} 段\"}}}}\n\n",
+                i
+            );
+            writer.write(event.as_bytes());
+            expected.extend_from_slice(event.as_bytes());
+        }
+        drop(writer);
+
+        let meta = &store.list(10)[0];
+        assert_eq!(meta.id, id);
+        assert_eq!(meta.raw_bytes, expected.len() as u64);
+        assert!(meta.chunks > 1);
+        assert!(meta.compressed_bytes < meta.raw_bytes);
+        assert_eq!(read_all(&store, &id).await, expected);
+
+        // 重新加载目录后记录仍可读取
+        let reloaded =
+            SseTranscriptStore::new(store.dir.clone(), &SseTranscriptConfig::default()).unwrap();
+        assert_eq!(read_all(&reloaded, &id).await, expected);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn test_evicts_oldest_and_skips_empty() {
+        let store = temp_store(1024, 2);
+        let ids: Vec<String> = (0..3)
+            .map(|i| {
+                let mut writer = store.start("/v1/messages", "m");
+                writer.write(format!("data: {}\n\n", i).as_bytes());
+                writer.id().to_string()
+            })
+            .collect();
+        // 没有输出的响应不登记
+        drop(store.start("/v1/messages", "m"));
+
+        let listed: Vec<String> = store.list(10).into_iter().map(|m| m.id).collect();
+        assert_eq!(listed, [ids[2].clone(), ids[1].clone()]);
+        assert!(store.open(&ids[0]).is_none());
+        assert!(!store.data_path(&ids[0]).exists());
+        assert!(store.open("../etc/passwd").is_none());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -0,0 +1,145 @@
+//! stop_sequence 截断
+//!
+//! Kiro 上游不支持 stop sequence，由代理在输出文本中查找第一次出现的序列（可跨越多个上游分片）：
+//! 命中后丢弃序列本身及之后的输出，`stop_reason` 报告为 `stop_sequence`，`stop_sequence` 为命中的序列。
+//! 末尾可能是某个序列前缀的文本会暂存到下一个分片，确认不构成匹配后再下发
+
+/// stop sequence 过滤器
+#[derive(Debug)]
+pub struct StopSequenceMatcher {
+    sequences: Vec<String>,
+    /// 暂存的末尾文本（某个序列的前缀）
+    pending: String,
+    /// 命中的序列
+    matched: Option<String>,
+}
+
+impl StopSequenceMatcher {
+    /// 创建过滤器，过滤掉空序列；没有有效序列时返回 None
+    pub fn new(sequences: &[String]) -> Option<Self> {
+        let sequences: Vec<String> = sequences
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect();
+        if sequences.is_empty() {
+            return None;
+        }
+        Some(Self {
+            sequences,
+            pending: String::new(),
+            matched: None,
+        })
+    }
+
+    /// 过滤一段输出文本，返回可以立即下发的部分
+    ///
+    /// 命中时返回序列之前的文本，之后的输入全部丢弃
+    pub fn push(&mut self, chunk: &str) -> String {
+        if self.matched.is_some() {
+            return String::new();
+        }
+        self.pending.push_str(chunk);
+
+        // 多个序列同时出现时取最先出现的，同一位置取最长的
+        let found = self
+            .sequences
+            .iter()
+            .filter_map(|seq| self.pending.find(seq.as_str()).map(|pos| (pos, seq)))
+            .min_by(|(a_pos, a), (b_pos, b)| a_pos.cmp(b_pos).then(b.len().cmp(&a.len())));
+        if let Some((pos, sequence)) = found {
+            self.matched = Some(sequence.clone());
+            let mut text = std::mem::take(&mut self.pending);
+            text.truncate(pos);
+            return text;
+        }
+
+        // 只保留可能与后续分片拼成匹配的末尾部分
+        let keep = self.partial_suffix_len();
+        let rest = self.pending.split_off(self.pending.len() - keep);
+        std::mem::replace(&mut self.pending, rest)
+    }
+
+    /// 输出结束时取出暂存的文本（已命中时为空）
+    pub fn flush(&mut self) -> String {
+        std::mem::take(&mut self.pending)
+    }
+
+    /// 命中的序列
+    pub fn matched(&self) -> Option<&str> {
+        self.matched.as_deref()
+    }
+
+    /// 暂存区末尾与某个序列真前缀重合的最大字节数
+    fn partial_suffix_len(&self) -> usize {
+        self.sequences
+            .iter()
+            .filter_map(|seq| {
+                (1..seq.len())
+                    .rev()
+                    .filter(|&n| seq.is_char_boundary(n))
+                    .find(|&n| self.pending.ends_with(&seq[..n]))
+            })
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matcher(sequences: &[&str]) -> StopSequenceMatcher {
+        let sequences: Vec<String> = sequences.iter().map(|s| s.to_string()).collect();
+        StopSequenceMatcher::new(&sequences).unwrap()
+    }
+
+    #[test]
+    fn test_match_within_single_chunk() {
+        let mut m = matcher(&["</answer>"]);
+        assert_eq!(m.push("42</answer> trailing"), "42");
+        assert_eq!(m.matched(), Some("</answer>"));
+        assert_eq!(m.push("more"), "");
+        assert_eq!(m.flush(), "");
+    }
+
+    #[test]
+    fn test_match_spanning_chunk_boundaries() {
+        let mut m = matcher(&["STOP"]);
+        assert_eq!(m.push("hello S"), "hello ");
+        assert_eq!(m.push("T"), "");
+        assert_eq!(m.push("OP\n"), "");
+        assert_eq!(m.matched(), Some("STOP"));
+    }
+
+    #[test]
+    fn test_multibyte_sequence_split_across_chunks() {
+        let mut m = matcher(&["。结束"]);
+        assert_eq!(m.push("答案是 42。"), "答案是 42");
+        assert_eq!(m.push("结"), "");
+        assert_eq!(m.push("束了"), "");
+        assert_eq!(m.matched(), Some("。结束"));
+    }
+
+    #[test]
+    fn test_earliest_match_wins() {
+        let mut m = matcher(&["B", "AB"]);
+        assert_eq!(m.push("xxAB"), "xx");
+        assert_eq!(m.matched(), Some("AB"));
+    }
+
+    #[test]
+    fn test_partial_match_released_when_broken() {
+        let mut m = matcher(&["###"]);
+        assert_eq!(m.push("a##"), "a");
+        assert_eq!(m.push(" #"), "## ");
+        assert_eq!(m.flush(), "#");
+        assert_eq!(m.matched(), None);
+    }
+
+    #[test]
+    fn test_empty_sequences() {
+        assert!(StopSequenceMatcher::new(&[String::new()]).is_none());
+        assert!(StopSequenceMatcher::new(&[]).is_none());
+    }
+}
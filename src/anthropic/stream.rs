@@ -2,12 +2,37 @@
 //!
 //! 实现 Kiro → Anthropic 流式响应转换和 SSE 状态管理
 
+use std::borrow::Cow;
 use std::collections::HashMap;
+use std::time::{Duration, Instant};
 
+use bytes::{BufMut, Bytes, BytesMut};
 use serde_json::json;
 use uuid::Uuid;
 
-use crate::kiro::model::events::Event;
+#[cfg(not(edge))]
+use crate::common::metrics;
+#[cfg(not(edge))]
+use crate::common::usage::RequestUsage;
+use crate::kiro::model::events::{Event, ExceptionKind};
+use crate::kiro::model::events::ToolUseEvent;
+use crate::model::config::{SseProfile, ToolInputValidation};
+use crate::token;
+
+#[cfg(not(edge))]
+use super::conversation::ConversationTracker;
+use super::diff_preview::DiffPreviewer;
+use super::echo_filter::EchoFilter;
+use super::error::ApiError;
+use super::event_order::{LateFragment, ReorderWindow};
+use super::references::ReferenceCollector;
+use super::refusal::{RefusalClassifier, RefusalDetector};
+#[cfg(not(edge))]
+use super::server_tools::WEB_SEARCH;
+use super::server_tools::{self, DeclaredServerTools, ServerToolUsage};
+use super::stop_sequence::StopSequenceMatcher;
+use super::tool_ids::ToolUseIds;
+use super::tool_validation::{self, ToolInputValidator};
 
 /// 找到小于等于目标位置的最近有效UTF-8字符边界
 ///
@@ -28,16 +53,32 @@ fn find_char_boundary(s: &str, target: usize) -> usize {
     pos
 }
 
+/// 按不超过 `max_bytes` 字节拆分字符串，不拆开多字节字符
+///
+/// `max_bytes` 小于单个字符的长度时该字符单独成段
+fn split_at_char_boundaries(s: &str, max_bytes: usize) -> impl Iterator<Item = &str> {
+    let mut rest = s;
+    std::iter::from_fn(move || {
+        if rest.is_empty() {
+            return None;
+        }
+        let mut end = find_char_boundary(rest, max_bytes);
+        if end == 0 {
+            end = rest.chars().next().map_or(rest.len(), char::len_utf8);
+        }
+        let (chunk, tail) = rest.split_at(end);
+        rest = tail;
+        Some(chunk)
+    })
+}
+
 /// 需要跳过的包裹字符
 ///
 /// 当 thinking 标签被这些字符包裹时，认为是在引用标签而非真正的标签：
 /// - 反引号 (`)：行内代码
 /// - 双引号 (")：字符串
 /// - 单引号 (')：字符串
-const QUOTE_CHARS: &[u8] = &[
-    b'`', b'"', b'\'', b'\\', b'#', b'!', b'@', b'$', b'%', b'^', b'&', b'*', b'(', b')', b'-',
-    b'_', b'=', b'+', b'[', b']', b'{', b'}', b';', b':', b'<', b'>', b',', b'.', b'?', b'/',
-];
+const QUOTE_CHARS: &[u8] = b"`\"'\\#!@$%^&*()-_=+[]{};:<>,.?/";
 
 /// 检查指定位置的字符是否是引用字符
 fn is_quote_char(buffer: &str, pos: usize) -> bool {
@@ -186,14 +227,98 @@ impl SseEvent {
         }
     }
 
-    /// 格式化为 SSE 字符串
-    pub fn to_sse_string(&self) -> String {
-        format!(
-            "event: {}\ndata: {}\n\n",
-            self.event,
-            serde_json::to_string(&self.data).unwrap_or_default()
+    /// 文本增量事件
+    pub fn text_delta(index: i32, text: &str) -> Self {
+        Self::new(
+            "content_block_delta",
+            json!({
+                "type": "content_block_delta",
+                "index": index,
+                "delta": {
+                    "type": "text_delta",
+                    "text": text
+                }
+            }),
         )
     }
+
+    /// 未经修改的文本增量事件的 (index, text)
+    ///
+    /// 兼容层改名或增删了字段的事件返回 None
+    fn as_text_delta(&self) -> Option<(i64, &str)> {
+        if self.event != "content_block_delta" {
+            return None;
+        }
+        let data = self.data.as_object()?;
+        let delta = data.get("delta")?.as_object()?;
+        if data.len() != 3
+            || delta.len() != 2
+            || data.get("type")?.as_str()? != "content_block_delta"
+            || delta.get("type")?.as_str()? != "text_delta"
+        {
+            return None;
+        }
+        Some((data.get("index")?.as_i64()?, delta.get("text")?.as_str()?))
+    }
+}
+
+/// SSE 编码缓冲区每次扩容的大小
+const SSE_BUFFER_CAPACITY: usize = 8 * 1024;
+
+/// 复用缓冲区的 SSE 编码器
+///
+/// 每个响应流持有一个编码器，事件直接序列化进同一块 `BytesMut`，再拆出 `Bytes`
+/// 交给响应体。之前发出的 `Bytes` 全部释放后，扩容时会原地回收这块内存，
+/// 避免每个事件分别为 JSON 字符串、格式化字符串各分配一次
+#[derive(Debug)]
+pub struct SseEncoder {
+    buf: BytesMut,
+}
+
+impl Default for SseEncoder {
+    fn default() -> Self {
+        Self {
+            buf: BytesMut::with_capacity(SSE_BUFFER_CAPACITY),
+        }
+    }
+}
+
+impl SseEncoder {
+    /// 编码单个事件：`event: <name>\ndata: <json>\n\n`
+    pub fn encode(&mut self, event: &SseEvent) -> Bytes {
+        if self.buf.capacity() - self.buf.len() < event.event.len() + 64 {
+            self.buf.reserve(SSE_BUFFER_CAPACITY);
+        }
+        if let Some((index, text)) = event.as_text_delta() {
+            return self.encode_text_delta(index, text);
+        }
+        self.buf.put_slice(b"event: ");
+        self.buf.put_slice(event.event.as_bytes());
+        self.buf.put_slice(b"\ndata: ");
+        if let Err(e) = serde_json::to_writer((&mut self.buf).writer(), &event.data) {
+            tracing::warn!("SSE 事件序列化失败: {}", e);
+        }
+        self.buf.put_slice(b"\n\n");
+        self.buf.split().freeze()
+    }
+
+    /// 文本增量的快速路径：固定部分直接写入，只序列化文本本身
+    ///
+    /// 输出与按 JSON 对象序列化的结果逐字节相同（键按字母序）
+    fn encode_text_delta(&mut self, index: i64, text: &str) -> Bytes {
+        use std::fmt::Write;
+
+        self.buf
+            .put_slice(b"event: content_block_delta\ndata: {\"delta\":{\"text\":");
+        if let Err(e) = serde_json::to_writer((&mut self.buf).writer(), text) {
+            tracing::warn!("SSE 事件序列化失败: {}", e);
+        }
+        self.buf.put_slice(b",\"type\":\"text_delta\"},\"index\":");
+        let _ = write!(self.buf, "{}", index);
+        self.buf
+            .put_slice(b",\"type\":\"content_block_delta\"}\n\n");
+        self.buf.split().freeze()
+    }
 }
 
 /// 内容块状态
@@ -237,6 +362,10 @@ pub struct SseStateManager {
     stop_reason: Option<String>,
     /// 是否有工具调用
     has_tool_use: bool,
+    /// 是否因请求截止时间提前结束
+    deadline_exceeded: bool,
+    /// 命中的 stop sequence
+    stop_sequence: Option<String>,
 }
 
 impl Default for SseStateManager {
@@ -255,6 +384,8 @@ impl SseStateManager {
             next_block_index: 0,
             stop_reason: None,
             has_tool_use: false,
+            deadline_exceeded: false,
+            stop_sequence: None,
         }
     }
 
@@ -265,6 +396,21 @@ impl SseStateManager {
             .is_some_and(|b| b.started && !b.stopped && b.block_type == expected_type)
     }
 
+    /// 结束所有未结束的工具块（按索引顺序）
+    fn close_tool_blocks(&mut self) -> Vec<SseEvent> {
+        let mut indices: Vec<i32> = self
+            .active_blocks
+            .iter()
+            .filter(|(_, b)| b.started && !b.stopped && b.block_type.ends_with("tool_use"))
+            .map(|(index, _)| *index)
+            .collect();
+        indices.sort_unstable();
+        indices
+            .into_iter()
+            .filter_map(|index| self.handle_content_block_stop(index))
+            .collect()
+    }
+
     /// 获取下一个块索引
     pub fn next_block_index(&mut self) -> i32 {
         let index = self.next_block_index;
@@ -282,6 +428,18 @@ impl SseStateManager {
         self.stop_reason = Some(reason.into());
     }
 
+    /// 记录命中的 stop sequence：stop_reason 记为 stop_sequence，message_delta 回显该序列
+    pub fn set_stop_sequence(&mut self, sequence: impl Into<String>) {
+        self.stop_sequence = Some(sequence.into());
+        self.set_stop_reason("stop_sequence");
+    }
+
+    /// 标记请求截止时间已到：stop_reason 记为 max_tokens，message_delta 附带 `deadline_exceeded`
+    pub fn mark_deadline_exceeded(&mut self) {
+        self.deadline_exceeded = true;
+        self.set_stop_reason("max_tokens");
+    }
+
     /// 检查是否存在非 thinking 类型的内容块（如 text 或 tool_use）
     fn has_non_thinking_blocks(&self) -> bool {
         self.active_blocks
@@ -319,9 +477,12 @@ impl SseStateManager {
     ) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
-        // 如果是 tool_use 块，先关闭之前的文本块
-        if block_type == "tool_use" {
-            self.has_tool_use = true;
+        // 如果是 tool_use / server_tool_use 块，先关闭之前的文本块
+        // 只有客户端工具调用会让 stop_reason 变为 tool_use
+        if block_type == "tool_use" || block_type == "server_tool_use" {
+            if block_type == "tool_use" {
+                self.has_tool_use = true;
+            }
             for (block_index, block) in self.active_blocks.iter_mut() {
                 if block.block_type == "text" && block.started && !block.stopped {
                     // 自动发送 content_block_stop 关闭文本块
@@ -404,6 +565,8 @@ impl SseStateManager {
         &mut self,
         input_tokens: i32,
         output_tokens: i32,
+        server_tool_usage: &ServerToolUsage,
+        estimated_cost: Option<f64>,
     ) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
@@ -424,18 +587,29 @@ impl SseStateManager {
         // 发送 message_delta
         if !self.message_delta_sent {
             self.message_delta_sent = true;
+            let mut usage = json!({
+                "input_tokens": input_tokens,
+                "output_tokens": output_tokens
+            });
+            if !server_tool_usage.is_empty() {
+                usage["server_tool_use"] = server_tool_usage.to_json();
+            }
+            if let Some(cost) = estimated_cost {
+                usage["kiro_estimated_cost"] = json!(cost);
+            }
+            let mut delta = json!({
+                "stop_reason": self.get_stop_reason(),
+                "stop_sequence": self.stop_sequence
+            });
+            if self.deadline_exceeded {
+                delta["deadline_exceeded"] = json!(true);
+            }
             events.push(SseEvent::new(
                 "message_delta",
                 json!({
                     "type": "message_delta",
-                    "delta": {
-                        "stop_reason": self.get_stop_reason(),
-                        "stop_sequence": null
-                    },
-                    "usage": {
-                        "input_tokens": input_tokens,
-                        "output_tokens": output_tokens
-                    }
+                    "delta": delta,
+                    "usage": usage
                 }),
             ));
         }
@@ -456,6 +630,52 @@ impl SseStateManager {
 /// 上下文窗口大小（200k tokens）
 const CONTEXT_WINDOW_SIZE: i32 = 200_000;
 
+/// 周期性用量事件触发器
+///
+/// 满足时间间隔或内容块间隔之一，且输出 tokens 有变化时触发
+#[derive(Debug, Clone)]
+pub struct UsageReporter {
+    interval: Option<Duration>,
+    every_blocks: Option<i32>,
+    last_at: Instant,
+    last_blocks: i32,
+    last_output_tokens: i32,
+}
+
+impl UsageReporter {
+    /// 创建触发器，两种间隔都为 0 时返回 None
+    pub fn new(interval_secs: u64, every_blocks: usize) -> Option<Self> {
+        let interval = (interval_secs > 0).then(|| Duration::from_secs(interval_secs));
+        let every_blocks = (every_blocks > 0).then_some(every_blocks as i32);
+        if interval.is_none() && every_blocks.is_none() {
+            return None;
+        }
+        Some(Self {
+            interval,
+            every_blocks,
+            last_at: Instant::now(),
+            last_blocks: 0,
+            last_output_tokens: 0,
+        })
+    }
+
+    fn should_report(&self, blocks: i32, output_tokens: i32) -> bool {
+        if output_tokens == self.last_output_tokens {
+            return false;
+        }
+        self.interval.is_some_and(|d| self.last_at.elapsed() >= d)
+            || self
+                .every_blocks
+                .is_some_and(|n| blocks - self.last_blocks >= n)
+    }
+
+    fn mark_reported(&mut self, blocks: i32, output_tokens: i32) {
+        self.last_at = Instant::now();
+        self.last_blocks = blocks;
+        self.last_output_tokens = output_tokens;
+    }
+}
+
 /// 流处理上下文
 pub struct StreamContext {
     /// SSE 状态管理器
@@ -468,10 +688,18 @@ pub struct StreamContext {
     pub input_tokens: i32,
     /// 从 contextUsageEvent 计算的实际输入 tokens
     pub context_input_tokens: Option<i32>,
-    /// 输出 tokens 累计
-    pub output_tokens: i32,
+    /// 输出 tokens 累计（含 max_tokens 检查）
+    output: OutputBudget,
     /// 工具块索引映射 (tool_id -> block_index)
     pub tool_block_indices: HashMap<String, i32>,
+    /// 上游 tool_use_id 去重
+    tool_ids: ToolUseIds,
+    /// 工具调用迟到片段的等待窗口
+    reorder: ReorderWindow,
+    /// 请求中声明的服务端工具
+    server_tools: DeclaredServerTools,
+    /// 服务端工具调用计数
+    pub server_tool_usage: ServerToolUsage,
     /// thinking 是否启用
     pub thinking_enabled: bool,
     /// thinking 内容缓冲区
@@ -487,6 +715,42 @@ pub struct StreamContext {
     /// 是否需要剥离 thinking 内容开头的换行符
     /// 模型输出 `<thinking>\n` 时，`\n` 可能与标签在同一 chunk 或下一 chunk
     strip_thinking_leading_newline: bool,
+    /// SSE 事件序列严格程度
+    profile: SseProfile,
+    /// 注入策略回显过滤器
+    echo_filter: Option<EchoFilter>,
+    /// 周期性用量事件触发器
+    usage_reporter: Option<UsageReporter>,
+    /// 上游会话 ID 核对器
+    #[cfg(not(edge))]
+    conversation: Option<ConversationTracker>,
+    /// thinking 预算（tokens），为 None 时不限制
+    thinking_budget: Option<i32>,
+    /// 已下发的 thinking tokens（估算值）
+    thinking_tokens: i32,
+    /// thinking 是否因超出预算被提前关闭
+    thinking_budget_exceeded: bool,
+    /// 代码引用收集器（启用追加引用说明时）
+    references: Option<ReferenceCollector>,
+    /// 请求的 stop_sequences 过滤器
+    stop_sequences: Option<StopSequenceMatcher>,
+    /// 工具输入校验器
+    tool_validator: Option<ToolInputValidator>,
+    /// 待校验的工具输入 (tool_id -> 已收到的输入)
+    tool_inputs: HashMap<String, String>,
+    /// 用量报表记录句柄（流结束时记录）
+    #[cfg(not(edge))]
+    request_usage: Option<RequestUsage>,
+    /// 编辑类工具 diff 预览生成器
+    diff_preview: Option<DiffPreviewer>,
+    /// 单个 input_json_delta 的最大字节数，为 None 时整段下发
+    tool_input_chunk_bytes: Option<usize>,
+    /// 拒绝回复识别
+    refusal: Option<RefusalDetector>,
+    /// 输出是否已达到 max_tokens
+    max_tokens_reached: bool,
+    /// 是否已以 error 事件结束
+    failed: bool,
 }
 
 impl StreamContext {
@@ -502,8 +766,12 @@ impl StreamContext {
             message_id: format!("msg_{}", Uuid::new_v4().to_string().replace('-', "")),
             input_tokens,
             context_input_tokens: None,
-            output_tokens: 0,
+            output: OutputBudget::default(),
             tool_block_indices: HashMap::new(),
+            tool_ids: ToolUseIds::default(),
+            reorder: ReorderWindow::default(),
+            server_tools: DeclaredServerTools::default(),
+            server_tool_usage: ServerToolUsage::default(),
             thinking_enabled,
             thinking_buffer: String::new(),
             in_thinking_block: false,
@@ -511,7 +779,142 @@ impl StreamContext {
             thinking_block_index: None,
             text_block_index: None,
             strip_thinking_leading_newline: false,
+            profile: SseProfile::default(),
+            echo_filter: None,
+            usage_reporter: None,
+            #[cfg(not(edge))]
+            conversation: None,
+            thinking_budget: None,
+            thinking_tokens: 0,
+            thinking_budget_exceeded: false,
+            references: None,
+            stop_sequences: None,
+            tool_validator: None,
+            tool_inputs: HashMap::new(),
+            #[cfg(not(edge))]
+            request_usage: None,
+            diff_preview: None,
+            tool_input_chunk_bytes: None,
+            refusal: None,
+            max_tokens_reached: false,
+            failed: false,
+        }
+    }
+
+    /// 设置 SSE 事件序列严格程度
+    pub fn with_profile(mut self, profile: SseProfile) -> Self {
+        self.profile = profile;
+        self
+    }
+
+    /// 启用注入策略回显过滤
+    pub fn with_echo_filter(mut self, filter: EchoFilter) -> Self {
+        self.echo_filter = Some(filter);
+        self
+    }
+
+    /// 启用周期性用量事件
+    pub fn with_usage_reporter(mut self, reporter: UsageReporter) -> Self {
+        self.usage_reporter = Some(reporter);
+        self
+    }
+
+    /// 启用上游会话 ID 核对
+    #[cfg(not(edge))]
+    pub fn with_conversation_tracker(mut self, tracker: ConversationTracker) -> Self {
+        self.conversation = Some(tracker);
+        self
+    }
+
+    /// 按请求的 `thinking.budget_tokens` 限制下发的 thinking 内容
+    pub fn with_thinking_budget(mut self, budget_tokens: i32) -> Self {
+        self.thinking_budget = Some(budget_tokens);
+        self
+    }
+
+    /// 收集上游代码引用，在回复末尾追加引用说明
+    pub fn with_code_references(mut self) -> Self {
+        self.references = Some(ReferenceCollector::default());
+        self
+    }
+
+    /// 在输出中查找 stop_sequences，命中时截断输出并回显该序列
+    pub fn with_stop_sequences(mut self, sequences: &[String]) -> Self {
+        self.stop_sequences = StopSequenceMatcher::new(sequences);
+        self
+    }
+
+    /// 按 input_schema 校验客户端工具的输入
+    pub fn with_tool_validator(mut self, validator: ToolInputValidator) -> Self {
+        self.tool_validator = Some(validator);
+        self
+    }
+
+    /// 为编辑类工具的输入并行下发 diff 预览事件（kiro_diff）
+    pub fn with_diff_preview(mut self, previewer: DiffPreviewer) -> Self {
+        self.diff_preview = Some(previewer);
+        self
+    }
+
+    /// 将较大的工具输入拆分为多个不超过 `bytes` 字节的 input_json_delta
+    pub fn with_tool_input_chunking(mut self, bytes: usize) -> Self {
+        self.tool_input_chunk_bytes = (bytes > 0).then_some(bytes);
+        self
+    }
+
+    /// 按请求中声明的服务端工具区分 `server_tool_use` 与 `tool_use`
+    pub fn with_server_tools(mut self, tools: DeclaredServerTools) -> Self {
+        self.server_tools = tools;
+        self
+    }
+
+    /// 工具调用的 stop 先于完整输入到达时，在之后的 `events` 个上游事件内接收迟到的输入片段
+    pub fn with_reorder_window(mut self, events: u32) -> Self {
+        self.reorder = ReorderWindow::new(events);
+        self
+    }
+
+    /// 识别拒绝回复，stop_reason 报告为 refusal
+    pub fn with_refusal_detection(mut self, classifier: &'static RefusalClassifier) -> Self {
+        self.refusal = Some(classifier.detector());
+        self
+    }
+
+    /// 按请求的 max_tokens 限制输出，达到上限后截断并以 max_tokens 结束
+    pub fn with_max_tokens(mut self, max_tokens: i32) -> Self {
+        self.output = OutputBudget::new(Some(max_tokens));
+        self
+    }
+
+    /// 流结束时把最终用量记入用量报表
+    #[cfg(not(edge))]
+    pub fn with_request_usage(mut self, usage: RequestUsage) -> Self {
+        self.request_usage = Some(usage);
+        self
+    }
+
+    /// 检查是否需要发送累计用量事件（kiro_usage）
+    ///
+    /// 应在每批上游事件处理完后调用
+    pub fn poll_usage_event(&mut self) -> Option<SseEvent> {
+        let blocks = self.state_manager.next_block_index;
+        let reporter = self.usage_reporter.as_mut()?;
+        if !reporter.should_report(blocks, self.output.approx_tokens()) {
+            return None;
         }
+        let output_tokens = self.output.tokens();
+        reporter.mark_reported(blocks, output_tokens);
+
+        Some(SseEvent::new(
+            "kiro_usage",
+            json!({
+                "type": "kiro_usage",
+                "usage": {
+                    "input_tokens": self.context_input_tokens.unwrap_or(self.input_tokens),
+                    "output_tokens": output_tokens
+                }
+            }),
+        ))
     }
 
     /// 生成 message_start 事件
@@ -534,10 +937,9 @@ impl StreamContext {
         })
     }
 
-    /// 生成初始事件序列 (message_start + 文本块 start)
+    /// 生成初始事件序列（message_start）
     ///
-    /// 当 thinking 启用时，不在初始化时创建文本块，而是等到实际收到内容时再创建。
-    /// 这样可以确保 thinking 块（索引 0）在文本块（索引 1）之前。
+    /// 内容块都等到实际收到内容时再创建，块索引按下发顺序递增
     pub fn generate_initial_events(&mut self) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
@@ -547,36 +949,57 @@ impl StreamContext {
             events.push(event);
         }
 
-        // 如果启用了 thinking，不在这里创建文本块
-        // thinking 块和文本块会在 process_content_with_thinking 中按正确顺序创建
-        if self.thinking_enabled {
-            return events;
+        // 文本块在第一段文本到达时才创建（见 emit_text_delta），与官方 API 一致：
+        // 直接以 tool_use 开头的回复从索引 0 开始就是 tool_use 块，不会出现空的文本块；
+        // 启用 thinking 时 thinking 块和文本块由 process_content_with_thinking 按顺序创建
+        events
+    }
+
+    /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
+    pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+        // 已以 error 事件结束，丢弃之后的所有事件
+        if self.failed {
+            return Vec::new();
+        }
+        // 命中 stop sequence 或达到 max_tokens 后不再下发任何内容，只接收迟到的 contextUsage
+        if self.output_stopped() {
+            self.reorder.tick_after_stop();
+            if matches!(event, Event::AssistantResponse(_) | Event::ToolUse(_)) {
+                return Vec::new();
+            }
         }
 
-        // 创建初始文本块（仅在未启用 thinking 时）
-        let text_block_index = self.state_manager.next_block_index();
-        self.text_block_index = Some(text_block_index);
-        let text_block_events = self.state_manager.handle_content_block_start(
-            text_block_index,
-            "text",
-            json!({
-                "type": "content_block_start",
-                "index": text_block_index,
-                "content_block": {
-                    "type": "text",
-                    "text": ""
+        // 合并 stop 之后迟到的工具输入片段；其他内容开始前或等待窗口用完时结束暂缓的工具块
+        let deferred = match event {
+            Event::ToolUse(tool_use) => {
+                if let Some(late) =
+                    self.reorder
+                        .accept(&tool_use.tool_use_id, &tool_use.input, tool_use.stop)
+                {
+                    return self.emit_late_fragment(&tool_use.input, late);
                 }
-            }),
-        );
-        events.extend(text_block_events);
-
+                self.reorder.drain()
+            }
+            Event::AssistantResponse(resp) if !resp.content.is_empty() => self.reorder.drain(),
+            _ => self.reorder.tick(),
+        };
+        let mut events = self.close_deferred_blocks(deferred);
+        events.extend(self.dispatch_kiro_event(event));
         events
     }
 
-    /// 处理 Kiro 事件并转换为 Anthropic SSE 事件
-    pub fn process_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
+    /// 按事件类型处理上游事件
+    fn dispatch_kiro_event(&mut self, event: &Event) -> Vec<SseEvent> {
         match event {
-            Event::AssistantResponse(resp) => self.process_assistant_response(&resp.content),
+            Event::AssistantResponse(resp) => {
+                #[cfg(not(edge))]
+                if let (Some(tracker), Some(id)) =
+                    (self.conversation.as_mut(), resp.conversation_id.as_deref())
+                {
+                    tracker.observe(id);
+                }
+                self.process_assistant_response(&resp.content)
+            }
             Event::ToolUse(tool_use) => self.process_tool_use(tool_use),
             Event::ContextUsage(context_usage) => {
                 // 从上下文使用百分比计算实际的 input_tokens
@@ -602,17 +1025,45 @@ impl StreamContext {
                 error_message,
             } => {
                 tracing::error!("收到错误事件: {} - {}", error_code, error_message);
-                Vec::new()
+                self.generate_error_events(&ApiError::from_upstream_event(
+                    error_code,
+                    error_message,
+                ))
             }
             Event::Exception {
                 exception_type,
                 message,
             } => {
-                // 处理 ContentLengthExceededException
-                if exception_type == "ContentLengthExceededException" {
+                let kind = ExceptionKind::from_type(exception_type);
+                tracing::warn!(
+                    kind = %kind,
+                    retryable = kind.is_retryable(),
+                    "收到异常事件: {} - {}",
+                    exception_type,
+                    message
+                );
+                // 输出被截断
+                if kind == ExceptionKind::ContentLengthExceeded {
                     self.state_manager.set_stop_reason("max_tokens");
+                    return Vec::new();
+                }
+                if let Some(refusal) = self.refusal.as_mut()
+                    && refusal.observe_exception(exception_type)
+                {
+                    tracing::info!("上游异常 {} 识别为拒绝回复", exception_type);
+                    return Vec::new();
+                }
+                // 其他异常按种类映射为对应类型的 error 事件并结束流
+                self.generate_error_events(&ApiError::from_upstream_event(exception_type, message))
+            }
+            Event::CodeReference(code_reference) => {
+                tracing::debug!(
+                    "收到 codeReferenceEvent: {} 条引用",
+                    code_reference.references.len()
+                );
+                if let Some(collector) = self.references.as_mut() {
+                    collector.push(code_reference);
                 }
-                tracing::warn!("收到异常事件: {} - {}", exception_type, message);
                 Vec::new()
             }
             _ => Vec::new(),
@@ -625,8 +1076,41 @@ impl StreamContext {
             return Vec::new();
         }
 
-        // 估算 tokens
-        self.output_tokens += estimate_tokens(content);
+        // 累计 tokens，超出 max_tokens 的部分被截断
+        let content = self.admit_output(content);
+        if content.is_empty() {
+            return Vec::new();
+        }
+
+        // 剥离注入策略文本的回显（可能暂存末尾不完整的部分）
+        match self.echo_filter.as_mut().map(|f| f.push(content)) {
+            Some(filtered) => self.emit_assistant_content(&filtered),
+            None => self.emit_assistant_content(content),
+        }
+    }
+
+    /// 输出回显过滤器中暂存的文本
+    fn flush_echo_filter(&mut self) -> Vec<SseEvent> {
+        match self.echo_filter.as_mut().map(EchoFilter::flush) {
+            Some(pending) => self.emit_assistant_content(&pending),
+            None => Vec::new(),
+        }
+    }
+
+    /// 输出助手文本（按 thinking 配置拆分为 thinking / text 块）
+    fn emit_assistant_content(&mut self, content: &str) -> Vec<SseEvent> {
+        if content.is_empty() {
+            return Vec::new();
+        }
+
+        // 快速路径：文本块已打开且没有待处理的 thinking 标签或 stop_sequences 时直接下发，
+        // 不经过 thinking 缓冲区
+        if let Some(index) = self.fast_text_block() {
+            if let Some(refusal) = self.refusal.as_mut() {
+                refusal.observe_text(content);
+            }
+            return vec![SseEvent::text_delta(index, content)];
+        }
 
         // 如果启用了thinking，需要处理thinking块
         if self.thinking_enabled {
@@ -638,6 +1122,19 @@ impl StreamContext {
         self.create_text_delta_events(content)
     }
 
+    /// 可走快速路径时返回当前打开的文本块索引
+    fn fast_text_block(&self) -> Option<i32> {
+        let thinking_settled = !self.thinking_enabled
+            || (self.thinking_extracted
+                && !self.in_thinking_block
+                && self.thinking_buffer.is_empty());
+        if !thinking_settled || self.stop_sequences.is_some() {
+            return None;
+        }
+        self.text_block_index
+            .filter(|&index| self.state_manager.is_block_open_of_type(index, "text"))
+    }
+
     /// 处理包含thinking块的内容
     fn process_content_with_thinking(&mut self, content: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
@@ -674,7 +1171,8 @@ impl StreamContext {
                             "index": thinking_index,
                             "content_block": {
                                 "type": "thinking",
-                                "thinking": ""
+                                "thinking": "",
+                                "signature": ""
                             }
                         }),
                     );
@@ -718,12 +1216,10 @@ impl StreamContext {
                 if let Some(end_pos) = find_real_thinking_end_tag(&self.thinking_buffer) {
                     // 提取 thinking 内容
                     let thinking_content = self.thinking_buffer[..end_pos].to_string();
-                    if !thinking_content.is_empty() {
-                        if let Some(thinking_index) = self.thinking_block_index {
-                            events.push(
-                                self.create_thinking_delta_event(thinking_index, &thinking_content),
-                            );
-                        }
+                    if !thinking_content.is_empty()
+                        && let Some(thinking_index) = self.thinking_block_index
+                    {
+                        events.extend(self.emit_thinking_delta(thinking_index, &thinking_content));
                     }
 
                     // 结束 thinking 块
@@ -752,12 +1248,10 @@ impl StreamContext {
                     let safe_len = find_char_boundary(&self.thinking_buffer, target_len);
                     if safe_len > 0 {
                         let safe_content = self.thinking_buffer[..safe_len].to_string();
-                        if !safe_content.is_empty() {
-                            if let Some(thinking_index) = self.thinking_block_index {
-                                events.push(
-                                    self.create_thinking_delta_event(thinking_index, &safe_content),
-                                );
-                            }
+                        if !safe_content.is_empty()
+                            && let Some(thinking_index) = self.thinking_block_index
+                        {
+                            events.extend(self.emit_thinking_delta(thinking_index, &safe_content));
                         }
                         self.thinking_buffer = self.thinking_buffer[safe_len..].to_string();
                     }
@@ -777,21 +1271,89 @@ impl StreamContext {
         events
     }
 
-    /// 创建 text_delta 事件
+    /// 计入一段模型输出，达到 max_tokens 时返回截断后的部分并标记输出停止
+    fn admit_output<'a>(&mut self, content: &'a str) -> &'a str {
+        let (kept, reached) = self.output.admit(content);
+        if reached {
+            self.max_tokens_reached = true;
+            self.state_manager.set_stop_reason("max_tokens");
+            tracing::debug!(
+                "输出达到 max_tokens ({})，截断后续内容",
+                self.output.limit.unwrap_or_default()
+            );
+        }
+        kept
+    }
+
+    /// 输出 tokens 累计
+    pub fn output_tokens(&mut self) -> i32 {
+        self.output.tokens()
+    }
+
+    /// 是否已命中 stop sequence
+    pub fn stop_sequence_hit(&self) -> bool {
+        self.stop_sequences
+            .as_ref()
+            .is_some_and(|m| m.matched().is_some())
+    }
+
+    /// 是否已停止输出：命中 stop sequence 或达到 max_tokens（调用方应结束流并断开上游）
+    pub fn output_stopped(&self) -> bool {
+        self.max_tokens_reached || self.stop_sequence_hit()
+    }
+
+    /// 输出停止后是否还应继续读取上游，等待迟到的 contextUsage（见 [`ReorderWindow`]）
+    pub fn awaits_context_usage(&self) -> bool {
+        self.output_stopped()
+            && self.context_input_tokens.is_none()
+            && self.reorder.within_window_after_stop()
+    }
+
+    /// 是否已以 error 事件结束（上游错误或响应流中断）
+    pub fn failed(&self) -> bool {
+        self.failed
+    }
+
+    /// 下发 stop_sequences 过滤器中暂存的文本
+    fn flush_stop_sequences(&mut self) -> Vec<SseEvent> {
+        match self.stop_sequences.as_mut().map(StopSequenceMatcher::flush) {
+            Some(pending) if !pending.is_empty() => self.emit_text_delta(&pending),
+            _ => Vec::new(),
+        }
+    }
+
+    /// 创建 text_delta 事件（经过 stop_sequences 过滤）
+    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+        let filtered = match self.stop_sequences.as_mut().map(|m| m.push(text)) {
+            Some(filtered) if filtered.is_empty() => return Vec::new(),
+            Some(filtered) => Cow::Owned(filtered),
+            None => Cow::Borrowed(text),
+        };
+        if let Some(refusal) = self.refusal.as_mut() {
+            refusal.observe_text(&filtered);
+        }
+        self.emit_text_delta(&filtered)
+    }
+
+    /// 下发 text_delta 事件
     ///
     /// 如果文本块尚未创建，会先创建文本块。
     /// 当发生 tool_use 时，状态机会自动关闭当前文本块；后续文本会自动创建新的文本块继续输出。
     ///
     /// 返回值包含可能的 content_block_start 事件和 content_block_delta 事件。
-    fn create_text_delta_events(&mut self, text: &str) -> Vec<SseEvent> {
+    fn emit_text_delta(&mut self, text: &str) -> Vec<SseEvent> {
         let mut events = Vec::new();
 
         // 如果当前 text_block_index 指向的块已经被关闭（例如 tool_use 开始时自动 stop），
         // 则丢弃该索引并创建新的文本块继续输出，避免 delta 被状态机拒绝导致“吞字”。
-        if let Some(idx) = self.text_block_index {
-            if !self.state_manager.is_block_open_of_type(idx, "text") {
-                self.text_block_index = None;
-            }
+        if let Some(idx) = self.text_block_index
+            && !self.state_manager.is_block_open_of_type(idx, "text")
+        {
+            self.text_block_index = None;
+        }
+        // 严格模式下内容块不交错：先结束仍未结束的工具块，再开启新的文本块
+        if self.text_block_index.is_none() && self.profile == SseProfile::Strict {
+            events.extend(self.state_manager.close_tool_blocks());
         }
 
         // 获取或创建文本块索引
@@ -820,23 +1382,48 @@ impl StreamContext {
         };
 
         // 发送 content_block_delta 事件
-        if let Some(delta_event) = self.state_manager.handle_content_block_delta(
-            text_index,
-            json!({
-                "type": "content_block_delta",
-                "index": text_index,
-                "delta": {
-                    "type": "text_delta",
-                    "text": text
-                }
-            }),
-        ) {
+        let delta = SseEvent::text_delta(text_index, text);
+        if let Some(delta_event) = self
+            .state_manager
+            .handle_content_block_delta(text_index, delta.data)
+        {
             events.push(delta_event);
         }
 
         events
     }
 
+    /// 下发 thinking 内容（受 thinking 预算约束）
+    ///
+    /// 累计 tokens 超出预算时，在本段之后关闭 thinking 块；之后的 thinking 内容
+    /// 只计入指标不再下发，上游的 `</thinking>` 仍照常解析以便后续文本正常输出
+    fn emit_thinking_delta(&mut self, index: i32, thinking: &str) -> Vec<SseEvent> {
+        let Some(budget) = self.thinking_budget else {
+            return vec![self.create_thinking_delta_event(index, thinking)];
+        };
+        let tokens = estimate_tokens(thinking);
+        if self.thinking_budget_exceeded {
+            #[cfg(not(edge))]
+            metrics::thinking_budget().record_suppressed(tokens as u64);
+            return Vec::new();
+        }
+
+        self.thinking_tokens += tokens;
+        let mut events = vec![self.create_thinking_delta_event(index, thinking)];
+        if self.thinking_tokens > budget {
+            tracing::warn!(
+                budget_tokens = budget,
+                thinking_tokens = self.thinking_tokens,
+                "thinking 超出预算，提前关闭 thinking 块"
+            );
+            #[cfg(not(edge))]
+            metrics::thinking_budget().record_overflow();
+            events.extend(self.close_thinking_block(index));
+            self.thinking_budget_exceeded = true;
+        }
+        events
+    }
+
     /// 创建 thinking_delta 事件
     fn create_thinking_delta_event(&self, index: i32, thinking: &str) -> SseEvent {
         SseEvent::new(
@@ -862,7 +1449,7 @@ impl StreamContext {
         let mut hasher = Sha256::new();
         hasher.update(self.message_id.as_bytes());
         hasher.update(index.to_le_bytes());
-        hasher.update(self.output_tokens.to_le_bytes());
+        hasher.update(self.output.approx_tokens().to_le_bytes());
         let hash = hasher.finalize();
         let signature = hex::encode(hash);
         SseEvent::new(
@@ -883,6 +1470,10 @@ impl StreamContext {
     /// 按 Anthropic API 规范发送：空 thinking_delta → signature_delta → content_block_stop
     fn close_thinking_block(&mut self, thinking_index: i32) -> Vec<SseEvent> {
         let mut events = Vec::new();
+        // 超出预算时已提前关闭
+        if self.thinking_budget_exceeded {
+            return events;
+        }
         // 空的 thinking_delta
         events.push(self.create_thinking_delta_event(thinking_index, ""));
         // signature_delta（Claude Code 依赖此事件）
@@ -895,44 +1486,120 @@ impl StreamContext {
     }
 
     /// 处理工具使用事件
-    fn process_tool_use(
-        &mut self,
-        tool_use: &crate::kiro::model::events::ToolUseEvent,
-    ) -> Vec<SseEvent> {
-        let mut events = Vec::new();
+    ///
+    /// 启用工具输入校验时，累积客户端工具的输入并在调用完整后校验：
+    /// 附加警告模式下照常下发，拦截模式下缓存到校验通过后再一次性下发
+    fn process_tool_use(&mut self, tool_use: &ToolUseEvent) -> Vec<SseEvent> {
+        let Some(mode) = self.tool_validator.as_ref().map(|v| v.mode()) else {
+            return self.emit_tool_use(tool_use);
+        };
+        if self.server_tools.lookup(&tool_use.name).is_some() {
+            return self.emit_tool_use(tool_use);
+        }
+
+        let buffer = self
+            .tool_inputs
+            .entry(tool_use.tool_use_id.clone())
+            .or_default();
+        buffer.push_str(&tool_use.input);
+        if !tool_use.stop {
+            if mode == ToolInputValidation::Correct {
+                return Vec::new();
+            }
+            return self.emit_tool_use(tool_use);
+        }
+
+        let input = self
+            .tool_inputs
+            .remove(&tool_use.tool_use_id)
+            .unwrap_or_default();
+        let result = match &self.tool_validator {
+            Some(validator) => validator.validate(&tool_use.name, &input),
+            None => Ok(()),
+        };
+        match (mode, result) {
+            (ToolInputValidation::Correct, Ok(())) => self.emit_tool_use(&ToolUseEvent {
+                input,
+                ..tool_use.clone()
+            }),
+            (ToolInputValidation::Correct, Err(errors)) => {
+                let mut events = self.flush_echo_filter();
+                events.extend(
+                    self.create_text_delta_events(&tool_validation::correction_text(
+                        &tool_use.name,
+                        &errors,
+                    )),
+                );
+                events
+            }
+            (_, Ok(())) => self.emit_tool_use(tool_use),
+            (_, Err(errors)) => {
+                let mut events = self.emit_tool_use(tool_use);
+                if let Some(stop) = events
+                    .iter_mut()
+                    .rev()
+                    .find(|e| e.event == "content_block_stop")
+                {
+                    stop.data["kiro_warning"] = tool_validation::warning(&errors);
+                }
+                events
+            }
+        }
+    }
+
+    /// 下发工具使用事件
+    fn emit_tool_use(&mut self, tool_use: &ToolUseEvent) -> Vec<SseEvent> {
+        // 先输出回显过滤器与 stop_sequences 过滤器中暂存的文本
+        let mut events = self.flush_echo_filter();
+        if self.stop_sequence_hit() {
+            return events;
+        }
+        events.extend(self.flush_stop_sequences());
+
+        // 上游复用已结束调用的 ID 时分配新 ID，避免并入前一个调用的内容块
+        let call_id = self.tool_ids.observe(&tool_use.tool_use_id, tool_use.stop);
+
+        // 按请求中声明的服务端工具区分客户端工具与服务端工具
+        let server_tool = self.server_tools.lookup(&tool_use.name);
+        let block_type = self.server_tools.content_block_type(&tool_use.name);
+        let tool_use_id = match server_tool {
+            Some(_) => server_tools::to_server_tool_use_id(&call_id),
+            None => call_id.clone(),
+        };
 
-        self.state_manager.set_has_tool_use(true);
+        if server_tool.is_none() {
+            self.state_manager.set_has_tool_use(true);
+        }
 
         // tool_use 必须发生在 thinking 结束之后。
         // 但当 `</thinking>` 后面没有 `\n\n`（例如紧跟 tool_use 或流结束）时，
         // thinking 结束标签会滞留在 thinking_buffer，导致后续 flush 时把 `</thinking>` 当作内容输出。
         // 这里在开始 tool_use block 前做一次“边界场景”的结束标签识别与过滤。
-        if self.thinking_enabled && self.in_thinking_block {
-            if let Some(end_pos) = find_real_thinking_end_tag_at_buffer_end(&self.thinking_buffer) {
-                let thinking_content = self.thinking_buffer[..end_pos].to_string();
-                if !thinking_content.is_empty() {
-                    if let Some(thinking_index) = self.thinking_block_index {
-                        events.push(
-                            self.create_thinking_delta_event(thinking_index, &thinking_content),
-                        );
-                    }
-                }
+        if self.thinking_enabled
+            && self.in_thinking_block
+            && let Some(end_pos) = find_real_thinking_end_tag_at_buffer_end(&self.thinking_buffer)
+        {
+            let thinking_content = self.thinking_buffer[..end_pos].to_string();
+            if !thinking_content.is_empty()
+                && let Some(thinking_index) = self.thinking_block_index
+            {
+                events.extend(self.emit_thinking_delta(thinking_index, &thinking_content));
+            }
 
-                // 结束 thinking 块
-                self.in_thinking_block = false;
-                self.thinking_extracted = true;
+            // 结束 thinking 块
+            self.in_thinking_block = false;
+            self.thinking_extracted = true;
 
-                if let Some(thinking_index) = self.thinking_block_index {
-                    events.extend(self.close_thinking_block(thinking_index));
-                }
+            if let Some(thinking_index) = self.thinking_block_index {
+                events.extend(self.close_thinking_block(thinking_index));
+            }
 
-                // 把结束标签后的内容当作普通文本（通常为空或空白）
-                let after_pos = end_pos + "</thinking>".len();
-                let remaining = self.thinking_buffer[after_pos..].trim_start().to_string();
-                self.thinking_buffer.clear();
-                if !remaining.is_empty() {
-                    events.extend(self.create_text_delta_events(&remaining));
-                }
+            // 把结束标签后的内容当作普通文本（通常为空或空白）
+            let after_pos = end_pos + "</thinking>".len();
+            let remaining = self.thinking_buffer[after_pos..].trim_start().to_string();
+            self.thinking_buffer.clear();
+            if !remaining.is_empty() {
+                events.extend(self.create_text_delta_events(&remaining));
             }
         }
 
@@ -949,25 +1616,24 @@ impl StreamContext {
         }
 
         // 获取或分配块索引
-        let block_index = if let Some(&idx) = self.tool_block_indices.get(&tool_use.tool_use_id) {
+        let block_index = if let Some(&idx) = self.tool_block_indices.get(&call_id) {
             idx
         } else {
             let idx = self.state_manager.next_block_index();
-            self.tool_block_indices
-                .insert(tool_use.tool_use_id.clone(), idx);
+            self.tool_block_indices.insert(call_id.clone(), idx);
             idx
         };
 
         // 发送 content_block_start
         let start_events = self.state_manager.handle_content_block_start(
             block_index,
-            "tool_use",
+            block_type,
             json!({
                 "type": "content_block_start",
                 "index": block_index,
                 "content_block": {
-                    "type": "tool_use",
-                    "id": tool_use.tool_use_id,
+                    "type": block_type,
+                    "id": tool_use_id,
                     "name": tool_use.name,
                     "input": {}
                 }
@@ -976,9 +1642,56 @@ impl StreamContext {
         events.extend(start_events);
 
         // 发送参数增量 (ToolUseEvent.input 是 String 类型)
-        if !tool_use.input.is_empty() {
-            self.output_tokens += (tool_use.input.len() as i32 + 3) / 4; // 估算 token
+        events.extend(self.create_input_json_delta_events(block_index, &tool_use.input));
+        // 输入被 max_tokens 截断：内容块由结束事件统一关闭
+        if self.max_tokens_reached {
+            return events;
+        }
+        self.reorder.observe(&call_id, &tool_use.input);
+
+        if server_tool.is_none()
+            && let Some(previewer) = self.diff_preview.as_mut()
+            && let Some(event) = previewer.feed(
+                block_index,
+                &call_id,
+                &tool_use.name,
+                &tool_use.input,
+                tool_use.stop,
+            )
+        {
+            events.push(event);
+        }
+
+        // 如果是完整的工具调用（stop=true），发送 content_block_stop
+        if tool_use.stop {
+            if let Some(spec) = server_tool {
+                self.server_tool_usage.add(spec, 1);
+            }
+            // 输入还不完整时暂不结束内容块，等待迟到的片段
+            if !self
+                .reorder
+                .defer_stop(&tool_use.tool_use_id, &call_id, block_index)
+                && let Some(stop_event) = self.state_manager.handle_content_block_stop(block_index)
+            {
+                events.push(stop_event);
+            }
+        }
+
+        events
+    }
 
+    /// 生成工具输入的 input_json_delta 事件
+    fn create_input_json_delta_events(&mut self, block_index: i32, input: &str) -> Vec<SseEvent> {
+        let mut events = Vec::new();
+        // 工具输入同样计入 max_tokens，超出时截断
+        let input = self.admit_output(input);
+        if input.is_empty() {
+            return events;
+        }
+
+        // 上游可能一次给出完整的大段输入（如 Write 工具），按配置拆分后逐段下发
+        let chunk_bytes = self.tool_input_chunk_bytes.unwrap_or(usize::MAX);
+        for chunk in split_at_char_boundaries(input, chunk_bytes) {
             if let Some(delta_event) = self.state_manager.handle_content_block_delta(
                 block_index,
                 json!({
@@ -986,27 +1699,44 @@ impl StreamContext {
                     "index": block_index,
                     "delta": {
                         "type": "input_json_delta",
-                        "partial_json": tool_use.input
+                        "partial_json": chunk
                     }
                 }),
             ) {
                 events.push(delta_event);
             }
         }
+        events
+    }
 
-        // 如果是完整的工具调用（stop=true），发送 content_block_stop
-        if tool_use.stop {
-            if let Some(stop_event) = self.state_manager.handle_content_block_stop(block_index) {
-                events.push(stop_event);
-            }
+    /// 下发合并到暂缓工具块的迟到输入片段，输入补全时结束该块
+    fn emit_late_fragment(&mut self, input: &str, late: LateFragment) -> Vec<SseEvent> {
+        let mut events = self.create_input_json_delta_events(late.block_index, input);
+        if late.complete
+            && let Some(stop_event) = self
+                .state_manager
+                .handle_content_block_stop(late.block_index)
+        {
+            events.push(stop_event);
         }
-
         events
     }
 
+    /// 结束暂缓的工具块
+    fn close_deferred_blocks(&mut self, indices: Vec<i32>) -> Vec<SseEvent> {
+        indices
+            .into_iter()
+            .filter_map(|index| self.state_manager.handle_content_block_stop(index))
+            .collect()
+    }
+
     /// 生成最终事件序列
     pub fn generate_final_events(&mut self) -> Vec<SseEvent> {
-        let mut events = Vec::new();
+        #[cfg(not(edge))]
+        if let Some(tracker) = self.conversation.take() {
+            tracker.finish();
+        }
+        let mut events = self.flush_echo_filter();
 
         // Flush thinking_buffer 中的剩余内容
         if self.thinking_enabled && !self.thinking_buffer.is_empty() {
@@ -1016,12 +1746,10 @@ impl StreamContext {
                     find_real_thinking_end_tag_at_buffer_end(&self.thinking_buffer)
                 {
                     let thinking_content = self.thinking_buffer[..end_pos].to_string();
-                    if !thinking_content.is_empty() {
-                        if let Some(thinking_index) = self.thinking_block_index {
-                            events.push(
-                                self.create_thinking_delta_event(thinking_index, &thinking_content),
-                            );
-                        }
+                    if !thinking_content.is_empty()
+                        && let Some(thinking_index) = self.thinking_block_index
+                    {
+                        events.extend(self.emit_thinking_delta(thinking_index, &thinking_content));
                     }
 
                     // 关闭 thinking 块
@@ -1041,9 +1769,8 @@ impl StreamContext {
                 } else {
                     // 如果还在 thinking 块内，发送剩余内容作为 thinking_delta
                     if let Some(thinking_index) = self.thinking_block_index {
-                        events.push(
-                            self.create_thinking_delta_event(thinking_index, &self.thinking_buffer),
-                        );
+                        let thinking_content = self.thinking_buffer.clone();
+                        events.extend(self.emit_thinking_delta(thinking_index, &thinking_content));
                     }
                     // 关闭 thinking 块
                     if let Some(thinking_index) = self.thinking_block_index {
@@ -1058,64 +1785,263 @@ impl StreamContext {
             self.thinking_buffer.clear();
         }
 
+        // 输出在 stop sequence 处截断；未命中时下发暂存的文本
+        events.extend(self.flush_stop_sequences());
+        if self.state_manager.stop_reason.is_none()
+            && !self.state_manager.has_tool_use
+            && let Some(sequence) = self
+                .stop_sequences
+                .as_ref()
+                .and_then(StopSequenceMatcher::matched)
+        {
+            let sequence = sequence.to_string();
+            self.state_manager.set_stop_sequence(sequence);
+        }
+
+        // 追加代码引用说明
+        if let Some(text) = self
+            .references
+            .as_ref()
+            .and_then(ReferenceCollector::render)
+        {
+            events.extend(self.emit_text_delta(&text));
+        }
+
+        // 拒绝回复：没有下发过文本时补发固定的说明文本
+        let refused = self.refusal.as_ref().is_some_and(RefusalDetector::refused);
+        if refused && !self.state_manager.deadline_exceeded {
+            self.state_manager.set_stop_reason("refusal");
+            if let Some(text) = self
+                .refusal
+                .as_ref()
+                .and_then(RefusalDetector::fallback_text)
+            {
+                events.extend(self.emit_text_delta(text));
+            }
+        }
+
         // 如果整个流中只产生了 thinking 块，没有 text 也没有 tool_use，
         // 则设置 stop_reason 为 max_tokens（表示模型耗尽了 token 预算在思考上），
         // 并补发一套完整的 text 事件（内容为一个空格），确保 content 数组中有 text 块
-        if self.thinking_enabled
+        // 严格模式下不做此补偿
+        if self.profile == SseProfile::Quirks
+            && !refused
+            && self.thinking_enabled
             && self.thinking_block_index.is_some()
             && !self.state_manager.has_non_thinking_blocks()
         {
-            self.state_manager.set_stop_reason("max_tokens");
-            events.extend(self.create_text_delta_events(" "));
+            // 输出在开头就命中 stop sequence 时保留 stop_sequence
+            if !self.stop_sequence_hit() {
+                self.state_manager.set_stop_reason("max_tokens");
+            }
+            events.extend(self.emit_text_delta(" "));
         }
 
         // 使用从 contextUsageEvent 计算的 input_tokens，如果没有则使用估算值
         let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
+        let output_tokens = self.output.tokens();
+        #[cfg(not(edge))]
+        let estimated_cost = self.request_usage.take().and_then(|usage| {
+            usage.record(
+                final_input_tokens,
+                output_tokens,
+                self.server_tool_usage.count(&WEB_SEARCH),
+            )
+        });
+        #[cfg(edge)]
+        let estimated_cost = None;
 
         // 生成最终事件
-        events.extend(
-            self.state_manager
-                .generate_final_events(final_input_tokens, self.output_tokens),
-        );
+        events.extend(self.state_manager.generate_final_events(
+            final_input_tokens,
+            output_tokens,
+            &self.server_tool_usage,
+            estimated_cost,
+        ));
         events
     }
+
+    /// 上游响应流中途失败时的事件序列
+    ///
+    /// 按 Anthropic 流式规范只发送一个 `error` 事件并结束流，不补发 `message_delta`/`message_stop`，
+    /// 客户端据此得知响应不完整；错误类型与请求前失败时的状态码对应，已产生的用量照常记录
+    pub fn generate_error_events(&mut self, error: &ApiError) -> Vec<SseEvent> {
+        self.failed = true;
+        #[cfg(not(edge))]
+        {
+            // 响应不完整，不核对会话回显
+            self.conversation = None;
+            let final_input_tokens = self.context_input_tokens.unwrap_or(self.input_tokens);
+            if let Some(usage) = self.request_usage.take() {
+                usage.record(
+                    final_input_tokens,
+                    self.output.tokens(),
+                    self.server_tool_usage.count(&WEB_SEARCH),
+                );
+            }
+        }
+        self.state_manager.message_delta_sent = true;
+        self.state_manager.message_ended = true;
+        vec![SseEvent::new(
+            "error",
+            json!({
+                "type": "error",
+                "error": {
+                    "type": error.error_type(),
+                    "message": error.to_string()
+                }
+            }),
+        )]
+    }
 }
 
-/// 简单的 token 估算
-fn estimate_tokens(text: &str) -> i32 {
-    let chars: Vec<char> = text.chars().collect();
-    let mut chinese_count = 0;
-    let mut other_count = 0;
+/// 快速估算文本的 tokens（逐段累计用，不调用分词器）
+pub fn estimate_tokens(text: &str) -> i32 {
+    token::estimate_tokens(text) as i32
+}
 
-    for c in &chars {
-        if *c >= '\u{4E00}' && *c <= '\u{9FFF}' {
-            chinese_count += 1;
-        } else {
-            other_count += 1;
+/// 按 max_tokens 截断一段文本输出（按 BPE 精确计数）
+///
+/// `used` 为此前已输出的 tokens（文本含 thinking，以及工具输入）。
+/// 返回保留的部分、其 tokens 以及是否达到上限
+pub fn limit_output(content: &str, used: i32, limit: i32) -> (&str, i32, bool) {
+    let tokens = token::count_tokens(content) as i32;
+    if used + tokens <= limit {
+        return (content, tokens, false);
+    }
+    let kept = token::truncate_to_tokens(content, (limit - used).max(0) as u64);
+    (kept, token::count_tokens(kept) as i32, true)
+}
+
+/// 暂存输出达到该字节数时批量分词，限制暂存的内存
+const OUTPUT_SETTLE_BYTES: usize = 4096;
+
+/// 模型输出的 tokens 累计与 max_tokens 检查，流式与非流式响应共用
+///
+/// 每段输出只做快速估算（见 [`estimate_tokens`]）并暂存，攒够一批或需要读取用量时再按 BPE 计数；
+/// 估算的累计值越过上限时才对已暂存的输出与当前这段精确计数并截断，
+/// 因此上限是近似的：估算偏低时输出可能略微超出 max_tokens
+#[derive(Debug, Default)]
+pub struct OutputBudget {
+    limit: Option<i32>,
+    /// 已按 BPE 计数的 tokens
+    counted: i32,
+    /// 尚未计数的输出
+    pending: String,
+    /// 尚未计数的输出的估算 tokens
+    pending_estimate: i32,
+}
+
+impl OutputBudget {
+    /// 创建累计器，`limit` 为 None 时不限制
+    pub fn new(limit: Option<i32>) -> Self {
+        Self {
+            limit: limit.map(|l| l.max(0)),
+            ..Self::default()
+        }
+    }
+
+    /// 计入一段输出，返回保留的部分以及是否达到上限
+    pub fn admit<'a>(&mut self, content: &'a str) -> (&'a str, bool) {
+        let estimate = estimate_tokens(content);
+        match self.limit {
+            Some(limit) if self.counted + self.pending_estimate + estimate > limit => {
+                self.settle();
+                let (kept, tokens, reached) = limit_output(content, self.counted, limit);
+                self.counted += tokens;
+                (kept, reached)
+            }
+            _ => {
+                self.pending.push_str(content);
+                self.pending_estimate += estimate;
+                if self.pending.len() >= OUTPUT_SETTLE_BYTES {
+                    self.settle();
+                }
+                (content, false)
+            }
         }
     }
 
-    // 中文约 1.5 字符/token，英文约 4 字符/token
-    let chinese_tokens = (chinese_count * 2 + 2) / 3;
-    let other_tokens = (other_count + 3) / 4;
+    /// 已输出的 tokens
+    pub fn tokens(&mut self) -> i32 {
+        self.settle();
+        self.counted
+    }
+
+    /// 已输出的 tokens 的近似值（不触发分词）
+    fn approx_tokens(&self) -> i32 {
+        self.counted + self.pending_estimate
+    }
 
-    (chinese_tokens + other_tokens).max(1)
+    /// 对暂存的输出按 BPE 计数
+    fn settle(&mut self) {
+        if !self.pending.is_empty() {
+            self.counted += token::count_tokens(&self.pending) as i32;
+            self.pending.clear();
+            self.pending_estimate = 0;
+        }
+    }
 }
 
-#[cfg(test)]
+#[cfg(all(test, not(edge)))]
 mod tests {
     use super::*;
 
     #[test]
     fn test_sse_event_format() {
         let event = SseEvent::new("message_start", json!({"type": "message_start"}));
-        let sse_str = event.to_sse_string();
+        let bytes = SseEncoder::default().encode(&event);
+        let sse_str = std::str::from_utf8(&bytes).unwrap();
 
         assert!(sse_str.starts_with("event: message_start\n"));
         assert!(sse_str.contains("data: "));
         assert!(sse_str.ends_with("\n\n"));
     }
 
+    #[test]
+    fn test_sse_encoder_sequential_events() {
+        let mut encoder = SseEncoder::default();
+        let small = SseEvent::new("ping", json!({"type": "ping"}));
+        let large = SseEvent::new(
+            "content_block_delta",
+            json!({"type": "content_block_delta", "delta": {"text": "x".repeat(20_000)}}),
+        );
+
+        let first = encoder.encode(&small);
+        let second = encoder.encode(&large);
+        let third = encoder.encode(&small);
+        assert_eq!(first, third);
+        assert_eq!(
+            std::str::from_utf8(&first).unwrap(),
+            "event: ping\ndata: {\"type\":\"ping\"}\n\n"
+        );
+        assert!(second.len() > 20_000);
+        assert!(second.ends_with(b"}\n\n"));
+    }
+
+    #[test]
+    fn test_text_delta_fast_encoding_matches_json() {
+        let slow = |event: &SseEvent| {
+            format!(
+                "event: {}\ndata: {}\n\n",
+                event.event,
+                serde_json::to_string(&event.data).unwrap()
+            )
+        };
+        let mut encoder = SseEncoder::default();
+        for text in ["hello", "", "引号\"与\n换行\u{1}", "\u{1F600}"] {
+            let event = SseEvent::text_delta(7, text);
+            assert!(event.as_text_delta().is_some());
+            assert_eq!(encoder.encode(&event), slow(&event).as_bytes());
+        }
+
+        // 兼容层添加了字段的事件走通用序列化
+        let mut event = SseEvent::text_delta(0, "x");
+        event.data["delta"]["extra"] = json!(true);
+        assert!(event.as_text_delta().is_none());
+        assert_eq!(encoder.encode(&event), slow(&event).as_bytes());
+    }
+
     #[test]
     fn test_sse_state_manager_message_start() {
         let mut manager = SseStateManager::new();
@@ -1150,13 +2076,51 @@ mod tests {
         assert!(event.is_none());
     }
 
+    #[test]
+    fn test_text_block_created_lazily() {
+        for thinking in [false, true] {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, thinking);
+            let initial_events = ctx.generate_initial_events();
+            assert_eq!(
+                initial_events
+                    .iter()
+                    .map(|e| e.event.as_str())
+                    .collect::<Vec<_>>(),
+                ["message_start"]
+            );
+
+            // 回复直接以 tool_use 开头：索引 0 就是 tool_use 块，与官方 API 一致
+            let mut events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "get_weather".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: "{}".to_string(),
+                stop: true,
+            });
+            events.extend(ctx.generate_final_events());
+            let starts: Vec<_> = events
+                .iter()
+                .filter(|e| e.event == "content_block_start")
+                .map(|e| {
+                    (
+                        e.data["index"].as_i64(),
+                        e.data["content_block"]["type"].clone(),
+                    )
+                })
+                .collect();
+            assert_eq!(starts, [(Some(0), json!("tool_use"))]);
+            let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+            assert_eq!(delta.data["delta"]["stop_reason"], "tool_use");
+        }
+    }
+
     #[test]
     fn test_text_delta_after_tool_use_restarts_text_block() {
         let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
 
-        let initial_events = ctx.generate_initial_events();
+        let _initial_events = ctx.generate_initial_events();
+        let text_events = ctx.process_assistant_response("preamble");
         assert!(
-            initial_events
+            text_events
                 .iter()
                 .any(|e| e.event == "content_block_start"
                     && e.data["content_block"]["type"] == "text")
@@ -1290,6 +2254,7 @@ mod tests {
 
     #[test]
     fn test_estimate_tokens() {
+        assert_eq!(estimate_tokens(""), 0);
         assert!(estimate_tokens("Hello") > 0);
         assert!(estimate_tokens("你好") > 0);
         assert!(estimate_tokens("Hello 你好") > 0);
@@ -1815,4 +2780,870 @@ mod tests {
             "stop_reason should be tool_use when tool_use is present"
         );
     }
+
+    #[test]
+    fn test_server_tool_use_block_and_usage() {
+        // 请求中声明的服务端工具应输出 server_tool_use 块，且不影响 stop_reason
+        let tools: Vec<crate::anthropic::types::Tool> =
+            serde_json::from_value(json!([{"type": "web_search_20250305", "name": "web_search"}]))
+                .unwrap();
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_server_tools(DeclaredServerTools::from_tools(Some(&tools)));
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        all_events.extend(
+            ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+                name: "web_search".to_string(),
+                tool_use_id: "tooluse_1".to_string(),
+                input: "{}".to_string(),
+                stop: true,
+            }),
+        );
+        all_events.extend(ctx.generate_final_events());
+
+        let block_start = all_events
+            .iter()
+            .find(|e| {
+                e.event == "content_block_start" && e.data["content_block"]["name"] == "web_search"
+            })
+            .expect("should have server tool block start");
+        assert_eq!(block_start.data["content_block"]["type"], "server_tool_use");
+        assert_eq!(
+            block_start.data["content_block"]["id"],
+            "srvtoolu_tooluse_1"
+        );
+
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "end_turn");
+        assert_eq!(
+            message_delta.data["usage"]["server_tool_use"]["web_search_requests"],
+            1
+        );
+    }
+
+    #[test]
+    fn test_client_tool_named_like_server_tool() {
+        // 未以服务端工具 type 声明的同名工具是客户端工具，照常以 tool_use 结束
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+            name: "web_fetch".to_string(),
+            tool_use_id: "tooluse_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+        });
+        all_events.extend(ctx.generate_final_events());
+
+        let block_start = all_events
+            .iter()
+            .find(|e| e.event == "content_block_start")
+            .expect("should have tool block start");
+        assert_eq!(block_start.data["content_block"]["type"], "tool_use");
+        assert_eq!(block_start.data["content_block"]["id"], "tooluse_1");
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "tool_use");
+        assert!(message_delta.data["usage"].get("server_tool_use").is_none());
+    }
+
+    #[test]
+    fn test_reused_tool_use_id_gets_new_block() {
+        // 上游在前一个调用结束后复用同一个 tool_use_id：应开启新块而不是并入前一个块
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+
+        let tool_use = |input: &str, stop: bool| crate::kiro::model::events::ToolUseEvent {
+            name: "read_file".to_string(),
+            tool_use_id: "tooluse_dup".to_string(),
+            input: input.to_string(),
+            stop,
+        };
+        let mut all_events = Vec::new();
+        all_events.extend(ctx.process_tool_use(&tool_use("{\"path\":", false)));
+        all_events.extend(ctx.process_tool_use(&tool_use("\"a.rs\"}", true)));
+        all_events.extend(ctx.process_tool_use(&tool_use("{\"path\":\"b.rs\"}", true)));
+        all_events.extend(ctx.generate_final_events());
+
+        let starts: Vec<_> = all_events
+            .iter()
+            .filter(|e| e.event == "content_block_start")
+            .map(|e| {
+                (
+                    e.data["index"].clone(),
+                    e.data["content_block"]["id"].clone(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            starts,
+            [
+                (json!(0), json!("tooluse_dup")),
+                (json!(1), json!("tooluse_dup_2"))
+            ]
+        );
+        let second_input: String = all_events
+            .iter()
+            .filter(|e| e.event == "content_block_delta" && e.data["index"] == 1)
+            .filter_map(|e| e.data["delta"]["partial_json"].as_str())
+            .collect();
+        assert_eq!(second_input, "{\"path\":\"b.rs\"}");
+        assert_eq!(
+            all_events
+                .iter()
+                .filter(|e| e.event == "content_block_stop")
+                .count(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_strict_profile_thinking_only_stream() {
+        // 严格模式下 thinking-only 流不补发文本块，stop_reason 保持 end_turn
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, true)
+            .with_profile(SseProfile::Strict);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        all_events.extend(ctx.process_assistant_response("<thinking>\nabc</thinking>"));
+        all_events.extend(ctx.generate_final_events());
+
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "end_turn");
+        assert!(!all_events.iter().any(|e| {
+            e.event == "content_block_start" && e.data["content_block"]["type"] == "text"
+        }));
+    }
+
+    #[test]
+    fn test_strict_profile_keeps_text_after_tool_use() {
+        // 严格模式下 tool_use 之后的文本不丢弃：先结束工具块，再开启新的文本块
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_profile(SseProfile::Strict);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_tool_use(&crate::kiro::model::events::ToolUseEvent {
+            name: "test_tool".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: "{}".to_string(),
+            stop: false,
+        });
+        all_events.extend(ctx.process_assistant_response("hello"));
+        all_events.extend(ctx.generate_final_events());
+
+        assert_eq!(collect_text_content(&all_events), "hello");
+        let blocks: Vec<(&str, i64)> = all_events
+            .iter()
+            .filter(|e| e.event.starts_with("content_block_") && e.event != "content_block_delta")
+            .map(|e| (e.event.as_str(), e.data["index"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(
+            blocks,
+            [
+                ("content_block_start", 0),
+                ("content_block_stop", 0),
+                ("content_block_start", 1),
+                ("content_block_stop", 1),
+            ]
+        );
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "tool_use");
+    }
+
+    #[test]
+    fn test_echo_filter_strips_policy_across_chunks() {
+        let policy = "Complete all chunked operations without commentary.";
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_echo_filter(EchoFilter::new([policy]));
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all = Vec::new();
+        all.extend(ctx.process_assistant_response("Done. Complete all chunked"));
+        all.extend(ctx.process_assistant_response(" operations without commentary."));
+        all.extend(ctx.process_assistant_response(" Bye"));
+        all.extend(ctx.generate_final_events());
+
+        assert_eq!(collect_text_content(&all), "Done.  Bye");
+    }
+
+    #[test]
+    fn test_usage_reporter_by_blocks() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 10, false)
+            .with_usage_reporter(UsageReporter::new(0, 1).unwrap());
+        let _initial_events = ctx.generate_initial_events();
+
+        ctx.process_assistant_response("hello world");
+        let event = ctx
+            .poll_usage_event()
+            .expect("should report after a new block");
+        assert_eq!(event.event, "kiro_usage");
+        assert_eq!(event.data["usage"]["input_tokens"], 10);
+        assert_eq!(event.data["usage"]["output_tokens"], ctx.output_tokens());
+
+        // 没有新内容块时不重复发送
+        ctx.process_assistant_response("more");
+        assert!(ctx.poll_usage_event().is_none());
+
+        assert!(UsageReporter::new(0, 0).is_none());
+    }
+
+    #[test]
+    fn test_deadline_exceeded_closes_blocks() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_assistant_response("partial answer");
+        ctx.state_manager.mark_deadline_exceeded();
+        all_events.extend(ctx.generate_final_events());
+
+        assert!(all_events.iter().any(|e| e.event == "content_block_stop"));
+        let message_delta = all_events
+            .iter()
+            .find(|e| e.event == "message_delta")
+            .expect("should have message_delta event");
+        assert_eq!(message_delta.data["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(message_delta.data["delta"]["deadline_exceeded"], true);
+    }
+
+    #[test]
+    fn test_thinking_budget_closes_block_early() {
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, true).with_thinking_budget(4);
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = Vec::new();
+        all_events.extend(ctx.process_assistant_response("<thinking>\n"));
+        all_events.extend(ctx.process_assistant_response(&"think ".repeat(40)));
+        all_events.extend(ctx.process_assistant_response(&"more ".repeat(40)));
+        all_events.extend(ctx.process_assistant_response("</thinking>\n\nanswer"));
+        all_events.extend(ctx.generate_final_events());
+
+        let thinking_index = ctx
+            .thinking_block_index
+            .expect("thinking block should exist");
+        let stop_pos = all_events
+            .iter()
+            .position(|e| e.event == "content_block_stop" && e.data["index"] == thinking_index)
+            .expect("thinking block should be closed");
+        assert_eq!(
+            all_events
+                .iter()
+                .filter(|e| e.event == "content_block_stop" && e.data["index"] == thinking_index)
+                .count(),
+            1
+        );
+        assert!(
+            all_events[stop_pos..]
+                .iter()
+                .all(|e| e.data["index"] != thinking_index || e.event == "content_block_stop"),
+            "no thinking deltas after the block is closed"
+        );
+        let suppressed: String = all_events
+            .iter()
+            .filter_map(|e| e.data["delta"]["thinking"].as_str())
+            .collect();
+        assert!(!suppressed.contains("more"));
+        assert!(
+            all_events
+                .iter()
+                .any(|e| e.data["delta"]["text"] == "answer")
+        );
+    }
+
+    #[test]
+    fn test_code_references_appended_before_message_delta() {
+        use crate::kiro::model::events::{CodeReference, CodeReferenceEvent};
+
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, false).with_code_references();
+        let _initial_events = ctx.generate_initial_events();
+
+        let mut all_events = ctx.process_assistant_response("fn main() {}");
+        all_events.extend(
+            ctx.process_kiro_event(&Event::CodeReference(CodeReferenceEvent {
+                references: vec![CodeReference {
+                    license_name: Some("MIT".to_string()),
+                    repository: Some("foo/bar".to_string()),
+                    ..CodeReference::default()
+                }],
+            })),
+        );
+        all_events.extend(ctx.generate_final_events());
+
+        let reference_pos = all_events
+            .iter()
+            .position(|e| {
+                e.data["delta"]["text"]
+                    .as_str()
+                    .is_some_and(|t| t.contains("- foo/bar (MIT)"))
+            })
+            .expect("should append code references");
+        let delta_pos = all_events
+            .iter()
+            .position(|e| e.event == "message_delta")
+            .unwrap();
+        assert!(reference_pos < delta_pos);
+    }
+    #[test]
+    fn test_stop_sequence_cuts_output_across_chunks() {
+        let sequences = vec!["</answer>".to_string(), "STOP".to_string()];
+        let run = |chunks: &[&str]| {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+                .with_stop_sequences(&sequences);
+            let _initial_events = ctx.generate_initial_events();
+            let mut events = Vec::new();
+            for chunk in chunks {
+                events.extend(ctx.process_assistant_response(chunk));
+            }
+            let hit = ctx.stop_sequence_hit();
+            events.extend(ctx.generate_final_events());
+            let text: String = events
+                .iter()
+                .filter_map(|e| e.data["delta"]["text"].as_str())
+                .collect();
+            let delta = events
+                .into_iter()
+                .find(|e| e.event == "message_delta")
+                .expect("message_delta should be emitted");
+            (hit, text, delta)
+        };
+
+        let (hit, text, delta) = run(&["42</an", "swer", ">\nignored"]);
+        assert!(hit);
+        assert_eq!(text, "42");
+        assert_eq!(delta.data["delta"]["stop_reason"], "stop_sequence");
+        assert_eq!(delta.data["delta"]["stop_sequence"], "</answer>");
+
+        // 暂存的部分匹配在确认不构成序列后照常下发
+        let (hit, text, delta) = run(&["ST", "OP", " more"]);
+        assert!(hit);
+        assert_eq!(text, "");
+        assert_eq!(delta.data["delta"]["stop_sequence"], "STOP");
+
+        let (hit, text, delta) = run(&["a </an", "d> S"]);
+        assert!(!hit);
+        assert_eq!(text, "a </and> S");
+        assert_eq!(delta.data["delta"]["stop_reason"], "end_turn");
+        assert!(delta.data["delta"]["stop_sequence"].is_null());
+    }
+
+    #[test]
+    fn test_stop_sequence_drops_later_tool_use() {
+        let sequences = vec!["STOP".to_string()];
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_stop_sequences(&sequences);
+        let _initial_events = ctx.generate_initial_events();
+        let mut events = ctx.process_assistant_response("done STOP");
+        events.extend(ctx.process_kiro_event(&Event::ToolUse(ToolUseEvent {
+            name: "read_file".to_string(),
+            tool_use_id: "tooluse_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+        })));
+        events.extend(ctx.generate_final_events());
+
+        assert!(
+            !events
+                .iter()
+                .any(|e| e.data["content_block"]["type"] == "tool_use")
+        );
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "stop_sequence");
+    }
+
+    #[test]
+    fn test_limit_output() {
+        assert_eq!(limit_output("hello", 0, 10), ("hello", 1, false));
+        let (kept, tokens, reached) = limit_output("one two three four", 8, 10);
+        assert!(reached);
+        assert_eq!((kept, tokens), ("one two", 2));
+        // 此前的输出（如工具输入）已超出上限时不再保留任何文本
+        assert_eq!(limit_output("more", 12, 10), ("", 0, true));
+    }
+
+    #[test]
+    fn test_max_tokens_truncates_output() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(5);
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_assistant_response("one two three"));
+        assert!(!ctx.output_stopped());
+        events.extend(ctx.process_assistant_response(" four five six seven"));
+        assert!(ctx.output_stopped());
+        events.extend(ctx.process_kiro_event(&Event::ToolUse(ToolUseEvent {
+            name: "read_file".to_string(),
+            tool_use_id: "tooluse_1".to_string(),
+            input: "{}".to_string(),
+            stop: true,
+        })));
+        events.extend(ctx.generate_final_events());
+
+        let text: String = events
+            .iter()
+            .filter_map(|e| e.data["delta"]["text"].as_str())
+            .collect();
+        assert_eq!(text, "one two three four five");
+        assert!(
+            !events
+                .iter()
+                .any(|e| e.data["content_block"]["type"] == "tool_use")
+        );
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(delta.data["usage"]["output_tokens"], 5);
+    }
+
+    #[test]
+    fn test_max_tokens_truncates_tool_input() {
+        let tool_use = |id: &str, input: &str| {
+            Event::ToolUse(ToolUseEvent {
+                name: "write_file".to_string(),
+                tool_use_id: id.to_string(),
+                input: input.to_string(),
+                stop: true,
+            })
+        };
+        let input =
+            r#"{"path": "notes.txt", "content": "one two three four five six seven eight"}"#;
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(8);
+        let mut events = ctx.generate_initial_events();
+        events.extend(ctx.process_kiro_event(&tool_use("tooluse_1", input)));
+        assert!(ctx.output_stopped());
+        events.extend(ctx.process_kiro_event(&tool_use("tooluse_2", "{}")));
+        events.extend(ctx.generate_final_events());
+
+        let partial: String = events
+            .iter()
+            .filter_map(|e| e.data["delta"]["partial_json"].as_str())
+            .collect();
+        assert!(input.starts_with(&partial) && partial.len() < input.len());
+        assert_eq!(token::count_tokens(&partial), 8);
+        let starts = events
+            .iter()
+            .filter(|e| e.data["content_block"]["type"] == "tool_use")
+            .count();
+        assert_eq!(starts, 1);
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "max_tokens");
+        assert_eq!(delta.data["usage"]["output_tokens"], 8);
+    }
+
+    #[test]
+    fn test_output_budget_counts_in_batches() {
+        let deltas = ["Hello", ", wor", "ld! ", "fn main() {}", " 你好"];
+        let mut budget = OutputBudget::new(Some(1000));
+        for delta in deltas {
+            assert_eq!(budget.admit(delta), (delta, false));
+        }
+        // 未越过上限时只做估算，读取用量时对整段输出计数一次
+        assert_eq!(budget.counted, 0);
+        assert_eq!(
+            budget.tokens(),
+            token::count_tokens(&deltas.concat()) as i32
+        );
+
+        // 估算越过上限时才精确计数并截断
+        let mut budget = OutputBudget::new(Some(4));
+        assert_eq!(budget.admit("one two"), ("one two", false));
+        let (kept, reached) = budget.admit(" three four five");
+        assert!(reached);
+        assert!(" three four five".starts_with(kept) && !kept.is_empty());
+        assert_eq!(budget.tokens(), 2 + token::count_tokens(kept) as i32);
+        assert!(budget.tokens() <= 4);
+    }
+
+    #[test]
+    fn test_late_tool_fragments() {
+        let tool_use = |id: &str, input: &str, stop: bool| {
+            Event::ToolUse(ToolUseEvent {
+                name: "write_file".to_string(),
+                tool_use_id: id.to_string(),
+                input: input.to_string(),
+                stop,
+            })
+        };
+        let context_usage = Event::ContextUsage(crate::kiro::model::events::ContextUsageEvent {
+            context_usage_percentage: 5.0,
+        });
+        let run = |window: u32, upstream: &[Event]| {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+                .with_reorder_window(window);
+            let mut events = ctx.generate_initial_events();
+            for event in upstream {
+                events.extend(ctx.process_kiro_event(event));
+            }
+            events.extend(ctx.generate_final_events());
+            events
+        };
+        let inputs = |events: &[SseEvent]| -> Vec<(i64, String)> {
+            let mut inputs: Vec<(i64, String)> = Vec::new();
+            for e in events {
+                let index = e.data["index"].as_i64().unwrap_or(-1);
+                if e.data["content_block"]["type"] == "tool_use" {
+                    inputs.push((index, String::new()));
+                }
+                if let Some(partial) = e.data["delta"]["partial_json"].as_str() {
+                    inputs.last_mut().unwrap().1.push_str(partial);
+                }
+            }
+            inputs
+        };
+
+        // stop 之后迟到的片段并入原调用，content_block_stop 在片段之后
+        let upstream = [
+            tool_use("a", r#"{"path":"notes.txt","#, false),
+            tool_use("a", r#""content":"hi""#, true),
+            context_usage.clone(),
+            tool_use("a", "}", false),
+        ];
+        let events = run(4, &upstream);
+        assert_eq!(
+            inputs(&events),
+            vec![(0, r#"{"path":"notes.txt","content":"hi"}"#.to_string())]
+        );
+        let stops: Vec<usize> = events
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| e.event == "content_block_stop")
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(stops.len(), 1);
+        assert!(events[stops[0] - 1].data["delta"]["partial_json"] == "}");
+
+        // 不等待时迟到的片段被当作复用 ID 的新调用
+        assert_eq!(inputs(&run(0, &upstream)).len(), 2);
+
+        // 窗口用完后结束暂缓的块，之后的片段不再合并
+        let events = run(1, &upstream);
+        assert_eq!(inputs(&events).len(), 2);
+
+        // 其他内容开始前结束暂缓的块
+        let events = run(
+            4,
+            &[
+                tool_use("a", r#"{"path":"#, true),
+                tool_use("b", "{}", true),
+            ],
+        );
+        let order: Vec<(&str, i64)> = events
+            .iter()
+            .filter(|e| e.event.starts_with("content_block_st"))
+            .map(|e| (e.event.as_str(), e.data["index"].as_i64().unwrap()))
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                ("content_block_start", 0),
+                ("content_block_stop", 0),
+                ("content_block_start", 1),
+                ("content_block_stop", 1),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_replayed_anomalies() {
+        use crate::kiro::fixture::FixtureScript;
+        use crate::kiro::parser::decoder::EventStreamDecoder;
+
+        // 逐帧回放乱序样本，按 handlers 的方式在输出停止（且不再等待）时断开上游
+        let replay = |seed: &str, ctx: &mut StreamContext| {
+            let path = format!("{}/fuzz/seeds/{}", env!("CARGO_MANIFEST_DIR"), seed);
+            let script = FixtureScript::parse(&std::fs::read_to_string(path).unwrap()).unwrap();
+            let mut decoder = EventStreamDecoder::new();
+            decoder.feed(&script.encode()).unwrap();
+            let mut events = ctx.generate_initial_events();
+            for frame in decoder.decode_iter() {
+                events.extend(ctx.process_kiro_event(&Event::from_frame(frame.unwrap()).unwrap()));
+                if ctx.failed() || (ctx.output_stopped() && !ctx.awaits_context_usage()) {
+                    break;
+                }
+            }
+            events.extend(ctx.generate_final_events());
+            events
+        };
+        let usage = |events: &[SseEvent]| {
+            let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+            (
+                delta.data["delta"]["stop_reason"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                delta.data["usage"]["input_tokens"].as_i64().unwrap(),
+            )
+        };
+
+        // 达到 max_tokens 后到达的 contextUsage 在窗口内计入最终用量
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_max_tokens(4)
+            .with_reorder_window(4);
+        let events = replay("late_context_usage.yaml", &mut ctx);
+        assert_eq!(usage(&events), ("max_tokens".to_string(), 25000));
+
+        // 不等待时立即断开上游，只能报告估算值
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false).with_max_tokens(4);
+        let events = replay("late_context_usage.yaml", &mut ctx);
+        assert_eq!(usage(&events), ("max_tokens".to_string(), 1));
+
+        // 窗口用完仍未收到时不再等待
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_max_tokens(4)
+            .with_reorder_window(1);
+        let events = replay("late_context_usage.yaml", &mut ctx);
+        assert_eq!(usage(&events), ("max_tokens".to_string(), 1));
+
+        // stop 之后迟到的工具输入片段并入原调用
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, false).with_reorder_window(4);
+        let events = replay("late_tool_fragment.yaml", &mut ctx);
+        let input: String = events
+            .iter()
+            .filter_map(|e| e.data["delta"]["partial_json"].as_str())
+            .collect();
+        assert_eq!(input, r#"{"path":"notes.txt","content":"hello"}"#);
+        assert_eq!(usage(&events), ("tool_use".to_string(), 16000));
+    }
+
+    #[test]
+    fn test_refusal_detection() {
+        use crate::model::config::RefusalConfig;
+
+        let classifier: &'static RefusalClassifier = Box::leak(Box::new(
+            RefusalClassifier::new(&RefusalConfig {
+                exceptions: vec!["GuardrailInterventionException".to_string()],
+                patterns: vec!["^I can't help".to_string()],
+                message: "I can't help with that.".to_string(),
+            })
+            .unwrap(),
+        ));
+        let run = |thinking: bool, chunks: &[&str], exception: bool| {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, thinking)
+                .with_refusal_detection(classifier);
+            let mut events = ctx.generate_initial_events();
+            for chunk in chunks {
+                events.extend(ctx.process_assistant_response(chunk));
+            }
+            if exception {
+                events.extend(ctx.process_kiro_event(&Event::Exception {
+                    exception_type: "GuardrailInterventionException".to_string(),
+                    message: "blocked".to_string(),
+                }));
+            }
+            events.extend(ctx.generate_final_events());
+            let text: String = events
+                .iter()
+                .filter_map(|e| e.data["delta"]["text"].as_str())
+                .collect();
+            let delta = events
+                .into_iter()
+                .find(|e| e.event == "message_delta")
+                .unwrap();
+            (text, delta.data["delta"]["stop_reason"].clone())
+        };
+
+        // 异常：没有文本输出时补发固定文本
+        let (text, reason) = run(true, &[], true);
+        assert_eq!(text, "I can't help with that.");
+        assert_eq!(reason, "refusal");
+
+        // 文本：按开头匹配，已有文本时不补发
+        let (text, reason) = run(false, &["I can't", " help with this."], false);
+        assert_eq!(text, "I can't help with this.");
+        assert_eq!(reason, "refusal");
+
+        let (_, reason) = run(false, &["Sure, I can't wait."], false);
+        assert_eq!(reason, "end_turn");
+    }
+
+    #[test]
+    fn test_diff_preview_parallel_to_input_json_delta() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+            .with_diff_preview(DiffPreviewer::new(&["Edit".to_string()]));
+        let mut events = ctx.generate_initial_events();
+        for (input, stop) in [
+            (r#"{"file_path":"a.rs","old_string":"x\n"#, false),
+            (r#"y","new_string":"z"}"#, false),
+            ("", true),
+        ] {
+            events.extend(ctx.process_tool_use(&ToolUseEvent {
+                name: "Edit".to_string(),
+                tool_use_id: "tool_1".to_string(),
+                input: input.to_string(),
+                stop,
+            }));
+        }
+
+        let names: Vec<&str> = events.iter().map(|e| e.event.as_str()).collect();
+        let first_diff = names.iter().position(|&n| n == "kiro_diff").unwrap();
+        assert_eq!(names[first_diff - 1], "content_block_delta");
+        let diff: String = events
+            .iter()
+            .filter(|e| e.event == "kiro_diff")
+            .map(|e| e.data["diff"].as_str().unwrap())
+            .collect();
+        assert_eq!(diff, "--- a/a.rs\n+++ b/a.rs\n@@ @@\n-x\n-y\n+z\n");
+        // 结束事件在 content_block_stop 之前
+        let done = names.iter().rposition(|&n| n == "kiro_diff").unwrap();
+        assert_eq!(names[done + 1], "content_block_stop");
+    }
+
+    #[test]
+    fn test_large_tool_input_split_into_deltas() {
+        let mut ctx =
+            StreamContext::new_with_thinking("test-model", 1, false).with_tool_input_chunking(8);
+        let _initial_events = ctx.generate_initial_events();
+        let input = r#"{"content":"写入文件"}"#;
+        let events = ctx.process_tool_use(&ToolUseEvent {
+            name: "Write".to_string(),
+            tool_use_id: "tool_1".to_string(),
+            input: input.to_string(),
+            stop: true,
+        });
+
+        let chunks: Vec<&str> = events
+            .iter()
+            .filter(|e| e.event == "content_block_delta")
+            .map(|e| e.data["delta"]["partial_json"].as_str().unwrap())
+            .collect();
+        assert!(chunks.len() > 1);
+        assert!(chunks.iter().all(|c| c.len() <= 8));
+        assert_eq!(chunks.concat(), input);
+
+        let pieces: Vec<&str> = split_at_char_boundaries("文字", 1).collect();
+        assert_eq!(pieces, ["文", "字"]);
+    }
+
+    #[test]
+    fn test_tool_input_validation_modes() {
+        let tools: Vec<crate::anthropic::types::Tool> = serde_json::from_value(json!([{
+            "name": "read",
+            "description": "",
+            "input_schema": {
+                "type": "object",
+                "properties": {"path": {"type": "string"}},
+                "required": ["path"]
+            }
+        }]))
+        .unwrap();
+        let run = |mode: ToolInputValidation| {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, false)
+                .with_tool_validator(ToolInputValidator::new(mode, Some(&tools)).unwrap());
+            let mut events = ctx.generate_initial_events();
+            for (input, stop) in [(r#"{"path""#, false), (": 1}", true)] {
+                events.extend(ctx.process_tool_use(&ToolUseEvent {
+                    name: "read".to_string(),
+                    tool_use_id: "tool_1".to_string(),
+                    input: input.to_string(),
+                    stop,
+                }));
+            }
+            events.extend(ctx.generate_final_events());
+            events
+        };
+
+        // 附加警告：照常下发，tool_use 块结束事件带 kiro_warning
+        let events = run(ToolInputValidation::Annotate);
+        let stop = events
+            .iter()
+            .find(|e| e.event == "content_block_stop" && e.data["index"] == 0)
+            .unwrap();
+        assert_eq!(
+            stop.data["kiro_warning"]["type"],
+            "tool_input_schema_mismatch"
+        );
+
+        // 拦截：不下发 tool_use，改为说明文本，stop_reason 不再是 tool_use
+        let events = run(ToolInputValidation::Correct);
+        assert!(
+            !events
+                .iter()
+                .any(|e| e.data["content_block"]["type"] == "tool_use")
+        );
+        assert!(events.iter().any(|e| {
+            e.data["delta"]["text"]
+                .as_str()
+                .is_some_and(|t| t.contains("read"))
+        }));
+        let delta = events.iter().find(|e| e.event == "message_delta").unwrap();
+        assert_eq!(delta.data["delta"]["stop_reason"], "end_turn");
+    }
+
+    #[test]
+    fn test_mid_stream_failure_emits_error_event() {
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+        let _ = ctx.process_assistant_response("partial answer");
+
+        let events = ctx.generate_error_events(&ApiError::Upstream(
+            "Upstream response stream was interrupted".to_string(),
+        ));
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event, "error");
+        assert_eq!(events[0].data["type"], "error");
+        assert_eq!(events[0].data["error"]["type"], "api_error");
+        // 不再补发 message_delta / message_stop
+        assert!(
+            !ctx.generate_final_events()
+                .iter()
+                .any(|e| { e.event == "message_delta" || e.event == "message_stop" })
+        );
+    }
+
+    #[test]
+    fn test_upstream_error_events_end_stream() {
+        let exception = |exception_type: &str| Event::Exception {
+            exception_type: exception_type.to_string(),
+            message: "boom".to_string(),
+        };
+        let cases = [
+            (exception("ThrottlingException"), "rate_limit_error"),
+            (exception("ExpiredTokenException"), "authentication_error"),
+            (exception("ValidationException"), "invalid_request_error"),
+            (exception("ModelStreamErrorException"), "overloaded_error"),
+            (
+                Event::Error {
+                    error_code: "InternalError".to_string(),
+                    error_message: "boom".to_string(),
+                },
+                "api_error",
+            ),
+        ];
+        for (event, expected) in cases {
+            let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+            let _initial_events = ctx.generate_initial_events();
+            let _ = ctx.process_assistant_response("partial answer");
+
+            let events = ctx.process_kiro_event(&event);
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].event, "error");
+            assert_eq!(events[0].data["error"]["type"], expected);
+            assert!(ctx.failed());
+            // 之后的内容被丢弃
+            let more = serde_json::from_value(json!({"content": "more"})).unwrap();
+            assert!(
+                ctx.process_kiro_event(&Event::AssistantResponse(more))
+                    .is_empty()
+            );
+        }
+
+        // 输出截断不是错误
+        let mut ctx = StreamContext::new_with_thinking("test-model", 1, false);
+        let _initial_events = ctx.generate_initial_events();
+        assert!(
+            ctx.process_kiro_event(&exception("ContentLengthExceededException"))
+                .is_empty()
+        );
+        assert!(!ctx.failed());
+    }
 }
@@ -0,0 +1,105 @@
+//! 系统提示词模板变量
+//!
+//! 配置中的 `converter.systemPrepend` / `converter.systemAppend` 支持 `{{name}}` 形式的变量，
+//! 在每次请求转换时展开，便于注入日期、模型等动态上下文。未知变量原样保留
+
+use chrono::{DateTime, Utc};
+
+/// 模板变量取值
+pub struct TemplateVars<'a> {
+    /// 当前时间
+    pub now: DateTime<Utc>,
+    /// 请求中的模型名
+    pub model: &'a str,
+    /// 映射后的 Kiro 模型 ID
+    pub kiro_model: &'a str,
+    /// 发送给 Kiro 的 conversationId
+    pub conversation_id: &'a str,
+    /// 客户端会话 ID（来自 metadata.user_id）
+    pub session_id: Option<&'a str>,
+}
+
+impl TemplateVars<'_> {
+    /// 查找变量值
+    fn lookup(&self, name: &str) -> Option<String> {
+        let value = match name {
+            "date" => self.now.format("%Y-%m-%d").to_string(),
+            "time" => self.now.format("%H:%M:%S").to_string(),
+            "datetime" => self.now.format("%Y-%m-%dT%H:%M:%SZ").to_string(),
+            "weekday" => self.now.format("%A").to_string(),
+            "model" => self.model.to_string(),
+            "kiro_model" => self.kiro_model.to_string(),
+            "conversation_id" => self.conversation_id.to_string(),
+            "session_id" => self.session_id.unwrap_or_default().to_string(),
+            _ => return None,
+        };
+        Some(value)
+    }
+}
+
+/// 展开模板中的 `{{name}}` 变量（变量名两侧允许空白）
+pub fn render(template: &str, vars: &TemplateVars) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            out.push_str(&rest[start..]);
+            return out;
+        };
+
+        let name = after[..end].trim();
+        match vars.lookup(name) {
+            Some(value) => out.push_str(&value),
+            None => {
+                tracing::debug!("未知的系统提示词模板变量: {}", name);
+                out.push_str(&rest[start..start + 2 + end + 2]);
+            }
+        }
+        rest = &after[end + 2..];
+    }
+
+    out.push_str(rest);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn vars() -> TemplateVars<'static> {
+        TemplateVars {
+            now: Utc.with_ymd_and_hms(2026, 3, 14, 9, 26, 53).unwrap(),
+            model: "claude-sonnet-4-5",
+            kiro_model: "claude-sonnet-4.5",
+            conversation_id: "conv-1",
+            session_id: None,
+        }
+    }
+
+    #[test]
+    fn test_render_known_vars() {
+        assert_eq!(
+            render(
+                "Today is {{date}} ({{ weekday }}), model {{model}} -> {{kiro_model}}",
+                &vars()
+            ),
+            "Today is 2026-03-14 (Saturday), model claude-sonnet-4-5 -> claude-sonnet-4.5"
+        );
+        assert_eq!(
+            render("{{datetime}} {{conversation_id}}[{{session_id}}]", &vars()),
+            "2026-03-14T09:26:53Z conv-1[]"
+        );
+    }
+
+    #[test]
+    fn test_render_keeps_unknown_and_unclosed() {
+        assert_eq!(
+            render("{{unknown}} {{date}} {{date", &vars()),
+            "{{unknown}} 2026-03-14 {{date"
+        );
+    }
+}
@@ -0,0 +1,128 @@
+//! tool_choice 转换
+//!
+//! Kiro API 没有对应的字段，按 Anthropic 语义近似实现：
+//! - `auto`：默认行为，不做处理
+//! - `any`：在当前消息末尾追加指令，要求至少调用一个工具
+//! - `tool`：追加指令，要求调用指定工具（名称必须出现在 `tools` 中）
+//! - `none`：不向上游发送请求中的工具定义；历史中引用的工具仍需以占位定义发送，此时追加不调用工具的指令
+//!
+//! `disable_parallel_tool_use` 无法在上游实现，忽略
+
+use serde_json::Value;
+
+use super::types::Tool;
+
+/// 解析后的 tool_choice
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ToolChoice {
+    Auto,
+    Any,
+    Tool(String),
+    None,
+}
+
+impl ToolChoice {
+    /// 解析请求中的 tool_choice，未指定时为 `auto`
+    pub fn parse(value: Option<&Value>, tools: Option<&[Tool]>) -> Result<Self, String> {
+        let Some(value) = value.filter(|v| !v.is_null()) else {
+            return Ok(ToolChoice::Auto);
+        };
+        let tools = tools.unwrap_or_default();
+        let choice_type = value["type"]
+            .as_str()
+            .ok_or_else(|| format!("tool_choice 必须是包含 type 字段的对象: {}", value))?;
+
+        match choice_type {
+            "auto" => Ok(ToolChoice::Auto),
+            "none" => Ok(ToolChoice::None),
+            "any" if tools.is_empty() => Err("tool_choice 为 any 时必须提供 tools".to_string()),
+            "any" => Ok(ToolChoice::Any),
+            "tool" => {
+                let name = value["name"]
+                    .as_str()
+                    .filter(|name| !name.is_empty())
+                    .ok_or_else(|| "tool_choice 为 tool 时必须指定 name".to_string())?;
+                if !tools.iter().any(|tool| tool.name == name) {
+                    return Err(format!("tool_choice 指定的工具不在 tools 中: {}", name));
+                }
+                Ok(ToolChoice::Tool(name.to_string()))
+            }
+            other => Err(format!("不支持的 tool_choice 类型: {}", other)),
+        }
+    }
+
+    /// 是否移除请求中的工具定义
+    pub fn strips_tools(&self) -> bool {
+        matches!(self, ToolChoice::None)
+    }
+
+    /// 追加到当前消息末尾的指令（`has_tools` 为最终发送给上游的工具列表是否非空）
+    pub fn directive(&self, has_tools: bool) -> Option<String> {
+        match self {
+            ToolChoice::Auto => None,
+            ToolChoice::Any => {
+                Some("You must respond by calling at least one of the available tools.".to_string())
+            }
+            ToolChoice::Tool(name) => {
+                Some(format!("You must respond by calling the `{}` tool.", name))
+            }
+            ToolChoice::None if has_tools => {
+                Some("Do not call any tools in this response; reply with text only.".to_string())
+            }
+            ToolChoice::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn tool(name: &str) -> Tool {
+        serde_json::from_value(json!({
+            "name": name,
+            "description": "test",
+            "input_schema": { "type": "object" }
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_parse_tool_choice() {
+        let tools = [tool("get_weather")];
+        let parse = |value: Value| ToolChoice::parse(Some(&value), Some(&tools));
+
+        assert_eq!(ToolChoice::parse(None, None), Ok(ToolChoice::Auto));
+        assert_eq!(parse(json!({ "type": "auto" })), Ok(ToolChoice::Auto));
+        assert_eq!(parse(json!({ "type": "none" })), Ok(ToolChoice::None));
+        assert_eq!(
+            parse(json!({ "type": "any", "disable_parallel_tool_use": true })),
+            Ok(ToolChoice::Any)
+        );
+        assert_eq!(
+            parse(json!({ "type": "tool", "name": "get_weather" })),
+            Ok(ToolChoice::Tool("get_weather".to_string()))
+        );
+
+        assert!(parse(json!({ "type": "tool", "name": "get_time" })).is_err());
+        assert!(parse(json!({ "type": "tool" })).is_err());
+        assert!(parse(json!({ "type": "required" })).is_err());
+        assert!(parse(json!("auto")).is_err());
+        assert!(ToolChoice::parse(Some(&json!({ "type": "any" })), None).is_err());
+    }
+
+    #[test]
+    fn test_directive() {
+        assert_eq!(ToolChoice::Auto.directive(true), None);
+        assert!(
+            ToolChoice::Tool("get_weather".to_string())
+                .directive(true)
+                .unwrap()
+                .contains("`get_weather`")
+        );
+        assert!(ToolChoice::None.strips_tools());
+        assert!(ToolChoice::None.directive(true).is_some());
+        assert_eq!(ToolChoice::None.directive(false), None);
+    }
+}
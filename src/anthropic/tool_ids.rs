@@ -0,0 +1,82 @@
+//! 上游 tool_use_id 去重
+//!
+//! Kiro 偶尔在同一个响应中为不同的工具调用复用同一个 tool_use_id。按 ID 累积输入时，
+//! 后一个调用会并入前一个调用的内容块（流式）或拼接到前一个调用的输入之后（非流式）。
+//! 某个 ID 的调用已经结束（`stop=true`）后再次出现时视为新的调用，分配带序号后缀的新 ID
+//! （`<id>_2`、`<id>_3`……），并计入 `duplicateToolIds` 指标
+
+use std::collections::{HashMap, HashSet};
+
+#[cfg(not(edge))]
+use crate::common::metrics;
+
+/// 单个响应内的工具调用 ID 分配
+#[derive(Debug, Default)]
+pub struct ToolUseIds {
+    /// 进行中的调用：上游 ID -> 下发的 ID
+    active: HashMap<String, String>,
+    /// 已下发的 ID
+    issued: HashSet<String>,
+}
+
+impl ToolUseIds {
+    /// 返回工具调用事件所属调用下发的 ID；`stop` 为 true 时该调用结束
+    pub fn observe(&mut self, upstream_id: &str, stop: bool) -> String {
+        if let Some(id) = self.active.get(upstream_id) {
+            let id = id.clone();
+            if stop {
+                self.active.remove(upstream_id);
+            }
+            return id;
+        }
+
+        let id = if self.issued.contains(upstream_id) {
+            let id = (2..)
+                .map(|n| format!("{}_{}", upstream_id, n))
+                .find(|id| !self.issued.contains(id))
+                .expect("序号足够分配");
+            tracing::warn!(
+                "上游复用了已结束的 tool_use_id {}，改为 {}",
+                upstream_id,
+                id
+            );
+            #[cfg(not(edge))]
+            metrics::duplicate_tool_ids().record_reassigned();
+            id
+        } else {
+            upstream_id.to_string()
+        };
+        self.issued.insert(id.clone());
+        if !stop {
+            self.active.insert(upstream_id.to_string(), id.clone());
+        }
+        id
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reused_id_after_stop_gets_suffix() {
+        let mut ids = ToolUseIds::default();
+        assert_eq!(ids.observe("tooluse_a", false), "tooluse_a");
+        assert_eq!(ids.observe("tooluse_a", true), "tooluse_a");
+        assert_eq!(ids.observe("tooluse_a", false), "tooluse_a_2");
+        assert_eq!(ids.observe("tooluse_a", false), "tooluse_a_2");
+        assert_eq!(ids.observe("tooluse_a", true), "tooluse_a_2");
+        assert_eq!(ids.observe("tooluse_a", true), "tooluse_a_3");
+    }
+
+    #[test]
+    fn test_suffix_avoids_real_ids() {
+        let mut ids = ToolUseIds::default();
+        assert_eq!(ids.observe("x_2", true), "x_2");
+        assert_eq!(ids.observe("x", true), "x");
+        assert_eq!(ids.observe("x", true), "x_3");
+        assert_eq!(ids.observe("y", false), "y");
+        assert_eq!(ids.observe("z", true), "z");
+        assert_eq!(ids.observe("y", true), "y");
+    }
+}
@@ -0,0 +1,167 @@
+//! 工具输入校验
+//!
+//! 按请求中声明的 `input_schema` 校验模型生成的 tool_use 输入。模型偶尔会生成不符合
+//! schema 的参数（缺少必填字段、类型错误等），部分客户端收到后会直接崩溃，
+//! 启用 `stream.toolInputValidation` 后可以为这类调用附加警告，或拦截后改为说明文本
+
+use std::collections::HashMap;
+
+use serde_json::json;
+
+use crate::model::config::ToolInputValidation;
+
+use super::types::Tool;
+
+/// 每个工具调用最多报告的错误数
+const MAX_ERRORS: usize = 5;
+
+/// 工具输入校验器
+pub struct ToolInputValidator {
+    mode: ToolInputValidation,
+    /// 工具名 -> 编译好的 schema
+    validators: HashMap<String, jsonschema::Validator>,
+}
+
+impl ToolInputValidator {
+    /// 按请求的工具列表构建校验器
+    ///
+    /// 未启用校验或没有可校验的工具时返回 None；无法编译的 schema 跳过校验
+    pub fn new(mode: ToolInputValidation, tools: Option<&[Tool]>) -> Option<Self> {
+        if mode == ToolInputValidation::Off {
+            return None;
+        }
+
+        let mut validators = HashMap::new();
+        for tool in tools.unwrap_or_default() {
+            if tool.input_schema.is_empty() {
+                continue;
+            }
+            let schema = json!(tool.input_schema);
+            match jsonschema::validator_for(&schema) {
+                Ok(validator) => {
+                    validators.insert(tool.name.clone(), validator);
+                }
+                Err(e) => {
+                    tracing::debug!(
+                        "工具 {} 的 input_schema 无法编译，跳过校验: {}",
+                        tool.name,
+                        e
+                    )
+                }
+            }
+        }
+
+        if validators.is_empty() {
+            return None;
+        }
+        Some(Self { mode, validators })
+    }
+
+    /// 校验失败时的处理方式
+    pub fn mode(&self) -> ToolInputValidation {
+        self.mode
+    }
+
+    /// 校验完整的工具输入 JSON 字符串，返回错误列表（未声明 schema 的工具视为通过）
+    pub fn validate(&self, name: &str, input: &str) -> Result<(), Vec<String>> {
+        let Some(validator) = self.validators.get(name) else {
+            return Ok(());
+        };
+
+        let input = if input.trim().is_empty() {
+            json!({})
+        } else {
+            serde_json::from_str(input).map_err(|e| vec![format!("输入不是有效的 JSON: {}", e)])?
+        };
+
+        let errors: Vec<String> = validator
+            .iter_errors(&input)
+            .take(MAX_ERRORS)
+            .map(|e| {
+                let path = e.instance_path().to_string();
+                if path.is_empty() {
+                    e.to_string()
+                } else {
+                    format!("{}: {}", path, e)
+                }
+            })
+            .collect();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            tracing::warn!(
+                "工具 {} 的输入不符合 input_schema: {}",
+                name,
+                errors.join("; ")
+            );
+            Err(errors)
+        }
+    }
+}
+
+/// 附加在 tool_use 块上的警告（`kiro_warning` 字段）
+pub fn warning(errors: &[String]) -> serde_json::Value {
+    json!({
+        "type": "tool_input_schema_mismatch",
+        "errors": errors
+    })
+}
+
+/// 拦截工具调用后代替其下发的说明文本
+pub fn correction_text(name: &str, errors: &[String]) -> String {
+    let mut text = format!(
+        "\n\n[工具调用 {} 的参数不符合其 input_schema，已被拦截，请修正后重新调用]\n",
+        name
+    );
+    for error in errors {
+        text.push_str(&format!("- {}\n", error));
+    }
+    text
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tool(name: &str, schema: serde_json::Value) -> Tool {
+        serde_json::from_value(json!({
+            "name": name,
+            "description": "",
+            "input_schema": schema
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_validate_against_schema() {
+        let tools = vec![tool(
+            "read",
+            json!({
+                "type": "object",
+                "properties": {"path": {"type": "string"}},
+                "required": ["path"]
+            }),
+        )];
+        let validator =
+            ToolInputValidator::new(ToolInputValidation::Annotate, Some(&tools)).unwrap();
+
+        assert!(validator.validate("read", r#"{"path": "a.rs"}"#).is_ok());
+        assert!(validator.validate("unknown", r#"{"x": 1}"#).is_ok());
+
+        let errors = validator.validate("read", r#"{"path": 1}"#).unwrap_err();
+        assert_eq!(errors.len(), 1);
+        assert!(errors[0].starts_with("/path: "));
+
+        // 空输入按 {} 校验
+        assert!(validator.validate("read", "").is_err());
+        assert!(validator.validate("read", "{not json").is_err());
+    }
+
+    #[test]
+    fn test_disabled_or_without_schema() {
+        let tools = vec![tool("read", json!({"type": "object"}))];
+        assert!(ToolInputValidator::new(ToolInputValidation::Off, Some(&tools)).is_none());
+        assert!(ToolInputValidator::new(ToolInputValidation::Correct, None).is_none());
+    }
+}
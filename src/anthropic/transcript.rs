@@ -0,0 +1,322 @@
+//! 会话记录渲染
+//!
+//! 将会话存储中缓存的历史消息（最近一次发送给上游的历史）渲染为独立的 HTML 或 Markdown 文件，
+//! 便于在问题报告和评审中分享：thinking 折叠显示，工具调用与工具结果单独成块
+
+use crate::kiro::model::requests::conversation::Message;
+
+/// 渲染格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TranscriptFormat {
+    Html,
+    Markdown,
+}
+
+impl TranscriptFormat {
+    /// 解析 `format` 查询参数（未指定时为 HTML）
+    pub fn parse(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("html") => Ok(TranscriptFormat::Html),
+            Some("markdown") | Some("md") => Ok(TranscriptFormat::Markdown),
+            Some(other) => Err(format!("format 只能是 html 或 markdown，收到: {}", other)),
+        }
+    }
+
+    pub fn content_type(self) -> &'static str {
+        match self {
+            TranscriptFormat::Html => "text/html; charset=utf-8",
+            TranscriptFormat::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+
+    pub fn extension(self) -> &'static str {
+        match self {
+            TranscriptFormat::Html => "html",
+            TranscriptFormat::Markdown => "md",
+        }
+    }
+}
+
+/// 消息中的内容块
+enum Block {
+    Text(String),
+    Thinking(String),
+    ToolUse {
+        id: String,
+        name: String,
+        input: String,
+    },
+    ToolResult {
+        id: String,
+        content: String,
+        is_error: bool,
+    },
+    Images(usize),
+}
+
+/// 一条消息
+struct Turn {
+    role: &'static str,
+    blocks: Vec<Block>,
+}
+
+/// 渲染会话记录
+pub fn render(session_id: &str, messages: &[Message], format: TranscriptFormat) -> String {
+    let turns: Vec<Turn> = messages.iter().map(turn).collect();
+    match format {
+        TranscriptFormat::Html => render_html(session_id, &turns),
+        TranscriptFormat::Markdown => render_markdown(session_id, &turns),
+    }
+}
+
+fn turn(message: &Message) -> Turn {
+    let mut blocks = Vec::new();
+    match message {
+        Message::User(user) => {
+            let msg = &user.user_input_message;
+            if !msg.content.is_empty() {
+                blocks.push(Block::Text(msg.content.clone()));
+            }
+            if !msg.images.is_empty() {
+                blocks.push(Block::Images(msg.images.len()));
+            }
+            for result in &msg.user_input_message_context.tool_results {
+                let content = result
+                    .content
+                    .iter()
+                    .map(|item| match item.get("text").and_then(|v| v.as_str()) {
+                        Some(text) => text.to_string(),
+                        None => serde_json::to_string_pretty(item).unwrap_or_default(),
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                blocks.push(Block::ToolResult {
+                    id: result.tool_use_id.clone(),
+                    content,
+                    is_error: result.is_error || result.status.as_deref() == Some("error"),
+                });
+            }
+            Turn {
+                role: "user",
+                blocks,
+            }
+        }
+        Message::Assistant(assistant) => {
+            let msg = &assistant.assistant_response_message;
+            let (thinking, text) = split_thinking(&msg.content);
+            if let Some(thinking) = thinking {
+                blocks.push(Block::Thinking(thinking.to_string()));
+            }
+            if !text.is_empty() {
+                blocks.push(Block::Text(text.to_string()));
+            }
+            for tool_use in msg.tool_uses.iter().flatten() {
+                blocks.push(Block::ToolUse {
+                    id: tool_use.tool_use_id.clone(),
+                    name: tool_use.name.clone(),
+                    input: serde_json::to_string_pretty(&tool_use.input).unwrap_or_default(),
+                });
+            }
+            Turn {
+                role: "assistant",
+                blocks,
+            }
+        }
+    }
+}
+
+/// 拆分转换器写入助手消息的 `<thinking>...</thinking>` 前缀
+fn split_thinking(content: &str) -> (Option<&str>, &str) {
+    if let Some(rest) = content.strip_prefix("<thinking>")
+        && let Some(end) = rest.find("</thinking>")
+    {
+        let text = rest[end + "</thinking>".len()..].trim_start_matches('\n');
+        return (Some(&rest[..end]), text);
+    }
+    (None, content)
+}
+
+fn render_markdown(session_id: &str, turns: &[Turn]) -> String {
+    let mut out = format!("# 会话 {}\n", session_id);
+    for turn in turns {
+        out.push_str(&format!("\n## {}\n", turn.role));
+        for block in &turn.blocks {
+            out.push('\n');
+            match block {
+                Block::Text(text) => {
+                    out.push_str(text);
+                    out.push('\n');
+                }
+                Block::Thinking(thinking) => {
+                    out.push_str("<details>\n<summary>Thinking</summary>\n\n");
+                    out.push_str(thinking);
+                    out.push_str("\n\n</details>\n");
+                }
+                Block::ToolUse { id, name, input } => {
+                    out.push_str(&format!("**工具调用** `{}` (`{}`)\n\n", name, id));
+                    out.push_str(&fenced(input, "json"));
+                }
+                Block::ToolResult {
+                    id,
+                    content,
+                    is_error,
+                } => {
+                    let label = if *is_error {
+                        "工具错误"
+                    } else {
+                        "工具结果"
+                    };
+                    out.push_str(&format!("**{}** (`{}`)\n\n", label, id));
+                    out.push_str(&fenced(content, ""));
+                }
+                Block::Images(count) => out.push_str(&format!("*[{} 张图片]*\n", count)),
+            }
+        }
+    }
+    out
+}
+
+/// 代码块，围栏长度超过内容中最长的连续反引号
+fn fenced(content: &str, lang: &str) -> String {
+    let longest = content.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat(longest.max(2) + 1);
+    format!("{}{}\n{}\n{}\n", fence, lang, content, fence)
+}
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,BlinkMacSystemFont,sans-serif;\
+max-width:960px;margin:2em auto;padding:0 1em;color:#222}\
+.turn{border-left:4px solid #ccc;margin:1.5em 0;padding:0 1em}\
+.user{border-color:#3b82f6}.assistant{border-color:#10b981}\
+.role{font-weight:bold;text-transform:uppercase;font-size:.8em;color:#666}\
+pre{background:#f6f8fa;padding:.8em;overflow-x:auto;white-space:pre-wrap}\
+details{color:#555}.error{color:#b91c1c}";
+
+fn render_html(session_id: &str, turns: &[Turn]) -> String {
+    let title = escape_html(&format!("会话 {}", session_id));
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+         <style>{}</style>\n</head>\n<body>\n<h1>{}</h1>\n",
+        title, HTML_STYLE, title
+    );
+    for turn in turns {
+        out.push_str(&format!(
+            "<div class=\"turn {}\">\n<p class=\"role\">{}</p>\n",
+            turn.role, turn.role
+        ));
+        for block in &turn.blocks {
+            match block {
+                Block::Text(text) => {
+                    out.push_str(&format!("<pre>{}</pre>\n", escape_html(text)));
+                }
+                Block::Thinking(thinking) => out.push_str(&format!(
+                    "<details>\n<summary>Thinking</summary>\n<pre>{}</pre>\n</details>\n",
+                    escape_html(thinking)
+                )),
+                Block::ToolUse { id, name, input } => out.push_str(&format!(
+                    "<p><strong>工具调用</strong> <code>{}</code> (<code>{}</code>)</p>\n\
+                     <pre>{}</pre>\n",
+                    escape_html(name),
+                    escape_html(id),
+                    escape_html(input)
+                )),
+                Block::ToolResult {
+                    id,
+                    content,
+                    is_error,
+                } => {
+                    let (class, label) = if *is_error {
+                        (" class=\"error\"", "工具错误")
+                    } else {
+                        ("", "工具结果")
+                    };
+                    out.push_str(&format!(
+                        "<p{}><strong>{}</strong> (<code>{}</code>)</p>\n<pre>{}</pre>\n",
+                        class,
+                        label,
+                        escape_html(id),
+                        escape_html(content)
+                    ));
+                }
+                Block::Images(count) => {
+                    out.push_str(&format!("<p><em>[{} 张图片]</em></p>\n", count));
+                }
+            }
+        }
+        out.push_str("</div>\n");
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::requests::conversation::{
+        AssistantMessage, HistoryAssistantMessage, HistoryUserMessage, UserInputMessageContext,
+        UserMessage,
+    };
+    use crate::kiro::model::requests::tool::{ToolResult, ToolUseEntry};
+
+    fn messages() -> Vec<Message> {
+        let assistant = AssistantMessage::new("<thinking>check the file</thinking>\n\nReading it.")
+            .with_tool_uses(vec![
+                ToolUseEntry::new("toolu_1", "read")
+                    .with_input(serde_json::json!({"path": "a.rs"})),
+            ]);
+        let user = UserMessage::new("Continue.", "claude-sonnet-4").with_context(
+            UserInputMessageContext::new()
+                .with_tool_results(vec![ToolResult::success("toolu_1", "fn main() { ``` }")]),
+        );
+        vec![
+            Message::User(HistoryUserMessage::new(
+                "<b>explain</b> a.rs",
+                "claude-sonnet-4",
+            )),
+            Message::Assistant(HistoryAssistantMessage {
+                assistant_response_message: assistant,
+            }),
+            Message::User(HistoryUserMessage {
+                user_input_message: user,
+            }),
+        ]
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let md = render("s1", &messages(), TranscriptFormat::Markdown);
+        assert!(md.starts_with("# 会话 s1\n"));
+        assert!(md.contains("<details>\n<summary>Thinking</summary>\n\ncheck the file\n"));
+        assert!(md.contains("\nReading it.\n"));
+        assert!(md.contains("**工具调用** `read` (`toolu_1`)"));
+        // 内容含三个反引号时使用更长的围栏
+        assert!(md.contains("````\nfn main() { ``` }\n````"));
+    }
+
+    #[test]
+    fn test_render_html_escapes_content() {
+        let html = render("s1", &messages(), TranscriptFormat::Html);
+        assert!(html.contains("<pre>&lt;b&gt;explain&lt;/b&gt; a.rs</pre>"));
+        assert!(html.contains("<details>\n<summary>Thinking</summary>"));
+        assert!(html.contains("<strong>工具结果</strong>"));
+        assert!(TranscriptFormat::parse(Some("pdf")).is_err());
+        assert_eq!(
+            TranscriptFormat::parse(Some("md")),
+            Ok(TranscriptFormat::Markdown)
+        );
+    }
+}
@@ -5,9 +5,11 @@ use std::collections::HashMap;
 
 // === 错误响应 ===
 
-/// API 错误响应
+/// API 错误响应：`{"type": "error", "error": {"type": "...", "message": "..."}}`
 #[derive(Debug, Serialize)]
 pub struct ErrorResponse {
+    #[serde(rename = "type")]
+    pub response_type: &'static str,
     pub error: ErrorDetail,
 }
 
@@ -23,17 +25,13 @@ impl ErrorResponse {
     /// 创建新的错误响应
     pub fn new(error_type: impl Into<String>, message: impl Into<String>) -> Self {
         Self {
+            response_type: "error",
             error: ErrorDetail {
                 error_type: error_type.into(),
                 message: message.into(),
             },
         }
     }
-
-    /// 创建认证错误响应
-    pub fn authentication_error() -> Self {
-        Self::new("authentication_error", "Invalid API key")
-    }
 }
 
 // === Models 端点类型 ===
@@ -49,6 +47,8 @@ pub struct Model {
     #[serde(rename = "type")]
     pub model_type: String,
     pub max_tokens: i32,
+    /// 是否支持 thinking 模式
+    pub thinking: bool,
 }
 
 /// 模型列表响应
@@ -111,10 +111,68 @@ pub struct Metadata {
     pub user_id: Option<String>,
 }
 
+/// 扩展字段：工作区上下文
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceContext {
+    /// 工作区目录
+    #[serde(default)]
+    pub folders: Vec<String>,
+    /// 当前编辑的文件
+    pub active_file: Option<WorkspaceFile>,
+    /// 编辑器中打开的其他文件
+    #[serde(default)]
+    pub open_files: Vec<WorkspaceFile>,
+}
+
+/// 扩展字段：工作区中的文件
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct WorkspaceFile {
+    /// 相对于工作区的文件路径
+    pub path: String,
+    /// 编程语言，未指定时按扩展名推断
+    pub language: Option<String>,
+    /// 文件内容
+    pub content: Option<String>,
+    /// 光标位置（仅 activeFile）
+    pub cursor: Option<WorkspacePosition>,
+    /// 选区（仅 activeFile，优先于 cursor）
+    pub selection: Option<WorkspaceSelection>,
+}
+
+/// 扩展字段：文件中的位置（行、列均从 0 开始）
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WorkspacePosition {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// 扩展字段：文件中的选区
+#[derive(Debug, Clone, Copy, Deserialize)]
+pub struct WorkspaceSelection {
+    pub start: WorkspacePosition,
+    pub end: WorkspacePosition,
+}
+
+/// 扩展字段：流式选项
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct StreamOptions {
+    /// 是否在流中周期性发送累计用量事件（kiro_usage）
+    #[serde(default)]
+    pub include_usage: bool,
+    /// 发送间隔（秒），未指定时使用配置 `stream.usageIntervalSecs`
+    pub usage_interval_secs: Option<u64>,
+    /// 每新增多少个内容块发送一次，未指定时使用配置 `stream.usageIntervalBlocks`
+    pub usage_interval_blocks: Option<usize>,
+}
+
 /// Messages 请求体
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct MessagesRequest {
     pub model: String,
+    /// 未指定（0）时使用配置 `maxTokens.default`
+    #[serde(default)]
     pub max_tokens: i32,
     pub messages: Vec<Message>,
     #[serde(default)]
@@ -127,6 +185,23 @@ pub struct MessagesRequest {
     pub output_config: Option<OutputConfig>,
     /// Claude Code 请求中的 metadata，包含 session 信息
     pub metadata: Option<Metadata>,
+    /// 扩展字段：从会话中已有的助手消息分支（重新生成 / 编辑后重发）
+    ///
+    /// 指定时 `messages` 只需包含新的轮次，历史由服务端沿分支重建
+    pub parent_message_id: Option<String>,
+    /// 扩展字段：流式选项（周期性用量事件）
+    pub stream_options: Option<StreamOptions>,
+    /// 自定义停止序列
+    pub stop_sequences: Option<Vec<String>>,
+    /// 扩展字段：工作区上下文（当前文件、光标、打开的文件），映射为 Kiro 的编辑器状态
+    pub workspace: Option<WorkspaceContext>,
+    /// 扩展字段：采样种子，用于评测时复现代理侧的随机选择（请求头 `x-kiro-seed` 优先）
+    ///
+    /// Kiro 上游不支持确定性采样，种子不会转发给上游
+    pub seed: Option<u64>,
+    /// 请求头 `Accept-Language`（由处理器填充，不从请求体读取）
+    #[serde(skip)]
+    pub accept_language: Option<String>,
 }
 
 /// 反序列化 system 字段，支持字符串或数组格式
@@ -134,8 +209,6 @@ fn deserialize_system<'de, D>(deserializer: D) -> Result<Option<Vec<SystemMessag
 where
     D: serde::Deserializer<'de>,
 {
-    use serde::de::Error;
-
     // 创建一个 visitor 来处理 string 或 array
     struct SystemVisitor;
 
@@ -204,9 +277,10 @@ pub struct SystemMessage {
 
 /// 工具定义
 ///
-/// 支持两种格式：
+/// 支持三种格式：
 /// 1. 普通工具：{ name, description, input_schema }
 /// 2. WebSearch 工具：{ type: "web_search_20250305", name: "web_search", max_uses: 8 }
+/// 3. Computer use 等客户端工具：{ type: "computer_20250124", name: "computer", display_width_px, ... }
 #[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct Tool {
     /// 工具类型，如 "web_search_20250305"（可选，仅 WebSearch 工具）
@@ -224,6 +298,15 @@ pub struct Tool {
     /// 最大使用次数（仅 WebSearch 工具）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub max_uses: Option<i32>,
+    /// 屏幕宽度（仅 computer use 工具）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_width_px: Option<u32>,
+    /// 屏幕高度（仅 computer use 工具）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_height_px: Option<u32>,
+    /// X11 显示编号（仅 computer use 工具）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_number: Option<u32>,
 }
 
 impl Tool {
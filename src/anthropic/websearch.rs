@@ -10,13 +10,21 @@ use axum::{
     response::{IntoResponse, Json, Response},
 };
 use bytes::Bytes;
-use futures::{Stream, stream};
+use futures::{Stream, StreamExt, stream};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
 use uuid::Uuid;
 
-use super::stream::SseEvent;
-use super::types::{ErrorResponse, MessagesRequest};
+use crate::common::redact;
+use crate::common::usage::RequestUsage;
+use crate::token;
+
+use super::error::ApiError;
+use super::server_tools::{self, ServerToolUsage, WEB_SEARCH};
+use super::compat::{self, CompatShim};
+use super::delivery::{Delivery, EventStream};
+use super::stream::{SseEncoder, SseEvent};
+use super::types::MessagesRequest;
 
 /// MCP 请求
 #[derive(Debug, Serialize)]
@@ -100,11 +108,13 @@ pub struct WebSearchResult {
 
 /// 检查请求是否包含 WebSearch 工具
 ///
-/// 条件：tools 中存在 web_search 工具（基于 tool_type 或 name 双重判断）
+/// 条件：tools 中存在以 `web_search_*` type 声明的工具（同名的客户端工具不算）
 pub fn has_web_search_tool(req: &MessagesRequest) -> bool {
-    req.tools
-        .as_ref()
-        .is_some_and(|tools| tools.iter().any(|t| t.is_web_search() || t.name == "web_search"))
+    req.tools.as_ref().is_some_and(|tools| {
+        tools
+            .iter()
+            .any(|t| server_tools::classify(t) == Some(&WEB_SEARCH))
+    })
 }
 
 /// 从消息中提取搜索查询
@@ -177,10 +187,7 @@ pub fn create_mcp_request(query: &str) -> (String, McpRequest) {
     );
 
     // tool_use_id 使用相同格式
-    let tool_use_id = format!(
-        "srvtoolu_{}",
-        Uuid::new_v4().to_string().replace('-', "")[..32].to_string()
-    );
+    let tool_use_id = server_tools::new_tool_use_id();
 
     let request = McpRequest {
         id: request_id,
@@ -209,21 +216,17 @@ pub fn parse_search_results(mcp_response: &McpResponse) -> Option<WebSearchResul
     serde_json::from_str(&content.text).ok()
 }
 
-/// 生成 WebSearch SSE 响应流
-pub fn create_websearch_sse_stream(
-    model: String,
-    query: String,
-    tool_use_id: String,
-    search_results: Option<WebSearchResults>,
-    input_tokens: i32,
+/// 把 WebSearch 事件序列经 SSE 兼容层编码为响应流
+fn create_websearch_sse_stream(
+    events: Vec<SseEvent>,
+    compat: Option<CompatShim>,
 ) -> impl Stream<Item = Result<Bytes, Infallible>> {
-    let events =
-        generate_websearch_events(&model, &query, &tool_use_id, search_results, input_tokens);
-
+    let mut encoder = SseEncoder::default();
     stream::iter(
         events
             .into_iter()
-            .map(|e| Ok(Bytes::from(e.to_sse_string()))),
+            .filter_map(move |e| compat::apply(compat.as_ref(), e))
+            .map(move |e| Ok(encoder.encode(&e))),
     )
 }
 
@@ -234,6 +237,7 @@ fn generate_websearch_events(
     tool_use_id: &str,
     search_results: Option<WebSearchResults>,
     input_tokens: i32,
+    usage: &RequestUsage,
 ) -> Vec<SseEvent> {
     let mut events = Vec::new();
     let message_id = format!(
@@ -243,6 +247,8 @@ fn generate_websearch_events(
 
     // 1. message_start
     let search_count = search_results.as_ref().map(|r| if r.results.is_empty() { 0 } else { 1 }).unwrap_or(0);
+    let mut server_tool_usage = ServerToolUsage::default();
+    server_tool_usage.add(&WEB_SEARCH, search_count);
     events.push(SseEvent::new(
         "message_start",
         json!({
@@ -260,9 +266,7 @@ fn generate_websearch_events(
                     "output_tokens": 0,
                     "cache_creation_input_tokens": 0,
                     "cache_read_input_tokens": 0,
-                    "server_tool_use": {
-                        "web_search_requests": search_count
-                    }
+                    "server_tool_use": server_tool_usage.to_json()
                 }
             }
         }),
@@ -390,7 +394,14 @@ fn generate_websearch_events(
     ));
 
     // 10. message_delta
-    let output_tokens = (summary.len() as i32 + 3) / 4; // 简单估算
+    let output_tokens = token::count_tokens(&summary) as i32;
+    let mut delta_usage = json!({
+        "output_tokens": output_tokens,
+        "server_tool_use": server_tool_usage.to_json()
+    });
+    if let Some(cost) = usage.record(input_tokens, output_tokens, search_count) {
+        delta_usage["kiro_estimated_cost"] = json!(cost);
+    }
     events.push(SseEvent::new(
         "message_delta",
         json!({
@@ -399,12 +410,7 @@ fn generate_websearch_events(
                 "stop_reason": "end_turn",
                 "stop_sequence": null
             },
-            "usage": {
-                "output_tokens": output_tokens,
-                "server_tool_use": {
-                    "web_search_requests": search_count
-                }
-            }
+            "usage": delta_usage
         }),
     ));
 
@@ -450,18 +456,15 @@ pub async fn handle_websearch_request(
     provider: std::sync::Arc<crate::kiro::provider::KiroProvider>,
     payload: &MessagesRequest,
     input_tokens: i32,
+    compat: Option<CompatShim>,
+    usage: RequestUsage,
+    delivery: Delivery,
 ) -> Response {
     // 1. 提取搜索查询
     let query = match extract_search_query(payload) {
         Some(q) => q,
         None => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(ErrorResponse::new(
-                    "invalid_request_error",
-                    "无法从消息中提取搜索查询",
-                )),
-            )
+            return ApiError::InvalidRequest("无法从消息中提取搜索查询".to_string())
                 .into_response();
         }
     };
@@ -491,11 +494,23 @@ pub async fn handle_websearch_request(
     // 4. 根据 stream 参数返回不同格式的响应
     let model = payload.model.clone();
     let search_count = search_results.as_ref().map(|r| if r.results.is_empty() { 0 } else { 1 }).unwrap_or(0);
+    let mut server_tool_usage = ServerToolUsage::default();
+    server_tool_usage.add(&WEB_SEARCH, search_count);
 
     if payload.stream {
         // 流式 SSE 响应
-        let stream =
-            create_websearch_sse_stream(model, query, tool_use_id, search_results, input_tokens);
+        let events = generate_websearch_events(
+            &model,
+            &query,
+            &tool_use_id,
+            search_results,
+            input_tokens,
+            &usage,
+        );
+        if delivery == Delivery::Events {
+            return EventStream::response(stream::iter(events).boxed());
+        }
+        let stream = create_websearch_sse_stream(events, compat);
 
         Response::builder()
             .status(StatusCode::OK)
@@ -550,7 +565,15 @@ pub async fn handle_websearch_request(
             "text": summary
         }));
 
-        let output_tokens = (summary.len() as i32 + 3) / 4;
+        let output_tokens = token::count_tokens(&summary) as i32;
+        let mut response_usage = json!({
+            "input_tokens": input_tokens,
+            "output_tokens": output_tokens,
+            "server_tool_use": server_tool_usage.to_json()
+        });
+        if let Some(cost) = usage.record(input_tokens, output_tokens, search_count) {
+            response_usage["kiro_estimated_cost"] = json!(cost);
+        }
 
         let response_body = json!({
             "id": message_id,
@@ -560,13 +583,7 @@ pub async fn handle_websearch_request(
             "model": model,
             "stop_reason": "end_turn",
             "stop_sequence": null,
-            "usage": {
-                "input_tokens": input_tokens,
-                "output_tokens": output_tokens,
-                "server_tool_use": {
-                    "web_search_requests": search_count
-                }
-            }
+            "usage": response_usage
         });
 
         (StatusCode::OK, Json(response_body)).into_response()
@@ -580,12 +597,12 @@ async fn call_mcp_api(
 ) -> anyhow::Result<McpResponse> {
     let request_body = serde_json::to_string(request)?;
 
-    tracing::debug!("MCP request: {}", request_body);
+    tracing::debug!("MCP request: {}", redact::body(&request_body));
 
     let response = provider.call_mcp(&request_body).await?;
 
     let body = response.text().await?;
-    tracing::debug!("MCP response: {}", body);
+    tracing::debug!("MCP response: {}", redact::body(&body));
 
     let mcp_response: McpResponse = serde_json::from_str(&body)?;
 
@@ -623,11 +640,20 @@ mod tests {
                 description: String::new(),
                 input_schema: Default::default(),
                 max_uses: Some(8),
+                display_width_px: None,
+                display_height_px: None,
+                display_number: None,
             }]),
             tool_choice: None,
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
         assert!(has_web_search_tool(&req));
@@ -653,6 +679,9 @@ mod tests {
                     description: String::new(),
                     input_schema: Default::default(),
                     max_uses: Some(8),
+                    display_width_px: None,
+                    display_height_px: None,
+                    display_number: None,
                 },
                 Tool {
                     tool_type: None,
@@ -660,12 +689,21 @@ mod tests {
                     description: "Other tool".to_string(),
                     input_schema: Default::default(),
                     max_uses: None,
+                    display_width_px: None,
+                    display_height_px: None,
+                    display_number: None,
                 },
             ]),
             tool_choice: None,
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
         // 多个工具时，只要包含 web_search 就应该被识别
@@ -693,6 +731,12 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
         let query = extract_search_query(&req);
@@ -718,6 +762,12 @@ mod tests {
             thinking: None,
             output_config: None,
             metadata: None,
+            parent_message_id: None,
+            stream_options: None,
+            stop_sequences: None,
+            workspace: None,
+            seed: None,
+            accept_language: None,
         };
 
         let query = extract_search_query(&req);
@@ -0,0 +1,287 @@
+//! WebSocket 流式传输
+//!
+//! 部分企业代理会缓冲 SSE 响应，流式输出要等到响应结束才一次性到达。`GET /v1/messages/ws`
+//! （及 `/cc/v1/messages/ws`）升级为 WebSocket 后，客户端发送一条文本消息（与 `POST /messages`
+//! 相同的请求体，`stream` 字段被忽略），服务端逐条下发与 SSE 相同的事件序列：每个事件一条文本消息，
+//! 内容即 SSE 的 `data` JSON（其中 `type` 字段为事件名）。事件直接取自内部事件流，不经过 SSE 编码与
+//! 兼容层。事件下发完毕后以 1000 关闭连接，响应流中途失败（下发 `error` 事件、没有 `message_stop`）
+//! 时以 1011 关闭，每个连接处理一个请求。
+//!
+//! 认证与 `x-kiro-*` 请求选项通过握手请求的请求头提供；请求在开始流式响应前失败时下发一条
+//! Anthropic 错误 JSON 后关闭。客户端中途关闭连接与 SSE 客户端断开的处理相同。
+//! 握手、分帧、UTF-8 校验与 ping/pong 由 axum（tungstenite）处理
+
+use std::time::Duration;
+
+use axum::{
+    Extension,
+    body::to_bytes,
+    extract::{
+        State,
+        ws::{
+            CloseFrame, Message, Utf8Bytes, WebSocket, WebSocketUpgrade, close_code,
+            rejection::WebSocketUpgradeRejection,
+        },
+    },
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+};
+use futures::{SinkExt, StreamExt};
+use tokio::sync::oneshot;
+
+use crate::common::auth::KeyIdentity;
+
+use super::delivery::{Delivery, EventStream};
+use super::error::ApiError;
+use super::handlers::{create_message, create_message_cc};
+use super::middleware::AppState;
+use super::router::MAX_BODY_SIZE;
+use super::types::MessagesRequest;
+
+/// 升级后等待客户端发送请求的时间
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// 请求转发到的端点
+#[derive(Debug, Clone, Copy)]
+enum Endpoint {
+    V1,
+    Cc,
+}
+
+/// GET /v1/messages/ws
+pub async fn messages_ws(
+    State(state): State<AppState>,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    upgrade(state, identity, headers, ws, Endpoint::V1)
+}
+
+/// GET /cc/v1/messages/ws
+pub async fn messages_ws_cc(
+    State(state): State<AppState>,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    upgrade(state, identity, headers, ws, Endpoint::Cc)
+}
+
+/// 返回 101 响应，连接升级后在后台处理请求；不是有效的升级请求时返回 Anthropic 错误
+fn upgrade(
+    state: AppState,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    ws: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+    endpoint: Endpoint,
+) -> Response {
+    let ws = match ws {
+        Ok(ws) => ws,
+        Err(rejection) => return ApiError::InvalidRequest(rejection.body_text()).into_response(),
+    };
+    ws.max_message_size(MAX_BODY_SIZE)
+        .max_frame_size(MAX_BODY_SIZE)
+        .on_failed_upgrade(|e| tracing::warn!("WebSocket 连接升级失败: {}", e))
+        .on_upgrade(move |socket| serve(socket, state, identity, headers, endpoint))
+}
+
+/// 关闭帧
+fn close(code: u16, reason: &'static str) -> Message {
+    Message::Close(Some(CloseFrame {
+        code,
+        reason: Utf8Bytes::from_static(reason),
+    }))
+}
+
+/// 读取客户端的请求消息，客户端关闭或断开时返回 None，违反约定时返回要下发的关闭帧
+async fn read_request(socket: &mut WebSocket) -> Result<Option<Utf8Bytes>, Message> {
+    loop {
+        match socket.recv().await {
+            Some(Ok(Message::Text(text))) => return Ok(Some(text)),
+            Some(Ok(Message::Ping(_) | Message::Pong(_))) => continue,
+            Some(Ok(Message::Binary(_))) => {
+                return Err(close(close_code::UNSUPPORTED, "只接受文本消息"));
+            }
+            Some(Ok(Message::Close(_))) | None => return Ok(None),
+            Some(Err(e)) => {
+                tracing::warn!("读取 WebSocket 请求失败: {}", e);
+                return Err(close(close_code::PROTOCOL, "无效的 WebSocket 消息"));
+            }
+        }
+    }
+}
+
+/// 在升级后的连接上处理一个请求
+async fn serve(
+    mut socket: WebSocket,
+    state: AppState,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    endpoint: Endpoint,
+) {
+    let message = match tokio::time::timeout(REQUEST_TIMEOUT, read_request(&mut socket)).await {
+        Ok(Ok(Some(message))) => message,
+        Ok(Ok(None)) => return,
+        Ok(Err(frame)) => {
+            let _ = socket.send(frame).await;
+            return;
+        }
+        Err(_) => {
+            let _ = socket.send(close(close_code::POLICY, "等待请求超时")).await;
+            return;
+        }
+    };
+
+    let mut response = match serde_json::from_str::<MessagesRequest>(&message) {
+        Ok(mut payload) => {
+            payload.stream = true;
+            match endpoint {
+                Endpoint::V1 => {
+                    create_message(state, identity, headers, payload, Delivery::Events).await
+                }
+                Endpoint::Cc => {
+                    create_message_cc(state, identity, headers, payload, Delivery::Events).await
+                }
+            }
+        }
+        Err(e) => ApiError::InvalidRequest(format!("请求体解析失败: {}", e)).into_response(),
+    };
+
+    // 开始流式响应前失败：下发错误 JSON 后关闭
+    let Some(mut events) = EventStream::take(&mut response) else {
+        if let Ok(body) = to_bytes(response.into_body(), MAX_BODY_SIZE).await {
+            let text = String::from_utf8_lossy(&body).into_owned();
+            let _ = socket.send(Message::Text(text.into())).await;
+        }
+        let _ = socket.send(close(close_code::NORMAL, "")).await;
+        return;
+    };
+
+    // 下发期间由单独的任务读取客户端消息（ping 由 tungstenite 自动回复），客户端关闭时停止转发
+    let (mut sender, mut receiver) = socket.split();
+    let (closed_tx, mut closed_rx) = oneshot::channel::<()>();
+    let reader_task = tokio::spawn(async move {
+        while let Some(Ok(message)) = receiver.next().await {
+            if matches!(message, Message::Close(_)) {
+                break;
+            }
+        }
+        let _ = closed_tx.send(());
+    });
+
+    // 流中途失败时只有 `error` 事件、没有 `message_stop`，据此选择关闭码
+    let mut stopped = false;
+    let completed = loop {
+        tokio::select! {
+            event = events.next() => match event {
+                Some(event) => {
+                    stopped = event.event == "message_stop";
+                    let data = event.data.to_string();
+                    if sender.send(Message::Text(data.into())).await.is_err() {
+                        break false;
+                    }
+                }
+                None => break true,
+            },
+            _ = &mut closed_rx => {
+                tracing::info!("WebSocket 客户端在响应结束前关闭连接");
+                break false;
+            }
+        }
+    };
+    reader_task.abort();
+    // 客户端已断开时提前丢弃响应流，与 SSE 客户端断开相同
+    drop(events);
+    if completed {
+        let frame = if stopped {
+            close(close_code::NORMAL, "")
+        } else {
+            close(close_code::ERROR, "响应流中断")
+        };
+        let _ = sender.send(frame).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio_tungstenite::tungstenite::{self, protocol::frame::coding::CloseCode};
+
+    use super::*;
+    use crate::model::config::ListenerAuth;
+
+    /// 启动测试服务，返回监听地址
+    async fn spawn_server() -> std::net::SocketAddr {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let app = crate::anthropic::create_router(AppState::new("sk-test"), ListenerAuth::None);
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        addr
+    }
+
+    /// 读取服务端消息直到关闭帧，返回文本消息与关闭码
+    async fn read_until_close<S>(client: &mut S) -> (Vec<String>, Option<CloseCode>)
+    where
+        S: futures::Stream<Item = Result<tungstenite::Message, tungstenite::Error>> + Unpin,
+    {
+        let mut texts = Vec::new();
+        while let Some(message) = client.next().await {
+            match message.unwrap() {
+                tungstenite::Message::Text(text) => texts.push(text.to_string()),
+                tungstenite::Message::Close(frame) => return (texts, frame.map(|f| f.code)),
+                _ => {}
+            }
+        }
+        (texts, None)
+    }
+
+    #[tokio::test]
+    async fn test_error_before_stream() {
+        let addr = spawn_server().await;
+        let (mut client, response) =
+            tokio_tungstenite::connect_async(format!("ws://{}/v1/messages/ws", addr))
+                .await
+                .unwrap();
+        assert_eq!(response.status(), 101);
+
+        let request = r#"{"model":"claude-sonnet-4-6","max_tokens":16,"messages":[{"role":"user","content":"hi"}]}"#;
+        client
+            .send(tungstenite::Message::Text(request.into()))
+            .await
+            .unwrap();
+
+        let (texts, code) = read_until_close(&mut client).await;
+        assert_eq!(texts.len(), 1);
+        let error: serde_json::Value = serde_json::from_str(&texts[0]).unwrap();
+        assert_eq!(error["type"], "error");
+        assert_eq!(code, Some(CloseCode::Normal));
+    }
+
+    #[tokio::test]
+    async fn test_rejects_binary_request() {
+        let addr = spawn_server().await;
+        let (mut client, _) =
+            tokio_tungstenite::connect_async(format!("ws://{}/cc/v1/messages/ws", addr))
+                .await
+                .unwrap();
+        client
+            .send(tungstenite::Message::Binary(b"{}".to_vec().into()))
+            .await
+            .unwrap();
+
+        let (texts, code) = read_until_close(&mut client).await;
+        assert!(texts.is_empty());
+        assert_eq!(code, Some(CloseCode::Unsupported));
+    }
+
+    #[tokio::test]
+    async fn test_requires_upgrade() {
+        let addr = spawn_server().await;
+        let response = reqwest::get(format!("http://{}/v1/messages/ws", addr))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 400);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["error"]["type"], "invalid_request_error");
+    }
+}
@@ -0,0 +1,197 @@
+//! 工作区上下文
+//!
+//! 客户端可通过扩展字段 `workspace` 附带工作区目录、当前文件（含光标或选区）与打开的文件，
+//! 转换器将其映射为 Kiro 当前消息的 `editorState`，而不是把文件内容拼进提示词文本
+
+use crate::kiro::model::requests::conversation::{
+    CursorState, EditorState, Position, ProgrammingLanguage, Range, TextDocument,
+};
+
+use super::types::{WorkspaceContext, WorkspaceFile, WorkspacePosition};
+
+/// 按扩展名推断的编程语言
+const LANGUAGE_EXTENSIONS: &[(&str, &str)] = &[
+    ("rs", "rust"),
+    ("py", "python"),
+    ("ts", "typescript"),
+    ("tsx", "tsx"),
+    ("js", "javascript"),
+    ("jsx", "jsx"),
+    ("java", "java"),
+    ("kt", "kotlin"),
+    ("go", "go"),
+    ("c", "c"),
+    ("h", "c"),
+    ("cpp", "cpp"),
+    ("cc", "cpp"),
+    ("hpp", "cpp"),
+    ("cs", "csharp"),
+    ("rb", "ruby"),
+    ("php", "php"),
+    ("swift", "swift"),
+    ("scala", "scala"),
+    ("sh", "shell"),
+    ("sql", "sql"),
+    ("json", "json"),
+    ("yaml", "yaml"),
+    ("yml", "yaml"),
+    ("toml", "toml"),
+    ("md", "markdown"),
+];
+
+/// 将工作区上下文转换为 Kiro 编辑器状态（没有任何内容时返回 None）
+///
+/// `max_file_chars` 为每个文件内容的最大字符数，为 0 时不转发文件内容
+pub fn editor_state(workspace: &WorkspaceContext, max_file_chars: usize) -> Option<EditorState> {
+    let document = workspace
+        .active_file
+        .as_ref()
+        .map(|file| text_document(file, max_file_chars));
+    let cursor_state = workspace.active_file.as_ref().and_then(cursor_state);
+    let relevant_documents: Vec<_> = workspace
+        .open_files
+        .iter()
+        .filter(|file| !file.path.is_empty())
+        .map(|file| text_document(file, max_file_chars))
+        .collect();
+    let workspace_folders: Vec<_> = workspace
+        .folders
+        .iter()
+        .filter(|folder| !folder.is_empty())
+        .cloned()
+        .collect();
+
+    if document.is_none() && relevant_documents.is_empty() && workspace_folders.is_empty() {
+        return None;
+    }
+    Some(EditorState {
+        document,
+        cursor_state,
+        use_relevant_documents: (!relevant_documents.is_empty()).then_some(true),
+        relevant_documents,
+        workspace_folders,
+    })
+}
+
+fn text_document(file: &WorkspaceFile, max_file_chars: usize) -> TextDocument {
+    let language = file
+        .language
+        .clone()
+        .filter(|l| !l.is_empty())
+        .or_else(|| infer_language(&file.path));
+    let text = file
+        .content
+        .as_deref()
+        .filter(|_| max_file_chars > 0)
+        .map(|content| truncate_chars(content, max_file_chars));
+
+    TextDocument {
+        relative_file_path: file.path.clone(),
+        programming_language: language.map(|language_name| ProgrammingLanguage { language_name }),
+        text,
+    }
+}
+
+fn cursor_state(file: &WorkspaceFile) -> Option<CursorState> {
+    if let Some(selection) = file.selection {
+        return Some(CursorState {
+            position: None,
+            range: Some(Range {
+                start: position(selection.start),
+                end: position(selection.end),
+            }),
+        });
+    }
+    file.cursor.map(|cursor| CursorState {
+        position: Some(position(cursor)),
+        range: None,
+    })
+}
+
+fn position(p: WorkspacePosition) -> Position {
+    Position {
+        line: p.line,
+        character: p.character,
+    }
+}
+
+/// 按扩展名推断编程语言
+fn infer_language(path: &str) -> Option<String> {
+    let extension = path.rsplit_once('.')?.1.to_ascii_lowercase();
+    LANGUAGE_EXTENSIONS
+        .iter()
+        .find(|(ext, _)| *ext == extension)
+        .map(|(_, language)| language.to_string())
+}
+
+/// 截断到最多 `max_chars` 个字符
+fn truncate_chars(text: &str, max_chars: usize) -> String {
+    match text.char_indices().nth(max_chars) {
+        Some((idx, _)) => {
+            tracing::debug!("工作区文件内容超过 {} 个字符，已截断", max_chars);
+            text[..idx].to_string()
+        }
+        None => text.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn workspace() -> WorkspaceContext {
+        serde_json::from_value(serde_json::json!({
+            "folders": ["/repo"],
+            "activeFile": {
+                "path": "src/main.rs",
+                "content": "fn main() {}",
+                "cursor": {"line": 0, "character": 3},
+                "selection": {"start": {"line": 0, "character": 0}, "end": {"line": 0, "character": 2}}
+            },
+            "openFiles": [
+                {"path": "lib/util.py", "content": "def f(): pass"},
+                {"path": "README", "language": "markdown"}
+            ]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_editor_state_mapping() {
+        let state = editor_state(&workspace(), 50_000).unwrap();
+        let json = serde_json::to_value(&state).unwrap();
+
+        assert_eq!(json["workspaceFolders"], serde_json::json!(["/repo"]));
+        assert_eq!(json["document"]["relativeFilePath"], "src/main.rs");
+        assert_eq!(
+            json["document"]["programmingLanguage"]["languageName"],
+            "rust"
+        );
+        assert_eq!(json["document"]["text"], "fn main() {}");
+        // 选区优先于光标
+        assert_eq!(json["cursorState"]["range"]["end"]["character"], 2);
+        assert!(json["cursorState"].get("position").is_none());
+
+        assert_eq!(json["useRelevantDocuments"], true);
+        assert_eq!(
+            json["relevantDocuments"][0]["programmingLanguage"]["languageName"],
+            "python"
+        );
+        assert_eq!(
+            json["relevantDocuments"][1]["programmingLanguage"]["languageName"],
+            "markdown"
+        );
+        assert!(json["relevantDocuments"][1].get("text").is_none());
+    }
+
+    #[test]
+    fn test_editor_state_limits() {
+        let state = editor_state(&workspace(), 4).unwrap();
+        assert_eq!(state.document.unwrap().text.as_deref(), Some("fn m"));
+
+        let state = editor_state(&workspace(), 0).unwrap();
+        assert!(state.document.unwrap().text.is_none());
+
+        assert!(editor_state(&WorkspaceContext::default(), 100).is_none());
+    }
+}
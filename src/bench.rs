@@ -0,0 +1,363 @@
+//! 压测工具（`kiro-rs bench`）
+//!
+//! 以可配置的并发、提示词长度和流式读取速率向本地服务（或任何兼容
+//! Anthropic Messages API 的 mock 后端）发送合成请求，统计首字节时间（TTFB）
+//! 和总耗时的分位数，并可采样目标进程的内存占用，用于验证性能相关的改动
+
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Duration, Instant};
+
+use parking_lot::Mutex;
+use serde::Serialize;
+use serde_json::json;
+
+use crate::http_client;
+use crate::model::config::TlsBackend;
+
+/// 压测选项
+#[derive(Debug, Clone)]
+pub struct BenchOptions {
+    /// 目标服务地址（不含路径）
+    pub url: String,
+    pub api_key: String,
+    pub model: String,
+    /// 总请求数
+    pub requests: usize,
+    /// 并发数
+    pub concurrency: usize,
+    /// 每个请求的提示词长度（字符）
+    pub prompt_chars: usize,
+    pub max_tokens: i32,
+    pub stream: bool,
+    /// 每个连接的读取速率（字节/秒），None 表示不限速
+    pub read_rate: Option<u64>,
+    /// 采样内存占用的目标进程 ID（仅 Linux）
+    pub pid: Option<u32>,
+    /// 单个请求超时（秒）
+    pub timeout_secs: u64,
+}
+
+/// 单个成功请求的测量值
+#[derive(Debug, Clone, Copy)]
+struct Sample {
+    ttfb: Duration,
+    total: Duration,
+    bytes: usize,
+}
+
+/// 耗时分位数（毫秒）
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Percentiles {
+    pub mean_ms: f64,
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+impl Percentiles {
+    fn from_durations(mut values: Vec<Duration>) -> Self {
+        if values.is_empty() {
+            return Self::default();
+        }
+        values.sort_unstable();
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mean = values.iter().map(|d| ms(*d)).sum::<f64>() / values.len() as f64;
+        Self {
+            mean_ms: mean,
+            p50_ms: ms(percentile(&values, 50.0)),
+            p90_ms: ms(percentile(&values, 90.0)),
+            p99_ms: ms(percentile(&values, 99.0)),
+            max_ms: ms(values[values.len() - 1]),
+        }
+    }
+}
+
+/// 最近秩法取分位数（输入已排序且非空）
+fn percentile(sorted: &[Duration], p: f64) -> Duration {
+    let rank = ((p / 100.0) * sorted.len() as f64).ceil() as usize;
+    sorted[rank.clamp(1, sorted.len()) - 1]
+}
+
+/// 目标进程内存占用（KiB）
+#[derive(Debug, Default, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MemoryStats {
+    pub rss_start_kb: u64,
+    pub rss_peak_kb: u64,
+    pub rss_end_kb: u64,
+}
+
+/// 压测报告
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BenchReport {
+    pub requests: usize,
+    pub succeeded: usize,
+    pub failed: usize,
+    /// 失败原因 → 次数
+    pub errors: BTreeMap<String, usize>,
+    pub duration_ms: f64,
+    pub requests_per_sec: f64,
+    pub bytes_received: usize,
+    pub ttfb: Percentiles,
+    pub total: Percentiles,
+    pub memory: Option<MemoryStats>,
+}
+
+impl BenchReport {
+    /// 文本格式报告
+    pub fn render_text(&self) -> String {
+        let row = |name: &str, p: &Percentiles| {
+            format!(
+                "{:<6} mean {:>9.1}  p50 {:>9.1}  p90 {:>9.1}  p99 {:>9.1}  max {:>9.1}\n",
+                name, p.mean_ms, p.p50_ms, p.p90_ms, p.p99_ms, p.max_ms
+            )
+        };
+
+        let mut out = format!(
+            "请求: {} 成功, {} 失败, 共 {}\n耗时: {:.1} ms, 吞吐: {:.2} req/s, 接收: {} 字节\n\n延迟 (ms)\n",
+            self.succeeded,
+            self.failed,
+            self.requests,
+            self.duration_ms,
+            self.requests_per_sec,
+            self.bytes_received
+        );
+        out.push_str(&row("ttfb", &self.ttfb));
+        out.push_str(&row("total", &self.total));
+
+        if let Some(memory) = &self.memory {
+            out.push_str(&format!(
+                "\n内存 (RSS): 开始 {} KiB, 峰值 {} KiB, 结束 {} KiB\n",
+                memory.rss_start_kb, memory.rss_peak_kb, memory.rss_end_kb
+            ));
+        }
+        if !self.errors.is_empty() {
+            out.push_str("\n失败原因:\n");
+            for (reason, count) in &self.errors {
+                out.push_str(&format!("  {:>5}  {}\n", count, reason));
+            }
+        }
+        out
+    }
+}
+
+/// 生成指定长度的提示词，附带序号避免命中上游缓存
+fn synthetic_prompt(chars: usize, seq: usize) -> String {
+    const FILLER: &str = "The quick brown fox jumps over the lazy dog. ";
+    let header = format!("[bench #{}] Summarize the following text.\n", seq);
+    let mut prompt = header;
+    while prompt.len() < chars {
+        prompt.push_str(FILLER);
+    }
+    prompt.truncate(chars.max(1));
+    prompt
+}
+
+/// 读取进程 RSS（KiB），非 Linux 或进程不存在时返回 None
+fn read_rss_kb(pid: u32) -> Option<u64> {
+    let status = std::fs::read_to_string(format!("/proc/{}/status", pid)).ok()?;
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix("VmRSS:"))
+        .and_then(|value| value.split_whitespace().next())
+        .and_then(|kb| kb.parse().ok())
+}
+
+/// 发送单个请求并测量
+async fn run_one(
+    client: &reqwest::Client,
+    options: &BenchOptions,
+    seq: usize,
+) -> Result<Sample, String> {
+    let body = json!({
+        "model": options.model,
+        "max_tokens": options.max_tokens,
+        "stream": options.stream,
+        "messages": [{"role": "user", "content": synthetic_prompt(options.prompt_chars, seq)}]
+    });
+
+    let started = Instant::now();
+    let mut response = client
+        .post(format!("{}/v1/messages", options.url.trim_end_matches('/')))
+        .header("x-api-key", &options.api_key)
+        .header("anthropic-version", "2023-06-01")
+        .json(&body)
+        .send()
+        .await
+        .map_err(|e| format!("请求失败: {}", e))?;
+
+    let status = response.status();
+    if !status.is_success() {
+        return Err(format!("HTTP {}", status.as_u16()));
+    }
+
+    let mut ttfb = None;
+    let mut bytes = 0;
+    while let Some(chunk) = response
+        .chunk()
+        .await
+        .map_err(|e| format!("读取响应失败: {}", e))?
+    {
+        ttfb.get_or_insert_with(|| started.elapsed());
+        bytes += chunk.len();
+        // 模拟慢速消费方
+        if let Some(rate) = options.read_rate.filter(|r| *r > 0) {
+            tokio::time::sleep(Duration::from_secs_f64(chunk.len() as f64 / rate as f64)).await;
+        }
+    }
+
+    let total = started.elapsed();
+    Ok(Sample {
+        ttfb: ttfb.unwrap_or(total),
+        total,
+        bytes,
+    })
+}
+
+/// 执行压测
+pub async fn run(options: BenchOptions) -> anyhow::Result<BenchReport> {
+    if options.requests == 0 || options.concurrency == 0 {
+        anyhow::bail!("requests 和 concurrency 必须大于 0");
+    }
+
+    let client = http_client::build_client(None, options.timeout_secs, TlsBackend::default())?;
+    let options = Arc::new(options);
+    let next = Arc::new(AtomicUsize::new(0));
+    let samples = Arc::new(Mutex::new(Vec::with_capacity(options.requests)));
+    let errors = Arc::new(Mutex::new(BTreeMap::<String, usize>::new()));
+
+    // 内存采样
+    let sampling = Arc::new(AtomicBool::new(true));
+    let sampler = options.pid.and_then(|pid| {
+        let start = read_rss_kb(pid)?;
+        let sampling = sampling.clone();
+        Some(tokio::spawn(async move {
+            let mut peak = start;
+            while sampling.load(Ordering::Relaxed) {
+                if let Some(rss) = read_rss_kb(pid) {
+                    peak = peak.max(rss);
+                }
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            let end = read_rss_kb(pid).unwrap_or(0);
+            MemoryStats {
+                rss_start_kb: start,
+                rss_peak_kb: peak.max(end),
+                rss_end_kb: end,
+            }
+        }))
+    });
+    if options.pid.is_some() && sampler.is_none() {
+        tracing::warn!("无法读取目标进程内存（仅支持 Linux），跳过内存采样");
+    }
+
+    let started = Instant::now();
+    let mut workers = tokio::task::JoinSet::new();
+    for _ in 0..options.concurrency.min(options.requests) {
+        let client = client.clone();
+        let options = options.clone();
+        let next = next.clone();
+        let samples = samples.clone();
+        let errors = errors.clone();
+        workers.spawn(async move {
+            loop {
+                let seq = next.fetch_add(1, Ordering::Relaxed);
+                if seq >= options.requests {
+                    break;
+                }
+                match run_one(&client, &options, seq).await {
+                    Ok(sample) => samples.lock().push(sample),
+                    Err(reason) => *errors.lock().entry(reason).or_default() += 1,
+                }
+            }
+        });
+    }
+    while workers.join_next().await.is_some() {}
+    let elapsed = started.elapsed();
+
+    sampling.store(false, Ordering::Relaxed);
+    let memory = match sampler {
+        Some(handle) => handle.await.ok(),
+        None => None,
+    };
+
+    let samples = std::mem::take(&mut *samples.lock());
+    let errors = std::mem::take(&mut *errors.lock());
+    let failed = errors.values().sum();
+    Ok(BenchReport {
+        requests: options.requests,
+        succeeded: samples.len(),
+        failed,
+        errors,
+        duration_ms: elapsed.as_secs_f64() * 1000.0,
+        requests_per_sec: samples.len() as f64 / elapsed.as_secs_f64().max(f64::EPSILON),
+        bytes_received: samples.iter().map(|s| s.bytes).sum(),
+        ttfb: Percentiles::from_durations(samples.iter().map(|s| s.ttfb).collect()),
+        total: Percentiles::from_durations(samples.iter().map(|s| s.total).collect()),
+        memory,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_percentiles() {
+        let values: Vec<Duration> = (1..=100).map(Duration::from_millis).collect();
+        let p = Percentiles::from_durations(values);
+        assert_eq!(p.p50_ms, 50.0);
+        assert_eq!(p.p90_ms, 90.0);
+        assert_eq!(p.p99_ms, 99.0);
+        assert_eq!(p.max_ms, 100.0);
+        assert_eq!(p.mean_ms, 50.5);
+
+        assert_eq!(Percentiles::from_durations(Vec::new()).max_ms, 0.0);
+    }
+
+    #[test]
+    fn test_synthetic_prompt_length() {
+        assert_eq!(synthetic_prompt(500, 3).len(), 500);
+        assert!(synthetic_prompt(500, 3).starts_with("[bench #3]"));
+        assert_ne!(synthetic_prompt(100, 1), synthetic_prompt(100, 2));
+    }
+
+    #[tokio::test]
+    async fn test_run_against_local_server() {
+        use axum::{Router, routing::post};
+
+        let app = Router::new().route(
+            "/v1/messages",
+            post(|| async { "event: message_stop\ndata: {}\n\n" }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let report = run(BenchOptions {
+            url: format!("http://{}", addr),
+            api_key: "test".to_string(),
+            model: "claude-sonnet-4-5".to_string(),
+            requests: 10,
+            concurrency: 3,
+            prompt_chars: 64,
+            max_tokens: 16,
+            stream: true,
+            read_rate: None,
+            pid: Some(std::process::id()),
+            timeout_secs: 10,
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(report.succeeded, 10);
+        assert_eq!(report.failed, 0);
+        assert!(report.ttfb.max_ms <= report.total.max_ms);
+        assert!(report.render_text().contains("10 成功"));
+    }
+}
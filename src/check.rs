@@ -0,0 +1,412 @@
+//! 部署自检（`kiro-rs check`）
+//!
+//! 依次检查配置语法、凭证文件、Token 有效性、模型映射覆盖、监听地址可绑定性与 TLS 证书，
+//! 以及可选的上游连通性，输出结构化报告。任一检查失败时以非零状态码退出，
+//! 便于在 CI/CD 中作为部署前的门禁
+
+use std::collections::HashSet;
+use std::path::Path;
+use std::time::Duration;
+
+use serde::Serialize;
+
+use crate::anthropic::models::ModelTable;
+use crate::common::tls;
+use crate::http_client::{self, ProxyConfig};
+use crate::kiro::model::credentials::CredentialsConfig;
+use crate::kiro::token_manager::MultiTokenManager;
+use crate::model::config::{Config, ListenerConfig};
+
+/// 检查选项
+#[derive(Debug, Clone)]
+pub struct CheckOptions {
+    pub config_path: String,
+    pub credentials_path: String,
+    /// 跳过所有需要访问网络的检查（Token 刷新、上游连通性）
+    pub offline: bool,
+    /// 检查上游 Kiro API 的连通性
+    pub upstream: bool,
+}
+
+/// 单项检查结果
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CheckStatus {
+    Pass,
+    Warn,
+    Fail,
+    Skip,
+}
+
+impl CheckStatus {
+    fn label(self) -> &'static str {
+        match self {
+            CheckStatus::Pass => "PASS",
+            CheckStatus::Warn => "WARN",
+            CheckStatus::Fail => "FAIL",
+            CheckStatus::Skip => "SKIP",
+        }
+    }
+}
+
+/// 单项检查
+#[derive(Debug, Clone, Serialize)]
+pub struct CheckItem {
+    pub name: String,
+    pub status: CheckStatus,
+    pub detail: String,
+}
+
+/// 检查报告
+#[derive(Debug, Default, Serialize)]
+pub struct CheckReport {
+    pub ok: bool,
+    pub checks: Vec<CheckItem>,
+}
+
+impl CheckReport {
+    fn push(&mut self, name: impl Into<String>, status: CheckStatus, detail: impl Into<String>) {
+        self.checks.push(CheckItem {
+            name: name.into(),
+            status,
+            detail: detail.into(),
+        });
+    }
+
+    /// 是否存在失败项
+    pub fn failed(&self) -> bool {
+        self.checks.iter().any(|c| c.status == CheckStatus::Fail)
+    }
+
+    /// 文本格式报告
+    pub fn render_text(&self) -> String {
+        let width = self.checks.iter().map(|c| c.name.len()).max().unwrap_or(0);
+        let mut out = String::new();
+        for check in &self.checks {
+            out.push_str(&format!(
+                "[{}] {:<width$}  {}\n",
+                check.status.label(),
+                check.name,
+                check.detail,
+                width = width
+            ));
+        }
+
+        let count = |status| self.checks.iter().filter(|c| c.status == status).count();
+        out.push_str(&format!(
+            "\n结果: {} 通过, {} 警告, {} 失败, {} 跳过\n",
+            count(CheckStatus::Pass),
+            count(CheckStatus::Warn),
+            count(CheckStatus::Fail),
+            count(CheckStatus::Skip)
+        ));
+        out
+    }
+}
+
+/// 执行全部检查
+pub async fn run(options: &CheckOptions) -> CheckReport {
+    let mut report = CheckReport::default();
+
+    // 配置文件：失败时后续检查没有意义
+    let config = match load_config(&options.config_path) {
+        Ok(config) => config,
+        Err(e) => {
+            report.push("config", CheckStatus::Fail, format!("{:#}", e));
+            return finish(report);
+        }
+    };
+    report.push(
+        "config",
+        CheckStatus::Pass,
+        format!("配置解析成功: {}", options.config_path),
+    );
+    match config.api_key.as_deref() {
+        Some(key) if !key.trim().is_empty() => {
+            report.push("config.apiKey", CheckStatus::Pass, "已设置")
+        }
+        _ => report.push(
+            "config.apiKey",
+            CheckStatus::Fail,
+            "配置文件中未设置 apiKey",
+        ),
+    }
+
+    check_models(&mut report, &config);
+    for listener in config.effective_listeners() {
+        check_listener(&mut report, &listener);
+        check_listener_tls(&mut report, &listener);
+    }
+
+    // 凭证文件
+    let credentials_config = match CredentialsConfig::load(&options.credentials_path) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            report.push("credentials", CheckStatus::Fail, format!("{:#}", e));
+            return finish(report);
+        }
+    };
+    let is_multiple_format = credentials_config.is_multiple();
+    let credentials_list = credentials_config.into_sorted_credentials();
+    let enabled = credentials_list.iter().filter(|c| !c.disabled).count();
+    if enabled == 0 {
+        report.push(
+            "credentials",
+            CheckStatus::Fail,
+            format!("{} 中没有可用的凭据", options.credentials_path),
+        );
+        return finish(report);
+    }
+    report.push(
+        "credentials",
+        CheckStatus::Pass,
+        format!(
+            "已加载 {} 个凭据（{} 个启用）",
+            credentials_list.len(),
+            enabled
+        ),
+    );
+
+    let proxy = ProxyConfig::from_config(&config);
+    let token_manager = match MultiTokenManager::new(
+        config.clone(),
+        credentials_list,
+        proxy.clone(),
+        Some(options.credentials_path.clone().into()),
+        is_multiple_format,
+    ) {
+        Ok(manager) => manager,
+        Err(e) => {
+            report.push("credentials", CheckStatus::Fail, format!("{:#}", e));
+            return finish(report);
+        }
+    };
+
+    if options.offline {
+        report.push("token", CheckStatus::Skip, "离线模式，未验证 Token");
+    } else {
+        check_tokens(&mut report, &token_manager).await;
+    }
+
+    if !options.upstream {
+        report.push("upstream", CheckStatus::Skip, "未启用（--upstream）");
+    } else if options.offline {
+        report.push("upstream", CheckStatus::Skip, "离线模式，未检查上游连通性");
+    } else {
+        check_upstream(&mut report, &token_manager, proxy.as_ref()).await;
+    }
+
+    finish(report)
+}
+
+fn finish(mut report: CheckReport) -> CheckReport {
+    report.ok = !report.failed();
+    report
+}
+
+/// 与服务启动时不同，配置文件不存在视为失败，避免部署时静默使用默认配置
+fn load_config(path: &str) -> anyhow::Result<Config> {
+    if !Path::new(path).exists() {
+        anyhow::bail!("配置文件不存在: {}", path);
+    }
+    Config::load(path)
+}
+
+/// `modelMapping` 配置有效，且 `/v1/models` 公布的每个模型都必须能映射到 Kiro 模型
+fn check_models(report: &mut CheckReport, config: &Config) {
+    let table = match ModelTable::from_config(&config.model_mapping) {
+        Ok(table) => table,
+        Err(e) => {
+            report.push("models", CheckStatus::Fail, e);
+            return;
+        }
+    };
+    let models = table.models();
+    let unmapped: Vec<_> = models
+        .iter()
+        .filter(|m| table.resolve(&m.id).is_none())
+        .map(|m| m.id.as_str())
+        .collect();
+
+    if unmapped.is_empty() {
+        report.push(
+            "models",
+            CheckStatus::Pass,
+            format!("{} 个公布模型均可映射", models.len()),
+        );
+    } else {
+        report.push(
+            "models",
+            CheckStatus::Fail,
+            format!("无法映射的模型: {}", unmapped.join(", ")),
+        );
+    }
+}
+
+/// 监听地址是否可以绑定（服务已在运行时会因端口占用而失败）
+fn check_listener(report: &mut CheckReport, listener: &ListenerConfig) {
+    let name = format!("listener {}", listener.bind);
+
+    if let Some(path) = listener.unix_path() {
+        if !cfg!(unix) {
+            report.push(name, CheckStatus::Fail, "当前平台不支持 Unix Socket 监听");
+            return;
+        }
+        let path = Path::new(path);
+        match path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() && !dir.is_dir() => {
+                report.push(
+                    name,
+                    CheckStatus::Fail,
+                    format!("目录不存在: {}", dir.display()),
+                );
+            }
+            _ if path.exists() => {
+                report.push(name, CheckStatus::Warn, "socket 文件已存在，启动时将被替换");
+            }
+            _ => report.push(name, CheckStatus::Pass, "可以创建 socket 文件"),
+        }
+        return;
+    }
+
+    match std::net::TcpListener::bind(&listener.bind) {
+        Ok(_) => report.push(name, CheckStatus::Pass, "可以绑定"),
+        Err(e) => report.push(name, CheckStatus::Fail, format!("无法绑定: {}", e)),
+    }
+}
+
+/// 监听器的 TLS 证书与私钥能否加载
+fn check_listener_tls(report: &mut CheckReport, listener: &ListenerConfig) {
+    let Some(config) = &listener.tls else {
+        return;
+    };
+    let name = format!("tls {}", listener.bind);
+    if listener.unix_path().is_some() {
+        report.push(name, CheckStatus::Fail, "Unix Socket 监听器不支持 TLS");
+        return;
+    }
+    match tls::server_config(config) {
+        Ok(_) if config.client_ca_file.is_some() => {
+            report.push(name, CheckStatus::Pass, "证书已加载，校验客户端证书")
+        }
+        Ok(_) => report.push(name, CheckStatus::Pass, "证书已加载"),
+        Err(e) => report.push(name, CheckStatus::Fail, format!("{:#}", e)),
+    }
+}
+
+/// 逐个验证启用的凭据：必要时刷新 Token（结果会回写凭证文件），再调用 getUsageLimits
+async fn check_tokens(report: &mut CheckReport, token_manager: &MultiTokenManager) {
+    for entry in token_manager.snapshot().entries {
+        let name = format!("token #{}", entry.id);
+        if entry.disabled {
+            report.push(name, CheckStatus::Skip, "凭据已禁用");
+            continue;
+        }
+        match token_manager.get_usage_limits_for(entry.id).await {
+            Ok(_) => report.push(name, CheckStatus::Pass, "Token 有效"),
+            Err(e) => report.push(name, CheckStatus::Fail, format!("{:#}", e)),
+        }
+    }
+}
+
+/// 对每个（代理, 区域）组合发起一次 HEAD 请求，只关心连接能否建立
+async fn check_upstream(
+    report: &mut CheckReport,
+    token_manager: &MultiTokenManager,
+    global_proxy: Option<&ProxyConfig>,
+) {
+    let config = token_manager.config();
+    let mut targets = HashSet::new();
+    for credentials in token_manager.enabled_credentials() {
+        let proxy = credentials.effective_proxy(global_proxy);
+        let domain = format!(
+            "q.{}.amazonaws.com",
+            credentials.effective_api_region(config)
+        );
+        if !targets.insert((proxy.clone(), domain.clone())) {
+            continue;
+        }
+
+        let name = format!("upstream {}", domain);
+        let client = match http_client::build_client(proxy.as_ref(), 10, config.tls_backend) {
+            Ok(client) => client,
+            Err(e) => {
+                report.push(
+                    name,
+                    CheckStatus::Fail,
+                    format!("创建 HTTP Client 失败: {}", e),
+                );
+                continue;
+            }
+        };
+        match client
+            .head(format!("https://{}/", domain))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+        {
+            Ok(response) => report.push(
+                name,
+                CheckStatus::Pass,
+                format!("可达（HTTP {}）", response.status().as_u16()),
+            ),
+            Err(e) => report.push(name, CheckStatus::Fail, format!("不可达: {}", e)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn listener(bind: &str) -> ListenerConfig {
+        ListenerConfig {
+            bind: bind.to_string(),
+            services: vec![],
+            auth: Default::default(),
+            api_key: None,
+            tls: None,
+        }
+    }
+
+    #[test]
+    fn test_advertised_models_are_mapped() {
+        let mut report = CheckReport::default();
+        check_models(&mut report, &Config::default());
+        assert_eq!(report.checks[0].status, CheckStatus::Pass);
+    }
+
+    #[test]
+    fn test_listener_bindability() {
+        let occupied = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = occupied.local_addr().unwrap().to_string();
+
+        let mut report = CheckReport::default();
+        check_listener(&mut report, &listener("127.0.0.1:0"));
+        check_listener(&mut report, &listener(&addr));
+        check_listener(&mut report, &listener("unix:/nonexistent-dir/kiro.sock"));
+
+        let statuses: Vec<_> = report.checks.iter().map(|c| c.status).collect();
+        assert_eq!(
+            statuses,
+            vec![CheckStatus::Pass, CheckStatus::Fail, CheckStatus::Fail]
+        );
+        assert!(!finish(report).ok);
+    }
+
+    #[tokio::test]
+    async fn test_missing_config_fails_early() {
+        let report = run(&CheckOptions {
+            config_path: "/nonexistent/config.json".to_string(),
+            credentials_path: "/nonexistent/credentials.json".to_string(),
+            offline: true,
+            upstream: false,
+        })
+        .await;
+
+        assert!(!report.ok);
+        assert_eq!(report.checks.len(), 1);
+        assert_eq!(report.checks[0].name, "config");
+        assert!(report.render_text().contains("1 失败"));
+    }
+}
@@ -39,3 +39,16 @@ pub fn extract_api_key(request: &Request<Body>) -> Option<String> {
 pub fn constant_time_eq(a: &str, b: &str) -> bool {
     a.as_bytes().ct_eq(b.as_bytes()).into()
 }
+
+/// 请求的调用方身份
+///
+/// 认证中间件写入请求扩展，日志、指标和用量统计按其区分调用方
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KeyIdentity {
+    /// 名称（`apiKeys` 中的 name、客户端证书 CN，主 API Key 为 `default`）
+    pub name: String,
+    /// 调用方对应的 API Key
+    pub api_key: String,
+    /// 允许使用的模型（为空时不限制）
+    pub models: Vec<String>,
+}
@@ -0,0 +1,839 @@
+//! 运行时指标
+//!
+//! 进程级计数器，使用原子变量累加，通过 Admin API 查询快照，或由 `/metrics` 以 Prometheus 格式导出
+
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::kiro::attempt::AttemptReason;
+
+/// 慢客户端计数器
+///
+/// 客户端读取 SSE 过慢、发送队列写满时累加
+pub struct SlowClientMetrics {
+    /// 出现过队列写满的流数量
+    slow_streams: AtomicU64,
+    /// 丢弃的 ping 事件数
+    pings_dropped: AtomicU64,
+    /// 被合并掉的增量事件数
+    deltas_coalesced: AtomicU64,
+    /// 因客户端过慢而中止的流数量
+    streams_aborted: AtomicU64,
+}
+
+impl SlowClientMetrics {
+    const fn new() -> Self {
+        Self {
+            slow_streams: AtomicU64::new(0),
+            pings_dropped: AtomicU64::new(0),
+            deltas_coalesced: AtomicU64::new(0),
+            streams_aborted: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_slow_stream(&self) {
+        self.slow_streams.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_pings_dropped(&self, count: u64) {
+        self.pings_dropped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_deltas_coalesced(&self, count: u64) {
+        self.deltas_coalesced.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_stream_aborted(&self) {
+        self.streams_aborted.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> SlowClientSnapshot {
+        SlowClientSnapshot {
+            slow_streams: self.slow_streams.load(Ordering::Relaxed),
+            pings_dropped: self.pings_dropped.load(Ordering::Relaxed),
+            deltas_coalesced: self.deltas_coalesced.load(Ordering::Relaxed),
+            streams_aborted: self.streams_aborted.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 慢客户端计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlowClientSnapshot {
+    pub slow_streams: u64,
+    pub pings_dropped: u64,
+    pub deltas_coalesced: u64,
+    pub streams_aborted: u64,
+}
+
+static SLOW_CLIENT: SlowClientMetrics = SlowClientMetrics::new();
+
+/// 全局慢客户端计数器
+pub fn slow_client() -> &'static SlowClientMetrics {
+    &SLOW_CLIENT
+}
+
+/// 注入策略回显计数器
+pub struct PolicyEchoMetrics {
+    /// 被剥离的回显次数
+    stripped: AtomicU64,
+    /// 被剥离的 thinking 指令回显次数
+    directives_stripped: AtomicU64,
+}
+
+impl PolicyEchoMetrics {
+    const fn new() -> Self {
+        Self {
+            stripped: AtomicU64::new(0),
+            directives_stripped: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_stripped(&self, count: u64) {
+        self.stripped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub fn record_directives_stripped(&self, count: u64) {
+        self.directives_stripped.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// 获取被剥离的回显次数
+    pub fn stripped(&self) -> u64 {
+        self.stripped.load(Ordering::Relaxed)
+    }
+
+    /// 获取被剥离的 thinking 指令回显次数
+    pub fn directives_stripped(&self) -> u64 {
+        self.directives_stripped.load(Ordering::Relaxed)
+    }
+}
+
+static POLICY_ECHO: PolicyEchoMetrics = PolicyEchoMetrics::new();
+
+/// 全局注入策略回显计数器
+pub fn policy_echo() -> &'static PolicyEchoMetrics {
+    &POLICY_ECHO
+}
+
+/// 上游重复 tool_use_id 计数器
+pub struct DuplicateToolIdMetrics {
+    /// 被重新分配 ID 的工具调用次数
+    reassigned: AtomicU64,
+}
+
+impl DuplicateToolIdMetrics {
+    const fn new() -> Self {
+        Self {
+            reassigned: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_reassigned(&self) {
+        self.reassigned.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取被重新分配 ID 的工具调用次数
+    pub fn reassigned(&self) -> u64 {
+        self.reassigned.load(Ordering::Relaxed)
+    }
+}
+
+static DUPLICATE_TOOL_IDS: DuplicateToolIdMetrics = DuplicateToolIdMetrics::new();
+
+/// 全局重复 tool_use_id 计数器
+pub fn duplicate_tool_ids() -> &'static DuplicateToolIdMetrics {
+    &DUPLICATE_TOOL_IDS
+}
+
+/// 迟到工具输入片段计数器
+pub struct LateToolFragmentMetrics {
+    /// 合并进已收到 stop 的工具调用的片段数
+    merged: AtomicU64,
+    /// 等待窗口用完仍未补全输入的工具调用数
+    expired: AtomicU64,
+}
+
+impl LateToolFragmentMetrics {
+    const fn new() -> Self {
+        Self {
+            merged: AtomicU64::new(0),
+            expired: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_merged(&self) {
+        self.merged.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_expired(&self) {
+        self.expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> LateToolFragmentSnapshot {
+        LateToolFragmentSnapshot {
+            merged: self.merged.load(Ordering::Relaxed),
+            expired: self.expired.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 迟到工具输入片段计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct LateToolFragmentSnapshot {
+    pub merged: u64,
+    pub expired: u64,
+}
+
+static LATE_TOOL_FRAGMENTS: LateToolFragmentMetrics = LateToolFragmentMetrics::new();
+
+/// 全局迟到工具输入片段计数器
+pub fn late_tool_fragments() -> &'static LateToolFragmentMetrics {
+    &LATE_TOOL_FRAGMENTS
+}
+
+/// 上游会话 ID 回显计数器
+pub struct ConversationEchoMetrics {
+    /// 回显与发送一致
+    matched: AtomicU64,
+    /// 回显与发送不一致
+    mismatched: AtomicU64,
+    /// 上游未回显
+    missing: AtomicU64,
+}
+
+impl ConversationEchoMetrics {
+    const fn new() -> Self {
+        Self {
+            matched: AtomicU64::new(0),
+            mismatched: AtomicU64::new(0),
+            missing: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_matched(&self) {
+        self.matched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_mismatched(&self) {
+        self.mismatched.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_missing(&self) {
+        self.missing.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> ConversationEchoSnapshot {
+        ConversationEchoSnapshot {
+            matched: self.matched.load(Ordering::Relaxed),
+            mismatched: self.mismatched.load(Ordering::Relaxed),
+            missing: self.missing.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 上游会话 ID 回显计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ConversationEchoSnapshot {
+    pub matched: u64,
+    pub mismatched: u64,
+    pub missing: u64,
+}
+
+static CONVERSATION_ECHO: ConversationEchoMetrics = ConversationEchoMetrics::new();
+
+/// 全局上游会话 ID 回显计数器
+pub fn conversation_echo() -> &'static ConversationEchoMetrics {
+    &CONVERSATION_ECHO
+}
+
+/// 会话淘汰计数器
+pub struct SessionEvictionMetrics {
+    /// 空闲超时淘汰
+    expired: AtomicU64,
+    /// 超出会话数上限淘汰
+    capacity: AtomicU64,
+    /// 超出内存预算淘汰
+    memory: AtomicU64,
+}
+
+impl SessionEvictionMetrics {
+    const fn new() -> Self {
+        Self {
+            expired: AtomicU64::new(0),
+            capacity: AtomicU64::new(0),
+            memory: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_expired(&self) {
+        self.expired.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_capacity(&self) {
+        self.capacity.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_memory(&self) {
+        self.memory.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> SessionEvictionSnapshot {
+        SessionEvictionSnapshot {
+            expired: self.expired.load(Ordering::Relaxed),
+            capacity: self.capacity.load(Ordering::Relaxed),
+            memory: self.memory.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 会话淘汰计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SessionEvictionSnapshot {
+    pub expired: u64,
+    pub capacity: u64,
+    pub memory: u64,
+}
+
+static SESSION_EVICTION: SessionEvictionMetrics = SessionEvictionMetrics::new();
+
+/// 全局会话淘汰计数器
+pub fn session_eviction() -> &'static SessionEvictionMetrics {
+    &SESSION_EVICTION
+}
+
+/// thinking 预算截断计数器
+pub struct ThinkingBudgetMetrics {
+    /// 超出预算被截断的响应数
+    overflows: AtomicU64,
+    /// 截断后未下发的 thinking tokens（估算值）
+    suppressed_tokens: AtomicU64,
+}
+
+impl ThinkingBudgetMetrics {
+    const fn new() -> Self {
+        Self {
+            overflows: AtomicU64::new(0),
+            suppressed_tokens: AtomicU64::new(0),
+        }
+    }
+
+    pub fn record_overflow(&self) {
+        self.overflows.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn record_suppressed(&self, tokens: u64) {
+        self.suppressed_tokens.fetch_add(tokens, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> ThinkingBudgetSnapshot {
+        ThinkingBudgetSnapshot {
+            overflows: self.overflows.load(Ordering::Relaxed),
+            suppressed_tokens: self.suppressed_tokens.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// thinking 预算截断计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ThinkingBudgetSnapshot {
+    pub overflows: u64,
+    pub suppressed_tokens: u64,
+}
+
+static THINKING_BUDGET: ThinkingBudgetMetrics = ThinkingBudgetMetrics::new();
+
+/// 全局 thinking 预算截断计数器
+pub fn thinking_budget() -> &'static ThinkingBudgetMetrics {
+    &THINKING_BUDGET
+}
+
+/// 上游失败尝试计数器（按原因）
+pub struct UpstreamFailureMetrics {
+    counts: [AtomicU64; AttemptReason::ALL.len()],
+}
+
+impl UpstreamFailureMetrics {
+    const fn new() -> Self {
+        Self {
+            counts: [const { AtomicU64::new(0) }; AttemptReason::ALL.len()],
+        }
+    }
+
+    pub fn record(&self, reason: AttemptReason) {
+        self.counts[reason as usize].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 指定原因的失败次数
+    pub fn count(&self, reason: AttemptReason) -> u64 {
+        self.counts[reason as usize].load(Ordering::Relaxed)
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> UpstreamFailureSnapshot {
+        let count = |reason: AttemptReason| self.count(reason);
+        UpstreamFailureSnapshot {
+            unauthorized: count(AttemptReason::Unauthorized),
+            throttled: count(AttemptReason::Throttled),
+            timeout: count(AttemptReason::Timeout),
+            network: count(AttemptReason::Network),
+            server_error: count(AttemptReason::ServerError),
+            quota_exhausted: count(AttemptReason::QuotaExhausted),
+            no_credential: count(AttemptReason::NoCredential),
+            rejected: count(AttemptReason::Rejected),
+        }
+    }
+}
+
+/// 上游失败尝试计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UpstreamFailureSnapshot {
+    pub unauthorized: u64,
+    pub throttled: u64,
+    pub timeout: u64,
+    pub network: u64,
+    pub server_error: u64,
+    pub quota_exhausted: u64,
+    pub no_credential: u64,
+    pub rejected: u64,
+}
+
+static UPSTREAM_FAILURES: UpstreamFailureMetrics = UpstreamFailureMetrics::new();
+
+/// 全局上游失败尝试计数器
+pub fn upstream_failures() -> &'static UpstreamFailureMetrics {
+    &UPSTREAM_FAILURES
+}
+
+/// 流式响应计数器
+pub struct ActiveStreamMetrics {
+    /// 正在转发的流数量
+    active: AtomicU64,
+    /// 累计开始的流数量
+    started: AtomicU64,
+    /// 客户端断开而取消上游请求的流数量
+    cancelled: AtomicU64,
+}
+
+impl ActiveStreamMetrics {
+    const fn new() -> Self {
+        Self {
+            active: AtomicU64::new(0),
+            started: AtomicU64::new(0),
+            cancelled: AtomicU64::new(0),
+        }
+    }
+
+    /// 开始一个流，返回的守卫释放时计为结束
+    pub fn start(&'static self) -> ActiveStreamGuard {
+        self.started.fetch_add(1, Ordering::Relaxed);
+        self.active.fetch_add(1, Ordering::Relaxed);
+        ActiveStreamGuard { metrics: self }
+    }
+
+    /// 记录一个因客户端断开而取消的流
+    pub fn record_cancelled(&self) {
+        self.cancelled.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// 获取当前计数快照
+    pub fn snapshot(&self) -> ActiveStreamSnapshot {
+        ActiveStreamSnapshot {
+            active: self.active.load(Ordering::Relaxed),
+            started: self.started.load(Ordering::Relaxed),
+            cancelled: self.cancelled.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// 活跃流守卫，释放时活跃流数量减一
+pub struct ActiveStreamGuard {
+    metrics: &'static ActiveStreamMetrics,
+}
+
+impl Drop for ActiveStreamGuard {
+    fn drop(&mut self) {
+        self.metrics.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// 流式响应计数快照
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActiveStreamSnapshot {
+    pub active: u64,
+    pub started: u64,
+    pub cancelled: u64,
+}
+
+static ACTIVE_STREAMS: ActiveStreamMetrics = ActiveStreamMetrics::new();
+
+/// 全局流式响应计数器
+pub fn active_streams() -> &'static ActiveStreamMetrics {
+    &ACTIVE_STREAMS
+}
+
+/// 正在处理的 API 请求数（收到请求到响应体发送完毕）
+pub struct InFlightMetrics {
+    active: AtomicU64,
+}
+
+impl InFlightMetrics {
+    const fn new() -> Self {
+        Self {
+            active: AtomicU64::new(0),
+        }
+    }
+
+    /// 开始一个请求，返回的守卫释放时计为结束
+    pub fn start(&'static self) -> InFlightGuard {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        InFlightGuard { metrics: self }
+    }
+
+    /// 当前正在处理的请求数
+    pub fn active(&self) -> u64 {
+        self.active.load(Ordering::Relaxed)
+    }
+}
+
+/// 请求守卫，释放时正在处理的请求数减一
+pub struct InFlightGuard {
+    metrics: &'static InFlightMetrics,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.metrics.active.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+static IN_FLIGHT: InFlightMetrics = InFlightMetrics::new();
+
+/// 全局正在处理的请求计数器
+pub fn in_flight() -> &'static InFlightMetrics {
+    &IN_FLIGHT
+}
+
+/// 请求耗时直方图的桶上界（秒）
+pub const LATENCY_BUCKETS: [f64; 11] =
+    [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0];
+
+/// 单个端点的请求统计
+#[derive(Debug, Clone, Default)]
+pub struct EndpointStats {
+    /// 状态码 -> 请求数
+    pub statuses: BTreeMap<u16, u64>,
+    /// 落入各个桶的请求数（非累计，最后一个为超出全部上界的请求）
+    pub buckets: [u64; LATENCY_BUCKETS.len() + 1],
+    /// 耗时总和（秒）
+    pub duration_sum: f64,
+}
+
+impl EndpointStats {
+    /// 请求总数
+    pub fn count(&self) -> u64 {
+        self.statuses.values().sum()
+    }
+}
+
+/// 请求计数与耗时（按端点）
+///
+/// 耗时为收到请求到返回响应头的时间，流式响应即首字节时间
+pub struct RequestMetrics {
+    endpoints: Mutex<BTreeMap<String, EndpointStats>>,
+}
+
+impl RequestMetrics {
+    const fn new() -> Self {
+        Self {
+            endpoints: parking_lot::const_mutex(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, endpoint: &str, status: u16, elapsed: Duration) {
+        let secs = elapsed.as_secs_f64();
+        let bucket = LATENCY_BUCKETS
+            .iter()
+            .position(|&bound| secs <= bound)
+            .unwrap_or(LATENCY_BUCKETS.len());
+
+        let mut endpoints = self.endpoints.lock();
+        let stats = endpoints.entry(endpoint.to_string()).or_default();
+        *stats.statuses.entry(status).or_default() += 1;
+        stats.buckets[bucket] += 1;
+        stats.duration_sum += secs;
+    }
+
+    /// 获取各端点的统计快照
+    pub fn snapshot(&self) -> BTreeMap<String, EndpointStats> {
+        self.endpoints.lock().clone()
+    }
+}
+
+static REQUESTS: RequestMetrics = RequestMetrics::new();
+
+/// 全局请求计数器
+pub fn requests() -> &'static RequestMetrics {
+    &REQUESTS
+}
+
+/// 单个模型的 tokens 用量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TokenCounts {
+    pub input: u64,
+    pub output: u64,
+}
+
+/// tokens 用量计数器（按模型）
+pub struct TokenMetrics {
+    models: Mutex<BTreeMap<String, TokenCounts>>,
+}
+
+impl TokenMetrics {
+    const fn new() -> Self {
+        Self {
+            models: parking_lot::const_mutex(BTreeMap::new()),
+        }
+    }
+
+    pub fn record(&self, model: &str, input_tokens: u64, output_tokens: u64) {
+        let mut models = self.models.lock();
+        let counts = models.entry(model.to_string()).or_default();
+        counts.input += input_tokens;
+        counts.output += output_tokens;
+    }
+
+    /// 获取各模型的用量快照
+    pub fn snapshot(&self) -> BTreeMap<String, TokenCounts> {
+        self.models.lock().clone()
+    }
+}
+
+static TOKENS: TokenMetrics = TokenMetrics::new();
+
+/// 全局 tokens 用量计数器
+pub fn tokens() -> &'static TokenMetrics {
+    &TOKENS
+}
+
+/// 单个 API Key 的请求计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct KeyCounts {
+    /// 通过认证的请求数
+    pub requests: u64,
+    /// 超出每分钟请求数被拒绝的请求数
+    pub rate_limited: u64,
+}
+
+/// 请求计数器（按 API Key 名称）
+pub struct KeyMetrics {
+    keys: Mutex<BTreeMap<String, KeyCounts>>,
+}
+
+impl KeyMetrics {
+    const fn new() -> Self {
+        Self {
+            keys: parking_lot::const_mutex(BTreeMap::new()),
+        }
+    }
+
+    pub fn record_request(&self, name: &str) {
+        self.keys
+            .lock()
+            .entry(name.to_string())
+            .or_default()
+            .requests += 1;
+    }
+
+    pub fn record_rate_limited(&self, name: &str) {
+        self.keys
+            .lock()
+            .entry(name.to_string())
+            .or_default()
+            .rate_limited += 1;
+    }
+
+    /// 获取各 Key 的计数快照
+    pub fn snapshot(&self) -> BTreeMap<String, KeyCounts> {
+        self.keys.lock().clone()
+    }
+}
+
+static API_KEYS: KeyMetrics = KeyMetrics::new();
+
+/// 全局按 API Key 的请求计数器
+pub fn api_keys() -> &'static KeyMetrics {
+    &API_KEYS
+}
+
+/// 最近错误保留的条数
+const RECENT_ERRORS_CAPACITY: usize = 50;
+
+/// 一次失败的上游尝试
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecentError {
+    /// 发生时间（RFC3339）
+    pub at: String,
+    pub credential_id: Option<u64>,
+    /// 失败原因（如 `throttled`）
+    pub reason: &'static str,
+    /// 上游 HTTP 状态码
+    pub status: Option<u16>,
+}
+
+/// 最近失败的上游尝试（环形缓冲区）
+pub struct RecentErrors {
+    entries: Mutex<VecDeque<RecentError>>,
+}
+
+impl RecentErrors {
+    const fn new() -> Self {
+        Self {
+            entries: parking_lot::const_mutex(VecDeque::new()),
+        }
+    }
+
+    pub fn record(&self, credential_id: Option<u64>, reason: AttemptReason, status: Option<u16>) {
+        let mut entries = self.entries.lock();
+        if entries.len() >= RECENT_ERRORS_CAPACITY {
+            entries.pop_front();
+        }
+        entries.push_back(RecentError {
+            at: chrono::Utc::now().to_rfc3339(),
+            credential_id,
+            reason: reason.code(),
+            status,
+        });
+    }
+
+    /// 最近的错误（新的在前）
+    pub fn list(&self) -> Vec<RecentError> {
+        self.entries.lock().iter().rev().cloned().collect()
+    }
+}
+
+static RECENT_ERRORS: RecentErrors = RecentErrors::new();
+
+/// 全局最近错误记录
+pub fn recent_errors() -> &'static RecentErrors {
+    &RECENT_ERRORS
+}
+
+/// 全部运行时指标的快照（进程启动以来的累计值）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MetricsSnapshot {
+    /// 慢客户端计数
+    pub slow_clients: SlowClientSnapshot,
+    /// 被剥离的注入策略回显次数
+    pub policy_echoes_stripped: u64,
+    /// 被剥离的 thinking 指令回显次数
+    pub thinking_directive_echoes_stripped: u64,
+    /// 上游会话 ID 回显核对计数
+    pub conversation_echoes: ConversationEchoSnapshot,
+    /// thinking 预算截断计数
+    pub thinking_budget: ThinkingBudgetSnapshot,
+    /// 会话存储淘汰计数
+    pub session_evictions: SessionEvictionSnapshot,
+    /// 上游失败尝试计数（按原因）
+    pub upstream_failures: UpstreamFailureSnapshot,
+    /// 流式响应计数
+    pub active_streams: ActiveStreamSnapshot,
+    /// 正在处理的 API 请求数
+    pub in_flight_requests: u64,
+    /// 因上游复用 tool_use_id 而重新分配 ID 的工具调用次数
+    pub duplicate_tool_ids: u64,
+    /// 迟到的工具输入片段计数
+    pub late_tool_fragments: LateToolFragmentSnapshot,
+}
+
+/// 获取全部运行时指标的快照
+pub fn snapshot() -> MetricsSnapshot {
+    MetricsSnapshot {
+        slow_clients: slow_client().snapshot(),
+        policy_echoes_stripped: policy_echo().stripped(),
+        thinking_directive_echoes_stripped: policy_echo().directives_stripped(),
+        conversation_echoes: conversation_echo().snapshot(),
+        thinking_budget: thinking_budget().snapshot(),
+        session_evictions: session_eviction().snapshot(),
+        upstream_failures: upstream_failures().snapshot(),
+        active_streams: active_streams().snapshot(),
+        in_flight_requests: in_flight().active(),
+        duplicate_tool_ids: duplicate_tool_ids().reassigned(),
+        late_tool_fragments: late_tool_fragments().snapshot(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_active_stream_guard() {
+        static STREAMS: ActiveStreamMetrics = ActiveStreamMetrics::new();
+        let first = STREAMS.start();
+        let second = STREAMS.start();
+        assert_eq!(STREAMS.snapshot().active, 2);
+        drop(first);
+        drop(second);
+        let snapshot = STREAMS.snapshot();
+        assert_eq!((snapshot.active, snapshot.started), (0, 2));
+    }
+
+    #[test]
+    fn test_in_flight_guard() {
+        static IN_FLIGHT: InFlightMetrics = InFlightMetrics::new();
+        let guard = IN_FLIGHT.start();
+        assert_eq!(IN_FLIGHT.active(), 1);
+        drop(guard);
+        assert_eq!(IN_FLIGHT.active(), 0);
+    }
+
+    #[test]
+    fn test_request_latency_buckets() {
+        let requests = RequestMetrics::new();
+        requests.record("/v1/messages", 200, Duration::from_millis(30));
+        requests.record("/v1/messages", 200, Duration::from_secs(3));
+        requests.record("/v1/messages", 429, Duration::from_secs(600));
+        let snapshot = requests.snapshot();
+        let stats = &snapshot["/v1/messages"];
+        assert_eq!(stats.count(), 3);
+        assert_eq!(stats.statuses[&429], 1);
+        assert_eq!(stats.buckets[0], 1);
+        assert_eq!(stats.buckets[6], 1);
+        assert_eq!(stats.buckets[LATENCY_BUCKETS.len()], 1);
+        assert!((stats.duration_sum - 603.03).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_recent_errors_keep_latest() {
+        let errors = RecentErrors::new();
+        for id in 0..(RECENT_ERRORS_CAPACITY as u64 + 5) {
+            errors.record(Some(id), AttemptReason::Throttled, Some(429));
+        }
+        let list = errors.list();
+        assert_eq!(list.len(), RECENT_ERRORS_CAPACITY);
+        assert_eq!(
+            list[0].credential_id,
+            Some(RECENT_ERRORS_CAPACITY as u64 + 4)
+        );
+        assert_eq!(list.last().unwrap().credential_id, Some(5));
+        assert_eq!(list[0].reason, "throttled");
+    }
+}
@@ -1,3 +1,10 @@
 //! 公共工具模块
 
 pub mod auth;
+pub mod metrics;
+pub mod openapi;
+pub mod prometheus;
+pub mod redact;
+pub mod telemetry;
+pub mod tls;
+pub mod usage;
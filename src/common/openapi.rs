@@ -0,0 +1,974 @@
+//! OpenAPI 文档
+//!
+//! `GET /openapi.json` 返回描述本代理所有端点的 OpenAPI 3.1 文档：`/v1`、`/cc/v1`、OpenAI 兼容端点、
+//! `/metrics`、Admin API，以及与官方 API 不同的扩展字段和 `x-kiro-*` 请求头/响应头，
+//! 供内部团队生成类型化客户端。
+//!
+//! 文档手工维护：新增端点、扩展字段或扩展头时同步更新这里。消息内容块等与官方 API 相同的结构
+//! 不展开描述，只标注本代理的差异
+
+use axum::{Json, Router, routing::get};
+use serde_json::{Map, Value, json};
+
+/// 文档路径
+pub const OPENAPI_PATH: &str = "/openapi.json";
+
+/// 创建 `/openapi.json` 路由（不需要认证）
+pub fn create_router() -> Router {
+    Router::new().route(OPENAPI_PATH, get(get_openapi))
+}
+
+async fn get_openapi() -> Json<Value> {
+    Json(document())
+}
+
+/// 组件引用
+fn reference(kind: &str, name: &str) -> Value {
+    json!({ "$ref": format!("#/components/{}/{}", kind, name) })
+}
+
+fn schema(name: &str) -> Value {
+    reference("schemas", name)
+}
+
+/// 单个操作的构建器
+struct Operation(Map<String, Value>);
+
+impl Operation {
+    fn new(operation_id: &str, tag: &str, summary: &str) -> Self {
+        let mut op = Map::new();
+        op.insert("operationId".to_string(), json!(operation_id));
+        op.insert("tags".to_string(), json!([tag]));
+        op.insert("summary".to_string(), json!(summary));
+        op.insert("parameters".to_string(), json!([]));
+        op.insert("responses".to_string(), json!({}));
+        Self(op)
+    }
+
+    fn push_parameter(&mut self, parameter: Value) {
+        if let Some(Value::Array(parameters)) = self.0.get_mut("parameters") {
+            parameters.push(parameter);
+        }
+    }
+
+    /// 引用 `components.parameters` 中的扩展请求头
+    fn headers(mut self, names: &[&str]) -> Self {
+        for name in names {
+            self.push_parameter(reference("parameters", name));
+        }
+        self
+    }
+
+    fn path_param(mut self, name: &str, schema_type: &str, description: &str) -> Self {
+        self.push_parameter(json!({
+            "name": name,
+            "in": "path",
+            "required": true,
+            "description": description,
+            "schema": { "type": schema_type },
+        }));
+        self
+    }
+
+    fn query(mut self, name: &str, schema: Value, description: &str) -> Self {
+        self.push_parameter(json!({
+            "name": name,
+            "in": "query",
+            "required": false,
+            "description": description,
+            "schema": schema,
+        }));
+        self
+    }
+
+    fn body(mut self, schema_name: &str, required: bool) -> Self {
+        self.0.insert(
+            "requestBody".to_string(),
+            json!({
+                "required": required,
+                "content": { "application/json": { "schema": schema(schema_name) } },
+            }),
+        );
+        self
+    }
+
+    /// 添加响应，`content` 为 (媒体类型, schema) 列表
+    fn response(mut self, status: &str, description: &str, content: &[(&str, Value)]) -> Self {
+        let mut response = json!({ "description": description });
+        if !content.is_empty() {
+            let content: Map<String, Value> = content
+                .iter()
+                .map(|(media, schema)| (media.to_string(), json!({ "schema": schema })))
+                .collect();
+            response["content"] = Value::Object(content);
+        }
+        if let Some(Value::Object(responses)) = self.0.get_mut("responses") {
+            responses.insert(status.to_string(), response);
+        }
+        self
+    }
+
+    fn json(self, status: &str, description: &str, schema_name: &str) -> Self {
+        self.response(
+            status,
+            description,
+            &[("application/json", schema(schema_name))],
+        )
+    }
+
+    /// 为成功响应引用 `components.headers` 中的扩展响应头
+    fn response_headers(mut self, status: &str, names: &[&str]) -> Self {
+        if let Some(response) = self
+            .0
+            .get_mut("responses")
+            .and_then(|responses| responses.get_mut(status))
+        {
+            let headers: Map<String, Value> = names
+                .iter()
+                .map(|name| (name.to_string(), reference("headers", name)))
+                .collect();
+            response["headers"] = Value::Object(headers);
+        }
+        self
+    }
+
+    /// Anthropic 格式的错误响应
+    fn errors(self, statuses: &[&str]) -> Self {
+        statuses.iter().fold(self, |op, status| {
+            op.json(status, "错误（Anthropic 错误格式）", "ErrorResponse")
+        })
+    }
+
+    fn security(mut self, schemes: &[&str]) -> Self {
+        let security: Vec<Value> = schemes
+            .iter()
+            .map(|scheme| {
+                let mut requirement = Map::new();
+                requirement.insert(scheme.to_string(), json!([]));
+                Value::Object(requirement)
+            })
+            .collect();
+        self.0.insert("security".to_string(), json!(security));
+        self
+    }
+
+    fn no_auth(mut self) -> Self {
+        self.0.insert("security".to_string(), json!([]));
+        self
+    }
+}
+
+/// 路径集合的构建器
+#[derive(Default)]
+struct Paths(Map<String, Value>);
+
+impl Paths {
+    fn add(&mut self, method: &str, path: &str, operation: Operation) {
+        let item = self.0.entry(path.to_string()).or_insert_with(|| json!({}));
+        item[method] = Value::Object(operation.0);
+    }
+}
+
+/// 消息接口的请求头与响应头
+const MESSAGE_HEADERS: &[&str] = &[
+    "x-kiro-options",
+    "x-kiro-deadline-ms",
+    "x-kiro-priority",
+    "x-kiro-converter",
+    "x-kiro-profile-arn",
+    "x-kiro-seed",
+    "x-request-id",
+];
+const MESSAGE_RESPONSE_HEADERS: &[&str] = &[
+    "x-request-id",
+    "x-kiro-model-substitution",
+    "x-kiro-max-tokens-clamped",
+    "x-kiro-upstream-attempts",
+    "x-kiro-sse-transcript",
+    "x-kiro-frames-decoded",
+    "x-kiro-bytes-received",
+    "x-kiro-bytes-skipped",
+    "x-kiro-parse-errors",
+    "x-kiro-upstream-duration-ms",
+];
+
+/// WebSocket 消息接口（`/v1/messages/ws` 与 `/cc/v1/messages/ws`）
+fn messages_ws_operation(operation_id: &str) -> Operation {
+    Operation::new(
+        operation_id,
+        "messages",
+        "创建消息（WebSocket 流式传输）：升级后发送一条 MessagesRequest 文本消息，\
+         每个流式事件以一条文本消息下发（内容同 SSE 的 data），结束后关闭连接",
+    )
+    .headers(MESSAGE_HEADERS)
+    .response("101", "切换到 WebSocket 协议", &[])
+    .errors(&["400", "401", "429"])
+}
+
+/// 消息接口（`/v1/messages` 与 `/cc/v1/messages`）
+fn messages_operation(operation_id: &str, summary: &str) -> Operation {
+    Operation::new(operation_id, "messages", summary)
+        .headers(MESSAGE_HEADERS)
+        .body("MessagesRequest", true)
+        .response(
+            "200",
+            "非流式返回消息；`stream: true` 时返回 SSE 事件流",
+            &[
+                ("application/json", schema("Message")),
+                ("text/event-stream", json!({ "type": "string" })),
+            ],
+        )
+        .response_headers("200", MESSAGE_RESPONSE_HEADERS)
+        .errors(&[
+            "400", "401", "403", "413", "429", "500", "502", "503", "529",
+        ])
+}
+
+fn count_tokens_operation(operation_id: &str) -> Operation {
+    Operation::new(
+        operation_id,
+        "messages",
+        "计算请求的输入 tokens（本地估算）",
+    )
+    .body("CountTokensRequest", true)
+    .json("200", "输入 tokens", "CountTokensResponse")
+    .errors(&["400", "401"])
+}
+
+fn api_paths(paths: &mut Paths) {
+    paths.add(
+        "get",
+        "/v1/models",
+        Operation::new("listModels", "models", "获取可用模型列表")
+            .json("200", "模型列表", "ModelsResponse")
+            .errors(&["401"]),
+    );
+    paths.add(
+        "post",
+        "/v1/messages",
+        messages_operation("createMessage", "创建消息"),
+    );
+    paths.add(
+        "get",
+        "/v1/messages/ws",
+        messages_ws_operation("createMessageWebSocket"),
+    );
+    paths.add(
+        "post",
+        "/v1/messages/count_tokens",
+        count_tokens_operation("countTokens"),
+    );
+    paths.add(
+        "post",
+        "/cc/v1/messages",
+        messages_operation(
+            "createMessageClaudeCode",
+            "创建消息（Claude Code 兼容：流式响应缓冲到上游结束，message_start 中的 input_tokens 准确）",
+        ),
+    );
+    paths.add(
+        "get",
+        "/cc/v1/messages/ws",
+        messages_ws_operation("createMessageWebSocketClaudeCode"),
+    );
+    paths.add(
+        "post",
+        "/cc/v1/messages/count_tokens",
+        count_tokens_operation("countTokensClaudeCode"),
+    );
+    paths.add(
+        "post",
+        "/v1/chat/completions",
+        Operation::new("createChatCompletion", "openai", "OpenAI 兼容的对话补全")
+            .headers(MESSAGE_HEADERS)
+            .body("ChatCompletionRequest", true)
+            .response(
+                "200",
+                "非流式返回 chat.completion；`stream: true` 时返回 SSE 事件流",
+                &[
+                    ("application/json", json!({ "type": "object" })),
+                    ("text/event-stream", json!({ "type": "string" })),
+                ],
+            )
+            .errors(&["400", "401", "429", "502"]),
+    );
+    paths.add(
+        "get",
+        "/metrics",
+        Operation::new(
+            "getMetrics",
+            "metrics",
+            "Prometheus 指标（配置了 metrics.apiKey 时需要认证）",
+        )
+        .security(&["metricsApiKey", "metricsBearer"])
+        .response(
+            "200",
+            "Prometheus 文本格式",
+            &[("text/plain", json!({ "type": "string" }))],
+        ),
+    );
+    paths.add(
+        "get",
+        OPENAPI_PATH,
+        Operation::new("getOpenApi", "meta", "本文档")
+            .no_auth()
+            .response(
+                "200",
+                "OpenAPI 3.1 文档",
+                &[("application/json", json!({ "type": "object" }))],
+            ),
+    );
+}
+
+fn admin(operation_id: &str, summary: &str) -> Operation {
+    Operation::new(operation_id, "admin", summary)
+        .security(&["adminApiKey", "adminBearer"])
+        .errors(&["400", "401", "404", "500"])
+}
+
+/// 凭据 ID 路径参数
+fn credential(operation: Operation) -> Operation {
+    operation.path_param("id", "integer", "凭据 ID")
+}
+
+fn admin_paths(paths: &mut Paths) {
+    const ADMIN: &str = "/api/admin";
+    let mut add = |method: &str, path: &str, operation: Operation| {
+        paths.add(method, &format!("{}{}", ADMIN, path), operation);
+    };
+    add(
+        "get",
+        "/credentials",
+        admin("listCredentials", "获取所有凭据状态").json(
+            "200",
+            "凭据状态",
+            "CredentialsStatusResponse",
+        ),
+    );
+    add(
+        "post",
+        "/credentials",
+        admin("addCredential", "添加新凭据")
+            .body("AddCredentialRequest", true)
+            .json("200", "添加结果", "AddCredentialResponse"),
+    );
+    add(
+        "delete",
+        "/credentials/{id}",
+        credential(admin("deleteCredential", "删除凭据")).json(
+            "200",
+            "操作结果",
+            "SuccessResponse",
+        ),
+    );
+    add(
+        "post",
+        "/credentials/{id}/disabled",
+        credential(admin("setCredentialDisabled", "设置凭据禁用状态"))
+            .body("SetDisabledRequest", true)
+            .json("200", "操作结果", "SuccessResponse"),
+    );
+    add(
+        "post",
+        "/credentials/{id}/priority",
+        credential(admin("setCredentialPriority", "设置凭据优先级"))
+            .body("SetPriorityRequest", true)
+            .json("200", "操作结果", "SuccessResponse"),
+    );
+    add(
+        "post",
+        "/credentials/{id}/reset",
+        credential(admin("resetCredentialFailures", "重置失败计数")).json(
+            "200",
+            "操作结果",
+            "SuccessResponse",
+        ),
+    );
+    add(
+        "post",
+        "/credentials/{id}/refresh",
+        credential(admin("refreshCredentialToken", "立即刷新凭据 Token")).json(
+            "200",
+            "刷新结果",
+            "AdminObject",
+        ),
+    );
+    add(
+        "get",
+        "/credentials/{id}/balance",
+        credential(admin("getCredentialBalance", "获取凭据余额")).json(
+            "200",
+            "余额",
+            "AdminObject",
+        ),
+    );
+    add(
+        "get",
+        "/config/load-balancing",
+        admin("getLoadBalancingMode", "获取负载均衡模式").json(
+            "200",
+            "当前模式",
+            "LoadBalancingMode",
+        ),
+    );
+    add(
+        "put",
+        "/config/load-balancing",
+        admin("setLoadBalancingMode", "设置负载均衡模式")
+            .body("LoadBalancingMode", true)
+            .json("200", "当前模式", "LoadBalancingMode"),
+    );
+    add(
+        "get",
+        "/config/thinking-extraction",
+        admin(
+            "getThinkingExtraction",
+            "获取运行时设置的 thinking 提取开关",
+        )
+        .json("200", "按模型的开关", "AdminObject"),
+    );
+    add(
+        "put",
+        "/config/thinking-extraction",
+        admin("setThinkingExtraction", "设置模型的 thinking 提取开关")
+            .body("SetThinkingExtractionRequest", true)
+            .json("200", "按模型的开关", "AdminObject"),
+    );
+    add(
+        "get",
+        "/status",
+        admin("getStatus", "获取运行状态").json("200", "运行状态", "AdminObject"),
+    );
+    add(
+        "get",
+        "/stats/stream",
+        admin("getStreamStats", "获取流式响应统计").json("200", "统计", "AdminObject"),
+    );
+    add(
+        "get",
+        "/stats/errors",
+        admin("getRecentErrors", "获取最近失败的上游尝试").json("200", "最近的失败", "AdminObject"),
+    );
+    add(
+        "get",
+        "/reports/usage",
+        admin("listUsageReports", "获取有用量报表的日期").json("200", "日期列表", "AdminObject"),
+    );
+    add(
+        "get",
+        "/reports/usage/{date}",
+        admin("getUsageReport", "获取单日用量报表")
+            .path_param("date", "string", "日期（YYYY-MM-DD）")
+            .query(
+                "format",
+                json!({ "enum": ["json", "csv"] }),
+                "`csv` 导出 CSV",
+            )
+            .response(
+                "200",
+                "用量报表",
+                &[
+                    ("application/json", schema("AdminObject")),
+                    ("text/csv", json!({ "type": "string" })),
+                ],
+            ),
+    );
+    add(
+        "get",
+        "/dead-letters",
+        admin("listDeadLetters", "查询转换失败的请求")
+            .query("kind", json!({ "type": "string" }), "按错误类别过滤")
+            .query("limit", json!({ "type": "integer" }), "最多返回的条数")
+            .json("200", "死信记录", "AdminObject"),
+    );
+    add(
+        "delete",
+        "/dead-letters",
+        admin("clearDeadLetters", "清空死信队列").json("200", "操作结果", "SuccessResponse"),
+    );
+    add(
+        "get",
+        "/dead-letters/{id}",
+        admin("getDeadLetter", "获取单条死信记录")
+            .path_param("id", "string", "记录 ID")
+            .json("200", "死信记录", "AdminObject"),
+    );
+    add(
+        "get",
+        "/sse-transcripts",
+        admin("listSseTranscripts", "列出 SSE 会话记录")
+            .query("limit", json!({ "type": "integer" }), "最多返回的条数")
+            .json("200", "记录列表", "AdminObject"),
+    );
+    add(
+        "get",
+        "/sse-transcripts/{id}",
+        admin("getSseTranscript", "下载 SSE 会话记录（逐帧解压）")
+            .path_param("id", "string", "记录 ID（响应头 `x-kiro-sse-transcript`）")
+            .response(
+                "200",
+                "原始 SSE 字节流",
+                &[("text/event-stream", json!({ "type": "string" }))],
+            ),
+    );
+    add(
+        "get",
+        "/conversations/{id}/render",
+        admin("renderConversation", "渲染会话记录")
+            .path_param("id", "string", "会话 ID")
+            .query(
+                "format",
+                json!({ "enum": ["html", "markdown"] }),
+                "默认 HTML",
+            )
+            .response(
+                "200",
+                "渲染结果",
+                &[
+                    ("text/html", json!({ "type": "string" })),
+                    ("text/markdown", json!({ "type": "string" })),
+                ],
+            ),
+    );
+    add(
+        "post",
+        "/models/{id}/probe",
+        admin("probeModel", "向模型发送探测请求，报告能力与首个增量耗时")
+            .path_param("id", "string", "模型 ID")
+            .json("200", "探测结果", "AdminObject"),
+    );
+    add(
+        "post",
+        "/replay/{id}",
+        admin(
+            "replayRequest",
+            "重放死信记录中的请求，返回与原始结果的差异",
+        )
+        .path_param("id", "string", "死信记录 ID")
+        .body("ReplayRequest", false)
+        .json("200", "原始与重放的结果", "AdminObject"),
+    );
+}
+
+/// 扩展请求头
+fn header_parameters() -> Value {
+    let header = |name: &str, schema: Value, description: &str| json!({ "name": name, "in": "header", "required": false, "description": description, "schema": schema });
+    json!({
+        "x-kiro-options": header(
+            "x-kiro-options",
+            json!({ "type": "string" }),
+            "JSON 对象，按请求覆盖扩展行为：thinking（blocks/text）、coalesceDeltas、strictSse、deadlineMs、priority、converter、unpinModel；未知选项返回 400",
+        ),
+        "x-kiro-deadline-ms": header(
+            "x-kiro-deadline-ms",
+            json!({ "type": "integer", "minimum": 1 }),
+            "墙钟时间上限（毫秒）；到达后返回已生成的部分，stop_reason 为 max_tokens 并带 deadline_exceeded: true",
+        ),
+        "x-kiro-priority": header(
+            "x-kiro-priority",
+            json!({ "enum": ["interactive", "batch"] }),
+            "并发受限时的排队优先级",
+        ),
+        "x-kiro-converter": header(
+            "x-kiro-converter",
+            json!({ "enum": ["stable", "experimental"] }),
+            "转换器版本，未指定时按 canary.percentage 选择",
+        ),
+        "x-kiro-profile-arn": header(
+            "x-kiro-profile-arn",
+            json!({ "type": "string" }),
+            "使用的 Kiro profile ARN，必须在 profiles.allowed 中",
+        ),
+        "x-kiro-seed": header(
+            "x-kiro-seed",
+            json!({ "type": "integer", "minimum": 0 }),
+            "采样种子（优先于请求体 seed），只用于复现代理侧的随机选择",
+        ),
+        "x-request-id": header(
+            "x-request-id",
+            json!({ "type": "string", "maxLength": 128 }),
+            "请求 ID，未提供时生成，通过同名响应头返回",
+        ),
+    })
+}
+
+/// 扩展响应头
+fn response_headers() -> Value {
+    let header = |description: &str, schema_type: &str| json!({ "description": description, "schema": { "type": schema_type } });
+    json!({
+        "x-request-id": header("请求 ID", "string"),
+        "x-kiro-model-substitution": header(
+            "模型被映射为更低档次或被降级时的提示，如 `requested=...; served=...; reason=budget`",
+            "string",
+        ),
+        "x-kiro-max-tokens-clamped": header(
+            "max_tokens 被下调到模型输出上限，如 `requested=200000; applied=64000`",
+            "string",
+        ),
+        "x-kiro-upstream-attempts": header(
+            "上游尝试次数与失败原因，如 `3; reasons=throttled,unauthorized`（upstream.attemptsHeader）",
+            "string",
+        ),
+        "x-kiro-sse-transcript": header("SSE 会话记录 ID（logging.sseTranscripts.enabled）", "string"),
+        "x-kiro-frames-decoded": header("解码的上游帧数（非流式，stream.decoderStats）", "integer"),
+        "x-kiro-bytes-received": header("收到的上游字节数（非流式，stream.decoderStats）", "integer"),
+        "x-kiro-bytes-skipped": header("跳过的上游字节数（非流式，stream.decoderStats）", "integer"),
+        "x-kiro-parse-errors": header("上游帧解析错误数（非流式，stream.decoderStats）", "integer"),
+        "x-kiro-upstream-duration-ms": header("上游响应耗时（非流式，stream.decoderStats）", "integer"),
+    })
+}
+
+fn schemas() -> Value {
+    let object = |description: &str| json!({ "type": "object", "description": description });
+    json!({
+        "ErrorResponse": {
+            "type": "object",
+            "required": ["type", "error"],
+            "properties": {
+                "type": { "const": "error" },
+                "error": {
+                    "type": "object",
+                    "required": ["type", "message"],
+                    "properties": {
+                        "type": {
+                            "enum": [
+                                "invalid_request_error", "authentication_error", "permission_error",
+                                "not_found_error", "request_too_large", "rate_limit_error",
+                                "api_error", "overloaded_error"
+                            ]
+                        },
+                        "message": { "type": "string" },
+                    },
+                },
+            },
+        },
+        "MessagesRequest": {
+            "type": "object",
+            "description": "Anthropic Messages API 请求，下列字段之外与官方 API 相同",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": { "type": "string" },
+                "max_tokens": {
+                    "type": "integer",
+                    "minimum": 1,
+                    "description": "可省略，省略时使用 maxTokens.default；超出模型输出上限时按 maxTokens.overLimit 下调或拒绝",
+                },
+                "messages": { "type": "array", "items": { "type": "object" } },
+                "stream": { "type": "boolean", "default": false },
+                "system": { "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "object" } }] },
+                "tools": { "type": "array", "items": { "type": "object" } },
+                "tool_choice": { "type": "object" },
+                "thinking": { "type": "object" },
+                "output_config": { "type": "object" },
+                "metadata": { "type": "object" },
+                "stop_sequences": { "type": "array", "items": { "type": "string" } },
+                "parent_message_id": {
+                    "type": "string",
+                    "description": "扩展字段：从会话中已有的助手消息分支，messages 只需包含新的轮次",
+                },
+                "stream_options": {
+                    "type": "object",
+                    "description": "扩展字段：流式选项（周期性用量事件）",
+                },
+                "workspace": {
+                    "type": "object",
+                    "description": "扩展字段：工作区上下文（当前文件、光标、打开的文件）",
+                },
+                "seed": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "扩展字段：采样种子，不转发给上游",
+                },
+            },
+        },
+        "Message": {
+            "type": "object",
+            "description": "Anthropic 消息响应",
+            "required": ["id", "type", "role", "content", "model", "stop_reason", "usage"],
+            "properties": {
+                "id": { "type": "string" },
+                "type": { "const": "message" },
+                "role": { "const": "assistant" },
+                "content": { "type": "array", "items": { "type": "object" } },
+                "model": { "type": "string" },
+                "stop_reason": { "type": ["string", "null"] },
+                "stop_sequence": { "type": ["string", "null"] },
+                "usage": {
+                    "type": "object",
+                    "properties": {
+                        "kiro_estimated_cost": {
+                            "type": "number",
+                            "description": "扩展字段：按 pricing 配置估算的费用（未配置该模型单价时省略）",
+                        },
+                    },
+                },
+                "deadline_exceeded": {
+                    "type": "boolean",
+                    "description": "扩展字段：到达 x-kiro-deadline-ms 后提前结束",
+                },
+            },
+        },
+        "CountTokensRequest": {
+            "type": "object",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": { "type": "string" },
+                "messages": { "type": "array", "items": { "type": "object" } },
+                "system": { "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "object" } }] },
+                "tools": { "type": "array", "items": { "type": "object" } },
+            },
+        },
+        "CountTokensResponse": {
+            "type": "object",
+            "required": ["input_tokens"],
+            "properties": { "input_tokens": { "type": "integer" } },
+        },
+        "ModelsResponse": {
+            "type": "object",
+            "required": ["object", "data"],
+            "properties": {
+                "object": { "const": "list" },
+                "data": { "type": "array", "items": schema("Model") },
+            },
+        },
+        "Model": {
+            "type": "object",
+            "required": ["id", "display_name", "type", "max_tokens", "thinking"],
+            "properties": {
+                "id": { "type": "string" },
+                "object": { "type": "string" },
+                "created": { "type": "integer" },
+                "owned_by": { "type": "string" },
+                "display_name": { "type": "string" },
+                "type": { "type": "string" },
+                "max_tokens": { "type": "integer", "description": "模型的输出上限" },
+                "thinking": { "type": "boolean", "description": "是否支持 thinking 模式" },
+            },
+        },
+        "ChatCompletionRequest": {
+            "type": "object",
+            "description": "OpenAI Chat Completions 请求，转换为 Anthropic 请求处理",
+            "required": ["model", "messages"],
+            "properties": {
+                "model": { "type": "string" },
+                "messages": { "type": "array", "items": { "type": "object" } },
+                "stream": { "type": "boolean", "default": false },
+                "max_tokens": { "type": "integer" },
+                "max_completion_tokens": { "type": "integer" },
+                "stop": { "oneOf": [{ "type": "string" }, { "type": "array", "items": { "type": "string" } }] },
+                "tools": { "type": "array", "items": { "type": "object" } },
+                "tool_choice": {},
+                "stream_options": { "type": "object" },
+                "seed": { "type": "integer", "minimum": 0 },
+                "user": { "type": "string", "description": "作为 metadata.user_id 传递" },
+            },
+        },
+        "SuccessResponse": {
+            "type": "object",
+            "properties": {
+                "success": { "type": "boolean" },
+                "message": { "type": "string" },
+            },
+        },
+        "AdminObject": object("Admin API 响应，结构见 README 的 Admin API 说明"),
+        "CredentialsStatusResponse": {
+            "type": "object",
+            "properties": {
+                "total": { "type": "integer" },
+                "available": { "type": "integer" },
+                "currentId": { "type": "integer" },
+                "credentials": { "type": "array", "items": { "type": "object" } },
+            },
+        },
+        "AddCredentialRequest": {
+            "type": "object",
+            "required": ["refreshToken"],
+            "properties": {
+                "refreshToken": { "type": "string" },
+                "authMethod": { "type": "string", "default": "social" },
+                "clientId": { "type": "string" },
+                "clientSecret": { "type": "string" },
+                "priority": { "type": "integer", "default": 0 },
+                "region": { "type": "string" },
+                "authRegion": { "type": "string" },
+                "apiRegion": { "type": "string" },
+                "machineId": { "type": "string" },
+                "email": { "type": "string" },
+                "proxyUrl": { "type": "string" },
+                "proxyUsername": { "type": "string" },
+                "proxyPassword": { "type": "string" },
+            },
+        },
+        "AddCredentialResponse": {
+            "type": "object",
+            "properties": {
+                "success": { "type": "boolean" },
+                "message": { "type": "string" },
+                "credentialId": { "type": "integer" },
+                "email": { "type": "string" },
+            },
+        },
+        "SetDisabledRequest": {
+            "type": "object",
+            "required": ["disabled"],
+            "properties": { "disabled": { "type": "boolean" } },
+        },
+        "SetPriorityRequest": {
+            "type": "object",
+            "required": ["priority"],
+            "properties": { "priority": { "type": "integer", "minimum": 0 } },
+        },
+        "LoadBalancingMode": {
+            "type": "object",
+            "required": ["mode"],
+            "properties": { "mode": { "enum": ["priority", "balanced"] } },
+        },
+        "SetThinkingExtractionRequest": {
+            "type": "object",
+            "required": ["model"],
+            "properties": {
+                "model": { "type": "string" },
+                "enabled": { "type": ["boolean", "null"], "description": "null 表示恢复配置文件中的设置" },
+            },
+        },
+        "ReplayRequest": {
+            "type": "object",
+            "properties": {
+                "model": { "type": "string" },
+                "thinking": { "type": "object" },
+                "converter": { "enum": ["stable", "experimental"] },
+            },
+        },
+    })
+}
+
+/// 构建 OpenAPI 文档
+pub fn document() -> Value {
+    let mut paths = Paths::default();
+    api_paths(&mut paths);
+    admin_paths(&mut paths);
+    json!({
+        "openapi": "3.1.0",
+        "info": {
+            "title": "kiro-rs",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Kiro 的 Anthropic Claude API 兼容代理。错误统一使用 Anthropic 错误格式；`x-kiro-*` 为本代理的扩展头",
+        },
+        "tags": [
+            { "name": "messages", "description": "Anthropic Messages API（/v1 与 Claude Code 兼容的 /cc/v1）" },
+            { "name": "models" },
+            { "name": "openai", "description": "OpenAI 兼容端点" },
+            { "name": "metrics" },
+            { "name": "admin", "description": "Admin API（需要 adminApiKey）" },
+            { "name": "meta" },
+        ],
+        "security": [{ "apiKey": [] }, { "bearer": [] }],
+        "paths": Value::Object(paths.0),
+        "components": {
+            "securitySchemes": {
+                "apiKey": { "type": "apiKey", "in": "header", "name": "x-api-key" },
+                "bearer": { "type": "http", "scheme": "bearer" },
+                "adminApiKey": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "x-api-key",
+                    "description": "配置项 adminApiKey",
+                },
+                "adminBearer": { "type": "http", "scheme": "bearer", "description": "配置项 adminApiKey" },
+                "metricsApiKey": {
+                    "type": "apiKey",
+                    "in": "header",
+                    "name": "x-api-key",
+                    "description": "配置项 metrics.apiKey，未配置时不需要认证",
+                },
+                "metricsBearer": {
+                    "type": "http",
+                    "scheme": "bearer",
+                    "description": "配置项 metrics.apiKey，未配置时不需要认证",
+                },
+            },
+            "parameters": header_parameters(),
+            "headers": response_headers(),
+            "schemas": schemas(),
+        },
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 收集文档中的所有 `$ref`
+    fn collect_refs<'a>(value: &'a Value, refs: &mut Vec<&'a str>) {
+        match value {
+            Value::Object(map) => {
+                for (key, value) in map {
+                    match (key.as_str(), value) {
+                        ("$ref", Value::String(target)) => refs.push(target),
+                        _ => collect_refs(value, refs),
+                    }
+                }
+            }
+            Value::Array(items) => items.iter().for_each(|item| collect_refs(item, refs)),
+            _ => {}
+        }
+    }
+
+    #[test]
+    fn test_document_covers_endpoints() {
+        let doc = document();
+        assert_eq!(doc["openapi"], "3.1.0");
+        for (method, path) in [
+            ("get", "/v1/models"),
+            ("post", "/v1/messages"),
+            ("post", "/v1/messages/count_tokens"),
+            ("get", "/v1/messages/ws"),
+            ("post", "/cc/v1/messages"),
+            ("post", "/cc/v1/messages/count_tokens"),
+            ("post", "/v1/chat/completions"),
+            ("get", "/metrics"),
+            ("get", "/openapi.json"),
+            ("get", "/api/admin/credentials"),
+            ("put", "/api/admin/config/load-balancing"),
+            ("post", "/api/admin/replay/{id}"),
+        ] {
+            assert!(
+                doc["paths"][path][method].is_object(),
+                "{} {}",
+                method,
+                path
+            );
+        }
+
+        let messages = &doc["paths"]["/v1/messages"]["post"];
+        let headers = &messages["responses"]["200"]["headers"];
+        assert!(headers["x-kiro-max-tokens-clamped"].is_object());
+        assert_eq!(
+            messages["responses"]["529"]["content"]["application/json"]["schema"]["$ref"],
+            "#/components/schemas/ErrorResponse"
+        );
+    }
+
+    #[test]
+    fn test_document_is_consistent() {
+        let doc = document();
+
+        // 所有引用都能解析
+        let mut refs = Vec::new();
+        collect_refs(&doc, &mut refs);
+        assert!(!refs.is_empty());
+        for target in refs {
+            let pointer = target.strip_prefix('#').unwrap();
+            assert!(doc.pointer(pointer).is_some(), "无法解析的引用: {}", target);
+        }
+
+        // operationId 唯一
+        let mut ids = std::collections::HashSet::new();
+        for item in doc["paths"].as_object().unwrap().values() {
+            for operation in item.as_object().unwrap().values() {
+                let id = operation["operationId"].as_str().unwrap();
+                assert!(ids.insert(id), "operationId 重复: {}", id);
+            }
+        }
+    }
+}
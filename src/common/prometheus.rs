@@ -0,0 +1,358 @@
+//! Prometheus 指标导出
+//!
+//! `GET /metrics` 以 Prometheus 文本格式导出请求数与耗时、上游失败次数、tokens 用量和各凭据的使用情况，
+//! 均为进程启动以来的累计值（gauge 除外）。多实例部署时由 Prometheus 分别抓取后在 Grafana 中聚合
+
+use std::fmt::Write;
+use std::sync::Arc;
+use std::time::Instant;
+
+use axum::{
+    Router,
+    body::Body,
+    extract::{MatchedPath, Request, State},
+    http::{StatusCode, header},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::get,
+};
+use futures::StreamExt;
+
+use crate::kiro::attempt::AttemptReason;
+use crate::kiro::token_manager::{CredentialEntrySnapshot, MultiTokenManager};
+
+use super::auth;
+use super::metrics::{self, LATENCY_BUCKETS};
+
+/// Prometheus 文本格式的 Content-Type
+const CONTENT_TYPE: &str = "text/plain; version=0.0.4; charset=utf-8";
+
+/// 指标路由状态
+#[derive(Clone)]
+struct MetricsState {
+    token_manager: Arc<MultiTokenManager>,
+    api_key: Option<String>,
+}
+
+/// 创建 `/metrics` 路由，配置了 `api_key` 时要求抓取方携带该密钥
+pub fn create_router(token_manager: Arc<MultiTokenManager>, api_key: Option<String>) -> Router {
+    let state = MetricsState {
+        token_manager,
+        api_key: api_key.filter(|key| !key.trim().is_empty()),
+    };
+    Router::new()
+        .route("/metrics", get(get_metrics))
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            metrics_auth_middleware,
+        ))
+        .with_state(state)
+}
+
+async fn metrics_auth_middleware(
+    State(state): State<MetricsState>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    if let Some(expected) = &state.api_key {
+        let authorized = auth::extract_api_key(&request)
+            .is_some_and(|key| auth::constant_time_eq(&key, expected));
+        if !authorized {
+            return StatusCode::UNAUTHORIZED.into_response();
+        }
+    }
+    next.run(request).await
+}
+
+async fn get_metrics(State(state): State<MetricsState>) -> impl IntoResponse {
+    let credentials = state.token_manager.snapshot().entries;
+    ([(header::CONTENT_TYPE, CONTENT_TYPE)], render(&credentials))
+}
+
+/// 统计请求数、耗时与正在处理的请求数的中间件（按匹配到的路由模板区分端点）
+///
+/// 流式响应在响应体发送完毕（或客户端断开）后才计为处理结束
+pub async fn track_requests(request: Request<Body>, next: Next) -> Response {
+    let endpoint = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string());
+    let guard = metrics::in_flight().start();
+    let started = Instant::now();
+    let response = next.run(request).await;
+    if let Some(endpoint) = endpoint {
+        metrics::requests().record(&endpoint, response.status().as_u16(), started.elapsed());
+    }
+
+    let streaming = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .is_some_and(|value| value.as_bytes().starts_with(b"text/event-stream"));
+    if !streaming {
+        return response;
+    }
+    response.map(|body| {
+        Body::from_stream(body.into_data_stream().inspect(move |_| {
+            let _ = &guard;
+        }))
+    })
+}
+
+/// 转义标签值
+fn escape_label(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// 写入指标头（HELP 与 TYPE）
+fn describe(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+/// 渲染全部指标
+fn render(credentials: &[CredentialEntrySnapshot]) -> String {
+    let mut out = String::new();
+
+    let requests = metrics::requests().snapshot();
+    describe(
+        &mut out,
+        "kiro_requests_total",
+        "counter",
+        "按端点与状态码统计的请求数",
+    );
+    for (endpoint, stats) in &requests {
+        for (status, count) in &stats.statuses {
+            let _ = writeln!(
+                out,
+                "kiro_requests_total{{endpoint=\"{}\",status=\"{}\"}} {}",
+                escape_label(endpoint),
+                status,
+                count
+            );
+        }
+    }
+
+    describe(
+        &mut out,
+        "kiro_request_duration_seconds",
+        "histogram",
+        "收到请求到返回响应头的耗时（流式响应即首字节时间）",
+    );
+    for (endpoint, stats) in &requests {
+        let endpoint = escape_label(endpoint);
+        let mut cumulative = 0;
+        for (bound, count) in LATENCY_BUCKETS.iter().zip(&stats.buckets) {
+            cumulative += count;
+            let _ = writeln!(
+                out,
+                "kiro_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"{}\"}} {}",
+                endpoint, bound, cumulative
+            );
+        }
+        let _ = writeln!(
+            out,
+            "kiro_request_duration_seconds_bucket{{endpoint=\"{}\",le=\"+Inf\"}} {}",
+            endpoint,
+            stats.count()
+        );
+        let _ = writeln!(
+            out,
+            "kiro_request_duration_seconds_sum{{endpoint=\"{}\"}} {}",
+            endpoint, stats.duration_sum
+        );
+        let _ = writeln!(
+            out,
+            "kiro_request_duration_seconds_count{{endpoint=\"{}\"}} {}",
+            endpoint,
+            stats.count()
+        );
+    }
+
+    describe(
+        &mut out,
+        "kiro_upstream_failures_total",
+        "counter",
+        "按原因统计的上游失败尝试次数",
+    );
+    let failures = metrics::upstream_failures();
+    for reason in AttemptReason::ALL {
+        let _ = writeln!(
+            out,
+            "kiro_upstream_failures_total{{reason=\"{}\"}} {}",
+            reason.code(),
+            failures.count(reason)
+        );
+    }
+
+    describe(
+        &mut out,
+        "kiro_tokens_total",
+        "counter",
+        "按模型统计的 tokens 用量",
+    );
+    for (model, counts) in metrics::tokens().snapshot() {
+        let model = escape_label(&model);
+        let _ = writeln!(
+            out,
+            "kiro_tokens_total{{model=\"{}\",direction=\"input\"}} {}",
+            model, counts.input
+        );
+        let _ = writeln!(
+            out,
+            "kiro_tokens_total{{model=\"{}\",direction=\"output\"}} {}",
+            model, counts.output
+        );
+    }
+
+    let keys = metrics::api_keys().snapshot();
+    describe(
+        &mut out,
+        "kiro_key_requests_total",
+        "counter",
+        "按 API Key 名称统计的请求数",
+    );
+    for (name, counts) in &keys {
+        let _ = writeln!(
+            out,
+            "kiro_key_requests_total{{key=\"{}\"}} {}",
+            escape_label(name),
+            counts.requests
+        );
+    }
+    describe(
+        &mut out,
+        "kiro_key_rate_limited_total",
+        "counter",
+        "按 API Key 名称统计的限流拒绝次数",
+    );
+    for (name, counts) in &keys {
+        let _ = writeln!(
+            out,
+            "kiro_key_rate_limited_total{{key=\"{}\"}} {}",
+            escape_label(name),
+            counts.rate_limited
+        );
+    }
+
+    let streams = metrics::active_streams().snapshot();
+    describe(
+        &mut out,
+        "kiro_active_streams",
+        "gauge",
+        "正在转发的流式响应数",
+    );
+    let _ = writeln!(out, "kiro_active_streams {}", streams.active);
+    describe(
+        &mut out,
+        "kiro_streams_cancelled_total",
+        "counter",
+        "客户端断开而取消上游请求的流式响应数",
+    );
+    let _ = writeln!(out, "kiro_streams_cancelled_total {}", streams.cancelled);
+    describe(
+        &mut out,
+        "kiro_in_flight_requests",
+        "gauge",
+        "正在处理的 API 请求数",
+    );
+    let _ = writeln!(
+        out,
+        "kiro_in_flight_requests {}",
+        metrics::in_flight().active()
+    );
+
+    describe(
+        &mut out,
+        "kiro_credential_requests_total",
+        "counter",
+        "各凭据的 API 调用成功次数",
+    );
+    for entry in credentials {
+        let _ = writeln!(
+            out,
+            "kiro_credential_requests_total{{credential=\"{}\"}} {}",
+            entry.id, entry.success_count
+        );
+    }
+    describe(
+        &mut out,
+        "kiro_credential_consecutive_failures",
+        "gauge",
+        "各凭据的连续失败次数",
+    );
+    for entry in credentials {
+        let _ = writeln!(
+            out,
+            "kiro_credential_consecutive_failures{{credential=\"{}\"}} {}",
+            entry.id, entry.failure_count
+        );
+    }
+    describe(
+        &mut out,
+        "kiro_credential_disabled",
+        "gauge",
+        "凭据是否被禁用（1 为禁用）",
+    );
+    for entry in credentials {
+        let _ = writeln!(
+            out,
+            "kiro_credential_disabled{{credential=\"{}\"}} {}",
+            entry.id,
+            u8::from(entry.disabled)
+        );
+    }
+    describe(
+        &mut out,
+        "kiro_credential_health_score",
+        "gauge",
+        "凭据健康度评分（0-1）",
+    );
+    for entry in credentials {
+        let _ = writeln!(
+            out,
+            "kiro_credential_health_score{{credential=\"{}\"}} {}",
+            entry.id, entry.health.score
+        );
+    }
+
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_prometheus_text() {
+        metrics::requests().record("/v1/messages", 200, std::time::Duration::from_millis(300));
+        metrics::tokens().record("claude-\"quoted\"", 12, 34);
+        metrics::api_keys().record_rate_limited("team-prom");
+
+        let text = render(&[]);
+        assert!(text.contains("# TYPE kiro_requests_total counter\n"));
+        assert!(text.contains("kiro_requests_total{endpoint=\"/v1/messages\",status=\"200\"} "));
+        assert!(text.contains(
+            "kiro_request_duration_seconds_bucket{endpoint=\"/v1/messages\",le=\"+Inf\"} "
+        ));
+        assert!(text.contains("kiro_upstream_failures_total{reason=\"throttled\"} "));
+        assert!(text.contains(
+            "kiro_tokens_total{model=\"claude-\\\"quoted\\\"\",direction=\"output\"} 34\n"
+        ));
+        assert!(text.contains("kiro_key_rate_limited_total{key=\"team-prom\"} 1\n"));
+
+        // 直方图的桶是累计值，+Inf 桶等于总数
+        let buckets: Vec<u64> = text
+            .lines()
+            .filter(|line| {
+                line.starts_with("kiro_request_duration_seconds_bucket{endpoint=\"/v1/messages\"")
+            })
+            .map(|line| line.rsplit(' ').next().unwrap().parse().unwrap())
+            .collect();
+        assert_eq!(buckets.len(), LATENCY_BUCKETS.len() + 1);
+        assert!(buckets.windows(2).all(|w| w[0] <= w[1]));
+        assert!(buckets[3] >= 1);
+    }
+}
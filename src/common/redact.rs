@@ -0,0 +1,177 @@
+//! 日志脱敏
+//!
+//! 请求/响应体在写入日志前统一经过这里处理：
+//! - 内置规则：API Key（`sk-...`）、Bearer Token、凭据类 JSON 字段、base64 图片数据
+//! - 自定义规则：`logging.redactPatterns` 中的正则，匹配内容替换为 `***`
+//! - 严格模式：完全不记录请求/响应体，只记录长度
+
+use std::borrow::Cow;
+use std::sync::OnceLock;
+
+use regex::{Captures, Regex};
+
+use crate::model::config::LoggingConfig;
+
+/// 替换文本
+const MASK: &str = "***";
+
+/// 脱敏器
+pub struct Redactor {
+    enabled: bool,
+    strict: bool,
+    api_key: Regex,
+    bearer: Regex,
+    secret_field: Regex,
+    base64_field: Regex,
+    custom: Vec<Regex>,
+}
+
+impl Redactor {
+    /// 根据日志配置创建脱敏器
+    ///
+    /// 无效的自定义正则会被忽略并输出警告
+    pub fn new(config: &LoggingConfig) -> Self {
+        let custom = config
+            .redact_patterns
+            .iter()
+            .filter_map(|pattern| match Regex::new(pattern) {
+                Ok(re) => Some(re),
+                Err(e) => {
+                    tracing::warn!("忽略无效的脱敏正则 {}: {}", pattern, e);
+                    None
+                }
+            })
+            .collect();
+
+        Self {
+            enabled: config.redact,
+            strict: config.strict,
+            api_key: Regex::new(r"sk-[A-Za-z0-9_\-]{8,}").unwrap(),
+            bearer: Regex::new(r"(?i)\bbearer\s+[A-Za-z0-9._~+/=\-]+").unwrap(),
+            secret_field: Regex::new(
+                r#"(?i)"(accessToken|refreshToken|clientSecret|clientId|apiKey|api_key|x-api-key|authorization|password|proxyPassword)"\s*:\s*"[^"]*""#,
+            )
+            .unwrap(),
+            base64_field: Regex::new(r#""(bytes|data)"\s*:\s*"([A-Za-z0-9+/=]{64,})""#).unwrap(),
+            custom,
+        }
+    }
+
+    /// 对文本做脱敏处理（未命中任何规则时不分配内存）
+    pub fn redact<'a>(&self, text: &'a str) -> Cow<'a, str> {
+        if !self.enabled {
+            return Cow::Borrowed(text);
+        }
+
+        let mut result = Cow::Borrowed(text);
+        result = replace(result, &self.base64_field, |caps: &Captures| {
+            format!("\"{}\":\"<base64 {} bytes>\"", &caps[1], caps[2].len())
+        });
+        result = replace(result, &self.secret_field, |caps: &Captures| {
+            format!("\"{}\":\"{}\"", &caps[1], MASK)
+        });
+        result = replace(result, &self.bearer, |_: &Captures| {
+            format!("Bearer {}", MASK)
+        });
+        result = replace(result, &self.api_key, |_: &Captures| format!("sk-{}", MASK));
+        for re in &self.custom {
+            result = replace(result, re, |_: &Captures| MASK.to_string());
+        }
+        result
+    }
+
+    /// 处理待记录的请求/响应体：严格模式下只保留长度
+    pub fn body<'a>(&self, body: &'a str) -> Cow<'a, str> {
+        if self.strict {
+            return Cow::Owned(format!("<body omitted, {} bytes>", body.len()));
+        }
+        self.redact(body)
+    }
+}
+
+fn replace<'a>(text: Cow<'a, str>, re: &Regex, rep: impl Fn(&Captures) -> String) -> Cow<'a, str> {
+    if !re.is_match(&text) {
+        return text;
+    }
+    Cow::Owned(re.replace_all(&text, rep).into_owned())
+}
+
+static REDACTOR: OnceLock<Redactor> = OnceLock::new();
+
+/// 初始化全局脱敏配置
+///
+/// 应在应用启动时调用一次；未初始化时使用默认配置
+pub fn init(config: &LoggingConfig) {
+    let _ = REDACTOR.set(Redactor::new(config));
+}
+
+fn redactor() -> &'static Redactor {
+    REDACTOR.get_or_init(|| Redactor::new(&LoggingConfig::default()))
+}
+
+/// 处理待记录的请求/响应体（脱敏，严格模式下省略）
+pub fn body(body: &str) -> Cow<'_, str> {
+    redactor().body(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn redactor_with(config: LoggingConfig) -> Redactor {
+        Redactor::new(&config)
+    }
+
+    #[test]
+    fn test_redacts_builtin_secrets() {
+        let r = redactor_with(LoggingConfig::default());
+
+        let text =
+            r#"{"apiKey":"abc","auth":"Bearer eyJhbGciOi.xyz","key":"sk-kiro-rs-123456789"}"#;
+        let redacted = r.redact(text);
+        assert!(!redacted.contains("abc\""));
+        assert!(!redacted.contains("eyJhbGciOi"));
+        assert!(!redacted.contains("123456789"));
+        assert!(redacted.contains(r#""apiKey":"***""#));
+    }
+
+    #[test]
+    fn test_redacts_base64_images() {
+        let r = redactor_with(LoggingConfig::default());
+        let data = "A".repeat(100);
+        let text = format!(r#"{{"format":"png","source":{{"bytes":"{}"}}}}"#, data);
+
+        let redacted = r.redact(&text);
+        assert!(redacted.contains("<base64 100 bytes>"));
+        assert!(!redacted.contains(&data));
+    }
+
+    #[test]
+    fn test_custom_patterns_and_passthrough() {
+        let r = redactor_with(LoggingConfig {
+            redact_patterns: vec![r"\d{3}-\d{4}".to_string(), "(".to_string()],
+            ..Default::default()
+        });
+
+        assert_eq!(r.redact("call 555-1234 now"), "call *** now");
+        assert!(matches!(r.redact("nothing secret"), Cow::Borrowed(_)));
+    }
+
+    #[test]
+    fn test_strict_mode_omits_body() {
+        let r = redactor_with(LoggingConfig {
+            strict: true,
+            ..Default::default()
+        });
+        assert_eq!(r.body("hello"), "<body omitted, 5 bytes>");
+    }
+
+    #[test]
+    fn test_disabled_redaction() {
+        let r = redactor_with(LoggingConfig {
+            redact: false,
+            ..Default::default()
+        });
+        assert_eq!(r.redact("Bearer abc"), "Bearer abc");
+    }
+}
@@ -0,0 +1,190 @@
+//! OpenTelemetry 链路追踪
+//!
+//! 启用 `telemetry.enabled` 后通过 OTLP/HTTP 导出 span，用于排查代理与 Kiro 之间的尾延迟。
+//! 一个请求的 span 结构：
+//!
+//! - `request`：整个请求（流式响应持续到流结束），带 `request_id`、路由与状态码
+//!   - `parse_request`：请求体解析
+//!   - `convert_request`：协议转换
+//!   - `kiro_api_call`：调用 Kiro 直到收到响应头（含凭据故障转移与重试）
+//!   - `sse_stream`：流式转发直到结束或客户端断开
+//!
+//! 请求 ID 取自 `x-request-id` 请求头（未提供时生成），通过同名响应头返回，并出现在该请求的所有日志中
+
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use anyhow::Context;
+use axum::{
+    Json,
+    body::Body,
+    extract::{FromRequest, MatchedPath, Request, rejection::JsonRejection},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_otlp::{SpanExporter, WithExportConfig, WithHttpConfig};
+use opentelemetry_sdk::Resource;
+use opentelemetry_sdk::trace::{Sampler, SdkTracer, SdkTracerProvider};
+use serde::de::DeserializeOwned;
+use tracing::Instrument;
+use tracing_opentelemetry::OpenTelemetryLayer;
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+use crate::model::config::TelemetryConfig;
+
+/// 请求 ID 请求头/响应头
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// 接受的客户端请求 ID 最大长度，超出时改为生成
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+/// 单次导出的超时
+const EXPORT_TIMEOUT: Duration = Duration::from_secs(10);
+
+type OtelLayer = OpenTelemetryLayer<Registry, SdkTracer>;
+
+static OTEL_LAYER: OnceLock<reload::Handle<Option<OtelLayer>, Registry>> = OnceLock::new();
+static PROVIDER: OnceLock<SdkTracerProvider> = OnceLock::new();
+
+/// 初始化日志（`RUST_LOG` 控制级别，默认 info）
+///
+/// 导出层在加载配置后由 [`init`] 装入；`stderr` 为 true 时日志写到 stderr
+pub fn init_subscriber(stderr: bool) {
+    let (otel, handle) = reload::Layer::new(None::<OtelLayer>);
+    let writer = if stderr {
+        BoxMakeWriter::new(std::io::stderr)
+    } else {
+        BoxMakeWriter::new(std::io::stdout)
+    };
+    tracing_subscriber::registry()
+        .with(otel)
+        .with(EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new("info")))
+        .with(tracing_subscriber::fmt::layer().with_writer(writer))
+        .init();
+    let _ = OTEL_LAYER.set(handle);
+}
+
+/// 按配置启用 OTLP 导出（启动时调用一次）
+pub fn init(config: &TelemetryConfig) -> anyhow::Result<()> {
+    if !config.enabled {
+        return Ok(());
+    }
+    if !(0.0..=1.0).contains(&config.sample_ratio) {
+        anyhow::bail!("sampleRatio 必须在 0 到 1 之间");
+    }
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(&config.endpoint)
+        .with_headers(config.headers.clone().into_iter().collect())
+        .with_timeout(EXPORT_TIMEOUT)
+        .build()
+        .context("创建 OTLP 导出器失败")?;
+    let provider = SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_sampler(Sampler::ParentBased(Box::new(Sampler::TraceIdRatioBased(
+            config.sample_ratio,
+        ))))
+        .with_resource(
+            Resource::builder()
+                .with_service_name(config.service_name.clone())
+                .build(),
+        )
+        .build();
+    let layer = tracing_opentelemetry::layer().with_tracer(provider.tracer("kiro-rs"));
+    OTEL_LAYER
+        .get()
+        .context("日志尚未初始化")?
+        .reload(Some(layer))?;
+    let _ = PROVIDER.set(provider);
+    tracing::info!("已启用 OTLP 链路追踪: {}", config.endpoint);
+    Ok(())
+}
+
+/// 导出尚未发送的 span（进程退出前调用）
+pub fn shutdown() {
+    if let Some(provider) = PROVIDER.get()
+        && let Err(e) = provider.shutdown()
+    {
+        tracing::warn!("导出剩余 span 失败: {}", e);
+    }
+}
+
+/// 客户端提供的请求 ID，无效时返回 None
+fn client_request_id(value: Option<&HeaderValue>) -> Option<String> {
+    let id = value?.to_str().ok()?.trim();
+    (!id.is_empty() && id.len() <= MAX_REQUEST_ID_LEN).then(|| id.to_string())
+}
+
+/// 为请求创建 `request` span 并返回 `x-request-id` 响应头的中间件
+pub async fn trace_requests(request: Request<Body>, next: Next) -> Response {
+    let request_id = client_request_id(request.headers().get(REQUEST_ID_HEADER))
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let route = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+        .unwrap_or_default();
+    let span = tracing::info_span!(
+        "request",
+        request_id = %request_id,
+        http.method = %request.method(),
+        http.route = %route,
+        http.status_code = tracing::field::Empty,
+    );
+
+    let mut response = next.run(request).instrument(span.clone()).await;
+    span.record("http.status_code", response.status().as_u16());
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+/// 在 `parse_request` span 中解析 JSON 请求体的提取器
+pub struct TracedJson<T>(pub T);
+
+impl<T, S> FromRequest<S> for TracedJson<T>
+where
+    T: DeserializeOwned,
+    S: Send + Sync,
+{
+    type Rejection = JsonRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(request, state)
+            .instrument(tracing::info_span!("parse_request"))
+            .await?;
+        Ok(Self(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_client_request_id() {
+        let id = |value: &str| client_request_id(Some(&HeaderValue::from_str(value).unwrap()));
+        assert_eq!(id("req-123").as_deref(), Some("req-123"));
+        assert_eq!(id("  req-123 ").as_deref(), Some("req-123"));
+        assert_eq!(id(""), None);
+        assert_eq!(id(&"x".repeat(MAX_REQUEST_ID_LEN + 1)), None);
+        assert_eq!(client_request_id(None), None);
+    }
+
+    #[test]
+    fn test_init_disabled_and_invalid() {
+        assert!(init(&TelemetryConfig::default()).is_ok());
+        let invalid = TelemetryConfig {
+            enabled: true,
+            sample_ratio: 1.5,
+            ..Default::default()
+        };
+        assert!(init(&invalid).is_err());
+    }
+}
@@ -0,0 +1,510 @@
+//! 用量报表
+//!
+//! 按天（UTC）、API Key、模型和 Kiro profile 累计请求数、tokens、Web Search 次数与估算费用，
+//! 并定期把累计结果连同运行时指标快照写入缓存目录下的 `kiro_usage.json`，重启后继续累计。Admin API 可按天导出 JSON 或 CSV 报表，
+//! 供没有部署 Prometheus 的团队统计用量
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use chrono::{NaiveDate, Utc};
+use parking_lot::Mutex;
+use serde::{Deserialize, Serialize};
+
+use crate::model::config::ModelPrice;
+
+use super::metrics::{self, MetricsSnapshot};
+
+/// 用量计数
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageCounters {
+    pub requests: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    #[serde(default)]
+    pub web_search_requests: u64,
+    /// 按 `pricing` 配置估算的费用（未配置单价的模型不计入）
+    #[serde(default)]
+    pub estimated_cost: f64,
+}
+
+impl UsageCounters {
+    fn add(&mut self, other: &UsageCounters) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.web_search_requests += other.web_search_requests;
+        self.estimated_cost += other.estimated_cost;
+    }
+}
+
+/// 请求所用模型的单价
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Price {
+    pub model: ModelPrice,
+    /// 每次 Web Search 的价格
+    pub web_search: f64,
+}
+
+impl Price {
+    /// 估算费用（保留 6 位小数）
+    pub fn cost(&self, input_tokens: i32, output_tokens: i32, web_searches: i32) -> f64 {
+        let tokens = input_tokens.max(0) as f64 * self.model.input_per_million
+            + output_tokens.max(0) as f64 * self.model.output_per_million;
+        let cost = tokens / 1_000_000.0 + web_searches.max(0) as f64 * self.web_search;
+        (cost * 1_000_000.0).round() / 1_000_000.0
+    }
+}
+
+/// 报表中的一行（某个 API Key 经某个 profile 在某个模型上的用量）
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct UsageRow {
+    /// 脱敏后的 API Key
+    pub key: String,
+    pub model: String,
+    /// 服务该请求的 Kiro profile ARN（未使用 profile 时为空）
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub profile: String,
+    #[serde(flatten)]
+    pub usage: UsageCounters,
+}
+
+/// 单日用量报表
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DailyReport {
+    /// 日期（UTC，`YYYY-MM-DD`）
+    pub date: String,
+    pub rows: Vec<UsageRow>,
+    /// 当天合计
+    pub total: UsageCounters,
+    /// 当天最后一次快照时的运行时指标（进程启动以来的累计值）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub metrics: Option<MetricsSnapshot>,
+}
+
+impl DailyReport {
+    /// 导出为 CSV
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from(
+            "date,key,model,profile,requests,input_tokens,output_tokens,web_search_requests,estimated_cost\n",
+        );
+        for row in &self.rows {
+            csv.push_str(&format!(
+                "{},{},{},{},{},{},{},{},{:.6}\n",
+                self.date,
+                csv_field(&row.key),
+                csv_field(&row.model),
+                csv_field(&row.profile),
+                row.usage.requests,
+                row.usage.input_tokens,
+                row.usage.output_tokens,
+                row.usage.web_search_requests,
+                row.usage.estimated_cost
+            ));
+        }
+        csv
+    }
+}
+
+/// 转义 CSV 字段
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// 单日累计数据
+#[derive(Default)]
+struct Day {
+    /// (key, model, profile) -> 用量
+    usage: BTreeMap<(String, String, String), UsageCounters>,
+    metrics: Option<MetricsSnapshot>,
+}
+
+impl Day {
+    fn report(&self, date: &str) -> DailyReport {
+        let mut total = UsageCounters::default();
+        let rows = self
+            .usage
+            .iter()
+            .map(|((key, model, profile), usage)| {
+                total.add(usage);
+                UsageRow {
+                    key: key.clone(),
+                    model: model.clone(),
+                    profile: profile.clone(),
+                    usage: *usage,
+                }
+            })
+            .collect();
+        DailyReport {
+            date: date.to_string(),
+            rows,
+            total,
+            metrics: self.metrics,
+        }
+    }
+}
+
+/// 用量存储
+pub struct UsageStore {
+    /// 持久化文件路径，为 None 时只在内存中统计
+    path: Option<PathBuf>,
+    retention_days: u32,
+    /// 日期 -> 当天数据
+    days: Mutex<BTreeMap<String, Day>>,
+}
+
+impl UsageStore {
+    /// 创建存储并加载已持久化的报表
+    pub fn new(path: Option<PathBuf>, retention_days: u32) -> Self {
+        let mut days = BTreeMap::new();
+        if let Some(reports) = path.as_deref().and_then(load_reports) {
+            for report in reports {
+                let usage = report
+                    .rows
+                    .into_iter()
+                    .map(|row| ((row.key, row.model, row.profile), row.usage))
+                    .collect();
+                days.insert(
+                    report.date,
+                    Day {
+                        usage,
+                        metrics: report.metrics,
+                    },
+                );
+            }
+        }
+        Self {
+            path,
+            retention_days,
+            days: Mutex::new(days),
+        }
+    }
+
+    /// 累计一次请求的用量
+    pub fn record(&self, key: &str, model: &str, profile: &str, usage: UsageCounters) {
+        self.days
+            .lock()
+            .entry(today())
+            .or_default()
+            .usage
+            .entry((key.to_string(), model.to_string(), profile.to_string()))
+            .or_default()
+            .add(&usage);
+    }
+
+    /// 某个 API Key 当天在匹配模型上的 tokens（输入 + 输出）
+    pub fn tokens_today(&self, api_key: &str, mut matches_model: impl FnMut(&str) -> bool) -> u64 {
+        let key = key_label(api_key);
+        self.days.lock().get(&today()).map_or(0, |day| {
+            day.usage
+                .iter()
+                .filter(|((k, model, _), _)| *k == key && matches_model(model))
+                .map(|(_, usage)| usage.input_tokens + usage.output_tokens)
+                .sum()
+        })
+    }
+
+    /// 有报表的日期（升序）
+    pub fn dates(&self) -> Vec<String> {
+        self.days.lock().keys().cloned().collect()
+    }
+
+    /// 指定日期的报表
+    pub fn report(&self, date: &str) -> Option<DailyReport> {
+        self.days.lock().get(date).map(|day| day.report(date))
+    }
+
+    /// 记录当前指标快照，清理过期报表并写入磁盘
+    pub fn snapshot(&self) {
+        let reports: Vec<DailyReport> = {
+            let mut days = self.days.lock();
+            days.entry(today()).or_default().metrics = Some(metrics::snapshot());
+
+            let cutoff = (Utc::now().date_naive() - chrono::Days::new(self.retention_days.into()))
+                .to_string();
+            days.retain(|date, _| date.as_str() > cutoff.as_str());
+
+            days.iter().map(|(date, day)| day.report(date)).collect()
+        };
+
+        let Some(path) = &self.path else {
+            return;
+        };
+        match serde_json::to_string_pretty(&reports) {
+            Ok(json) => {
+                if let Err(e) = std::fs::write(path, json) {
+                    tracing::warn!("保存用量报表失败: {}", e);
+                }
+            }
+            Err(e) => tracing::warn!("序列化用量报表失败: {}", e),
+        }
+    }
+}
+
+fn load_reports(path: &Path) -> Option<Vec<DailyReport>> {
+    let content = std::fs::read_to_string(path).ok()?;
+    match serde_json::from_str(&content) {
+        Ok(reports) => Some(reports),
+        Err(e) => {
+            tracing::warn!("解析用量报表缓存失败，将忽略: {}", e);
+            None
+        }
+    }
+}
+
+fn today() -> String {
+    Utc::now().date_naive().to_string()
+}
+
+/// 校验日期格式（`YYYY-MM-DD`）
+pub fn is_valid_date(date: &str) -> bool {
+    NaiveDate::parse_from_str(date, "%Y-%m-%d").is_ok()
+}
+
+/// 报表中使用的 API Key 标识（只保留首尾各 4 个字符）
+pub fn key_label(api_key: &str) -> String {
+    let chars: Vec<char> = api_key.chars().collect();
+    if chars.len() < 12 {
+        return "***".to_string();
+    }
+    let head: String = chars[..4].iter().collect();
+    let tail: String = chars[chars.len() - 4..].iter().collect();
+    format!("{}***{}", head, tail)
+}
+
+static STORE: OnceLock<UsageStore> = OnceLock::new();
+
+/// 初始化全局用量存储（`path` 为持久化文件路径）
+pub fn init(path: Option<PathBuf>, retention_days: u32) {
+    let _ = STORE.set(UsageStore::new(path, retention_days));
+}
+
+/// 全局用量存储（未初始化时为 None）
+pub fn store() -> Option<&'static UsageStore> {
+    STORE.get()
+}
+
+/// 单个请求的用量记录句柄
+#[derive(Debug, Clone)]
+pub struct RequestUsage {
+    key: String,
+    model: String,
+    profile: String,
+    price: Option<Price>,
+}
+
+impl RequestUsage {
+    pub fn new(api_key: &str, model: &str) -> Self {
+        Self {
+            key: key_label(api_key),
+            model: model.to_string(),
+            profile: String::new(),
+            price: None,
+        }
+    }
+
+    /// 记录服务该请求的 Kiro profile ARN
+    pub fn with_profile(mut self, profile_arn: Option<&str>) -> Self {
+        self.profile = profile_arn.unwrap_or_default().to_string();
+        self
+    }
+
+    /// 设置用于估算费用的单价
+    pub fn with_price(mut self, price: Option<Price>) -> Self {
+        self.price = price;
+        self
+    }
+
+    /// 请求结束时记录最终用量，返回估算费用（未配置单价时为 None）
+    pub fn record(&self, input_tokens: i32, output_tokens: i32, web_searches: i32) -> Option<f64> {
+        let cost = self
+            .price
+            .map(|price| price.cost(input_tokens, output_tokens, web_searches));
+        metrics::tokens().record(
+            &self.model,
+            input_tokens.max(0) as u64,
+            output_tokens.max(0) as u64,
+        );
+        if let Some(store) = store() {
+            let usage = UsageCounters {
+                requests: 1,
+                input_tokens: input_tokens.max(0) as u64,
+                output_tokens: output_tokens.max(0) as u64,
+                web_search_requests: web_searches.max(0) as u64,
+                estimated_cost: cost.unwrap_or_default(),
+            };
+            store.record(&self.key, &self.model, &self.profile, usage);
+        }
+        cost
+    }
+}
+
+/// 启动定期快照任务（间隔为 0 时不持久化）
+pub fn spawn_snapshotter(interval_secs: u64) {
+    if interval_secs == 0 || store().is_none() {
+        return;
+    }
+
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            if let Some(store) = store()
+                && let Err(e) = tokio::task::spawn_blocking(|| store.snapshot()).await
+            {
+                tracing::warn!("用量快照任务异常: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PROFILE: &str = "arn:aws:codewhisperer:us-east-1:123456789012:profile/ABC";
+
+    fn tokens(input_tokens: u64, output_tokens: u64) -> UsageCounters {
+        UsageCounters {
+            requests: 1,
+            input_tokens,
+            output_tokens,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tokens_today() {
+        let store = UsageStore::new(None, 30);
+        let key = key_label("sk-team-a-0001");
+        store.record(&key, "claude-opus-4-6", "", tokens(100, 20));
+        store.record(&key, "claude-opus-4-6", PROFILE, tokens(30, 5));
+        store.record(&key, "claude-sonnet-4-6", "", tokens(1000, 100));
+        store.record(
+            &key_label("sk-team-b-0002"),
+            "claude-opus-4-6",
+            "",
+            tokens(7, 7),
+        );
+
+        let opus = |model: &str| model.contains("opus");
+        assert_eq!(store.tokens_today("sk-team-a-0001", opus), 155);
+        assert_eq!(store.tokens_today("sk-team-b-0002", opus), 14);
+        assert_eq!(store.tokens_today("sk-team-c-0003", opus), 0);
+    }
+
+    #[test]
+    fn test_record_and_report() {
+        let store = UsageStore::new(None, 30);
+        store.record("sk-abcdefgh-1234", "claude-sonnet-4-6", "", tokens(100, 20));
+        store.record("sk-abcdefgh-1234", "claude-sonnet-4-6", "", tokens(50, 10));
+        store.record("sk-abcdefgh-1234", "claude-opus-4-6", "", tokens(10, 1));
+        store.record("sk-abcdefgh-1234", "claude-opus-4-6", PROFILE, tokens(5, 1));
+
+        let date = today();
+        assert_eq!(store.dates(), vec![date.clone()]);
+        let report = store.report(&date).unwrap();
+        assert_eq!(report.rows.len(), 3);
+        assert_eq!(
+            report.total,
+            UsageCounters {
+                requests: 4,
+                input_tokens: 165,
+                output_tokens: 32,
+                ..Default::default()
+            }
+        );
+
+        let csv = report.to_csv();
+        let lines: Vec<_> = csv.lines().collect();
+        assert_eq!(
+            lines[0],
+            "date,key,model,profile,requests,input_tokens,output_tokens,web_search_requests,estimated_cost"
+        );
+        assert_eq!(
+            lines[2],
+            format!(
+                "{},sk-abcdefgh-1234,claude-opus-4-6,{},1,5,1,0,0.000000",
+                date, PROFILE
+            )
+        );
+        assert_eq!(
+            lines[3],
+            format!(
+                "{},sk-abcdefgh-1234,claude-sonnet-4-6,,2,150,30,0,0.000000",
+                date
+            )
+        );
+    }
+
+    #[test]
+    fn test_estimated_cost() {
+        let price = Price {
+            model: ModelPrice {
+                input_per_million: 3.0,
+                output_per_million: 15.0,
+            },
+            web_search: 0.01,
+        };
+        assert_eq!(price.cost(1_000_000, 0, 0), 3.0);
+        assert_eq!(price.cost(2000, 1000, 2), 0.041);
+        assert_eq!(price.cost(-1, 0, 0), 0.0);
+
+        let store = UsageStore::new(None, 30);
+        for (input, output, searches) in [(2000, 1000, 2), (1000, 0, 0)] {
+            store.record(
+                "sk-abcdefgh-1234",
+                "claude-sonnet-4-6",
+                "",
+                UsageCounters {
+                    web_search_requests: searches,
+                    estimated_cost: price.cost(input as i32, output as i32, searches as i32),
+                    ..tokens(input, output)
+                },
+            );
+        }
+        let report = store.report(&today()).unwrap();
+        assert_eq!(report.total.web_search_requests, 2);
+        assert!((report.total.estimated_cost - 0.044).abs() < 1e-9);
+        assert!(
+            report
+                .to_csv()
+                .lines()
+                .nth(1)
+                .unwrap()
+                .ends_with(",2,3000,1000,2,0.044000")
+        );
+    }
+
+    #[test]
+    fn test_snapshot_persists_and_reloads() {
+        let path = std::env::temp_dir().join(format!("kiro-usage-{}.json", uuid::Uuid::new_v4()));
+        let store = UsageStore::new(Some(path.clone()), 30);
+        store.record(
+            &key_label("sk-abcdefgh-1234"),
+            "claude-sonnet-4-6",
+            PROFILE,
+            tokens(100, 20),
+        );
+        store.snapshot();
+
+        let reloaded = UsageStore::new(Some(path.clone()), 30);
+        let report = reloaded.report(&today()).unwrap();
+        assert_eq!(report.rows[0].key, "sk-a***1234");
+        assert_eq!(report.rows[0].profile, PROFILE);
+        assert_eq!(report.total.requests, 1);
+        assert!(report.metrics.is_some());
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,199 @@
+//! 上游请求尝试记录
+//!
+//! 记录一次上游调用中每次失败尝试的原因（凭据失效、限流、超时等），用于日志、
+//! 按原因累计的指标，以及可选的 `x-kiro-upstream-attempts` 响应头，
+//! 便于排查“为什么这个请求这么慢”
+
+use std::fmt;
+
+use crate::kiro::model::events::ExceptionKind;
+
+/// 失败尝试的原因
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AttemptReason {
+    /// 凭据无效、过期或无权限（401/403），切换凭据
+    Unauthorized,
+    /// 限流（429 / ThrottlingException）
+    Throttled,
+    /// 超时（408 或请求超时）
+    Timeout,
+    /// 连接失败等网络错误
+    Network,
+    /// 上游内部错误（5xx / ServiceUnavailable）
+    ServerError,
+    /// 额度已用尽（402 MONTHLY_REQUEST_COUNT），禁用凭据并切换
+    QuotaExhausted,
+    /// 没有可用凭据（全部禁用或刷新失败）
+    NoCredential,
+    /// 请求被上游拒绝（400 等），不再重试
+    Rejected,
+}
+
+impl AttemptReason {
+    /// 全部原因（用于指标）
+    pub const ALL: [AttemptReason; 8] = [
+        AttemptReason::Unauthorized,
+        AttemptReason::Throttled,
+        AttemptReason::Timeout,
+        AttemptReason::Network,
+        AttemptReason::ServerError,
+        AttemptReason::QuotaExhausted,
+        AttemptReason::NoCredential,
+        AttemptReason::Rejected,
+    ];
+
+    /// 按失败响应判断原因：能识别出 Kiro 异常时按异常种类，否则按状态码
+    pub fn from_response(status: u16, body: &str) -> Self {
+        if let Some(kind) = ExceptionKind::from_error_body(body) {
+            return match kind {
+                ExceptionKind::AccessDenied => AttemptReason::Unauthorized,
+                ExceptionKind::Throttling => AttemptReason::Throttled,
+                ExceptionKind::ServiceUnavailable | ExceptionKind::Unknown => {
+                    AttemptReason::ServerError
+                }
+                ExceptionKind::ContentLengthExceeded
+                | ExceptionKind::Validation
+                | ExceptionKind::ResourceNotFound => AttemptReason::Rejected,
+            };
+        }
+
+        match status {
+            401 | 403 => AttemptReason::Unauthorized,
+            408 => AttemptReason::Timeout,
+            429 => AttemptReason::Throttled,
+            400..=499 => AttemptReason::Rejected,
+            _ => AttemptReason::ServerError,
+        }
+    }
+
+    /// 按请求发送错误判断原因
+    pub fn from_send_error(err: &reqwest::Error) -> Self {
+        if err.is_timeout() {
+            AttemptReason::Timeout
+        } else {
+            AttemptReason::Network
+        }
+    }
+
+    /// 原因代码
+    pub fn code(self) -> &'static str {
+        match self {
+            AttemptReason::Unauthorized => "unauthorized",
+            AttemptReason::Throttled => "throttled",
+            AttemptReason::Timeout => "timeout",
+            AttemptReason::Network => "network",
+            AttemptReason::ServerError => "server_error",
+            AttemptReason::QuotaExhausted => "quota_exhausted",
+            AttemptReason::NoCredential => "no_credential",
+            AttemptReason::Rejected => "rejected",
+        }
+    }
+}
+
+impl fmt::Display for AttemptReason {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+/// 一次失败的尝试
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FailedAttempt {
+    /// 使用的凭据 ID（未能获取凭据时为 None）
+    pub credential_id: Option<u64>,
+    pub reason: AttemptReason,
+    /// 上游响应状态码（未收到响应时为 None）
+    pub status: Option<u16>,
+}
+
+/// 一次上游调用的尝试记录
+///
+/// 调用成功时作为扩展附加到上游响应上，供 handler 生成响应头
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UpstreamAttempts {
+    pub failures: Vec<FailedAttempt>,
+}
+
+impl UpstreamAttempts {
+    /// 记录一次失败的尝试并累加指标
+    pub fn record(
+        &mut self,
+        credential_id: Option<u64>,
+        reason: AttemptReason,
+        status: Option<u16>,
+    ) {
+        crate::common::metrics::upstream_failures().record(reason);
+        crate::common::metrics::recent_errors().record(credential_id, reason, status);
+        self.failures.push(FailedAttempt {
+            credential_id,
+            reason,
+            status,
+        });
+    }
+
+    /// 总尝试次数（含最终成功的一次）
+    pub fn total(&self) -> usize {
+        self.failures.len() + 1
+    }
+
+    /// 失败原因列表，如 `throttled,unauthorized`
+    pub fn reasons(&self) -> String {
+        self.failures
+            .iter()
+            .map(|f| f.reason.code())
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+
+    /// `x-kiro-upstream-attempts` 响应头的值，如 `3; reasons=throttled,unauthorized`
+    pub fn header_value(&self) -> String {
+        if self.failures.is_empty() {
+            return self.total().to_string();
+        }
+        format!("{}; reasons={}", self.total(), self.reasons())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_reason_from_response() {
+        assert_eq!(
+            AttemptReason::from_response(403, ""),
+            AttemptReason::Unauthorized
+        );
+        assert_eq!(
+            AttemptReason::from_response(429, ""),
+            AttemptReason::Throttled
+        );
+        assert_eq!(
+            AttemptReason::from_response(408, ""),
+            AttemptReason::Timeout
+        );
+        assert_eq!(
+            AttemptReason::from_response(400, "bad"),
+            AttemptReason::Rejected
+        );
+        assert_eq!(
+            AttemptReason::from_response(502, ""),
+            AttemptReason::ServerError
+        );
+        // 异常种类优先于状态码
+        assert_eq!(
+            AttemptReason::from_response(400, r#"{"__type":"ThrottlingException"}"#),
+            AttemptReason::Throttled
+        );
+    }
+
+    #[test]
+    fn test_header_value() {
+        let mut attempts = UpstreamAttempts::default();
+        assert_eq!(attempts.header_value(), "1");
+
+        attempts.record(Some(1), AttemptReason::Throttled, Some(429));
+        attempts.record(Some(1), AttemptReason::Unauthorized, Some(401));
+        assert_eq!(attempts.header_value(), "3; reasons=throttled,unauthorized");
+    }
+}
@@ -0,0 +1,380 @@
+//! Kiro 登录（AWS SSO OIDC 设备授权）
+//!
+//! `kiro-rs login` 按 OAuth 设备授权流程获取 IdC 凭据：注册 OIDC 公共客户端、发起设备授权，
+//! 用户在浏览器中打开验证链接并确认后轮询换取 Token，最后把 refreshToken 与 clientId/clientSecret
+//! 追加到凭据文件，无需再从 Kiro IDE 的缓存中手动复制。默认登录 AWS Builder ID，
+//! 通过 `--start-url` 登录组织的 IAM Identity Center
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{Context, bail};
+use chrono::Utc;
+use reqwest::{Client, StatusCode};
+use serde::{Deserialize, Serialize};
+
+use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::kiro::model::token_refresh::IdcRefreshResponse;
+use crate::model::config::Config;
+
+/// AWS Builder ID 的起始 URL
+pub const BUILDER_ID_START_URL: &str = "https://view.awsapps.com/start";
+
+/// 设备授权的 grant type
+const DEVICE_CODE_GRANT: &str = "urn:ietf:params:oauth:grant-type:device_code";
+
+/// 注册 OIDC 客户端时使用的名称
+const CLIENT_NAME: &str = "kiro-rs";
+
+/// 申请的权限范围（与 Kiro IDE 相同）
+const SCOPES: &[&str] = &[
+    "codewhisperer:completions",
+    "codewhisperer:analysis",
+    "codewhisperer:conversations",
+    "codewhisperer:transformations",
+    "codewhisperer:taskassist",
+];
+
+/// 未返回轮询间隔时使用的间隔（秒）
+const DEFAULT_POLL_INTERVAL_SECS: u64 = 5;
+
+/// 收到 slow_down 后增加的轮询间隔（秒）
+const SLOW_DOWN_SECS: u64 = 5;
+
+/// 登录选项
+#[derive(Debug, Clone)]
+pub struct LoginOptions {
+    /// IAM Identity Center 起始 URL（AWS Builder ID 为 [`BUILDER_ID_START_URL`]）
+    pub start_url: String,
+    /// OIDC 服务所在 Region
+    pub region: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterClientRequest<'a> {
+    client_name: &'a str,
+    client_type: &'a str,
+    scopes: &'a [&'a str],
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RegisterClientResponse {
+    client_id: String,
+    client_secret: String,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceAuthorizationRequest<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    start_url: &'a str,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct DeviceAuthorizationResponse {
+    device_code: String,
+    user_code: String,
+    verification_uri: String,
+    #[serde(default)]
+    verification_uri_complete: Option<String>,
+    expires_in: u64,
+    #[serde(default)]
+    interval: Option<u64>,
+}
+
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct CreateTokenRequest<'a> {
+    client_id: &'a str,
+    client_secret: &'a str,
+    grant_type: &'a str,
+    device_code: &'a str,
+}
+
+/// OIDC 错误响应
+#[derive(Deserialize)]
+struct OidcError {
+    error: String,
+    #[serde(default)]
+    error_description: Option<String>,
+}
+
+/// 一次轮询的结果
+#[derive(Debug)]
+enum PollResult {
+    Token(IdcRefreshResponse),
+    /// 用户尚未确认授权
+    Pending,
+    /// 轮询过快，需要增加间隔
+    SlowDown,
+}
+
+/// 解析 CreateToken 的响应
+fn poll_result(status: StatusCode, body: &str) -> anyhow::Result<PollResult> {
+    if status.is_success() {
+        let token = serde_json::from_str(body).context("解析 Token 响应失败")?;
+        return Ok(PollResult::Token(token));
+    }
+    let error: OidcError = serde_json::from_str(body)
+        .map_err(|_| anyhow::anyhow!("换取 Token 失败: {} {}", status, body))?;
+    match error.error.as_str() {
+        "authorization_pending" => Ok(PollResult::Pending),
+        "slow_down" => Ok(PollResult::SlowDown),
+        "access_denied" => bail!("用户拒绝了授权"),
+        "expired_token" => bail!("设备授权已过期，请重新登录"),
+        other => bail!(
+            "换取 Token 失败: {} {}",
+            other,
+            error.error_description.unwrap_or_default()
+        ),
+    }
+}
+
+/// OIDC 服务地址
+fn oidc_endpoint(region: &str) -> String {
+    format!("https://oidc.{}.amazonaws.com", region)
+}
+
+/// 发送 JSON 请求并解析成功响应
+async fn post_json<T: for<'de> Deserialize<'de>>(
+    client: &Client,
+    url: &str,
+    body: &impl Serialize,
+    action: &str,
+) -> anyhow::Result<T> {
+    let response = client.post(url).json(body).send().await?;
+    let status = response.status();
+    let text = response.text().await.unwrap_or_default();
+    if !status.is_success() {
+        bail!("{}失败: {} {}", action, status, text);
+    }
+    serde_json::from_str(&text).with_context(|| format!("解析{}响应失败", action))
+}
+
+/// 执行设备授权登录，返回新的 IdC 凭据
+pub async fn login(options: &LoginOptions, config: &Config) -> anyhow::Result<KiroCredentials> {
+    let proxy = ProxyConfig::from_config(config);
+    let client = build_client(proxy.as_ref(), 60, config.tls_backend)?;
+    authorize(&client, &oidc_endpoint(&options.region), options).await
+}
+
+async fn authorize(
+    client: &Client,
+    endpoint: &str,
+    options: &LoginOptions,
+) -> anyhow::Result<KiroCredentials> {
+    let registered: RegisterClientResponse = post_json(
+        client,
+        &format!("{}/client/register", endpoint),
+        &RegisterClientRequest {
+            client_name: CLIENT_NAME,
+            client_type: "public",
+            scopes: SCOPES,
+        },
+        "注册 OIDC 客户端",
+    )
+    .await?;
+
+    let device: DeviceAuthorizationResponse = post_json(
+        client,
+        &format!("{}/device_authorization", endpoint),
+        &DeviceAuthorizationRequest {
+            client_id: &registered.client_id,
+            client_secret: &registered.client_secret,
+            start_url: &options.start_url,
+        },
+        "发起设备授权",
+    )
+    .await?;
+
+    println!(
+        "请在浏览器中打开以下链接完成登录: {}",
+        device
+            .verification_uri_complete
+            .as_deref()
+            .unwrap_or(&device.verification_uri)
+    );
+    println!("验证码: {}", device.user_code);
+
+    let deadline = Instant::now() + Duration::from_secs(device.expires_in);
+    let mut interval = device.interval.unwrap_or(DEFAULT_POLL_INTERVAL_SECS);
+    let token = loop {
+        if Instant::now() >= deadline {
+            bail!("等待授权超时，请重新登录");
+        }
+        tokio::time::sleep(Duration::from_secs(interval)).await;
+
+        let response = client
+            .post(format!("{}/token", endpoint))
+            .json(&CreateTokenRequest {
+                client_id: &registered.client_id,
+                client_secret: &registered.client_secret,
+                grant_type: DEVICE_CODE_GRANT,
+                device_code: &device.device_code,
+            })
+            .send()
+            .await?;
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        match poll_result(status, &body)? {
+            PollResult::Token(token) => break token,
+            PollResult::Pending => {}
+            PollResult::SlowDown => interval += SLOW_DOWN_SECS,
+        }
+    };
+
+    let Some(refresh_token) = token.refresh_token else {
+        bail!("Token 响应中缺少 refreshToken");
+    };
+    Ok(KiroCredentials {
+        access_token: Some(token.access_token),
+        refresh_token: Some(refresh_token),
+        expires_at: token
+            .expires_in
+            .map(|secs| (Utc::now() + chrono::Duration::seconds(secs)).to_rfc3339()),
+        auth_method: Some("idc".to_string()),
+        client_id: Some(registered.client_id),
+        client_secret: Some(registered.client_secret),
+        region: Some(options.region.clone()),
+        ..Default::default()
+    })
+}
+
+/// 把新凭据追加到凭据文件，返回文件中的凭据数
+///
+/// 文件不存在或为空时新建；单凭据格式的文件改写为数组格式
+pub fn save_credentials(path: &Path, credentials: KiroCredentials) -> anyhow::Result<usize> {
+    let mut list = match CredentialsConfig::load(path)
+        .with_context(|| format!("读取凭据文件失败: {}", path.display()))?
+    {
+        CredentialsConfig::Single(existing) => vec![existing],
+        CredentialsConfig::Multiple(list) => list,
+    };
+    list.push(credentials);
+    let json = serde_json::to_string_pretty(&list).context("序列化凭据失败")?;
+    std::fs::write(path, json).with_context(|| format!("写入凭据文件失败: {}", path.display()))?;
+    Ok(list.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use axum::{Json, Router, http::StatusCode as AxumStatus, routing::post};
+    use serde_json::{Value, json};
+
+    use super::*;
+
+    #[test]
+    fn test_poll_result() {
+        let pending = r#"{"error":"authorization_pending","error_description":"waiting"}"#;
+        assert!(matches!(
+            poll_result(StatusCode::BAD_REQUEST, pending).unwrap(),
+            PollResult::Pending
+        ));
+        assert!(matches!(
+            poll_result(StatusCode::BAD_REQUEST, r#"{"error":"slow_down"}"#).unwrap(),
+            PollResult::SlowDown
+        ));
+        let denied = poll_result(StatusCode::BAD_REQUEST, r#"{"error":"access_denied"}"#);
+        assert!(denied.unwrap_err().to_string().contains("拒绝"));
+        assert!(poll_result(StatusCode::INTERNAL_SERVER_ERROR, "oops").is_err());
+
+        let token = r#"{"accessToken":"at","refreshToken":"rt","expiresIn":3600}"#;
+        match poll_result(StatusCode::OK, token).unwrap() {
+            PollResult::Token(token) => assert_eq!(token.refresh_token.as_deref(), Some("rt")),
+            other => panic!("unexpected {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_device_authorization_flow() {
+        let polls = Arc::new(AtomicUsize::new(0));
+        let counter = polls.clone();
+        let app = Router::new()
+            .route(
+                "/client/register",
+                post(|Json(body): Json<Value>| async move {
+                    assert_eq!(body["clientType"], "public");
+                    Json(json!({"clientId": "cid", "clientSecret": "secret"}))
+                }),
+            )
+            .route(
+                "/device_authorization",
+                post(|Json(body): Json<Value>| async move {
+                    assert_eq!(body["startUrl"], BUILDER_ID_START_URL);
+                    Json(json!({
+                        "deviceCode": "dc",
+                        "userCode": "ABCD-EFGH",
+                        "verificationUri": "https://device.sso.us-east-1.amazonaws.com/",
+                        "expiresIn": 600,
+                        "interval": 0
+                    }))
+                }),
+            )
+            .route(
+                "/token",
+                post(move |Json(body): Json<Value>| async move {
+                    assert_eq!(body["grantType"], DEVICE_CODE_GRANT);
+                    if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                        return (
+                            AxumStatus::BAD_REQUEST,
+                            Json(json!({"error": "authorization_pending"})),
+                        );
+                    }
+                    (
+                        AxumStatus::OK,
+                        Json(json!({"accessToken": "at", "refreshToken": "rt", "expiresIn": 3600})),
+                    )
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+
+        let options = LoginOptions {
+            start_url: BUILDER_ID_START_URL.to_string(),
+            region: "us-east-1".to_string(),
+        };
+        let credentials = authorize(&Client::new(), &endpoint, &options)
+            .await
+            .unwrap();
+        assert_eq!(polls.load(Ordering::SeqCst), 2);
+        assert_eq!(credentials.refresh_token.as_deref(), Some("rt"));
+        assert_eq!(credentials.client_id.as_deref(), Some("cid"));
+        assert_eq!(credentials.auth_method.as_deref(), Some("idc"));
+        assert_eq!(credentials.region.as_deref(), Some("us-east-1"));
+        assert!(credentials.expires_at.is_some());
+    }
+
+    #[test]
+    fn test_save_credentials() {
+        let path = std::env::temp_dir().join(format!("kiro-login-{}.json", uuid::Uuid::new_v4()));
+        let credentials = |token: &str| KiroCredentials {
+            refresh_token: Some(token.to_string()),
+            ..Default::default()
+        };
+
+        assert_eq!(save_credentials(&path, credentials("a")).unwrap(), 1);
+
+        // 单凭据格式改写为数组
+        std::fs::write(&path, r#"{"refreshToken":"a"}"#).unwrap();
+        assert_eq!(save_credentials(&path, credentials("b")).unwrap(), 2);
+        let saved = CredentialsConfig::load(&path)
+            .unwrap()
+            .into_sorted_credentials();
+        let tokens: Vec<_> = saved
+            .iter()
+            .map(|c| c.refresh_token.as_deref().unwrap())
+            .collect();
+        assert_eq!(tokens, ["a", "b"]);
+
+        std::fs::remove_file(&path).unwrap();
+    }
+}
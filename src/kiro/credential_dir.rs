@@ -0,0 +1,389 @@
+//! 凭据目录
+//!
+//! 配置 `credentialsDir.path` 后，目录中的每个 `*.json` 凭据文件（单对象或数组格式）
+//! 都会加载到凭据池中，并按 `scanIntervalSecs` 定期扫描：新增的文件加入凭据池，
+//! 修改的文件重新加载，删除的文件从凭据池中移除，无需重启即可轮换账号。
+//!
+//! 主凭据文件按 `credentialsReloadIntervalSecs` 检查修改时间，被外部修改（如重新登录）
+//! 时同样重新加载
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use crate::kiro::model::credentials::{CredentialsConfig, KiroCredentials};
+use crate::kiro::token_manager::{MultiTokenManager, file_modified};
+
+/// 一次同步的结果
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct SyncSummary {
+    /// 新增的凭据数量
+    pub added: usize,
+    /// 移除的凭据数量
+    pub removed: usize,
+}
+
+/// 列出目录中的凭据文件及其修改时间
+///
+/// 跳过主凭据文件和服务自身写入的缓存文件（`kiro_*.json`）
+fn scan(
+    dir: &Path,
+    exclude: Option<&Path>,
+) -> anyhow::Result<HashMap<PathBuf, Option<SystemTime>>> {
+    let exclude = exclude.and_then(|p| p.canonicalize().ok());
+    let mut files = HashMap::new();
+
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        let is_json = path.extension().is_some_and(|ext| ext == "json");
+        let is_cache = path
+            .file_name()
+            .and_then(|name| name.to_str())
+            .is_some_and(|name| name.starts_with("kiro_"));
+        if !is_json || is_cache || !path.is_file() {
+            continue;
+        }
+        if exclude.is_some() && path.canonicalize().ok() == exclude {
+            continue;
+        }
+
+        let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+        files.insert(path, modified);
+    }
+    Ok(files)
+}
+
+/// 读取并解析凭据文件，返回有效的凭据及是否为数组格式
+///
+/// 文件为空（如正在写入）、读取或解析失败时返回 None
+fn read_credentials(path: &Path) -> Option<(Vec<KiroCredentials>, bool)> {
+    let credentials = match std::fs::read_to_string(path) {
+        Ok(content) if content.trim().is_empty() => {
+            tracing::debug!("凭据文件为空，等待下次扫描: {:?}", path);
+            return None;
+        }
+        Ok(content) => serde_json::from_str::<CredentialsConfig>(&content),
+        Err(e) => {
+            tracing::warn!("读取凭据文件失败 {:?}: {}", path, e);
+            return None;
+        }
+    };
+    let credentials = match credentials {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            tracing::warn!("解析凭据文件失败 {:?}: {}", path, e);
+            return None;
+        }
+    };
+
+    let multiple = credentials.is_multiple();
+    let credentials = credentials
+        .into_sorted_credentials()
+        .into_iter()
+        .filter(|c| {
+            let valid = c.refresh_token.as_deref().is_some_and(|t| !t.is_empty());
+            if !valid {
+                tracing::warn!("凭据文件 {:?} 中有缺少 refreshToken 的凭据，已跳过", path);
+            }
+            valid
+        })
+        .collect();
+    Some((credentials, multiple))
+}
+
+/// 将目录中的凭据文件同步到凭据池
+///
+/// 解析失败或内容为空的文件（如正在写入）保持上一次加载的结果，下次扫描时重试
+pub fn sync(manager: &MultiTokenManager, dir: &Path) -> anyhow::Result<SyncSummary> {
+    let files = scan(dir, manager.credentials_path())?;
+    let loaded = manager.credential_file_mtimes();
+    let mut summary = SyncSummary::default();
+
+    for (path, modified) in &files {
+        if loaded.get(path) == Some(modified) {
+            continue;
+        }
+
+        let Some((credentials, multiple)) = read_credentials(path) else {
+            continue;
+        };
+        let (added, removed) = manager.load_credential_file(path, credentials, multiple, *modified);
+        summary.added += added;
+        summary.removed += removed;
+    }
+
+    for path in loaded.keys().filter(|path| !files.contains_key(*path)) {
+        summary.removed += manager.remove_credential_file(path);
+    }
+
+    Ok(summary)
+}
+
+/// 主凭据文件被外部修改时重新加载
+///
+/// 文件被删除、内容为空或解析失败时保留当前凭据，下次检查时重试
+pub fn sync_credentials_file(manager: &MultiTokenManager) -> anyhow::Result<SyncSummary> {
+    let Some(path) = manager.credentials_path() else {
+        return Ok(SyncSummary::default());
+    };
+    let modified = file_modified(path);
+    if modified.is_none() || modified == manager.credentials_file_modified() {
+        return Ok(SyncSummary::default());
+    }
+    let Some((credentials, _)) = read_credentials(path) else {
+        return Ok(SyncSummary::default());
+    };
+
+    let (added, removed) = manager.reload_credentials_file(credentials, modified);
+    Ok(SyncSummary { added, removed })
+}
+
+/// 启动目录扫描任务（间隔为 0 时只在启动时加载一次）
+pub fn spawn_watcher(manager: Arc<MultiTokenManager>, dir: PathBuf, interval_secs: u64) {
+    let target = format!("凭据目录 {:?}", dir);
+    spawn_periodic(target, interval_secs, move || sync(&manager, &dir));
+}
+
+/// 启动主凭据文件检查任务（间隔为 0 时不检查）
+pub fn spawn_file_watcher(manager: Arc<MultiTokenManager>, interval_secs: u64) {
+    let Some(path) = manager.credentials_path() else {
+        return;
+    };
+    let target = format!("凭据文件 {:?}", path);
+    spawn_periodic(target, interval_secs, move || {
+        sync_credentials_file(&manager)
+    });
+}
+
+/// 按间隔在阻塞线程池中执行同步任务
+fn spawn_periodic<F>(target: String, interval_secs: u64, task: F)
+where
+    F: Fn() -> anyhow::Result<SyncSummary> + Send + Sync + 'static,
+{
+    if interval_secs == 0 {
+        return;
+    }
+
+    tracing::info!("已启用{}监视，间隔 {} 秒", target, interval_secs);
+    let task = Arc::new(task);
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let task = task.clone();
+            match tokio::task::spawn_blocking(move || task()).await {
+                Ok(Ok(summary)) if summary != SyncSummary::default() => tracing::info!(
+                    "{}已更新: 新增 {} 个，移除 {} 个凭据",
+                    target,
+                    summary.added,
+                    summary.removed
+                ),
+                Ok(Ok(_)) => {}
+                Ok(Err(e)) => tracing::warn!("检查{}失败: {}", target, e),
+                Err(e) => tracing::warn!("{}监视任务异常: {}", target, e),
+            }
+        }
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::config::Config;
+    use std::time::UNIX_EPOCH;
+
+    /// 写入文件并设置修改时间（避免同一秒内多次写入时修改时间相同）
+    fn write(path: &Path, content: &str, mtime_secs: u64) {
+        std::fs::write(path, content).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(UNIX_EPOCH + Duration::from_secs(mtime_secs))
+            .unwrap();
+    }
+
+    fn temp_dir() -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("kiro-credentials-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir(&dir).unwrap();
+        dir
+    }
+
+    fn empty_manager() -> MultiTokenManager {
+        MultiTokenManager::new(Config::default(), vec![], None, None, true).unwrap()
+    }
+
+    #[test]
+    fn test_sync_adds_updates_and_removes_files() {
+        let dir = temp_dir();
+        let manager = empty_manager();
+
+        let a = dir.join("a.json");
+        let b = dir.join("b.json");
+        write(&a, r#"{"refreshToken": "rt-a"}"#, 1000);
+        write(
+            &b,
+            r#"[{"refreshToken": "rt-b1"}, {"refreshToken": "rt-b2"}]"#,
+            1000,
+        );
+        write(&dir.join("notes.txt"), "ignored", 1000);
+        write(&dir.join("kiro_stats.json"), "{}", 1000);
+
+        let summary = sync(&manager, &dir).unwrap();
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 3,
+                removed: 0
+            }
+        );
+        assert_ne!(manager.snapshot().current_id, 0);
+
+        // 未变化的文件不会重复加载
+        assert_eq!(sync(&manager, &dir).unwrap(), SyncSummary::default());
+
+        // 修改文件：保留 rt-b1（ID 不变），rt-b2 替换为 rt-b3
+        let b1_id = |m: &MultiTokenManager| {
+            m.snapshot()
+                .entries
+                .iter()
+                .find(|e| e.refresh_token_hash.as_deref() == Some(&sha256("rt-b1")))
+                .map(|e| e.id)
+        };
+        let before = b1_id(&manager);
+        write(
+            &b,
+            r#"[{"refreshToken": "rt-b1"}, {"refreshToken": "rt-b3"}]"#,
+            2000,
+        );
+        let summary = sync(&manager, &dir).unwrap();
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 1,
+                removed: 1
+            }
+        );
+        assert_eq!(b1_id(&manager), before);
+
+        // 删除文件
+        std::fs::remove_file(&a).unwrap();
+        let summary = sync(&manager, &dir).unwrap();
+        assert_eq!(
+            summary,
+            SyncSummary {
+                added: 0,
+                removed: 1
+            }
+        );
+        assert_eq!(manager.total_count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_file_keeps_previous_credentials() {
+        let dir = temp_dir();
+        let manager = empty_manager();
+
+        let path = dir.join("a.json");
+        write(&path, r#"{"refreshToken": "rt-a"}"#, 1000);
+        sync(&manager, &dir).unwrap();
+
+        write(&path, "{ not json", 2000);
+        assert_eq!(sync(&manager, &dir).unwrap(), SyncSummary::default());
+        assert_eq!(manager.total_count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_skips_credentials_with_invalid_proxy() {
+        let dir = temp_dir();
+        let manager = empty_manager();
+
+        let path = dir.join("a.json");
+        write(
+            &path,
+            r#"[{"refreshToken": "rt-a"}, {"refreshToken": "rt-b", "proxyUrl": "ftp://proxy:21"}]"#,
+            1000,
+        );
+        assert_eq!(
+            sync(&manager, &dir).unwrap(),
+            SyncSummary {
+                added: 1,
+                removed: 0
+            }
+        );
+        assert_eq!(manager.total_count(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_reload_modified_credentials_file() {
+        let dir = temp_dir();
+        let path = dir.join("credentials.json");
+        write(&path, r#"[{"id": 1, "refreshToken": "rt-a"}]"#, 1000);
+        let credentials =
+            serde_json::from_str::<CredentialsConfig>(&std::fs::read_to_string(&path).unwrap())
+                .unwrap()
+                .into_sorted_credentials();
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            credentials,
+            None,
+            Some(path.clone()),
+            true,
+        )
+        .unwrap();
+
+        // 自身的回写不视为外部修改
+        assert_eq!(
+            sync_credentials_file(&manager).unwrap(),
+            SyncSummary::default()
+        );
+
+        // 重新登录：同一 ID 换了 refreshToken，同时新增一个账号
+        write(
+            &path,
+            r#"[{"id": 1, "refreshToken": "rt-a2"}, {"refreshToken": "rt-b"}]"#,
+            2000,
+        );
+        assert_eq!(
+            sync_credentials_file(&manager).unwrap(),
+            SyncSummary {
+                added: 1,
+                removed: 0
+            }
+        );
+        let entries = manager.snapshot().entries;
+        assert_eq!(entries.len(), 2);
+        assert!(
+            entries
+                .iter()
+                .any(|e| e.id == 1 && e.refresh_token_hash.as_deref() == Some(&sha256("rt-a2")))
+        );
+        assert_eq!(
+            sync_credentials_file(&manager).unwrap(),
+            SyncSummary::default()
+        );
+
+        // 写入中的文件保留当前凭据
+        write(&path, "", 3000);
+        assert_eq!(
+            sync_credentials_file(&manager).unwrap(),
+            SyncSummary::default()
+        );
+        assert_eq!(manager.total_count(), 2);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn sha256(value: &str) -> String {
+        use sha2::{Digest, Sha256};
+        hex::encode(Sha256::digest(value.as_bytes()))
+    }
+}
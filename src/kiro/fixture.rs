@@ -0,0 +1,221 @@
+//! 事件流测试样本生成
+//!
+//! `kiro-rs gen-fixture` 读取 YAML 描述的事件脚本，编码为 AWS Event Stream 二进制文件，
+//! 便于为解码器和流处理编写回归样本。脚本示例：
+//!
+//! ```yaml
+//! events:
+//!   - type: assistantResponse
+//!     content: "你好"
+//!   - type: toolUse
+//!     toolUseId: tooluse_1
+//!     name: read_file
+//!     input: '{"path":'
+//!   - type: toolUse
+//!     toolUseId: tooluse_1
+//!     name: read_file
+//!     input: '"a.txt"}'
+//!     stop: true
+//!   - type: contextUsage
+//!     percentage: 12.5
+//!   - type: exception
+//!     exceptionType: ThrottlingException
+//!     message: Too many requests
+//! ```
+
+use std::path::Path;
+
+use anyhow::Context;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::parser::encoder;
+
+/// 样本脚本
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct FixtureScript {
+    pub events: Vec<FixtureEvent>,
+}
+
+/// 脚本中的单个事件
+#[derive(Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "camelCase", deny_unknown_fields)]
+pub enum FixtureEvent {
+    /// assistantResponseEvent 文本片段
+    #[serde(rename_all = "camelCase")]
+    AssistantResponse {
+        content: String,
+        #[serde(default)]
+        conversation_id: Option<String>,
+    },
+    /// toolUseEvent 片段（input 为原始 JSON 字符串，可以是不完整的片段）
+    #[serde(rename_all = "camelCase")]
+    ToolUse {
+        tool_use_id: String,
+        name: String,
+        #[serde(default)]
+        input: String,
+        #[serde(default)]
+        stop: bool,
+    },
+    /// contextUsageEvent
+    ContextUsage { percentage: f64 },
+    /// 异常帧
+    #[serde(rename_all = "camelCase")]
+    Exception {
+        exception_type: String,
+        #[serde(default)]
+        message: String,
+    },
+    /// 错误帧
+    #[serde(rename_all = "camelCase")]
+    Error {
+        error_code: String,
+        #[serde(default)]
+        message: String,
+    },
+}
+
+impl FixtureEvent {
+    /// 编码为单个消息帧
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            FixtureEvent::AssistantResponse {
+                content,
+                conversation_id,
+            } => {
+                let mut payload = json!({ "content": content });
+                if let Some(id) = conversation_id {
+                    payload["conversationId"] = json!(id);
+                }
+                encoder::encode_event("assistantResponseEvent", payload.to_string().as_bytes())
+            }
+            FixtureEvent::ToolUse {
+                tool_use_id,
+                name,
+                input,
+                stop,
+            } => {
+                let payload = json!({
+                    "toolUseId": tool_use_id,
+                    "name": name,
+                    "input": input,
+                    "stop": stop,
+                });
+                encoder::encode_event("toolUseEvent", payload.to_string().as_bytes())
+            }
+            FixtureEvent::ContextUsage { percentage } => {
+                let payload = json!({ "contextUsagePercentage": percentage });
+                encoder::encode_event("contextUsageEvent", payload.to_string().as_bytes())
+            }
+            FixtureEvent::Exception {
+                exception_type,
+                message,
+            } => encoder::encode_exception(exception_type, message),
+            FixtureEvent::Error {
+                error_code,
+                message,
+            } => encoder::encode_error(error_code, message),
+        }
+    }
+}
+
+impl FixtureScript {
+    /// 解析 YAML 脚本（JSON 也是合法的 YAML）
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        serde_yaml::from_str(source).context("解析样本脚本失败")
+    }
+
+    /// 按顺序编码所有事件
+    pub fn encode(&self) -> Vec<u8> {
+        self.events.iter().flat_map(FixtureEvent::encode).collect()
+    }
+}
+
+/// 读取脚本并写出二进制样本，返回写入的事件数
+pub fn generate(script: &Path, output: &Path) -> anyhow::Result<usize> {
+    let source = std::fs::read_to_string(script)
+        .with_context(|| format!("读取样本脚本失败: {}", script.display()))?;
+    let script = FixtureScript::parse(&source)?;
+    std::fs::write(output, script.encode())
+        .with_context(|| format!("写入样本文件失败: {}", output.display()))?;
+    Ok(script.events.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::model::events::Event;
+    use crate::kiro::parser::decoder::EventStreamDecoder;
+
+    const SCRIPT: &str = r#"
+events:
+  - type: assistantResponse
+    content: "你好"
+    conversationId: conv-1
+  - type: toolUse
+    toolUseId: tooluse_1
+    name: read_file
+    input: '{"path":'
+  - type: toolUse
+    toolUseId: tooluse_1
+    name: read_file
+    input: '"a.txt"}'
+    stop: true
+  - type: contextUsage
+    percentage: 12.5
+  - type: exception
+    exceptionType: ThrottlingException
+    message: Too many requests
+"#;
+
+    #[test]
+    fn test_script_decodes_back_to_events() {
+        let bytes = FixtureScript::parse(SCRIPT).unwrap().encode();
+
+        let mut decoder = EventStreamDecoder::new();
+        decoder.feed(&bytes).unwrap();
+        let events: Vec<Event> = decoder
+            .decode_iter()
+            .map(|frame| Event::from_frame(frame.unwrap()).unwrap())
+            .collect();
+        assert_eq!(events.len(), 5);
+
+        match &events[0] {
+            Event::AssistantResponse(e) => {
+                assert_eq!(e.content, "你好");
+                assert_eq!(e.conversation_id.as_deref(), Some("conv-1"));
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match &events[2] {
+            Event::ToolUse(e) => {
+                assert_eq!(e.tool_use_id, "tooluse_1");
+                assert_eq!(e.input, "\"a.txt\"}");
+                assert!(e.stop);
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match &events[3] {
+            Event::ContextUsage(e) => assert_eq!(e.context_usage_percentage, 12.5),
+            other => panic!("unexpected event: {:?}", other),
+        }
+        match &events[4] {
+            Event::Exception {
+                exception_type,
+                message,
+            } => {
+                assert_eq!(exception_type, "ThrottlingException");
+                assert_eq!(message, "Too many requests");
+            }
+            other => panic!("unexpected event: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_rejects_unknown_event_type() {
+        let err = FixtureScript::parse("events:\n  - type: bogus\n").unwrap_err();
+        assert!(format!("{:#}", err).contains("bogus"));
+    }
+}
@@ -0,0 +1,179 @@
+//! 最近帧环形缓冲区
+//!
+//! 每个流式请求保留最近 `logging.frameRingSize` 个上游帧的摘要（消息类型、事件类型、负载大小与负载开头），
+//! 平时只在内存中覆盖写入，请求以错误结束时才写入日志，用于事后排查而不必常开原始帧日志。
+//! 负载开头同样经过日志脱敏，严格模式下只记录长度
+
+use std::collections::VecDeque;
+use std::sync::OnceLock;
+
+use crate::common::redact;
+use crate::model::config::LoggingConfig;
+
+use super::parser::frame::Frame;
+
+/// 每帧保留的负载开头字节数
+const PAYLOAD_PREFIX_BYTES: usize = 64;
+
+/// 单个帧的摘要
+#[derive(Debug, Clone)]
+struct FrameSummary {
+    /// 请求内的帧序号（从 1 开始）
+    seq: u64,
+    message_type: String,
+    /// 事件类型、异常类型或错误代码
+    kind: String,
+    payload_len: usize,
+    payload_prefix: Vec<u8>,
+}
+
+/// 最近帧环形缓冲区
+#[derive(Debug)]
+pub struct FrameRing {
+    capacity: usize,
+    frames: VecDeque<FrameSummary>,
+    /// 已记录的帧总数
+    seen: u64,
+}
+
+impl FrameRing {
+    /// 创建缓冲区，容量为 0 时不记录
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            frames: VecDeque::with_capacity(capacity),
+            seen: 0,
+        }
+    }
+
+    /// 按全局配置的容量创建缓冲区
+    pub fn for_request() -> Self {
+        let capacity = CAPACITY
+            .get()
+            .copied()
+            .unwrap_or_else(|| LoggingConfig::default().frame_ring_size);
+        Self::new(capacity)
+    }
+
+    /// 记录一个帧的摘要，超出容量时覆盖最早的记录
+    pub fn record(&mut self, frame: &Frame) {
+        self.seen += 1;
+        if self.capacity == 0 {
+            return;
+        }
+        if self.frames.len() == self.capacity {
+            self.frames.pop_front();
+        }
+        let headers = &frame.headers;
+        self.frames.push_back(FrameSummary {
+            seq: self.seen,
+            message_type: frame.message_type().unwrap_or("event").to_string(),
+            kind: headers
+                .event_type()
+                .or_else(|| headers.exception_type())
+                .or_else(|| headers.error_code())
+                .unwrap_or("-")
+                .to_string(),
+            payload_len: frame.payload.len(),
+            payload_prefix: frame.payload[..frame.payload.len().min(PAYLOAD_PREFIX_BYTES)].to_vec(),
+        });
+    }
+
+    /// 将缓冲区中的帧写入日志
+    pub fn dump(&self, reason: &str) {
+        if self.frames.is_empty() {
+            return;
+        }
+        tracing::warn!(
+            "{}，最近 {} 个上游帧（共 {} 个）：",
+            reason,
+            self.frames.len(),
+            self.seen
+        );
+        for line in self.lines() {
+            tracing::warn!("  {}", line);
+        }
+    }
+
+    fn lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.frames.iter().map(|f| {
+            let prefix = String::from_utf8_lossy(&f.payload_prefix);
+            let ellipsis = if f.payload_len > f.payload_prefix.len() {
+                "…"
+            } else {
+                ""
+            };
+            format!(
+                "#{} {}/{} {} bytes: {}{}",
+                f.seq,
+                f.message_type,
+                f.kind,
+                f.payload_len,
+                redact::body(&prefix.escape_debug().to_string()),
+                ellipsis
+            )
+        })
+    }
+}
+
+static CAPACITY: OnceLock<usize> = OnceLock::new();
+
+/// 初始化每个请求保留的帧数
+///
+/// 应在应用启动时调用一次；未初始化时使用默认配置
+pub fn init(config: &LoggingConfig) {
+    let _ = CAPACITY.set(config.frame_ring_size);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::parser::header::{HeaderValue, Headers};
+
+    fn frame(event_type: &str, payload: &str) -> Frame {
+        let mut headers = Headers::new();
+        headers.insert(
+            ":message-type".to_string(),
+            HeaderValue::String("event".to_string()),
+        );
+        headers.insert(
+            ":event-type".to_string(),
+            HeaderValue::String(event_type.to_string()),
+        );
+        Frame {
+            headers,
+            payload: payload.as_bytes().to_vec(),
+        }
+    }
+
+    #[test]
+    fn test_keeps_last_frames() {
+        let mut ring = FrameRing::new(2);
+        for i in 0..5 {
+            ring.record(&frame(
+                "assistantResponseEvent",
+                &format!("{{\"content\":\"{}\"}}", i),
+            ));
+        }
+        let lines: Vec<String> = ring.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            r#"#4 event/assistantResponseEvent 15 bytes: {\"content\":\"3\"}"#
+        );
+        assert!(lines[1].starts_with("#5 "));
+    }
+
+    #[test]
+    fn test_truncates_payload_and_disables_at_zero() {
+        let mut ring = FrameRing::new(4);
+        ring.record(&frame("toolUseEvent", &"x".repeat(200)));
+        let line = ring.lines().next().unwrap();
+        assert!(line.contains("200 bytes"));
+        assert!(line.ends_with(&format!("{}…", "x".repeat(PAYLOAD_PREFIX_BYTES))));
+
+        let mut ring = FrameRing::new(0);
+        ring.record(&frame("toolUseEvent", "{}"));
+        assert_eq!(ring.lines().count(), 0);
+    }
+}
@@ -0,0 +1,194 @@
+//! 凭据健康度
+//!
+//! 按上游调用结果为每个凭据维护滚动健康度：响应延迟、错误率与限流率均为指数加权移动平均。
+//! 负载均衡按评分分配流量，降级的凭据在被连续失败禁用之前就少接或不接请求；
+//! 没有新请求时错误率、限流率与超出的延迟按半衰期衰减，降级的凭据会逐步恢复
+
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use crate::kiro::attempt::AttemptReason;
+use crate::model::config::CredentialHealthConfig;
+
+/// 新样本的权重
+const ALPHA: f64 = 0.2;
+
+/// 一次上游调用结果
+#[derive(Debug, Clone, Copy)]
+pub enum HealthSample {
+    /// 调用成功（收到响应头的耗时）
+    Success(Duration),
+    /// 调用失败
+    Failure(AttemptReason),
+}
+
+/// 单个凭据的健康度
+#[derive(Debug, Clone, Default)]
+pub struct CredentialHealth {
+    /// 平均响应延迟（毫秒）
+    latency_ms: Option<f64>,
+    error_rate: f64,
+    throttle_rate: f64,
+    samples: u64,
+    last_sample: Option<Instant>,
+}
+
+/// 健康度快照（用于 Admin API）
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct HealthSnapshot {
+    /// 健康度评分（0-1，1 为完全健康）
+    pub score: f64,
+    /// 平均响应延迟（毫秒）
+    pub latency_ms: Option<u64>,
+    /// 错误率（0-1）
+    pub error_rate: f64,
+    /// 限流率（0-1）
+    pub throttle_rate: f64,
+    /// 累计样本数
+    pub samples: u64,
+}
+
+impl CredentialHealth {
+    /// 记录一次调用结果（请求被拒绝、没有可用凭据等与凭据无关的失败不计入）
+    pub fn record(&mut self, sample: HealthSample, now: Instant, config: &CredentialHealthConfig) {
+        let (error, throttle) = match sample {
+            HealthSample::Success(_) => (0.0, 0.0),
+            HealthSample::Failure(AttemptReason::Throttled) => (0.0, 1.0),
+            HealthSample::Failure(AttemptReason::Rejected | AttemptReason::NoCredential) => return,
+            HealthSample::Failure(_) => (1.0, 0.0),
+        };
+
+        *self = self.decayed(now, config);
+        self.error_rate += ALPHA * (error - self.error_rate);
+        self.throttle_rate += ALPHA * (throttle - self.throttle_rate);
+        if let HealthSample::Success(latency) = sample {
+            let latency = latency.as_secs_f64() * 1000.0;
+            self.latency_ms = Some(match self.latency_ms {
+                Some(avg) => avg + ALPHA * (latency - avg),
+                None => latency,
+            });
+        }
+        self.samples += 1;
+        self.last_sample = Some(now);
+    }
+
+    /// 健康度评分（0-1）
+    pub fn score(&self, now: Instant, config: &CredentialHealthConfig) -> f64 {
+        let health = self.decayed(now, config);
+        let latency_factor = match health.latency_ms {
+            Some(latency) if latency > config.latency_target_ms as f64 => {
+                config.latency_target_ms as f64 / latency
+            }
+            _ => 1.0,
+        };
+        (1.0 - health.error_rate) * (1.0 - health.throttle_rate) * latency_factor
+    }
+
+    /// 是否已降级
+    pub fn is_degraded(&self, now: Instant, config: &CredentialHealthConfig) -> bool {
+        self.score(now, config) < config.min_score
+    }
+
+    pub fn snapshot(&self, now: Instant, config: &CredentialHealthConfig) -> HealthSnapshot {
+        let health = self.decayed(now, config);
+        HealthSnapshot {
+            score: round3(self.score(now, config)),
+            latency_ms: health.latency_ms.map(|l| l.round() as u64),
+            error_rate: round3(health.error_rate),
+            throttle_rate: round3(health.throttle_rate),
+            samples: self.samples,
+        }
+    }
+
+    /// 按距上次样本的时间衰减错误率、限流率与超出期望值的延迟
+    fn decayed(&self, now: Instant, config: &CredentialHealthConfig) -> Self {
+        let Some(last) = self.last_sample else {
+            return self.clone();
+        };
+        let half_life = config.recovery_half_life_secs.max(1) as f64;
+        let elapsed = now.saturating_duration_since(last).as_secs_f64();
+        let factor = 0.5f64.powf(elapsed / half_life);
+
+        let target = config.latency_target_ms as f64;
+        Self {
+            latency_ms: self.latency_ms.map(|latency| {
+                if latency > target {
+                    target + (latency - target) * factor
+                } else {
+                    latency
+                }
+            }),
+            error_rate: self.error_rate * factor,
+            throttle_rate: self.throttle_rate * factor,
+            samples: self.samples,
+            last_sample: self.last_sample,
+        }
+    }
+}
+
+fn round3(value: f64) -> f64 {
+    (value * 1000.0).round() / 1000.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_score_degrades_and_recovers() {
+        let config = CredentialHealthConfig::default();
+        let start = Instant::now();
+        let mut health = CredentialHealth::default();
+        assert_eq!(health.score(start, &config), 1.0);
+
+        for _ in 0..10 {
+            health.record(
+                HealthSample::Failure(AttemptReason::ServerError),
+                start,
+                &config,
+            );
+        }
+        assert!(health.is_degraded(start, &config));
+
+        // 请求被拒绝与凭据无关，不影响评分
+        let before = health.score(start, &config);
+        health.record(
+            HealthSample::Failure(AttemptReason::Rejected),
+            start,
+            &config,
+        );
+        assert_eq!(health.score(start, &config), before);
+
+        // 没有新请求时按半衰期恢复
+        let later = start + Duration::from_secs(config.recovery_half_life_secs * 4);
+        assert!(!health.is_degraded(later, &config));
+        assert!(health.score(later, &config) > 0.9);
+    }
+
+    #[test]
+    fn test_latency_and_throttle() {
+        let config = CredentialHealthConfig::default();
+        let now = Instant::now();
+
+        let mut slow = CredentialHealth::default();
+        slow.record(
+            HealthSample::Success(Duration::from_millis(config.latency_target_ms * 2)),
+            now,
+            &config,
+        );
+        assert_eq!(slow.score(now, &config), 0.5);
+
+        let mut throttled = CredentialHealth::default();
+        throttled.record(
+            HealthSample::Failure(AttemptReason::Throttled),
+            now,
+            &config,
+        );
+        let snapshot = throttled.snapshot(now, &config);
+        assert_eq!(snapshot.throttle_rate, 0.2);
+        assert_eq!(snapshot.error_rate, 0.0);
+        assert_eq!(snapshot.score, 0.8);
+    }
+}
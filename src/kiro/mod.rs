@@ -1,7 +1,14 @@
 //! Kiro API 客户端模块
 
+pub mod attempt;
+pub mod auth;
+pub mod credential_dir;
+pub mod fixture;
+pub mod frame_ring;
+pub mod health;
 pub mod machine_id;
 pub mod model;
 pub mod parser;
 pub mod provider;
+pub mod scheduler;
 pub mod token_manager;
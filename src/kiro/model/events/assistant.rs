@@ -15,7 +15,7 @@ use super::base::EventPayload;
 ///
 /// # 设计说明
 ///
-/// 此结构体只保留实际使用的 `content` 字段，其他 API 返回的字段
+/// 此结构体只保留实际使用的 `content` 和用于诊断的会话标识字段，其他 API 返回的字段
 /// 通过 `#[serde(flatten)]` 捕获到 `extra` 中，确保反序列化不会失败。
 ///
 /// # 示例
@@ -34,6 +34,10 @@ pub struct AssistantResponseEvent {
     #[serde(default)]
     pub content: String,
 
+    /// 上游回显的会话 ID
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub conversation_id: Option<String>,
+
     /// 捕获其他未使用的字段，确保反序列化兼容性
     #[serde(flatten)]
     #[serde(skip_serializing)]
@@ -51,6 +55,7 @@ impl Default for AssistantResponseEvent {
     fn default() -> Self {
         Self {
             content: String::new(),
+            conversation_id: None,
             extra: serde_json::Value::Null,
         }
     }
@@ -88,6 +93,7 @@ mod tests {
         }"#;
         let event: AssistantResponseEvent = serde_json::from_str(json).unwrap();
         assert_eq!(event.content, "Done");
+        assert_eq!(event.conversation_id.as_deref(), Some("conv-123"));
     }
 
     #[test]
@@ -16,6 +16,8 @@ pub enum EventType {
     Metering,
     /// 上下文使用率事件
     ContextUsage,
+    /// 代码引用事件
+    CodeReference,
     /// 未知事件类型
     Unknown,
 }
@@ -28,6 +30,7 @@ impl EventType {
             "toolUseEvent" => Self::ToolUse,
             "meteringEvent" => Self::Metering,
             "contextUsageEvent" => Self::ContextUsage,
+            "codeReferenceEvent" => Self::CodeReference,
             _ => Self::Unknown,
         }
     }
@@ -39,6 +42,7 @@ impl EventType {
             Self::ToolUse => "toolUseEvent",
             Self::Metering => "meteringEvent",
             Self::ContextUsage => "contextUsageEvent",
+            Self::CodeReference => "codeReferenceEvent",
             Self::Unknown => "unknown",
         }
     }
@@ -71,6 +75,8 @@ pub enum Event {
     Metering(()),
     /// 上下文使用率
     ContextUsage(super::ContextUsageEvent),
+    /// 代码引用
+    CodeReference(super::CodeReferenceEvent),
     /// 未知事件 (保留原始帧数据)
     Unknown {},
     /// 服务端错误
@@ -121,6 +127,10 @@ impl Event {
                 let payload = super::ContextUsageEvent::from_frame(&frame)?;
                 Ok(Self::ContextUsage(payload))
             }
+            EventType::CodeReference => {
+                let payload = super::CodeReferenceEvent::from_frame(&frame)?;
+                Ok(Self::CodeReference(payload))
+            }
             EventType::Unknown => Ok(Self::Unknown {}),
         }
     }
@@ -172,6 +182,10 @@ mod tests {
             EventType::from_str("contextUsageEvent"),
             EventType::ContextUsage
         );
+        assert_eq!(
+            EventType::from_str("codeReferenceEvent"),
+            EventType::CodeReference
+        );
         assert_eq!(EventType::from_str("unknown_type"), EventType::Unknown);
     }
 
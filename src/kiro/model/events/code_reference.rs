@@ -0,0 +1,56 @@
+//! 代码引用事件
+//!
+//! 处理 codeReferenceEvent 类型的事件：上游在生成内容与开源代码相似时给出的许可证归属信息
+
+use serde::Deserialize;
+
+use crate::kiro::parser::error::ParseResult;
+use crate::kiro::parser::frame::Frame;
+
+use super::base::EventPayload;
+
+/// 代码引用事件
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeReferenceEvent {
+    /// 引用列表
+    #[serde(default)]
+    pub references: Vec<CodeReference>,
+}
+
+/// 单条代码引用
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CodeReference {
+    /// 许可证名称
+    #[serde(default)]
+    pub license_name: Option<String>,
+    /// 来源仓库
+    #[serde(default)]
+    pub repository: Option<String>,
+    /// 来源链接
+    #[serde(default)]
+    pub url: Option<String>,
+    /// 补充说明
+    #[serde(default)]
+    pub information: Option<String>,
+    /// 被引用内容在本次回复中的位置
+    #[serde(default)]
+    pub recommendation_content_span: Option<ContentSpan>,
+}
+
+/// 内容区间（字符偏移）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ContentSpan {
+    #[serde(default)]
+    pub start: i64,
+    #[serde(default)]
+    pub end: i64,
+}
+
+impl EventPayload for CodeReferenceEvent {
+    fn from_frame(frame: &Frame) -> ParseResult<Self> {
+        frame.payload_as_json()
+    }
+}
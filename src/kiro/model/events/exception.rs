@@ -0,0 +1,204 @@
+//! 上游异常分类
+//!
+//! Kiro 会以异常帧（`:message-type = exception`）或错误响应体中的 `__type`
+//! 报告异常。这里把异常名归类，并给出统一的处理方式，供重试、凭据故障转移
+//! 和对外错误映射共同使用，避免各处各自按字符串判断
+
+use std::fmt;
+
+/// 异常种类
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionKind {
+    /// 输出长度达到上限（响应被截断，不是请求错误）
+    ContentLengthExceeded,
+    /// 请求频率或配额限制
+    Throttling,
+    /// 请求参数无效
+    Validation,
+    /// 凭据无效、过期或无权限
+    AccessDenied,
+    /// 请求的资源（如 profile）不存在
+    ResourceNotFound,
+    /// 上游内部错误或暂时不可用
+    ServiceUnavailable,
+    /// 未识别的异常
+    Unknown,
+}
+
+/// 异常的处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExceptionClass {
+    /// 瞬态错误：退避后重试，不计入凭据失败
+    Retryable,
+    /// 凭据问题：计入凭据失败并切换到其他凭据
+    Failover,
+    /// 终止：重试或切换凭据都无意义
+    Terminal,
+}
+
+/// 已知异常名 → 种类
+const KNOWN_EXCEPTIONS: &[(&str, ExceptionKind)] = &[
+    (
+        "ContentLengthExceededException",
+        ExceptionKind::ContentLengthExceeded,
+    ),
+    ("ThrottlingException", ExceptionKind::Throttling),
+    ("TooManyRequestsException", ExceptionKind::Throttling),
+    ("ServiceQuotaExceededException", ExceptionKind::Throttling),
+    ("ValidationException", ExceptionKind::Validation),
+    ("SerializationException", ExceptionKind::Validation),
+    ("BadRequestException", ExceptionKind::Validation),
+    ("AccessDeniedException", ExceptionKind::AccessDenied),
+    ("UnauthorizedException", ExceptionKind::AccessDenied),
+    ("ExpiredTokenException", ExceptionKind::AccessDenied),
+    ("UnrecognizedClientException", ExceptionKind::AccessDenied),
+    ("InvalidSignatureException", ExceptionKind::AccessDenied),
+    ("ResourceNotFoundException", ExceptionKind::ResourceNotFound),
+    ("InternalServerException", ExceptionKind::ServiceUnavailable),
+    ("InternalFailure", ExceptionKind::ServiceUnavailable),
+    (
+        "ServiceUnavailableException",
+        ExceptionKind::ServiceUnavailable,
+    ),
+    (
+        "ModelStreamErrorException",
+        ExceptionKind::ServiceUnavailable,
+    ),
+];
+
+/// 去掉异常名的命名空间与附加信息
+///
+/// 兼容 `com.amazon.aws.codewhisperer#ThrottlingException` 和
+/// `ThrottlingException:http://internal.amazon.com/...` 这类带命名空间的写法
+pub fn exception_name(exception_type: &str) -> &str {
+    exception_type
+        .rsplit('#')
+        .next()
+        .unwrap_or(exception_type)
+        .split(':')
+        .next()
+        .unwrap_or_default()
+        .trim()
+}
+
+impl ExceptionKind {
+    /// 按异常名分类（见 [`exception_name`]）
+    pub fn from_type(exception_type: &str) -> Self {
+        let name = exception_name(exception_type);
+        KNOWN_EXCEPTIONS
+            .iter()
+            .find(|(known, _)| *known == name)
+            .map(|(_, kind)| *kind)
+            .unwrap_or(ExceptionKind::Unknown)
+    }
+
+    /// 从错误响应体中识别异常
+    ///
+    /// 优先读取 JSON 的 `__type` 字段，否则在文本中查找已知异常名；
+    /// 无法识别时返回 None
+    pub fn from_error_body(body: &str) -> Option<Self> {
+        if let Ok(value) = serde_json::from_str::<serde_json::Value>(body)
+            && let Some(exception_type) = value.get("__type").and_then(|v| v.as_str())
+        {
+            let kind = Self::from_type(exception_type);
+            if kind != ExceptionKind::Unknown {
+                return Some(kind);
+            }
+        }
+
+        KNOWN_EXCEPTIONS
+            .iter()
+            .find(|(known, _)| body.contains(known))
+            .map(|(_, kind)| *kind)
+    }
+
+    /// 处理方式
+    pub fn class(self) -> ExceptionClass {
+        match self {
+            ExceptionKind::Throttling | ExceptionKind::ServiceUnavailable => {
+                ExceptionClass::Retryable
+            }
+            ExceptionKind::AccessDenied => ExceptionClass::Failover,
+            ExceptionKind::ContentLengthExceeded
+            | ExceptionKind::Validation
+            | ExceptionKind::ResourceNotFound => ExceptionClass::Terminal,
+            // 未识别的异常按瞬态错误处理，与未知状态码的兜底策略一致
+            ExceptionKind::Unknown => ExceptionClass::Retryable,
+        }
+    }
+
+    /// 是否值得重试（包括切换凭据后重试）
+    pub fn is_retryable(self) -> bool {
+        self.class() != ExceptionClass::Terminal
+    }
+}
+
+impl fmt::Display for ExceptionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ExceptionKind::ContentLengthExceeded => "content_length_exceeded",
+            ExceptionKind::Throttling => "throttling",
+            ExceptionKind::Validation => "validation",
+            ExceptionKind::AccessDenied => "access_denied",
+            ExceptionKind::ResourceNotFound => "resource_not_found",
+            ExceptionKind::ServiceUnavailable => "service_unavailable",
+            ExceptionKind::Unknown => "unknown",
+        };
+        f.write_str(name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_type_strips_namespace() {
+        assert_eq!(
+            ExceptionKind::from_type("ThrottlingException"),
+            ExceptionKind::Throttling
+        );
+        assert_eq!(
+            ExceptionKind::from_type("com.amazon.aws.codewhisperer#ValidationException"),
+            ExceptionKind::Validation
+        );
+        assert_eq!(
+            ExceptionKind::from_type("AccessDeniedException:http://internal.amazon.com/coral/"),
+            ExceptionKind::AccessDenied
+        );
+        assert_eq!(
+            ExceptionKind::from_type("SomethingNewException"),
+            ExceptionKind::Unknown
+        );
+    }
+
+    #[test]
+    fn test_from_error_body() {
+        assert_eq!(
+            ExceptionKind::from_error_body(
+                r#"{"__type":"com.amazon.aws.codewhisperer#ThrottlingException","message":"slow down"}"#
+            ),
+            Some(ExceptionKind::Throttling)
+        );
+        assert_eq!(
+            ExceptionKind::from_error_body("ExpiredTokenException: token expired"),
+            Some(ExceptionKind::AccessDenied)
+        );
+        assert_eq!(
+            ExceptionKind::from_error_body(r#"{"message":"oops"}"#),
+            None
+        );
+    }
+
+    #[test]
+    fn test_classification() {
+        assert_eq!(ExceptionKind::Throttling.class(), ExceptionClass::Retryable);
+        assert_eq!(
+            ExceptionKind::AccessDenied.class(),
+            ExceptionClass::Failover
+        );
+        assert_eq!(ExceptionKind::Validation.class(), ExceptionClass::Terminal);
+        assert!(!ExceptionKind::ContentLengthExceeded.is_retryable());
+        assert!(ExceptionKind::ServiceUnavailable.is_retryable());
+    }
+}
@@ -4,10 +4,14 @@
 
 mod assistant;
 mod base;
+mod code_reference;
 mod context_usage;
+mod exception;
 mod tool_use;
 
 pub use assistant::AssistantResponseEvent;
 pub use base::Event;
+pub use code_reference::{CodeReference, CodeReferenceEvent};
 pub use context_usage::ContextUsageEvent;
+pub use exception::{ExceptionClass, ExceptionKind, exception_name};
 pub use tool_use::ToolUseEvent;
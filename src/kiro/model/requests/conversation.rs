@@ -150,6 +150,9 @@ pub struct UserInputMessageContext {
     /// 可用工具列表
     #[serde(default, skip_serializing_if = "Vec::is_empty")]
     pub tools: Vec<Tool>,
+    /// 编辑器状态（当前文件、光标位置、打开的文件与工作区目录）
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub editor_state: Option<Box<EditorState>>,
 }
 
 impl UserInputMessageContext {
@@ -169,6 +172,81 @@ impl UserInputMessageContext {
         self.tool_results = results;
         self
     }
+
+    /// 设置编辑器状态
+    pub fn with_editor_state(mut self, editor_state: EditorState) -> Self {
+        self.editor_state = Some(Box::new(editor_state));
+        self
+    }
+}
+
+/// 编辑器状态
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct EditorState {
+    /// 当前编辑的文件
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub document: Option<TextDocument>,
+    /// 当前文件中的光标位置或选区
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cursor_state: Option<CursorState>,
+    /// 其他相关文件（如编辑器中打开的文件）
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub relevant_documents: Vec<TextDocument>,
+    /// 是否参考 relevantDocuments
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub use_relevant_documents: Option<bool>,
+    /// 工作区目录
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub workspace_folders: Vec<String>,
+}
+
+/// 文本文件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TextDocument {
+    /// 相对于工作区的文件路径
+    pub relative_file_path: String,
+    /// 编程语言
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub programming_language: Option<ProgrammingLanguage>,
+    /// 文件内容
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+}
+
+/// 编程语言
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ProgrammingLanguage {
+    /// 语言名称（如 "rust"、"typescript"）
+    pub language_name: String,
+}
+
+/// 光标状态（光标位置或选区，二选一）
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CursorState {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub position: Option<Position>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub range: Option<Range>,
+}
+
+/// 文件中的位置（行、列均从 0 开始）
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+/// 文件中的区间
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Range {
+    pub start: Position,
+    pub end: Position,
 }
 
 /// Kiro 图片
@@ -31,7 +31,7 @@
 //! ```
 
 use super::error::{ParseError, ParseResult};
-use super::frame::{Frame, PRELUDE_SIZE, parse_frame};
+use super::frame::{Frame, PRELUDE_SIZE, is_plausible_prelude, parse_frame};
 use bytes::{Buf, BytesMut};
 
 /// 默认最大缓冲区大小 (16 MB)
@@ -40,6 +40,9 @@ pub const DEFAULT_MAX_BUFFER_SIZE: usize = 16 * 1024 * 1024;
 /// 默认最大连续错误数
 pub const DEFAULT_MAX_ERRORS: usize = 5;
 
+/// 容错恢复时向前扫描帧边界的最大字节数
+pub const RESYNC_WINDOW: usize = 64 * 1024;
+
 /// 默认初始缓冲区容量
 pub const DEFAULT_BUFFER_CAPACITY: usize = 8192;
 
@@ -62,6 +65,21 @@ pub enum DecoderState {
     Stopped,
 }
 
+/// 解码统计快照
+///
+/// 用于按请求观察上游数据质量（帧数、字节数、跳过字节、解析错误）
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DecoderStats {
+    /// 已解码的帧数量
+    pub frames_decoded: usize,
+    /// 累计接收的字节数
+    pub bytes_received: usize,
+    /// 容错恢复时跳过的字节数
+    pub bytes_skipped: usize,
+    /// 累计解析错误数（不因成功解码而清零）
+    pub parse_errors: usize,
+}
+
 /// 流式事件解码器
 ///
 /// 用于从字节流中解析 AWS Event Stream 消息帧
@@ -99,6 +117,10 @@ pub struct EventStreamDecoder {
     max_buffer_size: usize,
     /// 跳过的字节数（用于调试）
     bytes_skipped: usize,
+    /// 累计接收的字节数
+    bytes_received: usize,
+    /// 累计解析错误数
+    parse_errors: usize,
 }
 
 impl Default for EventStreamDecoder {
@@ -123,6 +145,8 @@ impl EventStreamDecoder {
             max_errors: DEFAULT_MAX_ERRORS,
             max_buffer_size: DEFAULT_MAX_BUFFER_SIZE,
             bytes_skipped: 0,
+            bytes_received: 0,
+            parse_errors: 0,
         }
     }
 
@@ -136,6 +160,8 @@ impl EventStreamDecoder {
             max_errors,
             max_buffer_size,
             bytes_skipped: 0,
+            bytes_received: 0,
+            parse_errors: 0,
         }
     }
 
@@ -155,6 +181,7 @@ impl EventStreamDecoder {
         }
 
         self.buffer.extend_from_slice(data);
+        self.bytes_received += data.len();
 
         // 从 Recovering 状态恢复到 Ready
         if self.state == DecoderState::Recovering {
@@ -204,6 +231,7 @@ impl EventStreamDecoder {
             }
             Err(e) => {
                 self.error_count += 1;
+                self.parse_errors += 1;
                 let error_msg = e.to_string();
 
                 // 检查是否超过最大错误数
@@ -236,30 +264,30 @@ impl EventStreamDecoder {
     /// 尝试容错恢复
     ///
     /// 根据错误类型采用不同的恢复策略（参考 kiro-kt 的设计）：
-    /// - Prelude 阶段错误（CRC 失败、长度异常）：跳过 1 字节，尝试找下一帧边界
-    /// - Data 阶段错误（Message CRC 失败、Header 解析失败）：跳过整个损坏帧
+    /// - Prelude 阶段错误（CRC 失败、长度异常）：向前扫描到下一个合理的 prelude
+    /// - Data 阶段错误（Message CRC 失败、Header 解析失败、未知头部值类型）：跳过整个损坏帧
     fn try_recover(&mut self, error: &ParseError) {
         if self.buffer.is_empty() {
             return;
         }
 
         match error {
-            // Prelude 阶段错误：可能是帧边界错位，逐字节扫描找下一个有效边界
+            // Prelude 阶段错误：可能是帧边界错位，扫描找下一个有效边界
             ParseError::PreludeCrcMismatch { .. }
             | ParseError::MessageTooSmall { .. }
             | ParseError::MessageTooLarge { .. } => {
-                let skipped_byte = self.buffer[0];
-                self.buffer.advance(1);
-                self.bytes_skipped += 1;
+                let skipped = self.resync();
                 tracing::warn!(
-                    "Prelude 错误恢复: 跳过字节 0x{:02x} (累计跳过 {} 字节)",
-                    skipped_byte,
+                    "Prelude 错误恢复: 跳过 {} 字节 (累计跳过 {} 字节)",
+                    skipped,
                     self.bytes_skipped
                 );
             }
 
             // Data 阶段错误：帧边界正确但数据损坏，跳过整个帧
-            ParseError::MessageCrcMismatch { .. } | ParseError::HeaderParseFailed(_) => {
+            ParseError::MessageCrcMismatch { .. }
+            | ParseError::HeaderParseFailed(_)
+            | ParseError::InvalidHeaderType(_) => {
                 // 尝试读取 total_length 来跳过整帧
                 if self.buffer.len() >= PRELUDE_SIZE {
                     let total_length = u32::from_be_bytes([
@@ -278,13 +306,11 @@ impl EventStreamDecoder {
                     }
                 }
 
-                // 无法确定帧长度，回退到逐字节跳过
-                let skipped_byte = self.buffer[0];
-                self.buffer.advance(1);
-                self.bytes_skipped += 1;
+                // 无法确定帧长度，回退到扫描下一个帧边界
+                let skipped = self.resync();
                 tracing::warn!(
-                    "Data 错误恢复 (回退): 跳过字节 0x{:02x} (累计跳过 {} 字节)",
-                    skipped_byte,
+                    "Data 错误恢复 (回退): 跳过 {} 字节 (累计跳过 {} 字节)",
+                    skipped,
                     self.bytes_skipped
                 );
             }
@@ -303,6 +329,26 @@ impl EventStreamDecoder {
         }
     }
 
+    /// 跳到下一个合理的 prelude，返回跳过的字节数
+    ///
+    /// 在 `RESYNC_WINDOW` 内逐个位置检查长度范围与 prelude CRC，而不是每次只跳 1 字节后
+    /// 重新解析，避免在帧中间反复产生 CRC 错误。未找到时跳过已检查的部分；缓冲区末尾
+    /// 不足一个 prelude 的字节保留，等更多数据到达后再判断
+    fn resync(&mut self) -> usize {
+        let last = self
+            .buffer
+            .len()
+            .saturating_sub(PRELUDE_SIZE)
+            .min(RESYNC_WINDOW);
+        let skip = (1..=last)
+            .find(|&i| is_plausible_prelude(&self.buffer[i..]))
+            .unwrap_or(last + 1)
+            .min(self.buffer.len());
+        self.buffer.advance(skip);
+        self.bytes_skipped += skip;
+        skip
+    }
+
     // ==================== 生命周期管理方法 ====================
 
     /// 重置解码器到初始状态
@@ -314,6 +360,8 @@ impl EventStreamDecoder {
         self.frames_decoded = 0;
         self.error_count = 0;
         self.bytes_skipped = 0;
+        self.bytes_received = 0;
+        self.parse_errors = 0;
     }
 
     /// 获取当前状态
@@ -351,6 +399,16 @@ impl EventStreamDecoder {
         self.bytes_skipped
     }
 
+    /// 获取解码统计快照
+    pub fn stats(&self) -> DecoderStats {
+        DecoderStats {
+            frames_decoded: self.frames_decoded,
+            bytes_received: self.bytes_received,
+            bytes_skipped: self.bytes_skipped,
+            parse_errors: self.parse_errors,
+        }
+    }
+
     /// 获取缓冲区中待处理的字节数
     pub fn buffer_len(&self) -> usize {
         self.buffer.len()
@@ -462,4 +520,79 @@ mod tests {
         assert!(decoder.is_ready());
         assert_eq!(decoder.error_count(), 0);
     }
+
+    #[test]
+    fn test_decoder_stats() {
+        let mut decoder = EventStreamDecoder::new();
+        decoder.feed(&[0u8; 20]).unwrap();
+
+        // 全零数据无法构成合法帧，解析失败并跳过字节；
+        // 末尾不足一个 prelude 的 11 字节保留等待更多数据
+        assert!(decoder.decode().is_err());
+        assert!(decoder.decode().unwrap().is_none());
+
+        let stats = decoder.stats();
+        assert_eq!(stats.bytes_received, 20);
+        assert_eq!(stats.parse_errors, 1);
+        assert_eq!(stats.bytes_skipped, 9);
+        assert_eq!(stats.frames_decoded, 0);
+
+        decoder.reset();
+        assert_eq!(decoder.stats(), DecoderStats::default());
+    }
+
+    #[test]
+    fn test_decoder_skips_frame_with_unknown_header_type() {
+        use crate::kiro::parser::crc::crc32;
+        use crate::kiro::parser::encoder::encode_event;
+
+        // CRC 正确但头部值类型未知 (10) 的帧
+        let headers = [1u8, b'x', 10];
+        let total_length = PRELUDE_SIZE + headers.len() + 4;
+        let mut bad = Vec::new();
+        bad.extend_from_slice(&(total_length as u32).to_be_bytes());
+        bad.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+        bad.extend_from_slice(&crc32(&bad).to_be_bytes());
+        bad.extend_from_slice(&headers);
+        bad.extend_from_slice(&crc32(&bad).to_be_bytes());
+
+        let mut decoder = EventStreamDecoder::new();
+        decoder.feed(&bad).unwrap();
+        decoder
+            .feed(&encode_event("assistantResponseEvent", b"{}"))
+            .unwrap();
+
+        assert!(matches!(
+            decoder.decode(),
+            Err(ParseError::InvalidHeaderType(10))
+        ));
+        assert_eq!(decoder.stats().bytes_skipped, total_length);
+        let frame = decoder.decode().unwrap().unwrap();
+        assert_eq!(frame.event_type(), Some("assistantResponseEvent"));
+    }
+
+    #[test]
+    fn test_decoder_resyncs_to_next_prelude() {
+        use crate::kiro::parser::encoder::encode_event;
+
+        let frame = encode_event("assistantResponseEvent", br#"{"content":"hi"}"#);
+        // 截断的帧尾部 + 无法构成 prelude 的垃圾数据
+        let mut data = frame[frame.len() / 2..].to_vec();
+        data.extend_from_slice(&[0xff; 100]);
+        let garbage = data.len();
+        data.extend_from_slice(&frame);
+        data.extend_from_slice(&frame);
+
+        let mut decoder = EventStreamDecoder::new();
+        decoder.feed(&data).unwrap();
+
+        // 一次恢复即对齐到下一帧，不会在帧中间反复出错
+        assert!(decoder.decode().is_err());
+        assert_eq!(decoder.stats().bytes_skipped, garbage);
+        for _ in 0..2 {
+            let frame = decoder.decode().unwrap().unwrap();
+            assert_eq!(frame.event_type(), Some("assistantResponseEvent"));
+        }
+        assert_eq!(decoder.stats().parse_errors, 1);
+    }
 }
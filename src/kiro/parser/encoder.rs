@@ -0,0 +1,164 @@
+//! AWS Event Stream 编码器
+//!
+//! 与解码器对称，把头部和负载编码为完整的消息帧，
+//! 主要用于生成解码器/流处理测试所需的二进制样本
+
+use super::crc::crc32;
+use super::frame::PRELUDE_SIZE;
+use super::header::{HeaderValue, HeaderValueType};
+
+/// 编码单个头部值（类型标识 + 值）
+fn encode_header_value(value: &HeaderValue, out: &mut Vec<u8>) {
+    match value {
+        HeaderValue::Bool(true) => out.push(HeaderValueType::BoolTrue as u8),
+        HeaderValue::Bool(false) => out.push(HeaderValueType::BoolFalse as u8),
+        HeaderValue::Byte(v) => {
+            out.push(HeaderValueType::Byte as u8);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        HeaderValue::Short(v) => {
+            out.push(HeaderValueType::Short as u8);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        HeaderValue::Integer(v) => {
+            out.push(HeaderValueType::Integer as u8);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        HeaderValue::Long(v) => {
+            out.push(HeaderValueType::Long as u8);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        HeaderValue::Timestamp(v) => {
+            out.push(HeaderValueType::Timestamp as u8);
+            out.extend_from_slice(&v.to_be_bytes());
+        }
+        HeaderValue::ByteArray(bytes) => {
+            out.push(HeaderValueType::ByteArray as u8);
+            out.extend_from_slice(&(bytes.len() as u16).to_be_bytes());
+            out.extend_from_slice(bytes);
+        }
+        HeaderValue::String(s) => {
+            out.push(HeaderValueType::String as u8);
+            out.extend_from_slice(&(s.len() as u16).to_be_bytes());
+            out.extend_from_slice(s.as_bytes());
+        }
+        HeaderValue::Uuid(uuid) => {
+            out.push(HeaderValueType::Uuid as u8);
+            out.extend_from_slice(uuid);
+        }
+    }
+}
+
+/// 编码头部列表
+///
+/// 头部按给定顺序写出，保证生成的样本字节稳定
+pub fn encode_headers(headers: &[(&str, HeaderValue)]) -> Vec<u8> {
+    let mut out = Vec::new();
+    for (name, value) in headers {
+        out.push(name.len() as u8);
+        out.extend_from_slice(name.as_bytes());
+        encode_header_value(value, &mut out);
+    }
+    out
+}
+
+/// 编码完整消息帧
+///
+/// 格式: total_length(4) | header_length(4) | prelude_crc(4) | headers | payload | message_crc(4)
+pub fn encode_frame(headers: &[(&str, HeaderValue)], payload: &[u8]) -> Vec<u8> {
+    let header_bytes = encode_headers(headers);
+    let total_length = PRELUDE_SIZE + header_bytes.len() + payload.len() + 4;
+
+    let mut out = Vec::with_capacity(total_length);
+    out.extend_from_slice(&(total_length as u32).to_be_bytes());
+    out.extend_from_slice(&(header_bytes.len() as u32).to_be_bytes());
+    let prelude_crc = crc32(&out);
+    out.extend_from_slice(&prelude_crc.to_be_bytes());
+    out.extend_from_slice(&header_bytes);
+    out.extend_from_slice(payload);
+    let message_crc = crc32(&out);
+    out.extend_from_slice(&message_crc.to_be_bytes());
+    out
+}
+
+/// 编码事件帧（:message-type = event）
+pub fn encode_event(event_type: &str, payload: &[u8]) -> Vec<u8> {
+    encode_frame(
+        &[
+            (":message-type", HeaderValue::String("event".to_string())),
+            (":event-type", HeaderValue::String(event_type.to_string())),
+            (
+                ":content-type",
+                HeaderValue::String("application/json".to_string()),
+            ),
+        ],
+        payload,
+    )
+}
+
+/// 编码异常帧（:message-type = exception）
+pub fn encode_exception(exception_type: &str, message: &str) -> Vec<u8> {
+    encode_frame(
+        &[
+            (
+                ":message-type",
+                HeaderValue::String("exception".to_string()),
+            ),
+            (
+                ":exception-type",
+                HeaderValue::String(exception_type.to_string()),
+            ),
+        ],
+        message.as_bytes(),
+    )
+}
+
+/// 编码错误帧（:message-type = error）
+pub fn encode_error(error_code: &str, message: &str) -> Vec<u8> {
+    encode_frame(
+        &[
+            (":message-type", HeaderValue::String("error".to_string())),
+            (":error-code", HeaderValue::String(error_code.to_string())),
+        ],
+        message.as_bytes(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kiro::parser::frame::parse_frame;
+
+    #[test]
+    fn test_encode_frame_roundtrip() {
+        let headers = [
+            (":message-type", HeaderValue::String("event".to_string())),
+            ("flag", HeaderValue::Bool(true)),
+            ("count", HeaderValue::Integer(-7)),
+            ("ts", HeaderValue::Timestamp(1_700_000_000_000)),
+            ("raw", HeaderValue::ByteArray(vec![1, 2, 3])),
+            ("id", HeaderValue::Uuid([9u8; 16])),
+        ];
+        let bytes = encode_frame(&headers, b"{\"content\":\"hi\"}");
+
+        let (frame, consumed) = parse_frame(&bytes).unwrap().unwrap();
+        assert_eq!(consumed, bytes.len());
+        assert_eq!(frame.message_type(), Some("event"));
+        assert_eq!(frame.headers.get("flag"), Some(&HeaderValue::Bool(true)));
+        assert_eq!(frame.headers.get("count"), Some(&HeaderValue::Integer(-7)));
+        assert_eq!(
+            frame.headers.get("raw"),
+            Some(&HeaderValue::ByteArray(vec![1, 2, 3]))
+        );
+        assert_eq!(frame.payload_as_str(), "{\"content\":\"hi\"}");
+    }
+
+    #[test]
+    fn test_encode_exception_roundtrip() {
+        let bytes = encode_exception("ThrottlingException", "slow down");
+        let (frame, _) = parse_frame(&bytes).unwrap().unwrap();
+        assert_eq!(frame.message_type(), Some("exception"));
+        assert_eq!(frame.headers.exception_type(), Some("ThrottlingException"));
+        assert_eq!(frame.payload_as_str(), "slow down");
+    }
+}
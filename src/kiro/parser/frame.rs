@@ -60,6 +60,22 @@ impl Frame {
     }
 }
 
+/// 判断缓冲区开头是否像一个帧的 prelude
+///
+/// 长度字段在合法范围内且 prelude CRC 正确，用于容错恢复时寻找下一个帧边界
+pub fn is_plausible_prelude(buffer: &[u8]) -> bool {
+    if buffer.len() < PRELUDE_SIZE {
+        return false;
+    }
+    let total_length = u32::from_be_bytes([buffer[0], buffer[1], buffer[2], buffer[3]]);
+    let header_length = u32::from_be_bytes([buffer[4], buffer[5], buffer[6], buffer[7]]);
+    let prelude_crc = u32::from_be_bytes([buffer[8], buffer[9], buffer[10], buffer[11]]);
+
+    (MIN_MESSAGE_SIZE as u32..=MAX_MESSAGE_SIZE).contains(&total_length)
+        && header_length <= total_length - MIN_MESSAGE_SIZE as u32
+        && crc32(&buffer[..8]) == prelude_crc
+}
+
 /// 尝试从缓冲区解析一个完整的帧
 ///
 /// 这是一个无状态的纯函数，每次调用独立解析。
@@ -0,0 +1,186 @@
+//! 解析器模糊测试入口
+//!
+//! 上游字节流不完全可信，解码器还会跳过损坏数据重新对齐帧边界，这里集中定义模糊测试的
+//! 驱动函数与不变量，供 `fuzz/` 下的 cargo-fuzz 目标和 `fuzz-smoke` 特性下的短时冒烟测试共用：
+//!
+//! ```text
+//! cargo fuzz run decode             # 长时间运行（需要 nightly 与 cargo-fuzz）
+//! cargo test --features fuzz-smoke  # 基于种子语料的确定性变异，适合 CI
+//! ```
+
+use super::decoder::EventStreamDecoder;
+use super::frame::{self, MIN_MESSAGE_SIZE};
+
+/// 模糊测试中解码器的缓冲区上限，避免超大长度字段让单个用例分配过多内存
+const MAX_BUFFER_SIZE: usize = 64 * 1024;
+
+/// 解码器的最大连续错误数（与默认值一致）
+const MAX_ERRORS: usize = super::decoder::DEFAULT_MAX_ERRORS;
+
+/// 解析单个帧
+pub fn parse_frame(data: &[u8]) {
+    if let Ok(Some((frame, consumed))) = frame::parse_frame(data) {
+        assert!(
+            (MIN_MESSAGE_SIZE..=data.len()).contains(&consumed),
+            "consumed {} of {} bytes",
+            consumed,
+            data.len()
+        );
+        assert!(frame.payload.len() <= consumed - MIN_MESSAGE_SIZE);
+        let _ = frame.message_type();
+        let _ = frame.event_type();
+        let _ = frame.payload_as_str();
+    }
+}
+
+/// 按任意分块把字节流送入解码器
+///
+/// 最后一个字节决定分块大小（数据本身仍全部送入），模拟上游任意切分的网络包
+pub fn decode(data: &[u8]) {
+    let Some(&last) = data.last() else {
+        return;
+    };
+    let chunk_size = last as usize % 64 + 1;
+    let mut decoder = EventStreamDecoder::with_config(1024, MAX_ERRORS, MAX_BUFFER_SIZE);
+    let mut fed = 0;
+
+    for chunk in data.chunks(chunk_size) {
+        if decoder.is_stopped() {
+            decoder.try_resume();
+        }
+        if decoder.feed(chunk).is_err() {
+            break;
+        }
+        fed += chunk.len();
+
+        loop {
+            let before = decoder.buffer_len();
+            match decoder.decode() {
+                Ok(Some(_)) => {}
+                Ok(None) => break,
+                Err(_) if decoder.is_stopped() => break,
+                Err(_) => {}
+            }
+            // 每次成功解码或容错恢复都必须消耗字节，否则调用方会陷入死循环
+            assert!(
+                decoder.buffer_len() < before,
+                "decoder made no progress ({} bytes buffered)",
+                before
+            );
+        }
+
+        let stats = decoder.stats();
+        assert_eq!(stats.bytes_received, fed);
+        assert!(stats.bytes_skipped + decoder.buffer_len() <= fed);
+        assert!(decoder.buffer_len() <= MAX_BUFFER_SIZE);
+    }
+}
+
+#[cfg(all(test, feature = "fuzz-smoke"))]
+mod smoke {
+    use std::path::Path;
+
+    use super::*;
+    use crate::kiro::parser::crc::crc32;
+    use crate::kiro::parser::frame::PRELUDE_SIZE;
+
+    /// 每个目标的变异次数，可通过 `FUZZ_SMOKE_ITERATIONS` 调整
+    fn iterations() -> usize {
+        std::env::var("FUZZ_SMOKE_ITERATIONS")
+            .ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(5_000)
+    }
+
+    fn corpus(target: &str) -> Vec<Vec<u8>> {
+        let dir = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("fuzz/corpus")
+            .join(target);
+        let mut entries: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap_or_else(|e| panic!("读取语料目录 {} 失败: {}", dir.display(), e))
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        entries.sort();
+        let corpus: Vec<_> = entries
+            .iter()
+            .map(|path| std::fs::read(path).unwrap())
+            .collect();
+        assert!(!corpus.is_empty(), "语料目录 {} 为空", dir.display());
+        corpus
+    }
+
+    /// 修复首帧的 prelude 与 message CRC，让变异能越过校验到达头部解析
+    fn fix_crcs(data: &mut [u8]) {
+        if data.len() < MIN_MESSAGE_SIZE {
+            return;
+        }
+        let total = u32::from_be_bytes([data[0], data[1], data[2], data[3]]) as usize;
+        let crc = crc32(&data[..8]).to_be_bytes();
+        data[8..PRELUDE_SIZE].copy_from_slice(&crc);
+        if (MIN_MESSAGE_SIZE..=data.len()).contains(&total) {
+            let crc = crc32(&data[..total - 4]).to_be_bytes();
+            data[total - 4..total].copy_from_slice(&crc);
+        }
+    }
+
+    fn mutate(rng: &mut fastrand::Rng, corpus: &[Vec<u8>]) -> Vec<u8> {
+        let mut data = corpus[rng.usize(..corpus.len())].clone();
+        for _ in 0..rng.usize(1..4) {
+            match rng.u8(..6) {
+                // 翻转若干位
+                0 if !data.is_empty() => {
+                    let i = rng.usize(..data.len());
+                    data[i] ^= 1 << rng.u8(..8);
+                }
+                // 覆盖为边界值
+                1 if !data.is_empty() => {
+                    let i = rng.usize(..data.len());
+                    data[i] = [0x00, 0x01, 0x7f, 0x80, 0xff][rng.usize(..5)];
+                }
+                // 截断
+                2 if !data.is_empty() => data.truncate(rng.usize(..data.len())),
+                // 插入随机字节
+                3 => {
+                    let i = rng.usize(..=data.len());
+                    let bytes: Vec<u8> = (0..rng.usize(1..16)).map(|_| rng.u8(..)).collect();
+                    data.splice(i..i, bytes);
+                }
+                // 拼接另一个样本的片段
+                4 => {
+                    let other = &corpus[rng.usize(..corpus.len())];
+                    let start = rng.usize(..=other.len());
+                    data.extend_from_slice(&other[start..]);
+                }
+                // 在帧内变异后修复 CRC
+                _ if data.len() > PRELUDE_SIZE => {
+                    let i = rng.usize(4..data.len());
+                    data[i] = rng.u8(..);
+                    fix_crcs(&mut data);
+                }
+                _ => {}
+            }
+        }
+        data
+    }
+
+    fn run(target: &str, harness: fn(&[u8])) {
+        let corpus = corpus(target);
+        for seed in &corpus {
+            harness(seed);
+        }
+        let mut rng = fastrand::Rng::with_seed(0x6b69_726f);
+        for _ in 0..iterations() {
+            harness(&mutate(&mut rng, &corpus));
+        }
+    }
+
+    #[test]
+    fn fuzz_smoke_parse_frame() {
+        run("parse_frame", parse_frame);
+    }
+
+    #[test]
+    fn fuzz_smoke_decode() {
+        run("decode", decode);
+    }
+}
@@ -136,14 +136,13 @@ pub fn parse_headers(data: &[u8], header_length: usize) -> ParseResult<Headers>
         });
     }
 
+    // 值的长度只能在头部区域内计算，越界说明头部已损坏，不能读入 payload
+    let data = &data[..header_length];
     let mut headers = Headers::new();
     let mut offset = 0;
 
-    while offset < header_length {
+    while offset < data.len() {
         // 读取头部名称长度 (1 byte)
-        if offset >= data.len() {
-            break;
-        }
         let name_len = data[offset] as usize;
         offset += 1;
 
@@ -156,26 +155,36 @@ pub fn parse_headers(data: &[u8], header_length: usize) -> ParseResult<Headers>
 
         // 读取头部名称
         if offset + name_len > data.len() {
-            return Err(ParseError::Incomplete {
-                needed: name_len,
-                available: data.len() - offset,
-            });
+            return Err(ParseError::HeaderParseFailed(format!(
+                "头部名称超出头部区域: 需要 {} 字节, 剩余 {} 字节",
+                name_len,
+                data.len() - offset
+            )));
         }
         let name = String::from_utf8_lossy(&data[offset..offset + name_len]).to_string();
         offset += name_len;
 
         // 读取值类型 (1 byte)
         if offset >= data.len() {
-            return Err(ParseError::Incomplete {
-                needed: 1,
-                available: 0,
-            });
+            return Err(ParseError::HeaderParseFailed(format!(
+                "头部 {} 缺少值类型",
+                name
+            )));
         }
         let value_type = HeaderValueType::try_from(data[offset])?;
         offset += 1;
 
         // 根据类型解析值
-        let value = parse_header_value(&data[offset..], value_type, &mut offset)?;
+        let value =
+            parse_header_value(&data[offset..], value_type, &mut offset).map_err(|e| match e {
+                ParseError::Incomplete { needed, available } => {
+                    ParseError::HeaderParseFailed(format!(
+                        "头部 {} 的值超出头部区域: 需要 {} 字节, 剩余 {} 字节",
+                        name, needed, available
+                    ))
+                }
+                other => other,
+            })?;
         headers.insert(name, value);
     }
 
@@ -314,4 +323,84 @@ mod tests {
         let headers = parse_headers(&data, data.len()).unwrap();
         assert_eq!(headers.get_string("x"), Some("ab"));
     }
+
+    /// 构造单个头部: name_len(1) + name + type(1) + value
+    fn header(name: &str, value_type: u8, value: &[u8]) -> Vec<u8> {
+        let mut data = vec![name.len() as u8];
+        data.extend_from_slice(name.as_bytes());
+        data.push(value_type);
+        data.extend_from_slice(value);
+        data
+    }
+
+    #[test]
+    fn test_parse_headers_all_value_types() {
+        let uuid: [u8; 16] = *b"0123456789abcdef";
+        let data = [
+            header("t", 0, &[]),
+            header("f", 1, &[]),
+            header("i8", 2, &[0xff]),
+            header("i16", 3, &(-300i16).to_be_bytes()),
+            header("i32", 4, &(-70_000i32).to_be_bytes()),
+            header("i64", 5, &i64::MIN.to_be_bytes()),
+            header("bytes", 6, &[0, 3, 0xde, 0xad, 0x00]),
+            header("str", 7, &[0, 2, b'o', b'k']),
+            header("ts", 8, &1_700_000_000_000i64.to_be_bytes()),
+            header("id", 9, &uuid),
+        ]
+        .concat();
+        let headers = parse_headers(&data, data.len()).unwrap();
+
+        assert_eq!(headers.get("t"), Some(&HeaderValue::Bool(true)));
+        assert_eq!(headers.get("f"), Some(&HeaderValue::Bool(false)));
+        assert_eq!(headers.get("i8"), Some(&HeaderValue::Byte(-1)));
+        assert_eq!(headers.get("i16"), Some(&HeaderValue::Short(-300)));
+        assert_eq!(headers.get("i32"), Some(&HeaderValue::Integer(-70_000)));
+        assert_eq!(headers.get("i64"), Some(&HeaderValue::Long(i64::MIN)));
+        assert_eq!(
+            headers.get("bytes"),
+            Some(&HeaderValue::ByteArray(vec![0xde, 0xad, 0x00]))
+        );
+        assert_eq!(headers.get_string("str"), Some("ok"));
+        assert_eq!(
+            headers.get("ts"),
+            Some(&HeaderValue::Timestamp(1_700_000_000_000))
+        );
+        assert_eq!(headers.get("id"), Some(&HeaderValue::Uuid(uuid)));
+    }
+
+    #[test]
+    fn test_parse_headers_value_exceeds_header_length() {
+        // 每种定长/变长类型截断 1 字节
+        for (value_type, value) in [
+            (2u8, vec![0x01]),
+            (3, vec![0; 2]),
+            (4, vec![0; 4]),
+            (5, vec![0; 8]),
+            (6, vec![0, 2, 1, 2]),
+            (7, vec![0, 2, b'a', b'b']),
+            (8, vec![0; 8]),
+            (9, vec![0; 16]),
+        ] {
+            let data = header("x", value_type, &value);
+            let err = parse_headers(&data, data.len() - 1).unwrap_err();
+            assert!(
+                matches!(err, ParseError::HeaderParseFailed(_)),
+                "type {}: {:?}",
+                value_type,
+                err
+            );
+        }
+
+        // 头部区域之后的字节（payload）不能被当作头部值读取
+        let mut data = header("s", 7, &[0, 4, b'a', b'b']);
+        let header_length = data.len();
+        data.extend_from_slice(b"cd");
+        assert!(parse_headers(&data, header_length).is_err());
+
+        assert!(matches!(
+            parse_headers(&header("x", 10, &[]), 3),
+            Err(ParseError::InvalidHeaderType(10))
+        ));
+    }
 }
@@ -5,6 +5,9 @@
 
 pub mod crc;
 pub mod decoder;
+pub mod encoder;
 pub mod error;
 pub mod frame;
+#[cfg(any(fuzzing, all(test, feature = "fuzz-smoke")))]
+pub mod fuzz;
 pub mod header;
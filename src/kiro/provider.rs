@@ -6,17 +6,21 @@
 
 use reqwest::Client;
 use reqwest::header::{AUTHORIZATION, CONNECTION, CONTENT_TYPE, HOST, HeaderMap, HeaderValue};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use tokio::time::sleep;
 use uuid::Uuid;
 
-use crate::http_client::{ProxyConfig, build_client};
+use crate::common::redact;
+use crate::http_client::{ProxyConfig, build_upstream_client};
+use crate::kiro::attempt::{AttemptReason, UpstreamAttempts};
+use crate::kiro::health::HealthSample;
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
+use crate::kiro::model::events::{ExceptionClass, ExceptionKind};
 use crate::kiro::token_manager::{CallContext, MultiTokenManager};
-use crate::model::config::TlsBackend;
+use crate::model::config::{TlsBackend, UpstreamConfig};
 use parking_lot::Mutex;
 
 /// 每个凭据的最大重试次数
@@ -25,19 +29,45 @@ const MAX_RETRIES_PER_CREDENTIAL: usize = 3;
 /// 总重试次数硬上限（避免无限重试）
 const MAX_TOTAL_RETRIES: usize = 9;
 
+/// 上游 HTTP Client 池
+///
+/// key = effective proxy config, value = reqwest::Client
+/// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client（及其连接池）
+struct ClientPool {
+    /// 全局代理配置（用于凭据无自定义代理时的回退）
+    global_proxy: Option<ProxyConfig>,
+    /// TLS 后端配置
+    tls_backend: TlsBackend,
+    /// 上游连接配置
+    upstream: UpstreamConfig,
+    clients: Mutex<HashMap<Option<ProxyConfig>, Client>>,
+}
+
+impl ClientPool {
+    fn build(&self, proxy: Option<&ProxyConfig>) -> anyhow::Result<Client> {
+        build_upstream_client(proxy, 720, self.tls_backend, &self.upstream)
+    }
+
+    /// 根据凭据的代理配置获取（或创建并缓存）对应的 reqwest::Client
+    fn client_for(&self, credentials: &KiroCredentials) -> anyhow::Result<Client> {
+        let effective = credentials.effective_proxy(self.global_proxy.as_ref());
+        let mut clients = self.clients.lock();
+        if let Some(client) = clients.get(&effective) {
+            return Ok(client.clone());
+        }
+        let client = self.build(effective.as_ref())?;
+        clients.insert(effective, client.clone());
+        Ok(client)
+    }
+}
+
 /// Kiro API Provider
 ///
 /// 核心组件，负责与 Kiro API 通信
 /// 支持多凭据故障转移和重试机制
 pub struct KiroProvider {
     token_manager: Arc<MultiTokenManager>,
-    /// 全局代理配置（用于凭据无自定义代理时的回退）
-    global_proxy: Option<ProxyConfig>,
-    /// Client 缓存：key = effective proxy config, value = reqwest::Client
-    /// 不同代理配置的凭据使用不同的 Client，共享相同代理的凭据复用 Client
-    client_cache: Mutex<HashMap<Option<ProxyConfig>, Client>>,
-    /// TLS 后端配置
-    tls_backend: TlsBackend,
+    clients: Arc<ClientPool>,
 }
 
 impl KiroProvider {
@@ -48,31 +78,50 @@ impl KiroProvider {
 
     /// 创建带代理配置的 KiroProvider 实例
     pub fn with_proxy(token_manager: Arc<MultiTokenManager>, proxy: Option<ProxyConfig>) -> Self {
-        let tls_backend = token_manager.config().tls_backend;
+        let config = token_manager.config();
+        let pool = ClientPool {
+            global_proxy: proxy,
+            tls_backend: config.tls_backend,
+            upstream: config.upstream.clone(),
+            clients: Mutex::new(HashMap::new()),
+        };
         // 预热：构建全局代理对应的 Client
-        let initial_client = build_client(proxy.as_ref(), 720, tls_backend)
+        let initial_client = pool
+            .build(pool.global_proxy.as_ref())
             .expect("创建 HTTP 客户端失败");
-        let mut cache = HashMap::new();
-        cache.insert(proxy.clone(), initial_client);
+        pool.clients
+            .lock()
+            .insert(pool.global_proxy.clone(), initial_client);
 
         Self {
             token_manager,
-            global_proxy: proxy,
-            client_cache: Mutex::new(cache),
-            tls_backend,
+            clients: Arc::new(pool),
         }
     }
 
-    /// 根据凭据的代理配置获取（或创建并缓存）对应的 reqwest::Client
     fn client_for(&self, credentials: &KiroCredentials) -> anyhow::Result<Client> {
-        let effective = credentials.effective_proxy(self.global_proxy.as_ref());
-        let mut cache = self.client_cache.lock();
-        if let Some(client) = cache.get(&effective) {
-            return Ok(client.clone());
+        self.clients.client_for(credentials)
+    }
+
+    /// 启动连接预热任务（`upstream.prewarmIntervalSecs` 为 0 时不启动）
+    ///
+    /// 定期对每个启用凭据的上游域名发送 HEAD 请求，使空闲后的首个请求无需重新建立连接和 TLS 握手
+    pub fn spawn_prewarm(&self) {
+        let interval_secs = self.clients.upstream.prewarm_interval_secs;
+        if interval_secs == 0 {
+            return;
         }
-        let client = build_client(effective.as_ref(), 720, self.tls_backend)?;
-        cache.insert(effective, client.clone());
-        Ok(client)
+
+        let token_manager = self.token_manager.clone();
+        let clients = self.clients.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(Duration::from_secs(interval_secs));
+            loop {
+                ticker.tick().await;
+                prewarm_connections(&token_manager, &clients).await;
+            }
+        });
+        tracing::info!("已启用上游连接预热，间隔 {} 秒", interval_secs);
     }
 
     /// 获取 token_manager 的引用
@@ -192,7 +241,9 @@ impl KiroProvider {
             AUTHORIZATION,
             HeaderValue::from_str(&format!("Bearer {}", ctx.token)).unwrap(),
         );
-        headers.insert(CONNECTION, HeaderValue::from_static("close"));
+        if !config.upstream.keep_alive {
+            headers.insert(CONNECTION, HeaderValue::from_static("close"));
+        }
 
         Ok(headers)
     }
@@ -294,6 +345,7 @@ impl KiroProvider {
         let total_credentials = self.token_manager.total_count();
         let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
         let mut last_error: Option<anyhow::Error> = None;
+        let mut attempts = UpstreamAttempts::default();
 
         for attempt in 0..max_retries {
             // 获取调用上下文
@@ -301,6 +353,7 @@ impl KiroProvider {
             let ctx = match self.token_manager.acquire_context(None).await {
                 Ok(c) => c,
                 Err(e) => {
+                    attempts.record(None, AttemptReason::NoCredential, None);
                     last_error = Some(e);
                     continue;
                 }
@@ -316,7 +369,8 @@ impl KiroProvider {
             };
 
             // 发送请求
-            let response = match self
+            let started = Instant::now();
+            let mut response = match self
                 .client_for(&ctx.credentials)?
                 .post(&url)
                 .headers(headers)
@@ -326,7 +380,11 @@ impl KiroProvider {
             {
                 Ok(resp) => resp,
                 Err(e) => {
+                    let reason = AttemptReason::from_send_error(&e);
+                    self.record_attempt(&mut attempts, ctx.id, reason, None);
                     tracing::warn!(
+                        reason = %reason,
+                        credential_id = ctx.id,
                         "MCP 请求发送失败（尝试 {}/{}）: {}",
                         attempt + 1,
                         max_retries,
@@ -345,6 +403,9 @@ impl KiroProvider {
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
+                self.token_manager
+                    .record_health(ctx.id, HealthSample::Success(started.elapsed()));
+                Self::attach_attempts(&mut response, attempts, "MCP");
                 return Ok(response);
             }
 
@@ -353,6 +414,12 @@ impl KiroProvider {
 
             // 402 额度用尽
             if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
+                self.record_attempt(
+                    &mut attempts,
+                    ctx.id,
+                    AttemptReason::QuotaExhausted,
+                    Some(status.as_u16()),
+                );
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
                 if !has_available {
                     anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
@@ -361,49 +428,40 @@ impl KiroProvider {
                 continue;
             }
 
-            // 400 Bad Request
-            if status.as_u16() == 400 {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
-            }
-
-            // 401/403 凭据问题
-            if matches!(status.as_u16(), 401 | 403) {
-                let has_available = self.token_manager.report_failure(ctx.id);
-                if !has_available {
-                    anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+            let reason = AttemptReason::from_response(status.as_u16(), &body);
+            self.record_attempt(&mut attempts, ctx.id, reason, Some(status.as_u16()));
+            match Self::classify_failure(status, &body) {
+                ExceptionClass::Terminal => {
+                    anyhow::bail!("MCP 请求失败: {} {}", status, body);
                 }
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
-                continue;
-            }
-
-            // 瞬态错误
-            if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
-                tracing::warn!(
-                    "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
-                    status,
-                    body
-                );
-                last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
-                if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                // 凭据问题
+                ExceptionClass::Failover => {
+                    let has_available = self.token_manager.report_failure(ctx.id);
+                    if !has_available {
+                        anyhow::bail!("MCP 请求失败（所有凭据已用尽）: {} {}", status, body);
+                    }
+                    last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                }
+                // 瞬态错误
+                ExceptionClass::Retryable => {
+                    tracing::warn!(
+                        reason = %reason,
+                        credential_id = ctx.id,
+                        "MCP 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
+                        attempt + 1,
+                        max_retries,
+                        status,
+                        redact::body(&body)
+                    );
+                    last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
+                    if attempt + 1 < max_retries {
+                        sleep(Self::retry_delay(attempt)).await;
+                    }
                 }
-                continue;
-            }
-
-            // 其他 4xx
-            if status.is_client_error() {
-                anyhow::bail!("MCP 请求失败: {} {}", status, body);
-            }
-
-            // 兜底
-            last_error = Some(anyhow::anyhow!("MCP 请求失败: {} {}", status, body));
-            if attempt + 1 < max_retries {
-                sleep(Self::retry_delay(attempt)).await;
             }
         }
 
+        Self::log_exhausted(&attempts, "MCP");
         Err(last_error.unwrap_or_else(|| {
             anyhow::anyhow!("MCP 请求失败：已达到最大重试次数（{}次）", max_retries)
         }))
@@ -423,6 +481,7 @@ impl KiroProvider {
         let total_credentials = self.token_manager.total_count();
         let max_retries = (total_credentials * MAX_RETRIES_PER_CREDENTIAL).min(MAX_TOTAL_RETRIES);
         let mut last_error: Option<anyhow::Error> = None;
+        let mut attempts = UpstreamAttempts::default();
         let api_type = if is_stream { "流式" } else { "非流式" };
 
         // 尝试从请求体中提取模型信息
@@ -433,6 +492,7 @@ impl KiroProvider {
             let ctx = match self.token_manager.acquire_context(model.as_deref()).await {
                 Ok(c) => c,
                 Err(e) => {
+                    attempts.record(None, AttemptReason::NoCredential, None);
                     last_error = Some(e);
                     continue;
                 }
@@ -448,7 +508,8 @@ impl KiroProvider {
             };
 
             // 发送请求
-            let response = match self
+            let started = Instant::now();
+            let mut response = match self
                 .client_for(&ctx.credentials)?
                 .post(&url)
                 .headers(headers)
@@ -458,7 +519,11 @@ impl KiroProvider {
             {
                 Ok(resp) => resp,
                 Err(e) => {
+                    let reason = AttemptReason::from_send_error(&e);
+                    self.record_attempt(&mut attempts, ctx.id, reason, None);
                     tracing::warn!(
+                        reason = %reason,
+                        credential_id = ctx.id,
                         "API 请求发送失败（尝试 {}/{}）: {}",
                         attempt + 1,
                         max_retries,
@@ -479,6 +544,9 @@ impl KiroProvider {
             // 成功响应
             if status.is_success() {
                 self.token_manager.report_success(ctx.id);
+                self.token_manager
+                    .record_health(ctx.id, HealthSample::Success(started.elapsed()));
+                Self::attach_attempts(&mut response, attempts, api_type);
                 return Ok(response);
             }
 
@@ -487,12 +555,20 @@ impl KiroProvider {
 
             // 402 Payment Required 且额度用尽：禁用凭据并故障转移
             if status.as_u16() == 402 && Self::is_monthly_request_limit(&body) {
+                self.record_attempt(
+                    &mut attempts,
+                    ctx.id,
+                    AttemptReason::QuotaExhausted,
+                    Some(status.as_u16()),
+                );
                 tracing::warn!(
+                    reason = %AttemptReason::QuotaExhausted,
+                    credential_id = ctx.id,
                     "API 请求失败（额度已用尽，禁用凭据并切换，尝试 {}/{}）: {} {}",
                     attempt + 1,
                     max_retries,
                     status,
-                    body
+                    redact::body(&body)
                 );
 
                 let has_available = self.token_manager.report_quota_exhausted(ctx.id);
@@ -514,87 +590,69 @@ impl KiroProvider {
                 continue;
             }
 
-            // 400 Bad Request - 请求问题，重试/切换凭据无意义
-            if status.as_u16() == 400 {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
-            }
+            let reason = AttemptReason::from_response(status.as_u16(), &body);
+            self.record_attempt(&mut attempts, ctx.id, reason, Some(status.as_u16()));
+            match Self::classify_failure(status, &body) {
+                // 400 等请求问题，重试/切换凭据无意义
+                ExceptionClass::Terminal => {
+                    anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
+                }
+                // 401/403、凭据过期等：计入失败并允许故障转移
+                ExceptionClass::Failover => {
+                    tracing::warn!(
+                        reason = %reason,
+                        credential_id = ctx.id,
+                        "API 请求失败（可能为凭据错误，尝试 {}/{}）: {} {}",
+                        attempt + 1,
+                        max_retries,
+                        status,
+                        redact::body(&body)
+                    );
 
-            // 401/403 - 更可能是凭据/权限问题：计入失败并允许故障转移
-            if matches!(status.as_u16(), 401 | 403) {
-                tracing::warn!(
-                    "API 请求失败（可能为凭据错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
-                    status,
-                    body
-                );
+                    let has_available = self.token_manager.report_failure(ctx.id);
+                    if !has_available {
+                        anyhow::bail!(
+                            "{} API 请求失败（所有凭据已用尽）: {} {}",
+                            api_type,
+                            status,
+                            body
+                        );
+                    }
 
-                let has_available = self.token_manager.report_failure(ctx.id);
-                if !has_available {
-                    anyhow::bail!(
-                        "{} API 请求失败（所有凭据已用尽）: {} {}",
+                    last_error = Some(anyhow::anyhow!(
+                        "{} API 请求失败: {} {}",
                         api_type,
                         status,
                         body
-                    );
+                    ));
                 }
-
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
-                continue;
-            }
-
-            // 429/408/5xx - 瞬态上游错误：重试但不禁用或切换凭据
-            // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
-            if matches!(status.as_u16(), 408 | 429) || status.is_server_error() {
-                tracing::warn!(
-                    "API 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
-                    attempt + 1,
-                    max_retries,
-                    status,
-                    body
-                );
-                last_error = Some(anyhow::anyhow!(
-                    "{} API 请求失败: {} {}",
-                    api_type,
-                    status,
-                    body
-                ));
-                if attempt + 1 < max_retries {
-                    sleep(Self::retry_delay(attempt)).await;
+                // 429/408/5xx、限流等瞬态上游错误：重试但不禁用或切换凭据
+                // （避免 429 high traffic / 502 high load 等瞬态错误把所有凭据锁死）
+                ExceptionClass::Retryable => {
+                    tracing::warn!(
+                        reason = %reason,
+                        credential_id = ctx.id,
+                        "API 请求失败（上游瞬态错误，尝试 {}/{}）: {} {}",
+                        attempt + 1,
+                        max_retries,
+                        status,
+                        redact::body(&body)
+                    );
+                    last_error = Some(anyhow::anyhow!(
+                        "{} API 请求失败: {} {}",
+                        api_type,
+                        status,
+                        body
+                    ));
+                    if attempt + 1 < max_retries {
+                        sleep(Self::retry_delay(attempt)).await;
+                    }
                 }
-                continue;
-            }
-
-            // 其他 4xx - 通常为请求/配置问题：直接返回，不计入凭据失败
-            if status.is_client_error() {
-                anyhow::bail!("{} API 请求失败: {} {}", api_type, status, body);
-            }
-
-            // 兜底：当作可重试的瞬态错误处理（不切换凭据）
-            tracing::warn!(
-                "API 请求失败（未知错误，尝试 {}/{}）: {} {}",
-                attempt + 1,
-                max_retries,
-                status,
-                body
-            );
-            last_error = Some(anyhow::anyhow!(
-                "{} API 请求失败: {} {}",
-                api_type,
-                status,
-                body
-            ));
-            if attempt + 1 < max_retries {
-                sleep(Self::retry_delay(attempt)).await;
             }
         }
 
         // 所有重试都失败
+        Self::log_exhausted(&attempts, api_type);
         Err(last_error.unwrap_or_else(|| {
             anyhow::anyhow!(
                 "{} API 请求失败：已达到最大重试次数（{}次）",
@@ -604,6 +662,63 @@ impl KiroProvider {
         }))
     }
 
+    /// 记录一次失败的尝试，并计入凭据健康度
+    fn record_attempt(
+        &self,
+        attempts: &mut UpstreamAttempts,
+        credential_id: u64,
+        reason: AttemptReason,
+        status: Option<u16>,
+    ) {
+        attempts.record(Some(credential_id), reason, status);
+        self.token_manager
+            .record_health(credential_id, HealthSample::Failure(reason));
+    }
+
+    /// 将尝试记录附加到成功的响应上（供 handler 生成 `x-kiro-upstream-attempts` 响应头）
+    fn attach_attempts(
+        response: &mut reqwest::Response,
+        attempts: UpstreamAttempts,
+        api_type: &str,
+    ) {
+        if !attempts.failures.is_empty() {
+            tracing::info!(
+                attempts = attempts.total(),
+                reasons = %attempts.reasons(),
+                "{} 请求在第 {} 次尝试时成功",
+                api_type,
+                attempts.total()
+            );
+        }
+        response.extensions_mut().insert(attempts);
+    }
+
+    fn log_exhausted(attempts: &UpstreamAttempts, api_type: &str) {
+        tracing::warn!(
+            attempts = attempts.failures.len(),
+            reasons = %attempts.reasons(),
+            "{} 请求重试耗尽",
+            api_type
+        );
+    }
+
+    /// 失败响应的处理方式
+    ///
+    /// 响应体中能识别出 Kiro 异常时按异常分类决定，否则按状态码：
+    /// 400/其他 4xx 终止，401/403 故障转移，408/429/5xx 及其他状态重试
+    fn classify_failure(status: reqwest::StatusCode, body: &str) -> ExceptionClass {
+        if let Some(kind) = ExceptionKind::from_error_body(body) {
+            return kind.class();
+        }
+
+        match status.as_u16() {
+            401 | 403 => ExceptionClass::Failover,
+            408 | 429 => ExceptionClass::Retryable,
+            _ if status.is_client_error() => ExceptionClass::Terminal,
+            _ => ExceptionClass::Retryable,
+        }
+    }
+
     fn retry_delay(attempt: usize) -> Duration {
         // 指数退避 + 少量抖动，避免上游抖动时放大故障
         const BASE_MS: u64 = 200;
@@ -639,6 +754,40 @@ impl KiroProvider {
     }
 }
 
+/// 对每个（代理, 上游域名）组合发送一次 HEAD 请求
+async fn prewarm_connections(token_manager: &MultiTokenManager, clients: &ClientPool) {
+    let config = token_manager.config();
+    let mut targets = HashSet::new();
+    for credentials in token_manager.enabled_credentials() {
+        let proxy = credentials.effective_proxy(clients.global_proxy.as_ref());
+        let domain = format!(
+            "q.{}.amazonaws.com",
+            credentials.effective_api_region(config)
+        );
+        if !targets.insert((proxy, domain.clone())) {
+            continue;
+        }
+
+        let client = match clients.client_for(&credentials) {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::debug!("连接预热跳过 {}: {}", domain, e);
+                continue;
+            }
+        };
+        // 只关心连接是否建立，响应状态码无意义
+        match client
+            .head(format!("https://{}/", domain))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await
+        {
+            Ok(_) => tracing::trace!("连接预热完成: {}", domain),
+            Err(e) => tracing::debug!("连接预热失败 {}: {}", domain, e),
+        }
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -697,6 +846,26 @@ mod tests {
                 .unwrap()
                 .starts_with("Bearer ")
         );
+        // 默认复用连接
+        assert!(headers.get(CONNECTION).is_none());
+    }
+
+    #[test]
+    fn test_build_headers_without_keep_alive() {
+        let mut config = Config::default();
+        let credentials = KiroCredentials {
+            refresh_token: Some("a".repeat(150)),
+            ..KiroCredentials::default()
+        };
+        config.upstream.keep_alive = false;
+
+        let provider = create_test_provider(config, credentials.clone());
+        let ctx = CallContext {
+            id: 1,
+            credentials,
+            token: "test_token".to_string(),
+        };
+        let headers = provider.build_headers(&ctx).unwrap();
         assert_eq!(headers.get(CONNECTION).unwrap(), "close");
     }
 
@@ -717,4 +886,39 @@ mod tests {
         let body = r#"{"message":"nope","reason":"DAILY_REQUEST_COUNT"}"#;
         assert!(!KiroProvider::is_monthly_request_limit(body));
     }
+    #[test]
+    fn test_classify_failure_prefers_exception_type() {
+        use reqwest::StatusCode;
+
+        // 状态码兜底
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::BAD_REQUEST, "bad"),
+            ExceptionClass::Terminal
+        );
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::FORBIDDEN, ""),
+            ExceptionClass::Failover
+        );
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::BAD_GATEWAY, ""),
+            ExceptionClass::Retryable
+        );
+
+        // 异常类型优先于状态码
+        let throttled = r#"{"__type":"com.amazon.aws.codewhisperer#ThrottlingException"}"#;
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::BAD_REQUEST, throttled),
+            ExceptionClass::Retryable
+        );
+        let expired = r#"{"__type":"ExpiredTokenException","message":"expired"}"#;
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::BAD_REQUEST, expired),
+            ExceptionClass::Failover
+        );
+        let invalid = r#"{"__type":"ValidationException","message":"bad input"}"#;
+        assert_eq!(
+            KiroProvider::classify_failure(StatusCode::INTERNAL_SERVER_ERROR, invalid),
+            ExceptionClass::Terminal
+        );
+    }
 }
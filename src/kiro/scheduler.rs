@@ -0,0 +1,324 @@
+//! 上游请求调度
+//!
+//! 配置 `scheduler.maxConcurrentRequests` 后，同时发往上游的请求数受限，超出的请求按
+//! `x-kiro-priority` 排队：
+//! - `interactive`（默认）优先放行
+//! - `batch` 排在 interactive 之后；为避免饿死，有 batch 请求排队时每连续放行
+//!   `interactiveBurst` 个 interactive 请求就放行一个 batch 请求
+//!
+//! 存在竞争时（有 interactive 请求排队，或已满载且有 interactive 请求在处理），
+//! batch 流按更小的分片、间隔转发上游数据，把带宽和处理时间让给 interactive 请求
+
+use std::collections::VecDeque;
+use std::sync::Arc;
+use std::time::Duration;
+
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use parking_lot::Mutex;
+use tokio::sync::oneshot;
+
+use crate::model::config::SchedulerConfig;
+
+/// 请求优先级请求头
+pub const PRIORITY_HEADER: &str = "x-kiro-priority";
+
+/// 请求优先级
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Priority {
+    /// 交互式请求（默认）
+    #[default]
+    Interactive,
+    /// 批处理请求，竞争时让路
+    Batch,
+}
+
+impl Priority {
+    /// 解析 `x-kiro-priority` 请求头（未设置时为 interactive）
+    pub fn from_header(value: Option<&str>) -> Result<Self, String> {
+        match value.map(|v| v.trim().to_ascii_lowercase()).as_deref() {
+            None | Some("interactive") => Ok(Priority::Interactive),
+            Some("batch") => Ok(Priority::Batch),
+            Some(other) => Err(format!(
+                "{} 只能是 interactive 或 batch，收到: {}",
+                PRIORITY_HEADER, other
+            )),
+        }
+    }
+}
+
+#[derive(Default)]
+struct SchedulerState {
+    /// 处理中的 interactive 请求数
+    running_interactive: usize,
+    /// 处理中的 batch 请求数
+    running_batch: usize,
+    interactive_waiters: VecDeque<oneshot::Sender<Permit>>,
+    batch_waiters: VecDeque<oneshot::Sender<Permit>>,
+    /// 有 batch 请求排队时连续放行的 interactive 请求数
+    interactive_streak: usize,
+}
+
+impl SchedulerState {
+    fn running(&self) -> usize {
+        self.running_interactive + self.running_batch
+    }
+}
+
+/// 上游请求调度器
+pub struct Scheduler {
+    max_concurrent: usize,
+    interactive_burst: usize,
+    batch_read_quantum: usize,
+    batch_pace: Duration,
+    state: Mutex<SchedulerState>,
+}
+
+impl Scheduler {
+    /// 按配置创建调度器（未限制并发时返回 None）
+    pub fn from_config(config: &SchedulerConfig) -> Option<Arc<Self>> {
+        if config.max_concurrent_requests == 0 {
+            return None;
+        }
+        Some(Arc::new(Self {
+            max_concurrent: config.max_concurrent_requests,
+            interactive_burst: config.interactive_burst.max(1),
+            batch_read_quantum: config.batch_read_quantum.max(1),
+            batch_pace: Duration::from_millis(config.batch_pace_ms),
+            state: Mutex::new(SchedulerState::default()),
+        }))
+    }
+
+    /// 获取处理名额，满载时按优先级排队
+    pub async fn acquire(self: &Arc<Self>, priority: Priority) -> Permit {
+        let receiver = {
+            let mut state = self.state.lock();
+            let queued = !state.interactive_waiters.is_empty() || !state.batch_waiters.is_empty();
+            if state.running() < self.max_concurrent && !queued {
+                return self.grant(&mut state, priority);
+            }
+
+            let (sender, receiver) = oneshot::channel();
+            match priority {
+                Priority::Interactive => state.interactive_waiters.push_back(sender),
+                Priority::Batch => state.batch_waiters.push_back(sender),
+            }
+            receiver
+        };
+
+        // 放行时名额随 Permit 一起发送；调度器存活期间发送端不会被丢弃
+        receiver.await.expect("调度器在等待期间被释放")
+    }
+
+    /// 当前是否存在竞争（batch 流应降速）
+    pub fn contended(&self) -> bool {
+        let state = self.state.lock();
+        !state.interactive_waiters.is_empty()
+            || (state.running() >= self.max_concurrent && state.running_interactive > 0)
+    }
+
+    fn grant(self: &Arc<Self>, state: &mut SchedulerState, priority: Priority) -> Permit {
+        match priority {
+            Priority::Interactive => state.running_interactive += 1,
+            Priority::Batch => state.running_batch += 1,
+        }
+        Permit {
+            scheduler: self.clone(),
+            priority,
+        }
+    }
+
+    /// 释放名额并放行排队的请求
+    fn release(self: &Arc<Self>, priority: Priority) {
+        // 等待方已取消时退回的名额，在解锁后释放（释放时会继续放行）
+        let mut cancelled = Vec::new();
+        let mut state = self.state.lock();
+        match priority {
+            Priority::Interactive => state.running_interactive -= 1,
+            Priority::Batch => state.running_batch -= 1,
+        }
+
+        while state.running() < self.max_concurrent {
+            state.interactive_waiters.retain(|s| !s.is_closed());
+            state.batch_waiters.retain(|s| !s.is_closed());
+
+            let batch_turn = !state.batch_waiters.is_empty()
+                && (state.interactive_waiters.is_empty()
+                    || state.interactive_streak >= self.interactive_burst);
+            let (sender, priority) = if batch_turn {
+                state.interactive_streak = 0;
+                (state.batch_waiters.pop_front(), Priority::Batch)
+            } else {
+                if !state.batch_waiters.is_empty() {
+                    state.interactive_streak += 1;
+                }
+                (state.interactive_waiters.pop_front(), Priority::Interactive)
+            };
+            let Some(sender) = sender else {
+                break;
+            };
+
+            let permit = self.grant(&mut state, priority);
+            if let Err(permit) = sender.send(permit) {
+                cancelled.push(permit);
+            }
+        }
+
+        drop(state);
+        drop(cancelled);
+    }
+}
+
+/// 处理名额，释放时放行排队的请求
+pub struct Permit {
+    scheduler: Arc<Scheduler>,
+    priority: Priority,
+}
+
+impl Permit {
+    /// 是否应降低转发速度
+    fn should_pace(&self) -> bool {
+        self.priority == Priority::Batch && self.scheduler.contended()
+    }
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        self.scheduler.release(self.priority);
+    }
+}
+
+/// 在上游数据流存续期间持有名额；batch 流在竞争时按小分片间隔转发
+pub fn paced<S, E>(body: S, permit: Permit) -> impl Stream<Item = Result<Bytes, E>> + Send
+where
+    S: Stream<Item = Result<Bytes, E>> + Send + Unpin,
+    E: Send,
+{
+    stream::unfold(
+        (body, permit, Bytes::new()),
+        |(mut body, permit, mut pending)| async move {
+            if pending.is_empty() {
+                match body.next().await? {
+                    Ok(chunk) => pending = chunk,
+                    Err(e) => return Some((Err(e), (body, permit, pending))),
+                }
+            }
+
+            let chunk = if permit.should_pace() {
+                tokio::time::sleep(permit.scheduler.batch_pace).await;
+                let quantum = permit.scheduler.batch_read_quantum.min(pending.len());
+                pending.split_to(quantum)
+            } else {
+                std::mem::take(&mut pending)
+            };
+            Some((Ok(chunk), (body, permit, pending)))
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scheduler(max_concurrent: usize, interactive_burst: usize) -> Arc<Scheduler> {
+        Scheduler::from_config(&SchedulerConfig {
+            max_concurrent_requests: max_concurrent,
+            interactive_burst,
+            batch_read_quantum: 4,
+            batch_pace_ms: 1,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn test_priority_header() {
+        assert_eq!(Priority::from_header(None), Ok(Priority::Interactive));
+        assert_eq!(Priority::from_header(Some("Batch")), Ok(Priority::Batch));
+        assert!(Priority::from_header(Some("urgent")).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_interactive_jumps_queue_with_fairness() {
+        let scheduler = scheduler(1, 2);
+        let running = scheduler.acquire(Priority::Batch).await;
+
+        let order = Arc::new(Mutex::new(Vec::new()));
+        let mut tasks = Vec::new();
+        for (name, priority) in [
+            ("b1", Priority::Batch),
+            ("b2", Priority::Batch),
+            ("i1", Priority::Interactive),
+            ("i2", Priority::Interactive),
+            ("i3", Priority::Interactive),
+        ] {
+            let scheduler = scheduler.clone();
+            let order = order.clone();
+            tasks.push(tokio::spawn(async move {
+                let _permit = scheduler.acquire(priority).await;
+                order.lock().push(name);
+                tokio::task::yield_now().await;
+            }));
+            tokio::task::yield_now().await;
+        }
+        assert!(scheduler.contended());
+
+        drop(running);
+        for task in tasks {
+            task.await.unwrap();
+        }
+        // 连续放行 2 个 interactive 后必须放行一个 batch
+        assert_eq!(*order.lock(), vec!["i1", "i2", "b1", "i3", "b2"]);
+        assert!(!scheduler.contended());
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_waiter_releases_slot() {
+        let scheduler = scheduler(1, 4);
+        let running = scheduler.acquire(Priority::Interactive).await;
+
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire(Priority::Batch).await })
+        };
+        tokio::task::yield_now().await;
+        waiter.abort();
+        let _ = waiter.await;
+
+        drop(running);
+        let _permit = tokio::time::timeout(
+            Duration::from_secs(1),
+            scheduler.acquire(Priority::Interactive),
+        )
+        .await
+        .expect("名额应已归还");
+    }
+
+    #[tokio::test]
+    async fn test_batch_stream_paced_under_contention() {
+        let scheduler = scheduler(1, 4);
+        let permit = scheduler.acquire(Priority::Batch).await;
+        let body = stream::iter(vec![Ok::<_, ()>(Bytes::from_static(b"0123456789"))]);
+
+        // 没有竞争时整块转发
+        let chunks: Vec<_> = paced(body.clone(), permit).collect().await;
+        assert_eq!(chunks, vec![Ok(Bytes::from_static(b"0123456789"))]);
+
+        // interactive 请求排队时按 4 字节分片转发
+        let permit = scheduler.acquire(Priority::Batch).await;
+        let waiter = {
+            let scheduler = scheduler.clone();
+            tokio::spawn(async move { scheduler.acquire(Priority::Interactive).await })
+        };
+        tokio::task::yield_now().await;
+        let chunks: Vec<_> = paced(body, permit).collect().await;
+        assert_eq!(
+            chunks,
+            vec![
+                Ok(Bytes::from_static(b"0123")),
+                Ok(Bytes::from_static(b"4567")),
+                Ok(Bytes::from_static(b"89")),
+            ]
+        );
+        drop(waiter.await.unwrap());
+    }
+}
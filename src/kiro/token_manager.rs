@@ -11,11 +11,12 @@ use sha2::{Digest, Sha256};
 use tokio::sync::Mutex as TokioMutex;
 
 use std::collections::HashMap;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::time::{Duration as StdDuration, Instant};
+use std::time::{Duration as StdDuration, Instant, SystemTime};
 
 use crate::http_client::{ProxyConfig, build_client};
+use crate::kiro::health::{CredentialHealth, HealthSample, HealthSnapshot};
 use crate::kiro::machine_id;
 use crate::kiro::model::credentials::KiroCredentials;
 use crate::kiro::model::token_refresh::{
@@ -384,6 +385,31 @@ pub(crate) async fn get_usage_limits(
     Ok(data)
 }
 
+/// 回写时使用的凭据内容（同步 disabled 状态）
+fn persisted_credentials(entry: &CredentialEntry) -> KiroCredentials {
+    let mut cred = entry.credentials.clone();
+    cred.canonicalize_auth_method();
+    cred.disabled = entry.disabled;
+    cred
+}
+
+/// 写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
+fn write_file(path: &Path, content: &str) -> anyhow::Result<()> {
+    use anyhow::Context;
+
+    if tokio::runtime::Handle::try_current().is_ok() {
+        tokio::task::block_in_place(|| std::fs::write(path, content))
+    } else {
+        std::fs::write(path, content)
+    }
+    .with_context(|| format!("回写凭据文件失败: {:?}", path))
+}
+
+/// 文件的修改时间
+pub(crate) fn file_modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
 // ============================================================================
 // 多凭据 Token 管理器
 // ============================================================================
@@ -400,10 +426,24 @@ struct CredentialEntry {
     disabled: bool,
     /// 禁用原因（用于区分手动禁用 vs 自动禁用，便于自愈）
     disabled_reason: Option<DisabledReason>,
+    /// 连续失败自动禁用后重新启用的时间
+    cooldown_until: Option<Instant>,
     /// API 调用成功次数
     success_count: u64,
     /// 最后一次 API 调用时间（RFC3339 格式）
     last_used_at: Option<String>,
+    /// 来源凭据文件（凭据目录中的文件），None 表示来自主凭据文件
+    source: Option<PathBuf>,
+    /// 滚动健康度（延迟、错误率、限流率）
+    health: CredentialHealth,
+}
+
+/// 凭据目录中已加载的文件
+struct CredentialFile {
+    /// 最近一次加载或回写后的修改时间
+    modified: Option<SystemTime>,
+    /// 是否为数组格式（回写时保持原格式）
+    multiple: bool,
 }
 
 /// 禁用原因
@@ -459,6 +499,14 @@ pub struct CredentialEntrySnapshot {
     /// 代理 URL（用于前端展示）
     #[serde(skip_serializing_if = "Option::is_none")]
     pub proxy_url: Option<String>,
+    /// 来源凭据文件（来自凭据目录时）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub source: Option<String>,
+    /// 健康度
+    pub health: HealthSnapshot,
+    /// 连续失败自动禁用后距离重新启用的剩余秒数
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cooldown_remaining_secs: Option<u64>,
 }
 
 /// 凭据管理器状态快照
@@ -492,6 +540,10 @@ pub struct MultiTokenManager {
     credentials_path: Option<PathBuf>,
     /// 是否为多凭据格式（数组格式才回写）
     is_multiple_format: bool,
+    /// 主凭据文件最近一次加载或回写后的修改时间
+    credentials_modified: Mutex<Option<SystemTime>>,
+    /// 凭据目录中已加载的文件
+    credential_files: Mutex<HashMap<PathBuf, CredentialFile>>,
     /// 负载均衡模式（运行时可修改）
     load_balancing_mode: Mutex<String>,
     /// 最近一次统计持久化时间（用于 debounce）
@@ -500,6 +552,24 @@ pub struct MultiTokenManager {
     stats_dirty: AtomicBool,
 }
 
+/// 输出凭据池状态
+fn log_pool_status(entries: &[CredentialEntry]) {
+    let count = |reason: DisabledReason| {
+        entries
+            .iter()
+            .filter(|e| e.disabled && e.disabled_reason == Some(reason))
+            .count()
+    };
+    tracing::info!(
+        "凭据池状态：可用 {}/{}，冷却中 {}，额度用尽 {}，手动禁用 {}",
+        entries.iter().filter(|e| !e.disabled).count(),
+        entries.len(),
+        count(DisabledReason::TooManyFailures),
+        count(DisabledReason::QuotaExceeded),
+        count(DisabledReason::Manual)
+    );
+}
+
 /// 每个凭据最大 API 调用失败次数
 const MAX_FAILURES_PER_CREDENTIAL: u32 = 3;
 /// 统计数据持久化防抖间隔
@@ -573,6 +643,9 @@ impl MultiTokenManager {
                     },
                     success_count: 0,
                     last_used_at: None,
+                    source: None,
+                    health: CredentialHealth::default(),
+                    cooldown_until: None,
                 }
             })
             .collect();
@@ -597,6 +670,7 @@ impl MultiTokenManager {
             .unwrap_or(0);
 
         let load_balancing_mode = config.load_balancing_mode.clone();
+        let credentials_modified = credentials_path.as_deref().and_then(file_modified);
         let manager = Self {
             config,
             proxy,
@@ -605,6 +679,8 @@ impl MultiTokenManager {
             refresh_lock: TokioMutex::new(()),
             credentials_path,
             is_multiple_format,
+            credentials_modified: Mutex::new(credentials_modified),
+            credential_files: Mutex::new(HashMap::new()),
             load_balancing_mode: Mutex::new(load_balancing_mode),
             last_stats_save_at: Mutex::new(None),
             stats_dirty: AtomicBool::new(false),
@@ -654,7 +730,9 @@ impl MultiTokenManager {
     /// 根据负载均衡模式选择下一个凭据
     ///
     /// - priority 模式：选择优先级最高（priority 最小）的可用凭据
-    /// - balanced 模式：轮询选择可用凭据
+    /// - balanced 模式：轮询选择可用凭据（启用健康度时按评分加权）
+    ///
+    /// 启用健康度时跳过已降级的凭据，全部降级时仍从所有可用凭据中选择
     ///
     /// # 参数
     /// - `model`: 可选的模型名称，用于过滤支持该模型的凭据（如 opus 模型需要付费订阅）
@@ -685,10 +763,35 @@ impl MultiTokenManager {
             return None;
         }
 
+        let now = Instant::now();
+        let health = &self.config.credential_health;
+        let available =
+            if health.enabled && available.iter().any(|e| !e.health.is_degraded(now, health)) {
+                available
+                    .into_iter()
+                    .filter(|e| !e.health.is_degraded(now, health))
+                    .collect()
+            } else {
+                available
+            };
+
         let mode = self.load_balancing_mode.lock().clone();
         let mode = mode.as_str();
 
         match mode {
+            "balanced" if health.enabled => {
+                // 按健康度加权的 Least-Used：成功次数除以评分，评分越低分到的请求越少
+                let load = |e: &CredentialEntry| {
+                    (e.success_count + 1) as f64 / e.health.score(now, health).max(0.01)
+                };
+                let entry = available.iter().min_by(|a, b| {
+                    load(a)
+                        .total_cmp(&load(b))
+                        .then(a.credentials.priority.cmp(&b.credentials.priority))
+                })?;
+
+                Some((entry.id, entry.credentials.clone()))
+            }
             "balanced" => {
                 // Least-Used 策略：选择成功次数最少的凭据
                 // 平局时按优先级排序（数字越小优先级越高）
@@ -729,19 +832,27 @@ impl MultiTokenManager {
                 );
             }
 
+            self.recover_cooled_down();
+
             let (id, credentials) = {
                 let is_balanced = self.load_balancing_mode.lock().as_str() == "balanced";
 
                 // balanced 模式：每次请求都轮询选择，不固定 current_id
-                // priority 模式：优先使用 current_id 指向的凭据
+                // priority 模式：优先使用 current_id 指向的凭据（已降级时重新选择）
                 let current_hit = if is_balanced {
                     None
                 } else {
                     let entries = self.entries.lock();
                     let current_id = *self.current_id.lock();
+                    let health = &self.config.credential_health;
+                    let now = Instant::now();
                     entries
                         .iter()
-                        .find(|e| e.id == current_id && !e.disabled)
+                        .find(|e| {
+                            e.id == current_id
+                                && !e.disabled
+                                && !(health.enabled && e.health.is_degraded(now, health))
+                        })
                         .map(|e| (e.id, e.credentials.clone()))
                 };
 
@@ -764,6 +875,7 @@ impl MultiTokenManager {
                                 if e.disabled_reason == Some(DisabledReason::TooManyFailures) {
                                     e.disabled = false;
                                     e.disabled_reason = None;
+                                    e.cooldown_until = None;
                                     e.failure_count = 0;
                                 }
                             }
@@ -804,6 +916,29 @@ impl MultiTokenManager {
         }
     }
 
+    /// 重新启用冷却时间已过的自动禁用凭据
+    fn recover_cooled_down(&self) {
+        let mut entries = self.entries.lock();
+        let now = Instant::now();
+        let mut recovered = false;
+        for e in entries.iter_mut() {
+            if e.disabled
+                && e.disabled_reason == Some(DisabledReason::TooManyFailures)
+                && e.cooldown_until.is_some_and(|until| until <= now)
+            {
+                e.disabled = false;
+                e.disabled_reason = None;
+                e.cooldown_until = None;
+                e.failure_count = 0;
+                recovered = true;
+                tracing::info!("凭据 #{} 冷却结束，已重新启用", e.id);
+            }
+        }
+        if recovered {
+            log_pool_status(&entries);
+        }
+    }
+
     /// 切换到下一个优先级最高的可用凭据（内部方法）
     fn switch_to_next_by_priority(&self) {
         let entries = self.entries.lock();
@@ -926,7 +1061,8 @@ impl MultiTokenManager {
 
     /// 将凭据列表回写到源文件
     ///
-    /// 仅在以下条件满足时回写：
+    /// 来自凭据目录的凭据回写到各自的文件（保持原格式）；其余凭据仅在以下条件满足时
+    /// 回写到主凭据文件：
     /// - 源文件是多凭据格式（数组）
     /// - credentials_path 已设置
     ///
@@ -937,6 +1073,8 @@ impl MultiTokenManager {
     fn persist_credentials(&self) -> anyhow::Result<bool> {
         use anyhow::Context;
 
+        self.persist_credential_files()?;
+
         // 仅多凭据格式才回写
         if !self.is_multiple_format {
             return Ok(false);
@@ -947,36 +1085,72 @@ impl MultiTokenManager {
             None => return Ok(false),
         };
 
+        // 文件已被外部修改（如重新登录）时不覆盖，等待文件监视重新加载
+        if self.config.credentials_reload_interval_secs > 0
+            && file_modified(path) != self.credentials_file_modified()
+        {
+            tracing::warn!("凭据文件 {:?} 已被外部修改，跳过回写", path);
+            return Ok(false);
+        }
+
         // 收集所有凭据
         let credentials: Vec<KiroCredentials> = {
             let entries = self.entries.lock();
             entries
                 .iter()
-                .map(|e| {
-                    let mut cred = e.credentials.clone();
-                    cred.canonicalize_auth_method();
-                    // 同步 disabled 状态到凭据对象
-                    cred.disabled = e.disabled;
-                    cred
-                })
+                .filter(|e| e.source.is_none())
+                .map(persisted_credentials)
                 .collect()
         };
 
         // 序列化为 pretty JSON
         let json = serde_json::to_string_pretty(&credentials).context("序列化凭据失败")?;
-
-        // 写入文件（在 Tokio runtime 内使用 block_in_place 避免阻塞 worker）
-        if tokio::runtime::Handle::try_current().is_ok() {
-            tokio::task::block_in_place(|| std::fs::write(path, &json))
-                .with_context(|| format!("回写凭据文件失败: {:?}", path))?;
-        } else {
-            std::fs::write(path, &json).with_context(|| format!("回写凭据文件失败: {:?}", path))?;
-        }
+        write_file(path, &json)?;
+        *self.credentials_modified.lock() = file_modified(path);
 
         tracing::debug!("已回写凭据到文件: {:?}", path);
         Ok(true)
     }
 
+    /// 将来自凭据目录的凭据回写到各自的文件
+    ///
+    /// 回写后记录新的修改时间，避免目录监视把自身的回写当作外部修改
+    fn persist_credential_files(&self) -> anyhow::Result<()> {
+        use anyhow::Context;
+
+        let mut by_file: HashMap<PathBuf, Vec<KiroCredentials>> = HashMap::new();
+        for entry in self.entries.lock().iter() {
+            if let Some(source) = &entry.source {
+                by_file
+                    .entry(source.clone())
+                    .or_default()
+                    .push(persisted_credentials(entry));
+            }
+        }
+
+        for (path, credentials) in by_file {
+            let mut files = self.credential_files.lock();
+            let Some(file) = files.get_mut(&path) else {
+                continue;
+            };
+            let json = match (file.multiple, credentials.as_slice()) {
+                (false, [single]) => serde_json::to_string_pretty(single),
+                _ => serde_json::to_string_pretty(&credentials),
+            }
+            .context("序列化凭据失败")?;
+
+            write_file(&path, &json)?;
+            file.modified = file_modified(&path);
+            tracing::debug!("已回写凭据到文件: {:?}", path);
+        }
+        Ok(())
+    }
+
+    /// 主凭据文件路径
+    pub fn credentials_path(&self) -> Option<&Path> {
+        self.credentials_path.as_deref()
+    }
+
     /// 获取缓存目录（凭据文件所在目录）
     pub fn cache_dir(&self) -> Option<PathBuf> {
         self.credentials_path
@@ -1097,6 +1271,16 @@ impl MultiTokenManager {
         self.save_stats_debounced();
     }
 
+    /// 记录一次上游调用结果，更新凭据健康度
+    pub fn record_health(&self, id: u64, sample: HealthSample) {
+        let mut entries = self.entries.lock();
+        if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+            entry
+                .health
+                .record(sample, Instant::now(), &self.config.credential_health);
+        }
+    }
+
     /// 报告指定凭据 API 调用失败
     ///
     /// 增加失败计数，达到阈值时禁用凭据并切换到优先级最高的可用凭据
@@ -1128,7 +1312,18 @@ impl MultiTokenManager {
             if failure_count >= MAX_FAILURES_PER_CREDENTIAL {
                 entry.disabled = true;
                 entry.disabled_reason = Some(DisabledReason::TooManyFailures);
-                tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+                let cooldown = self.config.credential_health.failure_cooldown_secs;
+                if cooldown > 0 {
+                    entry.cooldown_until = Some(Instant::now() + StdDuration::from_secs(cooldown));
+                    tracing::error!(
+                        "凭据 #{} 已连续失败 {} 次，已被禁用（{} 秒后重新启用）",
+                        id,
+                        failure_count,
+                        cooldown
+                    );
+                } else {
+                    tracing::error!("凭据 #{} 已连续失败 {} 次，已被禁用", id, failure_count);
+                }
 
                 // 切换到优先级最高的可用凭据
                 if let Some(next) = entries
@@ -1145,6 +1340,7 @@ impl MultiTokenManager {
                 } else {
                     tracing::error!("所有凭据均已禁用！");
                 }
+                log_pool_status(&entries);
             }
 
             entries.iter().any(|e| !e.disabled)
@@ -1179,10 +1375,12 @@ impl MultiTokenManager {
             // 设为阈值，便于在管理面板中直观看到该凭据已不可用
             entry.failure_count = MAX_FAILURES_PER_CREDENTIAL;
 
+            entry.cooldown_until = None;
+
             tracing::error!("凭据 #{} 额度已用尽（MONTHLY_REQUEST_COUNT），已被禁用", id);
 
             // 切换到优先级最高的可用凭据
-            if let Some(next) = entries
+            let has_next = if let Some(next) = entries
                 .iter()
                 .filter(|e| !e.disabled)
                 .min_by_key(|e| e.credentials.priority)
@@ -1197,7 +1395,9 @@ impl MultiTokenManager {
             } else {
                 tracing::error!("所有凭据均已禁用！");
                 false
-            }
+            };
+            log_pool_status(&entries);
+            has_next
         };
         self.save_stats_debounced();
         result
@@ -1242,6 +1442,16 @@ impl MultiTokenManager {
         .await
     }
 
+    /// 获取所有未禁用凭据的副本
+    pub fn enabled_credentials(&self) -> Vec<KiroCredentials> {
+        self.entries
+            .lock()
+            .iter()
+            .filter(|e| !e.disabled)
+            .map(|e| e.credentials.clone())
+            .collect()
+    }
+
     // ========================================================================
     // Admin API 方法
     // ========================================================================
@@ -1251,6 +1461,8 @@ impl MultiTokenManager {
         let entries = self.entries.lock();
         let current_id = *self.current_id.lock();
         let available = entries.iter().filter(|e| !e.disabled).count();
+        let now = Instant::now();
+        let health_config = &self.config.credential_health;
 
         ManagerSnapshot {
             entries: entries
@@ -1275,6 +1487,12 @@ impl MultiTokenManager {
                     last_used_at: e.last_used_at.clone(),
                     has_proxy: e.credentials.proxy_url.is_some(),
                     proxy_url: e.credentials.proxy_url.clone(),
+                    source: e.source.as_ref().map(|p| p.display().to_string()),
+                    health: e.health.snapshot(now, health_config),
+                    cooldown_remaining_secs: e
+                        .cooldown_until
+                        .filter(|_| e.disabled)
+                        .map(|until| until.saturating_duration_since(now).as_secs()),
                 })
                 .collect(),
             current_id,
@@ -1292,6 +1510,7 @@ impl MultiTokenManager {
                 .find(|e| e.id == id)
                 .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?;
             entry.disabled = disabled;
+            entry.cooldown_until = None;
             if !disabled {
                 // 启用时重置失败计数
                 entry.failure_count = 0;
@@ -1336,12 +1555,49 @@ impl MultiTokenManager {
             entry.failure_count = 0;
             entry.disabled = false;
             entry.disabled_reason = None;
+            entry.cooldown_until = None;
         }
         // 持久化更改
         self.persist_credentials()?;
         Ok(())
     }
 
+    /// 立即刷新指定凭据的 Token，不论是否即将过期（Admin API）
+    ///
+    /// 返回刷新后的过期时间
+    pub async fn force_refresh(&self, id: u64) -> anyhow::Result<Option<String>> {
+        let _guard = self.refresh_lock.lock().await;
+
+        let credentials = {
+            let entries = self.entries.lock();
+            entries
+                .iter()
+                .find(|e| e.id == id)
+                .map(|e| e.credentials.clone())
+                .ok_or_else(|| anyhow::anyhow!("凭据不存在: {}", id))?
+        };
+
+        let effective_proxy = credentials.effective_proxy(self.proxy.as_ref());
+        let new_creds = refresh_token(&credentials, &self.config, effective_proxy.as_ref()).await?;
+        if is_token_expired(&new_creds) {
+            anyhow::bail!("刷新后的 Token 仍然无效或已过期");
+        }
+
+        let expires_at = new_creds.expires_at.clone();
+        {
+            let mut entries = self.entries.lock();
+            if let Some(entry) = entries.iter_mut().find(|e| e.id == id) {
+                entry.credentials = new_creds;
+            }
+        }
+
+        if let Err(e) = self.persist_credentials() {
+            tracing::warn!("Token 刷新后持久化失败: {}", e);
+        }
+        tracing::info!("凭据 #{} 已手动刷新 Token", id);
+        Ok(expires_at)
+    }
+
     /// 获取指定凭据的使用额度（Admin API）
     pub async fn get_usage_limits_for(&self, id: u64) -> anyhow::Result<UsageLimitsResponse> {
         let credentials = {
@@ -1522,6 +1778,9 @@ impl MultiTokenManager {
                 disabled_reason: None,
                 success_count: 0,
                 last_used_at: None,
+                source: None,
+                health: CredentialHealth::default(),
+                cooldown_until: None,
             });
         }
 
@@ -1595,6 +1854,235 @@ impl MultiTokenManager {
         Ok(())
     }
 
+    // ========================================================================
+    // 凭据目录
+    // ========================================================================
+
+    /// 凭据目录中已加载文件的修改时间（供目录监视比对）
+    pub fn credential_file_mtimes(&self) -> HashMap<PathBuf, Option<SystemTime>> {
+        self.credential_files
+            .lock()
+            .iter()
+            .map(|(path, file)| (path.clone(), file.modified))
+            .collect()
+    }
+
+    /// 加载（或重新加载）凭据目录中的一个文件
+    ///
+    /// 合并规则见 [`Self::merge_credentials`]
+    ///
+    /// # Returns
+    /// (新增数量, 移除数量)
+    pub fn load_credential_file(
+        &self,
+        path: &Path,
+        credentials: Vec<KiroCredentials>,
+        multiple: bool,
+        modified: Option<SystemTime>,
+    ) -> (usize, usize) {
+        let (added, removed) = self.merge_credentials(Some(path), credentials);
+        self.credential_files
+            .lock()
+            .insert(path.to_path_buf(), CredentialFile { modified, multiple });
+        self.ensure_current_exists();
+
+        if added > 0 || removed > 0 {
+            tracing::info!(
+                "已加载凭据文件 {:?}: 新增 {} 个，移除 {} 个",
+                path,
+                added,
+                removed
+            );
+        }
+        (added, removed)
+    }
+
+    /// 主凭据文件最近一次加载或回写后的修改时间（供文件监视比对）
+    pub fn credentials_file_modified(&self) -> Option<SystemTime> {
+        *self.credentials_modified.lock()
+    }
+
+    /// 重新加载被外部修改的主凭据文件（如重新登录后写入了新的 refreshToken）
+    ///
+    /// 合并规则见 [`Self::merge_credentials`]；文件格式（单对象/数组）以启动时为准
+    ///
+    /// # Returns
+    /// (新增数量, 移除数量)
+    pub fn reload_credentials_file(
+        &self,
+        credentials: Vec<KiroCredentials>,
+        modified: Option<SystemTime>,
+    ) -> (usize, usize) {
+        let (added, removed) = self.merge_credentials(None, credentials);
+        *self.credentials_modified.lock() = modified;
+        self.ensure_current_exists();
+
+        tracing::info!(
+            "主凭据文件已重新加载: 新增 {} 个，移除 {} 个",
+            added,
+            removed
+        );
+        (added, removed)
+    }
+
+    /// 将一个凭据文件的内容合并到凭据池（`source` 为 None 表示主凭据文件）
+    ///
+    /// 按 refreshToken 匹配：文件中仍存在的凭据更新内容并保留运行时状态（失败计数、
+    /// 统计等）；refreshToken 变化但 ID 相同的凭据视为重新登录，保留统计并清除
+    /// 连续失败导致的禁用；不再存在的凭据从池中移除，新增的凭据分配 ID 后加入池中。
+    /// 与其他来源重复的凭据、proxyUrl 无效的凭据会被跳过
+    fn merge_credentials(
+        &self,
+        source: Option<&Path>,
+        credentials: Vec<KiroCredentials>,
+    ) -> (usize, usize) {
+        let source_path = source.or(self.credentials_path.as_deref());
+        let credentials: Vec<KiroCredentials> = credentials
+            .into_iter()
+            .filter(|cred| {
+                let Some(Err(e)) = cred.effective_proxy(None).map(|p| p.validate()) else {
+                    return true;
+                };
+                tracing::warn!(
+                    "凭据文件 {:?} 中的凭据 {:?} proxyUrl 配置无效，已跳过: {}",
+                    source_path,
+                    cred.id,
+                    e
+                );
+                false
+            })
+            .collect();
+        let refresh_hash = |cred: &KiroCredentials| cred.refresh_token.as_deref().map(sha256_hex);
+        let mut added = 0;
+
+        let mut entries = self.entries.lock();
+        let before = entries.len();
+        let incoming: Vec<(Option<String>, Option<u64>)> = credentials
+            .iter()
+            .map(|c| (refresh_hash(c), c.id))
+            .collect();
+        entries.retain(|e| {
+            e.source.as_deref() != source
+                || incoming.iter().any(|(hash, id)| {
+                    (hash.is_some() && *hash == refresh_hash(&e.credentials)) || *id == Some(e.id)
+                })
+        });
+        let removed = before - entries.len();
+
+        for mut cred in credentials {
+            cred.canonicalize_auth_method();
+            let hash = refresh_hash(&cred);
+
+            let same_token = entries
+                .iter()
+                .position(|e| hash.is_some() && refresh_hash(&e.credentials) == hash);
+            if let Some(index) = same_token
+                && entries[index].source.as_deref() != source
+            {
+                tracing::warn!(
+                    "凭据文件 {:?} 中的凭据与凭据 #{} 重复，已跳过",
+                    source_path,
+                    entries[index].id
+                );
+                continue;
+            }
+            let existing = same_token.or_else(|| {
+                entries
+                    .iter()
+                    .position(|e| e.source.as_deref() == source && cred.id == Some(e.id))
+            });
+
+            if let Some(index) = existing {
+                let entry = &mut entries[index];
+                if same_token.is_some() {
+                    // 保留运行时刷新得到的 accessToken
+                    if cred.access_token.is_none() {
+                        cred.access_token = entry.credentials.access_token.take();
+                        cred.expires_at = entry.credentials.expires_at.take();
+                    }
+                } else {
+                    tracing::info!("凭据 #{} 的 refreshToken 已更新", entry.id);
+                    entry.failure_count = 0;
+                    if entry.disabled_reason == Some(DisabledReason::TooManyFailures) {
+                        entry.disabled = false;
+                        entry.disabled_reason = None;
+                        entry.cooldown_until = None;
+                    }
+                }
+                cred.id = Some(entry.id);
+                if cred.machine_id.is_none() {
+                    cred.machine_id = entry.credentials.machine_id.take();
+                }
+                if cred.disabled && !entry.disabled {
+                    entry.disabled = true;
+                    entry.disabled_reason = Some(DisabledReason::Manual);
+                }
+                entry.credentials = cred;
+                continue;
+            }
+
+            // 文件中的 ID 与现有凭据冲突时重新分配
+            let id = match cred.id {
+                Some(id) if !entries.iter().any(|e| e.id == id) => id,
+                _ => entries.iter().map(|e| e.id).max().unwrap_or(0) + 1,
+            };
+            cred.id = Some(id);
+            if cred.machine_id.is_none() {
+                cred.machine_id = machine_id::generate_from_credentials(&cred, &self.config);
+            }
+            entries.push(CredentialEntry {
+                id,
+                disabled: cred.disabled,
+                disabled_reason: cred.disabled.then_some(DisabledReason::Manual),
+                credentials: cred,
+                failure_count: 0,
+                success_count: 0,
+                last_used_at: None,
+                source: source.map(Path::to_path_buf),
+                health: CredentialHealth::default(),
+                cooldown_until: None,
+            });
+            added += 1;
+        }
+        (added, removed)
+    }
+
+    /// 移除来自某个凭据文件的全部凭据（文件被删除时）
+    ///
+    /// # Returns
+    /// 移除的凭据数量
+    pub fn remove_credential_file(&self, path: &Path) -> usize {
+        self.credential_files.lock().remove(path);
+        let removed = {
+            let mut entries = self.entries.lock();
+            let before = entries.len();
+            entries.retain(|e| e.source.as_deref() != Some(path));
+            before - entries.len()
+        };
+        self.ensure_current_exists();
+
+        tracing::info!("凭据文件 {:?} 已删除，移除 {} 个凭据", path, removed);
+        removed
+    }
+
+    /// 当前凭据被移除（或尚未选择）时，切换到优先级最高的可用凭据；没有凭据时重置为 0
+    fn ensure_current_exists(&self) {
+        let current_exists = {
+            let entries = self.entries.lock();
+            let current_id = *self.current_id.lock();
+            entries.iter().any(|e| e.id == current_id)
+        };
+        if current_exists {
+            return;
+        }
+
+        self.select_highest_priority();
+        let entries = self.entries.lock();
+        if entries.is_empty() {
+            *self.current_id.lock() = 0;
+        }
+    }
+
     /// 获取负载均衡模式（Admin API）
     pub fn get_load_balancing_mode(&self) -> String {
         self.load_balancing_mode.lock().clone()
@@ -1867,6 +2355,34 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_multi_token_manager_skips_degraded_credential() {
+        use crate::kiro::attempt::AttemptReason;
+
+        let credentials = ["token1", "token2"]
+            .map(|token| KiroCredentials {
+                refresh_token: Some(token.to_string()),
+                ..Default::default()
+            })
+            .to_vec();
+        let manager =
+            MultiTokenManager::new(Config::default(), credentials, None, None, false).unwrap();
+
+        for _ in 0..10 {
+            manager.record_health(1, HealthSample::Failure(AttemptReason::ServerError));
+        }
+        assert_eq!(manager.select_next_credential(None).unwrap().0, 2);
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[0].health.score < 0.2);
+        assert_eq!(snapshot.entries[1].health.score, 1.0);
+
+        // 全部降级时仍按优先级选择
+        for _ in 0..10 {
+            manager.record_health(2, HealthSample::Failure(AttemptReason::Timeout));
+        }
+        assert_eq!(manager.select_next_credential(None).unwrap().0, 1);
+    }
+
     #[test]
     fn test_set_load_balancing_mode_persists_to_config_file() {
         let config_path = std::env::temp_dir().join(format!(
@@ -1925,6 +2441,37 @@ mod tests {
         assert_eq!(manager.available_count(), 2);
     }
 
+    #[tokio::test]
+    async fn test_multi_token_manager_failure_cooldown_reenables() {
+        let cred = |token: &str| KiroCredentials {
+            access_token: Some(token.to_string()),
+            expires_at: Some((Utc::now() + Duration::hours(1)).to_rfc3339()),
+            ..Default::default()
+        };
+        let (cred1, cred2) = (cred("t1"), cred("t2"));
+
+        let manager =
+            MultiTokenManager::new(Config::default(), vec![cred1, cred2], None, None, false)
+                .unwrap();
+
+        for _ in 0..MAX_FAILURES_PER_CREDENTIAL {
+            manager.report_failure(1);
+        }
+        let snapshot = manager.snapshot();
+        assert!(snapshot.entries[0].disabled);
+        assert!(snapshot.entries[0].cooldown_remaining_secs.unwrap() > 290);
+        assert_eq!(snapshot.entries[1].cooldown_remaining_secs, None);
+
+        // 冷却未结束时不启用
+        assert_eq!(manager.acquire_context(None).await.unwrap().token, "t2");
+        assert_eq!(manager.available_count(), 1);
+
+        manager.entries.lock()[0].cooldown_until = Some(Instant::now());
+        manager.acquire_context(None).await.unwrap();
+        assert_eq!(manager.available_count(), 2);
+        assert_eq!(manager.snapshot().entries[0].failure_count, 0);
+    }
+
     #[test]
     fn test_multi_token_manager_report_quota_exhausted() {
         let config = Config::default();
@@ -1966,6 +2513,26 @@ mod tests {
         assert_eq!(manager.available_count(), 0);
     }
 
+    #[tokio::test]
+    async fn test_force_refresh_errors() {
+        let manager = MultiTokenManager::new(
+            Config::default(),
+            vec![KiroCredentials::default()],
+            None,
+            None,
+            false,
+        )
+        .unwrap();
+
+        let err = manager.force_refresh(99).await.unwrap_err().to_string();
+        assert!(err.contains("不存在"), "实际: {}", err);
+
+        // 缺少 refreshToken 时在本地校验失败，不发起网络请求，凭据保持不变
+        let err = manager.force_refresh(1).await.unwrap_err().to_string();
+        assert!(err.contains("缺少 refreshToken"), "实际: {}", err);
+        assert_eq!(manager.snapshot().entries[0].expires_at, None);
+    }
+
     // ============ 凭据级 Region 优先级测试 ============
 
     #[test]
@@ -1,33 +1,38 @@
 mod admin;
 mod admin_ui;
 mod anthropic;
+mod bench;
+mod check;
 mod common;
 mod http_client;
 mod kiro;
 mod model;
+mod openai;
 pub mod token;
 
 use std::sync::Arc;
 
+use axum::Router;
 use clap::Parser;
 use kiro::model::credentials::{CredentialsConfig, KiroCredentials};
 use kiro::provider::KiroProvider;
 use kiro::token_manager::MultiTokenManager;
-use model::arg::Args;
-use model::config::Config;
+use model::arg::{Args, Command, ConfigCommand};
+use model::config::{Config, ListenerAuth, ListenerConfig, ListenerService};
+use tokio::task::JoinSet;
 
 #[tokio::main]
 async fn main() {
     // 解析命令行参数
-    let args = Args::parse();
+    let mut args = Args::parse();
 
-    // 初始化日志
-    tracing_subscriber::fmt()
-        .with_env_filter(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| tracing_subscriber::EnvFilter::new("info")),
-        )
-        .init();
+    // 初始化日志（子命令的日志写到 stderr，避免混入 stdout 上的报告）
+    common::telemetry::init_subscriber(args.command.is_some());
+
+    if let Some(command) = args.command.take() {
+        run_command(command, &args).await;
+        return;
+    }
 
     // 加载配置
     let config_path = args
@@ -65,16 +70,20 @@ async fn main() {
     });
 
     // 构建代理配置
-    let proxy_config = config.proxy_url.as_ref().map(|url| {
-        let mut proxy = http_client::ProxyConfig::new(url);
-        if let (Some(username), Some(password)) = (&config.proxy_username, &config.proxy_password) {
-            proxy = proxy.with_auth(username, password);
-        }
-        proxy
-    });
+    let proxy_config = http_client::ProxyConfig::from_config(&config);
 
-    if proxy_config.is_some() {
-        tracing::info!("已配置 HTTP 代理: {}", config.proxy_url.as_ref().unwrap());
+    if let Some(proxy) = &proxy_config {
+        if let Err(e) = proxy.validate() {
+            tracing::error!("proxyUrl 配置无效: {}", e);
+            std::process::exit(1);
+        }
+        tracing::info!("已配置 HTTP 代理: {}", proxy.url);
+    }
+    for credentials in &credentials_list {
+        if let Some(Err(e)) = credentials.effective_proxy(None).map(|p| p.validate()) {
+            tracing::error!(id = ?credentials.id, "凭据的 proxyUrl 配置无效: {}", e);
+            std::process::exit(1);
+        }
     }
 
     // 创建 MultiTokenManager 和 KiroProvider
@@ -90,7 +99,49 @@ async fn main() {
         std::process::exit(1);
     });
     let token_manager = Arc::new(token_manager);
+
+    // 主凭据文件被外部修改（如重新登录）时重新加载
+    kiro::credential_dir::spawn_file_watcher(
+        token_manager.clone(),
+        config.credentials_reload_interval_secs,
+    );
+
+    // 加载凭据目录，并定期扫描以增删凭据
+    if let Some(dir) = config.credentials_dir.path.as_deref() {
+        let dir = std::path::PathBuf::from(dir);
+        match kiro::credential_dir::sync(&token_manager, &dir) {
+            Ok(summary) => tracing::info!("已从凭据目录 {:?} 加载 {} 个凭据", dir, summary.added),
+            Err(e) => {
+                tracing::error!("加载凭据目录失败 {:?}: {}", dir, e);
+                std::process::exit(1);
+            }
+        }
+        kiro::credential_dir::spawn_watcher(
+            token_manager.clone(),
+            dir,
+            config.credentials_dir.scan_interval_secs,
+        );
+    }
+
+    // 初始化用量报表（持久化到凭据文件所在目录）
+    common::usage::init(
+        token_manager.cache_dir().map(|d| d.join("kiro_usage.json")),
+        config.reports.retention_days,
+    );
+    common::usage::spawn_snapshotter(config.reports.snapshot_interval_secs);
+
     let kiro_provider = KiroProvider::with_proxy(token_manager.clone(), proxy_config.clone());
+    kiro_provider.spawn_prewarm();
+
+    if let Err(e) = common::telemetry::init(&config.telemetry) {
+        tracing::error!("telemetry 配置错误: {:#}", e);
+        std::process::exit(1);
+    }
+
+    // 初始化日志脱敏配置
+    common::redact::init(&config.logging);
+    kiro::frame_ring::init(&config.logging);
+    anthropic::refusal::init(&config.stream.refusal);
 
     // 初始化 count_tokens 配置
     token::init_config(token::CountTokensConfig {
@@ -100,63 +151,416 @@ async fn main() {
         proxy: proxy_config,
         tls_backend: config.tls_backend,
     });
+    tokio::task::spawn_blocking(token::warm_up);
 
-    // 构建 Anthropic API 路由（从第一个凭据获取 profile_arn）
-    let anthropic_app = anthropic::create_router_with_provider(
-        &api_key,
-        Some(kiro_provider),
-        first_credentials.profile_arn.clone(),
-    );
+    if let Err(e) = anthropic::models::init(&config.model_mapping) {
+        tracing::error!("modelMapping 配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = anthropic::profile::validate_config(&config.profiles) {
+        tracing::error!("profiles 配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = anthropic::budget::validate_config(&config.budgets) {
+        tracing::error!("budgets 配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = anthropic::pricing::validate_config(&config.pricing) {
+        tracing::error!("pricing 配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    if let Err(e) = anthropic::api_keys::validate_config(&config.api_keys) {
+        tracing::error!("apiKeys 配置错误: {}", e);
+        std::process::exit(1);
+    }
+
+    // 构建 Anthropic API 共享状态（从第一个凭据获取 profile_arn）
+    let mut anthropic_state = anthropic::AppState::new(&api_key)
+        .with_kiro_provider(kiro_provider)
+        .with_config(config.clone());
+    if let Some(arn) = first_credentials.profile_arn.clone() {
+        anthropic_state = anthropic_state.with_profile_arn(arn);
+    }
+    anthropic_state.session_store.spawn_sweeper();
+    // 转换失败死信队列（持久化到凭据文件所在目录）
+    if config.dead_letter.enabled {
+        let store = anthropic::DeadLetterStore::new(
+            token_manager
+                .cache_dir()
+                .map(|d| d.join("kiro_dead_letters.json")),
+            &config.dead_letter,
+        );
+        let store = Arc::new(store);
+        store.spawn_flusher();
+        anthropic_state = anthropic_state.with_dead_letters(store);
+    }
+    // SSE 会话记录（保存到凭据文件所在目录）
+    if config.logging.sse_transcripts.enabled {
+        if config.logging.strict {
+            tracing::warn!("logging.strict 已启用，不记录 SSE 会话记录");
+        } else if let Some(dir) = token_manager.cache_dir() {
+            match anthropic::SseTranscriptStore::new(
+                dir.join("kiro_sse_transcripts"),
+                &config.logging.sse_transcripts,
+            ) {
+                Ok(store) => {
+                    anthropic_state = anthropic_state.with_sse_transcripts(Arc::new(store));
+                }
+                Err(e) => tracing::warn!("创建 SSE 会话记录目录失败: {}", e),
+            }
+        } else {
+            tracing::warn!("无法确定缓存目录，不记录 SSE 会话记录");
+        }
+    }
+    // 请求/响应日志（保存到凭据文件所在目录）
+    if config.logging.requests.enabled {
+        if let Some(dir) = token_manager.cache_dir() {
+            match anthropic::RequestLogStore::new(
+                dir.join("kiro_request_logs"),
+                &config.logging.requests,
+            ) {
+                Ok(store) => {
+                    anthropic_state = anthropic_state.with_request_logs(Arc::new(store));
+                }
+                Err(e) => tracing::warn!("创建请求日志目录失败: {}", e),
+            }
+        } else {
+            tracing::warn!("无法确定缓存目录，不记录请求日志");
+        }
+    }
 
     // 构建 Admin API 路由（如果配置了非空的 admin_api_key）
     // 安全检查：空字符串被视为未配置，防止空 key 绕过认证
-    let admin_key_valid = config
-        .admin_api_key
-        .as_ref()
-        .map(|k| !k.trim().is_empty())
-        .unwrap_or(false);
-
-    let app = if let Some(admin_key) = &config.admin_api_key {
-        if admin_key.trim().is_empty() {
+    let admin_app = match &config.admin_api_key {
+        Some(admin_key) if admin_key.trim().is_empty() => {
             tracing::warn!("admin_api_key 配置为空，Admin API 未启用");
-            anthropic_app
-        } else {
-            let admin_service = admin::AdminService::new(token_manager.clone());
+            None
+        }
+        Some(admin_key) => {
+            let mut admin_service = admin::AdminService::new(token_manager.clone())
+                .with_session_store(anthropic_state.session_store.clone())
+                .with_app_state(anthropic_state.clone());
+            if let Some(store) = &anthropic_state.dead_letters {
+                admin_service = admin_service.with_dead_letters(store.clone());
+            }
             let admin_state = admin::AdminState::new(admin_key, admin_service);
             let admin_app = admin::create_admin_router(admin_state);
 
             // 创建 Admin UI 路由
-            let admin_ui_app = admin_ui::create_admin_ui_router();
+            let admin_ui_app = admin_ui::create_admin_ui_router(config.admin_dashboard);
 
             tracing::info!("Admin API 已启用");
             tracing::info!("Admin UI 已启用: /admin");
-            anthropic_app
-                .nest("/api/admin", admin_app)
-                .nest("/admin", admin_ui_app)
+            if config.admin_dashboard {
+                tracing::info!("状态面板已启用: /admin/ui");
+            }
+            Some(
+                Router::new()
+                    .nest("/api/admin", admin_app)
+                    .nest("/admin", admin_ui_app),
+            )
         }
-    } else {
-        anthropic_app
+        None => None,
     };
 
-    // 启动服务器
-    let addr = format!("{}:{}", config.host, config.port);
-    tracing::info!("启动 Anthropic API 端点: {}", addr);
+    // 按监听器组合路由
+    let listeners = config.effective_listeners();
+    let mut servers = JoinSet::new();
+    let mut admin_enabled = false;
+    let mut metrics_enabled = false;
+    for listener in listeners {
+        let mut app = Router::new();
+        if listener.serves(ListenerService::Api) {
+            let mut state = anthropic_state.clone();
+            if let Some(key) = &listener.api_key {
+                state = state.with_api_key(key);
+            }
+            if listener.auth == ListenerAuth::None {
+                tracing::warn!("监听器 {} 未启用 API Key 认证", listener.bind);
+            }
+            let api = Router::new()
+                .merge(openai::create_router(state.clone(), listener.auth))
+                .merge(anthropic::create_router(state, listener.auth))
+                .merge(common::openapi::create_router())
+                .route_layer(axum::middleware::from_fn(
+                    common::prometheus::track_requests,
+                ))
+                .route_layer(axum::middleware::from_fn(common::telemetry::trace_requests));
+            app = app.merge(api);
+        }
+        if listener.serves(ListenerService::Metrics) && config.metrics.enabled {
+            metrics_enabled = true;
+            app = app.merge(common::prometheus::create_router(
+                token_manager.clone(),
+                config.metrics.api_key.clone(),
+            ));
+        }
+        if listener.serves(ListenerService::Admin)
+            && let Some(admin_app) = &admin_app
+        {
+            admin_enabled = true;
+            app = app.merge(admin_app.clone());
+        }
+
+        let services: Vec<_> = listener
+            .services
+            .iter()
+            .map(|s| format!("{:?}", s).to_lowercase())
+            .collect();
+        tracing::info!("启动监听器: {} ({})", listener.bind, services.join(", "));
+        let listener_bind = listener.bind.clone();
+        servers.spawn(async move { (listener_bind, serve(listener, app).await) });
+    }
+
     tracing::info!("API Key: {}***", &api_key[..(api_key.len() / 2)]);
     tracing::info!("可用 API:");
     tracing::info!("  GET  /v1/models");
     tracing::info!("  POST /v1/messages");
     tracing::info!("  POST /v1/messages/count_tokens");
-    if admin_key_valid {
+    tracing::info!("  POST /v1/chat/completions");
+    tracing::info!("  GET  /openapi.json");
+    if metrics_enabled {
+        tracing::info!("  GET  /metrics");
+    }
+    if admin_enabled {
         tracing::info!("Admin API:");
         tracing::info!("  GET  /api/admin/credentials");
         tracing::info!("  POST /api/admin/credentials/:index/disabled");
         tracing::info!("  POST /api/admin/credentials/:index/priority");
         tracing::info!("  POST /api/admin/credentials/:index/reset");
         tracing::info!("  GET  /api/admin/credentials/:index/balance");
+        tracing::info!("  POST /api/admin/models/:id/probe");
         tracing::info!("Admin UI:");
         tracing::info!("  GET  /admin");
+        if config.admin_dashboard {
+            tracing::info!("  GET  /admin/ui");
+        }
     }
 
-    let listener = tokio::net::TcpListener::bind(&addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    // 任一监听器退出即结束进程
+    if let Some(Ok((bind, result))) = servers.join_next().await {
+        if let Err(e) = result {
+            tracing::error!("监听器 {} 异常退出: {}", bind, e);
+        }
+        common::telemetry::shutdown();
+        std::process::exit(1);
+    }
+}
+
+/// 在单个监听器上提供服务
+async fn serve(listener: ListenerConfig, app: Router) -> anyhow::Result<()> {
+    if listener.unix_path().is_some() && listener.tls.is_some() {
+        anyhow::bail!("Unix Socket 监听器不支持 TLS");
+    }
+
+    #[cfg(unix)]
+    if let Some(path) = listener.unix_path() {
+        // 清理上次运行遗留的 socket 文件
+        let _ = std::fs::remove_file(path);
+        let uds = tokio::net::UnixListener::bind(path)?;
+        axum::serve(uds, app).await?;
+        return Ok(());
+    }
+
+    if listener.unix_path().is_some() {
+        anyhow::bail!("当前平台不支持 Unix Socket 监听");
+    }
+    let tcp = tokio::net::TcpListener::bind(&listener.bind).await?;
+    if let Some(tls) = &listener.tls {
+        let tls_listener = common::tls::TlsListener::new(tcp, tls)?;
+        let app = app.layer(axum::middleware::from_fn(common::tls::client_identity));
+        axum::serve(
+            tls_listener,
+            app.into_make_service_with_connect_info::<common::tls::TlsPeer>(),
+        )
+        .await?;
+        return Ok(());
+    }
+    axum::serve(tcp, app).await?;
+    Ok(())
+}
+
+/// 执行辅助子命令
+async fn run_command(command: Command, args: &Args) {
+    match command {
+        Command::GenFixture { script, output } => match kiro::fixture::generate(&script, &output) {
+            Ok(count) => {
+                tracing::info!("已写入 {} 个事件到 {}", count, output.display());
+            }
+            Err(e) => {
+                tracing::error!("生成样本失败: {:#}", e);
+                std::process::exit(1);
+            }
+        },
+        Command::Check {
+            offline,
+            upstream,
+            json,
+        } => {
+            let options = check::CheckOptions {
+                config_path: args
+                    .config
+                    .clone()
+                    .unwrap_or_else(|| Config::default_config_path().to_string()),
+                credentials_path: args
+                    .credentials
+                    .clone()
+                    .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string()),
+                offline,
+                upstream,
+            };
+            let report = check::run(&options).await;
+            if json {
+                println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("检查报告序列化失败")
+                );
+            } else {
+                print!("{}", report.render_text());
+            }
+            if !report.ok {
+                std::process::exit(1);
+            }
+        }
+        Command::Login { start_url, region } => {
+            let config_path = args
+                .config
+                .clone()
+                .unwrap_or_else(|| Config::default_config_path().to_string());
+            let config = Config::load(&config_path).unwrap_or_else(|e| {
+                tracing::error!("加载配置失败: {}", e);
+                std::process::exit(1);
+            });
+            let credentials_path = args
+                .credentials
+                .clone()
+                .unwrap_or_else(|| KiroCredentials::default_credentials_path().to_string());
+            let options = kiro::auth::LoginOptions {
+                start_url: start_url
+                    .unwrap_or_else(|| kiro::auth::BUILDER_ID_START_URL.to_string()),
+                region: region.unwrap_or_else(|| config.effective_auth_region().to_string()),
+            };
+            let result = kiro::auth::login(&options, &config)
+                .await
+                .and_then(|credentials| {
+                    kiro::auth::save_credentials(
+                        std::path::Path::new(&credentials_path),
+                        credentials,
+                    )
+                });
+            match result {
+                Ok(count) => tracing::info!(
+                    "登录成功，凭据已写入 {}（共 {} 个凭据）",
+                    credentials_path,
+                    count
+                ),
+                Err(e) => {
+                    tracing::error!("登录失败: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Config {
+            action: ConfigCommand::Migrate { dry_run },
+        } => {
+            let config_path = args
+                .config
+                .clone()
+                .unwrap_or_else(|| Config::default_config_path().to_string());
+            match Config::migrate_file(std::path::Path::new(&config_path), dry_run) {
+                Ok((migration, content)) => {
+                    for change in &migration.changes {
+                        tracing::info!("{}", change);
+                    }
+                    if dry_run {
+                        println!("{}", content);
+                    } else if migration.migrated() {
+                        tracing::info!(
+                            "已将 {} 从版本 {} 迁移到 {}，原文件备份为 {}.bak",
+                            config_path,
+                            migration.from,
+                            migration.to,
+                            config_path
+                        );
+                    } else {
+                        tracing::info!("{} 已是当前版本 {}，无需迁移", config_path, migration.to);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("迁移配置失败: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Bench {
+            url,
+            api_key,
+            model,
+            requests,
+            concurrency,
+            prompt_chars,
+            max_tokens,
+            no_stream,
+            read_rate,
+            pid,
+            timeout,
+            json,
+        } => {
+            // 未指定地址或 API Key 时从配置文件读取
+            let config_path = args
+                .config
+                .clone()
+                .unwrap_or_else(|| Config::default_config_path().to_string());
+            let config = Config::load(&config_path).unwrap_or_else(|e| {
+                tracing::error!("加载配置失败: {}", e);
+                std::process::exit(1);
+            });
+            let url = url.unwrap_or_else(|| {
+                let host = match config.host.as_str() {
+                    "0.0.0.0" | "::" => "127.0.0.1",
+                    host => host,
+                };
+                format!("http://{}:{}", host, config.port)
+            });
+            let Some(api_key) = api_key.or(config.api_key) else {
+                tracing::error!("未指定 --api-key，配置文件中也未设置 apiKey");
+                std::process::exit(1);
+            };
+
+            let options = bench::BenchOptions {
+                url,
+                api_key,
+                model,
+                requests,
+                concurrency,
+                prompt_chars,
+                max_tokens,
+                stream: !no_stream,
+                read_rate,
+                pid,
+                timeout_secs: timeout,
+            };
+            tracing::info!(
+                "开始压测 {}: {} 个请求, 并发 {}",
+                options.url,
+                options.requests,
+                options.concurrency
+            );
+            match bench::run(options).await {
+                Ok(report) if json => println!(
+                    "{}",
+                    serde_json::to_string_pretty(&report).expect("压测报告序列化失败")
+                ),
+                Ok(report) => print!("{}", report.render_text()),
+                Err(e) => {
+                    tracing::error!("压测失败: {:#}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+    }
 }
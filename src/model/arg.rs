@@ -1,14 +1,124 @@
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 
 /// Anthropic <-> Kiro API 客户端
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Args {
     /// 配置文件路径
-    #[arg(short, long)]
+    #[arg(short, long, global = true)]
     pub config: Option<String>,
 
     /// 凭证文件路径
-    #[arg(long)]
+    #[arg(long, global = true)]
     pub credentials: Option<String>,
+
+    /// 子命令（缺省时启动服务）
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+/// 辅助子命令
+#[derive(Subcommand, Debug)]
+pub enum Command {
+    /// 根据 YAML 事件脚本生成 AWS Event Stream 二进制样本
+    GenFixture {
+        /// 事件脚本路径
+        script: PathBuf,
+
+        /// 输出文件路径
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// 部署自检：校验配置、凭证、Token、模型映射与监听地址，失败时返回非零状态码
+    Check {
+        /// 跳过需要访问网络的检查（Token 刷新、上游连通性）
+        #[arg(long)]
+        offline: bool,
+
+        /// 检查上游 Kiro API 的连通性
+        #[arg(long)]
+        upstream: bool,
+
+        /// 以 JSON 格式输出报告
+        #[arg(long)]
+        json: bool,
+    },
+    /// 通过 AWS SSO 设备授权登录 Kiro，并把获得的凭据追加到凭证文件
+    Login {
+        /// IAM Identity Center 起始 URL，缺省登录 AWS Builder ID
+        #[arg(long)]
+        start_url: Option<String>,
+
+        /// OIDC 服务所在 Region，缺省使用配置中的 authRegion / region
+        #[arg(long)]
+        region: Option<String>,
+    },
+    /// 配置文件维护
+    Config {
+        #[command(subcommand)]
+        action: ConfigCommand,
+    },
+    /// 压测：以合成请求驱动本地服务（或 mock 后端），输出延迟分位数与内存占用
+    Bench {
+        /// 目标服务地址，默认使用配置中的 host/port
+        #[arg(long)]
+        url: Option<String>,
+
+        /// API Key，默认使用配置中的 apiKey
+        #[arg(long)]
+        api_key: Option<String>,
+
+        /// 请求的模型
+        #[arg(long, default_value = "claude-sonnet-4-5-20250929")]
+        model: String,
+
+        /// 总请求数
+        #[arg(short = 'n', long, default_value_t = 100)]
+        requests: usize,
+
+        /// 并发数
+        #[arg(long, default_value_t = 8)]
+        concurrency: usize,
+
+        /// 每个请求的提示词长度（字符）
+        #[arg(long, default_value_t = 1000)]
+        prompt_chars: usize,
+
+        /// 请求的 max_tokens
+        #[arg(long, default_value_t = 256)]
+        max_tokens: i32,
+
+        /// 使用非流式请求
+        #[arg(long)]
+        no_stream: bool,
+
+        /// 每个连接的读取速率（字节/秒），模拟慢速消费方，缺省不限速
+        #[arg(long)]
+        read_rate: Option<u64>,
+
+        /// 采样内存占用的目标进程 ID（仅 Linux）
+        #[arg(long)]
+        pid: Option<u32>,
+
+        /// 单个请求超时（秒）
+        #[arg(long, default_value_t = 300)]
+        timeout: u64,
+
+        /// 以 JSON 格式输出报告
+        #[arg(long)]
+        json: bool,
+    },
+}
+
+/// 配置文件维护子命令
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommand {
+    /// 将配置文件迁移到当前格式版本并写回，原文件备份为 `<文件名>.bak`
+    Migrate {
+        /// 只输出迁移后的内容，不写文件
+        #[arg(long)]
+        dry_run: bool,
+    },
 }
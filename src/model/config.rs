@@ -1,18 +1,1045 @@
 use anyhow::Context;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+use super::migrate::{self, CONFIG_VERSION, Migration};
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
 #[serde(rename_all = "kebab-case")]
 pub enum TlsBackend {
+    #[default]
     Rustls,
     NativeTls,
 }
 
-impl Default for TlsBackend {
+/// 历史消息配对策略
+///
+/// Kiro API 要求历史消息严格按 user → assistant 交替，
+/// 当出现无法配对的 user 消息（系统提示词、末尾连续 user 消息）时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum HistoryPairingStrategy {
+    /// 插入占位 assistant 回复完成配对（默认）
+    #[default]
+    Placeholder,
+    /// 将无法配对的 user 内容合并到后续 user 消息，不伪造 assistant 回复
+    Merge,
+}
+
+/// 连续 user 消息的合并格式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UserTurnJoin {
+    /// 直接以换行拼接（默认）
+    #[default]
+    Newline,
+    /// 每段前插入 `[user message N]` 标记
+    Marker,
+    /// 转为 `User: ...` 对话记录格式
+    Transcript,
+}
+
+/// Computer use 等 Anthropic 定义的客户端工具处理策略
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ComputerUsePolicy {
+    /// 补全描述与输入 Schema 后作为普通工具传给 Kiro（默认）
+    #[default]
+    Passthrough,
+    /// 拒绝包含此类工具的请求（返回 400）
+    Reject,
+}
+
+/// 不支持的内容块处理策略
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum UnsupportedBlockPolicy {
+    /// 丢弃并记录警告（默认）
+    #[default]
+    Drop,
+    /// 拒绝包含此类内容块的请求（返回 400）
+    Error,
+    /// 将内容块序列化为 JSON 文本保留在消息中
+    Stringify,
+}
+
+/// 转换器无法处理的内容块类型（如 `document`、`redacted_thinking` 或 Anthropic 新增的类型）的处理配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UnsupportedBlocksConfig {
+    /// 未在 `types` 中列出的块类型的处理策略；严格部署可设为 `error`，让新的块类型直接报错
+    pub default: UnsupportedBlockPolicy,
+
+    /// 按块类型指定的处理策略
+    pub types: BTreeMap<String, UnsupportedBlockPolicy>,
+}
+
+impl Default for UnsupportedBlocksConfig {
+    fn default() -> Self {
+        Self {
+            default: UnsupportedBlockPolicy::Drop,
+            // redacted_thinking 为加密内容，无法转发给 Kiro，即使严格部署也只能丢弃
+            types: BTreeMap::from([(
+                "redacted_thinking".to_string(),
+                UnsupportedBlockPolicy::Drop,
+            )]),
+        }
+    }
+}
+
+impl UnsupportedBlocksConfig {
+    /// 指定块类型的处理策略
+    pub fn policy(&self, block_type: &str) -> UnsupportedBlockPolicy {
+        self.types.get(block_type).copied().unwrap_or(self.default)
+    }
+}
+
+/// 协议转换配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ConverterConfig {
+    /// 历史消息配对策略
+    pub history_pairing: HistoryPairingStrategy,
+
+    /// 系统提示词配对时使用的 assistant 占位回复
+    pub system_ack_text: String,
+
+    /// 末尾孤立 user 消息配对时使用的 assistant 占位回复
+    pub user_ack_text: String,
+
+    /// 连续 user 消息的合并格式
+    pub user_turn_join: UserTurnJoin,
+
+    /// Computer use 等客户端工具处理策略
+    pub computer_use: ComputerUsePolicy,
+
+    /// 是否按内容哈希对请求内的重复图片去重
+    pub dedup_images: bool,
+
+    /// 是否支持通过 `parent_message_id` 扩展字段从会话中的助手消息分支
+    pub conversation_branches: bool,
+
+    /// 工具 input_schema 内联 `$ref` 后允许的最大嵌套深度
+    pub tool_schema_max_depth: usize,
+
+    /// user 消息（当前消息及历史）没有文本（如仅包含 tool_result）时使用的占位文本，空字符串表示保持为空
+    pub empty_content_placeholder: String,
+
+    /// 模型映射降级（如请求 opus 4.7 实际使用 opus 4.6）时是否提示客户端
+    ///
+    /// 通过 `x-kiro-model-substitution` 响应头和 SSE 注释提示，同一会话对同一模型只提示一次
+    pub model_downgrade_notice: bool,
+
+    /// 插入到客户端系统提示词之前的文本，支持 `{{date}}`、`{{model}}`、`{{conversation_id}}` 等模板变量
+    pub system_prepend: String,
+
+    /// 追加到客户端系统提示词之后的文本，模板变量同 `system_prepend`
+    pub system_append: String,
+
+    /// 不支持的内容块处理策略
+    pub unsupported_blocks: UnsupportedBlocksConfig,
+
+    /// 扩展字段 `workspace` 中每个文件内容的最大字符数，超出部分截断；0 表示不转发文件内容
+    pub workspace_max_file_chars: usize,
+
+    /// 回复语言提示
+    pub language_directive: LanguageDirectiveConfig,
+
+    /// 发送给上游的历史最多保留的对话轮数（一条 user 消息及其回复为一轮），0 表示不限制
+    ///
+    /// 系统提示词不计入轮数；窗口起点的 tool_result 若引用已丢弃的 tool_use 会一并移除
+    pub max_history_turns: usize,
+}
+
+impl Default for ConverterConfig {
+    fn default() -> Self {
+        Self {
+            history_pairing: HistoryPairingStrategy::default(),
+            system_ack_text: "I will follow these instructions.".to_string(),
+            user_ack_text: "OK".to_string(),
+            user_turn_join: UserTurnJoin::default(),
+            computer_use: ComputerUsePolicy::default(),
+            dedup_images: false,
+            conversation_branches: false,
+            tool_schema_max_depth: 32,
+            empty_content_placeholder: "Continue.".to_string(),
+            model_downgrade_notice: true,
+            system_prepend: String::new(),
+            system_append: String::new(),
+            unsupported_blocks: UnsupportedBlocksConfig::default(),
+            workspace_max_file_chars: 50_000,
+            language_directive: LanguageDirectiveConfig::default(),
+            max_history_turns: 0,
+        }
+    }
+}
+
+/// 回复语言提示配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LanguageDirectiveConfig {
+    /// 是否按最新一条 user 消息的语言在系统提示词末尾追加回复语言指令
+    pub enabled: bool,
+
+    /// 指令模板，`{{language}}` 替换为识别出的语言名称（如 `Chinese`）
+    pub template: String,
+
+    /// 消息中至少有多少个字母（汉字、假名、谚文按 3 个计）才做判断
+    pub min_letters: usize,
+
+    /// 消息文字过少无法判断时，是否按请求头 `Accept-Language` 推断
+    pub use_accept_language: bool,
+}
+
+impl Default for LanguageDirectiveConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            template: "Respond in {{language}} (the language of the user's latest message) \
+                       unless the user explicitly asks for another language."
+                .to_string(),
+            min_letters: 12,
+            use_accept_language: true,
+        }
+    }
+}
+
+/// 日志配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct LoggingConfig {
+    /// 是否对日志中的请求/响应体做脱敏（API Key、Token、base64 图片等）
+    pub redact: bool,
+
+    /// 严格模式：完全不记录请求/响应体
+    pub strict: bool,
+
+    /// 额外的脱敏正则，匹配内容替换为 `***`
+    pub redact_patterns: Vec<String>,
+
+    /// 流式响应 SSE 会话记录
+    pub sse_transcripts: SseTranscriptConfig,
+
+    /// 请求/响应日志（JSONL，按大小轮转）
+    pub requests: RequestLogConfig,
+
+    /// 每个流式请求保留的最近上游帧数，请求以错误结束时写入日志（0 表示不保留）
+    pub frame_ring_size: usize,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            redact: true,
+            strict: false,
+            redact_patterns: Vec::new(),
+            sse_transcripts: SseTranscriptConfig::default(),
+            requests: RequestLogConfig::default(),
+            frame_ring_size: 32,
+        }
+    }
+}
+
+/// SSE 会话记录配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SseTranscriptConfig {
+    /// 是否记录流式响应下发的完整 SSE 字节流
+    pub enabled: bool,
+
+    /// 分块大小（字节），每块压缩为一个独立的 zstd 帧写入磁盘
+    pub chunk_bytes: usize,
+
+    /// zstd 压缩级别（1-22）
+    pub level: i32,
+
+    /// 最多保留的记录数，超出时删除最早的记录
+    pub max_transcripts: usize,
+}
+
+impl Default for SseTranscriptConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            chunk_bytes: 64 * 1024,
+            level: 3,
+            max_transcripts: 200,
+        }
+    }
+}
+
+/// 请求/响应日志配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RequestLogConfig {
+    /// 是否记录每个消息请求（含转换后的 Kiro 请求体）与响应摘要
+    pub enabled: bool,
+
+    /// 单个日志文件的大小上限（字节），超出时轮转
+    pub max_file_bytes: u64,
+
+    /// 最多保留的日志文件数（含当前文件）
+    pub max_files: usize,
+}
+
+impl Default for RequestLogConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_file_bytes: 10 * 1024 * 1024,
+            max_files: 5,
+        }
+    }
+}
+
+/// SSE 事件序列严格程度
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum SseProfile {
+    /// 兼容 Claude Code 的非标准序列（默认）：
+    /// thinking-only 流补发空格文本块并以 max_tokens 结束，tool_use 之后的文本自动开启新文本块
+    #[default]
+    Quirks,
+    /// 严格遵循 Anthropic 规范，不做上述补偿；内容块不交错，tool_use 之后的文本先结束工具块再开启新文本块
+    Strict,
+}
+
+/// SSE 兼容层 profile（适配期望不同事件名或字段的第三方客户端）
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CompatProfile {
+    /// 不改写事件（默认）
+    #[default]
+    None,
+    /// Claude Code：丢弃 `kiro_*` 扩展事件
+    ClaudeCode,
+    /// OpenAI 转换网关：丢弃扩展事件与扩展字段，补齐缓存用量字段
+    OpenaiBridge,
+    /// 严格 Anthropic：丢弃扩展事件与扩展字段
+    StrictAnthropic,
+    /// 使用 `stream.compatRules` 自定义规则
+    Custom,
+}
+
+/// 自定义 SSE 兼容规则
+///
+/// 字段路径为 JSON Pointer（相对事件的 data）；删除和添加字段按原事件名匹配，最后再重命名事件
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CompatRulesConfig {
+    /// 事件重命名：原事件名 -> 新事件名（data 中的 `type` 一并改写）
+    pub rename_events: BTreeMap<String, String>,
+    /// 丢弃的事件
+    pub drop_events: Vec<String>,
+    /// 删除的字段：事件名 -> 字段路径列表
+    pub remove_fields: BTreeMap<String, Vec<String>>,
+    /// 添加的字段（已存在时保留原值）：事件名 -> 字段路径 -> 值
+    pub add_fields: BTreeMap<String, BTreeMap<String, serde_json::Value>>,
+}
+
+/// 流式响应配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct StreamConfig {
+    /// 是否在响应中附带上游解码统计
+    ///
+    /// 流式响应在 `message_stop` 前发送 `kiro_stats` 事件，非流式响应通过 `x-kiro-*` 响应头返回
+    pub decoder_stats: bool,
+
+    /// `/v1/messages` 的 SSE 严格程度
+    pub v1_profile: SseProfile,
+
+    /// `/cc/v1/messages` 的 SSE 严格程度
+    pub cc_profile: SseProfile,
+
+    /// `/v1/messages` 的 SSE 兼容层 profile
+    pub v1_compat: CompatProfile,
+
+    /// `/cc/v1/messages` 的 SSE 兼容层 profile
+    pub cc_compat: CompatProfile,
+
+    /// 兼容层 profile 为 `custom` 时使用的规则
+    pub compat_rules: CompatRulesConfig,
+
+    /// SSE 发送队列最多缓存的事件数，0 表示不使用队列（直接按客户端读取速度拉取上游）
+    ///
+    /// 队列写满时依次丢弃 ping、合并增量事件，最终以 overloaded_error 中止流
+    pub outgoing_queue_size: usize,
+
+    /// 是否剥离模型对注入策略文本（Write/Edit 工具描述后缀、分块写入策略）的逐字回显
+    pub strip_policy_echo: bool,
+
+    /// 周期性用量事件的默认发送间隔（秒），0 表示不按时间发送
+    ///
+    /// 仅对请求中设置了 `stream_options.include_usage` 的流生效
+    pub usage_interval_secs: u64,
+
+    /// 周期性用量事件的默认内容块间隔，0 表示不按内容块发送
+    pub usage_interval_blocks: usize,
+
+    /// 是否按请求的 `thinking.budget_tokens` 截断 thinking 输出
+    ///
+    /// 超出预算后关闭 thinking 块，后续 thinking 内容不再下发
+    pub enforce_thinking_budget: bool,
+
+    /// 上游代码引用（许可证归属）信息的处理方式
+    pub code_references: CodeReferenceMode,
+
+    /// 按请求声明的 input_schema 校验模型生成的工具输入
+    pub tool_input_validation: ToolInputValidation,
+
+    /// 编辑类工具的流式 diff 预览
+    pub edit_diff_preview: EditDiffPreviewConfig,
+
+    /// 单个 `input_json_delta` 的最大字节数，较大的工具输入拆分为多个增量下发，0 表示不拆分
+    pub tool_input_chunk_bytes: usize,
+
+    /// 工具调用的 stop 先于剩余输入片段到达时，继续等待迟到片段的上游事件数；
+    /// 输出提前停止后同样在这么多个上游事件内等待迟到的 contextUsage。0 表示不等待
+    pub reorder_window: u32,
+
+    /// 拒绝回复识别（映射为 `stop_reason: "refusal"`）
+    pub refusal: RefusalConfig,
+}
+
+impl Default for StreamConfig {
     fn default() -> Self {
-        Self::Rustls
+        Self {
+            decoder_stats: false,
+            v1_profile: SseProfile::default(),
+            cc_profile: SseProfile::default(),
+            v1_compat: CompatProfile::default(),
+            cc_compat: CompatProfile::default(),
+            compat_rules: CompatRulesConfig::default(),
+            outgoing_queue_size: 512,
+            strip_policy_echo: true,
+            usage_interval_secs: 5,
+            usage_interval_blocks: 0,
+            enforce_thinking_budget: true,
+            code_references: CodeReferenceMode::default(),
+            tool_input_validation: ToolInputValidation::default(),
+            edit_diff_preview: EditDiffPreviewConfig::default(),
+            tool_input_chunk_bytes: 2048,
+            reorder_window: 0,
+            refusal: RefusalConfig::default(),
+        }
+    }
+}
+
+/// 编辑类工具的流式 diff 预览配置
+///
+/// 启用后，工具输入流式下发的同时以 `kiro_diff` 扩展事件下发统一 diff 格式的修改预览
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct EditDiffPreviewConfig {
+    /// 是否启用（默认关闭）
+    pub enabled: bool,
+
+    /// 生成预览的工具名（区分大小写）
+    pub tools: Vec<String>,
+}
+
+impl Default for EditDiffPreviewConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            tools: vec!["Edit".to_string(), "Write".to_string()],
+        }
+    }
+}
+
+/// 拒绝回复识别配置
+///
+/// 未配置任何规则时不识别
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct RefusalConfig {
+    /// 视为拒绝的 Kiro 异常名（不含命名空间，如 `GuardrailInterventionException`）
+    pub exceptions: Vec<String>,
+
+    /// 视为拒绝的回复正则，匹配回复文本的开头部分
+    pub patterns: Vec<String>,
+
+    /// 识别为拒绝且没有文本输出时补发的文本块内容，为空时不补发
+    pub message: String,
+}
+
+/// 上游代码引用（codeReferenceEvent）处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum CodeReferenceMode {
+    /// 忽略（默认）
+    #[default]
+    Drop,
+    /// 在回复末尾追加引用说明
+    Append,
+}
+
+/// 工具输入不符合 input_schema 时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ToolInputValidation {
+    /// 不校验（默认）
+    #[default]
+    Off,
+    /// 照常下发，在 tool_use 块上附加 `kiro_warning`
+    Annotate,
+    /// 拦截该工具调用，改为下发说明错误的文本，由模型在下一轮修正
+    Correct,
+}
+
+/// 上游连接配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct UpstreamConfig {
+    /// 是否复用到上游的连接（关闭时每个请求附带 `Connection: close`）
+    pub keep_alive: bool,
+
+    /// 连接池中空闲连接的保留时间（秒），0 表示不限制
+    pub pool_idle_timeout_secs: u64,
+
+    /// 每个上游主机最多保留的空闲连接数
+    pub pool_max_idle_per_host: usize,
+
+    /// TCP keepalive 探测间隔（秒），0 表示不启用
+    pub tcp_keepalive_secs: u64,
+
+    /// 连接预热间隔（秒），0 表示不预热
+    ///
+    /// 定期向每个凭据对应的上游域名发送 HEAD 请求，保持连接池中有已完成 TLS 握手的连接
+    pub prewarm_interval_secs: u64,
+
+    /// 是否在响应中添加 `x-kiro-upstream-attempts` 头（上游尝试次数与各次失败原因）
+    pub attempts_header: bool,
+}
+
+impl Default for UpstreamConfig {
+    fn default() -> Self {
+        Self {
+            keep_alive: true,
+            pool_idle_timeout_secs: 90,
+            pool_max_idle_per_host: 8,
+            tcp_keepalive_secs: 60,
+            prewarm_interval_secs: 0,
+            attempts_header: false,
+        }
+    }
+}
+
+/// 会话存储配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SessionConfig {
+    /// 最多保留的会话数，超出时淘汰最久未访问的会话
+    pub max_entries: usize,
+
+    /// 会话空闲超过该时间（秒）后淘汰，0 表示不按时间淘汰
+    pub idle_ttl_secs: u64,
+
+    /// 会话存储的内存预算（MB，按保存的历史和分支内容估算），0 表示不限制
+    pub max_memory_mb: usize,
+
+    /// 是否保存每个会话最近一次发送给上游的历史，供会话渲染接口使用
+    pub keep_history: bool,
+
+    /// 是否把会话固定在首轮请求映射到的 Kiro 模型上（客户端中途切换模型名时仍使用首轮的模型）
+    pub pin_model: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> Self {
+        Self {
+            max_entries: 1024,
+            idle_ttl_secs: 86400,
+            max_memory_mb: 256,
+            keep_history: false,
+            pin_model: false,
+        }
+    }
+}
+
+/// 凭据目录配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CredentialsDirConfig {
+    /// 凭据目录，其中每个 `*.json` 文件（单对象或数组格式）都会加载到凭据池，未设置时不启用
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub path: Option<String>,
+
+    /// 扫描目录变化的间隔（秒），0 表示只在启动时加载一次
+    pub scan_interval_secs: u64,
+}
+
+impl Default for CredentialsDirConfig {
+    fn default() -> Self {
+        Self {
+            path: None,
+            scan_interval_secs: 10,
+        }
+    }
+}
+
+/// 用量报表配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ReportsConfig {
+    /// 将用量与指标快照写入缓存目录的间隔（秒），0 表示只在内存中统计
+    pub snapshot_interval_secs: u64,
+
+    /// 用量报表保留的天数
+    pub retention_days: u32,
+}
+
+impl Default for ReportsConfig {
+    fn default() -> Self {
+        Self {
+            snapshot_interval_secs: 300,
+            retention_days: 30,
+        }
+    }
+}
+
+/// 上游请求调度配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct SchedulerConfig {
+    /// 同时发往上游的请求数上限，0 表示不限制（不排队，也不区分优先级）
+    pub max_concurrent_requests: usize,
+
+    /// 有 batch 请求排队时，连续放行多少个 interactive 请求后必须放行一个 batch 请求
+    pub interactive_burst: usize,
+
+    /// 存在竞争时 batch 流每次转发的最大字节数
+    pub batch_read_quantum: usize,
+
+    /// 存在竞争时 batch 流两次转发之间的间隔（毫秒）
+    pub batch_pace_ms: u64,
+}
+
+impl Default for SchedulerConfig {
+    fn default() -> Self {
+        Self {
+            max_concurrent_requests: 0,
+            interactive_burst: 4,
+            batch_read_quantum: 1024,
+            batch_pace_ms: 20,
+        }
+    }
+}
+
+/// 转换器配置灰度：稳定与实验两个版本使用同一份转换器代码，实验版本另有叠加的配置覆盖项
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CanaryConfig {
+    /// 使用实验转换器的请求比例（0-100）
+    pub percentage: u8,
+
+    /// 实验转换器在 `converter` 基础上覆盖的配置项（格式与 `converter` 相同）
+    #[serde(skip_serializing_if = "serde_json::Map::is_empty")]
+    pub converter: serde_json::Map<String, serde_json::Value>,
+
+    /// 使用实验转换器时是否在后台用稳定版本再转换一次，并记录两者产出的 Kiro 请求差异
+    pub log_diffs: bool,
+}
+
+impl Default for CanaryConfig {
+    fn default() -> Self {
+        Self {
+            percentage: 0,
+            converter: serde_json::Map::new(),
+            log_diffs: true,
+        }
+    }
+}
+
+impl CanaryConfig {
+    /// 实验转换器的配置：稳定配置叠加 `converter` 覆盖项
+    pub fn experimental_converter(
+        &self,
+        stable: &ConverterConfig,
+    ) -> anyhow::Result<ConverterConfig> {
+        let mut merged = serde_json::to_value(stable)?;
+        if let Some(fields) = merged.as_object_mut() {
+            fields.extend(self.converter.clone());
+        }
+        Ok(serde_json::from_value(merged)?)
+    }
+}
+
+/// 凭据健康度配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct CredentialHealthConfig {
+    /// 是否按健康度调整凭据选择
+    pub enabled: bool,
+
+    /// 期望的上游响应延迟（毫秒），平均延迟超出时按比例降低评分
+    pub latency_target_ms: u64,
+
+    /// 没有新请求时错误率、限流率与超出的延迟衰减一半所需的时间（秒）
+    pub recovery_half_life_secs: u64,
+
+    /// 评分低于该值的凭据视为降级，仅在没有健康凭据时使用
+    pub min_score: f64,
+
+    /// 连续失败被自动禁用的凭据经过该时间（秒）后重新启用，0 表示保持禁用直到所有凭据都不可用
+    pub failure_cooldown_secs: u64,
+}
+
+impl Default for CredentialHealthConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            latency_target_ms: 10_000,
+            recovery_half_life_secs: 120,
+            min_score: 0.2,
+            failure_cooldown_secs: 300,
+        }
+    }
+}
+
+/// 转换失败死信队列配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct DeadLetterConfig {
+    /// 是否记录转换失败的请求
+    pub enabled: bool,
+
+    /// 最多保留的条数，超出时淘汰最早的记录
+    pub capacity: usize,
+
+    /// 每条记录保存的请求体上限（字节），超出部分截断
+    pub max_payload_bytes: usize,
+}
+
+impl Default for DeadLetterConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            capacity: 100,
+            max_payload_bytes: 16 * 1024,
+        }
+    }
+}
+
+/// Prometheus 指标导出配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MetricsConfig {
+    /// 是否提供 `GET /metrics`（仅在提供 `metrics` 服务的监听器上）
+    pub enabled: bool,
+
+    /// 抓取使用的密钥（`Authorization: Bearer` 或 `x-api-key`），未配置时不校验
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+}
+
+/// OpenTelemetry 链路追踪配置
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct TelemetryConfig {
+    /// 是否通过 OTLP/HTTP 导出 span
+    pub enabled: bool,
+
+    /// OTLP/HTTP traces 端点（完整 URL）
+    pub endpoint: String,
+
+    /// 导出请求附带的请求头（如采集端的认证）
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub headers: BTreeMap<String, String>,
+
+    /// 上报的服务名
+    pub service_name: String,
+
+    /// 采样比例（0-1），按请求整体采样
+    pub sample_ratio: f64,
+}
+
+impl Default for TelemetryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            endpoint: "http://localhost:4318/v1/traces".to_string(),
+            headers: BTreeMap::new(),
+            service_name: "kiro-rs".to_string(),
+            sample_ratio: 1.0,
+        }
+    }
+}
+
+/// Kiro profile ARN 配置（一个部署服务多个 Kiro profile）
+///
+/// 默认使用第一个凭据的 profileArn
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ProfilesConfig {
+    /// API Key -> 该 Key 的请求使用的 profile ARN（对应监听器的 apiKey 或全局 apiKey）
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub by_api_key: BTreeMap<String, String>,
+
+    /// 允许请求通过 `x-kiro-profile-arn` 请求头指定的 profile ARN，为空时不接受该请求头
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+}
+
+/// 单个 API Key 的配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ApiKeyConfig {
+    /// 名称，用于日志和指标
+    pub name: String,
+
+    /// API Key
+    pub key: String,
+
+    /// 允许使用的客户端模型名（不区分大小写，可用 `*` 通配），为空时不限制
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+
+    /// 每分钟允许的请求数，未配置时不限制
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub requests_per_minute: Option<u32>,
+}
+
+/// 请求 max_tokens 配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct MaxTokensConfig {
+    /// 请求未指定 max_tokens 时使用的值，未配置时与官方 API 一样返回 400
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub default: Option<i32>,
+
+    /// max_tokens 超出模型输出上限时的处理方式
+    pub over_limit: MaxTokensPolicy,
+}
+
+/// max_tokens 超出模型输出上限时的处理方式
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum MaxTokensPolicy {
+    /// 下调到模型的输出上限，并通过 `x-kiro-max-tokens-clamped` 响应头提示（默认）
+    #[default]
+    Clamp,
+    /// 与官方 API 一样返回 400
+    Reject,
+}
+
+/// 每日 token 预算配置
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BudgetConfig {
+    /// 预算规则，按顺序取第一条匹配请求模型和 API Key 的规则
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<BudgetRule>,
+}
+
+/// 单条预算规则
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct BudgetRule {
+    /// 客户端模型名（不区分大小写），可用 `*` 通配任意字符
+    pub model: String,
+
+    /// 每个 API Key 每天（UTC）在匹配模型上可用的 tokens（输入 + 输出）
+    pub daily_tokens: u64,
+
+    /// 超出预算后改用的客户端模型名，可再匹配下一条规则形成降级链
+    pub downgrade_to: String,
+
+    /// 适用的 API Key，为空时适用于所有 Key
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<String>,
+}
+
+/// 费用估算配置
+///
+/// 按 Kiro 模型的单价估算每个请求的费用，通过响应用量中的 `kiro_estimated_cost` 字段返回并计入用量报表。
+/// 单价的货币单位由使用方自行约定
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct PricingConfig {
+    /// Kiro 模型 ID（不区分大小写）-> 单价，未配置单价的模型不估算费用
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub models: BTreeMap<String, ModelPrice>,
+
+    /// 每次 Web Search 的价格
+    pub web_search_per_request: f64,
+}
+
+/// 单个模型的单价
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ModelPrice {
+    /// 每百万输入 tokens 的价格
+    pub input_per_million: f64,
+
+    /// 每百万输出 tokens 的价格
+    pub output_per_million: f64,
+}
+
+/// 模型映射配置
+///
+/// 客户端模型名按顺序匹配自定义规则，再匹配内置规则（sonnet/opus/haiku），第一条命中的规则决定 Kiro 模型
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ModelMappingConfig {
+    /// 自定义映射规则，优先于内置规则
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub aliases: Vec<ModelAlias>,
+
+    /// 是否在自定义规则之后保留内置映射
+    pub builtin: bool,
+}
+
+impl Default for ModelMappingConfig {
+    fn default() -> Self {
+        Self {
+            aliases: Vec::new(),
+            builtin: true,
+        }
+    }
+}
+
+/// 单条模型映射规则
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ModelAlias {
+    /// 客户端模型名（不区分大小写），可用 `*` 通配任意字符
+    pub name: String,
+
+    /// 映射到的 Kiro 模型 ID
+    pub target: String,
+
+    /// Kiro 是否支持该模型的 thinking 模式
+    pub thinking: bool,
+
+    /// 是否从流式响应中提取 thinking 块
+    ///
+    /// 模型从不输出 `<thinking>` 标签时可关闭，文本不再为探测标签而暂存尾部，直接下发
+    pub extract_thinking: bool,
+
+    /// 是否在 `/v1/models` 中公布（含通配符的规则不公布）
+    pub list: bool,
+
+    /// `/v1/models` 中的显示名，默认为 name
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+
+    /// `/v1/models` 中的创建时间（Unix 秒）
+    pub created: i64,
+
+    /// 模型的输出 tokens 上限，请求的 max_tokens 超出时按 `maxTokens.overLimit` 处理
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_output_tokens: Option<i32>,
+}
+
+impl Default for ModelAlias {
+    fn default() -> Self {
+        Self {
+            name: String::new(),
+            target: String::new(),
+            thinking: true,
+            extract_thinking: true,
+            list: true,
+            display_name: None,
+            created: 0,
+            max_output_tokens: None,
+        }
+    }
+}
+
+/// 监听器提供的服务
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListenerService {
+    /// Anthropic 兼容 API（`/v1`、`/cc/v1`）
+    Api,
+    /// Admin API 与 Admin UI（需配置 adminApiKey）
+    Admin,
+    /// Prometheus 指标（`/metrics`，需启用 metrics.enabled）
+    Metrics,
+}
+
+/// 监听器的 API 认证策略
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ListenerAuth {
+    /// 校验 API Key
+    #[default]
+    ApiKey,
+    /// 不校验（仅适用于本机或 Unix Socket 等受信任的接入方式）
+    None,
+}
+
+/// 监听器配置
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ListenerConfig {
+    /// 监听地址：`host:port`，或 `unix:/path/to.sock`
+    pub bind: String,
+
+    /// 提供的服务
+    #[serde(default = "default_listener_services")]
+    pub services: Vec<ListenerService>,
+
+    /// Anthropic API 的认证策略（Admin API 始终校验 adminApiKey）
+    #[serde(default)]
+    pub auth: ListenerAuth,
+
+    /// 该监听器使用的 API Key，未配置时使用全局 apiKey
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub api_key: Option<String>,
+
+    /// TLS 配置（仅 TCP 监听器），未配置时使用明文 HTTP
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ListenerTlsConfig>,
+}
+
+/// 监听器 TLS 配置
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, rename_all = "camelCase")]
+pub struct ListenerTlsConfig {
+    /// 服务端证书链文件（PEM）
+    pub cert_file: String,
+
+    /// 服务端私钥文件（PEM）
+    pub key_file: String,
+
+    /// 签发客户端证书的 CA 文件（PEM），配置后启用客户端证书认证（mTLS）
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ca_file: Option<String>,
+
+    /// 是否拒绝未提供客户端证书的连接（为 false 时仍可使用 API Key 认证）
+    pub require_client_cert: bool,
+
+    /// 客户端证书 CN -> 该客户端的 API Key 身份
+    ///
+    /// 证书 CN 在表中的请求跳过 API Key 校验，按映射到的 Key 统计用量和选择 profile
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub client_identities: BTreeMap<String, String>,
+}
+
+fn default_listener_services() -> Vec<ListenerService> {
+    vec![
+        ListenerService::Api,
+        ListenerService::Admin,
+        ListenerService::Metrics,
+    ]
+}
+
+impl ListenerConfig {
+    /// 是否提供指定服务
+    pub fn serves(&self, service: ListenerService) -> bool {
+        self.services.contains(&service)
+    }
+
+    /// Unix Socket 路径（`unix:` 前缀），TCP 地址返回 None
+    pub fn unix_path(&self) -> Option<&str> {
+        self.bind.strip_prefix("unix:")
     }
 }
 
@@ -20,6 +1047,10 @@ impl Default for TlsBackend {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct Config {
+    /// 配置格式版本，缺省视为 0，加载时自动迁移到当前版本
+    #[serde(default)]
+    pub version: u32,
+
     #[serde(default = "default_host")]
     pub host: String,
 
@@ -48,6 +1079,11 @@ pub struct Config {
     #[serde(default)]
     pub api_key: Option<String>,
 
+    /// 额外的 API Key（各自带名称、模型白名单与限流），与 apiKey 同时有效
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub api_keys: Vec<ApiKeyConfig>,
+
     #[serde(default = "default_system_version")]
     pub system_version: String,
 
@@ -86,10 +1122,100 @@ pub struct Config {
     #[serde(default)]
     pub admin_api_key: Option<String>,
 
+    /// 是否提供内置状态面板（`/admin/ui`，需启用 Admin API）
+    #[serde(default = "default_admin_dashboard")]
+    pub admin_dashboard: bool,
+
     /// 负载均衡模式（"priority" 或 "balanced"）
     #[serde(default = "default_load_balancing_mode")]
     pub load_balancing_mode: String,
 
+    /// 协议转换配置
+    #[serde(default)]
+    pub converter: ConverterConfig,
+
+    /// 日志配置
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// 流式响应配置
+    #[serde(default)]
+    pub stream: StreamConfig,
+
+    /// 上游连接配置
+    #[serde(default)]
+    pub upstream: UpstreamConfig,
+
+    /// 会话存储配置
+    #[serde(default)]
+    pub sessions: SessionConfig,
+
+    /// 检查主凭据文件变化的间隔（秒），文件被外部修改时重新加载，0 表示不检查
+    #[serde(default = "default_credentials_reload_interval_secs")]
+    pub credentials_reload_interval_secs: u64,
+
+    /// 凭据目录配置
+    #[serde(default)]
+    pub credentials_dir: CredentialsDirConfig,
+
+    /// 用量报表配置
+    #[serde(default)]
+    pub reports: ReportsConfig,
+
+    /// 上游请求调度配置
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+
+    /// 转换器灰度配置
+    #[serde(default)]
+    pub canary: CanaryConfig,
+
+    /// 凭据健康度配置
+    #[serde(default)]
+    pub credential_health: CredentialHealthConfig,
+
+    /// 转换失败死信队列配置
+    #[serde(default)]
+    pub dead_letter: DeadLetterConfig,
+
+    /// Prometheus 指标导出配置
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+
+    /// OpenTelemetry 链路追踪配置
+    #[serde(default)]
+    pub telemetry: TelemetryConfig,
+
+    /// Kiro profile ARN 配置
+    #[serde(default)]
+    pub profiles: ProfilesConfig,
+
+    /// 模型映射配置
+    #[serde(default)]
+    pub model_mapping: ModelMappingConfig,
+
+    /// 按 API Key 的每日 token 预算与自动降级
+    #[serde(default)]
+    pub budgets: BudgetConfig,
+
+    /// 请求 max_tokens 的默认值与按模型输出上限的限制
+    #[serde(default)]
+    pub max_tokens: MaxTokensConfig,
+
+    /// 按模型单价估算请求费用
+    #[serde(default)]
+    pub pricing: PricingConfig,
+
+    /// 监听器列表，未配置时按 host/port 启动单个监听器（API + Admin）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub listeners: Vec<ListenerConfig>,
+
+    /// 按 host/port 启动的默认监听器的 TLS 配置（配置了 listeners 时不生效）
+    #[serde(default)]
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tls: Option<ListenerTlsConfig>,
+
     /// 配置文件路径（运行时元数据，不写入 JSON）
     #[serde(skip)]
     config_path: Option<PathBuf>,
@@ -128,6 +1254,14 @@ fn default_tls_backend() -> TlsBackend {
     TlsBackend::Rustls
 }
 
+fn default_credentials_reload_interval_secs() -> u64 {
+    5
+}
+
+fn default_admin_dashboard() -> bool {
+    true
+}
+
 fn default_load_balancing_mode() -> String {
     "priority".to_string()
 }
@@ -135,6 +1269,7 @@ fn default_load_balancing_mode() -> String {
 impl Default for Config {
     fn default() -> Self {
         Self {
+            version: CONFIG_VERSION,
             host: default_host(),
             port: default_port(),
             region: default_region(),
@@ -143,6 +1278,7 @@ impl Default for Config {
             kiro_version: default_kiro_version(),
             machine_id: None,
             api_key: None,
+            api_keys: Vec::new(),
             system_version: default_system_version(),
             node_version: default_node_version(),
             tls_backend: default_tls_backend(),
@@ -153,7 +1289,29 @@ impl Default for Config {
             proxy_username: None,
             proxy_password: None,
             admin_api_key: None,
+            admin_dashboard: default_admin_dashboard(),
             load_balancing_mode: default_load_balancing_mode(),
+            converter: ConverterConfig::default(),
+            logging: LoggingConfig::default(),
+            stream: StreamConfig::default(),
+            upstream: UpstreamConfig::default(),
+            sessions: SessionConfig::default(),
+            credentials_reload_interval_secs: default_credentials_reload_interval_secs(),
+            credentials_dir: CredentialsDirConfig::default(),
+            reports: ReportsConfig::default(),
+            scheduler: SchedulerConfig::default(),
+            canary: CanaryConfig::default(),
+            credential_health: CredentialHealthConfig::default(),
+            dead_letter: DeadLetterConfig::default(),
+            metrics: MetricsConfig::default(),
+            telemetry: TelemetryConfig::default(),
+            profiles: ProfilesConfig::default(),
+            model_mapping: ModelMappingConfig::default(),
+            budgets: BudgetConfig::default(),
+            max_tokens: MaxTokensConfig::default(),
+            pricing: PricingConfig::default(),
+            listeners: Vec::new(),
+            tls: None,
             config_path: None,
         }
     }
@@ -177,22 +1335,85 @@ impl Config {
         self.api_region.as_deref().unwrap_or(&self.region)
     }
 
+    /// 获取实际生效的监听器列表
+    /// 未配置 listeners 时回退到 host/port 单个监听器
+    pub fn effective_listeners(&self) -> Vec<ListenerConfig> {
+        if !self.listeners.is_empty() {
+            return self.listeners.clone();
+        }
+        vec![ListenerConfig {
+            bind: format!("{}:{}", self.host, self.port),
+            services: default_listener_services(),
+            auth: ListenerAuth::ApiKey,
+            api_key: None,
+            tls: self.tls.clone(),
+        }]
+    }
+
     /// 从文件加载配置
     pub fn load<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         let path = path.as_ref();
         if !path.exists() {
             // 配置文件不存在，返回默认配置
-            let mut config = Self::default();
-            config.config_path = Some(path.to_path_buf());
-            return Ok(config);
+            return Ok(Self {
+                config_path: Some(path.to_path_buf()),
+                ..Self::default()
+            });
         }
 
-        let content = fs::read_to_string(path)?;
-        let mut config: Config = serde_json::from_str(&content)?;
+        let (value, migration) = Self::read_migrated(path)?;
+        if migration.migrated() {
+            tracing::warn!(
+                "配置文件版本 {} 已在内存中迁移到 {}，可执行 `kiro-rs config migrate` 写回文件",
+                migration.from,
+                migration.to
+            );
+            for change in &migration.changes {
+                tracing::warn!("  {}", change);
+            }
+        }
+        let mut config: Config = serde_json::from_value(value)?;
+        config
+            .canary
+            .experimental_converter(&config.converter)
+            .context("canary.converter 配置无效")?;
         config.config_path = Some(path.to_path_buf());
         Ok(config)
     }
 
+    /// 读取配置文件并迁移到当前版本
+    fn read_migrated(path: &Path) -> anyhow::Result<(serde_json::Value, Migration)> {
+        let content = fs::read_to_string(path)?;
+        let mut value: serde_json::Value = serde_json::from_str(&content)?;
+        let migration = migrate::migrate(&mut value)?;
+        Ok((value, migration))
+    }
+
+    /// 将配置文件迁移到当前版本并写回（`kiro-rs config migrate`）
+    ///
+    /// 迁移后的内容须能通过完整校验才会写入，原文件备份为 `<文件名>.bak`；
+    /// `dry_run` 时只返回迁移结果与迁移后的内容，不写文件
+    pub fn migrate_file(path: &Path, dry_run: bool) -> anyhow::Result<(Migration, String)> {
+        let (value, migration) = Self::read_migrated(path)
+            .with_context(|| format!("读取配置文件失败: {}", path.display()))?;
+        let config: Config = serde_json::from_value(value.clone()).context("迁移后的配置无效")?;
+        config
+            .canary
+            .experimental_converter(&config.converter)
+            .context("canary.converter 配置无效")?;
+
+        let content = serde_json::to_string_pretty(&value).context("序列化配置失败")?;
+        if dry_run || !migration.migrated() {
+            return Ok((migration, content));
+        }
+        let mut backup = path.as_os_str().to_owned();
+        backup.push(".bak");
+        fs::copy(path, &backup).with_context(|| format!("备份配置文件失败: {}", path.display()))?;
+        fs::write(path, format!("{}\n", content))
+            .with_context(|| format!("写入配置文件失败: {}", path.display()))?;
+        Ok((migration, content))
+    }
+
     /// 获取配置文件路径（如果有）
     pub fn config_path(&self) -> Option<&Path> {
         self.config_path.as_deref()
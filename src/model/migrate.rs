@@ -0,0 +1,194 @@
+//! 配置文件版本迁移
+//!
+//! 配置文件顶层的 `version` 字段记录格式版本，缺省视为 0。加载时按版本依次执行迁移步骤
+//! （字段改名、为改变了默认值的字段写入旧默认值等），再反序列化为 [`Config`](super::config::Config)；
+//! `kiro-rs config migrate` 将迁移结果写回文件。
+//!
+//! 迁移直接作用于 JSON 值，只改写需要变化的字段，未出现的字段仍由反序列化时的默认值补全
+
+use serde_json::{Map, Value};
+
+/// 当前配置格式版本
+pub const CONFIG_VERSION: u32 = 1;
+
+/// 单个迁移步骤：将 `版本 N` 的配置改写为 `版本 N+1`，返回变更说明
+type Step = fn(&mut Map<String, Value>) -> Vec<String>;
+
+/// 迁移步骤，下标为起始版本
+const STEPS: [Step; CONFIG_VERSION as usize] = [v0_to_v1];
+
+/// 迁移结果
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Migration {
+    /// 迁移前的版本
+    pub from: u32,
+    /// 迁移后的版本
+    pub to: u32,
+    /// 变更说明
+    pub changes: Vec<String>,
+}
+
+impl Migration {
+    /// 是否执行了迁移
+    pub fn migrated(&self) -> bool {
+        self.from != self.to
+    }
+}
+
+/// 将配置 JSON 迁移到当前版本
+///
+/// 配置版本高于当前程序支持的版本时返回错误，避免旧程序误读新格式
+pub fn migrate(value: &mut Value) -> anyhow::Result<Migration> {
+    let map = value
+        .as_object_mut()
+        .ok_or_else(|| anyhow::anyhow!("配置文件顶层必须是 JSON 对象"))?;
+    let from = match map.get("version") {
+        None => 0,
+        Some(v) => v
+            .as_u64()
+            .and_then(|v| u32::try_from(v).ok())
+            .ok_or_else(|| anyhow::anyhow!("无效的配置版本: {}", v))?,
+    };
+    if from > CONFIG_VERSION {
+        anyhow::bail!(
+            "配置文件版本 {} 高于当前程序支持的版本 {}，请升级 kiro-rs",
+            from,
+            CONFIG_VERSION
+        );
+    }
+
+    let mut changes = Vec::new();
+    for (version, step) in STEPS.iter().enumerate().skip(from as usize) {
+        for change in step(map) {
+            changes.push(format!("v{} -> v{}: {}", version, version + 1, change));
+        }
+    }
+    if from != CONFIG_VERSION {
+        map.insert("version".to_string(), Value::from(CONFIG_VERSION));
+    }
+
+    Ok(Migration {
+        from,
+        to: CONFIG_VERSION,
+        changes,
+    })
+}
+
+/// 字段改名：仅在新字段不存在时移动，两者同时存在时以新字段为准并丢弃旧字段
+fn rename(map: &mut Map<String, Value>, from: &str, to: &str) -> Option<String> {
+    let value = map.remove(from)?;
+    if map.contains_key(to) {
+        return Some(format!("{} 与 {} 同时存在，已移除 {}", from, to, from));
+    }
+    map.insert(to.to_string(), value);
+    Some(format!("{} 改名为 {}", from, to))
+}
+
+/// 未版本化的配置：顶层字段统一为 camelCase
+///
+/// 早期配置中手写的 snake_case 字段（如 `api_key`、`load_balancing_mode`）会被静默忽略，
+/// 迁移后按 camelCase 生效。仅处理顶层字段，各配置段内的键可能是用户数据（模型名、事件名）
+fn v0_to_v1(map: &mut Map<String, Value>) -> Vec<String> {
+    let legacy: Vec<String> = map.keys().filter(|k| k.contains('_')).cloned().collect();
+    legacy
+        .iter()
+        .filter_map(|key| rename(map, key, &snake_to_camel(key)))
+        .collect()
+}
+
+fn snake_to_camel(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' {
+            upper = !out.is_empty();
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_migrate_unversioned_config() {
+        let mut value = json!({
+            "port": 8990,
+            "api_key": "sk-legacy",
+            "load_balancing_mode": "balanced",
+            "admin_api_key": "old",
+            "adminApiKey": "new",
+            "modelMapping": { "aliases": [] }
+        });
+        let migration = migrate(&mut value).unwrap();
+        assert!(migration.migrated());
+        assert_eq!((migration.from, migration.to), (0, CONFIG_VERSION));
+        assert_eq!(migration.changes.len(), 3);
+        assert_eq!(
+            value,
+            json!({
+                "version": CONFIG_VERSION,
+                "port": 8990,
+                "apiKey": "sk-legacy",
+                "loadBalancingMode": "balanced",
+                "adminApiKey": "new",
+                "modelMapping": { "aliases": [] }
+            })
+        );
+
+        // 已是当前版本时不再改写
+        let before = value.clone();
+        let migration = migrate(&mut value).unwrap();
+        assert!(!migration.migrated());
+        assert!(migration.changes.is_empty());
+        assert_eq!(value, before);
+    }
+
+    #[test]
+    fn test_migrate_rejects_newer_or_invalid_version() {
+        assert!(migrate(&mut json!({ "version": CONFIG_VERSION + 1 })).is_err());
+        assert!(migrate(&mut json!({ "version": "1" })).is_err());
+        assert!(migrate(&mut json!([])).is_err());
+    }
+
+    #[test]
+    fn test_load_and_migrate_file() {
+        use crate::model::config::Config;
+
+        let dir = std::env::temp_dir().join(format!("kiro-config-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("config.json");
+        std::fs::write(
+            &path,
+            r#"{"port": 9000, "load_balancing_mode": "balanced"}"#,
+        )
+        .unwrap();
+
+        let config = Config::load(&path).unwrap();
+        assert_eq!(config.version, CONFIG_VERSION);
+        assert_eq!(config.load_balancing_mode, "balanced");
+
+        let (migration, _) = Config::migrate_file(&path, true).unwrap();
+        assert!(migration.migrated());
+        assert!(!dir.join("config.json.bak").exists());
+
+        Config::migrate_file(&path, false).unwrap();
+        let written: Value =
+            serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(
+            written,
+            serde_json::json!({ "version": CONFIG_VERSION, "port": 9000, "loadBalancingMode": "balanced" })
+        );
+        assert!(dir.join("config.json.bak").exists());
+        assert!(!Config::migrate_file(&path, false).unwrap().0.migrated());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
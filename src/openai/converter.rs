@@ -0,0 +1,386 @@
+//! OpenAI ⇄ Anthropic 格式转换
+//!
+//! 请求转换为 Anthropic Messages 请求后走现有的 Kiro 转换与调用流程，
+//! 响应（及错误）再从 Anthropic 格式转换回 OpenAI 格式
+
+use serde_json::{Value, json};
+
+use crate::anthropic::types::MessagesRequest;
+
+use super::types::{ChatCompletionRequest, ChatMessage};
+
+/// 请求未指定 `max_tokens` / `max_completion_tokens` 时使用的输出上限
+const DEFAULT_MAX_TOKENS: i32 = 8192;
+
+/// 将 Chat Completions 请求转换为 Anthropic Messages 请求
+///
+/// - `system` / `developer` 消息合并为系统提示词
+/// - assistant 的 `tool_calls` 转换为 `tool_use` 块，`tool` 消息转换为 `tool_result` 块
+/// - 连续的同角色消息合并（多个 `tool` 结果并入同一条 user 消息）
+pub fn to_messages_request(req: ChatCompletionRequest) -> Result<MessagesRequest, String> {
+    let mut system = Vec::new();
+    let mut messages: Vec<Value> = Vec::new();
+
+    for message in &req.messages {
+        match message.role.as_str() {
+            "system" | "developer" => system.push(text_of(message.content.as_ref())?),
+            "user" => push_message(&mut messages, "user", user_blocks(message)?),
+            "assistant" => push_message(&mut messages, "assistant", assistant_blocks(message)?),
+            "tool" => {
+                let tool_use_id = message
+                    .tool_call_id
+                    .clone()
+                    .ok_or("tool message is missing tool_call_id")?;
+                let block = json!({
+                    "type": "tool_result",
+                    "tool_use_id": tool_use_id,
+                    "content": text_of(message.content.as_ref())?
+                });
+                push_message(&mut messages, "user", vec![block]);
+            }
+            other => return Err(format!("unsupported message role: {}", other)),
+        }
+    }
+
+    let tools: Vec<Value> = req
+        .tools
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|t| t.tool_type == "function")
+        .map(|t| {
+            json!({
+                "name": t.function.name,
+                "description": t.function.description,
+                "input_schema": t.function.parameters.unwrap_or_else(|| json!({"type": "object"}))
+            })
+        })
+        .collect();
+
+    let mut body = json!({
+        "model": req.model,
+        "max_tokens": req.max_completion_tokens.or(req.max_tokens).unwrap_or(DEFAULT_MAX_TOKENS),
+        "messages": messages,
+        "stream": req.stream,
+    });
+    if !system.is_empty() {
+        body["system"] = json!(system.join("\n\n"));
+    }
+    if !tools.is_empty() {
+        body["tools"] = json!(tools);
+    }
+    if let Some(choice) = &req.tool_choice {
+        body["tool_choice"] = tool_choice(choice)?;
+    }
+    if let Some(stop) = req.stop {
+        body["stop_sequences"] = json!(stop.into_vec());
+    }
+    if let Some(seed) = req.seed {
+        body["seed"] = json!(seed);
+    }
+    if let Some(user) = req.user {
+        body["metadata"] = json!({ "user_id": user });
+    }
+
+    serde_json::from_value(body).map_err(|e| e.to_string())
+}
+
+/// 追加消息，与上一条同角色时合并内容块
+fn push_message(messages: &mut Vec<Value>, role: &str, blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut()
+        && last["role"] == role
+        && let Some(content) = last["content"].as_array_mut()
+    {
+        content.extend(blocks);
+        return;
+    }
+    messages.push(json!({ "role": role, "content": blocks }));
+}
+
+/// 提取纯文本内容（字符串或 text 片段数组）
+fn text_of(content: Option<&Value>) -> Result<String, String> {
+    match content {
+        None | Some(Value::Null) => Ok(String::new()),
+        Some(Value::String(text)) => Ok(text.clone()),
+        Some(Value::Array(parts)) => parts
+            .iter()
+            .map(|part| match part["type"].as_str() {
+                Some("text") => Ok(part["text"].as_str().unwrap_or_default()),
+                other => Err(format!(
+                    "unsupported content part type here: {}",
+                    other.unwrap_or("unknown")
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map(|texts| texts.concat()),
+        Some(other) => Err(format!("invalid message content: {}", other)),
+    }
+}
+
+/// user 消息内容块（文本与 data URL 图片）
+fn user_blocks(message: &ChatMessage) -> Result<Vec<Value>, String> {
+    let Some(Value::Array(parts)) = &message.content else {
+        let text = text_of(message.content.as_ref())?;
+        return Ok(vec![json!({ "type": "text", "text": text })]);
+    };
+    parts
+        .iter()
+        .map(|part| match part["type"].as_str() {
+            Some("text") => Ok(json!({ "type": "text", "text": part["text"] })),
+            Some("image_url") => {
+                let url = part["image_url"]["url"].as_str().unwrap_or_default();
+                image_block(url)
+            }
+            other => Err(format!(
+                "unsupported content part type: {}",
+                other.unwrap_or("unknown")
+            )),
+        })
+        .collect()
+}
+
+/// 将 `data:<media_type>;base64,<data>` 转换为 Anthropic image 块
+///
+/// Kiro 只接受内联图片，不支持远程 URL
+fn image_block(url: &str) -> Result<Value, String> {
+    let (media_type, data) = url
+        .strip_prefix("data:")
+        .and_then(|rest| rest.split_once(";base64,"))
+        .ok_or("only base64 data URLs are supported for image_url")?;
+    Ok(json!({
+        "type": "image",
+        "source": { "type": "base64", "media_type": media_type, "data": data }
+    }))
+}
+
+/// assistant 消息内容块（文本与工具调用）
+fn assistant_blocks(message: &ChatMessage) -> Result<Vec<Value>, String> {
+    let mut blocks = Vec::new();
+    let text = text_of(message.content.as_ref())?;
+    if !text.is_empty() {
+        blocks.push(json!({ "type": "text", "text": text }));
+    }
+    for call in message.tool_calls.iter().flatten() {
+        // 参数不是合法 JSON 对象时按空对象处理，避免整段历史被拒绝
+        let input = serde_json::from_str::<Value>(&call.function.arguments)
+            .ok()
+            .filter(Value::is_object)
+            .unwrap_or_else(|| json!({}));
+        blocks.push(json!({
+            "type": "tool_use",
+            "id": call.id,
+            "name": call.function.name,
+            "input": input
+        }));
+    }
+    Ok(blocks)
+}
+
+/// 转换 tool_choice
+fn tool_choice(choice: &Value) -> Result<Value, String> {
+    match choice {
+        Value::String(mode) => match mode.as_str() {
+            "auto" => Ok(json!({ "type": "auto" })),
+            "none" => Ok(json!({ "type": "none" })),
+            "required" => Ok(json!({ "type": "any" })),
+            other => Err(format!("unsupported tool_choice: {}", other)),
+        },
+        _ => match choice["function"]["name"].as_str() {
+            Some(name) => Ok(json!({ "type": "tool", "name": name })),
+            None => Err(format!("unsupported tool_choice: {}", choice)),
+        },
+    }
+}
+
+/// Anthropic stop_reason 对应的 OpenAI finish_reason
+pub fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" | "model_context_window_exceeded" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Chat Completions 响应 ID（沿用 Anthropic 消息 ID）
+pub fn completion_id(message_id: &str) -> String {
+    format!(
+        "chatcmpl-{}",
+        message_id.strip_prefix("msg_").unwrap_or(message_id)
+    )
+}
+
+/// OpenAI 用量对象
+pub fn usage(input_tokens: i64, output_tokens: i64) -> Value {
+    json!({
+        "prompt_tokens": input_tokens,
+        "completion_tokens": output_tokens,
+        "total_tokens": input_tokens + output_tokens
+    })
+}
+
+/// 将 Anthropic 非流式响应转换为 `chat.completion` 对象
+///
+/// thinking 内容放在 `reasoning_content` 字段，与常见的 OpenAI 兼容服务一致
+pub fn to_chat_completion(message: &Value, model: &str, created: i64) -> Value {
+    let mut text = String::new();
+    let mut reasoning = String::new();
+    let mut tool_calls = Vec::new();
+    for block in message["content"].as_array().into_iter().flatten() {
+        match block["type"].as_str() {
+            Some("text") => text.push_str(block["text"].as_str().unwrap_or_default()),
+            Some("thinking") => reasoning.push_str(block["thinking"].as_str().unwrap_or_default()),
+            Some("tool_use") => tool_calls.push(json!({
+                "id": block["id"],
+                "type": "function",
+                "function": {
+                    "name": block["name"],
+                    "arguments": block["input"].to_string()
+                }
+            })),
+            _ => {}
+        }
+    }
+
+    let mut choice_message = json!({
+        "role": "assistant",
+        "content": (!text.is_empty() || tool_calls.is_empty()).then_some(text),
+    });
+    if !reasoning.is_empty() {
+        choice_message["reasoning_content"] = json!(reasoning);
+    }
+    if !tool_calls.is_empty() {
+        choice_message["tool_calls"] = json!(tool_calls);
+    }
+
+    json!({
+        "id": completion_id(message["id"].as_str().unwrap_or_default()),
+        "object": "chat.completion",
+        "created": created,
+        "model": model,
+        "choices": [{
+            "index": 0,
+            "message": choice_message,
+            "finish_reason": finish_reason(message["stop_reason"].as_str().unwrap_or_default())
+        }],
+        "usage": usage(
+            message["usage"]["input_tokens"].as_i64().unwrap_or(0),
+            message["usage"]["output_tokens"].as_i64().unwrap_or(0)
+        )
+    })
+}
+
+/// 将 Anthropic 错误体转换为 OpenAI 错误格式
+pub fn to_openai_error(error: &Value) -> Value {
+    json!({
+        "error": {
+            "message": error["error"]["message"],
+            "type": error["error"]["type"],
+            "param": null,
+            "code": null
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(body: Value) -> MessagesRequest {
+        to_messages_request(serde_json::from_value(body).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_tool_call_round_trip_request() {
+        let req = request(json!({
+            "model": "claude-sonnet-4-20250514",
+            "messages": [
+                {"role": "system", "content": "Be brief."},
+                {"role": "user", "content": [
+                    {"type": "text", "text": "Weather?"},
+                    {"type": "image_url", "image_url": {"url": "data:image/png;base64,iVBORw0KGgo="}}
+                ]},
+                {"role": "assistant", "content": null, "tool_calls": [
+                    {"id": "call_1", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Paris\"}"}},
+                    {"id": "call_2", "type": "function",
+                     "function": {"name": "get_weather", "arguments": "{\"city\":\"Rome\"}"}}
+                ]},
+                {"role": "tool", "tool_call_id": "call_1", "content": "sunny"},
+                {"role": "tool", "tool_call_id": "call_2", "content": "rain"}
+            ],
+            "tools": [{"type": "function", "function": {
+                "name": "get_weather",
+                "description": "Get weather",
+                "parameters": {"type": "object", "properties": {"city": {"type": "string"}}}
+            }}],
+            "tool_choice": "required",
+            "stop": "END",
+            "seed": 42,
+            "max_completion_tokens": 256
+        }));
+
+        assert_eq!(req.max_tokens, 256);
+        assert_eq!(req.system.as_ref().unwrap()[0].text, "Be brief.");
+        assert_eq!(req.tools.as_ref().unwrap()[0].name, "get_weather");
+        assert_eq!(req.tool_choice, Some(json!({"type": "any"})));
+        assert_eq!(req.stop_sequences, Some(vec!["END".to_string()]));
+        assert_eq!(req.seed, Some(42));
+
+        assert_eq!(req.messages.len(), 3);
+        assert_eq!(
+            req.messages[0].content[1]["source"]["media_type"],
+            "image/png"
+        );
+        let tool_uses = &req.messages[1].content;
+        assert_eq!(tool_uses[1]["type"], "tool_use");
+        assert_eq!(tool_uses[1]["input"], json!({"city": "Rome"}));
+        // 连续的 tool 结果合并为同一条 user 消息
+        let results = req.messages[2].content.as_array().unwrap();
+        assert_eq!(req.messages[2].role, "user");
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[1]["tool_use_id"], "call_2");
+    }
+
+    #[test]
+    fn test_rejects_remote_image_url() {
+        let req = serde_json::from_value(json!({
+            "model": "claude-sonnet-4-20250514",
+            "messages": [{"role": "user", "content": [
+                {"type": "image_url", "image_url": {"url": "https://example.com/a.png"}}
+            ]}]
+        }))
+        .unwrap();
+        assert!(to_messages_request(req).unwrap_err().contains("data URL"));
+    }
+
+    #[test]
+    fn test_to_chat_completion() {
+        let message = json!({
+            "id": "msg_abc",
+            "type": "message",
+            "role": "assistant",
+            "content": [
+                {"type": "thinking", "thinking": "hmm"},
+                {"type": "text", "text": "Checking."},
+                {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {"city": "Paris"}}
+            ],
+            "stop_reason": "tool_use",
+            "usage": {"input_tokens": 10, "output_tokens": 5}
+        });
+        let completion = to_chat_completion(&message, "claude-sonnet-4", 1);
+
+        assert_eq!(completion["id"], "chatcmpl-abc");
+        let choice = &completion["choices"][0];
+        assert_eq!(choice["finish_reason"], "tool_calls");
+        assert_eq!(choice["message"]["content"], "Checking.");
+        assert_eq!(choice["message"]["reasoning_content"], "hmm");
+        assert_eq!(
+            choice["message"]["tool_calls"][0]["function"]["arguments"],
+            r#"{"city":"Paris"}"#
+        );
+        assert_eq!(completion["usage"]["total_tokens"], 15);
+    }
+}
@@ -0,0 +1,118 @@
+//! OpenAI 兼容端点 Handler 函数
+
+use std::convert::Infallible;
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Extension, State},
+    http::{HeaderMap, StatusCode, header},
+    response::{IntoResponse, Json, Response},
+};
+use futures::{StreamExt, stream};
+use serde_json::{Value, json};
+
+use crate::anthropic::{self, AppState, Delivery, EventStream};
+use crate::common::auth::KeyIdentity;
+use crate::common::telemetry::TracedJson;
+
+use super::converter::{to_chat_completion, to_messages_request, to_openai_error};
+use super::stream::{ChunkTranslator, DONE};
+use super::types::ChatCompletionRequest;
+
+/// POST /v1/chat/completions
+///
+/// 转换为 Anthropic Messages 请求后复用 `/v1/messages` 的处理流程，再把响应转换回 OpenAI 格式
+pub async fn post_chat_completions(
+    State(state): State<AppState>,
+    identity: Option<Extension<KeyIdentity>>,
+    headers: HeaderMap,
+    TracedJson(payload): TracedJson<ChatCompletionRequest>,
+) -> Response {
+    tracing::info!(
+        model = %payload.model,
+        stream = %payload.stream,
+        message_count = %payload.messages.len(),
+        "Received POST /v1/chat/completions request"
+    );
+
+    let model = payload.model.clone();
+    let include_usage = payload
+        .stream_options
+        .as_ref()
+        .is_some_and(|o| o.include_usage);
+    let request = match to_messages_request(payload) {
+        Ok(request) => request,
+        Err(message) => {
+            tracing::warn!("OpenAI 请求转换失败: {}", message);
+            return error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message);
+        }
+    };
+
+    // 流式响应直接取内部事件流转换，不经过 SSE 编码与端点兼容层
+    let mut response =
+        anthropic::create_message(state, identity, headers, request, Delivery::Events).await;
+    let created = chrono::Utc::now().timestamp();
+    let events = EventStream::take(&mut response);
+    let (mut parts, body) = response.into_parts();
+    parts.headers.remove(header::CONTENT_LENGTH);
+
+    if let Some(events) = events {
+        let mut translator = ChunkTranslator::new(&model, created, include_usage);
+        let chunks = events
+            .filter_map(move |event| {
+                let output = translator.translate(&event.data);
+                std::future::ready(
+                    (!output.is_empty()).then(|| Ok::<_, Infallible>(Bytes::from(output))),
+                )
+            })
+            .chain(stream::once(async {
+                Ok(Bytes::from_static(DONE.as_bytes()))
+            }));
+        return Response::from_parts(parts, Body::from_stream(chunks));
+    }
+
+    // 非流式响应与错误：读取完整的 Anthropic JSON 后转换
+    let bytes = match axum::body::to_bytes(body, usize::MAX).await {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                "api_error",
+                &format!("读取响应失败: {}", e),
+            );
+        }
+    };
+    let body: Value = match serde_json::from_slice(&bytes) {
+        Ok(body) => body,
+        Err(e) => {
+            return error_response(
+                StatusCode::BAD_GATEWAY,
+                "api_error",
+                &format!("解析响应失败: {}", e),
+            );
+        }
+    };
+    let body = if parts.status.is_success() {
+        to_chat_completion(&body, &model, created)
+    } else {
+        to_openai_error(&body)
+    };
+    parts.headers.insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    Response::from_parts(parts, Body::from(body.to_string()))
+}
+
+/// OpenAI 格式的错误响应
+fn error_response(status: StatusCode, error_type: &str, message: &str) -> Response {
+    let body = json!({
+        "error": {
+            "message": message,
+            "type": error_type,
+            "param": null,
+            "code": null
+        }
+    });
+    (status, Json(body)).into_response()
+}
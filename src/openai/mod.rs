@@ -0,0 +1,18 @@
+//! OpenAI API 兼容服务模块
+//!
+//! 提供 OpenAI Chat Completions 兼容端点，供只支持 OpenAI 协议的客户端
+//! （LibreChat、Continue、Cursor 等）直接接入。请求转换为 Anthropic Messages 请求后
+//! 复用 `anthropic` 模块的 Kiro 转换与调用流程，响应再转换回 OpenAI 格式。
+//!
+//! # 支持的端点
+//! - `POST /v1/chat/completions` - 创建对话补全（支持 `stream`、`tools` / `tool_calls`）
+//!
+//! 模型列表沿用 `GET /v1/models`，其响应格式与 OpenAI 兼容
+
+mod converter;
+mod handlers;
+mod router;
+mod stream;
+mod types;
+
+pub use router::create_router;
@@ -0,0 +1,33 @@
+//! OpenAI 兼容 API 路由配置
+
+use axum::{Router, extract::DefaultBodyLimit, middleware, routing::post};
+
+use crate::anthropic::{AppState, auth_middleware, cors_layer};
+use crate::model::config::ListenerAuth;
+
+use super::handlers::post_chat_completions;
+
+/// 请求体最大大小限制 (50MB，与 Anthropic 端点一致)
+const MAX_BODY_SIZE: usize = 50 * 1024 * 1024;
+
+/// 创建 OpenAI 兼容 API 路由
+///
+/// # 端点
+/// - `POST /v1/chat/completions` - 创建对话补全
+///
+/// 认证方式与 Anthropic 端点相同（`Authorization: Bearer <token>` 或 `x-api-key`）
+pub fn create_router(state: AppState, auth: ListenerAuth) -> Router {
+    let mut routes = Router::new().route("/v1/chat/completions", post(post_chat_completions));
+
+    if auth == ListenerAuth::ApiKey {
+        routes = routes.layer(middleware::from_fn_with_state(
+            state.clone(),
+            auth_middleware,
+        ));
+    }
+
+    routes
+        .layer(cors_layer())
+        .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
+        .with_state(state)
+}
@@ -0,0 +1,199 @@
+//! Anthropic SSE → OpenAI `chat.completion.chunk` 流转换
+//!
+//! Anthropic 流式响应按事件逐个转换：
+//! - `message_start` → 含 `role` 的首个 chunk
+//! - `text_delta` / `thinking_delta` → `content` / `reasoning_content`
+//! - `tool_use` 块 → `tool_calls`（首个 chunk 携带 id 与函数名，之后的 `input_json_delta` 追加到 `arguments`）
+//! - `message_delta` → 携带 `finish_reason` 的 chunk
+//! - `error` → OpenAI 错误对象
+//!
+//! `ping` 与 `kiro_*` 扩展事件被丢弃；请求设置 `stream_options.include_usage` 时，
+//! 在 `[DONE]` 之前追加只含用量的 chunk
+
+use std::collections::HashMap;
+
+use serde_json::{Value, json};
+
+use super::converter::{completion_id, finish_reason, to_openai_error, usage};
+
+/// 流结束标记
+pub const DONE: &str = "data: [DONE]\n\n";
+
+/// 单个请求的 chunk 转换状态
+#[derive(Debug)]
+pub struct ChunkTranslator {
+    id: String,
+    model: String,
+    created: i64,
+    include_usage: bool,
+    /// Anthropic 内容块索引 → tool_calls 索引
+    tool_indices: HashMap<i64, usize>,
+    input_tokens: i64,
+    output_tokens: i64,
+}
+
+impl ChunkTranslator {
+    pub fn new(model: &str, created: i64, include_usage: bool) -> Self {
+        Self {
+            id: String::new(),
+            model: model.to_string(),
+            created,
+            include_usage,
+            tool_indices: HashMap::new(),
+            input_tokens: 0,
+            output_tokens: 0,
+        }
+    }
+
+    /// 转换一个 Anthropic 事件，返回编码后的 SSE 文本（可能为空）
+    pub fn translate(&mut self, event: &Value) -> String {
+        let mut out = String::new();
+        match event["type"].as_str() {
+            Some("message_start") => {
+                let message = &event["message"];
+                self.id = completion_id(message["id"].as_str().unwrap_or_default());
+                self.record_usage(&message["usage"]);
+                self.push_delta(
+                    &mut out,
+                    json!({ "role": "assistant", "content": "" }),
+                    None,
+                );
+            }
+            Some("content_block_start") if event["content_block"]["type"] == "tool_use" => {
+                let index = self.tool_indices.len();
+                self.tool_indices
+                    .insert(event["index"].as_i64().unwrap_or(-1), index);
+                let block = &event["content_block"];
+                let delta = json!({ "tool_calls": [{
+                    "index": index,
+                    "id": block["id"],
+                    "type": "function",
+                    "function": { "name": block["name"], "arguments": "" }
+                }]});
+                self.push_delta(&mut out, delta, None);
+            }
+            Some("content_block_delta") => {
+                let delta = &event["delta"];
+                let delta = match delta["type"].as_str() {
+                    Some("text_delta") => json!({ "content": delta["text"] }),
+                    Some("thinking_delta") => json!({ "reasoning_content": delta["thinking"] }),
+                    Some("input_json_delta") => {
+                        let Some(&index) = event["index"]
+                            .as_i64()
+                            .and_then(|i| self.tool_indices.get(&i))
+                        else {
+                            return out;
+                        };
+                        json!({ "tool_calls": [{
+                            "index": index,
+                            "function": { "arguments": delta["partial_json"] }
+                        }]})
+                    }
+                    _ => return out,
+                };
+                self.push_delta(&mut out, delta, None);
+            }
+            Some("message_delta") => {
+                self.record_usage(&event["usage"]);
+                if let Some(stop_reason) = event["delta"]["stop_reason"].as_str() {
+                    self.push_delta(&mut out, json!({}), Some(finish_reason(stop_reason)));
+                }
+            }
+            Some("message_stop") if self.include_usage => {
+                let chunk = json!({
+                    "id": self.id,
+                    "object": "chat.completion.chunk",
+                    "created": self.created,
+                    "model": self.model,
+                    "choices": [],
+                    "usage": usage(self.input_tokens, self.output_tokens)
+                });
+                push_data(&mut out, &chunk);
+            }
+            Some("error") => push_data(&mut out, &to_openai_error(event)),
+            _ => {}
+        }
+        out
+    }
+
+    fn record_usage(&mut self, usage: &Value) {
+        if let Some(tokens) = usage["input_tokens"].as_i64() {
+            self.input_tokens = tokens;
+        }
+        if let Some(tokens) = usage["output_tokens"].as_i64() {
+            self.output_tokens = tokens;
+        }
+    }
+
+    fn push_delta(&self, out: &mut String, delta: Value, finish_reason: Option<&str>) {
+        let chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }]
+        });
+        push_data(out, &chunk);
+    }
+}
+
+fn push_data(out: &mut String, data: &Value) {
+    out.push_str("data: ");
+    out.push_str(&data.to_string());
+    out.push_str("\n\n");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chunks(output: &str) -> Vec<Value> {
+        output
+            .split("\n\n")
+            .filter_map(|block| block.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_translate_text_and_tool_calls() {
+        let mut translator = ChunkTranslator::new("claude-sonnet-4", 1, true);
+        let events = [
+            json!({"type": "message_start", "message": {"id": "msg_1", "usage": {"input_tokens": 12, "output_tokens": 1}}}),
+            json!({"type": "content_block_start", "index": 0, "content_block": {"type": "text", "text": ""}}),
+            json!({"type": "content_block_delta", "index": 0, "delta": {"type": "text_delta", "text": "Hi"}}),
+            json!({"type": "content_block_stop", "index": 0}),
+            json!({"type": "content_block_start", "index": 1, "content_block": {"type": "tool_use", "id": "toolu_1", "name": "get_weather", "input": {}}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "{\"city\":"}}),
+            json!({"type": "content_block_delta", "index": 1, "delta": {"type": "input_json_delta", "partial_json": "\"Paris\"}"}}),
+            json!({"type": "content_block_stop", "index": 1}),
+            json!({"type": "message_delta", "delta": {"stop_reason": "tool_use"}, "usage": {"output_tokens": 8}}),
+            json!({"type": "message_stop"}),
+        ];
+        let output: String = events.iter().map(|e| translator.translate(e)).collect();
+        let chunks = chunks(&output);
+
+        assert_eq!(chunks.len(), 7);
+        assert!(chunks.iter().all(|c| c["id"] == "chatcmpl-1"));
+        assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+        assert_eq!(chunks[1]["choices"][0]["delta"]["content"], "Hi");
+        let call = &chunks[2]["choices"][0]["delta"]["tool_calls"][0];
+        assert_eq!(
+            (call["index"].clone(), call["id"].clone()),
+            (json!(0), json!("toolu_1"))
+        );
+        let arguments: String = chunks[3..5]
+            .iter()
+            .map(|c| {
+                c["choices"][0]["delta"]["tool_calls"][0]["function"]["arguments"]
+                    .as_str()
+                    .unwrap()
+                    .to_string()
+            })
+            .collect();
+        assert_eq!(arguments, r#"{"city":"Paris"}"#);
+        assert_eq!(chunks[5]["choices"][0]["finish_reason"], "tool_calls");
+        assert_eq!(chunks[6]["choices"], json!([]));
+        assert_eq!(chunks[6]["usage"]["total_tokens"], 20);
+    }
+}
@@ -0,0 +1,98 @@
+//! OpenAI Chat Completions 请求类型定义
+
+use serde::Deserialize;
+
+/// Chat Completions 请求体
+#[derive(Debug, Deserialize)]
+pub struct ChatCompletionRequest {
+    pub model: String,
+    pub messages: Vec<ChatMessage>,
+    #[serde(default)]
+    pub stream: bool,
+    /// 已弃用的最大输出 tokens，`max_completion_tokens` 优先
+    pub max_tokens: Option<i32>,
+    pub max_completion_tokens: Option<i32>,
+    /// 停止序列，字符串或字符串数组
+    pub stop: Option<Stop>,
+    pub tools: Option<Vec<ChatTool>>,
+    pub tool_choice: Option<serde_json::Value>,
+    pub stream_options: Option<ChatStreamOptions>,
+    /// 采样种子，作为扩展字段 `seed` 传递（上游不支持确定性采样）
+    pub seed: Option<u64>,
+    /// 终端用户标识，作为 Anthropic `metadata.user_id` 传递（可携带 session）
+    pub user: Option<String>,
+}
+
+/// 停止序列
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Stop {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Stop {
+    pub fn into_vec(self) -> Vec<String> {
+        match self {
+            Self::One(s) => vec![s],
+            Self::Many(v) => v,
+        }
+    }
+}
+
+/// 对话消息
+#[derive(Debug, Deserialize)]
+pub struct ChatMessage {
+    /// system / developer / user / assistant / tool
+    pub role: String,
+    /// 字符串或内容片段数组（assistant 仅含工具调用时为 null）
+    #[serde(default)]
+    pub content: Option<serde_json::Value>,
+    /// assistant 发起的工具调用
+    pub tool_calls: Option<Vec<ToolCall>>,
+    /// tool 消息对应的工具调用 ID
+    pub tool_call_id: Option<String>,
+}
+
+/// 工具调用
+#[derive(Debug, Deserialize)]
+pub struct ToolCall {
+    pub id: String,
+    pub function: FunctionCall,
+}
+
+/// 工具调用的函数名与参数
+#[derive(Debug, Deserialize)]
+pub struct FunctionCall {
+    pub name: String,
+    /// JSON 编码的参数字符串
+    #[serde(default)]
+    pub arguments: String,
+}
+
+/// 工具定义
+#[derive(Debug, Deserialize)]
+pub struct ChatTool {
+    /// 目前只有 `function`
+    #[serde(rename = "type")]
+    pub tool_type: String,
+    pub function: FunctionDefinition,
+}
+
+/// 函数定义
+#[derive(Debug, Deserialize)]
+pub struct FunctionDefinition {
+    pub name: String,
+    #[serde(default)]
+    pub description: String,
+    /// 参数 JSON Schema
+    pub parameters: Option<serde_json::Value>,
+}
+
+/// 流式选项
+#[derive(Debug, Default, Deserialize)]
+pub struct ChatStreamOptions {
+    /// 是否在 `[DONE]` 前发送一个只含用量的 chunk
+    #[serde(default)]
+    pub include_usage: bool,
+}
@@ -0,0 +1,78 @@
+//! BPE 分词器
+//!
+//! Claude 的分词器未公开，这里使用 cl100k_base 词表近似：两者同为字节级 BPE、词表规模相近，
+//! 计数结果与官方 count_tokens 接近。词表在首次使用时加载，启动时可调用 [`warm_up`] 提前加载
+
+use tiktoken_rs::{CoreBPE, cl100k_base_singleton};
+
+fn encoder() -> &'static CoreBPE {
+    cl100k_base_singleton()
+}
+
+/// 提前加载词表，避免首个请求承担加载耗时
+pub fn warm_up() {
+    let started = std::time::Instant::now();
+    encoder();
+    tracing::debug!("BPE 词表加载完成，耗时 {:?}", started.elapsed());
+}
+
+/// 计算文本的 token 数量
+///
+/// 文本中的 `<|endoftext|>` 等特殊标记按普通文本计数
+pub fn count(text: &str) -> usize {
+    if text.is_empty() {
+        return 0;
+    }
+    encoder().encode_ordinary(text).len()
+}
+
+/// 截取不超过 `max_tokens` 个 token 的最长前缀（按字符边界二分查找）
+pub fn truncate(text: &str, max_tokens: usize) -> &str {
+    if count(text) <= max_tokens {
+        return text;
+    }
+    let boundaries: Vec<usize> = text.char_indices().map(|(i, _)| i).collect();
+    // boundaries[lo] 处的前缀始终满足限制，boundaries[hi] 处的前缀始终超出
+    let (mut lo, mut hi) = (0, boundaries.len());
+    while hi - lo > 1 {
+        let mid = (lo + hi) / 2;
+        if count(&text[..boundaries[mid]]) <= max_tokens {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    &text[..boundaries[lo]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_count() {
+        assert_eq!(count(""), 0);
+        assert_eq!(count("hello world"), 2);
+        assert_eq!(count("<|endoftext|>"), count("<|endof") + count("text|>"));
+
+        // 代码的 token 密度明显高于按 4 字符/token 的估算
+        let code = "fn main() { let x: Vec<u8> = vec![1, 2, 3]; println!(\"{:?}\", x); }";
+        assert!(count(code) > code.len() / 4);
+        assert!(count("你好，世界") > 0);
+    }
+
+    #[test]
+    fn test_truncate() {
+        let text = "hello world, 你好世界 and more words here";
+        assert_eq!(truncate(text, 1000), text);
+        assert_eq!(truncate(text, 0), "");
+        for max in 1..count(text) {
+            let prefix = truncate(text, max);
+            assert!(count(prefix) <= max);
+            assert!(text.starts_with(prefix));
+            // 再多一个字符就会超出限制
+            let next = text[prefix.len()..].chars().next().unwrap();
+            assert!(count(&text[..prefix.len() + next.len_utf8()]) > max);
+        }
+    }
+}
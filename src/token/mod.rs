@@ -0,0 +1,175 @@
+//! Token 计算模块
+//!
+//! 提供文本 token 数量计算功能：配置了外部 count_tokens API 时优先调用（见 [`remote`]），
+//! 否则（或调用失败时）使用本地 BPE 分词器计数。
+
+mod bpe;
+#[cfg(not(edge))]
+mod remote;
+
+pub use bpe::warm_up;
+#[cfg(not(edge))]
+pub(crate) use remote::count_all_tokens;
+#[cfg(not(edge))]
+pub use remote::{CountTokensConfig, init_config};
+
+use crate::anthropic::types::{Message, SystemMessage, Tool};
+
+/// 计算文本的 token 数量
+///
+/// 使用 BPE 分词器（见 [`bpe`]），对代码、JSON 等符号密集的文本比按字符数估算准确得多
+pub fn count_tokens(text: &str) -> u64 {
+    bpe::count(text) as u64
+}
+
+/// 截取不超过 `max_tokens` 个 token 的最长前缀
+pub fn truncate_to_tokens(text: &str, max_tokens: u64) -> &str {
+    bpe::truncate(text, max_tokens as usize)
+}
+
+/// 本地计算请求的输入 tokens
+fn count_all_tokens_local(
+    system: Option<Vec<SystemMessage>>,
+    messages: Vec<Message>,
+    tools: Option<Vec<Tool>>,
+) -> u64 {
+    let mut total = 0;
+
+    // 系统消息
+    if let Some(ref system) = system {
+        for msg in system {
+            total += count_tokens(&msg.text);
+        }
+    }
+
+    // 用户消息
+    for msg in &messages {
+        if let serde_json::Value::String(s) = &msg.content {
+            total += count_tokens(s);
+        } else if let serde_json::Value::Array(arr) = &msg.content {
+            for item in arr {
+                if let Some(text) = item.get("text").and_then(|v| v.as_str()) {
+                    total += count_tokens(text);
+                }
+            }
+        }
+    }
+
+    // 工具定义
+    if let Some(ref tools) = tools {
+        for tool in tools {
+            total += count_tokens(&tool.name);
+            total += count_tokens(&tool.description);
+            let input_schema_json = serde_json::to_string(&tool.input_schema).unwrap_or_default();
+            total += count_tokens(&input_schema_json);
+        }
+    }
+
+    total.max(1)
+}
+
+/// 估算输出 tokens
+pub(crate) fn estimate_output_tokens(content: &[serde_json::Value]) -> i32 {
+    let mut total = 0;
+
+    for block in content {
+        if let Some(text) = block.get("text").and_then(|v| v.as_str()) {
+            total += count_tokens(text) as i32;
+        }
+        if block.get("type").and_then(|v| v.as_str()) == Some("tool_use") {
+            // 工具调用开销
+            if let Some(input) = block.get("input") {
+                let input_str = serde_json::to_string(input).unwrap_or_default();
+                total += count_tokens(&input_str) as i32;
+            }
+        }
+    }
+
+    total.max(1)
+}
+
+/// 快速估算文本的 token 数量（不调用分词器）
+///
+/// 按 cl100k 的预切分规则近似：字母串每 10 个字符、数字每 3 位、ASCII 标点每 2 个计为一个 token，
+/// 其余符号与 CJK 字符逐个计数，字母串前的单个空格或标点并入其中。
+/// 用于流式输出逐段累计，常见文本与 BPE 计数相差 ±25% 以内
+pub fn estimate_tokens(text: &str) -> u64 {
+    #[derive(PartialEq)]
+    enum Run {
+        None,
+        Letters,
+        Digits,
+        Space,
+        Punct,
+    }
+
+    let mut total = 0u64;
+    let mut run = Run::None;
+    let mut run_len = 0u64;
+    let flush = |run: &Run, len: u64| match run {
+        Run::Letters => len.div_ceil(10),
+        Run::Punct => len.div_ceil(2),
+        Run::Digits => len.div_ceil(3),
+        Run::Space => u64::from(len > 1),
+        Run::None => 0,
+    };
+    for c in text.chars() {
+        let kind = if c.is_ascii_alphabetic() || (c.is_alphabetic() && !is_cjk(c)) {
+            Run::Letters
+        } else if c.is_ascii_digit() {
+            Run::Digits
+        } else if c.is_whitespace() {
+            Run::Space
+        } else if c.is_ascii_punctuation() {
+            Run::Punct
+        } else {
+            // 全角标点、其他符号与 CJK 字符各自计为一个 token
+            total += flush(&run, run_len) + 1;
+            run = Run::None;
+            run_len = 0;
+            continue;
+        };
+        if kind == run {
+            run_len += 1;
+            continue;
+        }
+        // 字母串前的单个标点与其合并为一个 token
+        if run == Run::Punct && kind == Run::Letters {
+            run_len -= 1;
+        }
+        total += flush(&run, run_len);
+        run = kind;
+        run_len = 1;
+    }
+    total + flush(&run, run_len)
+}
+
+/// 是否为 CJK 文字（假名、汉字、谚文）
+fn is_cjk(c: char) -> bool {
+    matches!(c, '\u{3040}'..='\u{30FF}' | '\u{3400}'..='\u{9FFF}' | '\u{AC00}'..='\u{D7AF}')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_estimate_tokens_close_to_bpe() {
+        assert_eq!(estimate_tokens(""), 0);
+        assert_eq!(estimate_tokens("hello"), 1);
+        let samples = [
+            "The quick brown fox jumps over the lazy dog. It was a bright cold day in April.",
+            "fn main() {\n    let x: Vec<u8> = vec![1, 2, 3];\n    println!(\"{:?}\", x);\n}\n",
+            "{\"file_path\": \"/home/user/project/src/main.rs\", \"content\": \"use std::io;\"}",
+            "你好，世界！这是一个用于测试分词器估算准确度的中文句子，包含标点符号。",
+            "  - item one\n  - item two\n\n## Heading\n\nSome **bold** text and `code`.",
+        ];
+        for text in samples {
+            let (bpe, estimate) = (count_tokens(text) as f64, estimate_tokens(text) as f64);
+            assert!(
+                (estimate - bpe).abs() <= bpe * 0.25,
+                "{text:?}: bpe {bpe}, estimate {estimate}"
+            );
+        }
+    }
+}
@@ -0,0 +1,124 @@
+//! 外部 count_tokens API
+//!
+//! 配置了 `countTokensApiUrl` 时估算输入 tokens 优先调用该 API，失败时回退到本地计数
+
+use std::sync::OnceLock;
+
+use crate::anthropic::types::{
+    CountTokensRequest, CountTokensResponse, Message, SystemMessage, Tool,
+};
+use crate::http_client::{ProxyConfig, build_client};
+use crate::model::config::TlsBackend;
+
+use super::count_all_tokens_local;
+
+/// Count Tokens API 配置
+#[derive(Clone, Default)]
+pub struct CountTokensConfig {
+    /// 外部 count_tokens API 地址
+    pub api_url: Option<String>,
+    /// count_tokens API 密钥
+    pub api_key: Option<String>,
+    /// count_tokens API 认证类型（"x-api-key" 或 "bearer"）
+    pub auth_type: String,
+    /// 代理配置
+    pub proxy: Option<ProxyConfig>,
+
+    pub tls_backend: TlsBackend,
+}
+
+/// 全局配置存储
+static COUNT_TOKENS_CONFIG: OnceLock<CountTokensConfig> = OnceLock::new();
+
+/// 初始化 count_tokens 配置
+///
+/// 应在应用启动时调用一次
+pub fn init_config(config: CountTokensConfig) {
+    let _ = COUNT_TOKENS_CONFIG.set(config);
+}
+
+/// 获取配置
+fn get_config() -> Option<&'static CountTokensConfig> {
+    COUNT_TOKENS_CONFIG.get()
+}
+
+/// 估算请求的输入 tokens
+///
+/// 优先调用远程 API，失败时回退到本地计算
+pub(crate) fn count_all_tokens(
+    model: String,
+    system: Option<Vec<SystemMessage>>,
+    messages: Vec<Message>,
+    tools: Option<Vec<Tool>>,
+) -> u64 {
+    // 检查是否配置了远程 API
+    if let Some(config) = get_config()
+        && let Some(api_url) = &config.api_url
+    {
+        // 尝试调用远程 API
+        let result = tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(call_remote_count_tokens(
+                api_url, config, model, &system, &messages, &tools,
+            ))
+        });
+
+        match result {
+            Ok(tokens) => {
+                tracing::debug!("远程 count_tokens API 返回: {}", tokens);
+                return tokens;
+            }
+            Err(e) => {
+                tracing::warn!("远程 count_tokens API 调用失败，回退到本地计算: {}", e);
+            }
+        }
+    }
+
+    // 本地计算
+    count_all_tokens_local(system, messages, tools)
+}
+
+/// 调用远程 count_tokens API
+async fn call_remote_count_tokens(
+    api_url: &str,
+    config: &CountTokensConfig,
+    model: String,
+    system: &Option<Vec<SystemMessage>>,
+    messages: &[Message],
+    tools: &Option<Vec<Tool>>,
+) -> Result<u64, Box<dyn std::error::Error + Send + Sync>> {
+    let client = build_client(config.proxy.as_ref(), 300, config.tls_backend)?;
+
+    // 构建请求体
+    let request = CountTokensRequest {
+        model, // 模型名称用于 token 计算
+        messages: messages.to_vec(),
+        system: system.clone(),
+        tools: tools.clone(),
+    };
+
+    // 构建请求
+    let mut req_builder = client.post(api_url);
+
+    // 设置认证头
+    if let Some(api_key) = &config.api_key {
+        if config.auth_type == "bearer" {
+            req_builder = req_builder.header("Authorization", format!("Bearer {}", api_key));
+        } else {
+            req_builder = req_builder.header("x-api-key", api_key);
+        }
+    }
+
+    // 发送请求
+    let response = req_builder
+        .header("Content-Type", "application/json")
+        .json(&request)
+        .send()
+        .await?;
+
+    if !response.status().is_success() {
+        return Err(format!("API 返回错误状态: {}", response.status()).into());
+    }
+
+    let result: CountTokensResponse = response.json().await?;
+    Ok(result.input_tokens as u64)
+}